@@ -0,0 +1,45 @@
+//! Request throughput through the user routes, in process
+//!
+//! `cargo bench --bench http`; the `bench` binary measures the same
+//! requests under concurrency, with p99 latency.
+
+use backend::bench::{self, BenchApp, Operation};
+use backend::database::create_pool_from_env;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use dotenvy::dotenv;
+use tokio::runtime::Runtime;
+
+fn http(c: &mut Criterion) {
+    dotenv().ok();
+    let runtime = Runtime::new().unwrap();
+    let pool = runtime
+        .block_on(create_pool_from_env())
+        .expect("Failed to create database pool");
+    let app = runtime
+        .block_on(BenchApp::new(pool.clone()))
+        .expect("Failed to set up the bench app");
+
+    let mut group = c.benchmark_group("http");
+    group.throughput(Throughput::Elements(1));
+    for operation in Operation::ALL {
+        group.bench_function(operation.name(), |b| {
+            b.to_async(&runtime).iter(|| async {
+                let status = app.send(operation).await;
+                assert!(
+                    status.is_success(),
+                    "{} answered {}",
+                    operation.name(),
+                    status
+                );
+            })
+        });
+    }
+    group.finish();
+
+    runtime
+        .block_on(bench::cleanup(&pool))
+        .expect("Failed to remove bench users");
+}
+
+criterion_group!(benches, http);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+//! Repository throughput against DATABASE_URL
+//!
+//! `cargo bench --bench repository`; compare runs with `--save-baseline`
+//! and `--baseline` to catch regressions.
+
+use backend::bench::{self, LIST_LIMIT};
+use backend::database::create_pool_from_env;
+use backend::models::user::UserListQuery;
+use backend::repository::user::{UserRepository, UserRepositoryTrait};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use dotenvy::dotenv;
+use tokio::runtime::Runtime;
+
+fn repository(c: &mut Criterion) {
+    dotenv().ok();
+    let runtime = Runtime::new().unwrap();
+    let pool = runtime
+        .block_on(create_pool_from_env())
+        .expect("Failed to create database pool");
+    let repo = UserRepository::new(pool.clone());
+    let user = runtime
+        .block_on(repo.create_user(bench::new_user()))
+        .expect("Failed to create user");
+    let query = UserListQuery::default();
+
+    let mut group = c.benchmark_group("repository");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("create", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { repo.create_user(bench::new_user()).await.unwrap() })
+    });
+    group.bench_function("get", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { repo.get_user_by_id(user.user_id()).await.unwrap() })
+    });
+    group.bench_function("list", |b| {
+        b.to_async(&runtime).iter(|| async {
+            repo.list_users_after(&query, None, LIST_LIMIT)
+                .await
+                .unwrap()
+        })
+    });
+    group.finish();
+
+    runtime
+        .block_on(bench::cleanup(&pool))
+        .expect("Failed to remove bench users");
+}
+
+criterion_group!(benches, repository);
+criterion_main!(benches);
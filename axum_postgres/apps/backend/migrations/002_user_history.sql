@@ -0,0 +1,55 @@
+-- Per-version history of test_users rows
+-- Populated by trigger so every write path is captured, including manual SQL
+
+CREATE TABLE IF NOT EXISTS test_users_history (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    operation VARCHAR(10) NOT NULL,
+    data JSONB NOT NULL,
+    changed_by VARCHAR(255),
+    changed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, version)
+);
+
+-- data holds the row after the change (before it, for DELETE).
+-- changed_by is taken from the transaction-local setting app.actor when set.
+CREATE OR REPLACE FUNCTION record_test_users_history() RETURNS TRIGGER AS $$
+DECLARE
+    target_id INTEGER;
+    snapshot JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        target_id := OLD.id;
+        snapshot := to_jsonb(OLD);
+    ELSE
+        IF TG_OP = 'UPDATE' AND OLD IS NOT DISTINCT FROM NEW THEN
+            RETURN NEW;
+        END IF;
+        target_id := NEW.id;
+        snapshot := to_jsonb(NEW);
+    END IF;
+
+    INSERT INTO test_users_history (user_id, version, operation, data, changed_by)
+    VALUES (
+        target_id,
+        COALESCE((SELECT MAX(version) FROM test_users_history WHERE user_id = target_id), 0) + 1,
+        TG_OP,
+        snapshot,
+        NULLIF(current_setting('app.actor', true), '')
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_users_history_trigger ON test_users;
+CREATE TRIGGER test_users_history_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON test_users
+    FOR EACH ROW EXECUTE FUNCTION record_test_users_history();
+
+-- Baseline snapshot for rows that existed before history tracking
+INSERT INTO test_users_history (user_id, version, operation, data, changed_at)
+SELECT u.id, 1, 'INSERT', to_jsonb(u), u.created_at
+FROM test_users u
+WHERE NOT EXISTS (SELECT 1 FROM test_users_history h WHERE h.user_id = u.id);
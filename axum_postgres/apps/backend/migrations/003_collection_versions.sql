@@ -0,0 +1,23 @@
+-- Per-table change counters for cheap collection ETags
+-- Bumped once per writing statement, so GET /api/users can answer 304
+-- without reading the table
+
+CREATE TABLE IF NOT EXISTS collection_versions (
+    name VARCHAR(64) PRIMARY KEY,
+    version BIGINT NOT NULL DEFAULT 0
+);
+
+INSERT INTO collection_versions (name) VALUES ('test_users')
+ON CONFLICT (name) DO NOTHING;
+
+CREATE OR REPLACE FUNCTION bump_collection_version() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE collection_versions SET version = version + 1 WHERE name = TG_TABLE_NAME;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_users_collection_version ON test_users;
+CREATE TRIGGER test_users_collection_version
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON test_users
+    FOR EACH STATEMENT EXECUTE FUNCTION bump_collection_version();
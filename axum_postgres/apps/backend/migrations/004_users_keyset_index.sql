@@ -0,0 +1,5 @@
+-- Index for keyset pagination of the user listing
+-- Matches ORDER BY created_at DESC, id DESC so cursor pages are index seeks
+
+CREATE INDEX IF NOT EXISTS idx_test_users_created_at_id
+    ON test_users (created_at DESC, id DESC);
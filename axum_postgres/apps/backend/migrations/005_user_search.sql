@@ -0,0 +1,42 @@
+-- Full-text search over user name and email
+-- search_vector is maintained by Postgres; name matches rank above email
+
+ALTER TABLE test_users
+    ADD COLUMN IF NOT EXISTS search_vector TSVECTOR
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', name), 'A') ||
+        setweight(to_tsvector('simple', email), 'B')
+    ) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_test_users_search_vector
+    ON test_users USING GIN (search_vector);
+
+-- Keep the derived column out of history snapshots
+CREATE OR REPLACE FUNCTION record_test_users_history() RETURNS TRIGGER AS $$
+DECLARE
+    target_id INTEGER;
+    snapshot JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        target_id := OLD.id;
+        snapshot := to_jsonb(OLD) - 'search_vector';
+    ELSE
+        IF TG_OP = 'UPDATE' AND OLD IS NOT DISTINCT FROM NEW THEN
+            RETURN NEW;
+        END IF;
+        target_id := NEW.id;
+        snapshot := to_jsonb(NEW) - 'search_vector';
+    END IF;
+
+    INSERT INTO test_users_history (user_id, version, operation, data, changed_by)
+    VALUES (
+        target_id,
+        COALESCE((SELECT MAX(version) FROM test_users_history WHERE user_id = target_id), 0) + 1,
+        TG_OP,
+        snapshot,
+        NULLIF(current_setting('app.actor', true), '')
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
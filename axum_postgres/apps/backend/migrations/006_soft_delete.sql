@@ -0,0 +1,10 @@
+-- Soft delete for users
+-- DELETE /api/users/:id sets deleted_at; reads skip those rows unless an
+-- admin asks for them, and POST /api/users/:id/restore clears it again
+
+ALTER TABLE test_users ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE;
+
+-- A deleted user's email may be reused, so uniqueness covers live rows only
+ALTER TABLE test_users DROP CONSTRAINT IF EXISTS test_users_email_key;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_test_users_email_live
+    ON test_users (email) WHERE deleted_at IS NULL;
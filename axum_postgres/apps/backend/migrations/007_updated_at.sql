@@ -0,0 +1,53 @@
+-- Last modification time of each user
+-- Set by a trigger so every write path, including soft delete and restore,
+-- keeps it current without the repository having to remember
+
+ALTER TABLE test_users
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
+UPDATE test_users SET updated_at = created_at;
+
+CREATE OR REPLACE FUNCTION touch_test_users_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    -- No-op updates leave the timestamp (and history) alone
+    IF OLD IS DISTINCT FROM NEW THEN
+        NEW.updated_at := NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_users_updated_at ON test_users;
+CREATE TRIGGER test_users_updated_at
+    BEFORE UPDATE ON test_users
+    FOR EACH ROW EXECUTE FUNCTION touch_test_users_updated_at();
+
+-- History rows carry their own changed_at, so snapshots leave the timestamp
+-- out rather than listing it as a change on every update
+CREATE OR REPLACE FUNCTION record_test_users_history() RETURNS TRIGGER AS $$
+DECLARE
+    target_id INTEGER;
+    snapshot JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        target_id := OLD.id;
+        snapshot := to_jsonb(OLD) - 'search_vector' - 'updated_at';
+    ELSE
+        IF TG_OP = 'UPDATE' AND OLD IS NOT DISTINCT FROM NEW THEN
+            RETURN NEW;
+        END IF;
+        target_id := NEW.id;
+        snapshot := to_jsonb(NEW) - 'search_vector' - 'updated_at';
+    END IF;
+
+    INSERT INTO test_users_history (user_id, version, operation, data, changed_by)
+    VALUES (
+        target_id,
+        COALESCE((SELECT MAX(version) FROM test_users_history WHERE user_id = target_id), 0) + 1,
+        TG_OP,
+        snapshot,
+        NULLIF(current_setting('app.actor', true), '')
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,19 @@
+-- Email uniqueness ignores letter case
+-- "Foo@x.com" and "foo@x.com" used to be separate users. Of live users whose
+-- emails differ only in case, the oldest keeps the address and the others
+-- are soft-deleted so the index can be built; an admin can restore them
+-- after changing one of the addresses.
+
+UPDATE test_users AS duplicate
+SET deleted_at = NOW()
+WHERE duplicate.deleted_at IS NULL
+  AND EXISTS (
+      SELECT 1 FROM test_users AS original
+      WHERE original.deleted_at IS NULL
+        AND LOWER(original.email) = LOWER(duplicate.email)
+        AND original.id < duplicate.id
+  );
+
+DROP INDEX IF EXISTS idx_test_users_email_live;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_test_users_email_lower_live
+    ON test_users (LOWER(email)) WHERE deleted_at IS NULL;
@@ -0,0 +1,46 @@
+-- Leave updated_at and history alone on updates that change nothing
+-- In a BEFORE trigger the generated search_vector of NEW is still NULL, so
+-- comparing whole rows saw every update as a change. Compare the stored
+-- fields only, without derived and bookkeeping columns.
+
+CREATE OR REPLACE FUNCTION test_users_fields(row_data test_users) RETURNS JSONB AS $$
+    SELECT to_jsonb(row_data) - 'search_vector' - 'updated_at';
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION touch_test_users_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    IF test_users_fields(OLD) IS DISTINCT FROM test_users_fields(NEW) THEN
+        NEW.updated_at := NOW();
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION record_test_users_history() RETURNS TRIGGER AS $$
+DECLARE
+    target_id INTEGER;
+    snapshot JSONB;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        target_id := OLD.id;
+        snapshot := test_users_fields(OLD);
+    ELSE
+        IF TG_OP = 'UPDATE' AND test_users_fields(OLD) = test_users_fields(NEW) THEN
+            RETURN NEW;
+        END IF;
+        target_id := NEW.id;
+        snapshot := test_users_fields(NEW);
+    END IF;
+
+    INSERT INTO test_users_history (user_id, version, operation, data, changed_by)
+    VALUES (
+        target_id,
+        COALESCE((SELECT MAX(version) FROM test_users_history WHERE user_id = target_id), 0) + 1,
+        TG_OP,
+        snapshot,
+        NULLIF(current_setting('app.actor', true), '')
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,10 @@
+-- Optional profile fields
+-- Validated in the API: phone is E.164, timezone an IANA name and locale a
+-- BCP 47 tag; the column sizes only cap what those formats allow
+
+ALTER TABLE test_users
+    ADD COLUMN IF NOT EXISTS display_name VARCHAR(100),
+    ADD COLUMN IF NOT EXISTS bio VARCHAR(500),
+    ADD COLUMN IF NOT EXISTS phone VARCHAR(16),
+    ADD COLUMN IF NOT EXISTS timezone VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS locale VARCHAR(35);
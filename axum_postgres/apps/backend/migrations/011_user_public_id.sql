@@ -0,0 +1,30 @@
+-- Non-sequential public ids
+-- Builds with the `uuid-ids` feature show public_id to clients instead of
+-- the serial id, so ids cannot be enumerated. The serial id stays the row
+-- key for joins and history. Values are UUIDv7: time-ordered like the
+-- serial, which keeps index inserts local.
+
+-- RFC 9562 version 7: 48-bit Unix milliseconds followed by random bits.
+-- Starts from a random v4 UUID, overwrites the timestamp and turns the
+-- version nibble 0100 into 0111; the variant bits are already right.
+CREATE OR REPLACE FUNCTION uuid_generate_v7() RETURNS UUID AS $$
+    SELECT encode(
+        set_bit(
+            set_bit(
+                overlay(
+                    uuid_send(gen_random_uuid())
+                    PLACING substring(int8send(floor(extract(epoch FROM clock_timestamp()) * 1000)::BIGINT) FROM 3)
+                    FROM 1 FOR 6
+                ),
+                52, 1
+            ),
+            53, 1
+        ),
+        'hex'
+    )::UUID;
+$$ LANGUAGE sql VOLATILE;
+
+ALTER TABLE test_users
+    ADD COLUMN IF NOT EXISTS public_id UUID NOT NULL DEFAULT uuid_generate_v7();
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_test_users_public_id ON test_users (public_id);
@@ -0,0 +1,10 @@
+-- Passwords for users who sign in through /api/auth
+-- Kept out of test_users so the hash never reaches responses, history
+-- snapshots or events. Users created through /api/users or SCIM have no
+-- row here and cannot sign in.
+CREATE TABLE IF NOT EXISTS user_credentials (
+    user_id INTEGER PRIMARY KEY REFERENCES test_users (id) ON DELETE CASCADE,
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,17 @@
+-- API keys for service-to-service clients
+-- Clients send the key in X-API-Key instead of signing in. Only a SHA-256
+-- hash is stored; the prefix identifies a key in listings without
+-- revealing it. Scopes limit what a key may do, e.g. {users:write}.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    key_hash BYTEA NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    created_by INTEGER REFERENCES test_users (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_created_by ON api_keys (created_by);
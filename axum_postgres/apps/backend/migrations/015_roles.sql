@@ -0,0 +1,25 @@
+-- Roles granted to users, checked by auth::role::RequireRole
+-- Roles are managed under /api/admin/roles by admins. Grant the first
+-- admin by hand:
+--   INSERT INTO user_roles (user_id, role_id)
+--   SELECT u.id, r.id FROM test_users u, roles r
+--   WHERE u.email = 'admin@example.com' AND r.name = 'admin';
+CREATE TABLE IF NOT EXISTS roles (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    description TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO roles (name, description)
+VALUES ('admin', 'Delete and import users, and grant roles')
+ON CONFLICT (name) DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id INTEGER NOT NULL REFERENCES test_users (id) ON DELETE CASCADE,
+    role_id INTEGER NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    granted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_roles_role_id ON user_roles (role_id);
@@ -0,0 +1,23 @@
+-- Sign-in with Google and GitHub
+-- oauth_states holds each started sign-in until the provider redirects
+-- back: the SHA-256 of the `state` parameter and the PKCE code verifier.
+CREATE TABLE IF NOT EXISTS oauth_states (
+    state_hash BYTEA PRIMARY KEY,
+    provider TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+-- Provider accounts linked to local users; `subject` is the provider's
+-- stable account id, so a changed email still finds the same user
+CREATE TABLE IF NOT EXISTS user_identities (
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES test_users (id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_identities_user_id ON user_identities (user_id);
@@ -0,0 +1,14 @@
+-- Cookie sessions, used instead of JWTs when AUTH_MODE=cookie
+-- The session cookie is stored as its SHA-256 hash. Each authenticated
+-- request pushes expires_at forward, so sessions end after a stretch of
+-- inactivity rather than at a fixed time.
+CREATE TABLE IF NOT EXISTS sessions (
+    id_hash BYTEA PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES test_users (id) ON DELETE CASCADE,
+    csrf_token TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions (user_id);
@@ -0,0 +1,23 @@
+-- Whether the user proved they receive mail at their address
+-- Users from before verification existed count as verified; new users
+-- start unverified until they follow the link from the verification email
+
+ALTER TABLE test_users
+    ADD COLUMN IF NOT EXISTS email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE test_users ALTER COLUMN email_verified SET DEFAULT FALSE;
+
+-- A new address has to be verified again, whichever path changed it;
+-- changing only the letter case keeps the verification
+CREATE OR REPLACE FUNCTION unverify_changed_test_users_email() RETURNS TRIGGER AS $$
+BEGIN
+    IF LOWER(NEW.email) IS DISTINCT FROM LOWER(OLD.email) THEN
+        NEW.email_verified := FALSE;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_users_email_unverified ON test_users;
+CREATE TRIGGER test_users_email_unverified
+    BEFORE UPDATE ON test_users
+    FOR EACH ROW EXECUTE FUNCTION unverify_changed_test_users_email();
@@ -0,0 +1,22 @@
+-- Links mailed by forgot-password, stored as SHA-256 hashes of the token
+-- A token is deleted when it is used, and a successful reset deletes the
+-- user's other tokens too, so every link works at most once.
+CREATE TABLE IF NOT EXISTS password_resets (
+    token_hash BYTEA PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES test_users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_resets_user ON password_resets (user_id);
+
+-- Recent forgot-password requests by the SHA-256 of the normalized email,
+-- whether or not a user has it, for rate limiting
+CREATE TABLE IF NOT EXISTS password_reset_requests (
+    id BIGSERIAL PRIMARY KEY,
+    email_hash BYTEA NOT NULL,
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_reset_requests_email
+    ON password_reset_requests (email_hash, requested_at);
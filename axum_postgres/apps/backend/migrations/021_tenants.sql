@@ -0,0 +1,33 @@
+-- Tenants for soft multi-tenancy
+-- Every user belongs to a tenant, and the repositories scope their queries
+-- to the tenant of the request. Existing rows join the default tenant,
+-- which requests naming no tenant belong to; tenant_id defaults to it so
+-- manual SQL keeps working.
+
+CREATE TABLE IF NOT EXISTS tenants (
+    id SERIAL PRIMARY KEY,
+    slug VARCHAR(63) NOT NULL UNIQUE,
+    name VARCHAR(255) NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO tenants (id, slug, name) VALUES (1, 'default', 'Default')
+ON CONFLICT (id) DO NOTHING;
+SELECT setval(pg_get_serial_sequence('tenants', 'id'), (SELECT MAX(id) FROM tenants));
+
+ALTER TABLE test_users
+    ADD COLUMN IF NOT EXISTS tenant_id INTEGER NOT NULL DEFAULT 1 REFERENCES tenants (id);
+ALTER TABLE audit_log
+    ADD COLUMN IF NOT EXISTS tenant_id INTEGER NOT NULL DEFAULT 1 REFERENCES tenants (id);
+
+-- Emails are unique among the live users of a tenant only
+DROP INDEX IF EXISTS idx_test_users_email_lower_live;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_test_users_tenant_email_lower_live
+    ON test_users (tenant_id, LOWER(email)) WHERE deleted_at IS NULL;
+
+-- Keyset pages of one tenant
+CREATE INDEX IF NOT EXISTS idx_test_users_tenant_created_at_id
+    ON test_users (tenant_id, created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_tenant ON audit_log (tenant_id, id);
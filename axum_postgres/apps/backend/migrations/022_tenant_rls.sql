@@ -0,0 +1,41 @@
+-- Row-level security for tenants
+-- With TENANT_ISOLATION=rls every pooled connection carries the tenant of
+-- the request in app.tenant_id, and these policies hide and refuse rows of
+-- other tenants. Connections that never set it (query isolation, psql,
+-- migrations) are not restricted, and neither are superusers, roles with
+-- BYPASSRLS or the owner of the tables: RLS mode needs the application to
+-- connect as a role of its own.
+
+-- Tenant of the connection, NULL when it is not set
+CREATE OR REPLACE FUNCTION current_tenant_id() RETURNS INTEGER AS $$
+    SELECT NULLIF(current_setting('app.tenant_id', true), '')::INTEGER
+$$ LANGUAGE sql STABLE;
+
+-- Rows inserted without a tenant join the connection's
+ALTER TABLE test_users ALTER COLUMN tenant_id SET DEFAULT COALESCE(current_tenant_id(), 1);
+ALTER TABLE audit_log ALTER COLUMN tenant_id SET DEFAULT COALESCE(current_tenant_id(), 1);
+
+ALTER TABLE test_users ENABLE ROW LEVEL SECURITY;
+DROP POLICY IF EXISTS tenant_isolation ON test_users;
+CREATE POLICY tenant_isolation ON test_users
+    USING (current_tenant_id() IS NULL OR tenant_id = current_tenant_id())
+    WITH CHECK (current_tenant_id() IS NULL OR tenant_id = current_tenant_id());
+
+ALTER TABLE audit_log ENABLE ROW LEVEL SECURITY;
+DROP POLICY IF EXISTS tenant_isolation ON audit_log;
+CREATE POLICY tenant_isolation ON audit_log
+    USING (current_tenant_id() IS NULL OR tenant_id = current_tenant_id())
+    WITH CHECK (current_tenant_id() IS NULL OR tenant_id = current_tenant_id());
+
+-- History rows from before tenants belong to the default tenant
+ALTER TABLE test_users_history ENABLE ROW LEVEL SECURITY;
+DROP POLICY IF EXISTS tenant_isolation ON test_users_history;
+CREATE POLICY tenant_isolation ON test_users_history
+    USING (
+        current_tenant_id() IS NULL
+        OR COALESCE((data->>'tenant_id')::INTEGER, 1) = current_tenant_id()
+    )
+    WITH CHECK (
+        current_tenant_id() IS NULL
+        OR COALESCE((data->>'tenant_id')::INTEGER, 1) = current_tenant_id()
+    );
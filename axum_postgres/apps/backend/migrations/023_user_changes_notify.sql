@@ -0,0 +1,32 @@
+-- Change feed: NOTIFY user_changes for every user row that changes
+-- Notifications are delivered when the transaction commits, and not at all
+-- when it rolls back. The payload names the row only; listeners read the
+-- row itself when they need it. Updates that change nothing stay quiet.
+
+CREATE OR REPLACE FUNCTION notify_test_users_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed test_users;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed := OLD;
+    ELSE
+        IF TG_OP = 'UPDATE' AND test_users_fields(OLD) = test_users_fields(NEW) THEN
+            RETURN NULL;
+        END IF;
+        changed := NEW;
+    END IF;
+
+    PERFORM pg_notify('user_changes', json_build_object(
+        'op', TG_OP,
+        'id', changed.id,
+        'public_id', changed.public_id,
+        'tenant_id', changed.tenant_id
+    )::TEXT);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_users_notify_change ON test_users;
+CREATE TRIGGER test_users_notify_change
+    AFTER INSERT OR UPDATE OR DELETE ON test_users
+    FOR EACH ROW EXECUTE FUNCTION notify_test_users_change();
@@ -0,0 +1,29 @@
+-- Change feed: say whether the changed user is deleted
+-- Deleting a user through the API only sets deleted_at, which the feed
+-- reports as an UPDATE like any other. The `deleted` flag tells listeners
+-- the user is gone without reading the row; it is true for soft-deleted
+-- rows and for rows deleted for good.
+
+CREATE OR REPLACE FUNCTION notify_test_users_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed test_users;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed := OLD;
+    ELSE
+        IF TG_OP = 'UPDATE' AND test_users_fields(OLD) = test_users_fields(NEW) THEN
+            RETURN NULL;
+        END IF;
+        changed := NEW;
+    END IF;
+
+    PERFORM pg_notify('user_changes', json_build_object(
+        'op', TG_OP,
+        'id', changed.id,
+        'public_id', changed.public_id,
+        'tenant_id', changed.tenant_id,
+        'deleted', TG_OP = 'DELETE' OR changed.deleted_at IS NOT NULL
+    )::TEXT);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
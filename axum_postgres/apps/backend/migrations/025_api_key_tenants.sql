@@ -0,0 +1,12 @@
+-- API keys belong to a tenant
+-- A key only authenticates requests of the tenant it was created in, like
+-- the tokens and sessions of its creator. Existing keys join their
+-- creator's tenant, and the default tenant when the creator is gone.
+
+ALTER TABLE api_keys
+    ADD COLUMN IF NOT EXISTS tenant_id INTEGER NOT NULL DEFAULT 1 REFERENCES tenants (id);
+
+UPDATE api_keys
+SET tenant_id = test_users.tenant_id
+FROM test_users
+WHERE test_users.id = api_keys.created_by AND api_keys.tenant_id <> test_users.tenant_id;
@@ -0,0 +1,11 @@
+-- API key expiry and rotation
+-- A key stops authenticating at expires_at, if set. Admins set it to end a
+-- key at once, and rotating a key sets it to the end of a grace period in
+-- which clients move to the key named by replaced_by. last_used_at is no
+-- longer written on every request but in batches, so it lags a little.
+
+ALTER TABLE api_keys
+    ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS replaced_by BIGINT REFERENCES api_keys (id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_tenant_id ON api_keys (tenant_id, id DESC);
@@ -0,0 +1,26 @@
+-- Progress of user imports
+-- POST /api/users/import commits the users of a file batch by batch and
+-- writes how far it got after each, so a large upload can be followed from
+-- any replica by its request ID. A failed import keeps the batches it
+-- committed; uploading the file again skips their rows as taken.
+
+CREATE TABLE IF NOT EXISTS user_imports (
+    id BIGSERIAL PRIMARY KEY,
+    tenant_id INTEGER NOT NULL DEFAULT COALESCE(current_tenant_id(), 1) REFERENCES tenants (id),
+    request_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'completed', 'failed')),
+    processed BIGINT NOT NULL DEFAULT 0,
+    created BIGINT NOT NULL DEFAULT 0,
+    rejected BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    UNIQUE (tenant_id, request_id)
+);
+
+ALTER TABLE user_imports ENABLE ROW LEVEL SECURITY;
+DROP POLICY IF EXISTS tenant_isolation ON user_imports;
+CREATE POLICY tenant_isolation ON user_imports
+    USING (current_tenant_id() IS NULL OR tenant_id = current_tenant_id())
+    WITH CHECK (current_tenant_id() IS NULL OR tenant_id = current_tenant_id());
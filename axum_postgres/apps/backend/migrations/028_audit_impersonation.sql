@@ -0,0 +1,4 @@
+-- Admin behind a change made with an impersonation token
+-- actor stays the impersonated user, who the change was made as; this
+-- records who actually made it, as `user:<id>`. NULL for every other change.
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS impersonated_by TEXT;
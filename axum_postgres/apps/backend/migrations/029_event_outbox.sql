@@ -0,0 +1,53 @@
+-- Outbox of user changes for the event publisher
+-- Notifications are lost while nobody listens; outbox rows are written in
+-- the transaction of the change and stay until the publisher deleted them
+-- after the broker confirmed their events, so a publisher that restarts or
+-- takes over from another replica carries on where the last one stopped.
+-- Rows are only written once a publisher has enabled the outbox, so
+-- deployments without one do not collect them. To stop publishing for good:
+--   UPDATE event_outbox_state SET enabled = FALSE; TRUNCATE event_outbox;
+
+CREATE TABLE IF NOT EXISTS event_outbox_state (
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (singleton),
+    enabled BOOLEAN NOT NULL DEFAULT FALSE
+);
+INSERT INTO event_outbox_state DEFAULT VALUES ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS event_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    -- The user_changes notification payload
+    change JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE OR REPLACE FUNCTION enqueue_test_users_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed test_users;
+BEGIN
+    IF NOT COALESCE((SELECT enabled FROM event_outbox_state), FALSE) THEN
+        RETURN NULL;
+    END IF;
+    IF TG_OP = 'DELETE' THEN
+        changed := OLD;
+    ELSE
+        IF TG_OP = 'UPDATE' AND test_users_fields(OLD) = test_users_fields(NEW) THEN
+            RETURN NULL;
+        END IF;
+        changed := NEW;
+    END IF;
+
+    INSERT INTO event_outbox (change) VALUES (jsonb_build_object(
+        'op', TG_OP,
+        'id', changed.id,
+        'public_id', changed.public_id,
+        'tenant_id', changed.tenant_id,
+        'deleted', TG_OP = 'DELETE' OR changed.deleted_at IS NOT NULL
+    ));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_users_enqueue_change ON test_users;
+CREATE TRIGGER test_users_enqueue_change
+    AFTER INSERT OR UPDATE OR DELETE ON test_users
+    FOR EACH ROW EXECUTE FUNCTION enqueue_test_users_change();
@@ -0,0 +1,127 @@
+-- test_users for the SQLite repository (cargo feature `sqlite`)
+-- Mirrors the Postgres schema as of 020: the same columns, the same
+-- case-insensitive uniqueness of live emails, and history, updated_at,
+-- email verification and collection versions kept by triggers.
+-- Timestamps are stored as RFC 3339 text in UTC with millisecond precision.
+
+CREATE TABLE IF NOT EXISTS test_users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- UUIDv7: 48-bit Unix milliseconds, version, variant and random bits
+    public_id BLOB NOT NULL UNIQUE DEFAULT (unhex(
+        printf('%012X', CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER))
+        || printf('7%03X', abs(random()) % 4096)
+        || printf('%X%03X', 8 + abs(random()) % 4, abs(random()) % 4096)
+        || hex(randomblob(6))
+    )),
+    name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    display_name TEXT,
+    bio TEXT,
+    phone TEXT,
+    timezone TEXT,
+    locale TEXT,
+    email_verified BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    deleted_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_test_users_created_at_id ON test_users (created_at DESC, id DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_test_users_email_lower_live
+    ON test_users (LOWER(email)) WHERE deleted_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS test_users_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    operation TEXT NOT NULL,
+    data TEXT NOT NULL,
+    changed_by TEXT,
+    changed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    UNIQUE (user_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS collection_versions (
+    name TEXT PRIMARY KEY,
+    version INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO collection_versions (name) VALUES ('test_users')
+ON CONFLICT (name) DO NOTHING;
+
+-- Stored fields of each row as history records them: the JSON of the
+-- Postgres snapshot, without updated_at
+CREATE VIEW IF NOT EXISTS test_users_snapshot AS
+SELECT id, json_object(
+    'id', id,
+    'public_id', lower(
+        substr(hex(public_id), 1, 8) || '-' || substr(hex(public_id), 9, 4) || '-'
+        || substr(hex(public_id), 13, 4) || '-' || substr(hex(public_id), 17, 4) || '-'
+        || substr(hex(public_id), 21)
+    ),
+    'name', name,
+    'email', email,
+    'active', json(CASE WHEN active THEN 'true' ELSE 'false' END),
+    'display_name', display_name,
+    'bio', bio,
+    'phone', phone,
+    'timezone', timezone,
+    'locale', locale,
+    'email_verified', json(CASE WHEN email_verified THEN 'true' ELSE 'false' END),
+    'created_at', created_at,
+    'deleted_at', deleted_at
+) AS data
+FROM test_users;
+
+CREATE TRIGGER IF NOT EXISTS test_users_inserted AFTER INSERT ON test_users
+BEGIN
+    INSERT INTO test_users_history (user_id, version, operation, data)
+    SELECT id, 1, 'INSERT', data FROM test_users_snapshot WHERE id = NEW.id;
+    UPDATE collection_versions SET version = version + 1 WHERE name = 'test_users';
+END;
+
+-- SQLite triggers cannot assign NEW, so the row is touched after the fact.
+-- Updates that change nothing leave updated_at and history alone; a new
+-- email address, beyond its letter case, has to be verified again.
+CREATE TRIGGER IF NOT EXISTS test_users_updated AFTER UPDATE ON test_users
+WHEN OLD.public_id IS NOT NEW.public_id
+    OR OLD.name IS NOT NEW.name
+    OR OLD.email IS NOT NEW.email
+    OR OLD.active IS NOT NEW.active
+    OR OLD.display_name IS NOT NEW.display_name
+    OR OLD.bio IS NOT NEW.bio
+    OR OLD.phone IS NOT NEW.phone
+    OR OLD.timezone IS NOT NEW.timezone
+    OR OLD.locale IS NOT NEW.locale
+    OR OLD.email_verified IS NOT NEW.email_verified
+    OR OLD.created_at IS NOT NEW.created_at
+    OR OLD.deleted_at IS NOT NEW.deleted_at
+BEGIN
+    UPDATE test_users
+    SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+        email_verified = CASE
+            WHEN LOWER(OLD.email) IS NOT LOWER(NEW.email) THEN FALSE
+            ELSE email_verified
+        END
+    WHERE id = NEW.id;
+    INSERT INTO test_users_history (user_id, version, operation, data)
+    SELECT id,
+        COALESCE((SELECT MAX(version) FROM test_users_history WHERE user_id = NEW.id), 0) + 1,
+        'UPDATE',
+        data
+    FROM test_users_snapshot WHERE id = NEW.id;
+    UPDATE collection_versions SET version = version + 1 WHERE name = 'test_users';
+END;
+
+-- BEFORE, while the row to snapshot is still there
+CREATE TRIGGER IF NOT EXISTS test_users_deleted BEFORE DELETE ON test_users
+BEGIN
+    INSERT INTO test_users_history (user_id, version, operation, data)
+    SELECT id,
+        COALESCE((SELECT MAX(version) FROM test_users_history WHERE user_id = OLD.id), 0) + 1,
+        'DELETE',
+        data
+    FROM test_users_snapshot WHERE id = OLD.id;
+    UPDATE collection_versions SET version = version + 1 WHERE name = 'test_users';
+END;
@@ -0,0 +1,382 @@
+//! Audit log of the changes made through the API
+//!
+//! Mutating handlers take an [`Audit`] and record an [`AuditEvent`] once the
+//! change is stored: who made it, the entity before and after with the
+//! fields that changed, and the ID and client IP of the request. Admins read
+//! the log at `GET /api/admin/audit-log`.
+//!
+//! Recording is best effort. The change is already committed when it is
+//! recorded, so a failed insert is logged instead of failing the request.
+
+use std::convert::Infallible;
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::auth::{AuthUser, Principal};
+use crate::middleware::client_ip;
+use crate::middleware::request_id::{RequestId, REQUEST_ID_HEADER};
+use crate::models::user::User;
+use crate::models::user_history::diff;
+use crate::models::user_id::UserId;
+use crate::repository::audit::{AuditRepository, AuditRepositoryTrait, NewAuditEntry};
+
+/// Kinds of entity in the log
+pub mod entity {
+    pub const USER: &str = "user";
+    /// A user's password; never logged, only that it changed
+    pub const PASSWORD: &str = "password";
+    pub const API_KEY: &str = "api_key";
+    /// A role held by a user, with ID `<role>:<user id>`
+    pub const ROLE_GRANT: &str = "role_grant";
+    pub const READ_ONLY_MODE: &str = "read_only_mode";
+    /// An admin obtaining a token to act as a user, with the user's ID
+    pub const IMPERSONATION: &str = "impersonation";
+    pub const TENANT: &str = "tenant";
+}
+
+/// Actor of changes made by the SCIM identity provider
+pub const SCIM_ACTOR: &str = "scim";
+/// Actor of changes made with ADMIN_TOKEN
+pub const ADMIN_TOKEN_ACTOR: &str = "admin_token";
+
+/// What was done to the entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+        }
+    }
+}
+
+/// Actor name of a signed-in user, `user:<id>`
+pub fn user_actor(id: UserId) -> String {
+    format!("user:{}", id)
+}
+
+/// Actor name of a principal: `user:<id>`, or `api_key:<id>` for API keys
+pub fn principal_actor(principal: &Principal) -> String {
+    match principal {
+        Principal::User { id, .. } => user_actor(*id),
+        Principal::ApiKey(key) => format!("api_key:{}", key.id),
+    }
+}
+
+/// One change to record
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEvent {
+    /// None for unauthenticated requests, such as registration
+    pub actor: Option<String>,
+    /// Admin who made the change with an impersonation token for `actor`
+    pub impersonated_by: Option<String>,
+    pub entity: &'static str,
+    pub entity_id: String,
+    pub action: AuditAction,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, entity: &'static str, entity_id: impl ToString) -> Self {
+        Self {
+            actor: None,
+            impersonated_by: None,
+            entity,
+            entity_id: entity_id.to_string(),
+            action,
+            before: None,
+            after: None,
+        }
+    }
+
+    /// Event about a user, identified by the ID clients know
+    pub fn user(action: AuditAction, user: &User) -> Self {
+        Self::new(action, entity::USER, user.user_id())
+    }
+
+    pub fn by(mut self, actor: String) -> Self {
+        self.actor = Some(actor);
+        self
+    }
+
+    /// Made by the principal; with an impersonation token the actor is the
+    /// impersonated user and the admin is recorded as `impersonated_by`
+    pub fn by_principal(mut self, principal: &Principal) -> Self {
+        if let Principal::User { act: Some(admin_id), .. } = principal {
+            self.impersonated_by = Some(user_actor(*admin_id));
+        }
+        self.by(principal_actor(principal))
+    }
+
+    /// Made by the signed-in user, see [`AuditEvent::by_principal`]
+    pub fn by_user(self, user: AuthUser) -> Self {
+        self.by_principal(&Principal::from(user))
+    }
+
+    /// The entity as it was, serialized as the API shows it
+    pub fn before(mut self, entity: impl Serialize) -> Self {
+        self.before = serde_json::to_value(entity).ok();
+        self
+    }
+
+    /// The entity as it is now, serialized as the API shows it
+    pub fn after(mut self, entity: impl Serialize) -> Self {
+        self.after = serde_json::to_value(entity).ok();
+        self
+    }
+
+    /// Fields that differ between `before` and `after`
+    pub fn changes(&self) -> Value {
+        let null = Value::Null;
+        let changes = diff(
+            self.before.as_ref().unwrap_or(&null),
+            self.after.as_ref().unwrap_or(&null),
+        );
+        serde_json::to_value(changes).unwrap_or_default()
+    }
+}
+
+/// Writes events to the audit_log table
+#[derive(Clone)]
+pub struct AuditService {
+    pool: PgPool,
+}
+
+impl AuditService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Store an event for the request described by `context`
+    pub async fn record(&self, event: AuditEvent, context: &AuditContext) {
+        let entry = entry(event, context);
+        let (entity, entity_id, action) = (entry.entity, entry.entity_id.clone(), entry.action);
+        if let Err(e) = AuditRepository::new(self.pool.clone()).insert(entry).await {
+            tracing::error!(
+                "Failed to record audit event {} {} {}: {:?}",
+                action,
+                entity,
+                entity_id,
+                e
+            );
+        }
+    }
+
+    /// Store events for the request described by `context` with a single
+    /// insert, for changes made in bulk
+    pub async fn record_all(&self, events: Vec<AuditEvent>, context: &AuditContext) {
+        let count = events.len();
+        let entries = events.into_iter().map(|event| entry(event, context)).collect();
+        if let Err(e) = AuditRepository::new(self.pool.clone()).insert_many(entries).await {
+            tracing::error!("Failed to record {} audit events: {:?}", count, e);
+        }
+    }
+}
+
+/// Row to store for `event`
+fn entry(event: AuditEvent, context: &AuditContext) -> NewAuditEntry {
+    NewAuditEntry {
+        changes: event.changes(),
+        actor: event.actor,
+        impersonated_by: event.impersonated_by,
+        entity: event.entity,
+        entity_id: event.entity_id,
+        action: event.action.as_str(),
+        before: event.before,
+        after: event.after,
+        request_id: context.request_id.clone(),
+        ip: context.ip.clone(),
+    }
+}
+
+/// Request an event was caused by
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditContext {
+    /// ID assigned by `middleware::request_id`, else the `X-Request-Id`
+    /// sent with the request
+    pub request_id: Option<String>,
+    /// First address in `X-Forwarded-For`, else the peer address when the
+    /// server was started with connect info
+    pub ip: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuditContext
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = |name: &str| {
+            parts
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        Ok(Self {
+            request_id: parts
+                .extensions
+                .get::<RequestId>()
+                .map(|id| id.0.clone())
+                .or_else(|| header(REQUEST_ID_HEADER).map(str::to_string)),
+            ip: client_ip(&parts.extensions),
+        })
+    }
+}
+
+/// [`AuditService`] with the request context, taken by mutating handlers
+#[derive(Clone)]
+pub struct Audit {
+    pub service: AuditService,
+    pub context: AuditContext,
+}
+
+impl Audit {
+    pub async fn record(&self, event: AuditEvent) {
+        self.service.record(event, &self.context).await
+    }
+
+    pub async fn record_all(&self, events: Vec<AuditEvent>) {
+        self.service.record_all(events, &self.context).await
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Audit
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let context = AuditContext::from_request_parts(parts, state).await?;
+        Ok(Self {
+            service: AuditService::new(PgPool::from_ref(state)),
+            context,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    use axum::{extract::ConnectInfo, http::Request};
+
+    use crate::middleware::proxy::ClientIp;
+    use serde_json::json;
+
+    #[test]
+    fn test_changes_list_changed_fields() {
+        let event = AuditEvent::new(AuditAction::Update, entity::USER, 7)
+            .before(json!({"name": "Jane", "active": true}))
+            .after(json!({"name": "Janet", "active": true}));
+        assert_eq!(event.entity_id, "7");
+        assert_eq!(
+            event.changes(),
+            json!({"name": {"from": "Jane", "to": "Janet"}})
+        );
+
+        let created =
+            AuditEvent::new(AuditAction::Create, entity::USER, 7).after(json!({"name": "Jane"}));
+        assert_eq!(
+            created.changes(),
+            json!({"name": {"from": null, "to": "Jane"}})
+        );
+        assert_eq!(
+            AuditEvent::new(AuditAction::Update, entity::PASSWORD, 7).changes(),
+            json!({})
+        );
+    }
+
+    #[test]
+    fn test_entries_keep_who_made_the_change() {
+        use crate::auth::api_key::ApiKeyPrincipal;
+
+        let context = AuditContext {
+            request_id: Some("import-1".to_string()),
+            ip: None,
+        };
+        let (user, admin) = (UserId::new(Default::default()), UserId::new(Default::default()));
+        let impersonated = Principal::User {
+            id: user,
+            act: Some(admin),
+        };
+        let key = Principal::ApiKey(ApiKeyPrincipal {
+            id: 9,
+            name: "Import".to_string(),
+            scopes: Vec::new(),
+            created_by: None,
+        });
+
+        // Bulk changes are stored as entries like single ones
+        let created = |principal: &Principal| {
+            entry(
+                AuditEvent::new(AuditAction::Create, entity::USER, 7).by_principal(principal),
+                &context,
+            )
+        };
+        let entry = created(&impersonated);
+        assert_eq!(entry.actor, Some(user_actor(user)));
+        assert_eq!(entry.impersonated_by, Some(user_actor(admin)));
+        assert_eq!(entry.request_id.as_deref(), Some("import-1"));
+        let entry = created(&key);
+        assert_eq!(entry.actor.as_deref(), Some("api_key:9"));
+        assert_eq!(entry.impersonated_by, None);
+    }
+
+    #[tokio::test]
+    async fn test_context_prefers_the_resolved_client_ip() {
+        let (mut parts, _) = Request::builder()
+            .header("x-request-id", "req-1")
+            .header("x-forwarded-for", "198.51.100.9")
+            .extension(ClientIp("203.0.113.7".parse().unwrap()))
+            .body(())
+            .unwrap()
+            .into_parts();
+        parts
+            .extensions
+            .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 4000))));
+        let context = AuditContext::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(context.request_id.as_deref(), Some("req-1"));
+        assert_eq!(context.ip.as_deref(), Some("203.0.113.7"));
+
+        parts.headers.clear();
+        parts.extensions.remove::<ClientIp>();
+        let context = AuditContext::from_request_parts(&mut parts, &())
+            .await
+            .unwrap();
+        assert_eq!(
+            context,
+            AuditContext {
+                request_id: None,
+                ip: Some("127.0.0.1".to_string()),
+            }
+        );
+    }
+}
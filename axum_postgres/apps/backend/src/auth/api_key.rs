@@ -0,0 +1,247 @@
+//! API keys for service-to-service clients
+//!
+//! A key is `ak_` followed by 43 random base64url characters. Clients send
+//! it in `X-API-Key`; `middleware::api_key::authenticate` resolves it to an
+//! [`ApiKeyPrincipal`] whose scopes say which endpoints it may call. A key
+//! may be given `<resource>:*`, such as `users:*`, for every scope of the
+//! resource.
+//!
+//! When a key was last used is noted in [`ApiKeyUsage`] rather than
+//! written on every request, and stored in batches.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use crate::models::api_key::ApiKey;
+use crate::repository::api_keys::{ApiKeyRepository, ApiKeyRepositoryTrait};
+
+/// Header that carries the key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Start of every key
+const KEY_PREFIX: &str = "ak_";
+
+/// Characters of a key shown in listings: `ak_` and 8 more
+const DISPLAY_PREFIX_LEN: usize = 11;
+
+/// How long a rotated key keeps working unless the admin says otherwise
+pub const DEFAULT_ROTATION_GRACE_SECS: u32 = 24 * 60 * 60;
+
+/// Longest grace period of a rotated key: 30 days
+pub const MAX_ROTATION_GRACE_SECS: u32 = 30 * 24 * 60 * 60;
+
+/// How often noted uses of keys are written
+const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What an API key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Export users and list who holds a role
+    UsersRead,
+    /// Create, change and delete users
+    UsersWrite,
+    /// Import users from CSV
+    UsersImport,
+    /// Grant and revoke roles
+    RolesWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::UsersRead,
+        Scope::UsersWrite,
+        Scope::UsersImport,
+        Scope::RolesWrite,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::UsersRead => "users:read",
+            Self::UsersWrite => "users:write",
+            Self::UsersImport => "users:import",
+            Self::RolesWrite => "roles:write",
+        }
+    }
+
+    /// What the scope is about, such as `users`
+    pub fn resource(self) -> &'static str {
+        self.as_str().split(':').next().unwrap_or_default()
+    }
+
+    /// Scopes a key given `s` holds: that scope, or every scope of the
+    /// resource for `<resource>:*`
+    pub fn granted_by(s: &str) -> Result<Vec<Scope>, String> {
+        let Some(resource) = s.strip_suffix(":*") else {
+            return s.parse().map(|scope| vec![scope]);
+        };
+        let scopes: Vec<Scope> = Self::ALL
+            .into_iter()
+            .filter(|scope| scope.resource() == resource)
+            .collect();
+        if scopes.is_empty() {
+            return Err(format!("Unknown scope '{}'", s));
+        }
+        Ok(scopes)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("Unknown scope '{}'", s))
+    }
+}
+
+/// Client authenticated by an API key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyPrincipal {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Row key of the user who created the key, whose roles it acts with
+    pub created_by: Option<i32>,
+}
+
+impl From<ApiKey> for ApiKeyPrincipal {
+    /// Scopes this build does not know are dropped
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id,
+            name: key.name,
+            scopes: key
+                .scopes
+                .iter()
+                .filter_map(|s| Scope::granted_by(s).ok())
+                .flatten()
+                .collect(),
+            created_by: key.created_by,
+        }
+    }
+}
+
+/// A new random key
+pub fn generate() -> String {
+    let mut secret = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .expect("Failed to generate an API key");
+    format!("{}{}", KEY_PREFIX, URL_SAFE_NO_PAD.encode(secret))
+}
+
+/// Start of a key that identifies it in listings
+pub fn display_prefix(key: &str) -> &str {
+    key.get(..DISPLAY_PREFIX_LEN).unwrap_or(key)
+}
+
+/// When API keys were last used, not yet written to the database
+///
+/// Cheap to clone; clones share what was noted. [`ApiKeyUsage::spawn`]
+/// writes it once a minute in one statement, and once more on shutdown.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeyUsage {
+    used: Arc<Mutex<HashMap<i64, DateTime<Utc>>>>,
+}
+
+impl ApiKeyUsage {
+    /// Note that the key was used just now
+    pub fn record(&self, id: i64) {
+        self.used.lock().unwrap().insert(id, Utc::now());
+    }
+
+    /// Write what was noted since the last flush
+    ///
+    /// What fails to be written is kept for the next flush, unless the key
+    /// was used again since.
+    pub async fn flush(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let used = std::mem::take(&mut *self.used.lock().unwrap());
+        if used.is_empty() {
+            return Ok(());
+        }
+        let (ids, times): (Vec<i64>, Vec<DateTime<Utc>>) =
+            used.iter().map(|(id, at)| (*id, *at)).unzip();
+        if let Err(e) = ApiKeyRepository::new(pool.clone())
+            .record_use(&ids, &times)
+            .await
+        {
+            let mut pending = self.used.lock().unwrap();
+            for (id, at) in used {
+                pending.entry(id).or_insert(at);
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Flush every minute until shutdown, and then once more
+    pub fn spawn(&self, pool: &PgPool, mut shutdown: watch::Receiver<bool>) {
+        let (usage, pool) = (self.clone(), pool.clone());
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(USAGE_FLUSH_INTERVAL);
+            loop {
+                let stopping = tokio::select! {
+                    _ = interval.tick() => false,
+                    _ = shutdown.changed() => true,
+                };
+                if let Err(e) = usage.flush(&pool).await {
+                    tracing::warn!("Failed to store when API keys were last used: {}", e);
+                }
+                if stopping {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_and_scopes() {
+        let key = generate();
+        assert!(key.starts_with("ak_"));
+        assert_eq!(key.len(), 46);
+        assert_ne!(key, generate());
+        assert_eq!(display_prefix(&key), &key[..11]);
+
+        assert_eq!("users:write".parse::<Scope>(), Ok(Scope::UsersWrite));
+        assert_eq!(Scope::UsersImport.to_string(), "users:import");
+        assert!("users:everything".parse::<Scope>().is_err());
+
+        assert_eq!(
+            Scope::granted_by("roles:write"),
+            Ok(vec![Scope::RolesWrite])
+        );
+        assert_eq!(
+            Scope::granted_by("users:*"),
+            Ok(vec![
+                Scope::UsersRead,
+                Scope::UsersWrite,
+                Scope::UsersImport
+            ])
+        );
+        assert!(Scope::granted_by("projects:*").is_err());
+        assert!(Scope::granted_by("*").is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Sign-in with email and password, and JWT bearer tokens or cookie sessions
+//!
+//! `POST /api/auth/register` and `POST /api/auth/login` hand out an access
+//! and a refresh token, or with AUTH_MODE=cookie start a [`session`];
+//! handlers that take an [`AuthUser`] only run for requests with a valid
+//! access token or session. Handlers that take a [`Principal`]
+//! also accept an API key with the right [`Scope`](api_key::Scope), and
+//! [`RequireRole`](role::RequireRole) further limits them to users with a role.
+//! Finer rules, such as who may edit which user, are in [`policy`].
+
+pub mod api_key;
+pub mod oauth;
+pub mod password;
+pub mod password_reset;
+pub mod policy;
+pub mod role;
+pub mod session;
+pub mod token;
+pub mod verification;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+
+use crate::error::AppError;
+use crate::middleware::bearer_token;
+use crate::models::user_id::UserId;
+use crate::tenant;
+use api_key::{ApiKeyPrincipal, Scope};
+use session::{AuthMode, SessionUser};
+use token::{TokenKeys, TokenKind};
+
+/// User signed in with `Authorization: Bearer <access token>`, or with a
+/// session cookie when AUTH_MODE=cookie
+///
+/// Rejects the request with 401 when the token is missing, expired, not
+/// signed with JWT_SECRET, a refresh token or issued in another tenant than
+/// the request's. In cookie mode bearer tokens are not accepted, and
+/// requests without a live session of a user of the tenant get 401.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthUser {
+    pub id: UserId,
+    /// Tenant the user signed in to, which is the tenant of the request
+    pub tenant_id: i32,
+    /// Admin acting as the user, with an impersonation token
+    pub act: Option<UserId>,
+}
+
+impl AuthUser {
+    /// Reject impersonation tokens with 403, for what only the user may do
+    /// themselves, such as managing their credentials or deleting their
+    /// account
+    pub fn require_own_sign_in(&self, action: &str) -> Result<(), AppError> {
+        Principal::from(*self).require_own_sign_in(action)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    TokenKeys: FromRef<S>,
+    AuthMode: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if AuthMode::from_ref(state) == AuthMode::Cookie {
+            return parts
+                .extensions
+                .get::<SessionUser>()
+                .map(|session| Self {
+                    id: session.id,
+                    // Sessions are looked up among the users of the tenant
+                    tenant_id: tenant::current(),
+                    act: None,
+                })
+                .ok_or_else(|| AppError::Unauthorized("Missing or expired session".to_string()));
+        }
+        let token = bearer_token(&parts.headers)
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+        let claims = TokenKeys::from_ref(state)
+            .verify(token, TokenKind::Access)
+            .map_err(|e| {
+                tracing::warn!("Rejected access token: {}", e);
+                AppError::Unauthorized(e.to_string())
+            })?;
+        if claims.tid != tenant::current() {
+            tracing::warn!(
+                "Rejected access token of tenant {} for tenant {}",
+                claims.tid,
+                tenant::current()
+            );
+            return Err(AppError::Unauthorized(
+                "Token was issued for another tenant".to_string(),
+            ));
+        }
+        let id = claims
+            .sub
+            .parse()
+            .map_err(|_| AppError::Unauthorized("Malformed token".to_string()))?;
+        let act = match &claims.act {
+            Some(act) => {
+                let admin = act
+                    .sub
+                    .parse()
+                    .map_err(|_| AppError::Unauthorized("Malformed token".to_string()))?;
+                tracing::info!("Admin user ID {} is acting as user ID {}", admin, id);
+                Some(admin)
+            }
+            None => None,
+        };
+        Ok(Self {
+            id,
+            tenant_id: claims.tid,
+            act,
+        })
+    }
+}
+
+/// Caller of a write endpoint: a signed-in user or an API key
+///
+/// API keys are resolved by `middleware::api_key::authenticate`; requests
+/// without one need a valid access token or session, as for [`AuthUser`].
+#[derive(Debug, Clone)]
+pub enum Principal {
+    User {
+        id: UserId,
+        /// Admin acting as the user, with an impersonation token
+        act: Option<UserId>,
+    },
+    ApiKey(ApiKeyPrincipal),
+}
+
+impl From<AuthUser> for Principal {
+    fn from(user: AuthUser) -> Self {
+        Self::User {
+            id: user.id,
+            act: user.act,
+        }
+    }
+}
+
+impl Principal {
+    /// Reject API keys without `scope` with 403; scopes do not limit users
+    pub fn require(&self, scope: Scope) -> Result<(), AppError> {
+        match self {
+            Self::ApiKey(key) if !key.scopes.contains(&scope) => Err(AppError::Forbidden(format!(
+                "API key lacks the {} scope",
+                scope
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject impersonation tokens with 403, as
+    /// [`AuthUser::require_own_sign_in`]; API keys are never impersonations
+    pub fn require_own_sign_in(&self, action: &str) -> Result<(), AppError> {
+        match self {
+            Self::User {
+                id,
+                act: Some(admin),
+            } => {
+                tracing::warn!(
+                    "Admin user ID {} acting as user ID {} tried to {}",
+                    admin,
+                    id,
+                    action
+                );
+                Err(AppError::Forbidden(format!(
+                    "Impersonation tokens cannot {}",
+                    action
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Principal
+where
+    TokenKeys: FromRef<S>,
+    AuthMode: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(key) = parts.extensions.get::<ApiKeyPrincipal>() {
+            return Ok(Self::ApiKey(key.clone()));
+        }
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        Ok(user.into())
+    }
+}
@@ -0,0 +1,385 @@
+//! Sign-in with Google and GitHub (OAuth 2.0 authorization code flow)
+//!
+//! `GET /api/auth/oauth/{provider}/start` redirects to the provider with a
+//! random `state` and a PKCE challenge. The provider redirects back to
+//! `/callback` with a code, which is exchanged for the user's verified
+//! email. That email finds, links or creates the local user, who then gets
+//! the usual access and refresh tokens.
+//!
+//! The state is also set as an HttpOnly cookie, and the callback only
+//! accepts a state that matches the cookie. Otherwise anyone could send a
+//! victim the callback link of a sign-in they started, and sign the victim
+//! in to the attacker's account.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use axum::http::HeaderValue;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::session;
+
+/// How long a started sign-in may take to come back
+pub const STATE_TTL_SECS: i64 = 10 * 60;
+
+/// Cookie holding the state of the browser's sign-in
+pub const STATE_COOKIE: &str = "oauth_state";
+
+/// `Set-Cookie` value for the state, expiring with it
+pub fn state_cookie(state: &str) -> HeaderValue {
+    session::set_cookie(STATE_COOKIE, state, true, STATE_TTL_SECS)
+}
+
+/// `Set-Cookie` value that removes the state once used
+pub fn cleared_state_cookie() -> HeaderValue {
+    session::set_cookie(STATE_COOKIE, "", true, 0)
+}
+
+/// Identity provider users can sign in with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Google,
+    GitHub,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 2] = [Provider::Google, Provider::GitHub];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Google => "google",
+            Self::GitHub => "github",
+        }
+    }
+
+    /// Prefix of the provider's environment variables, e.g. `GOOGLE`
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Self::Google => "GOOGLE",
+            Self::GitHub => "GITHUB",
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Client registration and endpoints of one provider
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Our `/callback` URL, as registered with the provider
+    pub redirect_uri: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    /// Where GitHub lists the user's emails; unused for Google
+    pub emails_url: String,
+    pub scopes: String,
+}
+
+impl ProviderConfig {
+    /// The provider's public endpoints for this client
+    pub fn new(
+        provider: Provider,
+        client_id: String,
+        client_secret: String,
+        redirect_uri: String,
+    ) -> Self {
+        let (authorize_url, token_url, userinfo_url, emails_url, scopes) = match provider {
+            Provider::Google => (
+                "https://accounts.google.com/o/oauth2/v2/auth",
+                "https://oauth2.googleapis.com/token",
+                "https://openidconnect.googleapis.com/v1/userinfo",
+                "",
+                "openid email profile",
+            ),
+            Provider::GitHub => (
+                "https://github.com/login/oauth/authorize",
+                "https://github.com/login/oauth/access_token",
+                "https://api.github.com/user",
+                "https://api.github.com/user/emails",
+                "read:user user:email",
+            ),
+        };
+        Self {
+            client_id,
+            client_secret,
+            redirect_uri,
+            authorize_url: authorize_url.to_string(),
+            token_url: token_url.to_string(),
+            userinfo_url: userinfo_url.to_string(),
+            emails_url: emails_url.to_string(),
+            scopes: scopes.to_string(),
+        }
+    }
+
+    /// Where to send the user to sign in
+    pub fn authorize_redirect(&self, state: &str, code_verifier: &str) -> String {
+        reqwest::Url::parse_with_params(
+            &self.authorize_url,
+            [
+                ("response_type", "code"),
+                ("client_id", self.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("scope", self.scopes.as_str()),
+                ("state", state),
+                ("code_challenge", &pkce_challenge(code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map(String::from)
+        .unwrap_or_else(|_| self.authorize_url.clone())
+    }
+}
+
+/// Provider account behind a completed sign-in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    /// The provider's stable account id
+    pub subject: String,
+    /// Primary email, if the provider has verified it
+    pub verified_email: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Why a provider could not complete a sign-in
+#[derive(Debug)]
+pub enum OAuthError {
+    /// The code was not exchanged for a token
+    Exchange(String),
+    /// The token did not yield the user's profile
+    Profile(String),
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exchange(e) => write!(f, "Code exchange failed: {}", e),
+            Self::Profile(e) => write!(f, "Profile lookup failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OAuthError {}
+
+/// Configured providers and the HTTP client that talks to them
+#[derive(Debug, Clone, Default)]
+pub struct OAuthProviders {
+    configs: Arc<HashMap<Provider, ProviderConfig>>,
+    client: reqwest::Client,
+}
+
+impl OAuthProviders {
+    /// Providers with `<PROVIDER>_CLIENT_ID` and `<PROVIDER>_CLIENT_SECRET`
+    ///
+    /// Callback URLs are built from OAUTH_REDIRECT_BASE_URL, the public URL
+    /// of this API; without it no provider is enabled.
+    pub fn from_env() -> Self {
+        let base = std::env::var("OAUTH_REDIRECT_BASE_URL").ok();
+        let mut providers = Self::default();
+        for provider in Provider::ALL {
+            let prefix = provider.env_prefix();
+            let (Ok(client_id), Ok(client_secret)) = (
+                std::env::var(format!("{}_CLIENT_ID", prefix)),
+                std::env::var(format!("{}_CLIENT_SECRET", prefix)),
+            ) else {
+                continue;
+            };
+            let Some(base) = base.as_deref() else {
+                tracing::warn!(
+                    "{}_CLIENT_ID is set but OAUTH_REDIRECT_BASE_URL is not; {} sign-in disabled",
+                    prefix,
+                    provider
+                );
+                continue;
+            };
+            let redirect_uri = format!(
+                "{}/api/auth/oauth/{}/callback",
+                base.trim_end_matches('/'),
+                provider
+            );
+            providers = providers.with(
+                provider,
+                ProviderConfig::new(provider, client_id, client_secret, redirect_uri),
+            );
+        }
+        providers
+    }
+
+    pub fn with(mut self, provider: Provider, config: ProviderConfig) -> Self {
+        Arc::make_mut(&mut self.configs).insert(provider, config);
+        self
+    }
+
+    pub fn get(&self, provider: Provider) -> Option<&ProviderConfig> {
+        self.configs.get(&provider)
+    }
+
+    /// Trade an authorization code for the account it signed in
+    pub async fn identify(
+        &self,
+        provider: Provider,
+        config: &ProviderConfig,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<Identity, OAuthError> {
+        let access_token = self.exchange(config, code, code_verifier).await?;
+        match provider {
+            Provider::Google => self.google_identity(config, &access_token).await,
+            Provider::GitHub => self.github_identity(config, &access_token).await,
+        }
+    }
+
+    async fn exchange(
+        &self,
+        config: &ProviderConfig,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<String, OAuthError> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            access_token: Option<String>,
+            error: Option<String>,
+        }
+
+        let response: TokenResponse = self
+            .client
+            .post(&config.token_url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &config.redirect_uri),
+                ("client_id", &config.client_id),
+                ("client_secret", &config.client_secret),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| OAuthError::Exchange(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OAuthError::Exchange(e.to_string()))?;
+        // GitHub answers 200 with an `error` member when the code is bad
+        response
+            .access_token
+            .ok_or_else(|| OAuthError::Exchange(response.error.unwrap_or_default()))
+    }
+
+    async fn google_identity(
+        &self,
+        config: &ProviderConfig,
+        access_token: &str,
+    ) -> Result<Identity, OAuthError> {
+        #[derive(Deserialize)]
+        struct UserInfo {
+            sub: String,
+            email: Option<String>,
+            #[serde(default)]
+            email_verified: bool,
+            name: Option<String>,
+        }
+
+        let info: UserInfo = self.get_json(&config.userinfo_url, access_token).await?;
+        Ok(Identity {
+            subject: info.sub,
+            verified_email: info.email.filter(|_| info.email_verified),
+            name: info.name,
+        })
+    }
+
+    async fn github_identity(
+        &self,
+        config: &ProviderConfig,
+        access_token: &str,
+    ) -> Result<Identity, OAuthError> {
+        #[derive(Deserialize)]
+        struct User {
+            id: i64,
+            login: String,
+            name: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Email {
+            email: String,
+            primary: bool,
+            verified: bool,
+        }
+
+        let user: User = self.get_json(&config.userinfo_url, access_token).await?;
+        let emails: Vec<Email> = self.get_json(&config.emails_url, access_token).await?;
+        Ok(Identity {
+            subject: user.id.to_string(),
+            verified_email: emails
+                .into_iter()
+                .find(|email| email.primary && email.verified)
+                .map(|email| email.email),
+            name: user.name.or(Some(user.login)),
+        })
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        access_token: &str,
+    ) -> Result<T, OAuthError> {
+        self.client
+            .get(url)
+            .bearer_auth(access_token)
+            .header(reqwest::header::ACCEPT, "application/json")
+            // GitHub rejects requests without one
+            .header(reqwest::header::USER_AGENT, "axum_postgres-backend")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| OAuthError::Profile(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| OAuthError::Profile(e.to_string()))
+    }
+}
+
+/// S256 PKCE challenge for a code verifier (RFC 7636)
+fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize_redirect() {
+        let config = ProviderConfig::new(
+            Provider::Google,
+            "client".to_string(),
+            "secret".to_string(),
+            "https://api.example.com/api/auth/oauth/google/callback".to_string(),
+        );
+        let url = reqwest::Url::parse(&config.authorize_redirect("xyz", "verifier")).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.host_str(), Some("accounts.google.com"));
+        assert_eq!(params["state"], "xyz");
+        assert_eq!(params["client_id"], "client");
+        assert_eq!(params["redirect_uri"], config.redirect_uri);
+        assert!(!params.contains_key("client_secret"));
+
+        // RFC 7636 appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Password hashing and policy
+//!
+//! New hashes are argon2id PHC strings (`$argon2id$v=19$m=...`). Hashes
+//! from before argon2id, stored as `pbkdf2-sha256$<iterations>$<salt>$<hash>`,
+//! still verify and are replaced on the user's next login.
+
+use std::collections::HashSet;
+use std::num::NonZeroU32;
+use std::sync::LazyLock;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Shortest password accepted, in characters
+pub const MIN_LENGTH: usize = 8;
+
+/// Longest password accepted, in characters; bounds the hashing work
+pub const MAX_LENGTH: usize = 128;
+
+/// Scheme name of the PBKDF2 hashes written before argon2id
+const LEGACY_SCHEME: &str = "pbkdf2-sha256";
+
+/// Frequently used passwords, one per line, rejected whatever the case
+static COMMON_PASSWORDS: LazyLock<HashSet<&'static str>> =
+    LazyLock::new(|| include_str!("common_passwords.txt").lines().collect());
+
+/// Hash compared against when a login names an unknown user, so the
+/// response takes as long as for a wrong password
+pub static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| PasswordService::default().hash(""));
+
+/// Why a password is not accepted
+pub fn policy_violation(password: &str) -> Option<&'static str> {
+    let length = password.chars().count();
+    if length < MIN_LENGTH {
+        Some("Password must be at least 8 characters")
+    } else if length > MAX_LENGTH {
+        Some("Password must be at most 128 characters")
+    } else if COMMON_PASSWORDS.contains(password.to_lowercase().as_str()) {
+        Some("Password is too common")
+    } else {
+        None
+    }
+}
+
+/// Hashes and verifies passwords with argon2id
+///
+/// Both are deliberately slow; call them from `spawn_blocking`.
+pub struct PasswordService {
+    argon2: Argon2<'static>,
+}
+
+impl Default for PasswordService {
+    /// argon2id with 19 MiB of memory and 2 passes, as recommended by OWASP
+    fn default() -> Self {
+        Self {
+            argon2: Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::DEFAULT),
+        }
+    }
+}
+
+impl PasswordService {
+    /// Salted hash of a password, as a PHC string
+    pub fn hash(&self, password: &str) -> String {
+        let mut salt = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .expect("Failed to generate a password salt");
+        let salt = SaltString::encode_b64(&salt).expect("16 bytes is a valid salt length");
+
+        self.argon2
+            .hash_password(password.as_bytes(), &salt)
+            .expect("Passwords up to MAX_LENGTH are hashable")
+            .to_string()
+    }
+
+    /// Whether the password matches a stored hash; malformed hashes never match
+    pub fn verify(&self, password: &str, stored: &str) -> bool {
+        if stored.starts_with(LEGACY_SCHEME) {
+            return verify_legacy(password, stored);
+        }
+        PasswordHash::new(stored).is_ok_and(|hash| {
+            self.argon2
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+
+    /// Whether a stored hash uses an older scheme or weaker parameters, and
+    /// should be replaced once the password is known
+    pub fn needs_rehash(&self, stored: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(stored) else {
+            return true;
+        };
+        let current = self.argon2.params();
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || !Params::try_from(&hash).is_ok_and(|params| {
+                (params.m_cost(), params.t_cost(), params.p_cost())
+                    == (current.m_cost(), current.t_cost(), current.p_cost())
+            })
+    }
+}
+
+fn verify_legacy(password: &str, stored: &str) -> bool {
+    let mut parts = stored.split('$');
+    let (Some(LEGACY_SCHEME), Some(iterations), Some(salt), Some(hash), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return false;
+    };
+    let (Some(iterations), Ok(salt), Ok(hash)) = (
+        iterations.parse().ok().and_then(NonZeroU32::new),
+        STANDARD_NO_PAD.decode(salt),
+        STANDARD_NO_PAD.decode(hash),
+    ) else {
+        return false;
+    };
+
+    pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &hash,
+    )
+    .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify() {
+        let passwords = PasswordService::default();
+        let stored = passwords.hash("correct horse battery staple");
+        assert!(stored.starts_with("$argon2id$v=19$"));
+        assert!(passwords.verify("correct horse battery staple", &stored));
+        assert!(!passwords.verify("correct horse battery stapler", &stored));
+        assert!(!passwords.needs_rehash(&stored));
+
+        // Salted, so the same password hashes differently each time
+        assert_ne!(stored, passwords.hash("correct horse battery staple"));
+
+        assert!(!passwords.verify("", "plaintext"));
+        assert!(passwords.needs_rehash("plaintext"));
+    }
+
+    #[test]
+    fn test_verifies_legacy_hashes() {
+        let passwords = PasswordService::default();
+        // PBKDF2-HMAC-SHA256 of "correct horse battery staple", 1000 iterations
+        let mut hash = [0u8; 32];
+        let salt = b"0123456789abcdef";
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(1000).unwrap(),
+            salt,
+            b"correct horse battery staple",
+            &mut hash,
+        );
+        let stored = format!(
+            "pbkdf2-sha256$1000${}${}",
+            STANDARD_NO_PAD.encode(salt),
+            STANDARD_NO_PAD.encode(hash)
+        );
+
+        assert!(passwords.verify("correct horse battery staple", &stored));
+        assert!(!passwords.verify("wrong", &stored));
+        assert!(passwords.needs_rehash(&stored));
+        assert!(!passwords.verify("", "pbkdf2-sha256$0$AAAA$AAAA"));
+    }
+
+    #[test]
+    fn test_policy() {
+        assert_eq!(policy_violation("correct horse battery staple"), None);
+        assert!(policy_violation("short").is_some());
+        assert!(policy_violation(&"x".repeat(MAX_LENGTH + 1)).is_some());
+        assert_eq!(
+            policy_violation("Password123"),
+            Some("Password is too common")
+        );
+        assert_eq!(
+            policy_violation("QWERTYUIOP"),
+            Some("Password is too common")
+        );
+    }
+}
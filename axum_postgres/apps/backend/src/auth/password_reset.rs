@@ -0,0 +1,37 @@
+//! Password reset by email
+//!
+//! `POST /api/auth/forgot-password` mails a link carrying a random token,
+//! which the web app redeems with a new password at
+//! `POST /api/auth/reset-password`. Tokens are stored hashed, expire after
+//! an hour and work once. Requests are limited per address, counted whether
+//! or not a user has it, so neither the answer nor the limit tells which
+//! addresses are registered.
+
+use crate::mail::{Mail, MailError};
+use crate::models::user::User;
+
+/// Seconds a reset link stays usable
+pub const PASSWORD_RESET_TTL_SECS: i64 = 60 * 60;
+/// Forgot-password requests allowed per address within the window
+pub const MAX_RESET_REQUESTS: i64 = 3;
+/// Seconds over which forgot-password requests are counted
+pub const RESET_REQUEST_WINDOW_SECS: i64 = 60 * 60;
+/// Page of the web app that redeems reset links
+pub const RESET_PASSWORD_PAGE: &str = "/reset-password";
+
+/// Mail the user a link that sets a new password with `token`
+pub async fn send_password_reset_email(
+    mail: &Mail,
+    user: &User,
+    token: &str,
+) -> Result<(), MailError> {
+    let body = format!(
+        "Hi {},\n\n\
+         Choose a new password by opening this link within an hour:\n\n\
+         {}\n\n\
+         If you did not ask to reset your password, you can ignore this email.\n",
+        user.name,
+        mail.link(RESET_PASSWORD_PAGE, token)
+    );
+    mail.send(&user.email, "Reset your password", body).await
+}
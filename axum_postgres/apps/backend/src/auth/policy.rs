@@ -0,0 +1,203 @@
+//! Who may do what to whom
+//!
+//! Every signed-in user holds the base permissions; roles add more. Handlers
+//! load an [`Actor`] and ask the `can_*` functions here instead of checking
+//! roles themselves, so the rules live in one place.
+
+use sqlx::PgPool;
+
+use super::role::Role;
+use super::Principal;
+use crate::error::AppError;
+use crate::models::user::User;
+use crate::models::user_id::UserId;
+use crate::repository::roles::{RoleRepository, RoleRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+
+/// Something an actor may be allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// Change one's own profile
+    EditOwnUser,
+    /// Change any user's profile
+    EditAnyUser,
+    /// Activate and deactivate users, oneself included
+    SetUserStatus,
+    /// Undo the deletion of any user
+    RestoreUser,
+}
+
+/// Permissions of every signed-in user
+const BASE_PERMISSIONS: &[Permission] = &[Permission::EditOwnUser];
+
+/// Permissions a role adds
+fn granted_by(role: Role) -> &'static [Permission] {
+    match role {
+        Role::Admin => &[
+            Permission::EditAnyUser,
+            Permission::SetUserStatus,
+            Permission::RestoreUser,
+        ],
+    }
+}
+
+/// User a request acts as, with their roles
+///
+/// An API key acts as the user who created it. Deleted users and keys
+/// whose creator is gone act as nobody and have no permissions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Actor {
+    pub id: Option<UserId>,
+    pub roles: Vec<Role>,
+}
+
+impl Actor {
+    pub fn new(id: UserId, roles: Vec<Role>) -> Self {
+        Self {
+            id: Some(id),
+            roles,
+        }
+    }
+
+    /// The user behind the principal and their roles
+    pub async fn load(pool: &PgPool, principal: &Principal) -> Result<Self, AppError> {
+        let users = UserRepository::new(pool.clone());
+        let user = match principal {
+            Principal::User { id, .. } => users.get_user_by_id(*id).await,
+            Principal::ApiKey(key) => match key.created_by {
+                Some(key) => users.get_user_by_key(key).await,
+                None => Ok(None),
+            },
+        }
+        .map_err(load_error)?;
+        let Some(user) = user else {
+            return Ok(Self::default());
+        };
+        let roles = RoleRepository::new(pool.clone())
+            .roles_of(user.id)
+            .await
+            .map_err(load_error)?;
+        Ok(Self::new(user.user_id(), roles))
+    }
+
+    pub fn has(&self, permission: Permission) -> bool {
+        self.id.is_some()
+            && (BASE_PERMISSIONS.contains(&permission)
+                || self
+                    .roles
+                    .iter()
+                    .any(|role| granted_by(*role).contains(&permission)))
+    }
+}
+
+/// Whether the actor may change the target user's profile
+pub fn can_edit_user(actor: &Actor, target: UserId) -> bool {
+    actor.has(Permission::EditAnyUser)
+        || (actor.id == Some(target) && actor.has(Permission::EditOwnUser))
+}
+
+/// Whether the actor may activate or deactivate users
+pub fn can_set_user_status(actor: &Actor) -> bool {
+    actor.has(Permission::SetUserStatus)
+}
+
+/// Whether the actor may restore deleted users
+pub fn can_restore_user(actor: &Actor) -> bool {
+    actor.has(Permission::RestoreUser)
+}
+
+/// Check an edit of `target` that may set `active`, rejecting with 403
+pub fn check_user_edit(actor: &Actor, target: &User, active: bool) -> Result<(), AppError> {
+    if !can_edit_user(actor, target.user_id()) {
+        return Err(AppError::Forbidden(
+            "You can only edit your own user".to_string(),
+        ));
+    }
+    if active != target.active && !can_set_user_status(actor) {
+        return Err(AppError::Forbidden(
+            "Only admins can activate or deactivate users".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn load_error(e: sqlx::Error) -> AppError {
+    tracing::error!("Database error loading the acting user: {:?}", e);
+    AppError::InternalServerError("Failed to load roles".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_id(n: u8) -> UserId {
+        #[cfg(not(feature = "uuid-ids"))]
+        return UserId::new(n.into());
+        #[cfg(feature = "uuid-ids")]
+        return UserId::new(uuid::Uuid::from_u128(n.into()));
+    }
+
+    #[test]
+    fn test_edit_user_matrix() {
+        let (me, other) = (user_id(1), user_id(2));
+        let user = Actor::new(me, vec![]);
+        let admin = Actor::new(me, vec![Role::Admin]);
+        let nobody = Actor::default();
+
+        // (actor, target, may edit)
+        let cases = [
+            (&user, me, true),
+            (&user, other, false),
+            (&admin, me, true),
+            (&admin, other, true),
+            (&nobody, me, false),
+            (&nobody, other, false),
+        ];
+        for (actor, target, expected) in cases {
+            assert_eq!(
+                can_edit_user(actor, target),
+                expected,
+                "{:?} editing {}",
+                actor,
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_restore_user_matrix() {
+        let user = Actor::new(user_id(1), vec![]);
+        let admin = Actor::new(user_id(1), vec![Role::Admin]);
+        let nobody_admin = Actor {
+            id: None,
+            roles: vec![Role::Admin],
+        };
+
+        // (actor, may restore)
+        let cases = [
+            (&user, false),
+            (&admin, true),
+            (&Actor::default(), false),
+            (&nobody_admin, false),
+        ];
+        for (actor, expected) in cases {
+            assert_eq!(can_restore_user(actor), expected, "{:?} restoring", actor);
+        }
+    }
+
+    #[test]
+    fn test_status_changes_take_admin() {
+        assert!(!can_set_user_status(&Actor::new(user_id(1), vec![])));
+        assert!(can_set_user_status(&Actor::new(
+            user_id(1),
+            vec![Role::Admin]
+        )));
+        assert!(!can_set_user_status(&Actor::default()));
+
+        let nobody_admin = Actor {
+            id: None,
+            roles: vec![Role::Admin],
+        };
+        assert!(!nobody_admin.has(Permission::EditAnyUser));
+    }
+}
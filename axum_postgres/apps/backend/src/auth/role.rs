@@ -0,0 +1,124 @@
+//! Roles granted to users, and the [`RequireRole`] extractor
+//!
+//! Roles live in the `roles` table and are granted through `user_roles`.
+//! An API key acts with the roles of the user who created it; its scopes
+//! still limit what it may do.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use super::policy::Actor;
+use super::session::AuthMode;
+use super::token::TokenKeys;
+use super::Principal;
+use crate::error::AppError;
+
+/// Role a user can be granted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Edits, deletes and imports any user, and grants roles
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 1] = [Role::Admin];
+
+    /// Name in the `roles` table
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("Unknown role '{}'", s))
+    }
+}
+
+/// Names the role a [`RequireRole`] asks for
+pub trait RequiredRole: Send + Sync {
+    const ROLE: Role;
+}
+
+/// [`RequiredRole`] for [`Role::Admin`]
+#[derive(Debug)]
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Caller holding the role `R`, e.g. `RequireRole<Admin>`
+///
+/// Rejects with 401 like [`Principal`] when there are no valid credentials,
+/// and with 403 when the caller lacks the role.
+#[derive(Debug)]
+pub struct RequireRole<R> {
+    pub principal: Principal,
+    role: PhantomData<R>,
+}
+
+#[async_trait]
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    R: RequiredRole,
+    TokenKeys: FromRef<S>,
+    AuthMode: FromRef<S>,
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let principal = Principal::from_request_parts(parts, state).await?;
+        let actor = Actor::load(&PgPool::from_ref(state), &principal).await?;
+        if !actor.roles.contains(&R::ROLE) {
+            tracing::warn!("{:?} lacks the {} role", principal, R::ROLE);
+            return Err(AppError::Forbidden(format!(
+                "Requires the {} role",
+                R::ROLE
+            )));
+        }
+        Ok(Self {
+            principal,
+            role: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_names() {
+        assert_eq!("admin".parse::<Role>(), Ok(Role::Admin));
+        assert_eq!(Role::Admin.to_string(), "admin");
+        assert_eq!(serde_json::to_value(Role::Admin).unwrap(), "admin");
+        assert!("root".parse::<Role>().is_err());
+    }
+}
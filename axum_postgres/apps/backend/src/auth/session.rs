@@ -0,0 +1,125 @@
+//! Cookie sessions, the alternative to JWTs for browser clients
+//!
+//! With AUTH_MODE=cookie, signing in sets an HttpOnly `session` cookie
+//! instead of handing out tokens, and [`AuthUser`](super::AuthUser) only
+//! accepts that cookie. `middleware::session::authenticate` resolves it on
+//! each request and pushes the expiry forward, so a session ends after
+//! [`SESSION_IDLE_TIMEOUT_SECS`] without requests.
+//!
+//! Browsers send cookies on cross-site requests too, so every request that
+//! is not GET, HEAD or OPTIONS must echo the session's CSRF token in the
+//! `X-CSRF-Token` header. The token is returned by sign-in and also set as
+//! the `csrf_token` cookie, which scripts on the site can read.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+
+use crate::models::user_id::UserId;
+
+/// How long a session lasts without requests
+pub const SESSION_IDLE_TIMEOUT_SECS: i64 = 24 * 60 * 60;
+
+/// Cookie holding the session id
+pub const SESSION_COOKIE: &str = "session";
+/// Cookie holding the CSRF token, readable by scripts
+pub const CSRF_COOKIE: &str = "csrf_token";
+/// Header that must repeat the CSRF token on mutating requests
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// How users authenticate after signing in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMode {
+    /// Access and refresh tokens, sent as `Authorization: Bearer`
+    #[default]
+    Jwt,
+    /// A session cookie, with a CSRF token on mutating requests
+    Cookie,
+}
+
+impl AuthMode {
+    /// Mode from AUTH_MODE (`jwt` or `cookie`), JWTs by default
+    pub fn from_env() -> Self {
+        match std::env::var("AUTH_MODE").as_deref() {
+            Ok("cookie") => Self::Cookie,
+            Ok("jwt") | Err(_) => Self::Jwt,
+            Ok(other) => {
+                tracing::warn!("Unknown AUTH_MODE '{}'; using JWTs", other);
+                Self::Jwt
+            }
+        }
+    }
+}
+
+/// User signed in with a valid session cookie, put in the request
+/// extensions by `middleware::session::authenticate`
+#[derive(Debug, Clone)]
+pub struct SessionUser {
+    pub id: UserId,
+    /// Fingerprint of the session id, the session's key in the database
+    pub session_hash: Vec<u8>,
+}
+
+/// Value of the cookie `name` in the request's `Cookie` headers
+pub fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// `Set-Cookie` value for the session id, expiring with the session
+pub fn session_cookie(session_id: &str) -> HeaderValue {
+    set_cookie(SESSION_COOKIE, session_id, true, SESSION_IDLE_TIMEOUT_SECS)
+}
+
+/// `Set-Cookie` value for the CSRF token, which scripts may read
+pub fn csrf_cookie(csrf_token: &str) -> HeaderValue {
+    set_cookie(CSRF_COOKIE, csrf_token, false, SESSION_IDLE_TIMEOUT_SECS)
+}
+
+/// `Set-Cookie` values that remove both cookies
+pub fn cleared_cookies() -> [HeaderValue; 2] {
+    [
+        set_cookie(SESSION_COOKIE, "", true, 0),
+        set_cookie(CSRF_COOKIE, "", false, 0),
+    ]
+}
+
+pub(crate) fn set_cookie(
+    name: &str,
+    value: &str,
+    http_only: bool,
+    max_age_secs: i64,
+) -> HeaderValue {
+    let http_only = if http_only { "; HttpOnly" } else { "" };
+    format!(
+        "{}={}; Path=/; Max-Age={}; Secure; SameSite=Lax{}",
+        name, value, max_age_secs, http_only
+    )
+    .parse()
+    .expect("Cookie values are base64url")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookies() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "theme=dark; session=abc".parse().unwrap());
+        headers.append(header::COOKIE, "csrf_token=xyz".parse().unwrap());
+        assert_eq!(cookie(&headers, SESSION_COOKIE), Some("abc"));
+        assert_eq!(cookie(&headers, CSRF_COOKIE), Some("xyz"));
+        assert_eq!(cookie(&headers, "sessions"), None);
+
+        let set = session_cookie("abc");
+        let set = set.to_str().unwrap();
+        assert!(set.starts_with("session=abc;"));
+        assert!(set.contains("HttpOnly"));
+        assert!(!csrf_cookie("xyz").to_str().unwrap().contains("HttpOnly"));
+    }
+}
@@ -0,0 +1,369 @@
+//! HS256 JSON Web Tokens (RFC 7519)
+//!
+//! Access tokens authorize calls to the write endpoints; refresh tokens are
+//! only good for `POST /api/auth/refresh`, which trades one for a new pair.
+//! Both carry the user id in `sub`, so checking an access token needs no
+//! database lookup. Refresh tokens are also recorded, by [`fingerprint`], in
+//! the `refresh_tokens` table so they can be rotated and revoked.
+//!
+//! Email verification tokens are mailed to users and also name the address
+//! they verify, so they stop working once the user changes their email.
+//!
+//! Impersonation tokens are access tokens an admin obtains for another user;
+//! they name the admin in `act` (RFC 8693) and come without a refresh token.
+//!
+//! Every token names the tenant it was issued in, in `tid`, and is only
+//! good for requests of that tenant.
+
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac};
+use serde::{Deserialize, Serialize};
+
+use crate::models::auth::TokenResponse;
+use crate::models::user_id::UserId;
+use crate::tenant::{self, DEFAULT_TENANT_ID};
+
+/// Lifetime of an access token
+pub const ACCESS_TOKEN_TTL_SECS: i64 = 15 * 60;
+
+/// Lifetime of a refresh token
+pub const REFRESH_TOKEN_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Lifetime of an email verification token
+pub const EMAIL_VERIFICATION_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Header of every token; tokens naming any other algorithm are rejected
+const HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// What a token may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Access,
+    Refresh,
+    #[serde(rename = "verify_email")]
+    VerifyEmail,
+}
+
+impl TokenKind {
+    fn ttl_secs(self) -> i64 {
+        match self {
+            Self::Access => ACCESS_TOKEN_TTL_SECS,
+            Self::Refresh => REFRESH_TOKEN_TTL_SECS,
+            Self::VerifyEmail => EMAIL_VERIFICATION_TTL_SECS,
+        }
+    }
+}
+
+/// Payload of a token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// Id of the user, as clients see it
+    pub sub: String,
+    pub typ: TokenKind,
+    /// Issue time, in seconds since the Unix epoch
+    pub iat: i64,
+    /// Expiry time, in seconds since the Unix epoch
+    pub exp: i64,
+    /// Random id, so no two tokens are alike
+    pub jti: String,
+    /// Address an email verification token verifies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Admin acting as the user, on impersonation tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActingParty>,
+    /// Tenant the token was issued in; tokens from before tenants carry
+    /// none and belong to the default tenant
+    #[serde(default = "default_tenant_id")]
+    pub tid: i32,
+}
+
+fn default_tenant_id() -> i32 {
+    DEFAULT_TENANT_ID
+}
+
+/// Party acting on behalf of the subject of a token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActingParty {
+    /// Id of the acting user, as clients see it
+    pub sub: String,
+}
+
+/// Why a token was not accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    Malformed,
+    BadSignature,
+    Expired,
+    WrongKind,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Malformed => "Malformed token",
+            Self::BadSignature => "Invalid token signature",
+            Self::Expired => "Token has expired",
+            Self::WrongKind => "Wrong kind of token",
+        })
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+/// Key that signs and verifies tokens
+#[derive(Debug, Clone)]
+pub struct TokenKeys {
+    key: hmac::Key,
+}
+
+impl TokenKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret),
+        }
+    }
+
+    /// Key from JWT_SECRET
+    ///
+    /// Without it a random key is used, so tokens stop working when the
+    /// server restarts and are not accepted by other replicas.
+    pub fn from_env() -> Self {
+        match std::env::var("JWT_SECRET") {
+            Ok(secret) if !secret.is_empty() => Self::new(secret.as_bytes()),
+            _ => {
+                tracing::warn!("JWT_SECRET is not set; tokens are signed with a random key");
+                Self::random()
+            }
+        }
+    }
+
+    pub fn random() -> Self {
+        let mut secret = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .expect("Failed to generate a random JWT key");
+        Self::new(&secret)
+    }
+
+    /// Token of `kind` for the user in the current tenant, valid from now
+    pub fn issue(&self, user_id: UserId, kind: TokenKind) -> String {
+        self.issue_with(user_id, kind, None, None)
+    }
+
+    /// Token proving the user receives mail at `email`
+    pub fn issue_email_verification(&self, user_id: UserId, email: &str) -> String {
+        self.issue_with(
+            user_id,
+            TokenKind::VerifyEmail,
+            Some(email.to_string()),
+            None,
+        )
+    }
+
+    /// Access token for the user that names `admin_id` as acting for them
+    pub fn issue_impersonation(&self, user_id: UserId, admin_id: UserId) -> String {
+        let act = ActingParty {
+            sub: admin_id.to_string(),
+        };
+        self.issue_with(user_id, TokenKind::Access, None, Some(act))
+    }
+
+    fn issue_with(
+        &self,
+        user_id: UserId,
+        kind: TokenKind,
+        email: Option<String>,
+        act: Option<ActingParty>,
+    ) -> String {
+        let mut jti = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut jti)
+            .expect("Failed to generate a token id");
+        let now = Utc::now().timestamp();
+        self.sign(&Claims {
+            sub: user_id.to_string(),
+            typ: kind,
+            iat: now,
+            exp: now + kind.ttl_secs(),
+            jti: URL_SAFE_NO_PAD.encode(jti),
+            email,
+            act,
+            tid: tenant::current(),
+        })
+    }
+
+    /// Fresh access and refresh tokens for the user
+    pub fn issue_pair(&self, user_id: UserId) -> TokenResponse {
+        TokenResponse {
+            access_token: self.issue(user_id, TokenKind::Access),
+            refresh_token: self.issue(user_id, TokenKind::Refresh),
+            token_type: "Bearer".to_string(),
+            expires_in: ACCESS_TOKEN_TTL_SECS,
+        }
+    }
+
+    /// Claims of a token of `kind` that this key signed and that has not expired
+    pub fn verify(&self, token: &str, kind: TokenKind) -> Result<Claims, TokenError> {
+        let (message, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let (header, payload) = message.split_once('.').ok_or(TokenError::Malformed)?;
+        if decode(header)? != HEADER.as_bytes() {
+            return Err(TokenError::Malformed);
+        }
+        hmac::verify(&self.key, message.as_bytes(), &decode(signature)?)
+            .map_err(|_| TokenError::BadSignature)?;
+
+        let claims: Claims =
+            serde_json::from_slice(&decode(payload)?).map_err(|_| TokenError::Malformed)?;
+        if claims.exp <= Utc::now().timestamp() {
+            return Err(TokenError::Expired);
+        }
+        if claims.typ != kind {
+            return Err(TokenError::WrongKind);
+        }
+        Ok(claims)
+    }
+
+    fn sign(&self, claims: &Claims) -> String {
+        let payload = serde_json::to_vec(claims).expect("Claims serialize to JSON");
+        let message = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(HEADER),
+            URL_SAFE_NO_PAD.encode(payload)
+        );
+        let signature = hmac::sign(&self.key, message.as_bytes());
+        format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature))
+    }
+}
+
+impl Default for TokenKeys {
+    fn default() -> Self {
+        Self::random()
+    }
+}
+
+/// SHA-256 of a token, the form refresh tokens are stored in
+pub fn fingerprint(token: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+/// 32 random bytes as base64url, for opaque secrets such as session ids
+pub fn random_secret() -> String {
+    let mut secret = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut secret)
+        .expect("Failed to generate a random secret");
+    URL_SAFE_NO_PAD.encode(secret)
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, TokenError> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| TokenError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_id() -> UserId {
+        #[cfg(not(feature = "uuid-ids"))]
+        return UserId::new(42);
+        #[cfg(feature = "uuid-ids")]
+        return UserId::new(uuid::Uuid::nil());
+    }
+
+    #[test]
+    fn test_issue_and_verify() {
+        let keys = TokenKeys::new(b"secret");
+        let token = keys.issue(user_id(), TokenKind::Access);
+        assert_eq!(token.split('.').count(), 3);
+
+        let claims = keys.verify(&token, TokenKind::Access).unwrap();
+        assert_eq!(claims.sub, user_id().to_string());
+        assert_eq!(claims.exp - claims.iat, ACCESS_TOKEN_TTL_SECS);
+        assert_eq!(claims.tid, DEFAULT_TENANT_ID);
+        assert_ne!(token, keys.issue(user_id(), TokenKind::Access));
+
+        assert_eq!(
+            keys.verify(&token, TokenKind::Refresh),
+            Err(TokenError::WrongKind)
+        );
+        assert_eq!(
+            TokenKeys::new(b"other").verify(&token, TokenKind::Access),
+            Err(TokenError::BadSignature)
+        );
+        assert_eq!(
+            keys.verify("not-a-token", TokenKind::Access),
+            Err(TokenError::Malformed)
+        );
+
+        let token = keys.issue_email_verification(user_id(), "jane@example.com");
+        let claims = keys.verify(&token, TokenKind::VerifyEmail).unwrap();
+        assert_eq!(claims.email.as_deref(), Some("jane@example.com"));
+        assert_eq!(
+            keys.verify(&token, TokenKind::Access),
+            Err(TokenError::WrongKind)
+        );
+        assert_eq!(claims.act, None);
+
+        let token = keys.issue_impersonation(user_id(), user_id());
+        let claims = keys.verify(&token, TokenKind::Access).unwrap();
+        assert_eq!(claims.act.unwrap().sub, user_id().to_string());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_name_their_tenant() {
+        let keys = TokenKeys::new(b"secret");
+        let token = tenant::scope(7, async { keys.issue(user_id(), TokenKind::Access) }).await;
+        assert_eq!(keys.verify(&token, TokenKind::Access).unwrap().tid, 7);
+
+        // Tokens issued before tenants existed
+        let claims: Claims = serde_json::from_value(serde_json::json!({
+            "sub": "42", "typ": "access", "iat": 0, "exp": 1, "jti": "old"
+        }))
+        .unwrap();
+        assert_eq!(claims.tid, DEFAULT_TENANT_ID);
+    }
+
+    #[test]
+    fn test_rejects_expired_and_tampered_tokens() {
+        let keys = TokenKeys::new(b"secret");
+        let now = Utc::now().timestamp();
+        let expired = keys.sign(&Claims {
+            sub: user_id().to_string(),
+            typ: TokenKind::Access,
+            iat: now - 120,
+            exp: now - 60,
+            jti: "expired".to_string(),
+            email: None,
+            act: None,
+            tid: DEFAULT_TENANT_ID,
+        });
+        assert_eq!(
+            keys.verify(&expired, TokenKind::Access),
+            Err(TokenError::Expired)
+        );
+
+        // Same claims under an unsigned `alg: none` header
+        let token = keys.issue(user_id(), TokenKind::Access);
+        let (_, rest) = token.split_once('.').unwrap();
+        let unsigned = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#),
+            rest
+        );
+        assert_eq!(
+            keys.verify(&unsigned, TokenKind::Access),
+            Err(TokenError::Malformed)
+        );
+    }
+}
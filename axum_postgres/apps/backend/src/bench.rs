@@ -0,0 +1,289 @@
+//! In-process workload for the benchmarks and the `bench` binary
+//!
+//! [`BenchApp`] serves the user routes from a router in this process, so
+//! timings cover extraction, handlers, the repository and Postgres without
+//! a network hop in between. Caching stays off: what is measured is the
+//! repository. Every user created here has a `bench-` email, and
+//! [`cleanup`] deletes them all afterwards.
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    routing::get,
+    Router,
+};
+use sqlx::PgPool;
+use tower::util::ServiceExt;
+
+use crate::auth::token::{TokenKeys, TokenKind};
+use crate::handlers::users::{self, USERS_PATH};
+use crate::health::HealthRegistry;
+use crate::models::user::CreateUserRequest;
+use crate::models::user_id::UserId;
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+use crate::state::AppState;
+
+/// Users listed per page by [`Operation::List`]
+pub const LIST_LIMIT: i64 = 20;
+
+/// Email of the user the requests are made as, and read by
+/// [`Operation::Get`]
+const OWNER_EMAIL: &str = "bench-owner@example.com";
+
+/// Request the benchmarks measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// `POST /api/users` with a new email
+    Create,
+    /// `GET /api/users/:id` of an existing user
+    Get,
+    /// `GET /api/users`, the first page of LIST_LIMIT users
+    List,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::Create, Operation::Get, Operation::List];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Create => "create",
+            Operation::Get => "get",
+            Operation::List => "list",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Operation::ALL
+            .into_iter()
+            .find(|operation| operation.name() == s)
+            .ok_or_else(|| format!("Unknown operation '{}': create, get or list", s))
+    }
+}
+
+/// Options of the `bench` binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchOptions {
+    /// Requests per operation
+    pub requests: usize,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    pub operations: Vec<Operation>,
+}
+
+impl BenchOptions {
+    pub const USAGE: &'static str =
+        "Usage: bench [--requests N] [--concurrency N] [--operation create|get|list]...";
+
+    /// Options from the arguments after the program name: 1000 requests of
+    /// every operation, 16 at a time, unless given
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            requests: 1000,
+            concurrency: 16,
+            operations: Vec::new(),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            let count = |value: String| match value.parse() {
+                Ok(0) | Err(_) => Err(format!("{} must be a positive number", arg)),
+                Ok(count) => Ok(count),
+            };
+            match arg.as_str() {
+                "--requests" => options.requests = count(value()?)?,
+                "--concurrency" => options.concurrency = count(value()?)?,
+                "--operation" => options.operations.push(value()?.parse()?),
+                _ => return Err(format!("Unknown argument '{}'", arg)),
+            }
+        }
+        if options.operations.is_empty() {
+            options.operations = Operation::ALL.to_vec();
+        }
+        Ok(options)
+    }
+}
+
+/// A request for a new user, with an email no other request of any run
+/// has used
+pub fn new_user() -> CreateUserRequest {
+    static RUN: OnceLock<i64> = OnceLock::new();
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let run = RUN.get_or_init(|| chrono::Utc::now().timestamp_millis());
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    CreateUserRequest {
+        name: format!("Bench User {}", n),
+        email: format!("bench-{}-{}@example.com", run, n),
+        ..Default::default()
+    }
+}
+
+/// Delete every user the benchmarks created, returning how many
+pub async fn cleanup(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM test_users WHERE email LIKE 'bench-%@example.com'")
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// The user routes in this process, signed in as a bench user
+#[derive(Clone)]
+pub struct BenchApp {
+    router: Router,
+    token: String,
+    owner: UserId,
+}
+
+impl BenchApp {
+    /// App on `pool`, creating the bench user on first use
+    pub async fn new(pool: PgPool) -> Result<Self, sqlx::Error> {
+        let repo = UserRepository::new(pool.clone());
+        let owner = match repo.find_user_by_email(OWNER_EMAIL).await? {
+            Some(owner) => owner,
+            None => {
+                repo.create_user(CreateUserRequest {
+                    name: "Bench Owner".to_string(),
+                    email: OWNER_EMAIL.to_string(),
+                    ..Default::default()
+                })
+                .await?
+            }
+        };
+
+        // Tokens never leave this process
+        let token_keys = TokenKeys::new(b"bench");
+        let token = token_keys.issue(owner.user_id(), TokenKind::Access);
+        let state = AppState::new(pool, HealthRegistry::default()).with_token_keys(token_keys);
+        let router = Router::new()
+            .route(USERS_PATH, get(users::list_users).post(users::create_user))
+            .route(users::USER_PATH, get(users::get_user_by_id))
+            .with_state(state);
+        Ok(Self {
+            router,
+            token,
+            owner: owner.user_id(),
+        })
+    }
+
+    /// Request for `operation`
+    pub fn request(&self, operation: Operation) -> Request<Body> {
+        let request =
+            Request::builder().header(header::AUTHORIZATION, format!("Bearer {}", self.token));
+        let request = match operation {
+            Operation::Create => request
+                .method(Method::POST)
+                .uri(USERS_PATH)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::to_vec(&new_user()).unwrap())),
+            Operation::Get => request
+                .uri(format!("{}/{}", USERS_PATH, self.owner))
+                .body(Body::empty()),
+            Operation::List => request
+                .uri(format!("{}?limit={}", USERS_PATH, LIST_LIMIT))
+                .body(Body::empty()),
+        };
+        request.expect("Bench requests are valid")
+    }
+
+    /// Send a request for `operation` and read the whole response
+    pub async fn send(&self, operation: Operation) -> StatusCode {
+        let response = self
+            .router
+            .clone()
+            .oneshot(self.request(operation))
+            .await
+            .expect("Routers are infallible");
+        let status = response.status();
+        // Serializing the body is part of the work measured
+        let _ = axum::body::to_bytes(response.into_body(), usize::MAX).await;
+        status
+    }
+}
+
+/// Latencies of a run of requests
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencySummary {
+    pub requests: usize,
+    pub elapsed: Duration,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Summary of `latencies`, taken over `elapsed` of wall time
+    pub fn new(mut latencies: Vec<Duration>, elapsed: Duration) -> Self {
+        latencies.sort_unstable();
+        Self {
+            requests: latencies.len(),
+            elapsed,
+            p50: percentile(&latencies, 50.0),
+            p99: percentile(&latencies, 99.0),
+            max: latencies.last().copied().unwrap_or_default(),
+        }
+    }
+
+    /// Requests per second
+    pub fn throughput(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Nearest-rank percentile `p` of `sorted`
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_summary() {
+        let latencies = (1..=100).rev().map(Duration::from_millis).collect();
+        let summary = LatencySummary::new(latencies, Duration::from_secs(2));
+        assert_eq!(summary.requests, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert_eq!(summary.max, Duration::from_millis(100));
+        assert_eq!(summary.throughput(), 50.0);
+
+        let empty = LatencySummary::new(Vec::new(), Duration::ZERO);
+        assert_eq!(empty.p99, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_options_from_args() {
+        let args = |args: &[&str]| BenchOptions::from_args(args.iter().map(|a| a.to_string()));
+        let defaults = args(&[]).unwrap();
+        assert_eq!(defaults.requests, 1000);
+        assert_eq!(defaults.operations, Operation::ALL);
+
+        let options = args(&["--requests", "50", "--operation", "get"]).unwrap();
+        assert_eq!(options.requests, 50);
+        assert_eq!(options.operations, [Operation::Get]);
+
+        assert!(args(&["--concurrency", "0"]).is_err());
+        assert!(args(&["--operation", "delete"]).is_err());
+        assert!(args(&["--requests"]).is_err());
+    }
+
+    #[test]
+    fn test_new_users_have_unique_bench_emails() {
+        let (first, second) = (new_user(), new_user());
+        assert_ne!(first.email, second.email);
+        assert!(first.email.starts_with("bench-"));
+    }
+}
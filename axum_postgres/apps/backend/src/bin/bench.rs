@@ -0,0 +1,103 @@
+// Load generator for the user API
+// Issues concurrent requests against an in-process router and reports
+// throughput and latency per operation:
+// cargo run --release --bin bench -- [--requests N] [--concurrency N] [--operation create|get|list]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use backend::bench::{self, BenchApp, BenchOptions, LatencySummary, Operation};
+use backend::database::create_pool_from_env;
+use dotenvy::dotenv;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let options = match BenchOptions::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, BenchOptions::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let pool = create_pool_from_env().await?;
+    let app = BenchApp::new(pool.clone()).await?;
+    eprintln!(
+        "=== {} requests per operation, {} at a time ===",
+        options.requests, options.concurrency
+    );
+    println!(
+        "{:<8} {:>10} {:>10} {:>10} {:>10} {:>8}",
+        "op", "req/s", "p50 ms", "p99 ms", "max ms", "errors"
+    );
+
+    let mut failed = false;
+    for &operation in &options.operations {
+        let (summary, errors) = run(&app, operation, &options).await;
+        let millis = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+        println!(
+            "{:<8} {:>10.0} {:>10.2} {:>10.2} {:>10.2} {:>8}",
+            operation.name(),
+            summary.throughput(),
+            millis(summary.p50),
+            millis(summary.p99),
+            millis(summary.max),
+            errors
+        );
+        failed |= errors > 0;
+    }
+
+    let removed = bench::cleanup(&pool).await?;
+    eprintln!("Removed {} bench users", removed);
+    if failed {
+        eprintln!("Some requests failed");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Send `options.requests` requests for `operation` from
+/// `options.concurrency` tasks; returns their latencies and how many did
+/// not succeed
+async fn run(
+    app: &BenchApp,
+    operation: Operation,
+    options: &BenchOptions,
+) -> (LatencySummary, usize) {
+    let remaining = Arc::new(AtomicUsize::new(options.requests));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..options.concurrency)
+        .map(|_| {
+            let app = app.clone();
+            let remaining = remaining.clone();
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                let mut errors = 0;
+                while remaining
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                    .is_ok()
+                {
+                    let sent = Instant::now();
+                    let status = app.send(operation).await;
+                    latencies.push(sent.elapsed());
+                    if !status.is_success() {
+                        errors += 1;
+                    }
+                }
+                (latencies, errors)
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(options.requests);
+    let mut errors = 0;
+    for worker in workers {
+        let (worker_latencies, worker_errors) = worker.await.expect("Bench worker panicked");
+        latencies.extend(worker_latencies);
+        errors += worker_errors;
+    }
+    (LatencySummary::new(latencies, started.elapsed()), errors)
+}
@@ -1,8 +1,8 @@
 // OpenAPI specification generator binary
 // Generates OpenAPI spec from Rust code and outputs to packages/openapi-spec/openapi.json
 
-use backend::docs::openapi_spec;
-use serde_json;
+use backend::docs::openapi_spec_for;
+use backend::middleware::envelope::ResponseEnvelope;
 use std::fs;
 use std::path::Path;
 
@@ -11,7 +11,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Generate OpenAPI specification
     eprintln!("1. Generating OpenAPI specification from Rust code...");
-    let spec = openapi_spec();
+    // RESPONSE_ENVELOPE selects the documented response shape
+    let spec = openapi_spec_for(ResponseEnvelope::from_env());
     
     // Convert to formatted JSON
     let json = serde_json::to_string_pretty(&spec)?;
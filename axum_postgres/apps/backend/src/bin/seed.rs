@@ -0,0 +1,43 @@
+// Seed data loader
+// Loads the admin user and demo data: cargo run --bin seed -- --env dev|test [--reset]
+
+use backend::database::DatabaseConfig;
+use backend::seeds::{self, SeedOptions};
+use dotenvy::dotenv;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+
+    let options = match SeedOptions::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, SeedOptions::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if std::env::var("RUST_ENV").is_ok_and(|env| env == "production") {
+        eprintln!("Refusing to seed with RUST_ENV=production");
+        std::process::exit(1);
+    }
+
+    eprintln!("=== Seeding the {} data set ===", options.env);
+    let pool = DatabaseConfig::from_env()?.connect().await?;
+    let report = seeds::run(&pool, &options).await?;
+
+    if options.reset {
+        eprintln!("Removed {} seeded users", report.removed);
+    }
+    eprintln!(
+        "Created {} users, {} already present",
+        report.created.len(),
+        report.existing
+    );
+    for email in &report.created {
+        eprintln!("   + {}", email);
+    }
+    if let Some(password) = report.admin_password {
+        eprintln!("Admin password, shown only once: {}", password);
+    }
+    Ok(())
+}
@@ -1,5 +1,4 @@
 // Simple OpenAPI test binary
-use serde_json;
 
 fn main() {
     println!("=== Simple OpenAPI Test ===");
@@ -1,9 +1,20 @@
 // User database operations test binary
 use backend::database::create_pool_from_env;
 use backend::models::user::{CreateUserRequest, UpdateUserRequest};
+use backend::models::user_id::UserId;
 use backend::repository::user::{UserRepository, UserRepositoryTrait};
 use dotenvy::dotenv;
 
+/// An id that no user has, in either id format
+fn missing_user_id() -> UserId {
+    let id = if cfg!(feature = "uuid-ids") {
+        "00000000-0000-7000-8000-000000000000"
+    } else {
+        "99999"
+    };
+    id.parse().unwrap()
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
@@ -22,11 +33,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let create_request = CreateUserRequest {
         name: "Operations Test User".to_string(),
         email: "operations_test@example.com".to_string(),
+        ..Default::default()
     };
 
     let created_user = repo.create_user(create_request).await?;
     println!("✅ User created successfully:");
-    println!("   ID: {}", created_user.id);
+    println!("   ID: {}", created_user.user_id());
     println!("   Name: {}", created_user.name);
     println!("   Email: {}", created_user.email);
     println!("   Active: {}", created_user.active);
@@ -34,7 +46,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Test get user by id
     println!("\n3. Testing get user by ID...");
-    let retrieved_user = repo.get_user_by_id(created_user.id).await?;
+    let retrieved_user = repo.get_user_by_id(created_user.user_id()).await?;
     if let Some(user) = retrieved_user {
         println!("✅ User retrieved successfully:");
         println!("   Name: {}", user.name);
@@ -62,9 +74,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         name: Some("Updated Operations User".to_string()),
         email: None,
         active: None,
+        ..Default::default()
     };
 
-    let updated_user = repo.update_user(created_user.id, update_request).await?;
+    let updated_user = repo.update_user(created_user.user_id(), update_request).await?;
     if let Some(user) = updated_user {
         println!("✅ User updated successfully:");
         println!("   Name: {} (changed)", user.name);
@@ -80,9 +93,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         name: Some("Fully Updated User".to_string()),
         email: Some("fully_updated@example.com".to_string()),
         active: Some(false),
+        ..Default::default()
     };
 
-    let updated_user = repo.update_user(created_user.id, update_request).await?;
+    let updated_user = repo.update_user(created_user.user_id(), update_request).await?;
     if let Some(user) = updated_user {
         println!("✅ User updated successfully:");
         println!("   Name: {} (changed)", user.name);
@@ -98,9 +112,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         name: Some("Ghost User".to_string()),
         email: None,
         active: None,
+        ..Default::default()
     };
 
-    let not_updated = repo.update_user(99999, update_request).await?;
+    let not_updated = repo.update_user(missing_user_id(), update_request).await?;
     if not_updated.is_none() {
         println!("✅ Correctly handled non-existent user update");
     } else {
@@ -109,12 +124,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Test delete user
     println!("\n8. Testing user deletion...");
-    let deleted = repo.delete_user(created_user.id).await?;
+    let deleted = repo.delete_user(created_user.user_id()).await?;
     if deleted {
         println!("✅ User deleted successfully");
 
         // Verify deletion
-        let verified = repo.get_user_by_id(created_user.id).await?;
+        let verified = repo.get_user_by_id(created_user.user_id()).await?;
         if verified.is_none() {
             println!("✅ Deletion verified - user not found");
         } else {
@@ -126,7 +141,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Test edge case: delete non-existent user
     println!("\n9. Testing edge case: delete non-existent user...");
-    let not_deleted = repo.delete_user(99999).await?;
+    let not_deleted = repo.delete_user(missing_user_id()).await?;
     if !not_deleted {
         println!("✅ Correctly handled non-existent user deletion");
     } else {
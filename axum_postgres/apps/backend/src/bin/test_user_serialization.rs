@@ -1,7 +1,7 @@
 // User serialization test binary
-use backend::models::user::{User, UserResponse, CreateUserRequest, UpdateUserRequest};
+use backend::models::user::{User, CreateUserRequest, UpdateUserRequest};
+use uuid::Uuid;
 use chrono::Utc;
-use serde_json;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -11,10 +11,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("1. Testing User struct serialization...");
     let user = User {
         id: 42,
+        public_id: Uuid::nil(),
         name: "Alice Johnson".to_string(),
         email: "alice@example.com".to_string(),
         active: true,
+        display_name: None,
+        bio: None,
+        phone: None,
+        timezone: None,
+        locale: None,
+        email_verified: false,
         created_at: Utc::now(),
+        updated_at: Utc::now(),
+        deleted_at: None,
     };
 
     let user_json = serde_json::to_string_pretty(&user)?;
@@ -31,7 +40,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let response_json = serde_json::to_string_pretty(&user_response)?;
     println!("UserResponse JSON:\n{}", response_json);
     println!("✅ UserResponse conversion successful");
-    println!("   ID converted: {} -> {}", user.id, user_response.id);
+    println!("   ID converted: {} -> {}", user.user_id(), user_response.id);
 
     // Test CreateUserRequest validation
     println!("\n3. Testing CreateUserRequest validation...");
@@ -40,6 +49,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let valid_request = CreateUserRequest {
         name: "Bob Smith".to_string(),
         email: "bob@example.com".to_string(),
+        ..Default::default()
     };
     
     match validator::Validate::validate(&valid_request) {
@@ -51,6 +61,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let invalid_email_request = CreateUserRequest {
         name: "Charlie Brown".to_string(),
         email: "invalid-email".to_string(),
+        ..Default::default()
     };
     
     match validator::Validate::validate(&invalid_email_request) {
@@ -65,6 +76,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let empty_name_request = CreateUserRequest {
         name: "".to_string(),
         email: "test@example.com".to_string(),
+        ..Default::default()
     };
     
     match validator::Validate::validate(&empty_name_request) {
@@ -82,6 +94,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         name: Some("Updated Name".to_string()),
         email: Some("updated@example.com".to_string()),
         active: Some(false),
+        ..Default::default()
     };
     
     match validator::Validate::validate(&valid_update) {
@@ -97,17 +110,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let users = vec![
         User {
             id: 1,
+            public_id: Uuid::nil(),
             name: "User One".to_string(),
             email: "one@example.com".to_string(),
             active: true,
+            display_name: None,
+            bio: None,
+            phone: None,
+            timezone: None,
+            locale: None,
+            email_verified: false,
             created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
         },
         User {
             id: 2,
+            public_id: Uuid::nil(),
             name: "User Two".to_string(),
             email: "two@example.com".to_string(),
             active: false,
+            display_name: None,
+            bio: None,
+            phone: None,
+            timezone: None,
+            locale: None,
+            email_verified: false,
             created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
         },
     ];
 
@@ -0,0 +1,167 @@
+//! The in-process tier: a bounded LRU in front of Redis
+//!
+//! Hot entries are answered without a network round trip, and keep being
+//! answered while Redis is down. Other replicas' writes reach this tier
+//! only through the change feed, so its entries live for a short TTL of
+//! their own whatever the caller asks for.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use moka::sync::Cache as Lru;
+use moka::Expiry;
+
+use super::Cache;
+
+/// Default for CACHE_MEMORY_CAPACITY
+pub const DEFAULT_CAPACITY: u64 = 10_000;
+
+/// Default for CACHE_MEMORY_TTL_MS
+pub const DEFAULT_MAX_TTL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+enum Value {
+    Text(String),
+    Fields(HashMap<String, String>),
+}
+
+#[derive(Clone)]
+struct Entry {
+    value: Arc<Value>,
+    ttl: Duration,
+}
+
+/// Expires every entry after its own TTL
+struct EntryTtl;
+
+impl Expiry<String, Entry> for EntryTtl {
+    fn expire_after_create(&self, _key: &String, entry: &Entry, _at: Instant) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &Entry,
+        _at: Instant,
+        _remaining: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+/// Cache tier in this process's memory
+///
+/// Cheap to clone; clones share the entries.
+#[derive(Clone)]
+pub struct MemoryCache {
+    entries: Lru<String, Entry>,
+    max_ttl: Duration,
+}
+
+impl MemoryCache {
+    /// Tier holding up to `capacity` entries, each for at most `max_ttl`
+    pub fn new(capacity: u64, max_ttl: Duration) -> Self {
+        Self {
+            entries: Lru::builder()
+                .max_capacity(capacity)
+                .expire_after(EntryTtl)
+                .build(),
+            max_ttl,
+        }
+    }
+
+    fn insert(&self, key: &str, value: Value, ttl: Duration) {
+        let entry = Entry {
+            value: Arc::new(value),
+            ttl: ttl.min(self.max_ttl),
+        };
+        self.entries.insert(key.to_string(), entry);
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    fn tier(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Option<String> {
+        match &*self.entries.get(key)?.value {
+            Value::Text(value) => Some(value.clone()),
+            Value::Fields(_) => None,
+        }
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) {
+        self.insert(key, Value::Text(value.to_string()), ttl);
+    }
+
+    async fn get_field(&self, key: &str, field: &str) -> Option<String> {
+        match &*self.entries.get(key)?.value {
+            Value::Fields(fields) => fields.get(field).cloned(),
+            Value::Text(_) => None,
+        }
+    }
+
+    /// Copies the hash; a field set concurrently may be lost, which only
+    /// costs a miss
+    async fn set_field(&self, key: &str, field: &str, value: &str, ttl: Duration) {
+        let mut fields = match self.entries.get(key).map(|entry| entry.value) {
+            Some(value) => match &*value {
+                Value::Fields(fields) => fields.clone(),
+                Value::Text(_) => HashMap::new(),
+            },
+            None => HashMap::new(),
+        };
+        fields.insert(field.to_string(), value.to_string());
+        self.insert(key, Value::Fields(fields), ttl);
+    }
+
+    async fn delete(&self, key: &str) {
+        self.entries.invalidate(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_values_and_fields() {
+        let memory = MemoryCache::new(100, Duration::from_secs(60));
+        memory.set("user", "1", Duration::from_secs(60)).await;
+        assert_eq!(memory.get("user").await.as_deref(), Some("1"));
+
+        memory
+            .set_field("list", "page:10", "[]", Duration::from_secs(60))
+            .await;
+        memory
+            .set_field("list", "after:10", "[1]", Duration::from_secs(60))
+            .await;
+        assert_eq!(
+            memory.get_field("list", "page:10").await.as_deref(),
+            Some("[]")
+        );
+        assert_eq!(memory.get("list").await, None);
+
+        // Deleting the hash drops every field
+        memory.delete("list").await;
+        assert_eq!(memory.get_field("list", "after:10").await, None);
+        assert_eq!(memory.get("user").await.as_deref(), Some("1"));
+    }
+
+    #[tokio::test]
+    async fn test_entries_expire_after_at_most_max_ttl() {
+        let memory = MemoryCache::new(100, Duration::from_millis(50));
+        memory.set("capped", "1", Duration::from_secs(60)).await;
+        memory.set("short", "1", Duration::from_millis(10)).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(memory.get("short").await, None);
+        assert_eq!(memory.get("capped").await.as_deref(), Some("1"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(memory.get("capped").await, None);
+    }
+}
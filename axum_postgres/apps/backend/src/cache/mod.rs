@@ -0,0 +1,382 @@
+//! Caching in tiers: an in-process LRU in front of Redis
+//!
+//! Every tier implements [`Cache`], and so does [`TieredCache`], which
+//! reads the tiers fastest first and writes through all of them; callers
+//! only ever see the one trait. The in-process tier answers hot lookups
+//! without a network round trip, and Redis shares entries between
+//! replicas.
+//!
+//! Set REDIS_URL to turn caching on. Every tier is optional: when Redis is
+//! unset, down or slow, reads miss and writes are dropped with a warning,
+//! and callers carry on from the database.
+
+pub mod memory;
+pub mod redis;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use metrics::counter;
+use serde::{de::DeserializeOwned, Serialize};
+
+use self::memory::MemoryCache;
+use self::redis::RedisCache;
+use crate::health::HealthCheck;
+
+/// Redis commands that failed, by operation
+pub const CACHE_ERRORS_TOTAL: &str = "cache_errors_total";
+
+/// Reads answered from the cache, by kind of entry
+pub const CACHE_HITS_TOTAL: &str = "cache_hits_total";
+
+/// Reads that went on to the database, by kind of entry
+pub const CACHE_MISSES_TOTAL: &str = "cache_misses_total";
+
+/// Reads a tier answered, by tier
+pub const CACHE_TIER_HITS_TOTAL: &str = "cache_tier_hits_total";
+
+/// Reads a tier passed on to the next one or the caller, by tier
+pub const CACHE_TIER_MISSES_TOTAL: &str = "cache_tier_misses_total";
+
+/// Default for CACHE_TIMEOUT_MS
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Default for CACHE_TTL_MS
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// A cache of text values and hashes of them, each with a TTL
+///
+/// Failures are not errors: a tier that cannot answer misses, and one that
+/// cannot store drops the write.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Name of the tier in metrics
+    fn tier(&self) -> &'static str;
+
+    async fn get(&self, key: &str) -> Option<String>;
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration);
+
+    /// `field` of the hash at `key`
+    async fn get_field(&self, key: &str, field: &str) -> Option<String>;
+
+    /// Set `field` of the hash at `key`, keeping the whole hash for `ttl`
+    /// from now
+    ///
+    /// Related entries go in one hash so that [`Cache::delete`] drops them
+    /// all at once.
+    async fn set_field(&self, key: &str, field: &str, value: &str, ttl: Duration);
+
+    /// Remove `key`, a value or a whole hash
+    async fn delete(&self, key: &str);
+}
+
+/// JSON values on top of any [`Cache`]
+#[async_trait]
+pub trait CacheExt: Cache {
+    /// The value stored at `key`; None when it is missing, unreadable or
+    /// the cache is unavailable
+    async fn get_json<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        decode(key, &self.get(key).await?)
+    }
+
+    /// Store `value` at `key` for `ttl`
+    async fn set_json<T: Serialize + Sync>(&self, key: &str, value: &T, ttl: Duration) {
+        if let Some(value) = encode(key, value) {
+            self.set(key, &value, ttl).await;
+        }
+    }
+
+    /// The value stored in `field` of the hash at `key`, like
+    /// [`CacheExt::get_json`]
+    async fn get_field_json<T: DeserializeOwned>(&self, key: &str, field: &str) -> Option<T> {
+        decode(key, &self.get_field(key, field).await?)
+    }
+
+    /// Store `value` in `field` of the hash at `key`, see [`Cache::set_field`]
+    async fn set_field_json<T: Serialize + Sync>(
+        &self,
+        key: &str,
+        field: &str,
+        value: &T,
+        ttl: Duration,
+    ) {
+        if let Some(value) = encode(key, value) {
+            self.set_field(key, field, &value, ttl).await;
+        }
+    }
+}
+
+impl<C: Cache + ?Sized> CacheExt for C {}
+
+fn decode<T: DeserializeOwned>(key: &str, value: &str) -> Option<T> {
+    serde_json::from_str(value)
+        .map_err(|e| tracing::warn!("Ignoring unreadable cache entry {}: {}", key, e))
+        .ok()
+}
+
+fn encode<T: Serialize>(key: &str, value: &T) -> Option<String> {
+    serde_json::to_string(value)
+        .map_err(|e| tracing::warn!("Failed to serialize cache entry {}: {}", key, e))
+        .ok()
+}
+
+/// The app's cache: the configured tiers, fastest first; does nothing
+/// without any
+///
+/// Cheap to clone; clones share the tiers.
+#[derive(Clone)]
+pub struct TieredCache {
+    tiers: Arc<[Arc<dyn Cache>]>,
+    redis: Option<RedisCache>,
+    ttl: Duration,
+}
+
+impl Default for TieredCache {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+impl TieredCache {
+    /// Cache on `memory` in front of `redis`, either of them optional
+    pub fn new(memory: Option<MemoryCache>, redis: Option<RedisCache>) -> Self {
+        let mut tiers: Vec<Arc<dyn Cache>> = Vec::new();
+        if let Some(memory) = memory {
+            tiers.push(Arc::new(memory));
+        }
+        if let Some(redis) = &redis {
+            tiers.push(Arc::new(redis.clone()));
+        }
+        Self {
+            tiers: tiers.into(),
+            redis,
+            ttl: DEFAULT_TTL,
+        }
+    }
+
+    /// Keep entries for `ttl` unless a caller asks otherwise
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Tiers from the environment:
+    /// - Redis on REDIS_URL, with CACHE_TIMEOUT_MS per command (250 by
+    ///   default)
+    /// - CACHE_MEMORY_CAPACITY entries in memory, for at most
+    ///   CACHE_MEMORY_TTL_MS each (5 seconds by default); 10000 entries by
+    ///   default with Redis, none without, and 0 turns the tier off
+    ///
+    /// Entries are kept for CACHE_TTL_MS, a minute by default.
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let number = |name: &str| -> Result<Option<u64>, String> {
+            var(name)
+                .map(|value| {
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number: {}", name, value))
+                })
+                .transpose()
+        };
+        let millis = |name: &str, default| -> Result<Duration, String> {
+            match number(name)? {
+                Some(0) => Err(format!("{} must be positive", name)),
+                Some(millis) => Ok(Duration::from_millis(millis)),
+                None => Ok(default),
+            }
+        };
+
+        let redis = match var("REDIS_URL") {
+            Some(url) => Some(RedisCache::new(
+                &url,
+                millis("CACHE_TIMEOUT_MS", DEFAULT_TIMEOUT)?,
+            )?),
+            None => None,
+        };
+        let default_capacity = if redis.is_some() {
+            memory::DEFAULT_CAPACITY
+        } else {
+            0
+        };
+        let capacity = number("CACHE_MEMORY_CAPACITY")?.unwrap_or(default_capacity);
+        let max_ttl = millis("CACHE_MEMORY_TTL_MS", memory::DEFAULT_MAX_TTL)?;
+        let memory = (capacity > 0).then(|| MemoryCache::new(capacity, max_ttl));
+
+        Ok(Self::new(memory, redis).with_ttl(millis("CACHE_TTL_MS", DEFAULT_TTL)?))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tiers.is_empty()
+    }
+
+    /// How long entries are kept by default
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// The Redis tier, when enabled
+    pub fn redis(&self) -> Option<&RedisCache> {
+        self.redis.as_ref()
+    }
+
+    /// Health check reporting Redis as an optional dependency, when enabled
+    pub fn check(&self) -> Option<CacheCheck> {
+        self.redis.clone().map(CacheCheck)
+    }
+
+    #[cfg(test)]
+    fn from_tiers(tiers: Vec<Arc<dyn Cache>>) -> Self {
+        Self {
+            tiers: tiers.into(),
+            redis: None,
+            ttl: DEFAULT_TTL,
+        }
+    }
+}
+
+/// Count a read of `tier`
+fn record(tier: &dyn Cache, hit: bool) {
+    let name = if hit {
+        CACHE_TIER_HITS_TOTAL
+    } else {
+        CACHE_TIER_MISSES_TOTAL
+    };
+    counter!(name, 1, "tier" => tier.tier());
+}
+
+/// Reads go through the tiers until one answers, and a hit in a slower
+/// tier is copied into the faster ones; writes go to every tier
+#[async_trait]
+impl Cache for TieredCache {
+    fn tier(&self) -> &'static str {
+        "tiered"
+    }
+
+    async fn get(&self, key: &str) -> Option<String> {
+        for (i, tier) in self.tiers.iter().enumerate() {
+            let Some(value) = tier.get(key).await else {
+                record(tier.as_ref(), false);
+                continue;
+            };
+            record(tier.as_ref(), true);
+            for faster in &self.tiers[..i] {
+                faster.set(key, &value, self.ttl).await;
+            }
+            return Some(value);
+        }
+        None
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) {
+        for tier in self.tiers.iter() {
+            tier.set(key, value, ttl).await;
+        }
+    }
+
+    async fn get_field(&self, key: &str, field: &str) -> Option<String> {
+        for (i, tier) in self.tiers.iter().enumerate() {
+            let Some(value) = tier.get_field(key, field).await else {
+                record(tier.as_ref(), false);
+                continue;
+            };
+            record(tier.as_ref(), true);
+            for faster in &self.tiers[..i] {
+                faster.set_field(key, field, &value, self.ttl).await;
+            }
+            return Some(value);
+        }
+        None
+    }
+
+    async fn set_field(&self, key: &str, field: &str, value: &str, ttl: Duration) {
+        for tier in self.tiers.iter() {
+            tier.set_field(key, field, value, ttl).await;
+        }
+    }
+
+    async fn delete(&self, key: &str) {
+        for tier in self.tiers.iter() {
+            tier.delete(key).await;
+        }
+    }
+}
+
+/// Redis as an optional health check: down means degraded, not unready
+pub struct CacheCheck(RedisCache);
+
+#[async_trait]
+impl HealthCheck for CacheCheck {
+    fn name(&self) -> &str {
+        "cache"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        self.0.ping().await
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_disabled_cache_misses_and_drops_writes() {
+        let cache = TieredCache::default();
+        assert!(!cache.is_enabled());
+        assert!(cache.check().is_none());
+        cache.set_json("key", &1, Duration::from_secs(60)).await;
+        assert_eq!(cache.get_json::<i32>("key").await, None);
+        cache
+            .set_field_json("key", "field", &1, Duration::from_secs(60))
+            .await;
+        assert_eq!(cache.get_field_json::<i32>("key", "field").await, None);
+        cache.delete("key").await;
+    }
+
+    #[tokio::test]
+    async fn test_slower_tier_hits_fill_the_faster_tiers() {
+        let ttl = Duration::from_secs(60);
+        let fast = MemoryCache::new(100, ttl);
+        let slow = MemoryCache::new(100, ttl);
+        let cache = TieredCache::from_tiers(vec![Arc::new(fast.clone()), Arc::new(slow.clone())]);
+
+        slow.set("user", "1", ttl).await;
+        slow.set_field("list", "page:10", "[]", ttl).await;
+        assert_eq!(cache.get_json::<i32>("user").await, Some(1));
+        assert_eq!(
+            cache.get_field("list", "page:10").await.as_deref(),
+            Some("[]")
+        );
+        assert_eq!(fast.get("user").await.as_deref(), Some("1"));
+        assert_eq!(
+            fast.get_field("list", "page:10").await.as_deref(),
+            Some("[]")
+        );
+
+        cache.delete("user").await;
+        assert_eq!(fast.get("user").await, None);
+        assert_eq!(slow.get("user").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_memory_tier_answers_while_redis_is_down() {
+        // Nothing listens on port 1
+        let redis = RedisCache::new("redis://127.0.0.1:1", Duration::from_millis(100)).unwrap();
+        let memory = MemoryCache::new(100, Duration::from_secs(60));
+        let cache = TieredCache::new(Some(memory), Some(redis));
+        assert!(cache.check().is_some());
+
+        cache.set_json("key", &1, Duration::from_secs(60)).await;
+        assert_eq!(cache.get_json::<i32>("key").await, Some(1));
+        cache.delete("key").await;
+        assert_eq!(cache.get_json::<i32>("key").await, None);
+    }
+}
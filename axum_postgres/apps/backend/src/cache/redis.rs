@@ -0,0 +1,201 @@
+//! The Redis tier, shared by every replica
+//!
+//! After a failed connection attempt the tier stays off for a few seconds
+//! instead of making every request wait for another one.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use metrics::counter;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::AsyncCommands;
+use tokio::sync::OnceCell;
+
+use super::{Cache, CACHE_ERRORS_TOTAL};
+
+/// Wait after a failed connection attempt before the next one
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Cache tier on a Redis server
+///
+/// Cheap to clone; clones share the connection.
+#[derive(Clone)]
+pub struct RedisCache(Arc<Redis>);
+
+struct Redis {
+    client: redis::Client,
+    /// Longest a connection attempt or a command may take
+    timeout: Duration,
+    /// Opened on first use; reconnects by itself once open
+    connection: OnceCell<ConnectionManager>,
+    /// No connection attempts before this after one failed
+    retry_at: Mutex<Option<Instant>>,
+}
+
+impl RedisCache {
+    /// Tier on the Redis at `url`, connecting on first use
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, String> {
+        let client =
+            redis::Client::open(url).map_err(|e| format!("REDIS_URL is invalid: {}", e))?;
+        Ok(Self(Arc::new(Redis {
+            client,
+            timeout,
+            connection: OnceCell::new(),
+            retry_at: Mutex::new(None),
+        })))
+    }
+
+    /// Round trip to Redis
+    pub async fn ping(&self) -> Result<(), String> {
+        let mut connection = self.0.connect().await.map_err(|e| e.to_string())?;
+        redis::cmd("PING")
+            .query_async::<String>(&mut connection)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// The shared connection, None while Redis is unavailable
+    ///
+    /// Also used for state shared between replicas besides cached entries.
+    pub(crate) async fn connection(&self) -> Option<ConnectionManager> {
+        let redis = &self.0;
+        if let Some(connection) = redis.connection.get() {
+            return Some(connection.clone());
+        }
+        if redis
+            .retry_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| Instant::now() < at)
+        {
+            return None;
+        }
+        match redis.connect().await {
+            Ok(connection) => Some(connection),
+            Err(e) => {
+                tracing::warn!("Failed to connect to Redis; continuing without it: {}", e);
+                counter!(CACHE_ERRORS_TOTAL, 1, "operation" => "connect");
+                *redis.retry_at.lock().unwrap() = Some(Instant::now() + RECONNECT_DELAY);
+                None
+            }
+        }
+    }
+}
+
+impl Redis {
+    async fn connect(&self) -> redis::RedisResult<ConnectionManager> {
+        self.connection
+            .get_or_try_init(|| {
+                let config = ConnectionManagerConfig::new()
+                    .set_connection_timeout(self.timeout)
+                    .set_response_timeout(self.timeout)
+                    // Retries are ours: see retry_at
+                    .set_number_of_retries(0);
+                ConnectionManager::new_with_config(self.client.clone(), config)
+            })
+            .await
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    fn tier(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Option<String> {
+        let mut connection = self.connection().await?;
+        connection
+            .get(key)
+            .await
+            .map_err(|e| failed("get", key, e))
+            .ok()?
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) {
+        let Some(mut connection) = self.connection().await else {
+            return;
+        };
+        let _: Result<(), _> = connection
+            .pset_ex(key, value, ttl_millis(ttl))
+            .await
+            .map_err(|e| failed("set", key, e));
+    }
+
+    async fn get_field(&self, key: &str, field: &str) -> Option<String> {
+        let mut connection = self.connection().await?;
+        connection
+            .hget(key, field)
+            .await
+            .map_err(|e| failed("get", key, e))
+            .ok()?
+    }
+
+    /// Sets the field and the TTL of the whole hash in one transaction
+    async fn set_field(&self, key: &str, field: &str, value: &str, ttl: Duration) {
+        let Some(mut connection) = self.connection().await else {
+            return;
+        };
+        let _: Result<(), _> = redis::pipe()
+            .atomic()
+            .hset(key, field, value)
+            .ignore()
+            .pexpire(key, ttl_millis(ttl) as i64)
+            .ignore()
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| failed("set", key, e));
+    }
+
+    async fn delete(&self, key: &str) {
+        let Some(mut connection) = self.connection().await else {
+            return;
+        };
+        let _: Result<(), _> = connection
+            .del(key)
+            .await
+            .map_err(|e| failed("delete", key, e));
+    }
+}
+
+/// `ttl` in whole milliseconds; Redis rejects a TTL of 0
+fn ttl_millis(ttl: Duration) -> u64 {
+    ttl.as_millis().clamp(1, i64::MAX as u128) as u64
+}
+
+/// Log a failed command and carry on
+fn failed(operation: &'static str, key: &str, e: redis::RedisError) {
+    tracing::warn!("Redis {} of {} failed: {}", operation, key, e);
+    counter!(CACHE_ERRORS_TOTAL, 1, "operation" => operation);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_redis_degrades_to_misses() {
+        // Nothing listens on port 1
+        let redis = RedisCache::new("redis://127.0.0.1:1", Duration::from_millis(100)).unwrap();
+
+        let started = Instant::now();
+        redis.set("key", "1", Duration::from_secs(60)).await;
+        assert_eq!(redis.get("key").await, None);
+        redis
+            .set_field("key", "field", "1", Duration::from_secs(60))
+            .await;
+        assert_eq!(redis.get_field("key", "field").await, None);
+        redis.delete("key").await;
+        assert!(redis.ping().await.is_err());
+        // Only the first attempt waits; the rest skip Redis until retry_at
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_invalid_url_is_rejected() {
+        assert!(RedisCache::new("not a url", Duration::from_millis(100)).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+//! Response field casing
+//!
+//! Fields are snake_case by default. Building with the `camel-case` feature
+//! renames response model fields to camelCase through serde, which utoipa
+//! also reads for the OpenAPI schemas. The helpers here apply the same rule
+//! to JSON that is not produced by a model, such as history snapshots and
+//! hand-written OpenAPI examples.
+
+use serde_json::{Map, Value};
+
+/// Whether responses use camelCase field names
+pub const CAMEL_CASE: bool = cfg!(feature = "camel-case");
+
+/// `created_at` -> `createdAt`; leading underscores (`_links`) are kept
+pub fn camel_case(name: &str) -> String {
+    let trimmed = name.trim_start_matches('_');
+    let mut out = name[..name.len() - trimmed.len()].to_string();
+    let mut upper = false;
+    for c in trimmed.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Field name under the configured casing
+pub fn field_name(name: &str) -> String {
+    if CAMEL_CASE {
+        camel_case(name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Rename every object key in `value` under the configured casing
+pub fn rename_keys(value: Value) -> Value {
+    if !CAMEL_CASE {
+        return value;
+    }
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| (camel_case(&key), rename_keys(value)))
+                .collect::<Map<String, Value>>(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(rename_keys).collect()),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(camel_case("created_at"), "createdAt");
+        assert_eq!(camel_case("error_budget_remaining"), "errorBudgetRemaining");
+        assert_eq!(camel_case("_links"), "_links");
+        assert_eq!(camel_case("name"), "name");
+    }
+}
@@ -0,0 +1,294 @@
+//! Change feed of user rows, from PostgreSQL LISTEN/NOTIFY
+//!
+//! A trigger notifies the `user_changes` channel whenever a user row
+//! changes, whoever changed it: this replica, another one, or SQL run by
+//! hand. [`ChangeFeed::listen`] receives those notifications on a
+//! connection of its own and fans them out to every in-process
+//! subscriber, so caches can drop stale entries and connected clients can
+//! be told about changes.
+//!
+//! Notifications sent while the listener is disconnected are lost. The
+//! feed then sends [`ChangeEvent::Resync`], and subscribers should treat
+//! anything derived from earlier changes as stale.
+//!
+//! Every replica listens by default. With [`Fanout::Redis`] one of them
+//! relays the notifications through Redis pub/sub to all the others; see
+//! [`redis`].
+
+pub mod redis;
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use tokio::sync::{broadcast, watch};
+
+use crate::models::user_id::UserId;
+
+/// Channel the `test_users` trigger notifies
+pub const CHANNEL: &str = "user_changes";
+
+/// Events a subscriber can fall behind by before it misses some
+const CAPACITY: usize = 1024;
+
+/// Wait between attempts to reconnect the listener
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// What happened to a user row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ChangeOp {
+    Insert,
+    /// Any update, including soft deletes and restores
+    Update,
+    /// The row is gone for good
+    Delete,
+}
+
+/// A committed change to a user row, as the trigger notifies it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct UserChange {
+    pub op: ChangeOp,
+    /// The payload carries both ids; clients know users by `public_id`
+    /// with `uuid-ids`
+    #[cfg_attr(feature = "uuid-ids", serde(rename = "public_id"))]
+    pub id: UserId,
+    pub tenant_id: i32,
+    /// The user is soft-deleted or gone for good; false in payloads of
+    /// triggers that predate the flag
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+impl UserChange {
+    /// The change a `user_changes` payload describes
+    pub fn parse(payload: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(payload)
+    }
+}
+
+/// What subscribers receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeEvent {
+    User(UserChange),
+    /// The listener reconnected and changes in between may have been lost
+    Resync,
+}
+
+/// Fans database changes out to in-process subscribers
+///
+/// Cheap to clone; clones share subscribers. Without a running
+/// [`ChangeFeed::listen`] subscribers receive nothing.
+#[derive(Debug, Clone)]
+pub struct ChangeFeed {
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+/// How changes reach the feed of each replica
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fanout {
+    /// Every replica listens on PostgreSQL
+    Postgres,
+    /// One replica listens and relays through the Redis at this URL
+    Redis(String),
+}
+
+impl Fanout {
+    /// CHANGE_FEED_FANOUT: `postgres` unless set, or `redis`, which needs
+    /// REDIS_URL
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let fanout = var("CHANGE_FEED_FANOUT").unwrap_or_default();
+        match fanout.trim() {
+            "" | "postgres" => Ok(Self::Postgres),
+            "redis" => match var("REDIS_URL").filter(|url| !url.is_empty()) {
+                Some(url) => Ok(Self::Redis(url)),
+                None => Err("CHANGE_FEED_FANOUT=redis needs REDIS_URL".to_string()),
+            },
+            other => Err(format!(
+                "CHANGE_FEED_FANOUT must be postgres or redis: {}",
+                other
+            )),
+        }
+    }
+}
+
+impl ChangeFeed {
+    /// A feed fed as CHANGE_FEED_FANOUT says until shutdown, unless
+    /// CHANGE_FEED_ENABLED is `false`/`0`
+    pub fn spawn_from_env(pool: &PgPool, shutdown: watch::Receiver<bool>) -> Result<Self, String> {
+        let feed = Self::default();
+        let fanout = Fanout::from_env()?;
+        let enabled = std::env::var("CHANGE_FEED_ENABLED")
+            .map(|value| !matches!(value.to_ascii_lowercase().as_str(), "false" | "0"))
+            .unwrap_or(true);
+        if !enabled {
+            return Ok(feed);
+        }
+        match fanout {
+            Fanout::Postgres => {
+                tokio::spawn(feed.clone().listen(pool.clone(), shutdown));
+            }
+            Fanout::Redis(url) => {
+                let client = ::redis::Client::open(url)
+                    .map_err(|e| format!("REDIS_URL is invalid: {}", e))?;
+                tokio::spawn(redis::relay(pool.clone(), client.clone(), shutdown.clone()));
+                tokio::spawn(redis::subscribe(feed.clone(), client, shutdown));
+            }
+        }
+        Ok(feed)
+    }
+
+    /// Receive every event from now on
+    ///
+    /// A subscriber more than a thousand events behind gets
+    /// `RecvError::Lagged` and should resync like on [`ChangeEvent::Resync`].
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Send `event` to every current subscriber
+    pub fn publish(&self, event: ChangeEvent) {
+        // Nobody subscribed is not an error
+        let _ = self.sender.send(event);
+    }
+
+    /// Forward `user_changes` notifications until shutdown
+    ///
+    /// Holds one connection of `pool` for as long as it runs, and
+    /// reconnects whenever that connection is lost.
+    pub async fn listen(self, pool: PgPool, mut shutdown: watch::Receiver<bool>) {
+        let mut listener = loop {
+            match connect_listener(&pool).await {
+                Ok(listener) => break listener,
+                Err(e) => {
+                    tracing::warn!("Failed to listen on {}: {}", CHANNEL, e);
+                    if wait_or_shutdown(&mut shutdown).await {
+                        return;
+                    }
+                }
+            }
+        };
+        tracing::info!("Listening for changes on {}", CHANNEL);
+
+        loop {
+            tokio::select! {
+                notification = listener.try_recv() => match notification {
+                    Ok(Some(notification)) => match UserChange::parse(notification.payload()) {
+                        Ok(change) => self.publish(ChangeEvent::User(change)),
+                        Err(e) => tracing::warn!(
+                            "Ignoring malformed {} payload {:?}: {}",
+                            CHANNEL,
+                            notification.payload(),
+                            e
+                        ),
+                    },
+                    // The next try_recv reconnects and listens again
+                    Ok(None) => {
+                        tracing::warn!("Lost the {} listener connection; reconnecting", CHANNEL);
+                        self.publish(ChangeEvent::Resync);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to reconnect the {} listener: {}", CHANNEL, e);
+                        if wait_or_shutdown(&mut shutdown).await {
+                            return;
+                        }
+                    }
+                },
+                _ = shutdown.changed() => return,
+            }
+        }
+    }
+}
+
+async fn connect_listener(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(CHANNEL).await?;
+    Ok(listener)
+}
+
+/// Sleep before retrying; true when shutdown came first
+async fn wait_or_shutdown(shutdown: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(RECONNECT_DELAY) => false,
+        _ = shutdown.changed() => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn fanout(vars: &[(&str, &str)]) -> Result<Fanout, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        Fanout::from_lookup(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_fanout_from_env() {
+        assert_eq!(fanout(&[]), Ok(Fanout::Postgres));
+        assert_eq!(
+            fanout(&[("CHANGE_FEED_FANOUT", "postgres")]),
+            Ok(Fanout::Postgres)
+        );
+        assert_eq!(
+            fanout(&[
+                ("CHANGE_FEED_FANOUT", "redis"),
+                ("REDIS_URL", "redis://localhost:6379")
+            ]),
+            Ok(Fanout::Redis("redis://localhost:6379".to_string()))
+        );
+        assert_eq!(
+            fanout(&[("CHANGE_FEED_FANOUT", "redis")]),
+            Err("CHANGE_FEED_FANOUT=redis needs REDIS_URL".to_string())
+        );
+        assert!(fanout(&[("CHANGE_FEED_FANOUT", "kafka")]).is_err());
+    }
+
+    #[test]
+    fn test_parses_trigger_payloads() {
+        let change = UserChange::parse(
+            r#"{"op": "UPDATE", "id": 7, "public_id": "0190a2b4-0000-7000-8000-000000000007", "tenant_id": 2, "deleted": true}"#,
+        )
+        .unwrap();
+        assert_eq!(change.op, ChangeOp::Update);
+        assert_eq!(change.tenant_id, 2);
+        assert!(change.deleted);
+        #[cfg(not(feature = "uuid-ids"))]
+        assert_eq!(change.id.to_string(), "7");
+        #[cfg(feature = "uuid-ids")]
+        assert_eq!(
+            change.id.to_string(),
+            "0190a2b4-0000-7000-8000-000000000007"
+        );
+
+        assert!(!UserChange::parse(r#"{"op": "INSERT", "id": 7, "public_id": "0190a2b4-0000-7000-8000-000000000007", "tenant_id": 2}"#).unwrap().deleted);
+        assert!(UserChange::parse(r#"{"op": "TRUNCATE", "id": 7}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publish_reaches_every_subscriber() {
+        let feed = ChangeFeed::default();
+        // Publishing without subscribers is fine
+        feed.publish(ChangeEvent::Resync);
+
+        let mut first = feed.subscribe();
+        let mut second = feed.clone().subscribe();
+        feed.publish(ChangeEvent::Resync);
+        assert_eq!(first.recv().await.unwrap(), ChangeEvent::Resync);
+        assert_eq!(second.recv().await.unwrap(), ChangeEvent::Resync);
+    }
+}
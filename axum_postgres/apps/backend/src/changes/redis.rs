@@ -0,0 +1,256 @@
+//! Fanout of the change feed through Redis pub/sub
+//!
+//! With CHANGE_FEED_FANOUT=redis, replicas do not each LISTEN on
+//! PostgreSQL. One replica at a time, whichever holds the relay's advisory
+//! lock, listens and publishes every notification to the Redis channel
+//! [`CHANNEL`]; every replica, that one included, subscribes to the
+//! channel and hands what arrives to its [`ChangeFeed`] subscribers. The
+//! feed then holds one database connection in all, however many replicas
+//! serve WebSocket clients.
+//!
+//! Both sides reconnect by themselves. Messages published while a
+//! subscriber is disconnected are lost, so it sends [`ChangeEvent::Resync`]
+//! to its subscribers, and a replica that takes over the relay asks every
+//! replica to resync for the changes it may have missed.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::StreamExt;
+use metrics::{counter, histogram};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use super::{wait_or_shutdown, ChangeEvent, ChangeFeed, UserChange};
+use crate::database::lock::lock_key;
+
+/// Redis channel the relay publishes changes to
+pub const CHANNEL: &str = "changes:user_changes";
+
+/// Advisory lock held by the replica that relays
+const RELAY_LOCK: &str = "changes.relay";
+
+/// Time from the relay publishing a change to a replica receiving it
+pub const FANOUT_LAG: &str = "change_feed_fanout_lag_seconds";
+
+/// Connections to Redis or PostgreSQL the fanout lost or failed to open,
+/// by side
+pub const FANOUT_RECONNECTS_TOTAL: &str = "change_feed_fanout_reconnects_total";
+
+/// Longest a connection attempt or a publish may take
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the relay publishes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Message {
+    /// A `user_changes` payload; none asks subscribers to resync
+    payload: Option<String>,
+    /// When it was published, in milliseconds since the Unix epoch
+    sent_at: i64,
+}
+
+impl Message {
+    fn new(payload: Option<&str>) -> Self {
+        Self {
+            payload: payload.map(str::to_string),
+            sent_at: Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+/// Relay `user_changes` notifications to Redis whenever this replica
+/// holds the relay lock, until shutdown
+pub async fn relay(pool: PgPool, client: redis::Client, mut shutdown: watch::Receiver<bool>) {
+    loop {
+        match relay_while_holding_lock(&pool, &client, &mut shutdown).await {
+            Ok(true) => return,
+            // Another replica relays; try again in case it stops
+            Ok(false) => {}
+            Err(e) => {
+                tracing::warn!("Stopped relaying changes to Redis: {}", e);
+                counter!(FANOUT_RECONNECTS_TOTAL, 1, "side" => "relay");
+            }
+        }
+        if wait_or_shutdown(&mut shutdown).await {
+            return;
+        }
+    }
+}
+
+/// Relay if the lock is free; true when it stopped for shutdown
+async fn relay_while_holding_lock(
+    pool: &PgPool,
+    client: &redis::Client,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<bool, String> {
+    let mut listener = PgListener::connect_with(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    let held: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+        .bind(lock_key(RELAY_LOCK))
+        .fetch_one(&mut listener)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !held {
+        return Ok(false);
+    }
+
+    let relayed = relay_notifications(&mut listener, client, shutdown).await;
+    // The lock belongs to the connection, which goes back to the pool
+    let _ = sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(lock_key(RELAY_LOCK))
+        .execute(&mut listener)
+        .await;
+    relayed
+}
+
+async fn relay_notifications(
+    listener: &mut PgListener,
+    client: &redis::Client,
+    shutdown: &mut watch::Receiver<bool>,
+) -> Result<bool, String> {
+    let config = redis::aio::ConnectionManagerConfig::new()
+        .set_connection_timeout(TIMEOUT)
+        .set_response_timeout(TIMEOUT);
+    let mut publisher = ConnectionManager::new_with_config(client.clone(), config)
+        .await
+        .map_err(|e| e.to_string())?;
+    listener
+        .listen(super::CHANNEL)
+        .await
+        .map_err(|e| e.to_string())?;
+    tracing::info!("Relaying {} to Redis", super::CHANNEL);
+
+    // Changes made while no replica relayed are lost
+    publish(&mut publisher, &Message::new(None)).await?;
+    loop {
+        tokio::select! {
+            notification = listener.try_recv() => match notification {
+                Ok(Some(notification)) => {
+                    publish(&mut publisher, &Message::new(Some(notification.payload()))).await?;
+                }
+                Ok(None) => return Err(format!("lost the {} listener connection", super::CHANNEL)),
+                Err(e) => return Err(e.to_string()),
+            },
+            _ = shutdown.changed() => return Ok(true),
+        }
+    }
+}
+
+async fn publish(publisher: &mut ConnectionManager, message: &Message) -> Result<(), String> {
+    let message = serde_json::to_string(message).map_err(|e| e.to_string())?;
+    publisher
+        .publish::<_, _, ()>(CHANNEL, message)
+        .await
+        .map_err(|e| format!("failed to publish to {}: {}", CHANNEL, e))
+}
+
+/// Pass what the relay publishes to the subscribers of `feed`, until
+/// shutdown
+pub async fn subscribe(
+    feed: ChangeFeed,
+    client: redis::Client,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        match client.get_async_pubsub().await {
+            Ok(mut pubsub) => match pubsub.subscribe(CHANNEL).await {
+                Ok(()) => {
+                    tracing::info!("Subscribed to {} on Redis", CHANNEL);
+                    let mut messages = pubsub.on_message();
+                    loop {
+                        tokio::select! {
+                            message = messages.next() => match message {
+                                Some(message) => match message.get_payload::<String>() {
+                                    Ok(text) => forward(&feed, &text),
+                                    Err(e) => tracing::warn!("Ignoring unreadable {} message: {}", CHANNEL, e),
+                                },
+                                None => break,
+                            },
+                            _ = shutdown.changed() => return,
+                        }
+                    }
+                    tracing::warn!("Lost the {} subscription; reconnecting", CHANNEL);
+                    feed.publish(ChangeEvent::Resync);
+                }
+                Err(e) => tracing::warn!("Failed to subscribe to {}: {}", CHANNEL, e),
+            },
+            Err(e) => tracing::warn!("Failed to connect to Redis for {}: {}", CHANNEL, e),
+        }
+        counter!(FANOUT_RECONNECTS_TOTAL, 1, "side" => "subscriber");
+        if wait_or_shutdown(&mut shutdown).await {
+            return;
+        }
+    }
+}
+
+/// Hand a published message to the subscribers of `feed`
+fn forward(feed: &ChangeFeed, text: &str) {
+    let message: Message = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => {
+            tracing::warn!("Ignoring malformed {} message {:?}: {}", CHANNEL, text, e);
+            return;
+        }
+    };
+    let lag_millis = (Utc::now().timestamp_millis() - message.sent_at).max(0);
+    histogram!(FANOUT_LAG, lag_millis as f64 / 1000.0);
+
+    match message.payload {
+        None => feed.publish(ChangeEvent::Resync),
+        Some(payload) => match UserChange::parse(&payload) {
+            Ok(change) => feed.publish(ChangeEvent::User(change)),
+            Err(e) => tracing::warn!(
+                "Ignoring malformed {} payload {:?}: {}",
+                super::CHANNEL,
+                payload,
+                e
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::changes::ChangeOp;
+
+    #[test]
+    fn test_forwards_published_changes() {
+        let feed = ChangeFeed::default();
+        let mut events = feed.subscribe();
+
+        let payload = r#"{"op": "INSERT", "id": 7, "public_id": "0190a2b4-0000-7000-8000-000000000007", "tenant_id": 1}"#;
+        let message = serde_json::to_string(&Message::new(Some(payload))).unwrap();
+        forward(&feed, &message);
+        match events.try_recv().unwrap() {
+            ChangeEvent::User(change) => assert_eq!(change.op, ChangeOp::Insert),
+            other => panic!("Expected a user change, got {:?}", other),
+        }
+
+        forward(&feed, &serde_json::to_string(&Message::new(None)).unwrap());
+        assert_eq!(events.try_recv().unwrap(), ChangeEvent::Resync);
+
+        forward(&feed, "not json");
+        forward(&feed, r#"{"payload": "{}", "sent_at": 0}"#);
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_stops_on_shutdown_while_redis_is_down() {
+        // Nothing listens on port 1
+        let client = redis::Client::open("redis://127.0.0.1:1").unwrap();
+        let (stop, shutdown) = watch::channel(false);
+        let subscriber = tokio::spawn(subscribe(ChangeFeed::default(), client, shutdown));
+
+        stop.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), subscriber)
+            .await
+            .expect("Subscriber stopped")
+            .unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! Conditional requests (RFC 9110 section 13)
+//!
+//! Each user has a strong ETag derived from its id and `updated_at`, which
+//! the database bumps on every change. GET answers 304 when `If-None-Match`
+//! lists the current tag, and writes carrying `If-Match` are refused with
+//! 412 once someone else has changed the user, so clients can update
+//! without overwriting a concurrent edit.
+//!
+//! Users also carry `Last-Modified`, for clients that only know
+//! `If-Modified-Since`; see [`crate::middleware::http_cache`].
+
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use chrono::{DateTime, Utc};
+
+use crate::error::AppError;
+use crate::models::user::User;
+
+/// Strong ETag of one user: `"user-<id>-<updated_at micros>"`
+pub fn user_etag(user: &User) -> String {
+    format!(
+        "\"user-{}-{}\"",
+        user.user_id(),
+        user.updated_at.timestamp_micros()
+    )
+}
+
+/// `Last-Modified` of one user: `updated_at` as an HTTP date
+pub fn user_last_modified(user: &User) -> String {
+    http_date(user.updated_at)
+}
+
+/// `at` in the IMF-fixdate format of HTTP headers, to the second
+pub fn http_date(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Whether a response last modified at `last_modified` changed after
+/// `since`, both HTTP dates
+///
+/// Dates that do not parse count as modified, so the full response is sent.
+pub fn modified_since(last_modified: &HeaderValue, since: &HeaderValue) -> bool {
+    let parse = |value: &HeaderValue| {
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+    };
+    match (parse(last_modified), parse(since)) {
+        (Some(last_modified), Some(since)) => last_modified > since,
+        _ => true,
+    }
+}
+
+/// Whether `If-None-Match` lists `etag`, using weak comparison
+pub fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    listed_tags(headers, header::IF_NONE_MATCH).any(|tag| tag == "*" || opaque(tag) == opaque(etag))
+}
+
+/// Fail with 412 unless `If-Match` is absent or lists `etag`
+///
+/// Uses strong comparison, so weak tags such as the listing's never match.
+pub fn check_if_match(headers: &HeaderMap, etag: &str) -> Result<(), AppError> {
+    if !headers.contains_key(header::IF_MATCH)
+        || listed_tags(headers, header::IF_MATCH).any(|tag| tag == "*" || tag == etag)
+    {
+        return Ok(());
+    }
+    Err(AppError::PreconditionFailed(
+        "User was modified since the given ETag".to_string(),
+    ))
+}
+
+/// Whether the request carries `If-Match` and so needs the current tag
+pub fn has_if_match(headers: &HeaderMap) -> bool {
+    headers.contains_key(header::IF_MATCH)
+}
+
+fn listed_tags(headers: &HeaderMap, name: HeaderName) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_none_match_weakly() {
+        let etag = "W/\"users-7\"";
+        assert!(none_match(
+            &headers(header::IF_NONE_MATCH, "W/\"users-7\""),
+            etag
+        ));
+        assert!(none_match(
+            &headers(header::IF_NONE_MATCH, "\"users-6\", \"users-7\""),
+            etag
+        ));
+        assert!(none_match(&headers(header::IF_NONE_MATCH, "*"), etag));
+        assert!(!none_match(
+            &headers(header::IF_NONE_MATCH, "W/\"users-6\""),
+            etag
+        ));
+        assert!(!none_match(&HeaderMap::new(), etag));
+    }
+
+    #[test]
+    fn test_if_match_strongly() {
+        let etag = "\"user-1-100\"";
+        assert!(check_if_match(&HeaderMap::new(), etag).is_ok());
+        assert!(check_if_match(&headers(header::IF_MATCH, "\"user-1-100\""), etag).is_ok());
+        assert!(check_if_match(&headers(header::IF_MATCH, "\"x\", \"user-1-100\""), etag).is_ok());
+        assert!(check_if_match(&headers(header::IF_MATCH, "*"), etag).is_ok());
+        assert!(check_if_match(&headers(header::IF_MATCH, "\"user-1-99\""), etag).is_err());
+        assert!(check_if_match(&headers(header::IF_MATCH, "W/\"user-1-100\""), etag).is_err());
+    }
+
+    #[test]
+    fn test_modified_since_to_the_second() {
+        let at = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.678Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let last_modified = HeaderValue::from_str(&http_date(at)).unwrap();
+        assert_eq!(last_modified, "Tue, 02 Jan 2024 03:04:05 GMT");
+
+        let since = |value: &'static str| HeaderValue::from_static(value);
+        assert!(!modified_since(&last_modified, &last_modified));
+        assert!(!modified_since(&last_modified, &since("Tue, 02 Jan 2024 03:04:06 GMT")));
+        assert!(modified_since(&last_modified, &since("Tue, 02 Jan 2024 03:04:04 GMT")));
+        assert!(modified_since(&last_modified, &since("yesterday")));
+    }
+}
@@ -0,0 +1,458 @@
+//! Message queue consumer framework
+//!
+//! A [`Consumer`] pulls deliveries from a [`MessageSource`], decodes them into
+//! [`EventEnvelope`]s, validates them against the [`EventRegistry`] and runs
+//! the handlers registered for that schema. Deliveries are acknowledged only
+//! after every handler succeeded (at-least-once). Messages that cannot be
+//! decoded, or that keep failing after `max_attempts`, are moved to the
+//! [`ParkingLot`] instead of being redelivered forever.
+
+#[cfg(feature = "nats")]
+pub mod nats;
+
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+
+use crate::events::{DomainEvent, EventEnvelope, EventRegistry};
+
+/// Consumer error type
+#[derive(Debug)]
+pub enum ConsumerError {
+    /// Broker connection or acknowledgement failure
+    Broker(String),
+    /// Handler failed to process an event
+    Handler(String),
+}
+
+impl std::fmt::Display for ConsumerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConsumerError::Broker(msg) => write!(f, "Broker error: {}", msg),
+            ConsumerError::Handler(msg) => write!(f, "Handler error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConsumerError {}
+
+/// A single message delivered by the broker
+#[async_trait]
+pub trait Delivery: Send {
+    /// Topic (subject, routing key) the message was published to
+    fn topic(&self) -> &str;
+    /// Raw message body
+    fn payload(&self) -> &[u8];
+    /// How many times this message has been delivered, starting at 1
+    fn attempt(&self) -> u32;
+    /// Confirm processing; the broker will not redeliver
+    async fn ack(self: Box<Self>) -> Result<(), ConsumerError>;
+    /// Reject for now; the broker will redeliver
+    async fn nak(self: Box<Self>) -> Result<(), ConsumerError>;
+}
+
+/// Stream of deliveries from a broker subscription
+#[async_trait]
+pub trait MessageSource: Send {
+    /// Next delivery, or `None` when the subscription is closed
+    async fn next(&mut self) -> Option<Result<Box<dyn Delivery>, ConsumerError>>;
+}
+
+/// Destination for poison messages
+#[async_trait]
+pub trait ParkingLot: Send + Sync {
+    async fn park(&self, topic: &str, payload: &[u8], reason: &str) -> Result<(), ConsumerError>;
+}
+
+/// Parking lot that only logs; used when no broker-side parking is configured
+pub struct LogParkingLot;
+
+#[async_trait]
+impl ParkingLot for LogParkingLot {
+    async fn park(&self, topic: &str, payload: &[u8], reason: &str) -> Result<(), ConsumerError> {
+        error!(
+            "Parked message from {}: {} ({} bytes)",
+            topic,
+            reason,
+            payload.len()
+        );
+        Ok(())
+    }
+}
+
+type Handler =
+    Arc<dyn Fn(EventEnvelope) -> BoxFuture<'static, Result<(), ConsumerError>> + Send + Sync>;
+
+/// Outcome of processing one delivery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Acked,
+    Redelivered,
+    Parked,
+}
+
+/// Event consumer dispatching deliveries to typed handlers
+pub struct Consumer {
+    registry: Arc<EventRegistry>,
+    handlers: HashMap<String, Vec<Handler>>,
+    parking_lot: Arc<dyn ParkingLot>,
+    max_attempts: u32,
+}
+
+impl Consumer {
+    pub fn new(registry: Arc<EventRegistry>) -> Self {
+        Self {
+            registry,
+            handlers: HashMap::new(),
+            parking_lot: Arc::new(LogParkingLot),
+            max_attempts: 5,
+        }
+    }
+
+    pub fn with_parking_lot(mut self, parking_lot: Arc<dyn ParkingLot>) -> Self {
+        self.parking_lot = parking_lot;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Register a handler for one event schema
+    pub fn on<E, F, Fut>(mut self, handler: F) -> Self
+    where
+        E: DomainEvent,
+        F: Fn(E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), ConsumerError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let boxed: Handler = Arc::new(move |envelope: EventEnvelope| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let event = E::deserialize(envelope.payload)
+                    .map_err(|e| ConsumerError::Handler(e.to_string()))?;
+                handler(event).await
+            })
+        });
+        self.handlers.entry(E::schema_id()).or_default().push(boxed);
+        self
+    }
+
+    /// Process one delivery and acknowledge, redeliver or park it
+    pub async fn process(&self, delivery: Box<dyn Delivery>) -> Result<Outcome, ConsumerError> {
+        let envelope = match serde_json::from_slice::<EventEnvelope>(delivery.payload()) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                return self
+                    .park(delivery, &format!("undecodable envelope: {}", e))
+                    .await
+            }
+        };
+
+        if let Err(e) = self.registry.validate(&envelope) {
+            return self.park(delivery, &e.to_string()).await;
+        }
+
+        let schema = envelope.schema_id();
+        let Some(handlers) = self.handlers.get(&schema) else {
+            debug!("No handler for {}, acknowledging", schema);
+            delivery.ack().await?;
+            return Ok(Outcome::Acked);
+        };
+
+        for handler in handlers {
+            if let Err(e) = handler(envelope.clone()).await {
+                if delivery.attempt() >= self.max_attempts {
+                    let reason = format!("{} failed {} times: {}", schema, delivery.attempt(), e);
+                    return self.park(delivery, &reason).await;
+                }
+                warn!(
+                    "Handler for {} failed (attempt {}): {}",
+                    schema,
+                    delivery.attempt(),
+                    e
+                );
+                delivery.nak().await?;
+                return Ok(Outcome::Redelivered);
+            }
+        }
+
+        delivery.ack().await?;
+        Ok(Outcome::Acked)
+    }
+
+    /// Consume until the source closes or shutdown is signalled
+    ///
+    /// A delivery that is already being processed is finished before the
+    /// loop exits, so shutdown never abandons a half-handled message.
+    pub async fn run<S: MessageSource>(&self, mut source: S, mut shutdown: watch::Receiver<bool>) {
+        loop {
+            let next = tokio::select! {
+                _ = shutdown.changed() => break,
+                next = source.next() => next,
+            };
+
+            match next {
+                Some(Ok(delivery)) => {
+                    if let Err(e) = self.process(delivery).await {
+                        error!("Failed to settle delivery: {}", e);
+                    }
+                }
+                Some(Err(e)) => error!("Failed to receive delivery: {}", e),
+                None => break,
+            }
+        }
+        info!("Consumer stopped");
+    }
+
+    async fn park(
+        &self,
+        delivery: Box<dyn Delivery>,
+        reason: &str,
+    ) -> Result<Outcome, ConsumerError> {
+        self.parking_lot
+            .park(delivery.topic(), delivery.payload(), reason)
+            .await?;
+        delivery.ack().await?;
+        Ok(Outcome::Parked)
+    }
+}
+
+/// Consumer configuration read from environment variables
+#[derive(Debug, Clone)]
+pub struct ConsumerConfig {
+    pub url: String,
+    pub stream: String,
+    pub durable_name: String,
+    pub topics: Vec<String>,
+    pub parking_subject: String,
+    pub max_attempts: u32,
+}
+
+impl ConsumerConfig {
+    /// Read consumer settings; `None` when no topics are configured
+    ///
+    /// Variables: NATS_URL, CONSUMER_TOPICS (comma separated), CONSUMER_STREAM,
+    /// CONSUMER_DURABLE_NAME, CONSUMER_PARKING_SUBJECT, CONSUMER_MAX_ATTEMPTS
+    pub fn from_env() -> Option<Self> {
+        let topics: Vec<String> = env::var("CONSUMER_TOPICS")
+            .ok()?
+            .split(',')
+            .map(|topic| topic.trim().to_string())
+            .filter(|topic| !topic.is_empty())
+            .collect();
+        if topics.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            url: env::var("NATS_URL").unwrap_or_else(|_| "nats://localhost:4222".to_string()),
+            stream: env::var("CONSUMER_STREAM").unwrap_or_else(|_| "DOMAIN_EVENTS".to_string()),
+            durable_name: env::var("CONSUMER_DURABLE_NAME")
+                .unwrap_or_else(|_| "backend".to_string()),
+            topics,
+            parking_subject: env::var("CONSUMER_PARKING_SUBJECT")
+                .unwrap_or_else(|_| "parking_lot".to_string()),
+            max_attempts: env::var("CONSUMER_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(5),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::user::UserDeletedV1;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Settlements(Arc<Mutex<Vec<&'static str>>>);
+
+    struct TestDelivery {
+        payload: Vec<u8>,
+        attempt: u32,
+        settlements: Settlements,
+    }
+
+    #[async_trait]
+    impl Delivery for TestDelivery {
+        fn topic(&self) -> &str {
+            "events.user"
+        }
+        fn payload(&self) -> &[u8] {
+            &self.payload
+        }
+        fn attempt(&self) -> u32 {
+            self.attempt
+        }
+        async fn ack(self: Box<Self>) -> Result<(), ConsumerError> {
+            self.settlements.0.lock().unwrap().push("ack");
+            Ok(())
+        }
+        async fn nak(self: Box<Self>) -> Result<(), ConsumerError> {
+            self.settlements.0.lock().unwrap().push("nak");
+            Ok(())
+        }
+    }
+
+    struct VecSource(Vec<Box<dyn Delivery>>);
+
+    #[async_trait]
+    impl MessageSource for VecSource {
+        async fn next(&mut self) -> Option<Result<Box<dyn Delivery>, ConsumerError>> {
+            self.0.pop().map(Ok)
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingParkingLot(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl ParkingLot for RecordingParkingLot {
+        async fn park(
+            &self,
+            _topic: &str,
+            _payload: &[u8],
+            reason: &str,
+        ) -> Result<(), ConsumerError> {
+            self.0.lock().unwrap().push(reason.to_string());
+            Ok(())
+        }
+    }
+
+    fn deleted_payload(user_id: &str) -> Vec<u8> {
+        let registry = EventRegistry::with_domain_events();
+        let envelope = registry
+            .envelopes(&UserDeletedV1 {
+                user_id: user_id.to_string(),
+            })
+            .unwrap()
+            .remove(0);
+        serde_json::to_vec(&envelope).unwrap()
+    }
+
+    fn delivery(payload: Vec<u8>, attempt: u32, settlements: &Settlements) -> Box<dyn Delivery> {
+        Box::new(TestDelivery {
+            payload,
+            attempt,
+            settlements: settlements.clone(),
+        })
+    }
+
+    fn consumer(
+        calls: Arc<AtomicU32>,
+        fail: bool,
+        parking_lot: Arc<RecordingParkingLot>,
+    ) -> Consumer {
+        Consumer::new(Arc::new(EventRegistry::with_domain_events()))
+            .with_parking_lot(parking_lot)
+            .with_max_attempts(3)
+            .on(move |event: UserDeletedV1| {
+                let calls = calls.clone();
+                async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    assert_eq!(event.user_id, "7");
+                    if fail {
+                        Err(ConsumerError::Handler("boom".to_string()))
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+    }
+
+    #[tokio::test]
+    async fn test_successful_handler_acks() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let parking_lot = Arc::new(RecordingParkingLot::default());
+        let consumer = consumer(calls.clone(), false, parking_lot);
+        let settlements = Settlements::default();
+
+        let outcome = consumer
+            .process(delivery(deleted_payload("7"), 1, &settlements))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, Outcome::Acked);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(*settlements.0.lock().unwrap(), vec!["ack"]);
+    }
+
+    #[tokio::test]
+    async fn test_failing_handler_redelivers_then_parks() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let parking_lot = Arc::new(RecordingParkingLot::default());
+        let consumer = consumer(calls.clone(), true, parking_lot.clone());
+        let settlements = Settlements::default();
+
+        let first = consumer
+            .process(delivery(deleted_payload("7"), 1, &settlements))
+            .await
+            .unwrap();
+        let last = consumer
+            .process(delivery(deleted_payload("7"), 3, &settlements))
+            .await
+            .unwrap();
+
+        assert_eq!(first, Outcome::Redelivered);
+        assert_eq!(last, Outcome::Parked);
+        assert_eq!(*settlements.0.lock().unwrap(), vec!["nak", "ack"]);
+        assert_eq!(parking_lot.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_messages_are_parked_without_calling_handlers() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let parking_lot = Arc::new(RecordingParkingLot::default());
+        let consumer = consumer(calls.clone(), false, parking_lot.clone());
+        let settlements = Settlements::default();
+
+        let garbage = consumer
+            .process(delivery(b"not json".to_vec(), 1, &settlements))
+            .await
+            .unwrap();
+        let unknown_schema = serde_json::to_vec(&serde_json::json!({
+            "event_type": "user.deleted",
+            "version": 99,
+            "occurred_at": "2024-01-01T00:00:00Z",
+            "payload": {"user_id": "7"}
+        }))
+        .unwrap();
+        let unknown = consumer
+            .process(delivery(unknown_schema, 1, &settlements))
+            .await
+            .unwrap();
+
+        assert_eq!(garbage, Outcome::Parked);
+        assert_eq!(unknown, Outcome::Parked);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(parking_lot.0.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_run_drains_source_and_stops() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let parking_lot = Arc::new(RecordingParkingLot::default());
+        let consumer = consumer(calls.clone(), false, parking_lot);
+        let settlements = Settlements::default();
+        let source = VecSource(vec![
+            delivery(deleted_payload("7"), 1, &settlements),
+            delivery(deleted_payload("7"), 1, &settlements),
+        ]);
+        let (_tx, rx) = watch::channel(false);
+
+        consumer.run(source, rx).await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
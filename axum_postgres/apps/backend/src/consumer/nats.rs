@@ -0,0 +1,164 @@
+//! NATS JetStream adapter for the consumer framework
+
+use async_nats::jetstream::{self, consumer::pull, AckKind};
+use async_trait::async_trait;
+use futures::StreamExt;
+
+use super::{ConsumerConfig, ConsumerError, Delivery, MessageSource, ParkingLot};
+use crate::health::HealthCheck;
+
+/// Durable pull subscription on a JetStream stream
+pub struct NatsSource {
+    messages: pull::Stream,
+}
+
+/// Parking lot that republishes poison messages to a JetStream subject
+pub struct NatsParkingLot {
+    context: jetstream::Context,
+    subject: String,
+}
+
+/// Optional readiness check on the broker connection
+///
+/// The consumer is not needed to serve HTTP traffic, so a lost connection
+/// degrades the service rather than taking it out of rotation.
+pub struct NatsCheck {
+    client: async_nats::Client,
+}
+
+struct NatsDelivery {
+    topic: String,
+    message: jetstream::Message,
+}
+
+/// Connect to NATS and open the configured durable consumer
+pub async fn connect(
+    config: &ConsumerConfig,
+) -> Result<(NatsSource, NatsParkingLot, NatsCheck), ConsumerError> {
+    let client = async_nats::connect(&config.url)
+        .await
+        .map_err(broker_error)?;
+    let check = NatsCheck {
+        client: client.clone(),
+    };
+    let context = jetstream::new(client);
+
+    let mut subjects = config.topics.clone();
+    subjects.push(format!("{}.>", config.parking_subject));
+    let stream = context
+        .get_or_create_stream(jetstream::stream::Config {
+            name: config.stream.clone(),
+            subjects,
+            ..Default::default()
+        })
+        .await
+        .map_err(broker_error)?;
+
+    let consumer = stream
+        .get_or_create_consumer(
+            &config.durable_name,
+            pull::Config {
+                durable_name: Some(config.durable_name.clone()),
+                filter_subjects: config.topics.clone(),
+                ack_policy: jetstream::consumer::AckPolicy::Explicit,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(broker_error)?;
+    let messages = consumer.messages().await.map_err(broker_error)?;
+
+    Ok((
+        NatsSource { messages },
+        NatsParkingLot {
+            context,
+            subject: config.parking_subject.clone(),
+        },
+        check,
+    ))
+}
+
+fn broker_error<E: std::fmt::Display>(e: E) -> ConsumerError {
+    ConsumerError::Broker(e.to_string())
+}
+
+#[async_trait]
+impl HealthCheck for NatsCheck {
+    fn name(&self) -> &str {
+        "broker"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        match self.client.connection_state() {
+            async_nats::connection::State::Connected => Ok(()),
+            state => Err(format!("NATS connection is {}", state)),
+        }
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+}
+
+#[async_trait]
+impl MessageSource for NatsSource {
+    async fn next(&mut self) -> Option<Result<Box<dyn Delivery>, ConsumerError>> {
+        let message = self.messages.next().await?;
+        Some(
+            message
+                .map(|message| {
+                    Box::new(NatsDelivery {
+                        topic: message.subject.to_string(),
+                        message,
+                    }) as Box<dyn Delivery>
+                })
+                .map_err(broker_error),
+        )
+    }
+}
+
+#[async_trait]
+impl Delivery for NatsDelivery {
+    fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    fn payload(&self) -> &[u8] {
+        &self.message.payload
+    }
+
+    fn attempt(&self) -> u32 {
+        self.message
+            .info()
+            .map(|info| info.delivered.max(1) as u32)
+            .unwrap_or(1)
+    }
+
+    async fn ack(self: Box<Self>) -> Result<(), ConsumerError> {
+        self.message.ack().await.map_err(broker_error)
+    }
+
+    async fn nak(self: Box<Self>) -> Result<(), ConsumerError> {
+        self.message
+            .ack_with(AckKind::Nak(None))
+            .await
+            .map_err(broker_error)
+    }
+}
+
+#[async_trait]
+impl ParkingLot for NatsParkingLot {
+    async fn park(&self, topic: &str, payload: &[u8], reason: &str) -> Result<(), ConsumerError> {
+        tracing::error!("Parking message from {}: {}", topic, reason);
+        self.context
+            .publish(
+                format!("{}.{}", self.subject, topic),
+                payload.to_vec().into(),
+            )
+            .await
+            .map_err(broker_error)?
+            .await
+            .map_err(broker_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Sampling CPU profiler (`cpu-profiling` feature)
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use pprof::protos::Message;
+
+/// Output format of a CPU profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    /// Interactive SVG flamegraph
+    Flamegraph,
+    /// pprof protobuf, for `go tool pprof`
+    Pprof,
+}
+
+impl ProfileFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ProfileFormat::Flamegraph => "image/svg+xml",
+            ProfileFormat::Pprof => "application/octet-stream",
+        }
+    }
+}
+
+/// Only one profiler can hook SIGPROF at a time
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Reason a profile could not be taken
+#[derive(Debug)]
+pub enum ProfileError {
+    /// Another profile is in progress
+    AlreadyRunning,
+    /// The profiler failed to start or to build the report
+    Failed(String),
+}
+
+impl std::fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileError::AlreadyRunning => write!(f, "A CPU profile is already running"),
+            ProfileError::Failed(msg) => write!(f, "CPU profiling failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+/// Sample the whole process for `duration`
+///
+/// Blocks the calling thread; run it on a blocking task.
+pub fn capture(
+    duration: Duration,
+    frequency: i32,
+    format: ProfileFormat,
+) -> Result<Vec<u8>, ProfileError> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return Err(ProfileError::AlreadyRunning);
+    }
+    let result = sample(duration, frequency, format);
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+fn sample(
+    duration: Duration,
+    frequency: i32,
+    format: ProfileFormat,
+) -> Result<Vec<u8>, ProfileError> {
+    let failed = |e: pprof::Error| ProfileError::Failed(e.to_string());
+
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .map_err(failed)?;
+    std::thread::sleep(duration);
+    let report = guard.report().build().map_err(failed)?;
+
+    let mut body = Vec::new();
+    match format {
+        ProfileFormat::Flamegraph => report.flamegraph(&mut body).map_err(failed)?,
+        ProfileFormat::Pprof => report
+            .pprof()
+            .map_err(failed)?
+            .encode(&mut body)
+            .map_err(|e| ProfileError::Failed(e.to_string()))?,
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_capture_is_rejected() {
+        let background =
+            std::thread::spawn(|| capture(Duration::from_millis(300), 99, ProfileFormat::Pprof));
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(matches!(
+            capture(Duration::from_millis(10), 99, ProfileFormat::Pprof),
+            Err(ProfileError::AlreadyRunning)
+        ));
+        assert!(!background.join().unwrap().unwrap().is_empty());
+    }
+}
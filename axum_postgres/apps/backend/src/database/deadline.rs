@@ -0,0 +1,46 @@
+//! Deadlines for the database work of a request
+//!
+//! `middleware::deadline` runs each request inside [`scope`]. Repository
+//! operations then get only the time left until the deadline, waiting for
+//! a pooled connection included: when the pool is saturated, a request
+//! gives up with 503 once its budget is spent instead of queueing for
+//! DB_ACQUIRE_TIMEOUT_MS while the client has long stopped waiting. Work
+//! outside a request, such as jobs, streamed exports and imports, has no
+//! deadline.
+
+use std::future::Future;
+
+use tokio::time::Instant;
+
+/// Repository operations cut short by their request's deadline, by
+/// operation
+pub const DB_DEADLINE_EXCEEDED_TOTAL: &str = "db_deadline_exceeded_total";
+
+tokio::task_local! {
+    /// When the database work of the current request must be done
+    static DEADLINE: Instant;
+}
+
+/// Run `future` with `deadline` for its database work
+pub async fn scope<F: Future>(deadline: Instant, future: F) -> F::Output {
+    DEADLINE.scope(deadline, future).await
+}
+
+/// Deadline of the current task, if it has one
+pub fn current() -> Option<Instant> {
+    DEADLINE.try_with(|deadline| *deadline).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_deadline_is_task_local() {
+        assert_eq!(current(), None);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert_eq!(scope(deadline, async { current() }).await, Some(deadline));
+        assert_eq!(current(), None);
+    }
+}
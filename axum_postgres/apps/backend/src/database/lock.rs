@@ -0,0 +1,85 @@
+//! Mutual exclusion across replicas with PostgreSQL advisory locks
+//!
+//! Work that must not run on several replicas at once, such as migrations
+//! and scheduled jobs, runs inside [`with_advisory_lock`] or
+//! [`try_with_advisory_lock`] under a lock name. The lock is transaction
+//! scoped (`pg_advisory_xact_lock`): a pooled connection holds it in an
+//! open transaction while the work runs, and ending the transaction
+//! releases it, so a replica that fails, or drops the future, half way
+//! never leaves it held.
+//!
+//! The work itself runs on other connections of the pool, so the pool
+//! needs room for at least one more.
+
+use std::future::Future;
+
+use ring::digest;
+use sqlx::PgPool;
+
+/// Lock taken by [`super::run_migrations`]
+pub const MIGRATIONS: &str = "migrations";
+
+/// 64-bit advisory lock key for `name`
+///
+/// The first eight bytes of its SHA-256, so every replica and every build
+/// derives the same key.
+pub fn lock_key(name: &str) -> i64 {
+    let hash = digest::digest(&digest::SHA256, name.as_bytes());
+    i64::from_be_bytes(hash.as_ref()[..8].try_into().unwrap())
+}
+
+/// Run `work` holding the lock `name`, waiting for other holders first
+pub async fn with_advisory_lock<F: Future>(
+    pool: &PgPool,
+    name: &str,
+    work: F,
+) -> Result<F::Output, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(lock_key(name))
+        .execute(&mut *tx)
+        .await?;
+    tracing::debug!("Took advisory lock {}", name);
+
+    let output = work.await;
+    tx.commit().await?;
+    Ok(output)
+}
+
+/// Run `work` holding the lock `name`; None without running it when
+/// another replica holds the lock
+pub async fn try_with_advisory_lock<F: Future>(
+    pool: &PgPool,
+    name: &str,
+    work: F,
+) -> Result<Option<F::Output>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_xact_lock($1)")
+        .bind(lock_key(name))
+        .fetch_one(&mut *tx)
+        .await?;
+    if !locked {
+        tracing::debug!("Advisory lock {} is held elsewhere", name);
+        return Ok(None);
+    }
+
+    let output = work.await;
+    tx.commit().await?;
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_keys_are_stable_per_name() {
+        assert_eq!(lock_key(MIGRATIONS), lock_key("migrations"));
+        assert_ne!(lock_key("jobs.purge_expired"), lock_key(MIGRATIONS));
+        // Pinned so that replicas of different builds still exclude each other
+        assert_eq!(
+            lock_key(""),
+            i64::from_be_bytes([0xe3, 0xb0, 0xc4, 0x42, 0x98, 0xfc, 0x1c, 0x14])
+        );
+    }
+}
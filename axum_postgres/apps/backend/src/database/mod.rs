@@ -0,0 +1,436 @@
+pub mod deadline;
+pub mod lock;
+
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    Connection, PgPool,
+};
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::tenant::{self, TenantIsolation};
+
+/// Primary pool for writes and replica pool for reads
+///
+/// Without a replica both are the same pool, so a repository built from a
+/// single `PgPool` sends everything to the primary. Replicas lag behind the
+/// primary, so only handlers that do not read their own writes take these.
+#[derive(Debug, Clone)]
+pub struct DbPools {
+    pub primary: PgPool,
+    pub replica: PgPool,
+}
+
+impl DbPools {
+    /// Pools for `primary`, reading from `replica` when there is one
+    pub fn new(primary: PgPool, replica: Option<PgPool>) -> Self {
+        let replica = replica.unwrap_or_else(|| primary.clone());
+        Self { primary, replica }
+    }
+}
+
+impl From<PgPool> for DbPools {
+    fn from(pool: PgPool) -> Self {
+        Self::new(pool, None)
+    }
+}
+
+/// Longest application_name PostgreSQL keeps; longer names are truncated
+const MAX_APPLICATION_NAME_LEN: usize = 63;
+
+/// Connection pool settings
+///
+/// Defaults match sqlx's, except for `max_connections`.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// PostgreSQL connection string
+    pub url: String,
+    pub max_connections: u32,
+    /// Connections kept open even when idle
+    pub min_connections: u32,
+    /// Longest a request waits for a free connection
+    pub acquire_timeout: Duration,
+    /// Longest the database work of a request may take, waiting for
+    /// connections included; see [`deadline`]
+    pub request_deadline: Option<Duration>,
+    /// Connections opened by [`DatabaseConfig::warm_up`]
+    pub warmup_connections: u32,
+    /// Idle connections above `min_connections` are closed after this
+    pub idle_timeout: Option<Duration>,
+    /// Connections are replaced after this
+    pub max_lifetime: Option<Duration>,
+    /// Statements running longer are cancelled by the server
+    pub statement_timeout: Option<Duration>,
+    /// Shown in `pg_stat_activity`; the connection string's when `None`
+    pub application_name: Option<String>,
+    /// With row-level security every connection is pointed at the current
+    /// tenant before it is used
+    pub tenant_isolation: TenantIsolation,
+}
+
+impl DatabaseConfig {
+    /// Defaults for `url`
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            request_deadline: None,
+            warmup_connections: 0,
+            idle_timeout: Some(Duration::from_secs(600)),
+            max_lifetime: Some(Duration::from_secs(1800)),
+            statement_timeout: None,
+            application_name: None,
+            tenant_isolation: TenantIsolation::Query,
+        }
+    }
+
+    /// Settings from DB_MAX_CONNECTIONS, DB_MIN_CONNECTIONS,
+    /// DB_ACQUIRE_TIMEOUT_MS, DB_REQUEST_DEADLINE_MS, DB_WARMUP_CONNECTIONS,
+    /// DB_IDLE_TIMEOUT_MS, DB_MAX_LIFETIME_MS, DB_STATEMENT_TIMEOUT_MS,
+    /// DB_APPLICATION_NAME and TENANT_ISOLATION
+    ///
+    /// A timeout of 0 turns the request deadline, the idle timeout, the
+    /// lifetime limit or the statement timeout off. Invalid values are errors, so a typo fails
+    /// startup instead of running with a default.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(get_database_url(), |name| env::var(name).ok())
+    }
+
+    /// Settings for DATABASE_REPLICA_URL, `None` when it is unset
+    ///
+    /// The replica pool takes the same pool settings as the primary.
+    pub fn replica_from_env() -> Result<Option<Self>, String> {
+        match env::var("DATABASE_REPLICA_URL") {
+            Ok(url) if !url.is_empty() => Ok(Some(Self {
+                url,
+                ..Self::from_env()?
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn from_lookup(url: String, var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let number = |name: &str| -> Result<Option<u64>, String> {
+            var(name)
+                .map(|value| {
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("{} is not a number: {}", name, value))
+                })
+                .transpose()
+        };
+        let count = |name: &str| -> Result<Option<u32>, String> {
+            number(name)?
+                .map(|value| {
+                    u32::try_from(value).map_err(|_| format!("{} is too large: {}", name, value))
+                })
+                .transpose()
+        };
+        // 0 means no limit
+        let limit = |name: &str, default: Option<Duration>| -> Result<Option<Duration>, String> {
+            Ok(match number(name)? {
+                Some(0) => None,
+                Some(millis) => Some(Duration::from_millis(millis)),
+                None => default,
+            })
+        };
+
+        let defaults = Self::new(url);
+        let config = Self {
+            max_connections: count("DB_MAX_CONNECTIONS")?.unwrap_or(defaults.max_connections),
+            min_connections: count("DB_MIN_CONNECTIONS")?.unwrap_or(defaults.min_connections),
+            acquire_timeout: number("DB_ACQUIRE_TIMEOUT_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.acquire_timeout),
+            request_deadline: limit("DB_REQUEST_DEADLINE_MS", defaults.request_deadline)?,
+            warmup_connections: count("DB_WARMUP_CONNECTIONS")?
+                .unwrap_or(defaults.warmup_connections),
+            idle_timeout: limit("DB_IDLE_TIMEOUT_MS", defaults.idle_timeout)?,
+            max_lifetime: limit("DB_MAX_LIFETIME_MS", defaults.max_lifetime)?,
+            statement_timeout: limit("DB_STATEMENT_TIMEOUT_MS", defaults.statement_timeout)?,
+            application_name: var("DB_APPLICATION_NAME").filter(|name| !name.is_empty()),
+            tenant_isolation: var("TENANT_ISOLATION")
+                .map(|value| value.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            ..defaults
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_connections == 0 {
+            return Err("DB_MAX_CONNECTIONS must be at least 1".to_string());
+        }
+        if self.min_connections > self.max_connections {
+            return Err(format!(
+                "DB_MIN_CONNECTIONS ({}) exceeds DB_MAX_CONNECTIONS ({})",
+                self.min_connections, self.max_connections
+            ));
+        }
+        if self.warmup_connections > self.max_connections {
+            return Err(format!(
+                "DB_WARMUP_CONNECTIONS ({}) exceeds DB_MAX_CONNECTIONS ({})",
+                self.warmup_connections, self.max_connections
+            ));
+        }
+        if self.acquire_timeout.is_zero() {
+            return Err("DB_ACQUIRE_TIMEOUT_MS must be positive".to_string());
+        }
+        if let Some(name) = &self.application_name {
+            if name.len() > MAX_APPLICATION_NAME_LEN || !name.is_ascii() {
+                return Err(format!(
+                    "DB_APPLICATION_NAME must be at most {} ASCII characters: {}",
+                    MAX_APPLICATION_NAME_LEN, name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the pool, with `min_connections` connected up front
+    ///
+    /// In RLS mode new connections and connections taken from the idle
+    /// pool are first pointed at the tenant of the task acquiring them,
+    /// which costs a round trip per acquire.
+    pub async fn connect(&self) -> Result<PgPool, sqlx::Error> {
+        let mut options = PgConnectOptions::from_str(&self.url)?;
+        if let Some(name) = &self.application_name {
+            options = options.application_name(name);
+        }
+        if let Some(timeout) = self.statement_timeout {
+            options = options.options([("statement_timeout", timeout.as_millis().to_string())]);
+        }
+
+        let mut pool = PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+            .max_lifetime(self.max_lifetime);
+        if self.tenant_isolation == TenantIsolation::RowLevelSecurity {
+            pool = pool
+                .after_connect(|conn, _| Box::pin(tenant::set_connection_tenant(conn)))
+                .before_acquire(|conn, _| {
+                    Box::pin(async move {
+                        tenant::set_connection_tenant(conn).await?;
+                        Ok(true)
+                    })
+                });
+        }
+        pool.connect_with(options).await
+    }
+
+    /// Open `warmup_connections` connections of `pool` at once and check
+    /// each with a round trip, so the first requests find them ready
+    ///
+    /// [`DatabaseConfig::connect`] only opens `min_connections`, one after
+    /// the other. Warmed connections above `min_connections` close after
+    /// `idle_timeout` unless requests keep them busy.
+    pub async fn warm_up(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let connections =
+            futures::future::try_join_all((0..self.warmup_connections).map(|_| async {
+                let mut connection = pool.acquire().await?;
+                connection.ping().await?;
+                Ok::<_, sqlx::Error>(connection)
+            }))
+            .await?;
+        // Back to the idle pool all together, so each was a separate connection
+        drop(connections);
+        Ok(())
+    }
+}
+
+/// Create PostgreSQL connection pool
+/// 
+/// # Arguments
+/// * `database_url` - PostgreSQL connection string
+/// 
+/// # Returns
+/// * `Result<PgPool, sqlx::Error>` - Connection pool or error
+pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
+    DatabaseConfig::new(database_url.to_string()).connect().await
+}
+
+/// Create database pool from environment variables
+/// 
+/// Reads DATABASE_URL from environment or constructs from individual variables,
+/// and the pool settings of [`DatabaseConfig::from_env`]
+pub async fn create_pool_from_env() -> Result<PgPool, sqlx::Error> {
+    let config = DatabaseConfig::from_env().map_err(|e| sqlx::Error::Configuration(e.into()))?;
+    config.connect().await
+}
+
+/// Get database URL from environment variables
+/// 
+/// Priority:
+/// 1. DATABASE_URL (full connection string)
+/// 2. Individual variables (DB_HOST, DB_PORT, etc.)
+pub fn get_database_url() -> String {
+    if let Ok(database_url) = env::var("DATABASE_URL") {
+        return database_url;
+    }
+
+    // Construct from individual variables
+    let host = env::var("DB_HOST").unwrap_or_else(|_| "localhost".to_string());
+    let port = env::var("DB_PORT").unwrap_or_else(|_| "5435".to_string());
+    let user = env::var("DB_USER").unwrap_or_else(|_| "postgres".to_string());
+    let password = env::var("DB_PASSWORD").unwrap_or_else(|_| "password".to_string());
+    let database = env::var("DB_NAME").unwrap_or_else(|_| "dev".to_string());
+
+    format!("postgresql://{}:{}@{}:{}/{}", user, password, host, port, database)
+}
+
+/// Test database connection
+/// 
+/// Performs a simple query to verify database connectivity
+pub async fn test_connection(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+/// Migrations applied to the database compared with those in this binary
+#[derive(Debug, Clone)]
+pub struct MigrationState {
+    /// Newest migration applied successfully, `None` when none has been
+    pub applied: Option<i64>,
+    /// Newest migration embedded in this binary
+    pub latest: i64,
+    /// Embedded migrations not applied yet, oldest first
+    pub pending: Vec<i64>,
+}
+
+/// Read `_sqlx_migrations` and compare it with the embedded migrations
+///
+/// A database never migrated has no `_sqlx_migrations` table yet, and
+/// every migration is pending.
+pub async fn migration_state(pool: &PgPool) -> Result<MigrationState, sqlx::Error> {
+    let applied: Vec<i64> =
+        match sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(applied) => applied,
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Vec::new(),
+            Err(e) => return Err(e),
+        };
+    let migrator = sqlx::migrate!("./migrations");
+    let pending = migrator
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect();
+    Ok(MigrationState {
+        applied: applied.iter().max().copied(),
+        latest: migrator.iter().map(|migration| migration.version).max().unwrap_or(0),
+        pending,
+    })
+}
+
+/// Apply the pending migrations, one replica at a time
+///
+/// Replicas starting together queue on the [`lock::MIGRATIONS`] lock; the
+/// first applies the migrations and the others then find none pending.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
+    let mut migrator = sqlx::migrate!("./migrations");
+    // The advisory lock already keeps replicas apart
+    migrator.set_locking(false);
+    lock::with_advisory_lock(pool, lock::MIGRATIONS, migrator.run(pool)).await?
+}
+
+/// Mask password in database URL for safe logging
+pub fn mask_password(url: &str) -> String {
+    if let Some(start) = url.find("://") {
+        if let Some(at_pos) = url.find('@') {
+            if let Some(colon_pos) = url[start + 3..at_pos].find(':') {
+                let mut masked = url.to_string();
+                let password_start = start + 3 + colon_pos + 1;
+                let password_end = at_pos;
+                masked.replace_range(password_start..password_end, "****");
+                return masked;
+            }
+        }
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<DatabaseConfig, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        DatabaseConfig::from_lookup("postgres://localhost/dev".to_string(), |name| {
+            vars.get(name).map(|value| value.to_string())
+        })
+    }
+
+    #[test]
+    fn test_pool_settings_from_env() {
+        let defaults = config(&[]).unwrap();
+        assert_eq!(defaults.max_connections, 10);
+        assert_eq!(defaults.idle_timeout, Some(Duration::from_secs(600)));
+        assert_eq!(defaults.statement_timeout, None);
+        assert_eq!(defaults.request_deadline, None);
+        assert_eq!(defaults.warmup_connections, 0);
+        assert_eq!(defaults.tenant_isolation, TenantIsolation::Query);
+
+        let config = config(&[
+            ("DB_MAX_CONNECTIONS", "20"),
+            ("DB_MIN_CONNECTIONS", "2"),
+            ("DB_ACQUIRE_TIMEOUT_MS", "1500"),
+            ("DB_REQUEST_DEADLINE_MS", "2000"),
+            ("DB_WARMUP_CONNECTIONS", "4"),
+            ("DB_IDLE_TIMEOUT_MS", "0"),
+            ("DB_STATEMENT_TIMEOUT_MS", "5000"),
+            ("DB_APPLICATION_NAME", "backend-api"),
+            ("TENANT_ISOLATION", "rls"),
+        ])
+        .unwrap();
+        assert_eq!(config.max_connections, 20);
+        assert_eq!(config.min_connections, 2);
+        assert_eq!(config.acquire_timeout, Duration::from_millis(1500));
+        assert_eq!(config.request_deadline, Some(Duration::from_secs(2)));
+        assert_eq!(config.warmup_connections, 4);
+        assert_eq!(config.idle_timeout, None);
+        assert_eq!(config.max_lifetime, Some(Duration::from_secs(1800)));
+        assert_eq!(config.statement_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.application_name.as_deref(), Some("backend-api"));
+        assert_eq!(config.tenant_isolation, TenantIsolation::RowLevelSecurity);
+    }
+
+    #[test]
+    fn test_invalid_pool_settings_are_rejected() {
+        let error = |vars: &[(&str, &str)]| config(vars).unwrap_err();
+        assert_eq!(
+            error(&[("DB_MAX_CONNECTIONS", "ten")]),
+            "DB_MAX_CONNECTIONS is not a number: ten"
+        );
+        assert_eq!(
+            error(&[("DB_MAX_CONNECTIONS", "0")]),
+            "DB_MAX_CONNECTIONS must be at least 1"
+        );
+        assert_eq!(
+            error(&[("DB_MAX_CONNECTIONS", "5"), ("DB_MIN_CONNECTIONS", "6")]),
+            "DB_MIN_CONNECTIONS (6) exceeds DB_MAX_CONNECTIONS (5)"
+        );
+        assert_eq!(
+            error(&[("DB_WARMUP_CONNECTIONS", "11")]),
+            "DB_WARMUP_CONNECTIONS (11) exceeds DB_MAX_CONNECTIONS (10)"
+        );
+        assert!(error(&[("DB_ACQUIRE_TIMEOUT_MS", "0")]).contains("positive"));
+        assert!(error(&[("DB_APPLICATION_NAME", &"x".repeat(64))]).contains("at most 63"));
+        assert_eq!(
+            error(&[("TENANT_ISOLATION", "strict")]),
+            "TENANT_ISOLATION must be query or rls: strict"
+        );
+    }
+}
@@ -1,14 +1,156 @@
-use utoipa::OpenApi;
-use crate::models::user::{UserResponse, CreateUserRequest, UpdateUserRequest, ErrorResponse};
+use crate::casing;
+use crate::error::{problem_type, PROBLEM_JSON};
+use crate::events::user::{UserCreatedV1, UserDeletedV1, UserUpdatedV1};
+use crate::handlers::admin::ReadOnlyStatus;
+use crate::handlers::ws::{EventType, UserEvent};
+use crate::auth::oauth::Provider;
+use crate::auth::role::Role;
+use crate::models::admin::{AdminUserPage, AdminUserResponse, ImpersonationResponse, UserRoles};
+use crate::models::envelope::ResponseMeta;
+use crate::models::api_key::{
+    AdminApiKeyResponse, ApiKeyResponse, CreateApiKeyRequest, CreatedApiKey, RotateApiKeyRequest,
+};
+use crate::audit::AuditAction;
+use crate::models::audit::{AuditEntryResponse, AuditLogPage};
+use crate::models::role::RoleResponse;
+use crate::models::tenant::{CreateTenantRequest, TenantResponse, UpdateTenantRequest};
+use crate::models::auth::{
+    AuthResponse, ChangePasswordRequest, ForgotPasswordRequest, LoginRequest, RefreshRequest,
+    RegisterRequest, ResendVerificationRequest, ResetPasswordRequest, TokenResponse,
+    VerifyEmailRequest,
+};
+use crate::middleware::envelope::ResponseEnvelope;
+use crate::routes::RouteInfo;
+use crate::slo::{SloObjective, SloReport, SloStatus};
+use crate::status::{BuildInfo, MigrationStatus, PoolStatus, ServiceStatus};
+use crate::models::scim::{
+    ScimEmail, ScimErrorResponse, ScimListResponse, ScimMeta, ScimName, ScimPatchOperation,
+    ScimPatchRequest, ScimUser,
+};
+use crate::models::links::{CollectionLinks, Link, UserLinks};
+use crate::models::user::{
+    CreateUserRequest, EmailAvailability, ErrorResponse, FieldError, ImportStatus, ImportSummary, RejectedRow,
+    ReplaceUserRequest, UpdateUserRequest, UserCollection, UserCount, UserList, UserPage,
+    UserResponse,
+};
+use crate::models::user_history::{FieldChange, UserHistoryEntry};
+use axum::http::StatusCode;
+use serde_json::{json, Value};
+use utoipa::openapi::example::{Example, ExampleBuilder};
+use utoipa::openapi::path::{Operation, PathItemType};
+use utoipa::openapi::schema::{ObjectBuilder, Ref, Schema, SchemaType};
+use utoipa::openapi::security::{
+    ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme,
+};
+use utoipa::openapi::RefOr;
+use utoipa::{Modify, OpenApi};
 
 /// Simplified OpenAPI documentation configuration
 #[derive(OpenApi)]
 #[openapi(
+    paths(
+        crate::handlers::users::create_user,
+        crate::handlers::users::import_users,
+        crate::handlers::users::get_import_status,
+        crate::handlers::users::export_users,
+        crate::handlers::users::get_me,
+        crate::handlers::users::update_me,
+        crate::handlers::users::delete_me,
+        crate::handlers::users::get_user_by_id,
+        crate::handlers::users::user_exists,
+        crate::handlers::users::list_users,
+        crate::handlers::users::update_user,
+        crate::handlers::users::patch_user,
+        crate::handlers::users::delete_user,
+        crate::handlers::users::restore_user,
+        crate::handlers::users::activate_user,
+        crate::handlers::users::deactivate_user,
+        crate::handlers::users::get_user_history,
+        crate::handlers::users::search_users,
+        crate::handlers::users::count_users,
+        crate::handlers::users::check_email,
+        crate::handlers::auth::register,
+        crate::handlers::auth::login,
+        crate::handlers::auth::refresh,
+        crate::handlers::auth::logout,
+        crate::handlers::auth::logout_all,
+        crate::handlers::auth::end_session,
+        crate::handlers::auth::verify_email,
+        crate::handlers::auth::resend_verification,
+        crate::handlers::auth::forgot_password,
+        crate::handlers::auth::reset_password,
+        crate::handlers::auth::change_password,
+        crate::handlers::auth::oauth_start,
+        crate::handlers::auth::oauth_callback,
+        crate::handlers::api_keys::create_api_key,
+        crate::handlers::api_keys::list_api_keys,
+        crate::handlers::api_keys::revoke_api_key,
+        crate::handlers::roles::list_roles,
+        crate::handlers::roles::list_role_users,
+        crate::handlers::roles::grant_role,
+        crate::handlers::roles::revoke_role,
+        crate::handlers::admin_users::list_admin_users,
+        crate::handlers::admin_users::force_password_reset,
+        crate::handlers::admin_users::impersonate_user,
+        crate::handlers::admin_users::get_user_roles,
+        crate::handlers::admin_users::set_user_roles,
+        crate::handlers::admin_api_keys::list_admin_api_keys,
+        crate::handlers::admin_api_keys::rotate_api_key,
+        crate::handlers::admin_api_keys::expire_api_key,
+        crate::handlers::audit::list_audit_log,
+        crate::handlers::status::get_status,
+        crate::handlers::scim::list_users,
+        crate::handlers::scim::create_user,
+        crate::handlers::scim::get_user,
+        crate::handlers::scim::replace_user,
+        crate::handlers::scim::patch_user,
+        crate::handlers::scim::delete_user,
+        crate::handlers::admin::get_read_only,
+        crate::handlers::admin::set_read_only,
+        crate::handlers::admin::get_slo,
+        crate::handlers::admin::list_routes,
+        crate::handlers::tenants::list_tenants,
+        crate::handlers::tenants::create_tenant,
+        crate::handlers::tenants::get_tenant,
+        crate::handlers::tenants::update_tenant,
+        crate::handlers::ws::subscribe
+    ),
     components(
-        schemas(UserResponse, CreateUserRequest, UpdateUserRequest, ErrorResponse)
+        schemas(UserResponse, CreateUserRequest, ReplaceUserRequest, UpdateUserRequest, ErrorResponse, FieldError),
+        schemas(ResponseMeta),
+        schemas(UserList, UserCollection, UserPage, UserCount, EmailAvailability, ImportSummary, ImportStatus, RejectedRow, Link, UserLinks, CollectionLinks),
+        schemas(UserHistoryEntry, FieldChange),
+        schemas(RegisterRequest, LoginRequest, RefreshRequest, ChangePasswordRequest, AuthResponse, TokenResponse),
+        schemas(VerifyEmailRequest, ResendVerificationRequest),
+        schemas(ForgotPasswordRequest, ResetPasswordRequest),
+        schemas(CreateApiKeyRequest, ApiKeyResponse, CreatedApiKey),
+        schemas(AdminApiKeyResponse, RotateApiKeyRequest),
+        schemas(Role, RoleResponse),
+        schemas(AdminUserResponse, AdminUserPage, UserRoles, ImpersonationResponse),
+        schemas(AuditAction, AuditEntryResponse, AuditLogPage),
+        schemas(ServiceStatus, BuildInfo, PoolStatus, MigrationStatus),
+        schemas(Provider),
+        schemas(ScimUser, ScimName, ScimEmail, ScimMeta, ScimListResponse),
+        schemas(ScimPatchRequest, ScimPatchOperation, ScimErrorResponse),
+        schemas(ReadOnlyStatus, SloReport, SloStatus, SloObjective, RouteInfo),
+        schemas(TenantResponse, CreateTenantRequest, UpdateTenantRequest),
+        schemas(UserCreatedV1, UserUpdatedV1, UserDeletedV1),
+        schemas(EventType, UserEvent)
     ),
+    modifiers(&OperationExamples, &ProblemDetails, &SecuritySchemes),
     tags(
-        (name = "users", description = "User management operations")
+        (name = "users", description = "User management operations. Send `Accept: application/vnd.api+json` for JSON:API documents"),
+        (name = "auth", description = "Sign-in with email and password, Google or GitHub, with JWTs or, when AUTH_MODE=cookie, a session cookie; write endpoints take the access token as a bearer token"),
+        (name = "api-keys", description = "API keys for service-to-service clients, sent as `X-API-Key` instead of an access token"),
+        (name = "roles", description = "Roles granted to users; deleting and importing users takes the admin role"),
+        (name = "admin-users", description = "User management for admins: soft-deleted users, forced password resets, impersonation and roles"),
+        (name = "admin-api-keys", description = "API keys of every user, for admins: when they were last used, rotation with a grace period, and forced expiry"),
+        (name = "audit", description = "Audit log of the changes made through the API, for admins"),
+        (name = "status", description = "Build, uptime, database pool, schema version and switches of the running service, for admins"),
+        (name = "scim", description = "SCIM 2.0 user provisioning for identity providers"),
+        (name = "admin", description = "Operational controls, enabled by ADMIN_TOKEN"),
+        (name = "events", description = "Change events of users over a WebSocket, sourced from PostgreSQL LISTEN/NOTIFY"),
+        (name = "tenants", description = "Tenants users belong to, enabled by ADMIN_TOKEN. API requests name theirs with `X-Tenant-Id` or a subdomain of TENANT_DOMAIN, and the default tenant otherwise")
     ),
     info(
         title = "axum_postgres API",
@@ -18,7 +160,1119 @@ use crate::models::user::{UserResponse, CreateUserRequest, UpdateUserRequest, Er
 )]
 pub struct ApiDoc;
 
-/// Get OpenAPI specification as JSON  
+/// Get OpenAPI specification as JSON
 pub fn openapi_spec() -> utoipa::openapi::OpenApi {
-    ApiDoc::openapi()
-}
\ No newline at end of file
+    let mut spec = ApiDoc::openapi();
+    if casing::CAMEL_CASE {
+        CamelCaseExamples.modify(&mut spec);
+    }
+    spec
+}
+
+/// Specification for the configured response shape
+///
+/// With the envelope enabled, every `/api` success body is documented as
+/// `{success, data, meta}` and the version gains a `+envelope` suffix, so
+/// generated clients for the two shapes are told apart.
+pub fn openapi_spec_for(envelope: ResponseEnvelope) -> utoipa::openapi::OpenApi {
+    let mut spec = openapi_spec();
+    if envelope.is_enabled() {
+        SuccessEnvelope.modify(&mut spec);
+    }
+    spec
+}
+
+/// Attaches named request/response examples to the user operations
+///
+/// Every body documented by an operation under `/api/users` or `/api/me`
+/// has one, 500s aside. Examples mirror the exact bodies produced by the
+/// handlers and `AppError`, so integrators can see success, validation
+/// failure, duplicate email and not-found cases side by side in Swagger UI.
+/// Other operations show the examples of their schemas.
+struct OperationExamples;
+
+impl Modify for OperationExamples {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let created = json!({
+            "id": "42",
+            "name": "Jane Doe",
+            "email": "jane@example.com",
+            "email_verified": false,
+            "active": true,
+            "display_name": null,
+            "bio": null,
+            "phone": null,
+            "timezone": null,
+            "locale": null,
+            "created_at": "2024-01-01T00:00:00+00:00",
+            "updated_at": "2024-01-01T00:00:00+00:00"
+        });
+        let updated = json!({
+            "id": "42",
+            "name": "Jane Smith",
+            "email": "jane@example.com",
+            "email_verified": true,
+            "active": false,
+            "display_name": "Jane",
+            "bio": null,
+            "phone": "+442071838750",
+            "timezone": "Europe/London",
+            "locale": "en-GB",
+            "created_at": "2024-01-01T00:00:00+00:00",
+            "updated_at": "2024-03-15T09:30:00+00:00"
+        });
+        let mut activated = updated.clone();
+        activated["active"] = json!(true);
+        activated["updated_at"] = json!("2024-03-16T08:00:00+00:00");
+
+        let mut invalid_email = error_body(
+            StatusCode::BAD_REQUEST,
+            "VALIDATION_FAILED",
+            "Validation errors: email: Invalid email format",
+        );
+        invalid_email["errors"] = json!([{"field": "email", "code": "invalid_email", "message": "Invalid email format"}]);
+        let validation_failure = example("Request body failed validation", invalid_email);
+        let duplicate_email = example(
+            "Email is already used by another user, in any letter case",
+            error_body(StatusCode::CONFLICT, "EMAIL_TAKEN", "Email address already exists"),
+        );
+        let invalid_id = example(
+            "Path ID is not a valid integer",
+            error_body(StatusCode::BAD_REQUEST, "BAD_REQUEST", "Invalid user ID format"),
+        );
+        let not_found = example(
+            "No user has this ID",
+            error_body(StatusCode::NOT_FOUND, "USER_NOT_FOUND", "User not found"),
+        );
+        let signed_out = example(
+            "No access token, session or API key was sent",
+            error_body(StatusCode::UNAUTHORIZED, "UNAUTHORIZED", "Missing bearer token"),
+        );
+        let expired = example(
+            "The access token has expired; refresh it",
+            error_body(StatusCode::UNAUTHORIZED, "UNAUTHORIZED", "Token has expired"),
+        );
+        let include_deleted = example(
+            "include_deleted=true without the admin token",
+            error_body(
+                StatusCode::UNAUTHORIZED,
+                "UNAUTHORIZED",
+                "include_deleted requires the admin token",
+            ),
+        );
+        let not_admin = example(
+            "The caller does not have the admin role",
+            error_body(StatusCode::FORBIDDEN, "FORBIDDEN", "Requires the admin role"),
+        );
+        let missing_scope = |scope: &str| {
+            example(
+                "The API key was created without this scope",
+                error_body(
+                    StatusCode::FORBIDDEN,
+                    "FORBIDDEN",
+                    &format!("API key lacks the {} scope", scope),
+                ),
+            )
+        };
+        let other_user = example(
+            "Only admins can edit other users",
+            error_body(StatusCode::FORBIDDEN, "FORBIDDEN", "You can only edit your own user"),
+        );
+        let active_flag = example(
+            "Only admins can change `active`",
+            error_body(
+                StatusCode::FORBIDDEN,
+                "FORBIDDEN",
+                "Only admins can activate or deactivate users",
+            ),
+        );
+        let impersonated_email = example(
+            "Admins acting as the user cannot change the email",
+            error_body(
+                StatusCode::FORBIDDEN,
+                "FORBIDDEN",
+                "Impersonation tokens cannot change the email",
+            ),
+        );
+        let stale = example(
+            "The user changed since the `If-Match` ETag was read",
+            error_body(
+                StatusCode::PRECONDITION_FAILED,
+                "PRECONDITION_FAILED",
+                "User was modified since the given ETag",
+            ),
+        );
+        let deleted_since = example(
+            "The signed-in user was deleted since signing in",
+            error_body(StatusCode::NOT_FOUND, "USER_NOT_FOUND", "User not found"),
+        );
+        let replace = example(
+            "Every field is required",
+            json!({"name": "Jane Smith", "email": "jane@example.com", "active": false}),
+        );
+
+        let paths = &mut openapi.paths;
+
+        if let Some(op) = operation(paths, "/api/users", PathItemType::Post) {
+            request_example(
+                op,
+                "valid",
+                example(
+                    "Valid new user",
+                    json!({"name": "Jane Doe", "email": "jane@example.com"}),
+                ),
+            );
+            request_example(
+                op,
+                "invalid_email",
+                example(
+                    "Rejected: malformed email",
+                    json!({"name": "Jane Doe", "email": "not-an-email"}),
+                ),
+            );
+            response_example(
+                op,
+                "201",
+                "created",
+                example("User created", created.clone()),
+            );
+            response_example(op, "400", "validation_failure", validation_failure.clone());
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "401", "expired", expired.clone());
+            response_example(op, "403", "missing_scope", missing_scope("users:write"));
+            response_example(op, "409", "duplicate_email", duplicate_email.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users", PathItemType::Get) {
+            response_example(
+                op,
+                "200",
+                "users",
+                example(
+                    "Users ordered by creation date, newest first",
+                    json!([created.clone()]),
+                ),
+            );
+            response_example(op, "200", "empty", example("No users exist yet", json!([])));
+            response_example(
+                op,
+                "206",
+                "slice",
+                example(
+                    "`Range: items=0-0` of 1234 users",
+                    json!([created.clone()]),
+                ),
+            );
+            response_example(
+                op,
+                "400",
+                "invalid_cursor",
+                example(
+                    "The cursor was not returned by a previous page",
+                    error_body(StatusCode::BAD_REQUEST, "BAD_REQUEST", "Invalid cursor"),
+                ),
+            );
+            response_example(
+                op,
+                "400",
+                "cursor_with_sort",
+                example(
+                    "Cursors only page through the newest-first order",
+                    error_body(
+                        StatusCode::BAD_REQUEST,
+                        "BAD_REQUEST",
+                        "Cursor pagination requires the default sort order",
+                    ),
+                ),
+            );
+            response_example(op, "401", "include_deleted", include_deleted.clone());
+            response_example(
+                op,
+                "416",
+                "past_the_end",
+                example(
+                    "`Range: items=2000-` of 1234 users",
+                    error_body(
+                        StatusCode::RANGE_NOT_SATISFIABLE,
+                        "RANGE_NOT_SATISFIABLE",
+                        "Range starts past the last of 1234 items",
+                    ),
+                ),
+            );
+        }
+
+        if let Some(op) = operation(paths, "/api/users/count", PathItemType::Get) {
+            response_example(
+                op,
+                "200",
+                "count",
+                example("Users matching the filters", json!({"count": 1234})),
+            );
+            response_example(op, "401", "include_deleted", include_deleted.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/check-email", PathItemType::Get) {
+            response_example(
+                op,
+                "200",
+                "available",
+                example("No user has this address", json!({"available": true})),
+            );
+            response_example(
+                op,
+                "200",
+                "taken",
+                example(
+                    "A user has this address, in any letter case",
+                    json!({"available": false}),
+                ),
+            );
+            response_example(op, "400", "validation_failure", validation_failure.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/search", PathItemType::Get) {
+            response_example(
+                op,
+                "200",
+                "matches",
+                example("`q=jane` matches, best match first", json!([created.clone()])),
+            );
+            response_example(
+                op,
+                "400",
+                "empty_query",
+                example(
+                    "`q` is blank",
+                    error_body(
+                        StatusCode::BAD_REQUEST,
+                        "BAD_REQUEST",
+                        "Search query must not be empty",
+                    ),
+                ),
+            );
+        }
+
+        if let Some(op) = operation(paths, "/api/users/import", PathItemType::Post) {
+            request_example(
+                op,
+                "csv",
+                example(
+                    "`file` field holding a CSV with a header row",
+                    json!("name,email\nJane Doe,jane@example.com\nJohn Doe,not-an-email\n"),
+                ),
+            );
+            response_example(
+                op,
+                "200",
+                "imported",
+                example(
+                    "One row imported and one rejected",
+                    json!({
+                        "processed": 2,
+                        "created": 1,
+                        "rejected": [{"row": 2, "errors": ["email: Invalid email format"]}]
+                    }),
+                ),
+            );
+            response_example(
+                op,
+                "400",
+                "not_multipart",
+                example(
+                    "The body is not a form upload",
+                    error_body(
+                        StatusCode::BAD_REQUEST,
+                        "BAD_REQUEST",
+                        "Expected a multipart/form-data upload",
+                    ),
+                ),
+            );
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "not_admin", not_admin.clone());
+            response_example(op, "403", "missing_scope", missing_scope("users:import"));
+            response_example(
+                op,
+                "409",
+                "running",
+                example(
+                    "An upload with the same X-Request-Id has not finished",
+                    error_body(
+                        StatusCode::CONFLICT,
+                        "CONFLICT",
+                        "An import with this X-Request-Id is still running",
+                    ),
+                ),
+            );
+            response_example(
+                op,
+                "413",
+                "too_large",
+                example(
+                    "Upload larger than the default MAX_UPLOAD_BYTES",
+                    error_body(
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        "PAYLOAD_TOO_LARGE",
+                        "Request body is larger than 4294967296 bytes",
+                    ),
+                ),
+            );
+        }
+
+        if let Some(op) = operation(paths, "/api/users/import/{request_id}", PathItemType::Get) {
+            response_example(
+                op,
+                "200",
+                "running",
+                example(
+                    "Import still reading rows",
+                    json!({
+                        "request_id": "5f0c6d2e-8a1b-4c3d-9e7f-0a1b2c3d4e5f",
+                        "status": "running",
+                        "processed": 250000,
+                        "created": 249870,
+                        "rejected": 130,
+                        "error": null,
+                        "started_at": "2024-01-01T00:00:00+00:00",
+                        "updated_at": "2024-01-01T00:01:30+00:00",
+                        "finished_at": null
+                    }),
+                ),
+            );
+            response_example(
+                op,
+                "200",
+                "completed",
+                example(
+                    "Import finished",
+                    json!({
+                        "request_id": "5f0c6d2e-8a1b-4c3d-9e7f-0a1b2c3d4e5f",
+                        "status": "completed",
+                        "processed": 1000000,
+                        "created": 999480,
+                        "rejected": 520,
+                        "error": null,
+                        "started_at": "2024-01-01T00:00:00+00:00",
+                        "updated_at": "2024-01-01T00:06:10+00:00",
+                        "finished_at": "2024-01-01T00:06:10+00:00"
+                    }),
+                ),
+            );
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "not_admin", not_admin.clone());
+            response_example(op, "403", "missing_scope", missing_scope("users:import"));
+            response_example(
+                op,
+                "404",
+                "unknown",
+                example(
+                    "No upload used this X-Request-Id",
+                    error_body(StatusCode::NOT_FOUND, "NOT_FOUND", "Import not found"),
+                ),
+            );
+        }
+
+        if let Some(op) = operation(paths, "/api/users/export", PathItemType::Get) {
+            // A string, so `CamelCaseExamples` cannot rename its fields later
+            let lines = [created.clone(), updated.clone()]
+                .into_iter()
+                .map(|user| format!("{}\n", casing::rename_keys(user)))
+                .collect::<String>();
+            response_example(
+                op,
+                "200",
+                "users",
+                example("One user per line, newest first", json!(lines)),
+            );
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "not_admin", not_admin.clone());
+            response_example(op, "403", "missing_scope", missing_scope("users:read"));
+        }
+
+        if let Some(op) = operation(paths, "/api/me", PathItemType::Get) {
+            response_example(op, "200", "me", example("The signed-in user", created.clone()));
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "401", "expired", expired.clone());
+            response_example(op, "404", "deleted", deleted_since.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/me", PathItemType::Put) {
+            request_example(op, "replace", replace.clone());
+            response_example(op, "200", "updated", example("User updated", updated.clone()));
+            response_example(op, "400", "validation_failure", validation_failure.clone());
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "active_flag", active_flag.clone());
+            response_example(op, "403", "impersonated_email", impersonated_email.clone());
+            response_example(op, "404", "deleted", deleted_since.clone());
+            response_example(op, "409", "duplicate_email", duplicate_email.clone());
+            response_example(op, "412", "stale", stale.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/me", PathItemType::Delete) {
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(
+                op,
+                "403",
+                "impersonating",
+                example(
+                    "Admins acting as the user cannot delete the account",
+                    error_body(
+                        StatusCode::FORBIDDEN,
+                        "FORBIDDEN",
+                        "Impersonation tokens cannot delete the account",
+                    ),
+                ),
+            );
+            response_example(op, "404", "deleted", deleted_since);
+            response_example(op, "412", "stale", stale.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}", PathItemType::Get) {
+            response_example(op, "200", "found", example("User found", created.clone()));
+            response_example(op, "400", "invalid_id", invalid_id.clone());
+            response_example(op, "401", "include_deleted", include_deleted);
+            response_example(op, "404", "not_found", not_found.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}", PathItemType::Put) {
+            request_example(op, "replace", replace);
+            response_example(op, "200", "updated", example("User updated", updated.clone()));
+            response_example(op, "400", "validation_failure", validation_failure.clone());
+            response_example(op, "409", "duplicate_email", duplicate_email.clone());
+            response_example(op, "400", "invalid_id", invalid_id.clone());
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "other_user", other_user.clone());
+            response_example(op, "403", "active_flag", active_flag.clone());
+            response_example(op, "403", "impersonated_email", impersonated_email.clone());
+            response_example(op, "403", "missing_scope", missing_scope("users:write"));
+            response_example(op, "404", "not_found", not_found.clone());
+            response_example(op, "412", "stale", stale.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}", PathItemType::Patch) {
+            request_example(
+                op,
+                "rename",
+                example("Change only the name", json!({"name": "Jane Smith"})),
+            );
+            request_example(
+                op,
+                "deactivate",
+                example("Deactivate the user", json!({"active": false})),
+            );
+            request_example(
+                op,
+                "profile",
+                example(
+                    "Set the time zone and clear the bio",
+                    json!({"timezone": "Europe/London", "bio": null}),
+                ),
+            );
+            response_example(op, "200", "updated", example("User updated", updated.clone()));
+            response_example(op, "400", "validation_failure", validation_failure);
+            response_example(op, "409", "duplicate_email", duplicate_email.clone());
+            response_example(
+                op,
+                "400",
+                "null_field",
+                example(
+                    "Required fields cannot be cleared",
+                    error_body(
+                        StatusCode::BAD_REQUEST,
+                        "BAD_REQUEST",
+                        "Field 'name' cannot be null",
+                    ),
+                ),
+            );
+            response_example(
+                op,
+                "400",
+                "unknown_field",
+                example(
+                    "Only the user's own fields can be patched",
+                    error_body(
+                        StatusCode::BAD_REQUEST,
+                        "BAD_REQUEST",
+                        "Field 'id' cannot be patched",
+                    ),
+                ),
+            );
+            response_example(op, "400", "invalid_id", invalid_id.clone());
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "other_user", other_user);
+            response_example(op, "403", "active_flag", active_flag);
+            response_example(op, "403", "impersonated_email", impersonated_email);
+            response_example(op, "403", "missing_scope", missing_scope("users:write"));
+            response_example(op, "404", "not_found", not_found.clone());
+            response_example(op, "412", "stale", stale.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}", PathItemType::Delete) {
+            response_example(op, "400", "invalid_id", invalid_id.clone());
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(op, "403", "not_admin", not_admin.clone());
+            response_example(op, "403", "missing_scope", missing_scope("users:write"));
+            response_example(op, "404", "not_found", not_found.clone());
+            response_example(op, "412", "stale", stale);
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}/restore", PathItemType::Post) {
+            response_example(op, "200", "restored", example("User restored", created.clone()));
+            response_example(op, "400", "invalid_id", invalid_id.clone());
+            response_example(op, "401", "signed_out", signed_out.clone());
+            response_example(
+                op,
+                "403",
+                "not_admin",
+                example(
+                    "The caller does not have the admin role",
+                    error_body(
+                        StatusCode::FORBIDDEN,
+                        "FORBIDDEN",
+                        "Only admins can restore deleted users",
+                    ),
+                ),
+            );
+            response_example(op, "403", "missing_scope", missing_scope("users:write"));
+            response_example(
+                op,
+                "404",
+                "not_deleted",
+                example(
+                    "No soft-deleted user has this ID",
+                    error_body(StatusCode::NOT_FOUND, "NOT_FOUND", "Deleted user not found"),
+                ),
+            );
+            response_example(
+                op,
+                "409",
+                "duplicate_email",
+                example(
+                    "Another user took the email while this one was deleted",
+                    error_body(StatusCode::CONFLICT, "EMAIL_TAKEN", "Email address already exists"),
+                ),
+            );
+        }
+
+        let activation_forbidden = example(
+            "The caller does not have the admin role",
+            error_body(
+                StatusCode::FORBIDDEN,
+                "FORBIDDEN",
+                "Only admins can activate or deactivate users",
+            ),
+        );
+        for (path, name, summary, user) in [
+            ("/api/users/{id}/activate", "activated", "User is active", activated),
+            ("/api/users/{id}/deactivate", "deactivated", "User is inactive", updated),
+        ] {
+            if let Some(op) = operation(paths, path, PathItemType::Post) {
+                response_example(op, "200", name, example(summary, user));
+                response_example(op, "400", "invalid_id", invalid_id.clone());
+                response_example(op, "401", "signed_out", signed_out.clone());
+                response_example(op, "403", "not_admin", activation_forbidden.clone());
+                response_example(op, "403", "missing_scope", missing_scope("users:write"));
+                response_example(op, "404", "not_found", not_found.clone());
+            }
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}/history", PathItemType::Get) {
+            response_example(
+                op,
+                "200",
+                "versions",
+                example(
+                    "Created, then renamed",
+                    json!([
+                        {
+                            "version": 1,
+                            "operation": "INSERT",
+                            "changed_by": null,
+                            "changed_at": "2024-01-01T00:00:00+00:00",
+                            "data": {"id": 42, "name": "Jane Doe", "email": "jane@example.com", "active": true},
+                            "changes": {
+                                "active": {"from": null, "to": true},
+                                "email": {"from": null, "to": "jane@example.com"},
+                                "id": {"from": null, "to": 42},
+                                "name": {"from": null, "to": "Jane Doe"}
+                            }
+                        },
+                        {
+                            "version": 2,
+                            "operation": "UPDATE",
+                            "changed_by": null,
+                            "changed_at": "2024-03-15T09:30:00+00:00",
+                            "data": {"id": 42, "name": "Jane Smith", "email": "jane@example.com", "active": true},
+                            "changes": {"name": {"from": "Jane Doe", "to": "Jane Smith"}}
+                        }
+                    ]),
+                ),
+            );
+            response_example(op, "400", "invalid_id", invalid_id.clone());
+            response_example(op, "404", "not_found", not_found.clone());
+        }
+
+        if let Some(op) = operation(paths, "/api/users/{id}/change-password", PathItemType::Post) {
+            request_example(
+                op,
+                "change",
+                example(
+                    "Current and new password",
+                    json!({"current_password": "correct horse battery staple", "new_password": "tr0ub4dor and more"}),
+                ),
+            );
+            let mut weak = error_body(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "Validation errors: new_password: Password must be at least 8 characters",
+            );
+            weak["errors"] = json!([{"field": "new_password", "code": "invalid_password", "message": "Password must be at least 8 characters"}]);
+            response_example(
+                op,
+                "400",
+                "weak_password",
+                example("The new password breaks the policy", weak),
+            );
+            response_example(op, "400", "invalid_id", invalid_id);
+            response_example(op, "401", "signed_out", signed_out);
+            response_example(
+                op,
+                "401",
+                "wrong_password",
+                example(
+                    "`current_password` does not match",
+                    error_body(
+                        StatusCode::UNAUTHORIZED,
+                        "UNAUTHORIZED",
+                        "Current password is incorrect",
+                    ),
+                ),
+            );
+            response_example(
+                op,
+                "403",
+                "other_user",
+                example(
+                    "The path names another user",
+                    error_body(
+                        StatusCode::FORBIDDEN,
+                        "FORBIDDEN",
+                        "You can only change your own password",
+                    ),
+                ),
+            );
+            response_example(op, "404", "not_found", not_found);
+        }
+    }
+}
+
+/// Documents error responses as `application/problem+json`
+///
+/// utoipa lists every JSON body under `application/json`; this moves the
+/// [`ErrorResponse`] ones, with their examples, to the media type
+/// `AppError` answers with.
+struct ProblemDetails;
+
+impl Modify for ProblemDetails {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let error_response = RefOr::<Schema>::Ref(Ref::from_schema_name("ErrorResponse"));
+        for item in openapi.paths.paths.values_mut() {
+            for operation in item.operations.values_mut() {
+                for response in operation.responses.responses.values_mut() {
+                    let RefOr::T(response) = response else {
+                        continue;
+                    };
+                    let is_problem = response
+                        .content
+                        .get("application/json")
+                        .is_some_and(|content| content.schema == error_response);
+                    if is_problem {
+                        if let Some(content) = response.content.shift_remove("application/json") {
+                            response.content.insert(PROBLEM_JSON.to_string(), content);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Declares the bearer tokens for `/scim/v2` and `/admin`, the user
+/// credentials: access tokens, session cookies and API keys with their
+/// scopes, and the admin role those credentials need for `/api/admin/users`
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("scim_token", bearer("Value of SCIM_BEARER_TOKEN"));
+            components.add_security_scheme("admin_token", bearer("Value of ADMIN_TOKEN"));
+            components.add_security_scheme(
+                "bearer_auth",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .description(Some("Access token from /api/auth/login or /api/auth/register"))
+                        .build(),
+                ),
+            );
+            components.add_security_scheme(
+                "session_cookie",
+                SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                    "session",
+                    "Session from /api/auth/login or /api/auth/register when AUTH_MODE=cookie; \
+                     other than GET, HEAD and OPTIONS, requests must also send the csrf_token \
+                     as X-CSRF-Token",
+                ))),
+            );
+            components.add_security_scheme(
+                "admin_role",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .description(Some(
+                            "Access token of a user holding the admin role; a session cookie or \
+                             API key of such a user works too",
+                        ))
+                        .build(),
+                ),
+            );
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                    "X-API-Key",
+                    "Key from POST /api/api-keys. Operations list the scope the key needs: \
+                     users:read, users:write, users:import or roles:write, and users:* or \
+                     roles:* grant every scope of the resource; those listing none need no \
+                     scope. Keys act with the roles of the user who created them",
+                ))),
+            );
+        }
+    }
+}
+
+/// Documents `middleware::envelope::wrap` on the `/api` success responses
+///
+/// Each wrapped body gets a `<Schema>Envelope` component, an
+/// [`ApiResponse`](crate::models::envelope::ApiResponse) whose `data` is the
+/// bare list for collections such as `UserPage`.
+struct SuccessEnvelope;
+
+impl Modify for SuccessEnvelope {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.info.version = format!("{}+envelope", openapi.info.version);
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if !path.starts_with("/api/") {
+                continue;
+            }
+            for operation in item.operations.values_mut() {
+                for (status, response) in operation.responses.responses.iter_mut() {
+                    let RefOr::T(response) = response else {
+                        continue;
+                    };
+                    if !status.starts_with('2') {
+                        continue;
+                    }
+                    let Some(content) = response.content.get_mut("application/json") else {
+                        continue;
+                    };
+
+                    content.schema = match &content.schema {
+                        RefOr::Ref(reference) => {
+                            let name = reference
+                                .ref_location
+                                .rsplit('/')
+                                .next()
+                                .unwrap_or_default()
+                                .to_string();
+                            let envelope_name = format!("{}Envelope", name);
+                            if !components.schemas.contains_key(&envelope_name) {
+                                let data = collection_items(components.schemas.get(&name))
+                                    .unwrap_or_else(|| content.schema.clone());
+                                components
+                                    .schemas
+                                    .insert(envelope_name.clone(), envelope_schema(data));
+                            }
+                            Ref::from_schema_name(envelope_name).into()
+                        }
+                        schema => envelope_schema(schema.clone()),
+                    };
+                    for example in content.examples.values_mut() {
+                        if let RefOr::T(example) = example {
+                            example.value = example.value.take().map(crate::middleware::envelope::envelop);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `{success, data, meta}` around `data`
+fn envelope_schema(data: RefOr<Schema>) -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .property(
+            "success",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::Boolean)
+                .example(Some(json!(true))),
+        )
+        .required("success")
+        .property("data", data)
+        .required("data")
+        .property("meta", Ref::from_schema_name("ResponseMeta"))
+        .required("meta")
+        .into()
+}
+
+/// Schema of the `data` list of a collection object, which the envelope
+/// unwraps; the other members move to `meta`
+fn collection_items(schema: Option<&RefOr<Schema>>) -> Option<RefOr<Schema>> {
+    match schema? {
+        RefOr::T(Schema::Object(object)) => object.properties.get("data").cloned(),
+        _ => None,
+    }
+}
+
+/// Renames the fields of hand-written examples to match camelCase schemas
+///
+/// Schemas follow the serde attributes on their own; the literal example
+/// bodies in `#[schema(example)]` and `OperationExamples` do not.
+struct CamelCaseExamples;
+
+impl Modify for CamelCaseExamples {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            for schema in components.schemas.values_mut() {
+                if let RefOr::T(Schema::Object(object)) = schema {
+                    rename_example(&mut object.example);
+                }
+            }
+        }
+
+        for item in openapi.paths.paths.values_mut() {
+            for operation in item.operations.values_mut() {
+                let requests = operation
+                    .request_body
+                    .iter_mut()
+                    .flat_map(|body| body.content.values_mut());
+                let responses = operation
+                    .responses
+                    .responses
+                    .values_mut()
+                    .filter_map(|response| match response {
+                        RefOr::T(response) => Some(response.content.values_mut()),
+                        RefOr::Ref(_) => None,
+                    })
+                    .flatten();
+                for content in requests.chain(responses) {
+                    rename_example(&mut content.example);
+                    for example in content.examples.values_mut() {
+                        if let RefOr::T(example) = example {
+                            rename_example(&mut example.value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn rename_example(example: &mut Option<Value>) {
+    if let Some(value) = example.take() {
+        *example = Some(casing::rename_keys(value));
+    }
+}
+
+fn bearer(description: &str) -> SecurityScheme {
+    SecurityScheme::Http(
+        HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .description(Some(description))
+            .build(),
+    )
+}
+
+/// Build an example with a short summary and literal value
+fn example(summary: &str, value: Value) -> Example {
+    ExampleBuilder::new()
+        .summary(summary)
+        .value(Some(value))
+        .build()
+}
+
+/// Error body as serialized by `AppError::into_response`
+fn error_body(status: StatusCode, code: &str, detail: &str) -> Value {
+    json!({
+        "type": problem_type(code),
+        "title": status.canonical_reason(),
+        "status": status.as_u16(),
+        "detail": detail,
+        "code": code,
+        "request_id": "5f0c6d2e-8a1b-4c3d-9e7f-0a1b2c3d4e5f",
+    })
+}
+
+fn operation<'a>(
+    paths: &'a mut utoipa::openapi::Paths,
+    path: &str,
+    method: PathItemType,
+) -> Option<&'a mut Operation> {
+    paths.paths.get_mut(path)?.operations.get_mut(&method)
+}
+
+fn request_example(operation: &mut Operation, name: &str, example: Example) {
+    if let Some(content) = operation
+        .request_body
+        .as_mut()
+        .and_then(|body| body.content.values_mut().next())
+    {
+        content.examples.insert(name.to_string(), RefOr::T(example));
+    }
+}
+
+fn response_example(operation: &mut Operation, status: &str, name: &str, example: Example) {
+    if let Some(RefOr::T(response)) = operation.responses.responses.get_mut(status) {
+        if let Some(content) = response.content.values_mut().next() {
+            content.examples.insert(name.to_string(), RefOr::T(example));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operations_carry_named_examples() {
+        let spec = serde_json::to_value(openapi_spec()).expect("Failed to serialize spec");
+        let post = &spec["paths"]["/api/users"]["post"];
+
+        let request = &post["requestBody"]["content"]["application/json"]["examples"];
+        assert!(request["valid"].is_object());
+        assert!(request["invalid_email"].is_object());
+
+        let bad_request = &post["responses"]["400"]["content"][PROBLEM_JSON]["examples"];
+        let validation_failure = &bad_request["validation_failure"]["value"];
+        assert_eq!(validation_failure["code"], "VALIDATION_FAILED");
+        assert_eq!(validation_failure["errors"][0]["field"], "email");
+        assert_eq!(validation_failure["errors"][0]["code"], "invalid_email");
+        let conflict = &post["responses"]["409"]["content"][PROBLEM_JSON]["examples"];
+        assert_eq!(conflict["duplicate_email"]["value"]["code"], "EMAIL_TAKEN");
+        assert_eq!(conflict["duplicate_email"]["value"]["status"], 409);
+
+        let get = &spec["paths"]["/api/users/{id}"]["get"];
+        assert!(get["responses"]["404"]["content"]["application/json"].is_null());
+        let not_found = &get["responses"]["404"]["content"][PROBLEM_JSON]["examples"];
+        assert_eq!(not_found["not_found"]["value"]["detail"], "User not found");
+        assert_eq!(
+            not_found["not_found"]["value"]["type"],
+            "/problems/user-not-found"
+        );
+    }
+
+    #[test]
+    fn test_user_operations_have_an_example_for_every_body() {
+        let spec = serde_json::to_value(openapi_spec()).expect("Failed to serialize spec");
+        let mut missing = Vec::new();
+        for (path, item) in spec["paths"].as_object().unwrap() {
+            if !path.starts_with("/api/users") && !path.starts_with("/api/me") {
+                continue;
+            }
+            for (method, operation) in item.as_object().unwrap() {
+                for (status, response) in operation["responses"].as_object().unwrap() {
+                    // Query string rejections of the export are answered in plain text
+                    if status == "500" || (path == "/api/users/export" && status == "400") {
+                        continue;
+                    }
+                    let Some(content) = response["content"].as_object() else {
+                        continue;
+                    };
+                    for (media_type, body) in content {
+                        if body["examples"].as_object().is_none_or(|e| e.is_empty()) {
+                            missing.push(format!("{} {} {} {}", method, path, status, media_type));
+                        }
+                    }
+                }
+            }
+        }
+        assert!(missing.is_empty(), "No examples for: {:?}", missing);
+
+        let export = &spec["paths"]["/api/users/export"]["get"]["responses"]["200"]["content"]
+            ["application/x-ndjson"]["examples"]["users"]["value"];
+        let first = export.as_str().unwrap().lines().next().unwrap();
+        let user: Value = serde_json::from_str(first).unwrap();
+        assert_eq!(user["email"], "jane@example.com");
+
+        let stale = &spec["paths"]["/api/me"]["put"]["responses"]["412"]["content"][PROBLEM_JSON]
+            ["examples"]["stale"]["value"];
+        assert_eq!(stale["code"], "PRECONDITION_FAILED");
+        assert_eq!(stale["status"], 412);
+    }
+
+    #[test]
+    fn test_operations_name_the_api_key_scope() {
+        let spec = serde_json::to_value(openapi_spec()).expect("Failed to serialize spec");
+        let api_key = |path: &str, method: &str| {
+            spec["paths"][path][method]["security"]
+                .as_array()
+                .and_then(|schemes| schemes.iter().find_map(|scheme| scheme.get("api_key")))
+                .cloned()
+        };
+        assert_eq!(api_key("/api/users", "post"), Some(json!(["users:write"])));
+        assert_eq!(api_key("/api/users/export", "get"), Some(json!(["users:read"])));
+        assert_eq!(api_key("/api/users/import", "post"), Some(json!(["users:import"])));
+        assert_eq!(
+            api_key("/api/admin/roles/{role}/users/{id}", "put"),
+            Some(json!(["roles:write"]))
+        );
+        assert_eq!(api_key("/api/admin/audit-log", "get"), Some(json!([])));
+        assert_eq!(api_key("/api/me", "get"), None);
+    }
+
+    #[test]
+    fn test_envelope_mode_wraps_success_schemas() {
+        let spec = serde_json::to_value(openapi_spec_for(ResponseEnvelope::new(true)))
+            .expect("Failed to serialize spec");
+        assert!(spec["info"]["version"].as_str().unwrap().ends_with("+envelope"));
+
+        let get = &spec["paths"]["/api/users/{id}"]["get"]["responses"];
+        let ok = &get["200"]["content"]["application/json"];
+        assert_eq!(
+            ok["schema"]["$ref"],
+            "#/components/schemas/UserResponseEnvelope"
+        );
+        let schemas = &spec["components"]["schemas"];
+        let envelope = &schemas["UserResponseEnvelope"]["properties"];
+        assert_eq!(envelope["data"]["$ref"], "#/components/schemas/UserResponse");
+        assert_eq!(envelope["meta"]["$ref"], "#/components/schemas/ResponseMeta");
+        // Collections are unwrapped: `data` is the list itself
+        let page = &schemas["AuditLogPageEnvelope"]["properties"]["data"];
+        assert_eq!(page["type"], "array");
+        assert_eq!(
+            page["items"]["$ref"],
+            "#/components/schemas/AuditEntryResponse"
+        );
+        assert_eq!(ok["examples"]["found"]["value"]["success"], true);
+        // Errors keep their shape
+        let not_found = &get["404"]["content"][PROBLEM_JSON]["schema"];
+        assert_eq!(not_found["$ref"], "#/components/schemas/ErrorResponse");
+    }
+
+    #[cfg(feature = "camel-case")]
+    #[test]
+    fn test_camel_case_schemas_and_examples() {
+        let spec = serde_json::to_value(openapi_spec()).expect("Failed to serialize spec");
+        let user = &spec["components"]["schemas"]["UserResponse"];
+        assert!(user["properties"]["createdAt"].is_object());
+        assert!(user["properties"]["created_at"].is_null());
+        assert!(user["example"]["createdAt"].is_string());
+
+        let created = &spec["paths"]["/api/users"]["post"]["responses"]["201"]["content"]
+            ["application/json"]["examples"]["created"]["value"];
+        assert!(created["createdAt"].is_string());
+    }
+}
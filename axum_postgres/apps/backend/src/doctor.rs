@@ -0,0 +1,563 @@
+//! `backend doctor`: startup self-test for deploy pipelines
+//!
+//! Validates configuration, database connectivity, migration status, broker
+//! and Redis reachability and clock skew against the database, then prints
+//! a pass/fail report. The process exits non-zero when any check fails, so a
+//! deploy can stop before the new version takes traffic.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+/// How long to wait for each network dependency
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Skew against the database clock that is reported as a warning
+const SKEW_WARN: Duration = Duration::from_secs(1);
+/// Skew that breaks token expiry and `created_at` ordering
+const SKEW_FAIL: Duration = Duration::from_secs(5);
+
+/// Numeric settings that silently fall back to a default when unparseable
+const NUMERIC_VARS: &[&str] = &[
+    "HEALTH_CHECK_TIMEOUT_MS",
+    "HEALTH_CHECK_INTERVAL_MS",
+    "ADMISSION_QUEUE_TIMEOUT_MS",
+    "ADMISSION_READ_CONCURRENCY",
+    "ADMISSION_READ_MAX_QUEUE",
+    "ADMISSION_WRITE_CONCURRENCY",
+    "ADMISSION_WRITE_MAX_QUEUE",
+    "REQUEST_TIMEOUT_SECS",
+    "MAX_BODY_BYTES",
+    "RATE_LIMIT_AUTH_PER_MINUTE",
+    "RATE_LIMIT_API_PER_MINUTE",
+    "SLOW_QUERY_MS",
+    "DB_RETRY_ATTEMPTS",
+    "DB_RETRY_BASE_DELAY_MS",
+    "DB_RETRY_MAX_DELAY_MS",
+    "DB_CIRCUIT_FAILURES",
+    "DB_CIRCUIT_OPEN_SECS",
+    "CONSUMER_MAX_ATTEMPTS",
+];
+
+/// Bearer tokens shorter than this are reported as weak
+const MIN_TOKEN_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+            Status::Skip => "SKIP",
+        }
+    }
+}
+
+/// Outcome of one doctor check
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub passed: bool,
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    fn new(checks: Vec<CheckResult>) -> Self {
+        Self {
+            passed: checks.iter().all(|check| check.status != Status::Fail),
+            checks,
+        }
+    }
+
+    /// One line per check followed by the overall verdict
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for check in &self.checks {
+            out.push_str(&format!(
+                "{}  {:<12} {}\n",
+                check.status.label(),
+                check.name,
+                check.detail
+            ));
+        }
+        out.push_str(if self.passed {
+            "\nAll checks passed\n"
+        } else {
+            "\nOne or more checks failed\n"
+        });
+        out
+    }
+}
+
+/// Run every check in order
+///
+/// Checks that need the database are skipped when it cannot be reached.
+pub async fn run() -> DoctorReport {
+    let mut checks = vec![check_config()];
+
+    let database_url = crate::database::get_database_url();
+    let pool = match tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        crate::database::create_pool(&database_url),
+    )
+    .await
+    {
+        Ok(Ok(pool)) => {
+            checks.push(CheckResult::new(
+                "database",
+                Status::Pass,
+                format!("Connected to {}", crate::database::mask_password(&database_url)),
+            ));
+            Some(pool)
+        }
+        Ok(Err(e)) => {
+            checks.push(connection_failure(&database_url, &e.to_string()));
+            None
+        }
+        Err(_) => {
+            checks.push(connection_failure(&database_url, "timed out"));
+            None
+        }
+    };
+
+    match &pool {
+        Some(pool) => {
+            checks.push(check_migrations(pool).await);
+            checks.push(check_clock_skew(pool).await);
+        }
+        None => {
+            checks.push(CheckResult::new("migrations", Status::Skip, "Database unreachable"));
+            checks.push(CheckResult::new("clock_skew", Status::Skip, "Database unreachable"));
+        }
+    }
+    if let Some(pool) = pool {
+        pool.close().await;
+    }
+
+    checks.push(check_broker().await);
+    checks.push(check_redis().await);
+
+    DoctorReport::new(checks)
+}
+
+fn connection_failure(database_url: &str, error: &str) -> CheckResult {
+    CheckResult::new(
+        "database",
+        Status::Fail,
+        format!(
+            "Cannot connect to {}: {}",
+            crate::database::mask_password(database_url),
+            error
+        ),
+    )
+}
+
+/// Settings the server would reject at startup or silently replace
+fn check_config() -> CheckResult {
+    let vars: HashMap<String, String> = std::env::vars().collect();
+    let mut problems = config_problems(&vars);
+    let weak_tokens = weak_tokens(&vars);
+
+    if let Err(e) = crate::slo::SloConfig::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::database::DatabaseConfig::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::jobs::JobConfig::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::cache::TieredCache::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::middleware::http_cache::HttpCaching::from_env(Default::default()) {
+        problems.push(e);
+    }
+    if let Err(e) = crate::middleware::rate_limit::RateLimitBackend::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::middleware::proxy::TrustedProxies::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::handlers::ws::WebSocketConfig::from_env() {
+        problems.push(e);
+    }
+    if let Err(e) = crate::changes::Fanout::from_env() {
+        problems.push(e);
+    }
+
+    if !problems.is_empty() {
+        CheckResult::new("config", Status::Fail, problems.join("; "))
+    } else if !weak_tokens.is_empty() {
+        CheckResult::new(
+            "config",
+            Status::Warn,
+            format!(
+                "{} shorter than {} characters",
+                weak_tokens.join(", "),
+                MIN_TOKEN_LEN
+            ),
+        )
+    } else {
+        CheckResult::new("config", Status::Pass, "Environment is valid")
+    }
+}
+
+fn config_problems(vars: &HashMap<String, String>) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(port) = vars.get("PORT") {
+        if port.parse::<u16>().is_err() {
+            problems.push(format!("PORT is not a valid port: {}", port));
+        }
+    }
+    if let Some(addr) = vars.get("INTERNAL_ADDR") {
+        if addr.parse::<SocketAddr>().is_err() {
+            problems.push(format!("INTERNAL_ADDR is not host:port: {}", addr));
+        }
+    }
+    for name in NUMERIC_VARS {
+        if let Some(value) = vars.get(*name) {
+            if value.parse::<u64>().is_err() {
+                problems.push(format!("{} is not a number: {}", name, value));
+            }
+        }
+    }
+    if let Some(value) = vars.get("READ_ONLY_MODE") {
+        if !matches!(
+            value.to_ascii_lowercase().as_str(),
+            "true" | "1" | "false" | "0"
+        ) {
+            problems.push(format!("READ_ONLY_MODE is not a boolean: {}", value));
+        }
+    }
+
+    problems
+}
+
+fn weak_tokens(vars: &HashMap<String, String>) -> Vec<&'static str> {
+    ["ADMIN_TOKEN", "SCIM_BEARER_TOKEN"]
+        .into_iter()
+        .filter(|name| {
+            vars.get(*name)
+                .is_some_and(|token| !token.is_empty() && token.len() < MIN_TOKEN_LEN)
+        })
+        .collect()
+}
+
+/// Compare the migrations embedded in this binary with `_sqlx_migrations`
+async fn check_migrations(pool: &PgPool) -> CheckResult {
+    let migrator = sqlx::migrate!("./migrations");
+
+    let applied: Vec<(i64, Vec<u8>, bool)> =
+        match sqlx::query_as("SELECT version, checksum, success FROM _sqlx_migrations")
+            .fetch_all(pool)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => {
+                return CheckResult::new(
+                    "migrations",
+                    Status::Fail,
+                    "No migrations have been applied; run `sqlx migrate run`",
+                );
+            }
+            Err(e) => {
+                return CheckResult::new(
+                    "migrations",
+                    Status::Fail,
+                    format!("Failed to read migration history: {}", e),
+                )
+            }
+        };
+    let applied: HashMap<i64, (Vec<u8>, bool)> = applied
+        .into_iter()
+        .map(|(version, checksum, success)| (version, (checksum, success)))
+        .collect();
+
+    let mut pending = Vec::new();
+    let mut problems = Vec::new();
+    for migration in migrator.iter() {
+        match applied.get(&migration.version) {
+            None => pending.push(migration.version.to_string()),
+            Some((_, false)) => problems.push(format!(
+                "{} ({}) failed partway",
+                migration.version, migration.description
+            )),
+            Some((checksum, true)) if checksum.as_slice() != &*migration.checksum => problems
+                .push(format!(
+                    "{} ({}) was edited after it was applied",
+                    migration.version, migration.description
+                )),
+            Some(_) => {}
+        }
+    }
+    let unknown = applied
+        .keys()
+        .filter(|version| !migrator.iter().any(|m| m.version == **version))
+        .count();
+
+    if !problems.is_empty() {
+        CheckResult::new("migrations", Status::Fail, problems.join("; "))
+    } else if !pending.is_empty() {
+        CheckResult::new(
+            "migrations",
+            Status::Fail,
+            format!("Pending migrations: {}", pending.join(", ")),
+        )
+    } else if unknown > 0 {
+        CheckResult::new(
+            "migrations",
+            Status::Warn,
+            format!(
+                "Database has {} migration(s) newer than this build",
+                unknown
+            ),
+        )
+    } else {
+        CheckResult::new(
+            "migrations",
+            Status::Pass,
+            format!("{} migrations applied", migrator.iter().count()),
+        )
+    }
+}
+
+/// Compare the local clock with the database clock
+async fn check_clock_skew(pool: &PgPool) -> CheckResult {
+    let before = Utc::now();
+    let db_now: DateTime<Utc> = match sqlx::query_scalar("SELECT now()").fetch_one(pool).await {
+        Ok(now) => now,
+        Err(e) => {
+            return CheckResult::new(
+                "clock_skew",
+                Status::Fail,
+                format!("Failed to read database time: {}", e),
+            )
+        }
+    };
+    let after = Utc::now();
+
+    // Assume the database read its clock halfway through the round trip
+    let local = before + (after - before) / 2;
+    let skew = (local - db_now).abs().to_std().unwrap_or_default();
+    CheckResult::new(
+        "clock_skew",
+        skew_status(skew),
+        format!("{}ms from the database clock", skew.as_millis()),
+    )
+}
+
+fn skew_status(skew: Duration) -> Status {
+    if skew >= SKEW_FAIL {
+        Status::Fail
+    } else if skew >= SKEW_WARN {
+        Status::Warn
+    } else {
+        Status::Pass
+    }
+}
+
+/// TCP reachability of the NATS server or Kafka broker when the consumer
+/// or the publisher is configured
+async fn check_broker() -> CheckResult {
+    use crate::publisher::Broker;
+
+    let (var, url, default_port) = match (
+        crate::consumer::ConsumerConfig::from_env(),
+        crate::publisher::PublisherConfig::from_env(),
+    ) {
+        (Some(config), _) => ("NATS_URL", config.url, 4222),
+        (None, Ok(Some(config))) => match config.broker {
+            Broker::Nats { url, .. } => ("NATS_URL", url, 4222),
+            Broker::Kafka { brokers } => ("KAFKA_BROKERS", brokers, 9092),
+        },
+        (None, Err(e)) => return CheckResult::new("broker", Status::Fail, e),
+        (None, Ok(None)) => {
+            return CheckResult::new(
+                "broker",
+                Status::Skip,
+                "Neither CONSUMER_TOPICS nor EVENT_PUBLISHER is set",
+            )
+        }
+    };
+    let Some(addr) = broker_address(&url, default_port) else {
+        return CheckResult::new(
+            "broker",
+            Status::Fail,
+            format!("{} has no host: {}", var, url),
+        );
+    };
+
+    match tokio::time::timeout(CONNECT_TIMEOUT, tokio::net::TcpStream::connect(&addr)).await {
+        Ok(Ok(_)) => CheckResult::new("broker", Status::Pass, format!("Reached {}", addr)),
+        Ok(Err(e)) => CheckResult::new(
+            "broker",
+            Status::Fail,
+            format!("Cannot reach {}: {}", addr, e),
+        ),
+        Err(_) => CheckResult::new(
+            "broker",
+            Status::Fail,
+            format!("Timed out reaching {}", addr),
+        ),
+    }
+}
+
+/// `host:port` of the first server in a NATS URL or Kafka broker list
+fn broker_address(url: &str, default_port: u16) -> Option<String> {
+    let server = url.split(',').next()?.trim();
+    let server = server.split_once("://").map_or(server, |(_, rest)| rest);
+    let server = server.rsplit_once('@').map_or(server, |(_, host)| host);
+    let server = server.trim_end_matches('/');
+    if server.is_empty() {
+        return None;
+    }
+    Some(if server.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        server.to_string()
+    } else {
+        format!("{}:{}", server, default_port)
+    })
+}
+
+/// Round trip to Redis when REDIS_URL is set
+async fn check_redis() -> CheckResult {
+    let Some(url) = std::env::var("REDIS_URL").ok().filter(|url| !url.is_empty()) else {
+        return CheckResult::new("redis", Status::Skip, "REDIS_URL is not set");
+    };
+    let Some(addr) = redis_address(&url) else {
+        return CheckResult::new("redis", Status::Fail, format!("REDIS_URL has no host: {}", url));
+    };
+    let redis = match crate::cache::redis::RedisCache::new(&url, CONNECT_TIMEOUT) {
+        Ok(redis) => redis,
+        Err(e) => return CheckResult::new("redis", Status::Fail, e),
+    };
+
+    match tokio::time::timeout(CONNECT_TIMEOUT, redis.ping()).await {
+        Ok(Ok(())) => CheckResult::new("redis", Status::Pass, format!("PING to {} answered", addr)),
+        Ok(Err(e)) => CheckResult::new(
+            "redis",
+            Status::Fail,
+            format!("Cannot reach {}: {}", addr, e),
+        ),
+        Err(_) => CheckResult::new(
+            "redis",
+            Status::Fail,
+            format!("Timed out reaching {}", addr),
+        ),
+    }
+}
+
+/// `host:port` of a Redis URL, without credentials or database number
+fn redis_address(url: &str) -> Option<String> {
+    let server = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let server = server.split(['/', '?']).next().unwrap_or_default();
+    let server = server.rsplit_once('@').map_or(server, |(_, host)| host);
+    if server.is_empty() {
+        return None;
+    }
+    Some(if server.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) {
+        server.to_string()
+    } else {
+        format!("{}:6379", server)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_config_problems_name_each_bad_setting() {
+        assert!(config_problems(&vars(&[("PORT", "3000"), ("READ_ONLY_MODE", "false")])).is_empty());
+
+        let problems = config_problems(&vars(&[
+            ("PORT", "http"),
+            ("INTERNAL_ADDR", "localhost"),
+            ("ADMISSION_READ_CONCURRENCY", "ten"),
+            ("READ_ONLY_MODE", "yes"),
+        ]));
+        assert_eq!(problems.len(), 4);
+        assert!(problems.iter().any(|p| p.starts_with("ADMISSION_READ_CONCURRENCY")));
+
+        assert_eq!(
+            weak_tokens(&vars(&[("ADMIN_TOKEN", "short"), ("SCIM_BEARER_TOKEN", "")])),
+            vec!["ADMIN_TOKEN"]
+        );
+    }
+
+    #[test]
+    fn test_broker_address_defaults_port_and_strips_credentials() {
+        assert_eq!(broker_address("nats://localhost:4222", 4222).as_deref(), Some("localhost:4222"));
+        assert_eq!(broker_address("nats://user:pw@nats", 4222).as_deref(), Some("nats:4222"));
+        assert_eq!(
+            broker_address("nats://a:4223,nats://b:4223", 4222).as_deref(),
+            Some("a:4223")
+        );
+        assert_eq!(broker_address("kafka-1,kafka-2", 9092).as_deref(), Some("kafka-1:9092"));
+        assert_eq!(broker_address("nats://", 4222), None);
+    }
+
+    #[test]
+    fn test_redis_address_defaults_port_and_strips_credentials() {
+        assert_eq!(redis_address("redis://localhost:6380").as_deref(), Some("localhost:6380"));
+        assert_eq!(redis_address("redis://:secret@cache/2").as_deref(), Some("cache:6379"));
+        assert_eq!(
+            redis_address("rediss://user:pw@cache.example.com:6390/0?protocol=resp3").as_deref(),
+            Some("cache.example.com:6390")
+        );
+        assert_eq!(redis_address("redis:///1"), None);
+    }
+
+    #[test]
+    fn test_report_fails_only_on_failed_checks() {
+        assert_eq!(skew_status(Duration::from_millis(200)), Status::Pass);
+        assert_eq!(skew_status(Duration::from_secs(2)), Status::Warn);
+        assert_eq!(skew_status(Duration::from_secs(30)), Status::Fail);
+
+        let report = DoctorReport::new(vec![
+            CheckResult::new("config", Status::Warn, "weak token"),
+            CheckResult::new("broker", Status::Skip, "not configured"),
+        ]);
+        assert!(report.passed);
+
+        let report = DoctorReport::new(vec![CheckResult::new("database", Status::Fail, "down")]);
+        assert!(!report.passed);
+        assert!(report.render().contains("FAIL  database"));
+    }
+}
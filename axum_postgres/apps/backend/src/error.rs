@@ -1,9 +1,28 @@
+//! Errors of the API, answered as RFC 7807 problem details
+//!
+//! Every error body is an [`ErrorResponse`] sent as
+//! `application/problem+json`: `type` and `code` identify the kind of
+//! problem and stay stable across releases, while `detail` is meant for
+//! people and may change.
+
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::models::user::{ErrorResponse, FieldError};
+
+/// Media type of error bodies
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Start of the `type` of every problem; the rest is the code in kebab case
+pub const PROBLEM_TYPE_BASE: &str = "/problems/";
+
+/// SQLSTATE codes of the constraint violations that are the caller's fault
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
 
 /// Application error type
 #[derive(Debug)]
@@ -12,33 +31,319 @@ pub enum AppError {
     InternalServerError(String),
     /// Bad request error  
     BadRequest(String),
+    /// Request body or query failed validation, field by field
+    Validation(ValidationErrors),
     /// Not found error
     NotFound(String),
+    /// No live user has the requested ID
+    UserNotFound,
+    /// Missing or invalid credentials
+    Unauthorized(String),
+    /// Signed in, but not allowed to do this
+    Forbidden(String),
+    /// Mutation rejected because read-only mode is on
+    ReadOnly,
+    /// Request shed by admission control; retry after the given seconds
+    Overloaded { retry_after_secs: u64 },
+    /// Too many requests from the same client or for the same resource;
+    /// retry after the given seconds
+    TooManyRequests { retry_after_secs: u64 },
+    /// `Range` starts past the end of a collection of `total` items
+    RangeNotSatisfiable { total: i64 },
+    /// `If-Match` no longer matches the resource's current ETag
+    PreconditionFailed(String),
+    /// Another live user already has the email, in any letter case
+    EmailTaken,
+    /// The change clashes with existing data, such as a unique value
+    /// already in use or a reference to a row that does not exist
+    Conflict(String),
+    /// The database cannot be reached or is refusing connections; retry
+    /// after the given seconds
+    DatabaseUnavailable { retry_after_secs: u64 },
+    /// The request was not answered within REQUEST_TIMEOUT_SECS
+    RequestTimeout,
+    /// The request body is larger than `limit` bytes
+    PayloadTooLarge { limit: usize },
+    /// The route does not answer `method`, only the `allowed` ones
+    MethodNotAllowed {
+        method: String,
+        allowed: Vec<&'static str>,
+    },
+    /// An upstream service, such as an OAuth provider, failed or answered
+    /// with something unusable
+    BadGateway(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::InternalServerError(msg) => {
-                tracing::error!("Internal server error: {}", msg);
-                (StatusCode::INTERNAL_SERVER_ERROR, msg)
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::BadRequest(_) | AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) | AppError::UserNotFound => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::ReadOnly
+            | AppError::Overloaded { .. }
+            | AppError::DatabaseUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::RangeNotSatisfiable { .. } => StatusCode::RANGE_NOT_SATISFIABLE,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::EmailTaken | AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+            AppError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::MethodNotAllowed { .. } => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// Stable machine-readable code, e.g. `USER_NOT_FOUND`
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InternalServerError(_) => "INTERNAL_ERROR",
+            AppError::BadRequest(_) => "BAD_REQUEST",
+            AppError::Validation(_) => "VALIDATION_FAILED",
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::UserNotFound => "USER_NOT_FOUND",
+            AppError::Unauthorized(_) => "UNAUTHORIZED",
+            AppError::Forbidden(_) => "FORBIDDEN",
+            AppError::ReadOnly => "READ_ONLY_MODE",
+            AppError::Overloaded { .. } => "OVERLOADED",
+            AppError::TooManyRequests { .. } => "RATE_LIMITED",
+            AppError::RangeNotSatisfiable { .. } => "RANGE_NOT_SATISFIABLE",
+            AppError::PreconditionFailed(_) => "PRECONDITION_FAILED",
+            AppError::EmailTaken => "EMAIL_TAKEN",
+            AppError::Conflict(_) => "CONFLICT",
+            AppError::DatabaseUnavailable { .. } => "DATABASE_UNAVAILABLE",
+            AppError::RequestTimeout => "REQUEST_TIMEOUT",
+            AppError::PayloadTooLarge { .. } => "PAYLOAD_TOO_LARGE",
+            AppError::MethodNotAllowed { .. } => "METHOD_NOT_ALLOWED",
+            AppError::BadGateway(_) => "BAD_GATEWAY",
+        }
+    }
+
+    /// Explanation for people, the `detail` of the body
+    fn detail(self) -> String {
+        match self {
+            AppError::InternalServerError(msg)
+            | AppError::BadRequest(msg)
+            | AppError::NotFound(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::PreconditionFailed(msg)
+            | AppError::Conflict(msg)
+            | AppError::BadGateway(msg) => msg,
+            AppError::Validation(errors) => format!(
+                "Validation errors: {}",
+                field_errors(&errors)
+                    .iter()
+                    .map(|error| format!("{}: {}", error.field, error.message))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            AppError::UserNotFound => "User not found".to_string(),
+            AppError::ReadOnly => "Service is in read-only mode".to_string(),
+            AppError::Overloaded { .. } => "Server is overloaded, retry later".to_string(),
+            AppError::TooManyRequests { .. } => "Too many requests, retry later".to_string(),
+            AppError::RangeNotSatisfiable { total } => {
+                format!("Range starts past the last of {} items", total)
             }
-            AppError::BadRequest(msg) => {
-                tracing::warn!("Bad request: {}", msg);
-                (StatusCode::BAD_REQUEST, msg)
+            AppError::EmailTaken => "Email address already exists".to_string(),
+            AppError::DatabaseUnavailable { .. } => {
+                "Database is unavailable, retry later".to_string()
             }
-            AppError::NotFound(msg) => {
-                tracing::info!("Not found: {}", msg);
-                (StatusCode::NOT_FOUND, msg)
+            AppError::RequestTimeout => "Request took too long to process".to_string(),
+            AppError::PayloadTooLarge { limit } => {
+                format!("Request body is larger than {} bytes", limit)
             }
+            AppError::MethodNotAllowed { method, allowed } => format!(
+                "{} is not allowed here; use one of {}",
+                method,
+                allowed.join(", ")
+            ),
+        }
+    }
+}
+
+/// Classify database errors by SQLSTATE and kind rather than by message,
+/// which depends on the server's locale
+///
+/// Unique violations of an email index become [`AppError::EmailTaken`],
+/// other constraint violations [`AppError::Conflict`], and lost or refused
+/// connections [`AppError::DatabaseUnavailable`]. Callers log the error
+/// itself, with their context, before converting it.
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::Database(db) => match db.code().as_deref() {
+                Some(UNIQUE_VIOLATION)
+                    if db.constraint().is_some_and(|name| name.contains("email")) =>
+                {
+                    AppError::EmailTaken
+                }
+                Some(UNIQUE_VIOLATION) => AppError::Conflict("Resource already exists".to_string()),
+                Some(FOREIGN_KEY_VIOLATION) => {
+                    AppError::Conflict("Referenced resource does not exist".to_string())
+                }
+                Some(code) if is_connection_failure(code) => database_unavailable(),
+                _ => AppError::InternalServerError("Database error".to_string()),
+            },
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed => database_unavailable(),
+            _ => AppError::InternalServerError("Database error".to_string()),
+        }
+    }
+}
+
+/// Retry once the circuit of `repository::resilience` lets requests through
+fn database_unavailable() -> AppError {
+    AppError::DatabaseUnavailable {
+        retry_after_secs: crate::repository::resilience::retry_after_secs(),
+    }
+}
+
+/// Connection exceptions (class 08), too many connections, and the server
+/// shutting down or restarting (class 57P)
+fn is_connection_failure(code: &str) -> bool {
+    code.starts_with("08") || code.starts_with("57P") || code == "53300"
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::Validation(errors)
+    }
+}
+
+/// `type` of the problem with this code
+pub fn problem_type(code: &str) -> String {
+    format!(
+        "{}{}",
+        PROBLEM_TYPE_BASE,
+        code.to_ascii_lowercase().replace('_', "-")
+    )
+}
+
+/// Every error of every invalid field, by field name
+///
+/// Fields of nested structs and lists are named by their path, such as
+/// `address.city` or `emails[1].value`.
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields = Vec::new();
+    collect_field_errors("", errors, &mut fields);
+    // Stable sort keeps the errors of one field in the order validated
+    fields.sort_by(|a, b| a.field.cmp(&b.field));
+    fields
+}
+
+fn collect_field_errors(prefix: &str, errors: &ValidationErrors, out: &mut Vec<FieldError>) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                out.extend(errors.iter().map(|error| FieldError {
+                    field: path.clone(),
+                    code: field_error_code(error),
+                    message: error.to_string(),
+                }));
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(&path, errors, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(&format!("{}[{}]", path, index), errors, out);
+                }
+            }
+        }
+    }
+}
+
+/// Snake case code of a field error, e.g. `invalid_email` or `too_short`
+///
+/// Length errors say which bound was broken; every other validator or
+/// custom code `x` becomes `invalid_x`.
+fn field_error_code(error: &ValidationError) -> String {
+    if error.code == "length" {
+        let length = error.params.get("value").and_then(|value| match value {
+            serde_json::Value::String(text) => Some(text.chars().count() as u64),
+            serde_json::Value::Array(items) => Some(items.len() as u64),
+            _ => None,
+        });
+        let min = error.params.get("min").and_then(serde_json::Value::as_u64);
+        return match (length, min) {
+            (Some(length), Some(min)) if length < min => "too_short".to_string(),
+            _ => "too_long".to_string(),
+        };
+    }
+    format!("invalid_{}", error.code)
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match &self {
+            AppError::InternalServerError(msg) => tracing::error!("Internal server error: {}", msg),
+            AppError::BadRequest(msg) => tracing::warn!("Bad request: {}", msg),
+            AppError::Validation(errors) => tracing::warn!("Validation failed: {}", errors),
+            AppError::NotFound(msg) => tracing::info!("Not found: {}", msg),
+            AppError::UserNotFound => tracing::info!("Not found: User not found"),
+            AppError::Forbidden(msg) => tracing::warn!("Forbidden: {}", msg),
+            AppError::Conflict(msg) => tracing::info!("Conflict: {}", msg),
+            AppError::DatabaseUnavailable { .. } => tracing::error!("Database is unavailable"),
+            AppError::RequestTimeout => tracing::warn!("Request timed out"),
+            AppError::BadGateway(msg) => tracing::error!("Bad gateway: {}", msg),
+            _ => {}
+        }
+        let retry_after = match &self {
+            AppError::Overloaded { retry_after_secs }
+            | AppError::TooManyRequests { retry_after_secs }
+            | AppError::DatabaseUnavailable { retry_after_secs } => Some(*retry_after_secs),
+            _ => None,
+        };
+        let allow = match &self {
+            AppError::MethodNotAllowed { allowed, .. } => Some(allowed.join(", ")),
+            _ => None,
+        };
+        let content_range = match &self {
+            AppError::RangeNotSatisfiable { total } => {
+                Some(crate::pagination::unsatisfied_range(*total))
+            }
+            _ => None,
         };
 
-        let body = Json(json!({
-            "success": false,
-            "message": error_message,
-        }));
+        let status = self.status();
+        let code = self.code();
+        let errors = match &self {
+            AppError::Validation(errors) => Some(field_errors(errors)),
+            _ => None,
+        };
+        let body = ErrorResponse {
+            problem_type: problem_type(code),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: self.detail(),
+            code: code.to_string(),
+            errors,
+            // Added by middleware::request_id, which knows the request
+            request_id: None,
+        };
 
-        (status, body).into_response()
+        let mut response = (status, Json(body)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        if let Some(secs) = retry_after {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        if let Some(content_range) = content_range {
+            headers.insert(header::CONTENT_RANGE, content_range);
+        }
+        if let Some(allow) = allow.and_then(|allow| HeaderValue::from_str(&allow).ok()) {
+            headers.insert(header::ALLOW, allow);
+        }
+        response
     }
 }
 
@@ -47,9 +352,87 @@ impl std::fmt::Display for AppError {
         match self {
             AppError::InternalServerError(msg) => write!(f, "Internal server error: {}", msg),
             AppError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            AppError::Validation(errors) => write!(f, "Validation failed: {}", errors),
             AppError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            AppError::UserNotFound => write!(f, "Not found: User not found"),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            AppError::ReadOnly => write!(f, "Service is in read-only mode"),
+            AppError::Overloaded { .. } => write!(f, "Server is overloaded"),
+            AppError::TooManyRequests { .. } => write!(f, "Too many requests"),
+            AppError::RangeNotSatisfiable { total } => {
+                write!(f, "Range not satisfiable for {} items", total)
+            }
+            AppError::PreconditionFailed(msg) => write!(f, "Precondition failed: {}", msg),
+            AppError::EmailTaken => write!(f, "Email address already exists"),
+            AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            AppError::DatabaseUnavailable { .. } => write!(f, "Database is unavailable"),
+            AppError::RequestTimeout => write!(f, "Request timed out"),
+            AppError::PayloadTooLarge { limit } => {
+                write!(f, "Request body is larger than {} bytes", limit)
+            }
+            AppError::MethodNotAllowed { method, .. } => {
+                write!(f, "Method {} not allowed", method)
+            }
+            AppError::BadGateway(msg) => write!(f, "Bad gateway: {}", msg),
         }
     }
 }
 
-impl std::error::Error for AppError {}
\ No newline at end of file
+impl std::error::Error for AppError {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lost_connections_are_unavailable() {
+        for e in [sqlx::Error::PoolTimedOut, sqlx::Error::PoolClosed] {
+            let error = AppError::from(e);
+            assert_eq!(error.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(error.code(), "DATABASE_UNAVAILABLE");
+        }
+        assert!(matches!(
+            AppError::from(sqlx::Error::RowNotFound),
+            AppError::InternalServerError(_)
+        ));
+
+        assert!(is_connection_failure("08006"));
+        assert!(is_connection_failure("57P01"));
+        assert!(is_connection_failure("53300"));
+        assert!(!is_connection_failure(UNIQUE_VIOLATION));
+    }
+
+    #[test]
+    fn test_field_errors_carry_codes_and_paths() {
+        let mut address = ValidationErrors::new();
+        address.add("city", ValidationError::new("length"));
+        let mut errors = ValidationErrors::new();
+        let mut email = ValidationError::new("email");
+        email.message = Some("Invalid email format".into());
+        errors.add("email", email);
+        let mut name = ValidationError::new("length");
+        name.add_param("min".into(), &1);
+        name.add_param("value".into(), &"");
+        errors.add("name", name);
+        errors.add("name", ValidationError::new("profanity"));
+        errors
+            .errors_mut()
+            .insert("address", ValidationErrorsKind::Struct(Box::new(address)));
+
+        let fields = field_errors(&errors);
+        let summary: Vec<(&str, &str)> = fields
+            .iter()
+            .map(|error| (error.field.as_str(), error.code.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("address.city", "too_long"),
+                ("email", "invalid_email"),
+                ("name", "too_short"),
+                ("name", "invalid_profanity"),
+            ]
+        );
+        assert_eq!(fields[1].message, "Invalid email format");
+    }
+}
@@ -0,0 +1,33 @@
+//! Versioned domain events
+//!
+//! Every event has a stable name (`user.created`) and an explicit schema
+//! version. Consumers address a contract as `name@vN`, e.g. `user.created@v1`.
+//!
+//! [`crate::publisher::Dispatcher`] builds every envelope it publishes with
+//! [`EventRegistry::envelopes`], so payloads are validated before they are
+//! published, and [`crate::consumer::Consumer`] validates what it receives.
+
+pub mod registry;
+pub mod user;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub use registry::{EventEnvelope, EventError, EventRegistry};
+
+/// A domain event payload with a fixed name and schema version
+pub trait DomainEvent: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Event name shared by every version, e.g. `user.created`
+    const NAME: &'static str;
+    /// Version of this payload shape
+    const VERSION: u32;
+
+    /// Schema identifier in `name@vN` form
+    fn schema_id() -> String {
+        schema_id(Self::NAME, Self::VERSION)
+    }
+}
+
+/// Format a schema identifier in `name@vN` form
+pub fn schema_id(name: &str, version: u32) -> String {
+    format!("{}@v{}", name, version)
+}
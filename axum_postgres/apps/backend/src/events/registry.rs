@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::user::{UserCreatedV1, UserDeletedV1, UserUpdatedV1};
+use super::{schema_id, DomainEvent};
+
+/// Serialized event ready to hand to a publisher
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub event_type: String,
+    pub version: u32,
+    pub occurred_at: DateTime<Utc>,
+    pub payload: Value,
+}
+
+impl EventEnvelope {
+    /// Schema identifier in `name@vN` form
+    pub fn schema_id(&self) -> String {
+        schema_id(&self.event_type, self.version)
+    }
+}
+
+/// Event registry error type
+#[derive(Debug)]
+pub enum EventError {
+    /// No schema registered under this `name@vN`
+    UnknownSchema(String),
+    /// Payload does not match the registered schema
+    InvalidPayload { schema: String, reason: String },
+}
+
+impl std::fmt::Display for EventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventError::UnknownSchema(schema) => write!(f, "Unknown event schema: {}", schema),
+            EventError::InvalidPayload { schema, reason } => {
+                write!(f, "Invalid payload for {}: {}", schema, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EventError {}
+
+type Validator = fn(&Value) -> Result<(), serde_json::Error>;
+type Downgrade = Box<dyn Fn(&Value) -> Result<Value, serde_json::Error> + Send + Sync>;
+
+/// Registry of known event schemas
+///
+/// `envelopes` validates payloads against their registered schema before
+/// the publisher sends them, and `validate` checks received ones. During a
+/// schema migration, register a downgrade from the new version to the old
+/// one and both versions are emitted for every event until the downgrade is
+/// removed.
+#[derive(Default)]
+pub struct EventRegistry {
+    validators: HashMap<String, Validator>,
+    downgrades: HashMap<String, Vec<(u32, Downgrade)>>,
+}
+
+impl EventRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with every domain event defined by the application
+    pub fn with_domain_events() -> Self {
+        let mut registry = Self::new();
+        registry.register::<UserCreatedV1>();
+        registry.register::<UserUpdatedV1>();
+        registry.register::<UserDeletedV1>();
+        registry
+    }
+
+    /// Register an event schema
+    pub fn register<E: DomainEvent>(&mut self) {
+        self.validators.insert(E::schema_id(), validate_as::<E>);
+    }
+
+    /// Also emit `To` whenever `From` is emitted
+    ///
+    /// Both versions must share the same event name.
+    pub fn register_downgrade<From, To>(&mut self)
+    where
+        From: DomainEvent,
+        To: DomainEvent + for<'a> std::convert::From<&'a From>,
+    {
+        assert_eq!(From::NAME, To::NAME, "downgrades must keep the event name");
+        self.register::<To>();
+
+        let convert: Downgrade = Box::new(|value| {
+            let newer = From::deserialize(value)?;
+            serde_json::to_value(To::from(&newer))
+        });
+        self.downgrades
+            .entry(From::schema_id())
+            .or_default()
+            .push((To::VERSION, convert));
+    }
+
+    /// Whether a schema is registered
+    pub fn is_registered(&self, schema: &str) -> bool {
+        self.validators.contains_key(schema)
+    }
+
+    /// Validate an envelope against its registered schema
+    pub fn validate(&self, envelope: &EventEnvelope) -> Result<(), EventError> {
+        let schema = envelope.schema_id();
+        let validator = self
+            .validators
+            .get(&schema)
+            .ok_or_else(|| EventError::UnknownSchema(schema.clone()))?;
+
+        validator(&envelope.payload).map_err(|e| EventError::InvalidPayload {
+            schema,
+            reason: e.to_string(),
+        })
+    }
+
+    /// Build validated envelopes for an event, one per emitted schema version
+    pub fn envelopes<E: DomainEvent>(&self, event: &E) -> Result<Vec<EventEnvelope>, EventError> {
+        let occurred_at = Utc::now();
+        let payload = serde_json::to_value(event).map_err(|e| EventError::InvalidPayload {
+            schema: E::schema_id(),
+            reason: e.to_string(),
+        })?;
+
+        let primary = EventEnvelope {
+            event_type: E::NAME.to_string(),
+            version: E::VERSION,
+            occurred_at,
+            payload,
+        };
+        self.validate(&primary)?;
+
+        let mut envelopes = Vec::new();
+        for (version, convert) in self.downgrades.get(&E::schema_id()).into_iter().flatten() {
+            let payload = convert(&primary.payload).map_err(|e| EventError::InvalidPayload {
+                schema: schema_id(E::NAME, *version),
+                reason: e.to_string(),
+            })?;
+
+            let envelope = EventEnvelope {
+                event_type: E::NAME.to_string(),
+                version: *version,
+                occurred_at,
+                payload,
+            };
+            self.validate(&envelope)?;
+            envelopes.push(envelope);
+        }
+        envelopes.insert(0, primary);
+
+        Ok(envelopes)
+    }
+}
+
+fn validate_as<E: DomainEvent>(value: &Value) -> Result<(), serde_json::Error> {
+    E::deserialize(value).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Hypothetical next version used to exercise multi-version emission
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct UserDeletedV2 {
+        user_id: String,
+        reason: String,
+    }
+
+    impl DomainEvent for UserDeletedV2 {
+        const NAME: &'static str = "user.deleted";
+        const VERSION: u32 = 2;
+    }
+
+    impl From<&UserDeletedV2> for UserDeletedV1 {
+        fn from(event: &UserDeletedV2) -> Self {
+            Self {
+                user_id: event.user_id.clone(),
+            }
+        }
+    }
+
+    fn envelope(event_type: &str, version: u32, payload: Value) -> EventEnvelope {
+        EventEnvelope {
+            event_type: event_type.to_string(),
+            version,
+            occurred_at: Utc::now(),
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_validate_accepts_registered_payload() {
+        let registry = EventRegistry::with_domain_events();
+        let valid = envelope("user.deleted", 1, json!({"user_id": "7"}));
+        assert!(registry.validate(&valid).is_ok());
+        assert_eq!(valid.schema_id(), "user.deleted@v1");
+    }
+
+    #[test]
+    fn test_validate_rejects_unknown_schema_and_bad_payload() {
+        let registry = EventRegistry::with_domain_events();
+
+        let unknown = envelope("user.deleted", 9, json!({"user_id": "7"}));
+        assert!(matches!(
+            registry.validate(&unknown),
+            Err(EventError::UnknownSchema(_))
+        ));
+
+        let missing_field = envelope("user.created", 1, json!({"user_id": "7"}));
+        assert!(matches!(
+            registry.validate(&missing_field),
+            Err(EventError::InvalidPayload { .. })
+        ));
+
+        let extra_field = envelope("user.deleted", 1, json!({"user_id": "7", "extra": true}));
+        assert!(registry.validate(&extra_field).is_err());
+    }
+
+    #[test]
+    fn test_envelopes_require_registration() {
+        let registry = EventRegistry::new();
+        let result = registry.envelopes(&UserDeletedV1 {
+            user_id: "7".to_string(),
+        });
+        assert!(matches!(result, Err(EventError::UnknownSchema(_))));
+    }
+
+    #[test]
+    fn test_downgrade_emits_every_version() {
+        let mut registry = EventRegistry::with_domain_events();
+        registry.register::<UserDeletedV2>();
+        registry.register_downgrade::<UserDeletedV2, UserDeletedV1>();
+
+        let event = UserDeletedV2 {
+            user_id: "7".to_string(),
+            reason: "requested".to_string(),
+        };
+        let envelopes = registry
+            .envelopes(&event)
+            .expect("Failed to build envelopes");
+
+        let schemas: Vec<String> = envelopes.iter().map(|e| e.schema_id()).collect();
+        assert_eq!(schemas, vec!["user.deleted@v2", "user.deleted@v1"]);
+        assert_eq!(envelopes[1].payload, json!({"user_id": "7"}));
+        assert_eq!(envelopes[0].occurred_at, envelopes[1].occurred_at);
+    }
+}
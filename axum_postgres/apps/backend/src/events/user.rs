@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::DomainEvent;
+use crate::models::user::User;
+use crate::models::user_id::UserId;
+
+/// `user.created@v1` - a user was created
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UserCreatedV1 {
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub active: bool,
+    pub created_at: String,
+}
+
+/// `user.updated@v1` - a user's fields changed; carries the new state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UserUpdatedV1 {
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub active: bool,
+}
+
+/// `user.deleted@v1` - a user was deleted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct UserDeletedV1 {
+    pub user_id: String,
+}
+
+impl DomainEvent for UserCreatedV1 {
+    const NAME: &'static str = "user.created";
+    const VERSION: u32 = 1;
+}
+
+impl DomainEvent for UserUpdatedV1 {
+    const NAME: &'static str = "user.updated";
+    const VERSION: u32 = 1;
+}
+
+impl DomainEvent for UserDeletedV1 {
+    const NAME: &'static str = "user.deleted";
+    const VERSION: u32 = 1;
+}
+
+impl From<&User> for UserCreatedV1 {
+    fn from(user: &User) -> Self {
+        Self {
+            user_id: user.user_id().to_string(),
+            name: user.name.clone(),
+            email: user.email.clone(),
+            active: user.active,
+            created_at: user.created_at.to_rfc3339(),
+        }
+    }
+}
+
+impl From<&User> for UserUpdatedV1 {
+    fn from(user: &User) -> Self {
+        Self {
+            user_id: user.user_id().to_string(),
+            name: user.name.clone(),
+            email: user.email.clone(),
+            active: user.active,
+        }
+    }
+}
+
+impl UserDeletedV1 {
+    pub fn new(user_id: UserId) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! NDJSON user export
+//!
+//! `GET /api/users/export?format=ndjson` streams every matching user as one
+//! JSON object per line, in id order, straight from a database cursor. Rows
+//! are serialized into chunks of about [`CHUNK_BYTES`] and handed to the
+//! response body through a channel of [`CHUNKS_IN_FLIGHT`] chunks: when the
+//! client reads slower than the database answers, the channel fills up and
+//! the cursor waits, so memory stays bounded however many rows there are.
+
+use std::io;
+
+use axum::body::{Body, Bytes};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use utoipa::IntoParams;
+
+use crate::database::DbPools;
+use crate::models::user::{User, UserListQuery};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+use crate::tenant;
+
+/// Content type of newline-delimited JSON
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// A chunk is sent once it holds at least this many bytes
+pub const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Chunks buffered between the cursor and the client
+pub const CHUNKS_IN_FLIGHT: usize = 4;
+
+/// Serializations an export can be written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One JSON object per line
+    #[default]
+    Ndjson,
+}
+
+/// Format of `GET /api/users/export`
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Only `ndjson`, the default
+    #[param(value_type = Option<String>, example = "ndjson")]
+    pub format: Option<ExportFormat>,
+}
+
+/// Chunks of the export, read on a task of their own
+pub type Chunks = mpsc::Receiver<Result<Bytes, io::Error>>;
+
+/// Start exporting the users matching `query`
+///
+/// The first chunk can be awaited before answering, so a database that
+/// fails at once still gets an error status; later failures cut the body
+/// short.
+pub fn export_users(pools: DbPools, query: UserListQuery) -> Chunks {
+    let (sender, receiver) = mpsc::channel(CHUNKS_IN_FLIGHT);
+    // Built before spawning, so it is scoped to the tenant of the request;
+    // the task runs in that tenant too, for the connections it acquires
+    let repo = UserRepository::new(pools);
+    tokio::spawn(tenant::scope(tenant::current(), async move {
+        let rows = repo
+            .stream_users(&query)
+            .map(|row| row.map(User::to_response));
+        let sent = send_chunks(rows, CHUNK_BYTES, &sender).await;
+        tracing::info!("Exported {} users", sent);
+    }));
+    receiver
+}
+
+/// Response body of the chunks, starting with one already received
+pub fn body(first: Option<Result<Bytes, io::Error>>, chunks: Chunks) -> Body {
+    let rest = futures::stream::unfold(chunks, |mut chunks| async move {
+        chunks.recv().await.map(|chunk| (chunk, chunks))
+    });
+    Body::from_stream(futures::stream::iter(first).chain(rest))
+}
+
+/// Write `rows` as NDJSON in chunks of at least `chunk_bytes`, each sent
+/// once the receiver has room for it; returns the rows sent
+///
+/// Stops at the first error, after sending it, or when the receiver is
+/// dropped because the client went away.
+async fn send_chunks<T: Serialize>(
+    rows: impl Stream<Item = Result<T, sqlx::Error>>,
+    chunk_bytes: usize,
+    sender: &mpsc::Sender<Result<Bytes, io::Error>>,
+) -> usize {
+    let mut rows = std::pin::pin!(rows);
+    let mut chunk = Vec::with_capacity(chunk_bytes);
+    let mut sent = 0;
+    let mut in_chunk = 0;
+    while let Some(row) = rows.next().await {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                tracing::error!("Database error exporting users: {:?}", e);
+                let _ = sender.send(Err(io::Error::other(e))).await;
+                return sent;
+            }
+        };
+        if let Err(e) = serde_json::to_writer(&mut chunk, &row) {
+            tracing::error!("Failed to serialize an exported row: {}", e);
+            let _ = sender.send(Err(io::Error::other(e))).await;
+            return sent;
+        }
+        chunk.push(b'\n');
+        in_chunk += 1;
+
+        if chunk.len() >= chunk_bytes {
+            let full = std::mem::replace(&mut chunk, Vec::with_capacity(chunk_bytes));
+            if sender.send(Ok(full.into())).await.is_err() {
+                tracing::info!("Export abandoned by the client");
+                return sent;
+            }
+            sent += in_chunk;
+            in_chunk = 0;
+        }
+    }
+    if !chunk.is_empty() && sender.send(Ok(chunk.into())).await.is_ok() {
+        sent += in_chunk;
+    }
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rows_are_sent_in_chunks() {
+        let rows = futures::stream::iter((1..=5).map(|id| Ok(serde_json::json!({ "id": id }))));
+        // Room for one chunk: the writer waits for the reader below
+        let (sender, mut receiver) = mpsc::channel(1);
+        let writer = tokio::spawn(async move { send_chunks(rows, 16, &sender).await });
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(String::from_utf8(chunk.unwrap().to_vec()).unwrap());
+        }
+        assert_eq!(writer.await.unwrap(), 5);
+        assert_eq!(
+            chunks,
+            vec![
+                "{\"id\":1}\n{\"id\":2}\n",
+                "{\"id\":3}\n{\"id\":4}\n",
+                "{\"id\":5}\n"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_an_error_ends_the_export() {
+        let rows = futures::stream::iter(vec![
+            Ok(serde_json::json!({ "id": 1 })),
+            Err(sqlx::Error::PoolTimedOut),
+            Ok(serde_json::json!({ "id": 2 })),
+        ]);
+        let (sender, mut receiver) = mpsc::channel(4);
+        assert_eq!(send_chunks(rows, CHUNK_BYTES, &sender).await, 0);
+        drop(sender);
+
+        assert!(receiver.recv().await.unwrap().is_err());
+        assert!(receiver.recv().await.is_none());
+    }
+}
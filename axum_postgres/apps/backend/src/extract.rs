@@ -0,0 +1,48 @@
+//! Extractors that reject with the API's error shape
+//!
+//! axum's own rejections answer in plain text. These wrappers behave the same
+//! but fail with [`AppError`], so a malformed request gets the usual JSON
+//! error body.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{
+        path::{ErrorKind, Path as AxumPath},
+        rejection::PathRejection,
+        FromRequestParts,
+    },
+    http::request::Parts,
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+
+/// `axum::extract::Path` that rejects with [`AppError`]
+///
+/// Values that do not deserialize answer 400 with the deserializer's
+/// message, e.g. "Invalid user ID format" for a [`crate::models::user_id::UserId`].
+#[derive(Debug, Clone, Copy)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match AxumPath::<T>::from_request_parts(parts, state).await {
+            Ok(AxumPath(value)) => Ok(Self(value)),
+            Err(PathRejection::FailedToDeserializePathParams(e)) => match e.kind() {
+                ErrorKind::Message(message) => Err(AppError::BadRequest(message.clone())),
+                ErrorKind::WrongNumberOfParameters { .. } | ErrorKind::UnsupportedType { .. } => {
+                    Err(AppError::InternalServerError(e.body_text()))
+                }
+                _ => Err(AppError::BadRequest(e.body_text())),
+            },
+            Err(rejection) => Err(AppError::InternalServerError(rejection.body_text())),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+use utoipa::ToSchema;
+
+use crate::audit::{entity, Audit, AuditAction, AuditEvent, ADMIN_TOKEN_ACTOR};
+use crate::middleware::read_only::ReadOnlyMode;
+use crate::routes::RouteTable;
+use crate::slo::SloTracker;
+
+/// Read-only mode state
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"enabled": true}))]
+pub struct ReadOnlyStatus {
+    pub enabled: bool,
+}
+
+/// Get read-only mode
+/// GET /admin/read-only
+#[utoipa::path(
+    get,
+    path = "/admin/read-only",
+    responses(
+        (status = 200, description = "Current read-only mode", body = ReadOnlyStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+#[instrument(skip(mode))]
+pub async fn get_read_only(State(mode): State<ReadOnlyMode>) -> impl IntoResponse {
+    Json(ReadOnlyStatus {
+        enabled: mode.is_enabled(),
+    })
+}
+
+/// Switch read-only mode on or off
+/// PUT /admin/read-only
+#[utoipa::path(
+    put,
+    path = "/admin/read-only",
+    request_body = ReadOnlyStatus,
+    responses(
+        (status = 200, description = "Read-only mode updated", body = ReadOnlyStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+#[instrument(skip(mode, audit))]
+pub async fn set_read_only(
+    State(mode): State<ReadOnlyMode>,
+    audit: Audit,
+    Json(payload): Json<ReadOnlyStatus>,
+) -> impl IntoResponse {
+    let previous = mode.set(payload.enabled);
+    if payload.enabled != previous {
+        audit
+            .record(
+                AuditEvent::new(AuditAction::Update, entity::READ_ONLY_MODE, "global")
+                    .by(ADMIN_TOKEN_ACTOR.to_string())
+                    .before(ReadOnlyStatus { enabled: previous })
+                    .after(&payload),
+            )
+            .await;
+    }
+
+    if payload.enabled && !previous {
+        warn!("Read-only mode enabled; mutating requests are now rejected");
+    } else if !payload.enabled && previous {
+        info!("Read-only mode disabled");
+    }
+
+    Json(ReadOnlyStatus {
+        enabled: payload.enabled,
+    })
+}
+
+/// Summarize SLO compliance
+/// GET /admin/slo
+#[utoipa::path(
+    get,
+    path = "/admin/slo",
+    responses(
+        (status = 200, description = "Compliance of every route with an objective over the rolling window", body = SloReport),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+#[instrument(skip(tracker))]
+pub async fn get_slo(State(tracker): State<Arc<SloTracker>>) -> impl IntoResponse {
+    Json(tracker.report())
+}
+
+/// List mounted routes
+/// GET /admin/routes
+#[utoipa::path(
+    get,
+    path = "/admin/routes",
+    responses(
+        (status = 200, description = "Every mounted path with the methods it answers, sorted by path", body = Vec<RouteInfo>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "admin"
+)]
+#[instrument(skip(routes))]
+pub async fn list_routes(Extension(routes): Extension<Arc<RouteTable>>) -> impl IntoResponse {
+    Json(routes.list())
+}
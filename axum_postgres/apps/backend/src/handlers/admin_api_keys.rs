@@ -0,0 +1,191 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa;
+use validator::Validate;
+
+use crate::audit::{entity, user_actor, Audit, AuditAction, AuditEvent};
+use crate::auth::api_key::{self, ApiKeyPrincipal, DEFAULT_ROTATION_GRACE_SECS};
+use crate::auth::role::{Admin, RequireRole};
+use crate::auth::token::fingerprint;
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::models::api_key::{
+    AdminApiKeyResponse, ApiKeyResponse, CreatedApiKey, RotateApiKeyRequest,
+};
+use crate::models::user_id::UserId;
+use crate::repository::api_keys::{ApiKeyRepository, ApiKeyRepositoryTrait};
+
+/// Route templates
+pub const ADMIN_API_KEYS_PATH: &str = "/api/admin/api-keys";
+pub const ADMIN_API_KEY_ROTATE_PATH: &str = "/api/admin/api-keys/:id/rotate";
+pub const ADMIN_API_KEY_EXPIRE_PATH: &str = "/api/admin/api-keys/:id/expire";
+
+/// List the API keys of every user
+/// GET /api/admin/api-keys
+///
+/// Newest first, revoked and expired keys included. `last_used_at` is
+/// stored in batches and can be up to a minute behind.
+#[utoipa::path(
+    get,
+    path = "/api/admin/api-keys",
+    responses(
+        (status = 200, description = "API keys of the tenant, newest first", body = Vec<AdminApiKeyResponse>),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-api-keys"
+)]
+#[instrument(skip(pool))]
+pub async fn list_admin_api_keys(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+) -> Result<Json<Vec<AdminApiKeyResponse>>, AppError> {
+    let keys = ApiKeyRepository::new(pool).list_all().await.map_err(|e| {
+        error!("Database error listing API keys for admins: {:?}", e);
+        AppError::InternalServerError("Failed to list API keys".to_string())
+    })?;
+    info!("Retrieved {} API keys for an admin", keys.len());
+    Ok(Json(keys.into_iter().map(Into::into).collect()))
+}
+
+/// Replace an API key with a new one
+/// POST /api/admin/api-keys/{id}/rotate
+///
+/// The new key has the name, scopes and creator of the old one, and is
+/// only in this response. The old key keeps working for
+/// `grace_period_secs`, a day by default, so clients can move over.
+#[utoipa::path(
+    post,
+    path = "/api/admin/api-keys/{id}/rotate",
+    params(
+        ("id" = String, Path, description = "API key ID")
+    ),
+    request_body = RotateApiKeyRequest,
+    responses(
+        (status = 201, description = "New API key; the old one expires after the grace period", body = CreatedApiKey),
+        (status = 400, description = "Invalid API key ID format or grace period", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or called with an API key", body = ErrorResponse),
+        (status = 404, description = "No working API key with this ID that was not rotated already", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-api-keys"
+)]
+#[instrument(skip(pool, audit, payload))]
+pub async fn rotate_api_key(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path(id): Path<i64>,
+    Json(payload): Json<RotateApiKeyRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let admin_id = signed_in_admin(principal)?;
+    if let Err(errors) = payload.validate() {
+        warn!("API key rotation validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let key = api_key::generate();
+    let grace_period_secs = payload
+        .grace_period_secs
+        .unwrap_or(DEFAULT_ROTATION_GRACE_SECS);
+    let rotated = ApiKeyRepository::new(pool)
+        .rotate(
+            id,
+            api_key::display_prefix(&key),
+            &fingerprint(&key),
+            grace_period_secs,
+        )
+        .await
+        .map_err(|e| {
+            error!("Database error rotating API key {}: {:?}", id, e);
+            AppError::InternalServerError("Failed to rotate API key".to_string())
+        })?
+        .ok_or_else(|| {
+            warn!("No API key {} to rotate", id);
+            AppError::NotFound("API key not found or already rotated".to_string())
+        })?;
+
+    let principal = ApiKeyPrincipal::from(rotated.clone());
+    info!(
+        "Admin user ID {} rotated API key {} to {}, with {}s of grace",
+        admin_id, id, principal.id, grace_period_secs
+    );
+    let api_key = ApiKeyResponse::from(rotated);
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Create, entity::API_KEY, principal.id)
+                .by(user_actor(admin_id))
+                .after(&api_key),
+        )
+        .await;
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { api_key, key })))
+}
+
+/// Make an API key stop working now
+/// POST /api/admin/api-keys/{id}/expire
+///
+/// Works on keys that are still within the grace period of a rotation too.
+#[utoipa::path(
+    post,
+    path = "/api/admin/api-keys/{id}/expire",
+    params(
+        ("id" = String, Path, description = "API key ID")
+    ),
+    responses(
+        (status = 200, description = "The expired API key", body = ApiKeyResponse),
+        (status = 400, description = "Invalid API key ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or called with an API key", body = ErrorResponse),
+        (status = 404, description = "No working API key with this ID", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-api-keys"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn expire_api_key(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path(id): Path<i64>,
+) -> Result<Json<ApiKeyResponse>, AppError> {
+    let admin_id = signed_in_admin(principal)?;
+    let expired = ApiKeyRepository::new(pool)
+        .expire(id)
+        .await
+        .map_err(|e| {
+            error!("Database error expiring API key {}: {:?}", id, e);
+            AppError::InternalServerError("Failed to expire API key".to_string())
+        })?
+        .ok_or_else(|| {
+            warn!("No API key {} to expire", id);
+            AppError::NotFound("API key not found".to_string())
+        })?;
+
+    warn!("Admin user ID {} expired API key {}", admin_id, id);
+    let api_key = ApiKeyResponse::from(expired);
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Update, entity::API_KEY, id)
+                .by(user_actor(admin_id))
+                .after(&api_key),
+        )
+        .await;
+    Ok(Json(api_key))
+}
+
+/// The signed-in admin; API keys cannot rotate or expire keys
+fn signed_in_admin(principal: Principal) -> Result<UserId, AppError> {
+    match principal {
+        Principal::User { id, .. } => Ok(id),
+        Principal::ApiKey(_) => Err(AppError::Forbidden(
+            "API keys cannot rotate or expire API keys".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,371 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa;
+
+use crate::audit::{entity, user_actor, Audit, AuditAction, AuditEvent};
+use crate::auth::api_key::Scope;
+use crate::auth::role::{Admin, RequireRole, Role};
+use crate::auth::session::AuthMode;
+use crate::auth::token::{TokenKeys, ACCESS_TOKEN_TTL_SECS};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::handlers::auth::{send_reset_link, sign_out_everywhere};
+use crate::handlers::roles::{grant_id, live_user};
+use crate::mail::Mail;
+use crate::models::admin::{
+    AdminUserPage, AdminUserQuery, AdminUserResponse, ImpersonationResponse, UserRoles,
+};
+use crate::models::user::{User, UserListQuery};
+use crate::models::user_id::UserId;
+use crate::pagination::{Cursor, CursorQuery};
+use crate::repository::credentials::{CredentialRepository, CredentialRepositoryTrait};
+use crate::repository::roles::{RoleRepository, RoleRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+use crate::tenant;
+
+/// Route templates
+pub const ADMIN_USERS_PATH: &str = "/api/admin/users";
+pub const ADMIN_USER_PASSWORD_RESET_PATH: &str = "/api/admin/users/:id/password-reset";
+pub const ADMIN_USER_IMPERSONATE_PATH: &str = "/api/admin/users/:id/impersonate";
+pub const ADMIN_USER_ROLES_PATH: &str = "/api/admin/users/:id/roles";
+
+/// List users, soft-deleted ones included, with their roles
+/// GET /api/admin/users
+///
+/// Newest first, a page at a time; follow `next_cursor` for older users.
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    params(AdminUserQuery, CursorQuery),
+    responses(
+        (status = 200, description = "One page of matching users", body = AdminUserPage),
+        (status = 400, description = "Invalid cursor", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-users"
+)]
+#[instrument(skip(pool))]
+pub async fn list_admin_users(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    Query(query): Query<AdminUserQuery>,
+    Query(page): Query<CursorQuery>,
+) -> Result<Json<AdminUserPage>, AppError> {
+    let after = page
+        .cursor
+        .as_deref()
+        .map(|token| {
+            Cursor::decode(token).ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))
+        })
+        .transpose()?;
+    let limit = page.page_size();
+
+    // One extra row tells whether there is a next page
+    let mut users = UserRepository::new(pool.clone())
+        .list_users_after(&UserListQuery::from(query), after, limit + 1)
+        .await
+        .map_err(|e| {
+            error!("Database error listing users for admins: {:?}", e);
+            AppError::InternalServerError("Failed to list users".to_string())
+        })?;
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|user| {
+            Cursor {
+                created_at: user.created_at,
+                id: user.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    let keys: Vec<i32> = users.iter().map(|user| user.id).collect();
+    let mut roles = RoleRepository::new(pool)
+        .roles_of_all(&keys)
+        .await
+        .map_err(|e| {
+            error!("Database error loading roles of users: {:?}", e);
+            AppError::InternalServerError("Failed to list users".to_string())
+        })?;
+    info!("Retrieved page of {} users for an admin", users.len());
+    Ok(Json(AdminUserPage {
+        data: users
+            .into_iter()
+            .map(|user| AdminUserResponse {
+                roles: roles.remove(&user.id).unwrap_or_default(),
+                user: user.to_response(),
+            })
+            .collect(),
+        next_cursor,
+    }))
+}
+
+/// Make a user choose a new password
+/// POST /api/admin/users/{id}/password-reset
+///
+/// Removes the user's password, signs them out everywhere and mails them a
+/// password reset link. Until they follow it they can only sign in with
+/// Google or GitHub, if linked.
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/password-reset",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 202, description = "Password removed; the reset email is being sent"),
+        (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the users:write scope", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-users"
+)]
+#[instrument(skip(pool, mail, audit))]
+pub async fn force_password_reset(
+    State(pool): State<PgPool>,
+    State(mail): State<Mail>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+) -> Result<StatusCode, AppError> {
+    principal.require(Scope::UsersWrite)?;
+    let user = live_user(&pool, user_id).await?;
+    let cleared = CredentialRepository::new(pool.clone())
+        .clear_password_hash(&user)
+        .await
+        .map_err(|e| reset_error("removing password", e))?;
+    sign_out_everywhere(&pool, &user)
+        .await
+        .map_err(|e| reset_error("signing user out", e))?;
+    if cleared {
+        audit
+            .record(
+                AuditEvent::new(AuditAction::Delete, entity::PASSWORD, user_id)
+                    .by_principal(&principal),
+            )
+            .await;
+    }
+    info!("Forced a password reset for user ID {}", user_id);
+
+    tokio::spawn(tenant::scope(tenant::current(), async move {
+        if let Err(e) = send_reset_link(&pool, &mail, &user).await {
+            error!("Failed to send forced password reset: {}", e);
+        }
+    }));
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Get an access token that acts as a user
+/// POST /api/admin/users/{id}/impersonate
+///
+/// For reproducing what a user sees. The token names the admin in its `act`
+/// claim, cannot be refreshed and only works with AUTH_MODE=jwt. It cannot
+/// create API keys, change the password, sign the user out everywhere or
+/// delete the account either, so nothing it does outlasts it. The audit log
+/// records its changes as the user's, with the admin in `impersonated_by`.
+/// Other admins and inactive users cannot be impersonated, and API keys
+/// cannot impersonate anyone.
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/impersonate",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "Access token acting as the user", body = ImpersonationResponse),
+        (status = 400, description = "Invalid user ID format, inactive user, or AUTH_MODE=cookie", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin, called with an API key, or the user is an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-users"
+)]
+#[instrument(skip(pool, keys, audit))]
+pub async fn impersonate_user(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    State(mode): State<AuthMode>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+) -> Result<Json<ImpersonationResponse>, AppError> {
+    let Principal::User { id: admin_id, .. } = principal else {
+        return Err(AppError::Forbidden(
+            "API keys cannot impersonate users".to_string(),
+        ));
+    };
+    if mode == AuthMode::Cookie {
+        return Err(AppError::BadRequest(
+            "Impersonation tokens need AUTH_MODE=jwt".to_string(),
+        ));
+    }
+    let user = live_user(&pool, user_id).await?;
+    if !user.active {
+        return Err(AppError::BadRequest(
+            "Cannot impersonate an inactive user".to_string(),
+        ));
+    }
+    if roles_of(&pool, &user).await?.contains(&Role::Admin) {
+        warn!(
+            "Admin user ID {} tried to impersonate admin user ID {}",
+            admin_id, user_id
+        );
+        return Err(AppError::Forbidden(
+            "Cannot impersonate an admin".to_string(),
+        ));
+    }
+
+    let access_token = keys.issue_impersonation(user_id, admin_id);
+    warn!(
+        "Admin user ID {} is impersonating user ID {}",
+        admin_id, user_id
+    );
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Create, entity::IMPERSONATION, user_id)
+                .by(user_actor(admin_id)),
+        )
+        .await;
+    Ok(Json(ImpersonationResponse {
+        user: user.to_response(),
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: ACCESS_TOKEN_TTL_SECS,
+    }))
+}
+
+/// List the roles of a user
+/// GET /api/admin/users/{id}/roles
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}/roles",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "Roles the user holds, by name", body = UserRoles),
+        (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-users"
+)]
+#[instrument(skip(pool))]
+pub async fn get_user_roles(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    Path(user_id): Path<UserId>,
+) -> Result<Json<UserRoles>, AppError> {
+    let user = live_user(&pool, user_id).await?;
+    let roles = roles_of(&pool, &user).await?;
+    Ok(Json(UserRoles { roles }))
+}
+
+/// Set the roles of a user
+/// PUT /api/admin/users/{id}/roles
+///
+/// Grants the listed roles the user lacks and revokes the ones not listed.
+/// Admins cannot take the admin role away from themselves.
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/roles",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    request_body = UserRoles,
+    responses(
+        (status = 200, description = "Roles the user now holds, by name", body = UserRoles),
+        (status = 400, description = "Unknown role or invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Not an admin, the API key lacks the roles:write scope, or the caller would lose the admin role", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_role" = [])),
+    tag = "admin-users"
+)]
+#[instrument(skip(pool, audit, payload))]
+pub async fn set_user_roles(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+    Json(payload): Json<UserRoles>,
+) -> Result<Json<UserRoles>, AppError> {
+    principal.require(Scope::RolesWrite)?;
+    let user = live_user(&pool, user_id).await?;
+    let is_self = match &principal {
+        Principal::User { id, .. } => *id == user_id,
+        Principal::ApiKey(key) => key.created_by == Some(user.id),
+    };
+    if is_self && !payload.roles.contains(&Role::Admin) {
+        return Err(AppError::Forbidden(
+            "Admins cannot remove their own admin role".to_string(),
+        ));
+    }
+
+    let repo = RoleRepository::new(pool.clone());
+    let held = roles_of(&pool, &user).await?;
+    for role in Role::ALL {
+        let (wanted, holds) = (payload.roles.contains(&role), held.contains(&role));
+        let (action, changed) = match (wanted, holds) {
+            (true, false) => (AuditAction::Create, repo.grant(role, &user).await),
+            (false, true) => (AuditAction::Delete, repo.revoke(role, &user).await),
+            _ => continue,
+        };
+        let changed = changed.map_err(|e| {
+            error!("Database error changing {} role: {:?}", role, e);
+            AppError::InternalServerError("Failed to set roles".to_string())
+        })?;
+        if changed {
+            info!("Set {} role of user ID {} to {}", role, user_id, wanted);
+            audit
+                .record(
+                    AuditEvent::new(action, entity::ROLE_GRANT, grant_id(role, user_id))
+                        .by_principal(&principal),
+                )
+                .await;
+        }
+    }
+
+    let roles = roles_of(&pool, &user).await?;
+    Ok(Json(UserRoles { roles }))
+}
+
+async fn roles_of(pool: &PgPool, user: &User) -> Result<Vec<Role>, AppError> {
+    RoleRepository::new(pool.clone())
+        .roles_of(user.id)
+        .await
+        .map_err(|e| {
+            error!(
+                "Database error loading roles of user {}: {:?}",
+                user.user_id(),
+                e
+            );
+            AppError::InternalServerError("Failed to load roles".to_string())
+        })
+}
+
+fn reset_error(action: &str, e: sqlx::Error) -> AppError {
+    error!("Database error {}: {:?}", action, e);
+    AppError::InternalServerError("Failed to reset password".to_string())
+}
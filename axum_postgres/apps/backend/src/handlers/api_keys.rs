@@ -0,0 +1,178 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa;
+use validator::Validate;
+
+use crate::audit::{entity, Audit, AuditAction, AuditEvent};
+use crate::auth::api_key::{self, ApiKeyPrincipal};
+use crate::auth::token::fingerprint;
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::models::api_key::{ApiKeyResponse, CreateApiKeyRequest, CreatedApiKey};
+use crate::models::user::User;
+use crate::repository::api_keys::{ApiKeyRepository, ApiKeyRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+
+/// Route templates
+pub const API_KEYS_PATH: &str = "/api/api-keys";
+pub const API_KEY_PATH: &str = "/api/api-keys/:id";
+
+/// Create an API key
+/// POST /api/api-keys
+///
+/// The key is only in this response; store it, since only its hash is kept.
+#[utoipa::path(
+    post,
+    path = "/api/api-keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "API key created", body = CreatedApiKey),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 403, description = "Signed in with an impersonation token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "api-keys"
+)]
+#[instrument(skip(pool, audit, payload))]
+pub async fn create_api_key(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    audit: Audit,
+    Json(payload): Json<CreateApiKeyRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    // A key would outlive the impersonation token that made it
+    auth.require_own_sign_in("create API keys")?;
+    if let Err(errors) = payload.validate() {
+        warn!("API key validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let user = signed_in_user(&pool, auth).await?;
+    let key = api_key::generate();
+    let created = ApiKeyRepository::new(pool)
+        .create(
+            &user,
+            &payload.name,
+            api_key::display_prefix(&key),
+            &fingerprint(&key),
+            &payload.scopes,
+        )
+        .await
+        .map_err(|e| {
+            error!("Database error creating API key: {:?}", e);
+            AppError::InternalServerError("Failed to create API key".to_string())
+        })?;
+
+    let principal = ApiKeyPrincipal::from(created.clone());
+    info!(
+        "User ID {} created API key {} with scopes {:?}",
+        user.user_id(),
+        principal.id,
+        principal.scopes
+    );
+    let api_key = ApiKeyResponse::from(created);
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Create, entity::API_KEY, principal.id)
+                .by_user(auth)
+                .after(&api_key),
+        )
+        .await;
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { api_key, key })))
+}
+
+/// List the signed-in user's API keys
+/// GET /api/api-keys
+///
+/// Revoked keys are left out, and keys are shown by prefix only.
+#[utoipa::path(
+    get,
+    path = "/api/api-keys",
+    responses(
+        (status = 200, description = "API keys of the user, newest first", body = Vec<ApiKeyResponse>),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "api-keys"
+)]
+#[instrument(skip(pool))]
+pub async fn list_api_keys(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+) -> Result<Json<Vec<ApiKeyResponse>>, AppError> {
+    let user = signed_in_user(&pool, auth).await?;
+    let keys = ApiKeyRepository::new(pool)
+        .list_for(&user)
+        .await
+        .map_err(|e| {
+            error!("Database error listing API keys: {:?}", e);
+            AppError::InternalServerError("Failed to list API keys".to_string())
+        })?;
+    Ok(Json(keys.into_iter().map(Into::into).collect()))
+}
+
+/// Revoke an API key
+/// DELETE /api/api-keys/{id}
+///
+/// Requests with the key are refused from then on.
+#[utoipa::path(
+    delete,
+    path = "/api/api-keys/{id}",
+    params(
+        ("id" = String, Path, description = "API key ID")
+    ),
+    responses(
+        (status = 204, description = "API key revoked"),
+        (status = 400, description = "Invalid API key ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 404, description = "The user has no such API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "api-keys"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn revoke_api_key(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    audit: Audit,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, AppError> {
+    let user = signed_in_user(&pool, auth).await?;
+    match ApiKeyRepository::new(pool).revoke(&user, id).await {
+        Ok(true) => {
+            info!("User ID {} revoked API key {}", user.user_id(), id);
+            audit
+                .record(AuditEvent::new(AuditAction::Delete, entity::API_KEY, id).by_user(auth))
+                .await;
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => {
+            warn!("API key {} not found for user ID {}", id, user.user_id());
+            Err(AppError::NotFound("API key not found".to_string()))
+        }
+        Err(e) => {
+            error!("Database error revoking API key: {:?}", e);
+            Err(AppError::InternalServerError(
+                "Failed to revoke API key".to_string(),
+            ))
+        }
+    }
+}
+
+/// The user the access token was issued to, unless deleted since
+async fn signed_in_user(pool: &PgPool, auth: AuthUser) -> Result<User, AppError> {
+    UserRepository::new(pool.clone())
+        .get_user_by_id(auth.id)
+        .await
+        .map_err(|e| {
+            error!("Database error loading user {}: {:?}", auth.id, e);
+            AppError::InternalServerError("Failed to load user".to_string())
+        })?
+        .ok_or_else(|| AppError::Unauthorized("User no longer exists".to_string()))
+}
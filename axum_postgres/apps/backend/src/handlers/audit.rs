@@ -0,0 +1,79 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use utoipa;
+
+use crate::auth::role::{Admin, RequireRole};
+use crate::error::AppError;
+use crate::models::audit::{AuditEntryResponse, AuditLogPage, AuditLogQuery};
+use crate::repository::audit::{AuditFilter, AuditRepository, AuditRepositoryTrait};
+
+/// Route templates
+pub const AUDIT_LOG_PATH: &str = "/api/admin/audit-log";
+
+/// List the changes made through the API
+/// GET /api/admin/audit-log
+///
+/// Newest first, a page at a time; follow `next_cursor` for older entries.
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-log",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, description = "One page of matching entries", body = AuditLogPage),
+        (status = 400, description = "Invalid filter or cursor", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = [])),
+    tag = "audit"
+)]
+#[instrument(skip(pool))]
+pub async fn list_audit_log(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<Json<AuditLogPage>, AppError> {
+    let before_id = query
+        .cursor
+        .as_deref()
+        .map(|cursor| {
+            cursor
+                .parse::<i64>()
+                .map_err(|_| AppError::BadRequest("Invalid cursor".to_string()))
+        })
+        .transpose()?;
+    let filter = AuditFilter {
+        actor: query.actor.clone(),
+        entity: query.entity.clone(),
+        entity_id: query.entity_id.clone(),
+        action: query.action.map(|action| action.as_str()),
+        since: query.since,
+        until: query.until,
+        before_id,
+    };
+    let page_size = query.page_size();
+
+    // One extra row tells whether there is a next page
+    let mut records = AuditRepository::new(pool)
+        .list(&filter, page_size + 1)
+        .await
+        .map_err(|e| {
+            error!("Database error listing audit log: {:?}", e);
+            AppError::InternalServerError("Failed to list audit log".to_string())
+        })?;
+    let next_cursor = if records.len() as i64 > page_size {
+        records.truncate(page_size as usize);
+        records.last().map(|record| record.id.to_string())
+    } else {
+        None
+    };
+    Ok(Json(AuditLogPage {
+        data: records.into_iter().map(AuditEntryResponse::from).collect(),
+        next_cursor,
+    }))
+}
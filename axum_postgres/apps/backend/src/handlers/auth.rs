@@ -0,0 +1,1168 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
+    Extension, Json,
+};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa;
+use validator::Validate;
+
+use crate::audit::{entity, user_actor, Audit, AuditAction, AuditEvent};
+use crate::auth::oauth::{
+    cleared_state_cookie, state_cookie, OAuthProviders, Provider, STATE_COOKIE, STATE_TTL_SECS,
+};
+use crate::auth::password::{PasswordService, DUMMY_HASH};
+use crate::auth::password_reset::{
+    send_password_reset_email, MAX_RESET_REQUESTS, PASSWORD_RESET_TTL_SECS,
+    RESET_REQUEST_WINDOW_SECS,
+};
+use crate::auth::session::{
+    cleared_cookies, cookie, csrf_cookie, session_cookie, AuthMode, SessionUser,
+    SESSION_IDLE_TIMEOUT_SECS,
+};
+use crate::auth::token::{
+    fingerprint, random_secret, TokenKeys, TokenKind, REFRESH_TOKEN_TTL_SECS,
+};
+use crate::auth::verification::{send_verification_email, EmailVerification};
+use crate::auth::AuthUser;
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::mail::Mail;
+use crate::middleware::constant_time_eq;
+use crate::models::auth::{
+    AuthResponse, ChangePasswordRequest, ForgotPasswordRequest, LoginRequest, OAuthCallbackQuery,
+    RefreshRequest, RegisterRequest, ResendVerificationRequest, ResetPasswordRequest,
+    TokenResponse, VerifyEmailRequest,
+};
+use crate::models::user::{CreateUserRequest, User, UserResponse};
+use crate::models::user_id::UserId;
+use crate::repository::credentials::{CredentialRepository, CredentialRepositoryTrait};
+use crate::repository::oauth::{OAuthRepository, OAuthRepositoryTrait};
+use crate::repository::password_resets::{PasswordResetRepository, PasswordResetRepositoryTrait};
+use crate::repository::refresh_tokens::{
+    RefreshTokenRepository, RefreshTokenRepositoryTrait, Rotation,
+};
+use crate::repository::sessions::{SessionRepository, SessionRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+use crate::tenant;
+
+/// Route templates
+pub const REGISTER_PATH: &str = "/api/auth/register";
+pub const LOGIN_PATH: &str = "/api/auth/login";
+pub const REFRESH_PATH: &str = "/api/auth/refresh";
+pub const LOGOUT_PATH: &str = "/api/auth/logout";
+pub const LOGOUT_ALL_PATH: &str = "/api/auth/logout-all";
+pub const SESSION_PATH: &str = "/api/auth/session";
+pub const VERIFY_EMAIL_PATH: &str = "/api/auth/verify-email";
+pub const RESEND_VERIFICATION_PATH: &str = "/api/auth/resend-verification";
+pub const FORGOT_PASSWORD_PATH: &str = "/api/auth/forgot-password";
+pub const RESET_PASSWORD_PATH: &str = "/api/auth/reset-password";
+pub const CHANGE_PASSWORD_PATH: &str = "/api/users/:id/change-password";
+pub const OAUTH_START_PATH: &str = "/api/auth/oauth/:provider/start";
+pub const OAUTH_CALLBACK_PATH: &str = "/api/auth/oauth/:provider/callback";
+
+/// Create a user with a password and sign them in
+/// POST /api/auth/register
+///
+/// Mails the user a link to verify their address. When verification is
+/// required the user is not signed in yet, and the response has no tokens.
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    request_body = RegisterRequest,
+    responses(
+        (status = 201, description = "User created, and signed in unless verification is required", body = AuthResponse),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 409, description = "Email already used by another user, in any letter case", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, mail, audit, payload))]
+pub async fn register(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    State(mode): State<AuthMode>,
+    State(mail): State<Mail>,
+    State(verification): State<EmailVerification>,
+    audit: Audit,
+    Json(payload): Json<RegisterRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    info!("Registering user: {}", payload.email);
+
+    if let Err(errors) = payload.validate() {
+        warn!("Registration validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let RegisterRequest {
+        name,
+        email,
+        password,
+    } = payload;
+    let password_hash = hash_password(password).await?;
+    let user = CreateUserRequest {
+        name,
+        email,
+        ..CreateUserRequest::default()
+    };
+
+    match CredentialRepository::new(pool.clone())
+        .register(user, &password_hash)
+        .await
+    {
+        Ok(user) => {
+            info!("User registered with ID: {}", user.user_id());
+            audit
+                .record(
+                    AuditEvent::user(AuditAction::Create, &user).after(user.clone().to_response()),
+                )
+                .await;
+            send_in_background(mail, keys.clone(), user.clone());
+            if verification.required {
+                return Ok((StatusCode::CREATED, awaiting_verification(user)));
+            }
+            let signed_in = signed_in(&pool, &keys, mode, user).await?;
+            Ok((StatusCode::CREATED, signed_in))
+        }
+        Err(e) => {
+            error!("Database error registering user: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Sign in with email and password
+/// POST /api/auth/login
+///
+/// Unknown emails, users without a password and wrong passwords all get
+/// the same answer, so the response does not tell which emails exist.
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Signed in", body = AuthResponse),
+        (status = 401, description = "Wrong email or password, or the user is inactive", body = ErrorResponse),
+        (status = 403, description = "Email not verified yet, when verification is required", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, payload))]
+pub async fn login(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    State(mode): State<AuthMode>,
+    State(verification): State<EmailVerification>,
+    Json(payload): Json<LoginRequest>,
+) -> Result<SignedIn, AppError> {
+    let user = UserRepository::new(pool.clone())
+        .find_user_by_email(&payload.email)
+        .await
+        .map_err(|e| {
+            error!("Database error looking up user for login: {:?}", e);
+            AppError::InternalServerError("Failed to sign in".to_string())
+        })?;
+    let stored = match &user {
+        Some(user) => CredentialRepository::new(pool.clone())
+            .password_hash(user)
+            .await
+            .map_err(|e| {
+                error!("Database error loading credentials: {:?}", e);
+                AppError::InternalServerError("Failed to sign in".to_string())
+            })?,
+        None => None,
+    };
+
+    let matches = verify_password(payload.password.clone(), stored.clone()).await?;
+    let (Some(user), Some(stored), true) = (user, stored, matches) else {
+        warn!("Failed login for {}", payload.email);
+        return Err(AppError::Unauthorized(
+            "Invalid email or password".to_string(),
+        ));
+    };
+    if !user.active {
+        warn!("Login refused for inactive user ID {}", user.user_id());
+        return Err(AppError::Unauthorized("User is inactive".to_string()));
+    }
+    if verification.required && !user.email_verified {
+        warn!("Login refused for unverified user ID {}", user.user_id());
+        return Err(AppError::Forbidden(
+            "Verify your email address before signing in".to_string(),
+        ));
+    }
+
+    if PasswordService::default().needs_rehash(&stored) {
+        upgrade_hash(&pool, &user, payload.password).await;
+    }
+
+    info!("User ID {} signed in", user.user_id());
+    signed_in(&pool, &keys, mode, user).await
+}
+
+/// Trade a refresh token for a new pair of tokens
+/// POST /api/auth/refresh
+///
+/// Each refresh token works once. Presenting one a second time means it
+/// was copied, so every token of that sign-in is revoked. Also fails once
+/// the user has been deleted or deactivated.
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, description = "New access and refresh tokens", body = TokenResponse),
+        (status = 401, description = "Invalid, expired, used or revoked refresh token, or the user is gone or inactive", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, payload))]
+pub async fn refresh(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let claims = keys
+        .verify(&payload.refresh_token, TokenKind::Refresh)
+        .map_err(|e| {
+            warn!("Rejected refresh token: {}", e);
+            AppError::Unauthorized(e.to_string())
+        })?;
+    let user_id: UserId = claims
+        .sub
+        .parse()
+        .map_err(|_| AppError::Unauthorized("Malformed token".to_string()))?;
+    // Before rotating, so a token sent to another tenant is not used up
+    if claims.tid != tenant::current() {
+        warn!(
+            "Rejected refresh token of tenant {} for tenant {}",
+            claims.tid,
+            tenant::current()
+        );
+        return Err(AppError::Unauthorized(
+            "Token was issued for another tenant".to_string(),
+        ));
+    }
+
+    let rotation = RefreshTokenRepository::new(pool.clone())
+        .rotate(&fingerprint(&payload.refresh_token))
+        .await
+        .map_err(|e| refresh_error("rotating refresh token", e))?;
+    let (user_key, family_id) = match rotation {
+        Rotation::Rotated {
+            user_key,
+            family_id,
+        } => (user_key, family_id),
+        Rotation::Reused => {
+            warn!(
+                "Refresh token reused for user ID {}; revoked its family",
+                user_id
+            );
+            return Err(AppError::Unauthorized(
+                "Refresh token was already used; sign in again".to_string(),
+            ));
+        }
+        Rotation::Revoked => {
+            return Err(AppError::Unauthorized(
+                "Refresh token has been revoked".to_string(),
+            ))
+        }
+    };
+
+    let user = UserRepository::new(pool.clone())
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| refresh_error("loading user", e))?
+        .filter(|user| user.id == user_key)
+        .ok_or_else(|| AppError::Unauthorized("User no longer exists".to_string()))?;
+    if !user.active {
+        return Err(AppError::Unauthorized("User is inactive".to_string()));
+    }
+    Ok(Json(
+        issue_tokens(&pool, &keys, &user, Some(family_id)).await?,
+    ))
+}
+
+/// Sign out one session
+/// POST /api/auth/logout
+///
+/// Revokes the refresh token and every token refreshed from the same
+/// sign-in. Access tokens already issued stay valid until they expire.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    request_body = RefreshRequest,
+    responses(
+        (status = 204, description = "Refresh tokens of this sign-in revoked"),
+        (status = 401, description = "Invalid or expired refresh token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, payload))]
+pub async fn logout(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<StatusCode, AppError> {
+    keys.verify(&payload.refresh_token, TokenKind::Refresh)
+        .map_err(|e| {
+            warn!("Rejected refresh token: {}", e);
+            AppError::Unauthorized(e.to_string())
+        })?;
+
+    let revoked = RefreshTokenRepository::new(pool)
+        .revoke_family_of(&fingerprint(&payload.refresh_token))
+        .await
+        .map_err(|e| {
+            error!("Database error revoking refresh tokens: {:?}", e);
+            AppError::InternalServerError("Failed to sign out".to_string())
+        })?;
+    info!("Signed out; revoked {} refresh tokens", revoked);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sign out everywhere
+/// POST /api/auth/logout-all
+///
+/// Revokes every refresh token and ends every session of the signed-in
+/// user. Access tokens already issued stay valid until they expire.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout-all",
+    responses(
+        (status = 204, description = "All refresh tokens of the user revoked and sessions ended"),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 403, description = "Session without a valid X-CSRF-Token header, or an impersonation token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "auth"
+)]
+#[instrument(skip(pool))]
+pub async fn logout_all(
+    State(pool): State<PgPool>,
+    State(mode): State<AuthMode>,
+    auth: AuthUser,
+) -> Result<impl IntoResponse, AppError> {
+    auth.require_own_sign_in("sign the user out everywhere")?;
+    let user = UserRepository::new(pool.clone())
+        .get_user_by_id(auth.id)
+        .await
+        .map_err(|e| {
+            error!("Database error loading user {}: {:?}", auth.id, e);
+            AppError::InternalServerError("Failed to sign out".to_string())
+        })?;
+    // A deleted user cannot refresh or resume a session anyway
+    if let Some(user) = user {
+        sign_out_everywhere(&pool, &user).await.map_err(|e| {
+            error!("Database error signing out everywhere: {:?}", e);
+            AppError::InternalServerError("Failed to sign out".to_string())
+        })?;
+    }
+    Ok((StatusCode::NO_CONTENT, signed_out(mode)))
+}
+
+/// Sign out of the current cookie session
+/// DELETE /api/auth/session
+///
+/// Ends the session and clears its cookies. Succeeds without a session, so
+/// it can always be used to reset the cookies.
+#[utoipa::path(
+    delete,
+    path = "/api/auth/session",
+    responses(
+        (status = 204, description = "Session ended and cookies cleared"),
+        (status = 403, description = "Session without a valid X-CSRF-Token header", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("session_cookie" = [])),
+    tag = "auth"
+)]
+#[instrument(skip(pool, session))]
+pub async fn end_session(
+    State(pool): State<PgPool>,
+    session: Option<Extension<SessionUser>>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(Extension(session)) = session {
+        SessionRepository::new(pool)
+            .delete(&session.session_hash)
+            .await
+            .map_err(|e| {
+                error!("Database error ending session: {:?}", e);
+                AppError::InternalServerError("Failed to sign out".to_string())
+            })?;
+        info!("User ID {} signed out", session.id);
+    }
+    Ok((StatusCode::NO_CONTENT, signed_out(AuthMode::Cookie)))
+}
+
+/// Change a user's password
+/// POST /api/users/{id}/change-password
+///
+/// Users may only change their own password, and must confirm it with the
+/// current one. Signs the user out everywhere, as after `logout-all`.
+#[utoipa::path(
+    post,
+    path = "/api/users/{id}/change-password",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    request_body = ChangePasswordRequest,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Invalid user ID format, or the new password breaks the policy", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token or session, or wrong current password", body = ErrorResponse),
+        (status = 403, description = "Signed in as another user, or with an impersonation token", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "auth"
+)]
+#[instrument(skip(pool, audit, payload))]
+pub async fn change_password(
+    State(pool): State<PgPool>,
+    auth: AuthUser,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    if auth.id != user_id {
+        return Err(AppError::Forbidden(
+            "You can only change your own password".to_string(),
+        ));
+    }
+    auth.require_own_sign_in("change passwords")?;
+    if let Err(errors) = payload.validate() {
+        warn!("Password change validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let user = UserRepository::new(pool.clone())
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| {
+            error!("Database error loading user {}: {:?}", user_id, e);
+            AppError::InternalServerError("Failed to change password".to_string())
+        })?
+        .ok_or(AppError::UserNotFound)?;
+    let credentials = CredentialRepository::new(pool.clone());
+    let stored = credentials.password_hash(&user).await.map_err(|e| {
+        error!("Database error loading credentials: {:?}", e);
+        AppError::InternalServerError("Failed to change password".to_string())
+    })?;
+
+    let matches = verify_password(payload.current_password, stored.clone()).await?;
+    if stored.is_none() || !matches {
+        warn!("Wrong current password for user ID {}", user_id);
+        return Err(AppError::Unauthorized(
+            "Current password is incorrect".to_string(),
+        ));
+    }
+
+    let password_hash = hash_password(payload.new_password).await?;
+    credentials
+        .set_password_hash(&user, &password_hash)
+        .await
+        .map_err(|e| {
+            error!("Database error storing password: {:?}", e);
+            AppError::InternalServerError("Failed to change password".to_string())
+        })?;
+
+    sign_out_everywhere(&pool, &user).await.map_err(|e| {
+        error!("Database error signing out everywhere: {:?}", e);
+        AppError::InternalServerError("Failed to change password".to_string())
+    })?;
+
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Update, entity::PASSWORD, user_id).by_user(auth),
+        )
+        .await;
+    info!("Password changed for user ID {}", user_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Verify an email address with the token from the verification email
+/// POST /api/auth/verify-email
+///
+/// Tokens stay usable until they expire, so following a link twice is
+/// harmless; they stop working when the user changes their address.
+#[utoipa::path(
+    post,
+    path = "/api/auth/verify-email",
+    request_body = VerifyEmailRequest,
+    responses(
+        (status = 200, description = "Email verified", body = UserResponse),
+        (status = 400, description = "Invalid or expired token, or the address has changed since", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, audit, payload))]
+pub async fn verify_email(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    audit: Audit,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<Json<UserResponse>, AppError> {
+    let invalid = || AppError::BadRequest("Invalid or expired verification token".to_string());
+    let claims = keys
+        .verify(&payload.token, TokenKind::VerifyEmail)
+        .map_err(|e| {
+            warn!("Rejected email verification token: {}", e);
+            invalid()
+        })?;
+    let (Ok(user_id), Some(email)) = (claims.sub.parse::<UserId>(), claims.email) else {
+        return Err(invalid());
+    };
+
+    let user = UserRepository::new(pool.clone())
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| verification_error("loading user", e))?
+        .ok_or_else(invalid)?;
+    let verified = CredentialRepository::new(pool)
+        .mark_email_verified(&user, &email)
+        .await
+        .map_err(|e| verification_error("verifying email", e))?
+        .ok_or_else(|| {
+            warn!(
+                "Verification token of user ID {} is for an old address",
+                user_id
+            );
+            invalid()
+        })?;
+    info!("Email verified for user ID {}", user_id);
+    // The token proves the request comes from the user
+    audit
+        .record(
+            AuditEvent::user(AuditAction::Update, &verified)
+                .by(user_actor(user_id))
+                .before(user.to_response())
+                .after(verified.clone().to_response()),
+        )
+        .await;
+    Ok(Json(verified.to_response()))
+}
+
+/// Send the verification email again
+/// POST /api/auth/resend-verification
+///
+/// Always answers 202, whether or not a user with an unverified address
+/// exists, so the response does not tell which emails are registered.
+#[utoipa::path(
+    post,
+    path = "/api/auth/resend-verification",
+    request_body = ResendVerificationRequest,
+    responses(
+        (status = 202, description = "Email sent if the address awaits verification"),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, mail, payload))]
+pub async fn resend_verification(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    State(mail): State<Mail>,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> Result<StatusCode, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+    let user = UserRepository::new(pool)
+        .find_user_by_email(&payload.email)
+        .await
+        .map_err(|e| verification_error("looking up user", e))?;
+    match user {
+        Some(user) if !user.email_verified => send_in_background(mail, keys, user),
+        _ => info!("No unverified user with the address; nothing to resend"),
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Mail a link to choose a new password
+/// POST /api/auth/forgot-password
+///
+/// Always answers 202, whether or not a user has the address, and looks the
+/// user up after answering so timing does not tell either. At most
+/// `MAX_RESET_REQUESTS` requests per address are accepted each hour.
+#[utoipa::path(
+    post,
+    path = "/api/auth/forgot-password",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 202, description = "Email sent if a user has the address"),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 429, description = "Too many requests for this address; see Retry-After", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, mail, payload))]
+pub async fn forgot_password(
+    State(pool): State<PgPool>,
+    State(mail): State<Mail>,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    if let Err(errors) = payload.validate() {
+        return Err(AppError::Validation(errors));
+    }
+
+    let window_start = Utc::now() - Duration::seconds(RESET_REQUEST_WINDOW_SECS);
+    let requests = PasswordResetRepository::new(pool.clone())
+        .record_request(&fingerprint(&payload.email), window_start)
+        .await
+        .map_err(|e| {
+            error!("Database error recording password reset request: {:?}", e);
+            AppError::InternalServerError("Failed to start password reset".to_string())
+        })?;
+    if requests > MAX_RESET_REQUESTS {
+        warn!("Too many password reset requests for one address");
+        return Err(AppError::TooManyRequests {
+            retry_after_secs: RESET_REQUEST_WINDOW_SECS as u64,
+        });
+    }
+
+    tokio::spawn(tenant::scope(tenant::current(), async move {
+        if let Err(e) = start_password_reset(&pool, &mail, &payload.email).await {
+            error!("Failed to start password reset: {}", e);
+        }
+    }));
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Set a new password with the token from the reset email
+/// POST /api/auth/reset-password
+///
+/// The token works once, and using it voids the user's other reset links.
+/// Signs the user out everywhere, as after `logout-all`, and counts their
+/// address as verified since they received mail there.
+#[utoipa::path(
+    post,
+    path = "/api/auth/reset-password",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Invalid, expired or used token, or the new password breaks the policy", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, audit, payload))]
+pub async fn reset_password(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    // Checked first, so a rejected password does not use up the token
+    if let Err(errors) = payload.validate() {
+        warn!("Password reset validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let invalid = || AppError::BadRequest("Invalid or expired reset token".to_string());
+    let resets = PasswordResetRepository::new(pool.clone());
+    let user_key = resets
+        .take(&fingerprint(&payload.token))
+        .await
+        .map_err(|e| reset_error("using reset token", e))?
+        .ok_or_else(|| {
+            warn!("Rejected unknown, expired or used password reset token");
+            invalid()
+        })?;
+    let user = UserRepository::new(pool.clone())
+        .get_user_by_key(user_key)
+        .await
+        .map_err(|e| reset_error("loading user", e))?
+        .ok_or_else(invalid)?;
+
+    let password_hash = hash_password(payload.new_password).await?;
+    let credentials = CredentialRepository::new(pool.clone());
+    credentials
+        .set_password_hash(&user, &password_hash)
+        .await
+        .map_err(|e| reset_error("storing password", e))?;
+    resets
+        .delete_all(&user)
+        .await
+        .map_err(|e| reset_error("voiding reset tokens", e))?;
+    credentials
+        .mark_email_verified(&user, &user.email)
+        .await
+        .map_err(|e| reset_error("verifying email", e))?;
+    sign_out_everywhere(&pool, &user)
+        .await
+        .map_err(|e| reset_error("signing out everywhere", e))?;
+
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Update, entity::PASSWORD, user.user_id())
+                .by(user_actor(user.user_id())),
+        )
+        .await;
+    info!("Password reset for user ID {}", user.user_id());
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Start signing in with Google or GitHub
+/// GET /api/auth/oauth/{provider}/start
+///
+/// Redirects the browser to the provider, which sends it back to
+/// `/callback` within ten minutes, and sets the `oauth_state` cookie the
+/// callback checks.
+#[utoipa::path(
+    get,
+    path = "/api/auth/oauth/{provider}/start",
+    params(
+        ("provider" = Provider, Path, description = "Identity provider")
+    ),
+    responses(
+        (status = 303, description = "Redirect to the provider's sign-in page, setting the oauth_state cookie"),
+        (status = 400, description = "Unknown provider", body = ErrorResponse),
+        (status = 404, description = "Provider not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, providers))]
+pub async fn oauth_start(
+    State(pool): State<PgPool>,
+    State(providers): State<OAuthProviders>,
+    Path(provider): Path<Provider>,
+) -> Result<impl IntoResponse, AppError> {
+    let config = configured(&providers, provider)?;
+    let (state, code_verifier) = (random_secret(), random_secret());
+    OAuthRepository::new(pool)
+        .save_state(
+            provider,
+            &fingerprint(&state),
+            &code_verifier,
+            Utc::now() + Duration::seconds(STATE_TTL_SECS),
+        )
+        .await
+        .map_err(|e| {
+            error!("Database error storing OAuth state: {:?}", e);
+            AppError::InternalServerError("Failed to start sign-in".to_string())
+        })?;
+    Ok((
+        AppendHeaders([(header::SET_COOKIE, state_cookie(&state))]),
+        Redirect::to(&config.authorize_redirect(&state, &code_verifier)),
+    ))
+}
+
+/// Finish signing in with Google or GitHub
+/// GET /api/auth/oauth/{provider}/callback
+///
+/// Signs in the user linked to the provider account. An account seen for
+/// the first time is linked to the user with its verified email, or a new
+/// user is created for it. A user who never verified that email could
+/// have been registered by anyone, so their password, API keys, sessions
+/// and refresh tokens are revoked as the account is linked. The state must
+/// match the `oauth_state` cookie set by `/start`, so only the browser that
+/// started the sign-in can finish it.
+#[utoipa::path(
+    get,
+    path = "/api/auth/oauth/{provider}/callback",
+    params(
+        ("provider" = Provider, Path, description = "Identity provider"),
+        OAuthCallbackQuery
+    ),
+    responses(
+        (status = 200, description = "Signed in", body = AuthResponse),
+        (status = 400, description = "Unknown provider, or no code or state", body = ErrorResponse),
+        (status = 401, description = "Sign-in declined, unknown or expired state, a state other than the oauth_state cookie's, no verified email, or the user is inactive", body = ErrorResponse),
+        (status = 404, description = "Provider not configured", body = ErrorResponse),
+        (status = 502, description = "The provider failed to complete the sign-in", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "auth"
+)]
+#[instrument(skip(pool, keys, providers, audit, headers, query))]
+#[allow(clippy::too_many_arguments)]
+pub async fn oauth_callback(
+    State(pool): State<PgPool>,
+    State(keys): State<TokenKeys>,
+    State(providers): State<OAuthProviders>,
+    State(mode): State<AuthMode>,
+    audit: Audit,
+    Path(provider): Path<Provider>,
+    headers: HeaderMap,
+    Query(query): Query<OAuthCallbackQuery>,
+) -> Result<SignedIn, AppError> {
+    let config = configured(&providers, provider)?;
+    if let Some(error) = query.error {
+        warn!("{} sign-in failed: {}", provider, error);
+        return Err(AppError::Unauthorized(format!(
+            "Sign-in with {} failed: {}",
+            provider, error
+        )));
+    }
+    let (Some(code), Some(state)) = (query.code, query.state) else {
+        return Err(AppError::BadRequest(
+            "Missing code or state parameter".to_string(),
+        ));
+    };
+
+    // Checked before the state is used up, so a forged callback cannot
+    // spend the state of the browser's own sign-in
+    if !cookie(&headers, STATE_COOKIE).is_some_and(|cookie| constant_time_eq(cookie, &state)) {
+        warn!("{} sign-in state does not match the browser's", provider);
+        return Err(AppError::Unauthorized(
+            "Sign-in was started in another browser; start again".to_string(),
+        ));
+    }
+
+    let oauth = OAuthRepository::new(pool.clone());
+    let code_verifier = oauth
+        .take_state(provider, &fingerprint(&state))
+        .await
+        .map_err(|e| oauth_error("loading OAuth state", e))?
+        .ok_or_else(|| {
+            warn!("Unknown or expired {} sign-in state", provider);
+            AppError::Unauthorized("Sign-in expired; start again".to_string())
+        })?;
+
+    let identity = providers
+        .identify(provider, config, &code, &code_verifier)
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Sign-in with {} failed: {}", provider, e)))?;
+
+    let user = match oauth
+        .find_identity(provider, &identity.subject)
+        .await
+        .map_err(|e| oauth_error("looking up linked account", e))?
+    {
+        Some(user) => user,
+        None => {
+            let Some(email) = identity.verified_email else {
+                warn!(
+                    "{} account {} has no verified email",
+                    provider, identity.subject
+                );
+                return Err(AppError::Unauthorized(format!(
+                    "Your {} account has no verified email address",
+                    provider
+                )));
+            };
+            let existing = UserRepository::new(pool.clone())
+                .find_user_by_email(&email)
+                .await
+                .map_err(|e| oauth_error("looking up user by email", e))?;
+            match existing {
+                Some(user) if user.email_verified => {
+                    oauth
+                        .link(provider, &identity.subject, &email, &user)
+                        .await
+                        .map_err(|e| oauth_error("linking account", e))?;
+                    info!("Linked {} account to user ID {}", provider, user.user_id());
+                    user
+                }
+                // Whoever registered the address may not own it, so they
+                // lose the account to the owner the provider vouches for
+                Some(user) => {
+                    let user = oauth
+                        .take_over(provider, &identity.subject, &email, &user)
+                        .await
+                        .map_err(|e| oauth_error("linking account", e))?
+                        .ok_or_else(|| {
+                            warn!("User ID {} changed during {} sign-in", user.user_id(), provider);
+                            AppError::Unauthorized("Sign-in failed; start again".to_string())
+                        })?;
+                    warn!(
+                        "Linked {} account to unverified user ID {}; revoked its password and sign-ins",
+                        provider,
+                        user.user_id()
+                    );
+                    user
+                }
+                None => {
+                    let name = identity
+                        .name
+                        .filter(|name| !name.trim().is_empty())
+                        .unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_string());
+                    let user = CreateUserRequest {
+                        name,
+                        email,
+                        ..CreateUserRequest::default()
+                    };
+                    let user = oauth
+                        .provision(provider, &identity.subject, user)
+                        .await
+                        .map_err(|e| oauth_error("creating user", e))?;
+                    info!(
+                        "Created user ID {} for {} account",
+                        user.user_id(),
+                        provider
+                    );
+                    audit
+                        .record(
+                            AuditEvent::user(AuditAction::Create, &user)
+                                .after(user.clone().to_response()),
+                        )
+                        .await;
+                    user
+                }
+            }
+        }
+    };
+
+    if !user.active {
+        warn!(
+            "{} sign-in refused for inactive user ID {}",
+            provider,
+            user.user_id()
+        );
+        return Err(AppError::Unauthorized("User is inactive".to_string()));
+    }
+    info!("User ID {} signed in with {}", user.user_id(), provider);
+    let mut signed_in = signed_in(&pool, &keys, mode, user).await?;
+    signed_in.cookies.push(cleared_state_cookie());
+    Ok(signed_in)
+}
+
+/// Response to a successful sign-in: tokens in the body, or in cookie
+/// mode a new session's cookies
+pub struct SignedIn {
+    body: AuthResponse,
+    cookies: Vec<HeaderValue>,
+}
+
+impl IntoResponse for SignedIn {
+    fn into_response(self) -> Response {
+        let mut response = Json(self.body).into_response();
+        for cookie in self.cookies {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+        response
+    }
+}
+
+async fn signed_in(
+    pool: &PgPool,
+    keys: &TokenKeys,
+    mode: AuthMode,
+    user: User,
+) -> Result<SignedIn, AppError> {
+    if mode == AuthMode::Cookie {
+        return start_session(pool, user).await;
+    }
+    Ok(SignedIn {
+        body: AuthResponse {
+            tokens: Some(issue_tokens(pool, keys, &user, None).await?),
+            csrf_token: None,
+            user: user.to_response(),
+        },
+        cookies: Vec::new(),
+    })
+}
+
+async fn start_session(pool: &PgPool, user: User) -> Result<SignedIn, AppError> {
+    let (session_id, csrf_token) = (random_secret(), random_secret());
+    SessionRepository::new(pool.clone())
+        .create(
+            &user,
+            &fingerprint(&session_id),
+            &csrf_token,
+            Utc::now() + Duration::seconds(SESSION_IDLE_TIMEOUT_SECS),
+        )
+        .await
+        .map_err(|e| {
+            error!("Database error starting session: {:?}", e);
+            AppError::InternalServerError("Failed to start session".to_string())
+        })?;
+    Ok(SignedIn {
+        cookies: vec![session_cookie(&session_id), csrf_cookie(&csrf_token)],
+        body: AuthResponse {
+            tokens: None,
+            csrf_token: Some(csrf_token),
+            user: user.to_response(),
+        },
+    })
+}
+
+/// Response to a registration that has to be verified before sign-in
+fn awaiting_verification(user: User) -> SignedIn {
+    SignedIn {
+        body: AuthResponse {
+            user: user.to_response(),
+            tokens: None,
+            csrf_token: None,
+        },
+        cookies: Vec::new(),
+    }
+}
+
+/// Mail the verification link without holding up the response, so slow
+/// mail servers neither delay requests nor reveal which addresses exist
+fn send_in_background(mail: Mail, keys: TokenKeys, user: User) {
+    // The token names the tenant, which the task must run in
+    tokio::spawn(tenant::scope(tenant::current(), async move {
+        match send_verification_email(&mail, &keys, &user).await {
+            Ok(()) => info!("Sent verification email to user ID {}", user.user_id()),
+            Err(e) => error!("{} for user ID {}", e, user.user_id()),
+        }
+    }));
+}
+
+/// Store a reset token for the user with the address, if any, and mail it
+async fn start_password_reset(
+    pool: &PgPool,
+    mail: &Mail,
+    email: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(user) = UserRepository::new(pool.clone())
+        .find_user_by_email(email)
+        .await?
+    else {
+        info!("No user with the address; no password reset sent");
+        return Ok(());
+    };
+    send_reset_link(pool, mail, &user).await
+}
+
+/// Store a new reset token for the user and mail them the link
+pub(crate) async fn send_reset_link(
+    pool: &PgPool,
+    mail: &Mail,
+    user: &User,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let token = random_secret();
+    let expires_at = Utc::now() + Duration::seconds(PASSWORD_RESET_TTL_SECS);
+    PasswordResetRepository::new(pool.clone())
+        .create(user, &fingerprint(&token), expires_at)
+        .await?;
+    send_password_reset_email(mail, user, &token).await?;
+    info!("Sent password reset email to user ID {}", user.user_id());
+    Ok(())
+}
+
+/// `Set-Cookie` headers that clear the session cookies in cookie mode
+pub(crate) fn signed_out(mode: AuthMode) -> AppendHeaders<Vec<(HeaderName, HeaderValue)>> {
+    let cookies = match mode {
+        AuthMode::Cookie => cleared_cookies().to_vec(),
+        AuthMode::Jwt => Vec::new(),
+    };
+    AppendHeaders(
+        cookies
+            .into_iter()
+            .map(|cookie| (header::SET_COOKIE, cookie))
+            .collect(),
+    )
+}
+
+/// Revoke every refresh token and end every session of the user
+pub(crate) async fn sign_out_everywhere(pool: &PgPool, user: &User) -> Result<(), sqlx::Error> {
+    let revoked = RefreshTokenRepository::new(pool.clone())
+        .revoke_all(user)
+        .await?;
+    let ended = SessionRepository::new(pool.clone())
+        .delete_all(user)
+        .await?;
+    info!(
+        "User ID {} signed out everywhere; revoked {} refresh tokens and ended {} sessions",
+        user.user_id(),
+        revoked,
+        ended
+    );
+    Ok(())
+}
+
+/// New token pair whose refresh token joins `family_id`, or starts a new
+/// family for a fresh sign-in
+async fn issue_tokens(
+    pool: &PgPool,
+    keys: &TokenKeys,
+    user: &User,
+    family_id: Option<uuid::Uuid>,
+) -> Result<TokenResponse, AppError> {
+    let tokens = keys.issue_pair(user.user_id());
+    RefreshTokenRepository::new(pool.clone())
+        .save(
+            user,
+            family_id,
+            &fingerprint(&tokens.refresh_token),
+            Utc::now() + Duration::seconds(REFRESH_TOKEN_TTL_SECS),
+        )
+        .await
+        .map_err(|e| {
+            error!("Database error storing refresh token: {:?}", e);
+            AppError::InternalServerError("Failed to issue tokens".to_string())
+        })?;
+    Ok(tokens)
+}
+
+fn refresh_error(action: &str, e: sqlx::Error) -> AppError {
+    error!("Database error {}: {:?}", action, e);
+    AppError::InternalServerError("Failed to refresh token".to_string())
+}
+
+/// Settings of a provider, or 404 when it is not configured
+fn configured(
+    providers: &OAuthProviders,
+    provider: Provider,
+) -> Result<&crate::auth::oauth::ProviderConfig, AppError> {
+    providers
+        .get(provider)
+        .ok_or_else(|| AppError::NotFound(format!("Sign-in with {} is not configured", provider)))
+}
+
+fn verification_error(action: &str, e: sqlx::Error) -> AppError {
+    error!("Database error {}: {:?}", action, e);
+    AppError::InternalServerError("Failed to verify email".to_string())
+}
+
+fn reset_error(action: &str, e: sqlx::Error) -> AppError {
+    error!("Database error {}: {:?}", action, e);
+    AppError::InternalServerError("Failed to reset password".to_string())
+}
+
+fn oauth_error(action: &str, e: sqlx::Error) -> AppError {
+    error!("Database error {}: {:?}", action, e);
+    AppError::InternalServerError("Failed to sign in".to_string())
+}
+
+/// Hashing is deliberately slow, so it runs off the async workers
+async fn hash_password(password: String) -> Result<String, AppError> {
+    tokio::task::spawn_blocking(move || PasswordService::default().hash(&password))
+        .await
+        .map_err(|e| {
+            error!("Password hashing failed: {:?}", e);
+            AppError::InternalServerError("Failed to hash password".to_string())
+        })
+}
+
+/// Checks against a dummy hash when there is none, to take the same time
+async fn verify_password(password: String, stored: Option<String>) -> Result<bool, AppError> {
+    tokio::task::spawn_blocking(move || {
+        PasswordService::default().verify(&password, stored.as_deref().unwrap_or(&DUMMY_HASH))
+    })
+    .await
+    .map_err(|e| {
+        error!("Password verification failed: {:?}", e);
+        AppError::InternalServerError("Failed to sign in".to_string())
+    })
+}
+
+/// Replace an outdated hash after a successful login; failures only cost
+/// another attempt at the next login
+async fn upgrade_hash(pool: &PgPool, user: &User, password: String) {
+    let Ok(password_hash) = hash_password(password).await else {
+        return;
+    };
+    match CredentialRepository::new(pool.clone())
+        .set_password_hash(user, &password_hash)
+        .await
+    {
+        Ok(()) => info!("Upgraded password hash for user ID {}", user.user_id()),
+        Err(e) => error!("Database error upgrading password hash: {:?}", e),
+    }
+}
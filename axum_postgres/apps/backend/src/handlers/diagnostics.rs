@@ -0,0 +1,147 @@
+//! Process diagnostics served only on the internal listener (INTERNAL_ADDR)
+
+#[cfg(feature = "cpu-profiling")]
+pub use cpu::*;
+#[cfg(feature = "jemalloc")]
+pub use memory::*;
+
+#[cfg(feature = "cpu-profiling")]
+mod cpu {
+    use std::time::Duration;
+
+    use axum::{
+        extract::Query,
+        http::{header, StatusCode},
+        response::IntoResponse,
+    };
+    use serde::Deserialize;
+    use tracing::{info, instrument};
+
+    use crate::cpu_profile::{self, ProfileError, ProfileFormat};
+    use crate::error::AppError;
+
+    /// Longest profile a single request may take
+    const MAX_SECONDS: u64 = 60;
+
+    /// Query parameters of `GET /admin/profile/cpu`
+    #[derive(Debug, Deserialize)]
+    pub struct CpuProfileQuery {
+        /// Sampling duration, 1-60 seconds (default 10)
+        pub seconds: Option<u64>,
+        /// Samples per second (default 99)
+        pub frequency: Option<i32>,
+        /// `flamegraph` (default) or `pprof`
+        pub format: Option<String>,
+    }
+
+    /// Profile the running process
+    /// GET /admin/profile/cpu?seconds=10&format=flamegraph
+    #[instrument]
+    pub async fn cpu_profile(
+        Query(query): Query<CpuProfileQuery>,
+    ) -> Result<impl IntoResponse, AppError> {
+        let seconds = query.seconds.unwrap_or(10);
+        if !(1..=MAX_SECONDS).contains(&seconds) {
+            return Err(AppError::BadRequest(format!(
+                "seconds must be between 1 and {}",
+                MAX_SECONDS
+            )));
+        }
+        let frequency = query.frequency.unwrap_or(99).clamp(1, 1000);
+        let format = match query.format.as_deref() {
+            None | Some("flamegraph") => ProfileFormat::Flamegraph,
+            Some("pprof") => ProfileFormat::Pprof,
+            Some(other) => {
+                return Err(AppError::BadRequest(format!(
+                    "Unknown profile format: {}",
+                    other
+                )))
+            }
+        };
+
+        info!("Capturing {}s CPU profile at {}Hz", seconds, frequency);
+        let profile = tokio::task::spawn_blocking(move || {
+            cpu_profile::capture(Duration::from_secs(seconds), frequency, format)
+        })
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?
+        .map_err(|e| match e {
+            ProfileError::AlreadyRunning => AppError::BadRequest(e.to_string()),
+            ProfileError::Failed(_) => AppError::InternalServerError(e.to_string()),
+        })?;
+
+        // An idle process may not be sampled at all
+        if profile.is_empty() {
+            return Ok(StatusCode::NO_CONTENT.into_response());
+        }
+
+        Ok((
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, format.content_type())],
+            profile,
+        )
+            .into_response())
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+mod memory {
+    use axum::{
+        http::{header, StatusCode},
+        response::IntoResponse,
+        Json,
+    };
+    use tracing::{error, info, instrument};
+
+    use crate::error::AppError;
+
+    /// Allocator statistics
+    /// GET /admin/memory
+    #[instrument]
+    pub async fn memory_stats() -> Result<impl IntoResponse, AppError> {
+        crate::memory::stats()
+            .map(Json)
+            .map_err(AppError::InternalServerError)
+    }
+
+    /// Dump and download a heap profile
+    /// POST /admin/memory/heap-profile
+    #[instrument]
+    pub async fn heap_profile() -> Result<impl IntoResponse, AppError> {
+        let path = std::env::temp_dir().join(format!(
+            "backend.{}.{}.heap",
+            std::process::id(),
+            chrono::Utc::now().timestamp_millis()
+        ));
+
+        let dump_path = path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            crate::memory::dump_heap_profile(&dump_path)?;
+            std::fs::read(&dump_path).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+        let _ = std::fs::remove_file(&path);
+
+        match result {
+            Ok(profile) => {
+                info!("Heap profile dumped ({} bytes)", profile.len());
+                Ok((
+                    StatusCode::OK,
+                    [
+                        (header::CONTENT_TYPE, "application/octet-stream"),
+                        (
+                            header::CONTENT_DISPOSITION,
+                            "attachment; filename=\"heap.prof\"",
+                        ),
+                    ],
+                    profile,
+                ))
+            }
+            Err(e) => {
+                error!("Heap profile dump failed: {}", e);
+                Err(AppError::BadRequest(e))
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Answers for requests that match no route, or no method of their route
+
+use std::sync::Arc;
+
+use axum::{
+    http::{Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Redirect, Response},
+    Extension,
+};
+use tracing::instrument;
+
+use crate::error::AppError;
+use crate::routes::RouteTable;
+
+/// Whether `GET /api/users/` is redirected to `/api/users`
+///
+/// From REDIRECT_TRAILING_SLASH (`false`/`0` disables it), on by default.
+#[derive(Debug, Clone, Copy)]
+pub struct TrailingSlash {
+    pub redirect: bool,
+}
+
+impl TrailingSlash {
+    pub fn from_env() -> Self {
+        let redirect = std::env::var("REDIRECT_TRAILING_SLASH")
+            .map(|value| !matches!(value.to_ascii_lowercase().as_str(), "false" | "0"))
+            .unwrap_or(true);
+        Self { redirect }
+    }
+}
+
+/// 404 handler
+///
+/// A path that only differs from a route by trailing slashes is redirected
+/// there with 308, which keeps the method and body.
+#[instrument(skip(routes))]
+pub async fn not_found(
+    Extension(routes): Extension<Arc<RouteTable>>,
+    Extension(trailing_slash): Extension<TrailingSlash>,
+    uri: Uri,
+) -> Response {
+    if trailing_slash.redirect {
+        let path = uri.path().trim_end_matches('/');
+        // `//host` would be a protocol-relative redirect to another site
+        let is_route = path != uri.path()
+            && !path.is_empty()
+            && !path.starts_with("//")
+            && routes.allowed_methods(path).is_some();
+        if is_route {
+            let location = match uri.query() {
+                Some(query) => format!("{}?{}", path, query),
+                None => path.to_string(),
+            };
+            return Redirect::permanent(&location).into_response();
+        }
+    }
+
+    (StatusCode::NOT_FOUND, Html("<h1>404 - Page Not Found</h1>")).into_response()
+}
+
+/// 405 with the methods the route answers in `Allow`
+#[instrument(skip(routes))]
+pub async fn method_not_allowed(
+    Extension(routes): Extension<Arc<RouteTable>>,
+    method: Method,
+    uri: Uri,
+) -> AppError {
+    let allowed = routes.allowed_methods(uri.path()).unwrap_or_default();
+    AppError::MethodNotAllowed {
+        method: method.to_string(),
+        allowed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request, http::header, Router};
+    use tower::util::ServiceExt;
+
+    use crate::routes::Routes;
+
+    async fn ok() {}
+
+    fn app(redirect: bool) -> Router {
+        let (router, routes) = Routes::<()>::new()
+            .get("/api/users", ok)
+            .post("/api/users", ok)
+            .delete("/api/users/:id", ok)
+            .into_parts();
+        router
+            .method_not_allowed_fallback(method_not_allowed)
+            .fallback(not_found)
+            .layer(Extension(Arc::new(routes)))
+            .layer(Extension(TrailingSlash { redirect }))
+    }
+
+    async fn send(app: Router, method: Method, uri: &str) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_wrong_methods_get_405_with_allow() {
+        let response = send(app(true), Method::POST, "/api/users/1").await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "DELETE, OPTIONS");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "METHOD_NOT_ALLOWED");
+        assert_eq!(problem["status"], 405);
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slashes_redirect_to_the_route() {
+        let response = send(app(true), Method::GET, "/api/users/?page=2").await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()[header::LOCATION], "/api/users?page=2");
+
+        let response = send(app(true), Method::POST, "/api/users//").await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(response.headers()[header::LOCATION], "/api/users");
+
+        for uri in ["/api/unknown/", "//api/users/", "/"] {
+            let response = send(app(true), Method::GET, uri).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+        }
+        let response = send(app(false), Method::GET, "/api/users/").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,11 +1,44 @@
-use axum::{response::IntoResponse, Json};
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde_json::json;
 
-/// Health check endpoint
-/// Returns system status and current timestamp
-pub async fn health() -> impl IntoResponse {
+use crate::health::HealthRegistry;
+
+/// Liveness endpoint
+/// The process is up and serving; dependencies are not checked, so a
+/// database outage never gets the pod restarted
+pub async fn live() -> impl IntoResponse {
     Json(json!({
         "status": "ok",
         "timestamp": chrono::Utc::now()
     }))
-}
\ No newline at end of file
+}
+
+/// Readiness endpoint
+/// Runs every registered dependency check; 503 when a required one is down.
+/// Optional dependencies being down reports "degraded" with 200.
+pub async fn ready(State(health): State<Arc<HealthRegistry>>) -> impl IntoResponse {
+    let report = health.run().await;
+    let status = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
+
+/// Startup endpoint
+/// 503 until every required dependency has been up once, including the
+/// migrations; 200 from then on, with the current per-dependency report
+pub async fn startup(State(health): State<Arc<HealthRegistry>>) -> impl IntoResponse {
+    let report = health.run().await;
+    let status = if health.has_started() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
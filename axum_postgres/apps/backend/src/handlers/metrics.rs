@@ -0,0 +1,14 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use crate::metrics::Metrics;
+
+/// Prometheus scrape endpoint
+/// GET /metrics
+///
+/// Mounted outside the middleware, so scrapes are neither traced nor counted.
+pub async fn metrics(State(metrics): State<Metrics>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+}
@@ -1,2 +1,16 @@
+pub mod auth;
+pub mod admin;
+pub mod admin_api_keys;
+pub mod admin_users;
+pub mod api_keys;
+pub mod audit;
+pub mod diagnostics;
+pub mod fallback;
 pub mod health;
-pub mod users;
\ No newline at end of file
+pub mod metrics;
+pub mod roles;
+pub mod scim;
+pub mod status;
+pub mod tenants;
+pub mod users;
+pub mod ws;
\ No newline at end of file
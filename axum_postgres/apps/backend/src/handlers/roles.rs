@@ -0,0 +1,201 @@
+use axum::{extract::State, http::StatusCode, Json};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa;
+
+use crate::audit::{entity, Audit, AuditAction, AuditEvent};
+use crate::auth::api_key::Scope;
+use crate::auth::role::{Admin, RequireRole, Role};
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::models::role::RoleResponse;
+use crate::models::user::{User, UserResponse};
+use crate::models::user_id::UserId;
+use crate::repository::roles::{RoleRepository, RoleRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+
+/// Route templates
+pub const ROLES_PATH: &str = "/api/admin/roles";
+pub const ROLE_USERS_PATH: &str = "/api/admin/roles/:role/users";
+pub const ROLE_USER_PATH: &str = "/api/admin/roles/:role/users/:id";
+
+/// List roles
+/// GET /api/admin/roles
+#[utoipa::path(
+    get,
+    path = "/api/admin/roles",
+    responses(
+        (status = 200, description = "Every role, by name", body = Vec<RoleResponse>),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = [])),
+    tag = "roles"
+)]
+#[instrument(skip(pool))]
+pub async fn list_roles(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+) -> Result<Json<Vec<RoleResponse>>, AppError> {
+    let roles = RoleRepository::new(pool).list().await.map_err(|e| {
+        error!("Database error listing roles: {:?}", e);
+        AppError::InternalServerError("Failed to list roles".to_string())
+    })?;
+    Ok(Json(roles.into_iter().map(Into::into).collect()))
+}
+
+/// List the users holding a role
+/// GET /api/admin/roles/{role}/users
+#[utoipa::path(
+    get,
+    path = "/api/admin/roles/{role}/users",
+    params(
+        ("role" = Role, Path, description = "Role name")
+    ),
+    responses(
+        (status = 200, description = "Users holding the role, oldest grant first", body = Vec<UserResponse>),
+        (status = 400, description = "Unknown role", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the users:read scope", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:read"])),
+    tag = "roles"
+)]
+#[instrument(skip(pool))]
+pub async fn list_role_users(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    Path(role): Path<Role>,
+) -> Result<Json<Vec<UserResponse>>, AppError> {
+    principal.require(Scope::UsersRead)?;
+    let users = RoleRepository::new(pool).members(role).await.map_err(|e| {
+        error!("Database error listing {} role members: {:?}", role, e);
+        AppError::InternalServerError("Failed to list role members".to_string())
+    })?;
+    Ok(Json(users.into_iter().map(UserResponse::from).collect()))
+}
+
+/// Grant a role to a user
+/// PUT /api/admin/roles/{role}/users/{id}
+///
+/// Granting a role the user already holds changes nothing.
+#[utoipa::path(
+    put,
+    path = "/api/admin/roles/{role}/users/{id}",
+    params(
+        ("role" = Role, Path, description = "Role name"),
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 204, description = "The user holds the role"),
+        (status = 400, description = "Unknown role or invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the roles:write scope", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["roles:write"])),
+    tag = "roles"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn grant_role(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path((role, user_id)): Path<(Role, UserId)>,
+) -> Result<StatusCode, AppError> {
+    principal.require(Scope::RolesWrite)?;
+    let user = live_user(&pool, user_id).await?;
+    let granted = RoleRepository::new(pool)
+        .grant(role, &user)
+        .await
+        .map_err(|e| {
+            error!("Database error granting {} role: {:?}", role, e);
+            AppError::InternalServerError("Failed to grant role".to_string())
+        })?;
+    if granted {
+        info!("Granted {} role to user ID {}", role, user_id);
+        audit
+            .record(
+                AuditEvent::new(AuditAction::Create, entity::ROLE_GRANT, grant_id(role, user_id))
+                    .by_principal(&principal),
+            )
+            .await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Take a role away from a user
+/// DELETE /api/admin/roles/{role}/users/{id}
+#[utoipa::path(
+    delete,
+    path = "/api/admin/roles/{role}/users/{id}",
+    params(
+        ("role" = Role, Path, description = "Role name"),
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 204, description = "Role revoked"),
+        (status = 400, description = "Unknown role or invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the roles:write scope", body = ErrorResponse),
+        (status = 404, description = "User not found or does not hold the role", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["roles:write"])),
+    tag = "roles"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn revoke_role(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path((role, user_id)): Path<(Role, UserId)>,
+) -> Result<StatusCode, AppError> {
+    principal.require(Scope::RolesWrite)?;
+    let user = live_user(&pool, user_id).await?;
+    match RoleRepository::new(pool).revoke(role, &user).await {
+        Ok(true) => {
+            info!("Revoked {} role from user ID {}", role, user_id);
+            audit
+                .record(
+                    AuditEvent::new(AuditAction::Delete, entity::ROLE_GRANT, grant_id(role, user_id))
+                        .by_principal(&principal),
+                )
+                .await;
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(false) => {
+            warn!("User ID {} does not hold the {} role", user_id, role);
+            Err(AppError::NotFound(format!(
+                "User does not hold the {} role",
+                role
+            )))
+        }
+        Err(e) => {
+            error!("Database error revoking {} role: {:?}", role, e);
+            Err(AppError::InternalServerError(
+                "Failed to revoke role".to_string(),
+            ))
+        }
+    }
+}
+
+/// Audit log ID of a role held by a user
+pub(crate) fn grant_id(role: Role, user_id: UserId) -> String {
+    format!("{}:{}", role, user_id)
+}
+
+/// Live user by ID, or 404
+pub(crate) async fn live_user(pool: &PgPool, user_id: UserId) -> Result<User, AppError> {
+    UserRepository::new(pool.clone())
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| {
+            error!("Database error loading user {}: {:?}", user_id, e);
+            AppError::InternalServerError("Failed to load user".to_string())
+        })?
+        .ok_or(AppError::UserNotFound)
+}
@@ -0,0 +1,496 @@
+//! SCIM 2.0 provisioning endpoints under `/scim/v2/Users`
+//!
+//! Identity providers (Okta, Azure AD) call these endpoints with a shared
+//! bearer token configured through `SCIM_BEARER_TOKEN`. Deprovisioning is
+//! either a PATCH setting `active` to false or a DELETE, depending on how the
+//! provider is configured.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa::IntoParams;
+use validator::{Validate, ValidationErrors};
+
+use crate::audit::{Audit, AuditAction, AuditEvent, SCIM_ACTOR};
+use crate::database::DbPools;
+use crate::error::AppError;
+use crate::middleware::bearer_token_matches;
+use crate::models::scim::{
+    ScimErrorResponse, ScimFilter, ScimListResponse, ScimPatchRequest, ScimRequestError, ScimUser,
+    ERROR_SCHEMA, LIST_RESPONSE_SCHEMA,
+};
+use crate::models::user::{normalize_email, CreateUserRequest, UpdateUserRequest, User};
+use crate::models::user_id::UserId;
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+
+/// Media type of every SCIM response
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+/// Page size used when the provider does not send `count`
+const DEFAULT_PAGE_SIZE: usize = 100;
+/// Largest page a single list request may return
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// SCIM error type, rendered as a SCIM error body
+#[derive(Debug)]
+pub enum ScimError {
+    /// Malformed request, with its SCIM `scimType`
+    BadRequest {
+        scim_type: &'static str,
+        detail: String,
+    },
+    /// Missing or wrong bearer token
+    Unauthorized,
+    /// Resource does not exist
+    NotFound(String),
+    /// userName already taken
+    Conflict(String),
+    /// Generic internal server error
+    InternalServerError(String),
+}
+
+impl From<ScimRequestError> for ScimError {
+    fn from(e: ScimRequestError) -> Self {
+        ScimError::BadRequest {
+            scim_type: e.scim_type,
+            detail: e.detail,
+        }
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        let (status, scim_type, detail) = match self {
+            ScimError::BadRequest { scim_type, detail } => {
+                tracing::warn!("SCIM bad request: {}", detail);
+                (StatusCode::BAD_REQUEST, Some(scim_type), detail)
+            }
+            ScimError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                None,
+                "Missing or invalid bearer token".to_string(),
+            ),
+            ScimError::NotFound(detail) => (StatusCode::NOT_FOUND, None, detail),
+            ScimError::Conflict(detail) => (StatusCode::CONFLICT, Some("uniqueness"), detail),
+            ScimError::InternalServerError(detail) => {
+                tracing::error!("SCIM internal server error: {}", detail);
+                (StatusCode::INTERNAL_SERVER_ERROR, None, detail)
+            }
+        };
+
+        let body = ScimErrorResponse {
+            schemas: vec![ERROR_SCHEMA.to_string()],
+            status: status.as_u16().to_string(),
+            scim_type: scim_type.map(str::to_string),
+            detail,
+        };
+
+        scim_response(status, body)
+    }
+}
+
+impl std::fmt::Display for ScimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScimError::BadRequest { scim_type, detail } => {
+                write!(f, "Bad request ({}): {}", scim_type, detail)
+            }
+            ScimError::Unauthorized => write!(f, "Unauthorized"),
+            ScimError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            ScimError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            ScimError::InternalServerError(msg) => write!(f, "Internal server error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ScimError {}
+
+/// Query parameters of `GET /scim/v2/Users`
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ScimListQuery {
+    /// Filter such as `userName eq "jane@example.com"`
+    pub filter: Option<String>,
+    /// 1-based index of the first result
+    pub start_index: Option<usize>,
+    /// Maximum number of results
+    pub count: Option<usize>,
+}
+
+/// SCIM bearer token from `SCIM_BEARER_TOKEN`; SCIM is disabled when unset
+pub fn bearer_token_from_env() -> Option<Arc<str>> {
+    std::env::var("SCIM_BEARER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(Arc::from)
+}
+
+/// Reject requests that do not carry the configured SCIM bearer token
+pub async fn require_bearer_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !bearer_token_matches(request.headers(), &token) {
+        warn!("Rejected SCIM request without a valid bearer token");
+        return ScimError::Unauthorized.into_response();
+    }
+
+    next.run(request).await
+}
+
+fn scim_response<T: Serialize>(status: StatusCode, body: T) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)],
+        Json(body),
+    )
+        .into_response()
+}
+
+fn validation_error(errors: ValidationErrors) -> ScimError {
+    ScimError::BadRequest {
+        scim_type: "invalidValue",
+        detail: format!(
+            "Validation errors: {}",
+            errors
+                .field_errors()
+                .iter()
+                .map(|(field, errors)| format!("{}: {}", field, errors[0]))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn database_error(action: &str, e: sqlx::Error) -> ScimError {
+    error!("Database error during SCIM {}: {:?}", action, e);
+    match AppError::from(e) {
+        AppError::EmailTaken => ScimError::Conflict("userName is already in use".to_string()),
+        _ => ScimError::InternalServerError(format!("Failed to {} user", action)),
+    }
+}
+
+/// SCIM ids that are not integers cannot exist
+fn parse_id(id: &str) -> Result<UserId, ScimError> {
+    id.parse::<UserId>()
+        .map_err(|_| ScimError::NotFound(format!("User {} not found", id)))
+}
+
+async fn apply_update(
+    repo: &UserRepository,
+    audit: &Audit,
+    user_id: UserId,
+    update: UpdateUserRequest,
+) -> Result<Response, ScimError> {
+    update.validate().map_err(validation_error)?;
+
+    let before = repo
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| database_error("update", e))?
+        .ok_or_else(|| ScimError::NotFound(format!("User {} not found", user_id)))?;
+    match repo.update_user(user_id, update).await {
+        Ok(Some(user)) => {
+            info!("SCIM updated user ID {}", user.user_id());
+            audit
+                .record(
+                    AuditEvent::user(AuditAction::Update, &user)
+                        .by(SCIM_ACTOR.to_string())
+                        .before(before.to_response())
+                        .after(user.clone().to_response()),
+                )
+                .await;
+            Ok(scim_response(StatusCode::OK, ScimUser::from_user(&user)))
+        }
+        Ok(None) => Err(ScimError::NotFound(format!("User {} not found", user_id))),
+        Err(e) => Err(database_error("update", e)),
+    }
+}
+
+/// List users
+/// GET /scim/v2/Users
+#[utoipa::path(
+    get,
+    path = "/scim/v2/Users",
+    params(ScimListQuery),
+    responses(
+        (status = 200, description = "Matching users", body = ScimListResponse, content_type = "application/scim+json"),
+        (status = 400, description = "Unsupported filter", body = ScimErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ScimErrorResponse)
+    ),
+    security(("scim_token" = [])),
+    tag = "scim"
+)]
+#[instrument(skip(pool))]
+pub async fn list_users(
+    State(pool): State<DbPools>,
+    Query(query): Query<ScimListQuery>,
+) -> Result<Response, ScimError> {
+    let filter = query.filter.as_deref().map(ScimFilter::parse).transpose()?;
+    let repo = UserRepository::new(pool);
+
+    let users: Vec<User> = match &filter {
+        Some(ScimFilter::UserName(email)) => repo
+            .find_user_by_email(email)
+            .await
+            .map(|user| user.into_iter().collect()),
+        _ => repo.list_users().await,
+    }
+    .map_err(|e| database_error("list", e))?;
+
+    let matching: Vec<User> = users
+        .into_iter()
+        .filter(|user| filter.as_ref().is_none_or(|f| f.matches(user)))
+        .collect();
+
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query.count.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let resources: Vec<ScimUser> = matching
+        .iter()
+        .skip(start_index - 1)
+        .take(count)
+        .map(ScimUser::from_user)
+        .collect();
+
+    info!(
+        "SCIM listed {} of {} users",
+        resources.len(),
+        matching.len()
+    );
+    Ok(scim_response(
+        StatusCode::OK,
+        ScimListResponse {
+            schemas: vec![LIST_RESPONSE_SCHEMA.to_string()],
+            total_results: matching.len(),
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        },
+    ))
+}
+
+/// Provision a user
+/// POST /scim/v2/Users
+#[utoipa::path(
+    post,
+    path = "/scim/v2/Users",
+    request_body(content = ScimUser, content_type = "application/scim+json"),
+    responses(
+        (status = 201, description = "User provisioned", body = ScimUser, content_type = "application/scim+json"),
+        (status = 400, description = "Invalid resource", body = ScimErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ScimErrorResponse),
+        (status = 409, description = "userName already in use", body = ScimErrorResponse)
+    ),
+    security(("scim_token" = [])),
+    tag = "scim"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn create_user(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Json(payload): Json<ScimUser>,
+) -> Result<Response, ScimError> {
+    let request = CreateUserRequest {
+        name: payload.resolved_name(),
+        email: normalize_email(&payload.user_name),
+        ..Default::default()
+    };
+    request.validate().map_err(validation_error)?;
+
+    info!("SCIM provisioning user: {}", request.email);
+    let repo = UserRepository::new(pool);
+
+    let existing = repo
+        .find_user_by_email(&request.email)
+        .await
+        .map_err(|e| database_error("create", e))?;
+    if existing.is_some() {
+        return Err(ScimError::Conflict(
+            "userName is already in use".to_string(),
+        ));
+    }
+
+    let mut user = repo
+        .create_user(request)
+        .await
+        .map_err(|e| database_error("create", e))?;
+
+    if payload.active == Some(false) {
+        let deactivate = UpdateUserRequest {
+            name: None,
+            email: None,
+            active: Some(false),
+            ..Default::default()
+        };
+        user = repo
+            .update_user(user.user_id(), deactivate)
+            .await
+            .map_err(|e| database_error("create", e))?
+            .unwrap_or(user);
+    }
+
+    info!("SCIM provisioned user ID {}", user.user_id());
+    audit
+        .record(
+            AuditEvent::user(AuditAction::Create, &user)
+                .by(SCIM_ACTOR.to_string())
+                .after(user.clone().to_response()),
+        )
+        .await;
+    Ok(scim_response(
+        StatusCode::CREATED,
+        ScimUser::from_user(&user),
+    ))
+}
+
+/// Get a user
+/// GET /scim/v2/Users/{id}
+#[utoipa::path(
+    get,
+    path = "/scim/v2/Users/{id}",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "User found", body = ScimUser, content_type = "application/scim+json"),
+        (status = 401, description = "Missing or invalid bearer token", body = ScimErrorResponse),
+        (status = 404, description = "User not found", body = ScimErrorResponse)
+    ),
+    security(("scim_token" = [])),
+    tag = "scim"
+)]
+#[instrument(skip(pool))]
+pub async fn get_user(
+    State(pool): State<DbPools>,
+    Path(id): Path<String>,
+) -> Result<Response, ScimError> {
+    let user_id = parse_id(&id)?;
+    let repo = UserRepository::new(pool);
+
+    match repo.get_user_by_id(user_id).await {
+        Ok(Some(user)) => Ok(scim_response(StatusCode::OK, ScimUser::from_user(&user))),
+        Ok(None) => Err(ScimError::NotFound(format!("User {} not found", id))),
+        Err(e) => Err(database_error("get", e)),
+    }
+}
+
+/// Replace a user
+/// PUT /scim/v2/Users/{id}
+#[utoipa::path(
+    put,
+    path = "/scim/v2/Users/{id}",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    request_body(content = ScimUser, content_type = "application/scim+json"),
+    responses(
+        (status = 200, description = "User replaced", body = ScimUser, content_type = "application/scim+json"),
+        (status = 400, description = "Invalid resource", body = ScimErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ScimErrorResponse),
+        (status = 404, description = "User not found", body = ScimErrorResponse),
+        (status = 409, description = "userName already in use", body = ScimErrorResponse)
+    ),
+    security(("scim_token" = [])),
+    tag = "scim"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn replace_user(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Path(id): Path<String>,
+    Json(payload): Json<ScimUser>,
+) -> Result<Response, ScimError> {
+    let user_id = parse_id(&id)?;
+    info!("SCIM replacing user ID {}", user_id);
+    apply_update(&UserRepository::new(pool), &audit, user_id, payload.to_update()).await
+}
+
+/// Patch a user
+/// PATCH /scim/v2/Users/{id}
+#[utoipa::path(
+    patch,
+    path = "/scim/v2/Users/{id}",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    request_body(content = ScimPatchRequest, content_type = "application/scim+json"),
+    responses(
+        (status = 200, description = "User patched", body = ScimUser, content_type = "application/scim+json"),
+        (status = 400, description = "Invalid operation", body = ScimErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ScimErrorResponse),
+        (status = 404, description = "User not found", body = ScimErrorResponse),
+        (status = 409, description = "userName already in use", body = ScimErrorResponse)
+    ),
+    security(("scim_token" = [])),
+    tag = "scim"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn patch_user(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Path(id): Path<String>,
+    Json(payload): Json<ScimPatchRequest>,
+) -> Result<Response, ScimError> {
+    let user_id = parse_id(&id)?;
+    let update = payload.to_update()?;
+    info!("SCIM patching user ID {}", user_id);
+    apply_update(&UserRepository::new(pool), &audit, user_id, update).await
+}
+
+/// Deprovision a user
+/// DELETE /scim/v2/Users/{id}
+#[utoipa::path(
+    delete,
+    path = "/scim/v2/Users/{id}",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 204, description = "User deleted"),
+        (status = 401, description = "Missing or invalid bearer token", body = ScimErrorResponse),
+        (status = 404, description = "User not found", body = ScimErrorResponse)
+    ),
+    security(("scim_token" = [])),
+    tag = "scim"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn delete_user(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Path(id): Path<String>,
+) -> Result<Response, ScimError> {
+    let user_id = parse_id(&id)?;
+    let repo = UserRepository::new(pool);
+
+    let before = repo
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|e| database_error("delete", e))?
+        .ok_or_else(|| ScimError::NotFound(format!("User {} not found", id)))?;
+    match repo.delete_user(user_id).await {
+        Ok(true) => {
+            info!("SCIM deprovisioned user ID {}", user_id);
+            audit
+                .record(
+                    AuditEvent::user(AuditAction::Delete, &before)
+                        .by(SCIM_ACTOR.to_string())
+                        .before(before.to_response()),
+                )
+                .await;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        Ok(false) => Err(ScimError::NotFound(format!("User {} not found", id))),
+        Err(e) => Err(database_error("delete", e)),
+    }
+}
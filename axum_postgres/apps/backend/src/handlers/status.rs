@@ -0,0 +1,64 @@
+use axum::{extract::State, Json};
+use sqlx::PgPool;
+use tracing::{error, instrument};
+use utoipa;
+
+use crate::auth::role::{Admin, RequireRole};
+use crate::auth::session::AuthMode;
+use crate::middleware::envelope::ResponseEnvelope;
+use crate::middleware::read_only::ReadOnlyMode;
+use crate::status::{BuildInfo, PoolStatus, ServiceStatus, Uptime};
+
+/// Route templates
+pub const ADMIN_STATUS_PATH: &str = "/api/admin/status";
+
+/// Report the build, uptime, database and switches of the service
+/// GET /api/admin/status
+///
+/// Answers even when the database is down, with `migrations` null.
+#[utoipa::path(
+    get,
+    path = "/api/admin/status",
+    responses(
+        (status = 200, description = "Status of the running service", body = ServiceStatus),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = [])),
+    tag = "status"
+)]
+#[instrument(skip_all)]
+pub async fn get_status(
+    State(pool): State<PgPool>,
+    State(uptime): State<Uptime>,
+    State(read_only): State<ReadOnlyMode>,
+    State(envelope): State<ResponseEnvelope>,
+    State(auth_mode): State<AuthMode>,
+    _admin: RequireRole<Admin>,
+) -> Json<ServiceStatus> {
+    let migrations = crate::database::migration_state(&pool)
+        .await
+        .map_err(|e| error!("Failed to read the migration history: {:?}", e))
+        .ok()
+        .map(Into::into);
+    let idle = pool.num_idle() as u32;
+
+    Json(ServiceStatus {
+        build: BuildInfo::current(),
+        started_at: uptime.started_at(),
+        uptime_secs: uptime.seconds(),
+        pool: PoolStatus {
+            size: pool.size(),
+            idle,
+            max: pool.options().get_max_connections(),
+        },
+        migrations,
+        flags: [
+            ("read_only", read_only.is_enabled()),
+            ("response_envelope", envelope.is_enabled()),
+            ("cookie_sessions", auth_mode == AuthMode::Cookie),
+        ]
+        .into_iter()
+        .collect(),
+    })
+}
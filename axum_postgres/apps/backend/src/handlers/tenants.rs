@@ -0,0 +1,195 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use sqlx::PgPool;
+use tracing::{error, info, instrument, warn};
+use utoipa;
+use validator::Validate;
+
+use crate::audit::{entity, Audit, AuditAction, AuditEvent, ADMIN_TOKEN_ACTOR};
+use crate::error::AppError;
+use crate::extract::Path;
+use crate::models::tenant::{CreateTenantRequest, TenantResponse, UpdateTenantRequest};
+use crate::repository::tenants::{TenantRepository, TenantRepositoryTrait};
+
+/// Route templates
+pub const TENANTS_PATH: &str = "/admin/tenants";
+pub const TENANT_PATH: &str = "/admin/tenants/:id";
+
+/// List tenants
+/// GET /admin/tenants
+#[utoipa::path(
+    get,
+    path = "/admin/tenants",
+    responses(
+        (status = 200, description = "Every tenant, oldest first", body = Vec<TenantResponse>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "tenants"
+)]
+#[instrument(skip(pool))]
+pub async fn list_tenants(State(pool): State<PgPool>) -> Result<impl IntoResponse, AppError> {
+    let tenants = TenantRepository::new(pool).list().await.map_err(|e| {
+        error!("Database error listing tenants: {:?}", e);
+        AppError::InternalServerError("Failed to list tenants".to_string())
+    })?;
+    Ok(Json(
+        tenants
+            .into_iter()
+            .map(TenantResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Create a tenant
+/// POST /admin/tenants
+///
+/// The slug names the tenant in `X-Tenant-Id` and as a subdomain of
+/// TENANT_DOMAIN; it cannot be changed later.
+#[utoipa::path(
+    post,
+    path = "/admin/tenants",
+    request_body = CreateTenantRequest,
+    responses(
+        (status = 201, description = "Tenant created", body = TenantResponse),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 409, description = "Slug already taken", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "tenants"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn create_tenant(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Json(payload): Json<CreateTenantRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        warn!("Tenant validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let tenant = TenantRepository::new(pool)
+        .create(&payload)
+        .await
+        .map_err(|e| match AppError::from(e) {
+            AppError::Conflict(_) => {
+                AppError::Conflict(format!("Tenant slug '{}' is taken", payload.slug))
+            }
+            e => {
+                error!("Database error creating tenant: {:?}", e);
+                AppError::InternalServerError("Failed to create tenant".to_string())
+            }
+        })?;
+    info!("Created tenant {} ({})", tenant.id, tenant.slug);
+
+    let tenant = TenantResponse::from(tenant);
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Create, entity::TENANT, tenant.id)
+                .by(ADMIN_TOKEN_ACTOR.to_string())
+                .after(&tenant),
+        )
+        .await;
+    Ok((StatusCode::CREATED, Json(tenant)))
+}
+
+/// Get a tenant
+/// GET /admin/tenants/{id}
+#[utoipa::path(
+    get,
+    path = "/admin/tenants/{id}",
+    params(
+        ("id" = i32, Path, description = "Tenant ID")
+    ),
+    responses(
+        (status = 200, description = "The tenant", body = TenantResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Tenant not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "tenants"
+)]
+#[instrument(skip(pool))]
+pub async fn get_tenant(
+    State(pool): State<PgPool>,
+    Path(id): Path<i32>,
+) -> Result<impl IntoResponse, AppError> {
+    let tenant = TenantRepository::new(pool)
+        .get(id)
+        .await
+        .map_err(|e| {
+            error!("Database error getting tenant {}: {:?}", id, e);
+            AppError::InternalServerError("Failed to get tenant".to_string())
+        })?
+        .ok_or_else(|| AppError::NotFound("Tenant not found".to_string()))?;
+    Ok(Json(TenantResponse::from(tenant)))
+}
+
+/// Rename, suspend or reactivate a tenant
+/// PATCH /admin/tenants/{id}
+///
+/// Requests for a suspended tenant get 403 until it is active again; its
+/// users and data are kept.
+#[utoipa::path(
+    patch,
+    path = "/admin/tenants/{id}",
+    params(
+        ("id" = i32, Path, description = "Tenant ID")
+    ),
+    request_body = UpdateTenantRequest,
+    responses(
+        (status = 200, description = "Tenant updated", body = TenantResponse),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Tenant not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = [])),
+    tag = "tenants"
+)]
+#[instrument(skip(pool, audit))]
+pub async fn update_tenant(
+    State(pool): State<PgPool>,
+    audit: Audit,
+    Path(id): Path<i32>,
+    Json(payload): Json<UpdateTenantRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = payload.validate() {
+        warn!("Tenant validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let repo = TenantRepository::new(pool);
+    let database_error = |e: sqlx::Error| {
+        error!("Database error updating tenant {}: {:?}", id, e);
+        AppError::InternalServerError("Failed to update tenant".to_string())
+    };
+    let before = repo
+        .get(id)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::NotFound("Tenant not found".to_string()))?;
+    let tenant = repo
+        .update(id, &payload)
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::NotFound("Tenant not found".to_string()))?;
+    if before.active && !tenant.active {
+        warn!("Suspended tenant {} ({})", tenant.id, tenant.slug);
+    }
+
+    let tenant = TenantResponse::from(tenant);
+    audit
+        .record(
+            AuditEvent::new(AuditAction::Update, entity::TENANT, id)
+                .by(ADMIN_TOKEN_ACTOR.to_string())
+                .before(TenantResponse::from(before))
+                .after(&tenant),
+        )
+        .await;
+    Ok(Json(tenant))
+}
@@ -1,17 +1,110 @@
+use std::collections::HashSet;
+
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    body::{Body, BodyDataStream},
+    extract::{Query, RawQuery, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use serde_json::Value;
 use sqlx::PgPool;
 use tracing::{info, warn, error, instrument};
 use utoipa;
 use validator::Validate;
 
+use crate::audit::{Audit, AuditAction, AuditEvent};
+use crate::auth::api_key::Scope;
+use crate::auth::policy::{self, Actor};
+use crate::auth::role::{Admin, RequireRole};
+use crate::auth::session::AuthMode;
+use crate::auth::{AuthUser, Principal};
+use crate::cache::TieredCache;
+use crate::conditional::{check_if_match, none_match, user_etag, user_last_modified};
+use crate::database::DbPools;
 use crate::error::AppError;
-use crate::models::user::{CreateUserRequest, UpdateUserRequest, UserResponse};
+use crate::export::{self, ExportQuery};
+use crate::extract::Path;
+use crate::handlers::auth::{sign_out_everywhere, signed_out};
+use crate::import;
+use crate::middleware::admin::IncludeDeleted;
+use crate::middleware::request_id::{RequestId, REQUEST_ID_HEADER};
+use crate::models::links::{CollectionLinks, Link, ResponseLinks, UserLinks};
+use crate::models::merge_patch;
+use crate::models::user::{
+    CreateUserRequest, EmailAvailability, EmailCheckQuery, ImportCounts, ImportStatus, ImportSummary, RejectedRow,
+    ReplaceUserRequest, User,
+    UserCollection, UserCount, UserList,
+    UserListQuery, UserPage, UserResponse, UserSearchQuery,
+};
+use crate::models::user_history::UserHistoryEntry;
+use crate::models::user_id::UserId;
+use crate::pagination::{
+    Cursor, CursorQuery, ItemRange, DEFAULT_PAGE_SIZE, MAX_RANGE_ITEMS, RANGE_UNIT,
+};
+use crate::repository::cached_user::CachedUserRepository;
 use crate::repository::user::{UserRepository, UserRepositoryTrait};
+use crate::repository::user_imports::{UserImportRepository, UserImportRepositoryTrait};
+use crate::tenant;
+
+/// Route templates, shared by the router and the generated `_links`
+pub const USERS_PATH: &str = "/api/users";
+pub const USER_PATH: &str = "/api/users/:id";
+pub const USER_HISTORY_PATH: &str = "/api/users/:id/history";
+pub const USER_SEARCH_PATH: &str = "/api/users/search";
+pub const USER_RESTORE_PATH: &str = "/api/users/:id/restore";
+pub const USER_COUNT_PATH: &str = "/api/users/count";
+pub const USER_EMAIL_CHECK_PATH: &str = "/api/users/check-email";
+pub const USER_IMPORT_PATH: &str = "/api/users/import";
+pub const USER_IMPORT_STATUS_PATH: &str = "/api/users/import/:request_id";
+pub const USER_EXPORT_PATH: &str = "/api/users/export";
+pub const USER_ACTIVATE_PATH: &str = "/api/users/:id/activate";
+pub const USER_DEACTIVATE_PATH: &str = "/api/users/:id/deactivate";
+pub const ME_PATH: &str = "/api/me";
+
+/// Links for one user
+pub fn user_links(id: &str) -> UserLinks {
+    UserLinks {
+        self_link: Link::to("GET", USER_PATH, Some(id)),
+        update: Link::to("PUT", USER_PATH, Some(id)),
+        delete: Link::to("DELETE", USER_PATH, Some(id)),
+        history: Link::to("GET", USER_HISTORY_PATH, Some(id)),
+    }
+}
+
+/// Links for the whole user listing; cursor pages add `next`
+pub fn collection_links() -> CollectionLinks {
+    CollectionLinks {
+        self_link: Link::to("GET", USERS_PATH, None),
+        create: Link::to("POST", USERS_PATH, None),
+        next: None,
+        prev: None,
+    }
+}
+
+/// Attach links to a user when RESPONSE_LINKS is enabled
+fn linked(user: UserResponse, links: ResponseLinks) -> UserResponse {
+    if links.is_enabled() {
+        let id = user.id.clone();
+        user.with_links(user_links(&id))
+    } else {
+        user
+    }
+}
+
+/// One user with its ETag, so the client can send it back in `If-Match`,
+/// and its `Last-Modified`
+fn user_response(status: StatusCode, user: User, links: ResponseLinks) -> Response {
+    let etag = user_etag(&user);
+    let last_modified = user_last_modified(&user);
+    let body = linked(user.to_response(), links);
+    (
+        status,
+        [(header::ETAG, etag), (header::LAST_MODIFIED, last_modified)],
+        Json(body),
+    )
+        .into_response()
+}
 
 /// Create new user
 /// POST /api/users
@@ -20,50 +113,480 @@ use crate::repository::user::{UserRepository, UserRepositoryTrait};
     path = "/api/users",
     request_body = CreateUserRequest,
     responses(
-        (status = 201, description = "User created successfully", body = UserResponse),
+        (status = 201, description = "User created successfully", body = UserResponse,
+            headers(("ETag" = String, description = "Current version of the user"))),
         (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "API key lacks the required scope", body = ErrorResponse),
+        (status = 409, description = "Email already used by another user, in any letter case", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
     tag = "users"
 )]
-#[instrument(skip(pool))]
+#[instrument(skip(pool, cache, audit))]
 pub async fn create_user(
     State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    principal: Principal,
+    audit: Audit,
     Json(payload): Json<CreateUserRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    principal.require(Scope::UsersWrite)?;
     info!("Creating new user: {}", payload.email);
 
     // Validate request
     if let Err(errors) = payload.validate() {
         warn!("User creation validation failed: {:?}", errors);
-        return Err(AppError::BadRequest(format!(
-            "Validation errors: {}",
-            errors
-                .field_errors()
-                .iter()
-                .map(|(field, errors)| format!("{}: {}", field, errors[0]))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )));
+        return Err(AppError::Validation(errors));
     }
 
-    let repo = UserRepository::new(pool);
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
 
     match repo.create_user(payload).await {
         Ok(user) => {
-            info!("User created successfully with ID: {}", user.id);
-            let response = user.to_response();
-            Ok((StatusCode::CREATED, Json(response)))
+            info!("User created successfully with ID: {}", user.user_id());
+            audit
+                .record(
+                    AuditEvent::user(AuditAction::Create, &user)
+                        .by_principal(&principal)
+                        .after(user.clone().to_response()),
+                )
+                .await;
+            Ok(user_response(StatusCode::CREATED, user, links))
         }
         Err(e) => {
             error!("Database error creating user: {:?}", e);
-            if e.to_string().contains("duplicate key") || e.to_string().contains("unique constraint") {
-                Err(AppError::BadRequest("Email address already exists".to_string()))
-            } else {
-                Err(AppError::InternalServerError("Failed to create user".to_string()))
-            }
+            Err(e.into())
+        }
+    }
+}
+
+/// Import users from a CSV upload
+/// POST /api/users/import
+///
+/// Takes `multipart/form-data` with the CSV in the `file` field. The header
+/// row names the columns: `name` and `email` are required, the profile
+/// fields optional. The file is read as it arrives and its valid rows are
+/// inserted in batches of 1000, each committed on its own; every other row
+/// is counted, and the first 1000 are listed with the line they start on,
+/// so one bad row does not block the rest. Uploads are limited to
+/// MAX_UPLOAD_BYTES, 4 GB by default, and have no timeout.
+///
+/// An import that fails part way keeps the batches it committed, and
+/// uploading the file again rejects their rows as taken. Progress is stored
+/// under the request's `X-Request-Id` and can be followed from another
+/// connection at `GET /api/users/import/{request_id}`. Admins only.
+#[utoipa::path(
+    post,
+    path = "/api/users/import",
+    request_body(content = String, content_type = "multipart/form-data",
+        description = "CSV in the `file` field, e.g. `name,email` then `Jane Doe,jane@example.com`"),
+    responses(
+        (status = 200, description = "Valid rows imported, the others counted", body = ImportSummary,
+            headers(("X-Request-Id" = String, description = "Key of the import's progress"))),
+        (status = 400, description = "Not a multipart upload, no `file` field, or an unusable header or row", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the required scope", body = ErrorResponse),
+        (status = 409, description = "An import with this X-Request-Id is still running", body = ErrorResponse),
+        (status = 413, description = "Upload larger than MAX_UPLOAD_BYTES", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:import"])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit, headers, body))]
+pub async fn import_users(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, AppError> {
+    principal.require(Scope::UsersImport)?;
+    let boundary = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(import::multipart_boundary)
+        .ok_or_else(|| AppError::BadRequest("Expected a multipart/form-data upload".to_string()))?
+        .to_string();
+
+    let request_id = audit
+        .context
+        .request_id
+        .clone()
+        .unwrap_or_else(|| RequestId::generate().0);
+    let imports = UserImportRepository::new(pool.clone());
+    let import_id = imports
+        .start(&request_id)
+        .await
+        .map_err(|e| {
+            error!("Database error starting user import {}: {:?}", request_id, e);
+            AppError::InternalServerError("Failed to import users".to_string())
+        })?
+        .ok_or_else(|| {
+            warn!("User import {} is still running", request_id);
+            AppError::Conflict("An import with this X-Request-Id is still running".to_string())
+        })?;
+    info!("Importing users as import {}", request_id);
+
+    // Like exports, the import runs outside the request's database deadline,
+    // which would cut a large upload short
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+    let batches = import::UserBatches::new(body.into_data_stream(), &boundary);
+    let imported = tokio::spawn(tenant::scope(tenant::current(), async move {
+        let mut counts = ImportCounts::default();
+        let result =
+            import_batches(&repo, &imports, import_id, batches, &audit, &principal, &mut counts)
+                .await;
+        let error = result.as_ref().err().map(ToString::to_string);
+        if let Err(e) = imports.finish(import_id, counts, error.as_deref()).await {
+            warn!("Failed to record the end of user import {}: {:?}", import_id, e);
+        }
+        result.map(|rejected| ImportSummary {
+            processed: counts.processed,
+            created: counts.created,
+            rejected,
+        })
+    }))
+    .await
+    .map_err(|e| {
+        error!("User import {} panicked: {:?}", request_id, e);
+        AppError::InternalServerError("Failed to import users".to_string())
+    })?;
+
+    let summary = match imported {
+        Ok(summary) => summary,
+        // Rewritten into problem details with the limit
+        Err(e) if e.is_too_large() => return Ok(StatusCode::PAYLOAD_TOO_LARGE.into_response()),
+        Err(import::ImportError::Database(e)) => {
+            error!("Database error importing users: {:?}", e);
+            return Err(AppError::InternalServerError("Failed to import users".to_string()));
+        }
+        Err(e) => return Err(AppError::BadRequest(e.to_string())),
+    };
+    info!(
+        "Imported {} of {} users in import {}",
+        summary.created, summary.processed, request_id
+    );
+    let mut response = Json(summary).into_response();
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(response)
+}
+
+/// Insert the batches of an upload, keeping `counts` and the stored
+/// progress up to date; the rejected rows listed in the summary
+async fn import_batches(
+    repo: &CachedUserRepository<UserRepository>,
+    imports: &UserImportRepository,
+    import_id: i64,
+    mut batches: import::UserBatches<BodyDataStream>,
+    audit: &Audit,
+    principal: &Principal,
+    counts: &mut ImportCounts,
+) -> Result<Vec<RejectedRow>, import::ImportError> {
+    let mut listed = Vec::new();
+    while let Some(batch) = batches.next_batch().await? {
+        let processed = batch.rows();
+        let (rows, users): (Vec<usize>, Vec<CreateUserRequest>) = batch.users.into_iter().unzip();
+        let emails: Vec<String> = users.iter().map(|user| user.email.clone()).collect();
+
+        let created = repo
+            .import_users(users)
+            .await
+            .map_err(import::ImportError::Database)?;
+        audit
+            .record_all(
+                created
+                    .iter()
+                    .map(|user| {
+                        AuditEvent::user(AuditAction::Create, user)
+                            .by_principal(principal)
+                            .after(user.clone().to_response())
+                    })
+                    .collect(),
+            )
+            .await;
+
+        // Rows left out by the insert had an email that was already taken
+        let inserted: HashSet<&str> = created.iter().map(|user| user.email.as_str()).collect();
+        let mut rejected = batch.rejected;
+        rejected.extend(
+            rows.into_iter()
+                .zip(&emails)
+                .filter(|(_, email)| !inserted.contains(email.as_str()))
+                .map(|(row, _)| RejectedRow {
+                    row,
+                    errors: vec!["email: Email address already exists".to_string()],
+                }),
+        );
+        rejected.sort_by_key(|row| row.row);
+
+        counts.processed += processed;
+        counts.created += created.len();
+        counts.rejected += rejected.len();
+        let room = import::MAX_LISTED_REJECTIONS.saturating_sub(listed.len());
+        listed.extend(rejected.into_iter().take(room));
+        if let Err(e) = imports.record_progress(import_id, *counts).await {
+            warn!("Failed to record the progress of user import {}: {:?}", import_id, e);
+        }
+    }
+    Ok(listed)
+}
+
+/// Get the progress of a user import
+/// GET /api/users/import/{request_id}
+///
+/// Keyed by the `X-Request-Id` of the upload, which is echoed in its
+/// response. Counts are stored after every batch, so they lag the upload
+/// by up to 1000 rows while it runs. Admins only.
+#[utoipa::path(
+    get,
+    path = "/api/users/import/{request_id}",
+    params(
+        ("request_id" = String, Path, description = "X-Request-Id of the upload")
+    ),
+    responses(
+        (status = 200, description = "Progress of the import", body = ImportStatus),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the required scope", body = ErrorResponse),
+        (status = 404, description = "No import with this request ID", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:import"])),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn get_import_status(
+    State(pool): State<PgPool>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    Path(request_id): Path<String>,
+) -> Result<Json<ImportStatus>, AppError> {
+    principal.require(Scope::UsersImport)?;
+    let status = UserImportRepository::new(pool)
+        .find(&request_id)
+        .await
+        .map_err(|e| {
+            error!("Database error getting user import {}: {:?}", request_id, e);
+            AppError::InternalServerError("Failed to get user import".to_string())
+        })?
+        .ok_or_else(|| {
+            warn!("No user import {}", request_id);
+            AppError::NotFound("Import not found".to_string())
+        })?;
+    Ok(Json(status))
+}
+
+/// Export users as NDJSON
+/// GET /api/users/export?format=ndjson
+///
+/// Streams every matching user, one JSON object per line in id order,
+/// without holding them in memory, so exports of any size take the same
+/// resources. Takes the filters of the listing; sort parameters are
+/// ignored. A failure once rows were sent cuts the body short. Admins only.
+#[utoipa::path(
+    get,
+    path = "/api/users/export",
+    params(ExportQuery, UserListQuery),
+    responses(
+        (status = 200, description = "Matching users, one per line", body = UserResponse, content_type = "application/x-ndjson"),
+        (status = 400, description = "Unknown format or filter", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the users:read scope", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:read"])),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn export_users(
+    State(pool): State<DbPools>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    // Only `ndjson` deserializes, so other formats are rejected here
+    Query(_format): Query<ExportQuery>,
+    Query(query): Query<UserListQuery>,
+) -> Result<Response, AppError> {
+    principal.require(Scope::UsersRead)?;
+    info!("Exporting users");
+
+    let mut chunks = export::export_users(pool, query);
+    // Wait for the first rows so an unreachable database is still an error
+    let first = match chunks.recv().await {
+        Some(Err(e)) => {
+            error!("Database error exporting users: {:?}", e);
+            return Err(AppError::InternalServerError("Failed to export users".to_string()));
+        }
+        first => first,
+    };
+    Ok((
+        [(header::CONTENT_TYPE, export::NDJSON)],
+        export::body(first, chunks),
+    )
+        .into_response())
+}
+
+/// Get the signed-in user
+/// GET /api/me
+#[utoipa::path(
+    get,
+    path = "/api/me",
+    params(
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a previous response")
+    ),
+    responses(
+        (status = 200, description = "The signed-in user", body = UserResponse,
+            headers(("ETag" = String, description = "Changes whenever the user is written"))),
+        (status = 304, description = "User unchanged since the given ETag"),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 404, description = "The user was deleted since signing in", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "users"
+)]
+#[instrument(skip(pool, headers))]
+pub async fn get_me(
+    State(pool): State<PgPool>,
+    State(links): State<ResponseLinks>,
+    auth: AuthUser,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let user = current_user(&UserRepository::new(pool), auth.id, "get").await?;
+    let etag = user_etag(&user);
+    if none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let mut response = user_response(StatusCode::OK, user, links);
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(response)
+}
+
+/// Replace the signed-in user
+/// PUT /api/me
+///
+/// Same as `PUT /api/users/{id}` with one's own ID, so `active` can only be
+/// changed by admins.
+#[utoipa::path(
+    put,
+    path = "/api/me",
+    params(
+        ("If-Match" = Option<String>, Header, description = "Only replace the user if its ETag still matches")
+    ),
+    request_body = ReplaceUserRequest,
+    responses(
+        (status = 200, description = "User updated successfully", body = UserResponse,
+            headers(("ETag" = String, description = "New version of the user"))),
+        (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 403, description = "Changing `active` without the admin role, or the email with an impersonation token", body = ErrorResponse),
+        (status = 404, description = "The user was deleted since signing in", body = ErrorResponse),
+        (status = 409, description = "Email already used by another user, in any letter case", body = ErrorResponse),
+        (status = 412, description = "User changed since the `If-Match` ETag", body = ErrorResponse),
+        (status = 422, description = "A required field is missing"),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit, headers))]
+pub async fn update_me(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    auth: AuthUser,
+    audit: Audit,
+    headers: HeaderMap,
+    Json(payload): Json<ReplaceUserRequest>,
+) -> Result<Response, AppError> {
+    info!("Replacing own user ID: {}", auth.id);
+
+    let actor = Actor::load(&pool, &Principal::from(auth)).await?;
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+    let current = current_user(&repo, auth.id, "update").await?;
+    check_if_match(&headers, &user_etag(&current))?;
+    policy::check_user_edit(&actor, &current, payload.active)?;
+    check_email_change(&Principal::from(auth), &current, &payload.email)?;
+    let user = save_user(&repo, auth.id, payload).await?;
+    audit
+        .record(
+            AuditEvent::user(AuditAction::Update, &user)
+                .by_user(auth)
+                .before(current.to_response())
+                .after(user.clone().to_response()),
+        )
+        .await;
+    Ok(user_response(StatusCode::OK, user, links))
+}
+
+/// Delete the signed-in user
+/// DELETE /api/me
+///
+/// Soft delete, as `DELETE /api/users/{id}`, which only admins may call for
+/// others. Signs the user out everywhere.
+#[utoipa::path(
+    delete,
+    path = "/api/me",
+    params(
+        ("If-Match" = Option<String>, Header, description = "Only delete the user if its ETag still matches")
+    ),
+    responses(
+        (status = 204, description = "User deleted and signed out"),
+        (status = 401, description = "Missing or invalid access token or session", body = ErrorResponse),
+        (status = 403, description = "Signed in with an impersonation token", body = ErrorResponse),
+        (status = 404, description = "The user was deleted since signing in", body = ErrorResponse),
+        (status = 412, description = "User changed since the `If-Match` ETag", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit, headers))]
+pub async fn delete_me(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(mode): State<AuthMode>,
+    auth: AuthUser,
+    audit: Audit,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    auth.require_own_sign_in("delete the account")?;
+    info!("Deleting own user ID: {}", auth.id);
+
+    let repo = CachedUserRepository::new(UserRepository::new(pool.clone()), cache);
+    let current = current_user(&repo, auth.id, "delete").await?;
+    check_if_match(&headers, &user_etag(&current))?;
+
+    match repo.delete_user(auth.id).await {
+        Ok(true) => {}
+        Ok(false) => {
+            warn!("User not found for deletion: ID {}", auth.id);
+            return Err(AppError::UserNotFound);
+        }
+        Err(e) => {
+            error!("Database error deleting user: {:?}", e);
+            return Err(AppError::InternalServerError("Failed to delete user".to_string()));
         }
     }
+    sign_out_everywhere(&pool, &current).await.map_err(|e| {
+        error!("Database error signing out everywhere: {:?}", e);
+        AppError::InternalServerError("Failed to delete user".to_string())
+    })?;
+
+    audit
+        .record(
+            AuditEvent::user(AuditAction::Delete, &current)
+                .by_user(auth)
+                .before(current.to_response()),
+        )
+        .await;
+    info!("User deleted their account: ID {}", auth.id);
+    Ok((StatusCode::NO_CONTENT, signed_out(mode)))
 }
 
 /// Get user by ID
@@ -72,37 +595,56 @@ pub async fn create_user(
     get,
     path = "/api/users/{id}",
     params(
-        ("id" = String, Path, description = "User ID")
+        ("id" = String, Path, description = "User ID"),
+        ("include_deleted" = Option<bool>, Query, description = "Admin only: also return a soft-deleted user"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a previous response")
     ),
     responses(
-        (status = 200, description = "User found", body = UserResponse),
+        (status = 200, description = "User found", body = UserResponse,
+            headers(("ETag" = String, description = "Changes whenever the user is written"))),
+        (status = 304, description = "User unchanged since the given ETag"),
         (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "include_deleted without the admin token", body = ErrorResponse),
         (status = 404, description = "User not found", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "users"
 )]
-#[instrument(skip(pool))]
+#[instrument(skip(pool, cache, headers))]
 pub async fn get_user_by_id(
-    State(pool): State<PgPool>,
-    Path(id): Path<String>,
-) -> Result<impl IntoResponse, AppError> {
-    let user_id = id.parse::<i32>()
-        .map_err(|_| AppError::BadRequest("Invalid user ID format".to_string()))?;
-
+    State(pool): State<DbPools>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    IncludeDeleted(include_deleted): IncludeDeleted,
+    Path(user_id): Path<UserId>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     info!("Getting user by ID: {}", user_id);
 
-    let repo = UserRepository::new(pool);
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+    let user = if include_deleted {
+        repo.get_user_including_deleted(user_id).await
+    } else {
+        repo.get_user_by_id(user_id).await
+    };
 
-    match repo.get_user_by_id(user_id).await {
+    match user {
         Ok(Some(user)) => {
+            let etag = user_etag(&user);
+            if none_match(&headers, &etag) {
+                info!("User unchanged ({})", etag);
+                return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+            }
             info!("User found: {}", user.email);
-            let response = user.to_response();
-            Ok((StatusCode::OK, Json(response)))
+            let mut response = user_response(StatusCode::OK, user, links);
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+            Ok(response)
         }
         Ok(None) => {
             warn!("User not found: ID {}", user_id);
-            Err(AppError::NotFound("User not found".to_string()))
+            Err(AppError::UserNotFound)
         }
         Err(e) => {
             error!("Database error getting user: {:?}", e);
@@ -111,32 +653,164 @@ pub async fn get_user_by_id(
     }
 }
 
+/// Check that a user exists
+/// HEAD /api/users/{id}
+///
+/// Answers from an index lookup without loading the user, so it carries no
+/// ETag or Content-Length; GET the user for those.
+#[utoipa::path(
+    head,
+    path = "/api/users/{id}",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "User exists"),
+        (status = 400, description = "Invalid user ID format"),
+        (status = 404, description = "User not found"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn user_exists(
+    State(pool): State<DbPools>,
+    Path(user_id): Path<UserId>,
+) -> Result<StatusCode, AppError> {
+    let repo = UserRepository::new(pool);
+
+    match repo.exists(user_id).await {
+        Ok(true) => Ok(StatusCode::OK),
+        Ok(false) => Err(AppError::UserNotFound),
+        Err(e) => {
+            error!("Database error checking user: {:?}", e);
+            Err(AppError::InternalServerError("Failed to check user".to_string()))
+        }
+    }
+}
+
 /// List all users
 /// GET /api/users
+///
+/// Answers 304 when `If-None-Match` carries the current collection ETag, so
+/// pollers skip reading and serializing an unchanged table. `Range: items=`
+/// returns a 206 slice instead of the whole list, and `?cursor=` or
+/// `?limit=` switches to keyset pages that stay fast on large tables.
+/// Filters and sort order apply to all three.
 #[utoipa::path(
     get,
     path = "/api/users",
+    params(
+        UserListQuery,
+        CursorQuery,
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a previous listing"),
+        ("Range" = Option<String>, Header, description = "Slice of the listing, e.g. `items=0-99`, `items=100-` or `items=-10`; at most 1000 items are returned")
+    ),
     responses(
-        (status = 200, description = "List of users", body = Vec<UserResponse>),
+        (status = 200, description = "List of users; wrapped with `_links` when RESPONSE_LINKS is enabled", body = UserList,
+            headers(("ETag" = String, description = "Changes whenever any user is written"))),
+        (status = 206, description = "Slice selected by `Range`", body = UserList,
+            headers(("Content-Range" = String, description = "Returned slice and total, e.g. `items 0-99/1234`"))),
+        (status = 304, description = "Listing unchanged since the given ETag"),
+        (status = 400, description = "Invalid cursor, or a cursor with a non-default sort order", body = ErrorResponse),
+        (status = 401, description = "include_deleted without the admin token", body = ErrorResponse),
+        (status = 416, description = "Range starts past the last user", body = ErrorResponse,
+            headers(("Content-Range" = String, description = "Total count, e.g. `items */1234`"))),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
     tag = "users"
 )]
-#[instrument(skip(pool))]
+#[instrument(skip(pool, cache, headers))]
+// One extractor per listing feature; grouping them would only hide that
+#[allow(clippy::too_many_arguments)]
 pub async fn list_users(
-    State(pool): State<PgPool>,
-) -> Result<impl IntoResponse, AppError> {
+    State(pool): State<DbPools>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    // Only checks the admin token; the flag itself travels in UserListQuery
+    _include_deleted: IncludeDeleted,
+    Query(query): Query<UserListQuery>,
+    Query(page): Query<CursorQuery>,
+    RawQuery(raw_query): RawQuery,
+    range: Option<ItemRange>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     info!("Listing all users");
 
-    let repo = UserRepository::new(pool);
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+
+    // Read the version first: a write racing the listing then yields an
+    // older tag with newer rows, which only costs one extra refetch
+    let etag = match repo.users_version().await {
+        Ok(version) => format!("W/\"users-{}\"", version),
+        Err(e) => {
+            error!("Database error reading users version: {:?}", e);
+            return Err(AppError::InternalServerError("Failed to list users".to_string()));
+        }
+    };
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+
+    if none_match(&headers, &etag) {
+        info!("User listing unchanged ({})", etag);
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    if page.is_requested() {
+        let body = list_users_by_cursor(&repo, &query, &page, raw_query.as_deref(), links).await?;
+        return Ok((StatusCode::OK, cache_headers, Json(body)).into_response());
+    }
+
+    let (users, content_range) = match range {
+        None => (repo.list_users_page(&query, 0, None).await, None),
+        Some(range) => {
+            let total = repo.count_users(&query).await.map_err(|e| {
+                error!("Database error counting users: {:?}", e);
+                AppError::InternalServerError("Failed to list users".to_string())
+            })?;
+            let Some(window) = range.window(total) else {
+                warn!("Unsatisfiable range {:?} for {} users", range, total);
+                return Err(AppError::RangeNotSatisfiable { total });
+            };
+            let users = repo
+                .list_users_page(&query, window.offset, Some(window.limit))
+                .await;
+            let content_range = users
+                .as_ref()
+                .map(|users| window.content_range(users.len(), total))
+                .ok();
+            (users, content_range)
+        }
+    };
 
-    match repo.list_users().await {
+    match users {
         Ok(users) => {
             info!("Retrieved {} users", users.len());
             let responses = users.into_iter()
-                .map(|user| user.to_response())
+                .map(|user| linked(user.to_response(), links))
                 .collect::<Vec<UserResponse>>();
-            Ok((StatusCode::OK, Json(responses)))
+            let body = if links.is_enabled() {
+                UserList::Linked(UserCollection {
+                    data: responses,
+                    links: collection_links(),
+                })
+            } else {
+                UserList::Plain(responses)
+            };
+
+            let mut response = (StatusCode::OK, cache_headers, Json(body)).into_response();
+            response
+                .headers_mut()
+                .insert(header::ACCEPT_RANGES, HeaderValue::from_static(RANGE_UNIT));
+            if let Some(content_range) = content_range {
+                *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_RANGE, content_range);
+            }
+            Ok(response)
         }
         Err(e) => {
             error!("Database error listing users: {:?}", e);
@@ -145,111 +819,742 @@ pub async fn list_users(
     }
 }
 
-/// Update user by ID
+/// One keyset page of the listing, ending with the cursor of its last user
+async fn list_users_by_cursor(
+    repo: &impl UserRepositoryTrait,
+    query: &UserListQuery,
+    page: &CursorQuery,
+    raw_query: Option<&str>,
+    links: ResponseLinks,
+) -> Result<UserList, AppError> {
+    // The cursor names a position in the newest-first order only
+    if !query.is_default_order() {
+        return Err(AppError::BadRequest(
+            "Cursor pagination requires the default sort order".to_string(),
+        ));
+    }
+    let after = match &page.cursor {
+        Some(token) => Some(
+            Cursor::decode(token)
+                .ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))?,
+        ),
+        None => None,
+    };
+    let limit = page.page_size();
+
+    // One extra row tells whether another page follows
+    let mut users = repo.list_users_after(query, after, limit + 1).await.map_err(|e| {
+        error!("Database error listing users: {:?}", e);
+        AppError::InternalServerError("Failed to list users".to_string())
+    })?;
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|user| {
+            Cursor {
+                created_at: user.created_at,
+                id: user.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+    info!("Retrieved page of {} users", users.len());
+
+    let collection = links.is_enabled().then(|| CollectionLinks {
+        next: next_cursor.as_ref().map(|cursor| Link {
+            href: next_page_href(raw_query, cursor, limit),
+            method: "GET".to_string(),
+        }),
+        ..collection_links()
+    });
+    Ok(UserList::Page(UserPage {
+        data: users
+            .into_iter()
+            .map(|user| linked(user.to_response(), links))
+            .collect(),
+        next_cursor,
+        links: collection,
+    }))
+}
+
+/// The current request with `cursor` and `limit` replaced, keeping filters
+fn next_page_href(raw_query: Option<&str>, cursor: &str, limit: i64) -> String {
+    let mut pairs = raw_query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| {
+            let name = pair.split('=').next().unwrap_or_default();
+            !name.is_empty() && name != "cursor" && name != "limit"
+        })
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    pairs.push(format!("cursor={}", cursor));
+    pairs.push(format!("limit={}", limit));
+    format!("{}?{}", USERS_PATH, pairs.join("&"))
+}
+
+/// Count users
+/// GET /api/users/count
+///
+/// Takes the same filters as the listing; sort parameters are ignored.
+#[utoipa::path(
+    get,
+    path = "/api/users/count",
+    params(UserListQuery),
+    responses(
+        (status = 200, description = "Number of matching users", body = UserCount),
+        (status = 401, description = "include_deleted without the admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn count_users(
+    State(pool): State<DbPools>,
+    // Only checks the admin token; the flag itself travels in UserListQuery
+    _include_deleted: IncludeDeleted,
+    Query(query): Query<UserListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let repo = UserRepository::new(pool);
+
+    match repo.count_users(&query).await {
+        Ok(count) => {
+            info!("Counted {} users", count);
+            Ok((StatusCode::OK, Json(UserCount { count })))
+        }
+        Err(e) => {
+            error!("Database error counting users: {:?}", e);
+            Err(AppError::InternalServerError("Failed to count users".to_string()))
+        }
+    }
+}
+
+/// Check whether an email address is free
+/// GET /api/users/check-email
+///
+/// Lets a sign-up form validate before submitting. Matching ignores case, and
+/// addresses of soft-deleted users count as free. Creating the user can
+/// still fail if someone takes the address in between.
+#[utoipa::path(
+    get,
+    path = "/api/users/check-email",
+    params(EmailCheckQuery),
+    responses(
+        (status = 200, description = "Whether the address can be used", body = EmailAvailability),
+        (status = 400, description = "Missing or malformed email", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn check_email(
+    State(pool): State<DbPools>,
+    Query(query): Query<EmailCheckQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Err(errors) = query.validate() {
+        warn!("Email check validation failed: {:?}", errors);
+        return Err(AppError::Validation(errors));
+    }
+
+    let repo = UserRepository::new(pool);
+
+    match repo.email_exists(&query.email).await {
+        Ok(taken) => {
+            info!("Email {} available: {}", query.email, !taken);
+            Ok((StatusCode::OK, Json(EmailAvailability { available: !taken })))
+        }
+        Err(e) => {
+            error!("Database error checking email: {:?}", e);
+            Err(AppError::InternalServerError("Failed to check email".to_string()))
+        }
+    }
+}
+
+/// Search users by name and email
+/// GET /api/users/search
+///
+/// Ranked full-text search; name matches outrank email matches.
+#[utoipa::path(
+    get,
+    path = "/api/users/search",
+    params(UserSearchQuery),
+    responses(
+        (status = 200, description = "Matching users, best match first", body = Vec<UserResponse>),
+        (status = 400, description = "Missing or empty query", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn search_users(
+    State(pool): State<DbPools>,
+    State(links): State<ResponseLinks>,
+    Query(search): Query<UserSearchQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let terms = search.q.trim();
+    if terms.is_empty() {
+        return Err(AppError::BadRequest("Search query must not be empty".to_string()));
+    }
+    let limit = search.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_RANGE_ITEMS);
+
+    info!("Searching users for {:?}", terms);
+
+    let repo = UserRepository::new(pool);
+
+    match repo.search_users(terms, limit).await {
+        Ok(users) => {
+            info!("Search matched {} users", users.len());
+            let responses = users.into_iter()
+                .map(|user| linked(user.to_response(), links))
+                .collect::<Vec<UserResponse>>();
+            Ok((StatusCode::OK, Json(responses)))
+        }
+        Err(e) => {
+            error!("Database error searching users: {:?}", e);
+            Err(AppError::InternalServerError("Failed to search users".to_string()))
+        }
+    }
+}
+
+/// Replace user by ID
 /// PUT /api/users/{id}
+///
+/// Every field is required; use PATCH to change only some of them. Users
+/// may edit themselves; only admins edit others or change `active`.
 #[utoipa::path(
     put,
     path = "/api/users/{id}",
     params(
-        ("id" = String, Path, description = "User ID")
+        ("id" = String, Path, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "Only replace the user if its ETag still matches")
     ),
-    request_body = UpdateUserRequest,
+    request_body = ReplaceUserRequest,
     responses(
-        (status = 200, description = "User updated successfully", body = UserResponse),
+        (status = 200, description = "User updated successfully", body = UserResponse,
+            headers(("ETag" = String, description = "New version of the user"))),
         (status = 400, description = "Validation error", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Editing another user or changing `active` without the admin role, changing the email with an impersonation token, or the API key lacks the required scope", body = ErrorResponse),
         (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 409, description = "Email already used by another user, in any letter case", body = ErrorResponse),
+        (status = 412, description = "User changed since the `If-Match` ETag", body = ErrorResponse),
+        (status = 422, description = "A required field is missing"),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
     tag = "users"
 )]
-#[instrument(skip(pool))]
+#[instrument(skip(pool, cache, audit, headers))]
+#[allow(clippy::too_many_arguments)]
 pub async fn update_user(
     State(pool): State<PgPool>,
-    Path(id): Path<String>,
-    Json(payload): Json<UpdateUserRequest>,
-) -> Result<impl IntoResponse, AppError> {
-    let user_id = id.parse::<i32>()
-        .map_err(|_| AppError::BadRequest("Invalid user ID format".to_string()))?;
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    principal: Principal,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+    headers: HeaderMap,
+    Json(payload): Json<ReplaceUserRequest>,
+) -> Result<Response, AppError> {
+    principal.require(Scope::UsersWrite)?;
+    info!("Replacing user ID: {}", user_id);
+
+    let actor = Actor::load(&pool, &principal).await?;
+    if !policy::can_edit_user(&actor, user_id) {
+        return Err(AppError::Forbidden("You can only edit your own user".to_string()));
+    }
+
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+    let current = current_user(&repo, user_id, "update").await?;
+    check_if_match(&headers, &user_etag(&current))?;
+    policy::check_user_edit(&actor, &current, payload.active)?;
+    check_email_change(&principal, &current, &payload.email)?;
+    let user = save_user(&repo, user_id, payload).await?;
+    audit
+        .record(
+            AuditEvent::user(AuditAction::Update, &user)
+                .by_principal(&principal)
+                .before(current.to_response())
+                .after(user.clone().to_response()),
+        )
+        .await;
+    Ok(user_response(StatusCode::OK, user, links))
+}
 
-    info!("Updating user ID: {}", user_id);
+/// Patch user by ID
+/// PATCH /api/users/{id}
+///
+/// The body is a JSON Merge Patch (RFC 7396): members present replace the
+/// stored values and absent ones are kept. `null` clears a profile field;
+/// name, email and active are required, so `null` for them is rejected.
+/// Users may patch themselves; only admins patch others or change `active`.
+#[utoipa::path(
+    patch,
+    path = "/api/users/{id}",
+    params(
+        ("id" = String, Path, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "Only patch the user if its ETag still matches")
+    ),
+    request_body(content = UpdateUserRequest, content_type = "application/merge-patch+json",
+        description = "Fields to change; `application/json` is accepted too"),
+    responses(
+        (status = 200, description = "User updated successfully", body = UserResponse,
+            headers(("ETag" = String, description = "New version of the user"))),
+        (status = 400, description = "Validation error, unknown field or null for a required field", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Editing another user or changing `active` without the admin role, changing the email with an impersonation token, or the API key lacks the required scope", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 409, description = "Email already used by another user, in any letter case", body = ErrorResponse),
+        (status = 412, description = "User changed since the `If-Match` ETag", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit, headers))]
+#[allow(clippy::too_many_arguments)]
+pub async fn patch_user(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    principal: Principal,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+    headers: HeaderMap,
+    Json(patch): Json<Value>,
+) -> Result<Response, AppError> {
+    principal.require(Scope::UsersWrite)?;
+    let Value::Object(fields) = &patch else {
+        return Err(AppError::BadRequest("Merge patch must be a JSON object".to_string()));
+    };
+    if let Some(field) = fields.keys().find(|field| !PATCHABLE_FIELDS.contains(&field.as_str())) {
+        return Err(AppError::BadRequest(format!("Field '{}' cannot be patched", field)));
+    }
+    if let Some((field, _)) = fields
+        .iter()
+        .find(|(field, value)| value.is_null() && REQUIRED_FIELDS.contains(&field.as_str()))
+    {
+        return Err(AppError::BadRequest(format!("Field '{}' cannot be null", field)));
+    }
+
+    info!("Patching user ID: {}", user_id);
+
+    let actor = Actor::load(&pool, &principal).await?;
+    if !policy::can_edit_user(&actor, user_id) {
+        return Err(AppError::Forbidden("You can only edit your own user".to_string()));
+    }
+
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+
+    let current = current_user(&repo, user_id, "update").await?;
+    check_if_match(&headers, &user_etag(&current))?;
 
+    let mut document = serde_json::json!({
+        "name": current.name,
+        "email": current.email,
+        "active": current.active,
+        "display_name": current.display_name,
+        "bio": current.bio,
+        "phone": current.phone,
+        "timezone": current.timezone,
+        "locale": current.locale,
+    });
+    merge_patch::apply(&mut document, patch);
+    let replacement: ReplaceUserRequest = serde_json::from_value(document)
+        .map_err(|e| AppError::BadRequest(format!("Invalid patch: {}", e)))?;
+    policy::check_user_edit(&actor, &current, replacement.active)?;
+    check_email_change(&principal, &current, &replacement.email)?;
+
+    let user = save_user(&repo, user_id, replacement).await?;
+    audit
+        .record(
+            AuditEvent::user(AuditAction::Update, &user)
+                .by_principal(&principal)
+                .before(current.to_response())
+                .after(user.clone().to_response()),
+        )
+        .await;
+    Ok(user_response(StatusCode::OK, user, links))
+}
+
+/// Members of the user representation a merge patch may set
+const PATCHABLE_FIELDS: [&str; 8] = [
+    "name",
+    "email",
+    "active",
+    "display_name",
+    "bio",
+    "phone",
+    "timezone",
+    "locale",
+];
+
+/// Patchable members that cannot be cleared with `null`
+const REQUIRED_FIELDS: [&str; 3] = ["name", "email", "active"];
+
+/// Load a live user before writing it, for PATCH and `If-Match`
+///
+/// `action` names the write in the 500 message, e.g. "update".
+async fn current_user(repo: &impl UserRepositoryTrait, user_id: UserId, action: &str) -> Result<User, AppError> {
+    match repo.get_user_by_id(user_id).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => {
+            warn!("User not found for {}: ID {}", action, user_id);
+            Err(AppError::UserNotFound)
+        }
+        Err(e) => {
+            error!("Database error getting user: {:?}", e);
+            Err(AppError::InternalServerError(format!("Failed to {} user", action)))
+        }
+    }
+}
+
+/// Reject a change of `current`'s email made with an impersonation token
+///
+/// The admin could otherwise set an address they control and take the
+/// account over with a password reset once the token has expired.
+fn check_email_change(principal: &Principal, current: &User, email: &str) -> Result<(), AppError> {
+    if email == current.email {
+        return Ok(());
+    }
+    principal.require_own_sign_in("change the email")
+}
+
+/// Validate and store a complete user, shared by PUT and PATCH
+async fn save_user(
+    repo: &impl UserRepositoryTrait,
+    user_id: UserId,
+    payload: ReplaceUserRequest,
+) -> Result<User, AppError> {
     // Validate request
     if let Err(errors) = payload.validate() {
         warn!("User update validation failed: {:?}", errors);
-        return Err(AppError::BadRequest(format!(
-            "Validation errors: {}",
-            errors
-                .field_errors()
-                .iter()
-                .map(|(field, errors)| format!("{}: {}", field, errors[0]))
-                .collect::<Vec<_>>()
-                .join(", ")
-        )));
+        return Err(AppError::Validation(errors));
     }
 
-    let repo = UserRepository::new(pool);
-
-    match repo.update_user(user_id, payload).await {
+    match repo.update_user(user_id, payload.into()).await {
         Ok(Some(user)) => {
             info!("User updated successfully: {}", user.email);
-            let response = user.to_response();
-            Ok((StatusCode::OK, Json(response)))
+            Ok(user)
         }
         Ok(None) => {
             warn!("User not found for update: ID {}", user_id);
-            Err(AppError::NotFound("User not found".to_string()))
+            Err(AppError::UserNotFound)
         }
         Err(e) => {
             error!("Database error updating user: {:?}", e);
-            if e.to_string().contains("duplicate key") || e.to_string().contains("unique constraint") {
-                Err(AppError::BadRequest("Email address already exists".to_string()))
-            } else {
-                Err(AppError::InternalServerError("Failed to update user".to_string()))
-            }
+            Err(e.into())
         }
     }
 }
 
 /// Delete user by ID
 /// DELETE /api/users/{id}
+///
+/// Soft delete: the user disappears from reads but is kept, with its
+/// history, until restored through `POST /api/users/{id}/restore`.
+/// Admins only.
 #[utoipa::path(
     delete,
     path = "/api/users/{id}",
     params(
-        ("id" = String, Path, description = "User ID")
+        ("id" = String, Path, description = "User ID"),
+        ("If-Match" = Option<String>, Header, description = "Only delete the user if its ETag still matches")
     ),
     responses(
         (status = 204, description = "User deleted successfully"),
         (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the required scope", body = ErrorResponse),
         (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 412, description = "User changed since the `If-Match` ETag", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
     ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
     tag = "users"
 )]
-#[instrument(skip(pool))]
+#[instrument(skip(pool, cache, audit, headers))]
 pub async fn delete_user(
     State(pool): State<PgPool>,
-    Path(id): Path<String>,
+    State(cache): State<TieredCache>,
+    RequireRole { principal, .. }: RequireRole<Admin>,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, AppError> {
-    let user_id = id.parse::<i32>()
-        .map_err(|_| AppError::BadRequest("Invalid user ID format".to_string()))?;
-
+    principal.require(Scope::UsersWrite)?;
     info!("Deleting user ID: {}", user_id);
 
-    let repo = UserRepository::new(pool);
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+    let current = current_user(&repo, user_id, "delete").await?;
+    check_if_match(&headers, &user_etag(&current))?;
 
     match repo.delete_user(user_id).await {
         Ok(true) => {
             info!("User deleted successfully: ID {}", user_id);
+            audit
+                .record(
+                    AuditEvent::user(AuditAction::Delete, &current)
+                        .by_principal(&principal)
+                        .before(current.to_response()),
+                )
+                .await;
             Ok(StatusCode::NO_CONTENT)
         }
         Ok(false) => {
             warn!("User not found for deletion: ID {}", user_id);
-            Err(AppError::NotFound("User not found".to_string()))
+            Err(AppError::UserNotFound)
         }
         Err(e) => {
             error!("Database error deleting user: {:?}", e);
             Err(AppError::InternalServerError("Failed to delete user".to_string()))
         }
     }
-}
\ No newline at end of file
+}
+
+/// Restore a soft-deleted user
+/// POST /api/users/{id}/restore
+///
+/// Admins only, so a deletion cannot be undone by whoever it was meant to
+/// lock out.
+#[utoipa::path(
+    post,
+    path = "/api/users/{id}/restore",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "User restored", body = UserResponse,
+            headers(("ETag" = String, description = "New version of the user"))),
+        (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the required scope", body = ErrorResponse),
+        (status = 404, description = "No deleted user with this ID", body = ErrorResponse),
+        (status = 409, description = "The email was reused by another user meanwhile", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit))]
+pub async fn restore_user(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    principal: Principal,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+) -> Result<impl IntoResponse, AppError> {
+    principal.require(Scope::UsersWrite)?;
+    if !policy::can_restore_user(&Actor::load(&pool, &principal).await?) {
+        return Err(AppError::Forbidden(
+            "Only admins can restore deleted users".to_string(),
+        ));
+    }
+    info!("Restoring user ID: {}", user_id);
+
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+
+    match repo.restore_user(user_id).await {
+        Ok(Some(user)) => {
+            info!("User restored successfully: ID {}", user_id);
+            audit
+                .record(
+                    AuditEvent::user(AuditAction::Restore, &user)
+                        .by_principal(&principal)
+                        .after(user.clone().to_response()),
+                )
+                .await;
+            Ok(user_response(StatusCode::OK, user, links))
+        }
+        Ok(None) => {
+            warn!("No deleted user to restore: ID {}", user_id);
+            Err(AppError::NotFound("Deleted user not found".to_string()))
+        }
+        Err(e) => {
+            error!("Database error restoring user: {:?}", e);
+            Err(e.into())
+        }
+    }
+}
+
+/// Activate a user
+/// POST /api/users/{id}/activate
+///
+/// A status change of its own, so the history shows it apart from profile
+/// edits. Activating an active user changes nothing. Admins only.
+#[utoipa::path(
+    post,
+    path = "/api/users/{id}/activate",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "User is active", body = UserResponse,
+            headers(("ETag" = String, description = "Current version of the user"))),
+        (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the required scope", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit))]
+pub async fn activate_user(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    principal: Principal,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+) -> Result<Response, AppError> {
+    principal.require(Scope::UsersWrite)?;
+    if !policy::can_set_user_status(&Actor::load(&pool, &principal).await?) {
+        return Err(AppError::Forbidden(
+            "Only admins can activate or deactivate users".to_string(),
+        ));
+    }
+    set_active(pool, cache, links, audit, principal, user_id, true).await
+}
+
+/// Deactivate a user
+/// POST /api/users/{id}/deactivate
+///
+/// The user stays readable; only `active` changes. Deactivating an
+/// inactive user changes nothing. Admins only.
+#[utoipa::path(
+    post,
+    path = "/api/users/{id}/deactivate",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "User is inactive", body = UserResponse,
+            headers(("ETag" = String, description = "Current version of the user"))),
+        (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token, session or API key", body = ErrorResponse),
+        (status = 403, description = "Not an admin, or the API key lacks the required scope", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = []), ("api_key" = ["users:write"])),
+    tag = "users"
+)]
+#[instrument(skip(pool, cache, audit))]
+pub async fn deactivate_user(
+    State(pool): State<PgPool>,
+    State(cache): State<TieredCache>,
+    State(links): State<ResponseLinks>,
+    principal: Principal,
+    audit: Audit,
+    Path(user_id): Path<UserId>,
+) -> Result<Response, AppError> {
+    principal.require(Scope::UsersWrite)?;
+    if !policy::can_set_user_status(&Actor::load(&pool, &principal).await?) {
+        return Err(AppError::Forbidden(
+            "Only admins can activate or deactivate users".to_string(),
+        ));
+    }
+    set_active(pool, cache, links, audit, principal, user_id, false).await
+}
+
+/// Shared by the activate and deactivate endpoints
+async fn set_active(
+    pool: PgPool,
+    cache: TieredCache,
+    links: ResponseLinks,
+    audit: Audit,
+    principal: Principal,
+    user_id: UserId,
+    active: bool,
+) -> Result<Response, AppError> {
+    let action = if active { "activate" } else { "deactivate" };
+
+    info!("Setting user ID {} active={}", user_id, active);
+
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+    let current = current_user(&repo, user_id, action).await?;
+
+    match repo.set_user_active(user_id, active).await {
+        Ok(Some(user)) => {
+            info!("User {}d: ID {}", action, user_id);
+            if user.active != current.active {
+                audit
+                    .record(
+                        AuditEvent::user(AuditAction::Update, &user)
+                            .by_principal(&principal)
+                            .before(current.to_response())
+                            .after(user.clone().to_response()),
+                    )
+                    .await;
+            }
+            Ok(user_response(StatusCode::OK, user, links))
+        }
+        Ok(None) => {
+            warn!("User not found to {}: ID {}", action, user_id);
+            Err(AppError::UserNotFound)
+        }
+        Err(e) => {
+            error!("Database error setting user status: {:?}", e);
+            Err(AppError::InternalServerError(format!("Failed to {} user", action)))
+        }
+    }
+}
+
+/// Get the change history of a user
+/// GET /api/users/{id}/history
+#[utoipa::path(
+    get,
+    path = "/api/users/{id}/history",
+    params(
+        ("id" = String, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "Versions of the user, oldest first", body = Vec<UserHistoryEntry>),
+        (status = 400, description = "Invalid user ID format", body = ErrorResponse),
+        (status = 404, description = "No history for this user", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    tag = "users"
+)]
+#[instrument(skip(pool))]
+pub async fn get_user_history(
+    State(pool): State<DbPools>,
+    Path(user_id): Path<UserId>,
+) -> Result<impl IntoResponse, AppError> {
+    info!("Getting history for user ID: {}", user_id);
+
+    let repo = UserRepository::new(pool);
+
+    match repo.get_user_history(user_id).await {
+        Ok(records) if records.is_empty() => {
+            warn!("No history for user ID {}", user_id);
+            Err(AppError::UserNotFound)
+        }
+        Ok(records) => {
+            info!("Retrieved {} history entries for user ID {}", records.len(), user_id);
+            Ok((StatusCode::OK, Json(UserHistoryEntry::from_records(records))))
+        }
+        Err(e) => {
+            error!("Database error getting user history: {:?}", e);
+            Err(AppError::InternalServerError("Failed to get user history".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_page_href_keeps_filters() {
+        assert_eq!(
+            next_page_href(Some("active=true&cursor=aa&limit=5&name_contains=j%20d"), "bb", 5),
+            "/api/users?active=true&name_contains=j%20d&cursor=bb&limit=5"
+        );
+        assert_eq!(next_page_href(None, "bb", 100), "/api/users?cursor=bb&limit=100");
+    }
+}
@@ -0,0 +1,462 @@
+//! Change events of users, pushed over a WebSocket
+//!
+//! `GET /ws` upgrades to a WebSocket that sends a JSON message for every
+//! change the [`ChangeFeed`] reports on a user of the tenant the caller
+//! signed in to, such as `{"type": "user.updated", "user_id": "7"}`.
+//! Deleting a user through the API is a soft delete and arrives as
+//! `user.deleted`; restoring it as `user.updated`. The events name the user
+//! only; clients fetch it for the rest.
+//!
+//! A connection starts with the subscription of its query string, e.g.
+//! `?events=user.created,user.deleted&user_ids=7,8`, and every event of
+//! every user without one. Sending
+//! `{"type": "subscribe", "events": [...], "user_ids": [...]}` replaces it,
+//! with fields left out meaning all; the server confirms with the
+//! subscription now in effect, `{"type": "subscribed", ...}`. When events
+//! may have been missed, the server sends `{"type": "resync"}` and clients
+//! should reload what they show.
+//!
+//! The server pings every WS_PING_INTERVAL_MS and closes connections it
+//! has not heard from by the next ping.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use metrics::{counter, decrement_gauge, increment_gauge};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, instrument};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::AuthUser;
+use crate::changes::{ChangeEvent, ChangeFeed, ChangeOp, UserChange};
+use crate::error::AppError;
+use crate::events::user::{UserCreatedV1, UserDeletedV1, UserUpdatedV1};
+use crate::events::DomainEvent;
+use crate::models::user_id::UserId;
+
+/// Route template
+pub const WS_PATH: &str = "/ws";
+
+/// Open WebSocket connections
+pub const WS_CONNECTIONS: &str = "ws_connections";
+/// Events sent over WebSockets, by type
+pub const WS_EVENTS_SENT_TOTAL: &str = "ws_events_sent_total";
+
+/// Largest message taken from clients; subscriptions are small
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Default of WS_PING_INTERVAL_MS
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Keepalive of WebSocket connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebSocketConfig {
+    /// Time between pings, and how long a client has to answer one
+    pub ping_interval: Duration,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: DEFAULT_PING_INTERVAL,
+        }
+    }
+}
+
+impl WebSocketConfig {
+    /// Ping every WS_PING_INTERVAL_MS, 30 seconds by default
+    pub fn from_env() -> Result<Self, String> {
+        let Some(value) = std::env::var("WS_PING_INTERVAL_MS")
+            .ok()
+            .filter(|value| !value.is_empty())
+        else {
+            return Ok(Self::default());
+        };
+        match value.trim().parse() {
+            Ok(0) | Err(_) => Err(format!(
+                "WS_PING_INTERVAL_MS must be a positive number of milliseconds: {}",
+                value
+            )),
+            Ok(millis) => Ok(Self {
+                ping_interval: Duration::from_millis(millis),
+            }),
+        }
+    }
+}
+
+/// Kind of change an event reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub enum EventType {
+    #[serde(rename = "user.created")]
+    UserCreated,
+    /// Any change but a delete, including a restore
+    #[serde(rename = "user.updated")]
+    UserUpdated,
+    /// Soft delete or purge
+    #[serde(rename = "user.deleted")]
+    UserDeleted,
+}
+
+impl EventType {
+    pub const ALL: [EventType; 3] = [
+        EventType::UserCreated,
+        EventType::UserUpdated,
+        EventType::UserDeleted,
+    ];
+
+    /// Name of the event, the same as of the domain event
+    pub fn name(self) -> &'static str {
+        match self {
+            EventType::UserCreated => UserCreatedV1::NAME,
+            EventType::UserUpdated => UserUpdatedV1::NAME,
+            EventType::UserDeleted => UserDeletedV1::NAME,
+        }
+    }
+
+    /// Type of the event reporting `change`
+    pub fn of(change: &UserChange) -> Self {
+        match change.op {
+            _ if change.deleted => EventType::UserDeleted,
+            ChangeOp::Delete => EventType::UserDeleted,
+            ChangeOp::Insert => EventType::UserCreated,
+            ChangeOp::Update => EventType::UserUpdated,
+        }
+    }
+}
+
+impl FromStr for EventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EventType::ALL
+            .into_iter()
+            .find(|event| event.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown event type '{}': user.created, user.updated or user.deleted",
+                    s
+                )
+            })
+    }
+}
+
+/// A change to a user, as sent to clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+#[schema(example = json!({"type": "user.updated", "user_id": "7"}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct UserEvent {
+    #[serde(rename = "type")]
+    pub event: EventType,
+    /// Id of the user, as a string like `UserResponse.id`
+    pub user_id: String,
+}
+
+impl From<&UserChange> for UserEvent {
+    fn from(change: &UserChange) -> Self {
+        Self {
+            event: EventType::of(change),
+            user_id: change.id.to_string(),
+        }
+    }
+}
+
+/// Subscription of a new connection; comma-separated lists
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubscriptionQuery {
+    /// Event types to send, all by default
+    #[param(example = "user.created,user.deleted")]
+    pub events: Option<String>,
+    /// Users to send events of, every user of the tenant by default
+    #[param(example = "7,8")]
+    pub user_ids: Option<String>,
+}
+
+/// Events a connection is sent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct Subscription {
+    /// Event types to send; all of them when None
+    pub events: Option<BTreeSet<EventType>>,
+    /// Users to send events of; every user of the tenant when None
+    #[cfg_attr(feature = "camel-case", serde(alias = "user_ids"))]
+    pub user_ids: Option<BTreeSet<UserId>>,
+}
+
+impl Subscription {
+    /// Subscription named by the query string
+    pub fn from_query(query: &SubscriptionQuery) -> Result<Self, String> {
+        fn list<T: FromStr + Ord>(value: &Option<String>) -> Result<Option<BTreeSet<T>>, T::Err> {
+            value
+                .as_deref()
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(str::parse)
+                        .collect()
+                })
+                .transpose()
+        }
+        Ok(Self {
+            events: list(&query.events)?,
+            user_ids: list::<UserId>(&query.user_ids).map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// Whether `change` is to be sent
+    pub fn matches(&self, change: &UserChange) -> bool {
+        self.events
+            .as_ref()
+            .is_none_or(|events| events.contains(&EventType::of(change)))
+            && self
+                .user_ids
+                .as_ref()
+                .is_none_or(|ids| ids.contains(&change.id))
+    }
+}
+
+/// Messages from clients
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    /// Replace the subscription
+    Subscribe(Subscription),
+}
+
+/// Messages about the connection itself
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Notice {
+    /// The subscription now in effect; null lists mean all
+    Subscribed {
+        events: Option<Vec<EventType>>,
+        #[cfg_attr(feature = "camel-case", serde(rename = "userIds"))]
+        user_ids: Option<Vec<String>>,
+    },
+    /// Events may have been missed; anything shown may be stale
+    Resync,
+    /// A message was not understood; the connection stays open
+    Error { message: String },
+}
+
+impl From<&Subscription> for Notice {
+    fn from(subscription: &Subscription) -> Self {
+        Notice::Subscribed {
+            events: subscription
+                .events
+                .as_ref()
+                .map(|events| events.iter().copied().collect()),
+            user_ids: subscription
+                .user_ids
+                .as_ref()
+                .map(|ids| ids.iter().map(UserId::to_string).collect()),
+        }
+    }
+}
+
+/// Stream change events of the tenant's users
+/// GET /ws
+///
+/// Upgrades to a WebSocket; see the module documentation for the messages.
+#[utoipa::path(
+    get,
+    path = "/ws",
+    params(SubscriptionQuery),
+    responses(
+        (status = 101, description = "Switched to a WebSocket sending a UserEvent per change", body = UserEvent),
+        (status = 400, description = "Unknown event type or malformed user ID", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token or session, or one of another tenant", body = ErrorResponse)
+    ),
+    security(("bearer_auth" = []), ("session_cookie" = [])),
+    tag = "events"
+)]
+#[instrument(skip_all)]
+pub async fn subscribe(
+    user: AuthUser,
+    State(changes): State<ChangeFeed>,
+    State(config): State<WebSocketConfig>,
+    Query(query): Query<SubscriptionQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    let subscription = Subscription::from_query(&query).map_err(AppError::BadRequest)?;
+    // Subscribed before answering, so no change after the upgrade is missed
+    let events = changes.subscribe();
+    info!(
+        "User ID {} subscribed to changes in tenant {}",
+        user.id, user.tenant_id
+    );
+    Ok(upgrade
+        .max_message_size(MAX_MESSAGE_BYTES)
+        .on_upgrade(move |socket| async move {
+            increment_gauge!(WS_CONNECTIONS, 1.0);
+            stream(socket, events, user.tenant_id, subscription, config).await;
+            decrement_gauge!(WS_CONNECTIONS, 1.0);
+            debug!("User ID {} unsubscribed from changes", user.id);
+        }))
+}
+
+/// Send the changes `subscription` matches until the client goes away
+async fn stream(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<ChangeEvent>,
+    tenant_id: i32,
+    mut subscription: Subscription,
+    config: WebSocketConfig,
+) {
+    let mut ping = tokio::time::interval_at(
+        tokio::time::Instant::now() + config.ping_interval,
+        config.ping_interval,
+    );
+    // Whether the client has been silent since the last ping
+    let mut silent = false;
+
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(ChangeEvent::User(change)) => {
+                    if change.tenant_id != tenant_id || !subscription.matches(&change) {
+                        continue;
+                    }
+                    let event = UserEvent::from(&change);
+                    counter!(WS_EVENTS_SENT_TOTAL, 1, "type" => event.event.name());
+                    json(&event)
+                }
+                Ok(ChangeEvent::Resync) | Err(RecvError::Lagged(_)) => json(&Notice::Resync),
+                Err(RecvError::Closed) => return,
+            },
+            received = socket.recv() => {
+                silent = false;
+                match received {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                        Ok(ClientMessage::Subscribe(replacement)) => {
+                            subscription = replacement;
+                            json(&Notice::from(&subscription))
+                        }
+                        Err(e) => json(&Notice::Error { message: e.to_string() }),
+                    },
+                    Some(Ok(Message::Binary(_))) => json(&Notice::Error {
+                        message: "Messages must be JSON text".to_string(),
+                    }),
+                    // Pings are answered by the socket itself
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+                }
+            }
+            _ = ping.tick() => {
+                if silent {
+                    debug!("Closing a WebSocket that did not answer its ping");
+                    let _ = socket
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::AWAY,
+                            reason: "Ping timeout".into(),
+                        })))
+                        .await;
+                    return;
+                }
+                silent = true;
+                Message::Ping(Vec::new())
+            }
+        };
+        if socket.send(message).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn json<T: Serialize>(message: &T) -> Message {
+    Message::Text(serde_json::to_string(message).expect("Messages serialize"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(op: ChangeOp, id: &str, deleted: bool) -> UserChange {
+        UserChange {
+            op,
+            id: id.parse().unwrap(),
+            tenant_id: 1,
+            deleted,
+        }
+    }
+
+    #[cfg(not(feature = "uuid-ids"))]
+    const IDS: [&str; 2] = ["7", "8"];
+    #[cfg(feature = "uuid-ids")]
+    const IDS: [&str; 2] = [
+        "0190a2b4-0000-7000-8000-000000000007",
+        "0190a2b4-0000-7000-8000-000000000008",
+    ];
+
+    #[test]
+    fn test_event_types_follow_the_change() {
+        for event in EventType::ALL {
+            assert_eq!(serde_json::to_value(event).unwrap(), event.name());
+            assert_eq!(event.name().parse::<EventType>(), Ok(event));
+        }
+        let [id, _] = IDS;
+        assert_eq!(
+            EventType::of(&change(ChangeOp::Insert, id, false)),
+            EventType::UserCreated
+        );
+        assert_eq!(
+            EventType::of(&change(ChangeOp::Update, id, false)),
+            EventType::UserUpdated
+        );
+        // Soft deletes are updates of the row
+        assert_eq!(
+            EventType::of(&change(ChangeOp::Update, id, true)),
+            EventType::UserDeleted
+        );
+        assert_eq!(
+            EventType::of(&change(ChangeOp::Delete, id, true)),
+            EventType::UserDeleted
+        );
+
+        let event = serde_json::to_value(UserEvent::from(&change(ChangeOp::Insert, id, false)));
+        assert_eq!(event.unwrap()["type"], "user.created");
+    }
+
+    #[test]
+    fn test_subscription_filters_events_and_users() {
+        let [seven, eight] = IDS;
+        let query = |events: Option<&str>, user_ids: Option<&str>| {
+            Subscription::from_query(&SubscriptionQuery {
+                events: events.map(str::to_string),
+                user_ids: user_ids.map(str::to_string),
+            })
+        };
+
+        let everything = query(None, None).unwrap();
+        assert_eq!(everything, Subscription::default());
+        assert!(everything.matches(&change(ChangeOp::Delete, seven, true)));
+
+        let deletes_of_seven = query(Some("user.deleted"), Some(seven)).unwrap();
+        assert!(deletes_of_seven.matches(&change(ChangeOp::Update, seven, true)));
+        assert!(!deletes_of_seven.matches(&change(ChangeOp::Update, seven, false)));
+        assert!(!deletes_of_seven.matches(&change(ChangeOp::Update, eight, true)));
+
+        assert!(query(Some("user.created,user.renamed"), None).is_err());
+        assert!(query(None, Some("seven")).is_err());
+
+        let message = format!(
+            r#"{{"type": "subscribe", "events": ["user.created"], "user_ids": ["{}"]}}"#,
+            eight
+        );
+        let ClientMessage::Subscribe(subscription) = serde_json::from_str(&message).unwrap();
+        assert!(subscription.matches(&change(ChangeOp::Insert, eight, false)));
+        assert!(!subscription.matches(&change(ChangeOp::Insert, seven, false)));
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "unsubscribe"}"#).is_err());
+    }
+}
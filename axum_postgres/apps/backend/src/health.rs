@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use sqlx::PgPool;
+
+/// Default per-check timeout when HEALTH_CHECK_TIMEOUT_MS is not set
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Default background check interval when HEALTH_CHECK_INTERVAL_MS is not set
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Dependency probe run by `/health/ready`
+///
+/// Subsystems register an implementation with the `HealthRegistry` at
+/// startup. A check that does not finish within its timeout counts as down.
+///
+/// Optional dependencies return `false` from `required`: when they are down
+/// the service reports itself degraded but stays ready, and callers consult
+/// `HealthRegistry::is_available` to skip them instead of failing requests.
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    /// Name reported in the readiness output
+    fn name(&self) -> &str;
+
+    /// Probe the dependency once
+    async fn check(&self) -> Result<(), String>;
+
+    /// Override the registry timeout for this check
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether the service cannot serve traffic without this dependency
+    fn required(&self) -> bool {
+        true
+    }
+}
+
+/// Result of one check
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct CheckStatus {
+    pub name: String,
+    /// "up" or "down"
+    pub status: &'static str,
+    pub required: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CheckStatus {
+    pub fn is_up(&self) -> bool {
+        self.status == "up"
+    }
+}
+
+/// Readiness of the service as a whole
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    /// "ready" when every check is up, "degraded" when only optional checks
+    /// are down, "unready" when a required check is down
+    pub status: &'static str,
+    pub checks: Vec<CheckStatus>,
+}
+
+impl ReadinessReport {
+    /// Whether the service should receive traffic; true when degraded
+    pub fn is_ready(&self) -> bool {
+        self.status != "unready"
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.status == "degraded"
+    }
+}
+
+/// Set of dependency checks run concurrently for readiness
+pub struct HealthRegistry {
+    checks: Vec<Arc<dyn HealthCheck>>,
+    timeout: Duration,
+    interval: Duration,
+    /// Outcome of the latest run per check name
+    available: RwLock<HashMap<String, bool>>,
+    /// Whether a run has found the service ready since startup
+    started: AtomicBool,
+}
+
+impl Default for HealthRegistry {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIMEOUT)
+    }
+}
+
+impl HealthRegistry {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            checks: Vec::new(),
+            timeout,
+            interval: DEFAULT_INTERVAL,
+            available: RwLock::new(HashMap::new()),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Registry configured from HEALTH_CHECK_TIMEOUT_MS and HEALTH_CHECK_INTERVAL_MS
+    pub fn from_env() -> Self {
+        let millis = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|ms| ms.parse().ok())
+                .map(Duration::from_millis)
+        };
+        let mut registry = Self::new(millis("HEALTH_CHECK_TIMEOUT_MS").unwrap_or(DEFAULT_TIMEOUT));
+        registry.interval = millis("HEALTH_CHECK_INTERVAL_MS").unwrap_or(DEFAULT_INTERVAL);
+        registry
+    }
+
+    /// Add a check
+    pub fn register(mut self, check: impl HealthCheck + 'static) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Run every check concurrently
+    pub async fn run(&self) -> ReadinessReport {
+        let checks = futures::future::join_all(
+            self.checks
+                .iter()
+                .map(|check| run_check(check.as_ref(), check.timeout().unwrap_or(self.timeout))),
+        )
+        .await;
+
+        let mut available = self.available.write().unwrap();
+        for check in &checks {
+            let was_up = available.insert(check.name.clone(), check.is_up());
+            if was_up == Some(false) && check.is_up() {
+                tracing::info!("Dependency {} recovered", check.name);
+            }
+        }
+        drop(available);
+
+        let status = if checks.iter().all(CheckStatus::is_up) {
+            "ready"
+        } else if checks.iter().all(|check| check.is_up() || !check.required) {
+            "degraded"
+        } else {
+            "unready"
+        };
+
+        let report = ReadinessReport { status, checks };
+        if report.is_ready() && !self.started.swap(true, Ordering::Relaxed) {
+            tracing::info!("Service started: every required dependency is up");
+        }
+        report
+    }
+
+    /// Whether the service has been ready once since startup
+    ///
+    /// Stays true when a dependency later goes down: from then on readiness,
+    /// not the startup probe, takes the service out of rotation.
+    pub fn has_started(&self) -> bool {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Whether a dependency was up on the latest run
+    ///
+    /// Unknown or not yet checked dependencies count as available, so callers
+    /// only skip a subsystem once it has actually been seen down.
+    pub fn is_available(&self, name: &str) -> bool {
+        self.available
+            .read()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or(true)
+    }
+
+    /// Re-run the checks every HEALTH_CHECK_INTERVAL_MS until shutdown
+    ///
+    /// Keeps `is_available` current even when nothing polls `/health/ready`.
+    pub async fn monitor(self: Arc<Self>, mut shutdown: tokio::sync::watch::Receiver<bool>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let report = self.run().await;
+                    if !report.is_ready() {
+                        tracing::error!("Service unready: a required dependency is down");
+                    } else if report.is_degraded() {
+                        tracing::warn!("Service degraded: an optional dependency is down");
+                    }
+                }
+                _ = shutdown.changed() => break,
+            }
+        }
+    }
+}
+
+async fn run_check(check: &dyn HealthCheck, timeout: Duration) -> CheckStatus {
+    let started = Instant::now();
+    let result = match tokio::time::timeout(timeout, check.check()).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {}ms", timeout.as_millis())),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    if let Err(e) = &result {
+        tracing::warn!("Health check {} failed: {}", check.name(), e);
+    }
+
+    CheckStatus {
+        name: check.name().to_string(),
+        status: if result.is_ok() { "up" } else { "down" },
+        required: check.required(),
+        latency_ms,
+        error: result.err(),
+    }
+}
+
+/// PostgreSQL connectivity check
+pub struct DatabaseCheck {
+    name: &'static str,
+    pool: PgPool,
+}
+
+impl DatabaseCheck {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            name: "database",
+            pool,
+        }
+    }
+
+    /// Check of the read replica, reported as `database_replica`
+    pub fn replica(pool: PgPool) -> Self {
+        Self {
+            name: "database_replica",
+            pool,
+        }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for DatabaseCheck {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        crate::database::test_connection(&self.pool)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Check that every migration embedded in this binary has been applied
+///
+/// A schema behind the code makes queries fail, so pending migrations keep
+/// the service unready until `sqlx migrate run` catches up.
+pub struct MigrationsCheck {
+    pool: PgPool,
+}
+
+impl MigrationsCheck {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for MigrationsCheck {
+    fn name(&self) -> &str {
+        "migrations"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let state = crate::database::migration_state(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        if state.pending.is_empty() {
+            return Ok(());
+        }
+        let pending: Vec<String> = state.pending.iter().map(i64::to_string).collect();
+        Err(format!("Pending migrations: {}", pending.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StaticCheck {
+        name: &'static str,
+        delay: Duration,
+        result: Result<(), String>,
+        required: bool,
+    }
+
+    #[async_trait]
+    impl HealthCheck for StaticCheck {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn check(&self) -> Result<(), String> {
+            tokio::time::sleep(self.delay).await;
+            self.result.clone()
+        }
+
+        fn required(&self) -> bool {
+            self.required
+        }
+    }
+
+    fn check(name: &'static str, delay_ms: u64, result: Result<(), String>) -> StaticCheck {
+        StaticCheck {
+            name,
+            delay: Duration::from_millis(delay_ms),
+            result,
+            required: true,
+        }
+    }
+
+    fn optional(check: StaticCheck) -> StaticCheck {
+        StaticCheck {
+            required: false,
+            ..check
+        }
+    }
+
+    #[tokio::test]
+    async fn test_all_checks_up_is_ready() {
+        let registry = HealthRegistry::new(Duration::from_millis(100))
+            .register(check("a", 0, Ok(())))
+            .register(check("b", 0, Ok(())));
+
+        let report = registry.run().await;
+        assert!(report.is_ready());
+        assert_eq!(report.checks.len(), 2);
+        assert!(report.checks.iter().all(|c| c.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_failure_and_timeout_are_reported_per_check() {
+        let registry = HealthRegistry::new(Duration::from_millis(50))
+            .register(check("ok", 0, Ok(())))
+            .register(check("broken", 0, Err("connection refused".to_string())))
+            .register(check("slow", 1_000, Ok(())));
+
+        let started = Instant::now();
+        let report = registry.run().await;
+        // Checks run concurrently, so the slow one only costs its timeout
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        assert!(!report.is_ready());
+        let by_name = |name: &str| report.checks.iter().find(|c| c.name == name).unwrap();
+        assert!(by_name("ok").is_up());
+        assert_eq!(
+            by_name("broken").error.as_deref(),
+            Some("connection refused")
+        );
+        assert!(by_name("slow")
+            .error
+            .as_deref()
+            .unwrap()
+            .starts_with("Timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_optional_failure_degrades_without_unreadying() {
+        let registry = HealthRegistry::new(Duration::from_millis(50))
+            .register(check("database", 0, Ok(())))
+            .register(optional(check("broker", 0, Err("down".to_string()))));
+
+        assert!(registry.is_available("broker"));
+
+        let report = registry.run().await;
+        assert!(report.is_ready());
+        assert!(report.is_degraded());
+        assert!(registry.is_available("database"));
+        assert!(!registry.is_available("broker"));
+
+        let registry = registry.register(check("cache", 0, Err("down".to_string())));
+        let report = registry.run().await;
+        assert!(!report.is_ready());
+        assert_eq!(report.status, "unready");
+    }
+
+    #[tokio::test]
+    async fn test_started_latches_on_first_ready_run() {
+        let registry = HealthRegistry::new(Duration::from_millis(50))
+            .register(check("database", 0, Err("starting up".to_string())));
+        assert!(!registry.has_started());
+        registry.run().await;
+        assert!(!registry.has_started());
+
+        let registry = HealthRegistry::new(Duration::from_millis(50))
+            .register(check("database", 0, Ok(())));
+        registry.run().await;
+        assert!(registry.has_started());
+    }
+}
@@ -0,0 +1,755 @@
+//! CSV user import
+//!
+//! `POST /api/users/import` takes a `multipart/form-data` upload whose
+//! `file` field is a CSV with a header row naming the columns: `name` and
+//! `email` are required, and any `CreateUserRequest` field may be added in
+//! any order. Rows are parsed and validated one at a time; the rows that
+//! fail are reported with their line number instead of failing the upload.
+//!
+//! The upload is read as it arrives: [`FormField`] picks the file out of
+//! the multipart body, [`CsvDecoder`] splits it into records whatever
+//! chunks it comes in, and [`UserBatches`] validates them [`BATCH_ROWS`]
+//! at a time for the handler to insert. Memory stays bounded by the batch
+//! and the longest row allowed, however large the file.
+
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use http_body_util::LengthLimitError;
+use validator::Validate;
+
+use crate::models::user::{normalize_email, CreateUserRequest, RejectedRow};
+
+/// Form field that carries the CSV file
+pub const FILE_FIELD: &str = "file";
+
+/// Columns an import file may have
+pub const COLUMNS: [&str; 7] = [
+    "name",
+    "email",
+    "display_name",
+    "bio",
+    "phone",
+    "timezone",
+    "locale",
+];
+
+/// Columns every import file must have
+const REQUIRED_COLUMNS: [&str; 2] = ["name", "email"];
+
+/// Rows validated and inserted together
+pub const BATCH_ROWS: usize = 1000;
+
+/// Longest row accepted, so a quote that is never closed cannot fill the
+/// memory
+pub const MAX_ROW_BYTES: usize = 64 * 1024;
+
+/// Longest headers of a multipart part
+const MAX_PART_HEADER_BYTES: usize = 8 * 1024;
+
+/// Rejected rows listed in the summary of an import; the others are only
+/// counted
+pub const MAX_LISTED_REJECTIONS: usize = 1000;
+
+/// Why an upload could not be imported
+#[derive(Debug)]
+pub enum ImportError {
+    /// Not a usable import file
+    Invalid(String),
+    /// The body could not be read, e.g. because it passed the upload limit
+    Body(axum::Error),
+    /// A batch could not be inserted
+    Database(sqlx::Error),
+}
+
+impl ImportError {
+    /// Whether the upload was cut off for passing the body limit
+    pub fn is_too_large(&self) -> bool {
+        let Self::Body(e) = self else {
+            return false;
+        };
+        let mut source = e.source();
+        while let Some(e) = source {
+            if e.is::<LengthLimitError>() {
+                return true;
+            }
+            source = e.source();
+        }
+        false
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(message) => f.write_str(message),
+            Self::Body(e) => write!(f, "Failed to read the upload: {}", e),
+            Self::Database(_) => f.write_str("Failed to import users"),
+        }
+    }
+}
+
+impl From<String> for ImportError {
+    fn from(message: String) -> Self {
+        Self::Invalid(message)
+    }
+}
+
+/// Boundary of a `multipart/form-data` content type
+pub fn multipart_boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    if !params
+        .next()?
+        .trim()
+        .eq_ignore_ascii_case("multipart/form-data")
+    {
+        return None;
+    }
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"'))
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Content of one field of a `multipart/form-data` body, read as the body
+/// arrives
+///
+/// Parts before the field are skipped. Only the bytes that could be the
+/// start of the delimiter ending the field are held back, so a field of
+/// any size takes about one chunk of memory.
+pub struct FormField<S> {
+    body: S,
+    name: String,
+    /// `\r\n--<boundary>`, which ends every part
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    state: Part,
+}
+
+/// Where a [`FormField`] is in the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    /// Before the first delimiter
+    Preamble,
+    /// Right after a delimiter: a line break for another part, or `--` for
+    /// the end of the body
+    Delimiter,
+    Headers,
+    /// Content of a part, the field's or another's
+    Content {
+        wanted: bool,
+    },
+    /// All of the field was read
+    Done,
+}
+
+impl<S> FormField<S>
+where
+    S: Stream<Item = Result<Bytes, axum::Error>> + Unpin,
+{
+    pub fn new(body: S, boundary: &str, name: &str) -> Self {
+        Self {
+            body,
+            name: name.to_string(),
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // The first delimiter may start the body, without a line break
+            buffer: b"\r\n".to_vec(),
+            state: Part::Preamble,
+        }
+    }
+
+    /// Next piece of the field's content; None once all of it was read
+    pub async fn next(&mut self) -> Result<Option<Vec<u8>>, ImportError> {
+        loop {
+            match self.state {
+                Part::Done => return Ok(None),
+                Part::Preamble => {
+                    if let Some(at) = find(&self.buffer, &self.delimiter) {
+                        self.buffer.drain(..at + self.delimiter.len());
+                        self.state = Part::Delimiter;
+                        continue;
+                    }
+                    self.take_content();
+                }
+                Part::Delimiter if self.buffer.len() >= 2 => {
+                    if self.buffer.starts_with(b"--") {
+                        return Err(self.missing());
+                    }
+                    if !self.buffer.starts_with(b"\r\n") {
+                        return Err(malformed());
+                    }
+                    self.buffer.drain(..2);
+                    self.state = Part::Headers;
+                    continue;
+                }
+                Part::Delimiter => {}
+                Part::Headers => {
+                    if let Some(at) = find(&self.buffer, b"\r\n\r\n") {
+                        let headers =
+                            std::str::from_utf8(&self.buffer[..at]).map_err(|_| malformed())?;
+                        let wanted = field_name(headers) == Some(self.name.as_str());
+                        self.buffer.drain(..at + 4);
+                        self.state = Part::Content { wanted };
+                        continue;
+                    }
+                    if self.buffer.len() > MAX_PART_HEADER_BYTES {
+                        return Err(malformed());
+                    }
+                }
+                Part::Content { wanted } => {
+                    if let Some(at) = find(&self.buffer, &self.delimiter) {
+                        let content: Vec<u8> = self.buffer.drain(..at).collect();
+                        self.buffer.drain(..self.delimiter.len());
+                        if wanted {
+                            self.state = Part::Done;
+                            return Ok(Some(content));
+                        }
+                        self.state = Part::Delimiter;
+                        continue;
+                    }
+                    let content = self.take_content();
+                    if wanted && !content.is_empty() {
+                        return Ok(Some(content));
+                    }
+                }
+            }
+
+            match self.body.next().await {
+                Some(Ok(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => return Err(ImportError::Body(e)),
+                None if matches!(self.state, Part::Content { wanted: true }) => {
+                    return Err(ImportError::Invalid(
+                        "The upload ended in the middle of the file".to_string(),
+                    ))
+                }
+                None => return Err(self.missing()),
+            }
+        }
+    }
+
+    /// Take what of the buffer cannot be part of a delimiter
+    fn take_content(&mut self) -> Vec<u8> {
+        let end = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+        self.buffer.drain(..end).collect()
+    }
+
+    fn missing(&self) -> ImportError {
+        ImportError::Invalid(format!("Missing form field '{}'", self.name))
+    }
+}
+
+fn malformed() -> ImportError {
+    ImportError::Invalid("Malformed multipart/form-data body".to_string())
+}
+
+/// `name` parameter of a part's `Content-Disposition: form-data` header
+fn field_name(headers: &str) -> Option<&str> {
+    let disposition = headers.lines().find_map(|line| {
+        let (header, value) = line.split_once(':')?;
+        header
+            .trim()
+            .eq_ignore_ascii_case("content-disposition")
+            .then_some(value)
+    })?;
+    disposition
+        .split(';')
+        .filter_map(|param| param.split_once('='))
+        .find(|(param, _)| param.trim() == "name")
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// CSV record with the line it starts on
+pub type Record = (usize, Vec<String>);
+
+/// Records of a CSV document (RFC 4180) fed in pieces
+///
+/// Fields may be quoted, and quoted fields may hold commas, line breaks and
+/// `""` for a quote. A piece may end anywhere, inside a field or a UTF-8
+/// character included. Blank lines are skipped.
+#[derive(Debug)]
+pub struct CsvDecoder {
+    /// Bytes of a character the last piece ended in
+    pending: Vec<u8>,
+    started: bool,
+    line: usize,
+    /// Line the current record starts on
+    start: usize,
+    in_record: bool,
+    record_bytes: usize,
+    fields: Vec<String>,
+    field: String,
+    quoted: bool,
+    /// A quote in a quoted field, which ends it unless another follows
+    quote: bool,
+    /// A `\r` ended the last line, so a `\n` right after it belongs to it
+    carriage_return: bool,
+}
+
+impl Default for CsvDecoder {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            started: false,
+            line: 1,
+            start: 1,
+            in_record: false,
+            record_bytes: 0,
+            fields: Vec::new(),
+            field: String::new(),
+            quoted: false,
+            quote: false,
+            carriage_return: false,
+        }
+    }
+}
+
+impl CsvDecoder {
+    /// Decode `bytes`, adding the records they complete to `records`
+    pub fn feed(&mut self, bytes: &[u8], records: &mut Vec<Record>) -> Result<(), String> {
+        self.pending.extend_from_slice(bytes);
+        let pending = std::mem::take(&mut self.pending);
+        let (text, rest) = match std::str::from_utf8(&pending) {
+            Ok(text) => (text, &[][..]),
+            // A character cut in two by the end of the piece
+            Err(e) if e.error_len().is_none() => {
+                let (valid, rest) = pending.split_at(e.valid_up_to());
+                (std::str::from_utf8(valid).map_err(|_| not_utf8())?, rest)
+            }
+            Err(_) => return Err(not_utf8()),
+        };
+        for c in text.chars() {
+            self.push(c, records)?;
+        }
+        self.pending = rest.to_vec();
+        Ok(())
+    }
+
+    /// The last record, when the document does not end with a line break
+    pub fn finish(&mut self) -> Result<Option<Record>, String> {
+        if !self.pending.is_empty() {
+            return Err(not_utf8());
+        }
+        if std::mem::take(&mut self.quote) {
+            self.quoted = false;
+        }
+        if !self.in_record {
+            return Ok(None);
+        }
+        if self.quoted {
+            return Err(format!("Unterminated quoted field on line {}", self.start));
+        }
+        Ok(Some(self.end_record()))
+    }
+
+    fn push(&mut self, c: char, records: &mut Vec<Record>) -> Result<(), String> {
+        if !std::mem::replace(&mut self.started, true) && c == '\u{feff}' {
+            return Ok(());
+        }
+        if std::mem::take(&mut self.carriage_return) && c == '\n' {
+            return Ok(());
+        }
+        if std::mem::take(&mut self.quote) {
+            if c == '"' {
+                self.field.push('"');
+                return Ok(());
+            }
+            self.quoted = false;
+        }
+        if !self.in_record {
+            // Blank lines
+            if matches!(c, '\r' | '\n') {
+                self.line += 1;
+                self.carriage_return = c == '\r';
+                return Ok(());
+            }
+            self.in_record = true;
+            self.start = self.line;
+            self.record_bytes = 0;
+        }
+
+        self.record_bytes += c.len_utf8();
+        if self.record_bytes > MAX_ROW_BYTES {
+            return Err(format!(
+                "The row on line {} is longer than {} bytes",
+                self.start, MAX_ROW_BYTES
+            ));
+        }
+        match c {
+            '"' if self.quoted => self.quote = true,
+            '"' if self.field.is_empty() => self.quoted = true,
+            ',' if !self.quoted => self.fields.push(std::mem::take(&mut self.field)),
+            '\r' | '\n' if !self.quoted => {
+                self.line += 1;
+                self.carriage_return = c == '\r';
+                records.push(self.end_record());
+            }
+            c => {
+                if c == '\n' {
+                    self.line += 1;
+                }
+                self.field.push(c);
+            }
+        }
+        Ok(())
+    }
+
+    fn end_record(&mut self) -> Record {
+        self.in_record = false;
+        let mut fields = std::mem::take(&mut self.fields);
+        fields.push(std::mem::take(&mut self.field));
+        (self.start, fields)
+    }
+}
+
+fn not_utf8() -> String {
+    "The file must be UTF-8 text".to_string()
+}
+
+/// Rows of an import file read together
+#[derive(Debug, Default)]
+pub struct Batch {
+    /// Valid rows with their line numbers, each email at most once
+    pub users: Vec<(usize, CreateUserRequest)>,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl Batch {
+    /// Rows read
+    pub fn rows(&self) -> usize {
+        self.users.len() + self.rejected.len()
+    }
+}
+
+/// Users of an import file in batches of [`BATCH_ROWS`] rows, read from the
+/// upload as it arrives
+pub struct UserBatches<S> {
+    field: FormField<S>,
+    decoder: CsvDecoder,
+    header: Option<Vec<String>>,
+    /// Decoded records waiting for their batch
+    records: VecDeque<Record>,
+    ended: bool,
+}
+
+impl<S> UserBatches<S>
+where
+    S: Stream<Item = Result<Bytes, axum::Error>> + Unpin,
+{
+    pub fn new(body: S, boundary: &str) -> Self {
+        Self {
+            field: FormField::new(body, boundary, FILE_FIELD),
+            decoder: CsvDecoder::default(),
+            header: None,
+            records: VecDeque::new(),
+            ended: false,
+        }
+    }
+
+    /// Validate the next batch of rows; None once the file ended
+    ///
+    /// Fails when the header is missing, repeats a column, names an
+    /// unknown column or lacks a required one, when a row is never closed
+    /// or too long, or when the upload cannot be read; every other problem
+    /// rejects just its row. An email repeated within a batch is rejected
+    /// here, one repeated in a later batch when inserting, as taken.
+    pub async fn next_batch(&mut self) -> Result<Option<Batch>, ImportError> {
+        let header = match self.header.take() {
+            Some(header) => header,
+            None => {
+                self.read(1).await?;
+                let (_, fields) = self
+                    .records
+                    .pop_front()
+                    .ok_or_else(|| "The file is empty".to_string())?;
+                parse_header(fields)?
+            }
+        };
+        self.read(BATCH_ROWS).await?;
+
+        let mut batch = Batch::default();
+        let mut emails = HashSet::new();
+        let rows = self.records.len().min(BATCH_ROWS);
+        for (row, fields) in self.records.drain(..rows) {
+            if fields.len() != header.len() {
+                batch.rejected.push(RejectedRow {
+                    row,
+                    errors: vec![format!(
+                        "Expected {} fields, found {}",
+                        header.len(),
+                        fields.len()
+                    )],
+                });
+                continue;
+            }
+
+            let user = user_from_row(&header, fields);
+            if let Err(errors) = user.validate() {
+                let mut errors: Vec<String> = errors
+                    .field_errors()
+                    .iter()
+                    .map(|(field, errors)| format!("{}: {}", field, errors[0]))
+                    .collect();
+                errors.sort();
+                batch.rejected.push(RejectedRow { row, errors });
+            } else if !emails.insert(user.email.clone()) {
+                batch.rejected.push(RejectedRow {
+                    row,
+                    errors: vec!["email: Appears earlier in the file".to_string()],
+                });
+            } else {
+                batch.users.push((row, user));
+            }
+        }
+        self.header = Some(header);
+        Ok((batch.rows() > 0).then_some(batch))
+    }
+
+    /// Decode the upload until `rows` records wait or the file ended
+    async fn read(&mut self, rows: usize) -> Result<(), ImportError> {
+        let mut records = Vec::new();
+        while self.records.len() < rows && !self.ended {
+            match self.field.next().await? {
+                Some(bytes) => self.decoder.feed(&bytes, &mut records)?,
+                None => {
+                    self.ended = true;
+                    records.extend(self.decoder.finish()?);
+                }
+            }
+            self.records.extend(records.drain(..));
+        }
+        Ok(())
+    }
+}
+
+/// Columns named by a header row
+///
+/// Fails when the header repeats a column, names an unknown column or
+/// lacks a required one.
+fn parse_header(fields: Vec<String>) -> Result<Vec<String>, String> {
+    let header: Vec<String> = fields
+        .iter()
+        .map(|column| column.trim().to_ascii_lowercase())
+        .collect();
+
+    for (i, column) in header.iter().enumerate() {
+        if !COLUMNS.contains(&column.as_str()) {
+            return Err(format!(
+                "Unknown column '{}'; expected {}",
+                column,
+                COLUMNS.join(", ")
+            ));
+        }
+        if header[..i].contains(column) {
+            return Err(format!("Column '{}' appears twice", column));
+        }
+    }
+    if let Some(missing) = REQUIRED_COLUMNS
+        .iter()
+        .find(|column| !header.iter().any(|name| name == *column))
+    {
+        return Err(format!("Missing required column '{}'", missing));
+    }
+    Ok(header)
+}
+
+/// Build a create request from a row; empty optional fields are left unset
+fn user_from_row(header: &[String], fields: Vec<String>) -> CreateUserRequest {
+    let mut user = CreateUserRequest::default();
+    for (column, value) in header.iter().zip(fields) {
+        let optional = (!value.trim().is_empty()).then(|| value.clone());
+        match column.as_str() {
+            "name" => user.name = value,
+            "email" => user.email = normalize_email(&value),
+            "display_name" => user.display_name = optional,
+            "bio" => user.bio = optional,
+            "phone" => user.phone = optional,
+            "timezone" => user.timezone = optional,
+            "locale" => user.locale = optional,
+            _ => {}
+        }
+    }
+    user
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, BoxStream};
+
+    /// `body` as a stream of chunks of `size` bytes
+    fn chunked(body: &[u8], size: usize) -> BoxStream<'static, Result<Bytes, axum::Error>> {
+        let chunks: Vec<_> = body
+            .chunks(size)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+        stream::iter(chunks).boxed()
+    }
+
+    async fn read_field(body: &[u8], size: usize, name: &str) -> Result<Vec<u8>, String> {
+        let mut field = FormField::new(chunked(body, size), "XyZ", name);
+        let mut content = Vec::new();
+        loop {
+            match field.next().await {
+                Ok(Some(piece)) => content.extend(piece),
+                Ok(None) => return Ok(content),
+                Err(ImportError::Invalid(message)) => return Err(message),
+                Err(e) => panic!("Unexpected error {}", e),
+            }
+        }
+    }
+
+    fn decode(text: &str, size: usize) -> Result<Vec<Record>, String> {
+        let mut decoder = CsvDecoder::default();
+        let mut records = Vec::new();
+        for piece in text.as_bytes().chunks(size) {
+            decoder.feed(piece, &mut records)?;
+        }
+        records.extend(decoder.finish()?);
+        Ok(records)
+    }
+
+    fn upload(csv: &str) -> Vec<u8> {
+        format!(
+            "--XyZ\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\n{}\r\n--XyZ--\r\n",
+            csv
+        )
+        .into_bytes()
+    }
+
+    async fn batches(csv: &str) -> Result<Vec<Batch>, String> {
+        let body = upload(csv);
+        let mut batches = UserBatches::new(chunked(&body, 7), "XyZ");
+        let mut read = Vec::new();
+        loop {
+            match batches.next_batch().await {
+                Ok(Some(batch)) => read.push(batch),
+                Ok(None) => return Ok(read),
+                Err(ImportError::Invalid(message)) => return Err(message),
+                Err(e) => panic!("Unexpected error {}", e),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_form_field() {
+        let content_type = "multipart/form-data; boundary=\"XyZ\"";
+        let boundary = multipart_boundary(content_type).unwrap();
+        assert_eq!(boundary, "XyZ");
+        assert_eq!(multipart_boundary("application/json"), None);
+
+        let body = b"preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"note\"\r\n\r\n\
+            hello\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"users.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            name,email\r\nJane,jane@example.com\r\n\r\n--XyZ--\r\n";
+        // However the body is split into chunks
+        for size in 1..=body.len() {
+            assert_eq!(read_field(body, size, "note").await.unwrap(), b"hello");
+            assert_eq!(
+                read_field(body, size, "file").await.unwrap(),
+                b"name,email\r\nJane,jane@example.com\r\n"
+            );
+            assert_eq!(
+                read_field(body, size, "other").await.unwrap_err(),
+                "Missing form field 'other'"
+            );
+        }
+        assert!(read_field(b"no parts", 3, "file").await.is_err());
+        assert_eq!(
+            read_field(
+                b"--XyZ\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\ncut",
+                5,
+                "file"
+            )
+            .await
+            .unwrap_err(),
+            "The upload ended in the middle of the file"
+        );
+    }
+
+    #[test]
+    fn test_csv_records() {
+        let csv = "\u{feff}a,b\r\n\r\n\"x, \"\"y\"\"\",\"two\nlines\"\nlast,é\n";
+        for size in 1..=csv.len() {
+            assert_eq!(
+                decode(csv, size).unwrap(),
+                vec![
+                    (1, vec!["a".to_string(), "b".to_string()]),
+                    (3, vec!["x, \"y\"".to_string(), "two\nlines".to_string()]),
+                    (5, vec!["last".to_string(), "é".to_string()]),
+                ]
+            );
+        }
+        assert_eq!(decode("a\r\nb", 1).unwrap()[1], (2, vec!["b".to_string()]));
+
+        assert_eq!(
+            decode("a\n\"open,b\n", 4).unwrap_err(),
+            "Unterminated quoted field on line 2"
+        );
+        assert!(decode(&format!("\"{}", "x".repeat(MAX_ROW_BYTES)), 1024).is_err());
+        let mut decoder = CsvDecoder::default();
+        assert!(decoder.feed(b"a,\xff\n", &mut Vec::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_user_batches() {
+        let csv = "Email,name,timezone\n\
+            Jane@Example.com,Jane,Europe/Paris\n\
+            bad-email,Bad,\n\
+            jane@example.com,Again,\n\
+            only-one-field\n\
+            john@example.com,John,";
+        let read = batches(csv).await.unwrap();
+        assert_eq!(read.len(), 1);
+
+        let users: Vec<_> = read[0]
+            .users
+            .iter()
+            .map(|(row, user)| (*row, user.email.as_str(), user.timezone.as_deref()))
+            .collect();
+        assert_eq!(
+            users,
+            vec![
+                (2, "jane@example.com", Some("Europe/Paris")),
+                (6, "john@example.com", None),
+            ]
+        );
+
+        let rejected: Vec<usize> = read[0].rejected.iter().map(|row| row.row).collect();
+        assert_eq!(rejected, vec![3, 4, 5]);
+        assert_eq!(
+            read[0].rejected[0].errors,
+            vec!["email: Invalid email format"]
+        );
+
+        assert_eq!(batches("").await.unwrap_err(), "The file is empty");
+        assert!(batches("name\nJane\n").await.is_err());
+        assert!(batches("name,email,age\n").await.is_err());
+        assert!(batches("name,email,name\n").await.is_err());
+        assert!(batches("name,email\n").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_batches_split_large_files() {
+        let rows = BATCH_ROWS * 2 + 1;
+        let mut csv = "name,email\n".to_string();
+        for i in 0..rows {
+            csv.push_str(&format!("User {},user{}@example.com\n", i, i));
+        }
+        let batches = batches(&csv).await.unwrap();
+        let sizes: Vec<usize> = batches.iter().map(Batch::rows).collect();
+        assert_eq!(sizes, vec![BATCH_ROWS, BATCH_ROWS, 1]);
+        assert_eq!(batches[2].users[0].0, rows + 1);
+    }
+}
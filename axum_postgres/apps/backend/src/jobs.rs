@@ -0,0 +1,136 @@
+//! Scheduled jobs
+//!
+//! Every replica schedules the same jobs, and each run first takes the
+//! job's advisory lock without waiting: when the ticks of several replicas
+//! meet, one of them does the work and the others skip that run.
+
+use std::future::Future;
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio::sync::watch;
+
+use crate::database::lock;
+use crate::repository::maintenance::{MaintenanceRepository, MaintenanceRepositoryTrait};
+
+/// Lock and name of the job deleting expired sessions and tokens
+pub const PURGE_EXPIRED: &str = "jobs.purge_expired";
+
+/// How often the jobs run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobConfig {
+    /// None turns the purge of expired rows off
+    pub purge_interval: Option<Duration>,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        Self {
+            purge_interval: Some(Duration::from_secs(60 * 60)),
+        }
+    }
+}
+
+impl JobConfig {
+    /// Intervals from JOB_PURGE_INTERVAL_MS, hourly by default; 0 turns
+    /// the job off
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let interval = |name: &str, default| -> Result<Option<Duration>, String> {
+            let Some(value) = var(name) else {
+                return Ok(default);
+            };
+            match value.trim().parse::<u64>() {
+                Ok(0) => Ok(None),
+                Ok(millis) => Ok(Some(Duration::from_millis(millis))),
+                Err(_) => Err(format!("{} is not a number: {}", name, value)),
+            }
+        };
+        Ok(Self {
+            purge_interval: interval("JOB_PURGE_INTERVAL_MS", Self::default().purge_interval)?,
+        })
+    }
+
+    /// Spawn the enabled jobs, which stop on shutdown
+    pub fn spawn(&self, pool: &PgPool, shutdown: watch::Receiver<bool>) {
+        if let Some(interval) = self.purge_interval {
+            let repo_pool = pool.clone();
+            let purge = move || {
+                let repo = MaintenanceRepository::new(repo_pool.clone());
+                async move { repo.purge_expired().await }
+            };
+            tokio::spawn(run_every(
+                pool.clone(),
+                PURGE_EXPIRED,
+                interval,
+                shutdown,
+                purge,
+            ));
+        }
+    }
+}
+
+/// Run `job` every `interval` until shutdown, on one replica at a time
+///
+/// The first run is right away. A run that fails is logged and the job
+/// tries again on the next tick.
+pub async fn run_every<F, Fut>(
+    pool: PgPool,
+    name: &'static str,
+    interval: Duration,
+    mut shutdown: watch::Receiver<bool>,
+    job: F,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<u64, sqlx::Error>>,
+{
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                match lock::try_with_advisory_lock(&pool, name, job()).await {
+                    Ok(Some(Ok(count))) => tracing::info!("Job {} done: {} rows", name, count),
+                    Ok(None) => tracing::debug!("Job {} skipped: running on another replica", name),
+                    Ok(Some(Err(e))) | Err(e) => tracing::warn!("Job {} failed: {}", name, e),
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<JobConfig, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        JobConfig::from_lookup(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_job_intervals_from_env() {
+        assert_eq!(config(&[]).unwrap(), JobConfig::default());
+        assert_eq!(
+            config(&[("JOB_PURGE_INTERVAL_MS", "60000")])
+                .unwrap()
+                .purge_interval,
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            config(&[("JOB_PURGE_INTERVAL_MS", "0")])
+                .unwrap()
+                .purge_interval,
+            None
+        );
+        assert_eq!(
+            config(&[("JOB_PURGE_INTERVAL_MS", "hourly")]).unwrap_err(),
+            "JOB_PURGE_INTERVAL_MS is not a number: hourly"
+        );
+    }
+}
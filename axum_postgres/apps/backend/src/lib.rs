@@ -1,6 +1,37 @@
+pub mod audit;
+pub mod auth;
+pub mod bench;
+pub mod cache;
+pub mod casing;
+pub mod changes;
+pub mod conditional;
+pub mod consumer;
+#[cfg(feature = "cpu-profiling")]
+pub mod cpu_profile;
 pub mod database;
 pub mod docs;
+pub mod doctor;
 pub mod error;
+pub mod events;
+pub mod export;
+pub mod extract;
 pub mod handlers;
+pub mod health;
+pub mod import;
+pub mod jobs;
+pub mod mail;
+#[cfg(feature = "jemalloc")]
+pub mod memory;
+pub mod metrics;
+pub mod middleware;
 pub mod models;
-pub mod repository;
\ No newline at end of file
+pub mod pagination;
+pub mod publisher;
+pub mod repository;
+pub mod routes;
+pub mod seeds;
+pub mod slo;
+pub mod state;
+pub mod status;
+pub mod telemetry;
+pub mod tenant;
@@ -1,34 +1,74 @@
 use axum::{
-    http::StatusCode,
+    extract::{DefaultBodyLimit, State},
     response::{Html, IntoResponse, Json},
-    routing::{delete, get, post, put},
-    Router,
+    middleware,
+    routing::get,
+    Extension, Router,
 };
+use backend::health::HealthRegistry;
+use backend::routes::Routes;
+use std::sync::Arc;
+use tokio::sync::watch;
 use tower::ServiceBuilder;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, trace::TraceLayer};
 use tracing::{info, instrument, error};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 #[tokio::main]
 async fn main() {
     // Load environment variables from .env file
     dotenvy::dotenv().ok();
 
-    // Initialize tracing
+    // `backend doctor [--json]` runs the startup self-test instead of serving
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("doctor") {
+        let report = backend::doctor::run().await;
+        if args.iter().any(|arg| arg == "--json") {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        } else {
+            print!("{}", report.render());
+        }
+        std::process::exit(if report.passed { 0 } else { 1 });
+    }
+
+    // Initialize tracing; spans are also exported over OTLP when
+    // OTEL_EXPORTER_OTLP_ENDPOINT is set
+    let filter = || {
+        tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "backend=debug,tower_http=debug".into())
+    };
+    let telemetry = backend::telemetry::Telemetry::from_env();
     tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(filter()))
         .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "backend=debug,tower_http=debug".into()),
+            telemetry
+                .as_ref()
+                .ok()
+                .and_then(Option::as_ref)
+                .map(|telemetry| telemetry.layer(filter())),
         )
-        .with(tracing_subscriber::fmt::layer())
         .init();
+    let telemetry = telemetry.unwrap_or_else(|e| {
+        tracing::warn!("{}", e);
+        None
+    });
 
     info!("Starting axum_postgres backend server");
 
     // Create database connection pool
-    let pool = backend::database::create_pool_from_env()
+    let database = backend::database::DatabaseConfig::from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    let pool = database
+        .connect()
         .await
         .map_err(|e| {
             error!("Failed to create database pool: {:?}", e);
@@ -37,8 +77,144 @@ async fn main() {
         .unwrap();
 
     info!("Database connection pool created successfully");
+    warm_up(&database, &pool, "database").await;
+
+    let run_migrations = std::env::var("RUN_MIGRATIONS")
+        .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1"))
+        .unwrap_or(false);
+    if run_migrations {
+        backend::database::run_migrations(&pool)
+            .await
+            .map_err(|e| {
+                error!("Failed to run migrations: {}", e);
+                std::process::exit(1);
+            })
+            .unwrap();
+        info!("Database migrations are up to date");
+    }
+
+    if database.tenant_isolation == backend::tenant::TenantIsolation::RowLevelSecurity {
+        backend::tenant::check_row_level_security(&pool)
+            .await
+            .map_err(|e| {
+                error!("{}", e);
+                std::process::exit(1);
+            })
+            .unwrap();
+        info!("Tenants isolated by row-level security");
+    }
+
+    // Read-only queries go to DATABASE_REPLICA_URL when it is set
+    let replica = backend::database::DatabaseConfig::replica_from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    let replica = match replica {
+        Some(config) => {
+            let replica = config
+                .connect()
+                .await
+                .map_err(|e| {
+                    error!("Failed to create read replica pool: {:?}", e);
+                    std::process::exit(1);
+                })
+                .unwrap();
+            info!("Read replica pool created successfully");
+            warm_up(&config, &replica, "read replica").await;
+            Some(replica)
+        }
+        None => None,
+    };
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let mut health = HealthRegistry::from_env()
+        .register(backend::health::DatabaseCheck::new(pool.clone()))
+        .register(backend::health::MigrationsCheck::new(pool.clone()));
+    if let Some(replica) = &replica {
+        health = health.register(backend::health::DatabaseCheck::replica(replica.clone()));
+    }
+    let cache = backend::cache::TieredCache::from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    if let Some(check) = cache.check() {
+        health = health.register(check);
+    }
+    let replica = replica.unwrap_or_else(|| pool.clone());
+    let (health, consumer) = start_consumer(health, shutdown_rx.clone()).await;
+
+    // Scheduled jobs run on every replica, one replica at a time per run
+    backend::jobs::JobConfig::from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap()
+        .spawn(&pool, shutdown_rx.clone());
+    let changes = backend::changes::ChangeFeed::spawn_from_env(&pool, shutdown_rx.clone())
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    backend::repository::cached_user::invalidate_on_changes(
+        cache.clone(),
+        &changes,
+        shutdown_rx.clone(),
+    );
+    let (health, publisher) =
+        start_publisher(health, &pool, &changes, shutdown_rx.clone()).await;
+    let api_key_usage = backend::auth::api_key::ApiKeyUsage::default();
+    api_key_usage.spawn(&pool, shutdown_rx.clone());
 
-    let app = create_app(pool);
+    let read_only = backend::middleware::read_only::ReadOnlyMode::from_env();
+    if read_only.is_enabled() {
+        tracing::warn!("Starting in read-only mode");
+    }
+    let slo = backend::slo::SloConfig::from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    let state = backend::state::AppState::new(pool, health)
+        .with_replica(replica)
+        .with_read_only(read_only)
+        .with_slo(backend::slo::SloTracker::new(slo))
+        .with_links(backend::models::links::ResponseLinks::from_env())
+        .with_envelope(backend::middleware::envelope::ResponseEnvelope::from_env())
+        .with_admin_token(backend::middleware::admin::AdminToken::from_env())
+        .with_token_keys(backend::auth::token::TokenKeys::from_env())
+        .with_oauth(backend::auth::oauth::OAuthProviders::from_env())
+        .with_auth_mode(backend::auth::session::AuthMode::from_env())
+        .with_mail(backend::mail::Mail::from_env())
+        .with_email_verification(backend::auth::verification::EmailVerification::from_env())
+        .with_tenants(backend::tenant::TenantResolver::from_env())
+        .with_changes(changes)
+        .with_cache(cache)
+        .with_api_key_usage(api_key_usage)
+        .with_websocket(
+            backend::handlers::ws::WebSocketConfig::from_env()
+                .map_err(|e| {
+                    error!("{}", e);
+                    std::process::exit(1);
+                })
+                .unwrap(),
+        );
+    tokio::spawn(state.health.clone().monitor(shutdown_rx.clone()));
+    let metrics = backend::metrics::Metrics::from_env(state.pool.clone())
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    let internal = start_internal_listener(state.clone(), shutdown_rx).await;
+    let app = create_app(state, metrics, database.request_deadline);
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
     let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
@@ -47,39 +223,545 @@ async fn main() {
     info!("Server running on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Connect info gives the audit log the client address
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal(shutdown_tx))
+    .await
+    .unwrap();
+
+    if let Some(internal) = internal {
+        let _ = internal.await;
+    }
+
+    if let Some(consumer) = consumer {
+        info!("Waiting for event consumer to finish");
+        let _ = consumer.await;
+    }
+
+    if let Some(publisher) = publisher {
+        info!("Waiting for event publisher to finish");
+        let _ = publisher.await;
+    }
+
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
+}
+
+/// Resolve on Ctrl+C or SIGTERM and notify background tasks
+async fn shutdown_signal(shutdown_tx: watch::Sender<bool>) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received");
+    let _ = shutdown_tx.send(true);
+}
+
+/// Open DB_WARMUP_CONNECTIONS connections of `pool` before serving
+///
+/// A failed warmup is only logged: the pool opens connections on demand.
+async fn warm_up(
+    config: &backend::database::DatabaseConfig,
+    pool: &sqlx::PgPool,
+    name: &str,
+) {
+    if config.warmup_connections == 0 {
+        return;
+    }
+    let started = std::time::Instant::now();
+    match config.warm_up(pool).await {
+        Ok(()) => info!(
+            "Warmed up {} {} connections in {:?}",
+            config.warmup_connections,
+            name,
+            started.elapsed()
+        ),
+        Err(e) => tracing::warn!("Failed to warm up {} connections: {}", name, e),
+    }
+}
+
+/// Start the event consumer when CONSUMER_TOPICS is configured
+///
+/// The broker is registered as an optional health check, so losing it
+/// degrades the service instead of failing readiness.
+#[cfg(feature = "nats")]
+async fn start_consumer(
+    health: HealthRegistry,
+    shutdown: watch::Receiver<bool>,
+) -> (HealthRegistry, Option<tokio::task::JoinHandle<()>>) {
+    use backend::consumer::{nats, Consumer, ConsumerConfig};
+    use backend::events::EventRegistry;
+
+    let Some(config) = ConsumerConfig::from_env() else {
+        return (health, None);
+    };
+    let (source, parking_lot, check) = match nats::connect(&config).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Failed to start event consumer: {}", e);
+            return (health, None);
+        }
+    };
+
+    // Application handlers are registered here with `.on(|event: SomeEvent| ...)`
+    let consumer = Consumer::new(Arc::new(EventRegistry::with_domain_events()))
+        .with_parking_lot(Arc::new(parking_lot))
+        .with_max_attempts(config.max_attempts);
+
+    info!("Event consumer subscribed to {:?}", config.topics);
+    let handle = tokio::spawn(async move { consumer.run(source, shutdown).await });
+    (health.register(check), Some(handle))
 }
 
-fn create_app(pool: sqlx::PgPool) -> Router {
-    Router::new()
+#[cfg(not(feature = "nats"))]
+async fn start_consumer(
+    health: HealthRegistry,
+    _shutdown: watch::Receiver<bool>,
+) -> (HealthRegistry, Option<tokio::task::JoinHandle<()>>) {
+    if backend::consumer::ConsumerConfig::from_env().is_some() {
+        tracing::warn!("CONSUMER_TOPICS is set but the `nats` feature is disabled; consumer not started");
+    }
+    (health, None)
+}
+
+/// Publish domain events of user changes when EVENT_PUBLISHER is set
+///
+/// Like the consumer's, the broker is an optional health check. The outbox
+/// is enabled before connecting, so changes made while the broker is
+/// unreachable are published once a replica connects.
+#[cfg(any(feature = "nats", feature = "kafka"))]
+async fn start_publisher(
+    health: HealthRegistry,
+    pool: &sqlx::PgPool,
+    changes: &backend::changes::ChangeFeed,
+    shutdown: watch::Receiver<bool>,
+) -> (HealthRegistry, Option<tokio::task::JoinHandle<()>>) {
+    use backend::events::EventRegistry;
+    use backend::publisher::{Dispatcher, EventPublisher, PublisherConfig};
+
+    let config = match PublisherConfig::from_env() {
+        Ok(Some(config)) => config,
+        Ok(None) => return (health, None),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let (publisher, health): (Arc<dyn EventPublisher>, HealthRegistry) = match &config.broker {
+        #[cfg(feature = "nats")]
+        backend::publisher::Broker::Nats { url, stream } => {
+            if let Err(e) = backend::publisher::enable_outbox(pool).await {
+                error!("Failed to enable the event outbox: {}", e);
+                return (health, None);
+            }
+            match backend::publisher::nats::connect(url, stream, &config.topics).await {
+                Ok((publisher, check)) => {
+                    info!("Event publisher sending to stream {}", stream);
+                    (Arc::new(publisher), health.register(check))
+                }
+                Err(e) => {
+                    error!("Failed to start event publisher: {}", e);
+                    return (health, None);
+                }
+            }
+        }
+        #[cfg(feature = "kafka")]
+        backend::publisher::Broker::Kafka { brokers } => {
+            if let Err(e) = backend::publisher::enable_outbox(pool).await {
+                error!("Failed to enable the event outbox: {}", e);
+                return (health, None);
+            }
+            match backend::publisher::kafka::connect(brokers) {
+                Ok((publisher, check)) => {
+                    info!("Event publisher sending to Kafka at {}", brokers);
+                    (Arc::new(publisher), health.register(check))
+                }
+                Err(e) => {
+                    error!("Failed to start event publisher: {}", e);
+                    return (health, None);
+                }
+            }
+        }
+        #[allow(unreachable_patterns)]
+        broker => {
+            tracing::warn!(
+                "EVENT_PUBLISHER is {} but the `{}` feature is disabled; publisher not started",
+                broker.name(),
+                broker.name()
+            );
+            return (health, None);
+        }
+    };
+
+    let dispatcher = Dispatcher::new(Arc::new(EventRegistry::with_domain_events()), publisher)
+        .with_topics(config.topics.clone());
+    let handle = tokio::spawn(dispatcher.run(pool.clone(), changes.clone(), shutdown));
+    (health, Some(handle))
+}
+
+#[cfg(not(any(feature = "nats", feature = "kafka")))]
+async fn start_publisher(
+    health: HealthRegistry,
+    _pool: &sqlx::PgPool,
+    _changes: &backend::changes::ChangeFeed,
+    _shutdown: watch::Receiver<bool>,
+) -> (HealthRegistry, Option<tokio::task::JoinHandle<()>>) {
+    match backend::publisher::PublisherConfig::from_env() {
+        Ok(Some(config)) => tracing::warn!(
+            "EVENT_PUBLISHER is {} but the `{}` feature is disabled; publisher not started",
+            config.broker.name(),
+            config.broker.name()
+        ),
+        Ok(None) => {}
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    (health, None)
+}
+
+fn create_app(
+    state: backend::state::AppState,
+    metrics: Option<backend::metrics::Metrics>,
+    db_deadline: Option<std::time::Duration>,
+) -> Router {
+    use backend::handlers::{
+        admin_api_keys, admin_users, api_keys, audit, auth, roles, status, users, ws,
+    };
+    use backend::middleware::limits::{self, RequestLimits};
+
+    let envelope = state.envelope;
+    // Imports are read as they arrive, however large the file
+    let limits = RequestLimits::from_env().with_uploads(&[users::USER_IMPORT_PATH]);
+    let rate_limit_backend = backend::middleware::rate_limit::RateLimitBackend::from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    let trusted_proxies = backend::middleware::proxy::TrustedProxies::from_env()
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+    let rate_limiter = backend::middleware::rate_limit::RateLimiter::from_env()
+        .with_backend(rate_limit_backend, state.cache.redis())
+        .with_token_keys(state.token_keys.clone());
+    let (router, routes) = Routes::new()
         // Routes
-        .route("/", get(root))
-        .route("/health", get(backend::handlers::health::health))
+        .get("/", root)
+        .get("/health/live", backend::handlers::health::live)
+        .get("/health/ready", backend::handlers::health::ready)
+        .get("/health/startup", backend::handlers::health::startup)
         // User API routes
-        .route("/api/users", get(backend::handlers::users::list_users))
-        .route("/api/users", post(backend::handlers::users::create_user))
-        .route("/api/users/:id", get(backend::handlers::users::get_user_by_id))
-        .route("/api/users/:id", put(backend::handlers::users::update_user))
-        .route("/api/users/:id", delete(backend::handlers::users::delete_user))
+        .get(users::USERS_PATH, users::list_users)
+        .post(users::USERS_PATH, users::create_user)
+        .post(users::USER_IMPORT_PATH, users::import_users)
+        .get(users::USER_IMPORT_STATUS_PATH, users::get_import_status)
+        .get(users::USER_EXPORT_PATH, users::export_users)
+        .get(users::USER_PATH, users::get_user_by_id)
+        .head(users::USER_PATH, users::user_exists)
+        .put(users::USER_PATH, users::update_user)
+        .patch(users::USER_PATH, users::patch_user)
+        .delete(users::USER_PATH, users::delete_user)
+        .post(users::USER_RESTORE_PATH, users::restore_user)
+        .post(users::USER_ACTIVATE_PATH, users::activate_user)
+        .post(users::USER_DEACTIVATE_PATH, users::deactivate_user)
+        .get(users::USER_HISTORY_PATH, users::get_user_history)
+        .get(users::USER_SEARCH_PATH, users::search_users)
+        .get(users::USER_COUNT_PATH, users::count_users)
+        .get(users::USER_EMAIL_CHECK_PATH, users::check_email)
+        .get(users::ME_PATH, users::get_me)
+        .put(users::ME_PATH, users::update_me)
+        .delete(users::ME_PATH, users::delete_me)
+        // Change events of the tenant's users, over a WebSocket
+        .get(ws::WS_PATH, ws::subscribe)
+        // Sign-in routes
+        .post(auth::REGISTER_PATH, auth::register)
+        .post(auth::LOGIN_PATH, auth::login)
+        .post(auth::REFRESH_PATH, auth::refresh)
+        .post(auth::LOGOUT_PATH, auth::logout)
+        .post(auth::LOGOUT_ALL_PATH, auth::logout_all)
+        .delete(auth::SESSION_PATH, auth::end_session)
+        .post(auth::VERIFY_EMAIL_PATH, auth::verify_email)
+        .post(auth::RESEND_VERIFICATION_PATH, auth::resend_verification)
+        .post(auth::FORGOT_PASSWORD_PATH, auth::forgot_password)
+        .post(auth::RESET_PASSWORD_PATH, auth::reset_password)
+        .post(auth::CHANGE_PASSWORD_PATH, auth::change_password)
+        .get(auth::OAUTH_START_PATH, auth::oauth_start)
+        .get(auth::OAUTH_CALLBACK_PATH, auth::oauth_callback)
+        // API key management routes
+        .post(api_keys::API_KEYS_PATH, api_keys::create_api_key)
+        .get(api_keys::API_KEYS_PATH, api_keys::list_api_keys)
+        .delete(api_keys::API_KEY_PATH, api_keys::revoke_api_key)
+        // Role management routes, for admins
+        .get(roles::ROLES_PATH, roles::list_roles)
+        .get(roles::ROLE_USERS_PATH, roles::list_role_users)
+        .put(roles::ROLE_USER_PATH, roles::grant_role)
+        .delete(roles::ROLE_USER_PATH, roles::revoke_role)
+        // User management for admins
+        .get(admin_users::ADMIN_USERS_PATH, admin_users::list_admin_users)
+        .post(admin_users::ADMIN_USER_PASSWORD_RESET_PATH, admin_users::force_password_reset)
+        .post(admin_users::ADMIN_USER_IMPERSONATE_PATH, admin_users::impersonate_user)
+        .get(admin_users::ADMIN_USER_ROLES_PATH, admin_users::get_user_roles)
+        .put(admin_users::ADMIN_USER_ROLES_PATH, admin_users::set_user_roles)
+        // API keys of every user, for admins
+        .get(admin_api_keys::ADMIN_API_KEYS_PATH, admin_api_keys::list_admin_api_keys)
+        .post(admin_api_keys::ADMIN_API_KEY_ROTATE_PATH, admin_api_keys::rotate_api_key)
+        .post(admin_api_keys::ADMIN_API_KEY_EXPIRE_PATH, admin_api_keys::expire_api_key)
+        // Audit log, for admins
+        .get(audit::AUDIT_LOG_PATH, audit::list_audit_log)
+        // Service status, for admins
+        .get(status::ADMIN_STATUS_PATH, status::get_status)
+        // X-API-Key resolves to a principal for the routes above, and in
+        // cookie mode the session cookie to the signed-in user
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            backend::middleware::api_key::authenticate,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            backend::middleware::session::authenticate,
+        ))
+        // SCIM provisioning routes
+        .merge(scim_routes())
+        // Everything above runs for the tenant the request names, so
+        // authentication and the repositories only see that tenant's users
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            backend::middleware::tenant::scope,
+        ))
+        // Read-only mode applies to everything above; admin routes stay writable
+        .route_layer(middleware::from_fn_with_state(
+            state.read_only.clone(),
+            backend::middleware::read_only::reject_writes,
+        ))
+        .merge(admin_routes())
+        // SLO accounting sees the final status, including read-only rejections
+        .route_layer(middleware::from_fn_with_state(
+            state.slo.clone(),
+            backend::middleware::slo::record,
+        ))
         // OpenAPI documentation routes
-        .route("/api-docs/openapi.json", get(openapi_spec))
+        .get("/api-docs/openapi.json", openapi_spec)
+        .into_parts();
+    let routes = Arc::new(routes);
+    let http_caching = backend::middleware::http_cache::HttpCaching::from_env(routes.clone())
+        .map_err(|e| {
+            error!("{}", e);
+            std::process::exit(1);
+        })
+        .unwrap();
+
+    let app = router
+        // Swagger UI serves its own assets and is not listed in /admin/routes
+        .merge(SwaggerUi::new("/swagger-ui").config(Config::from("/api-docs/openapi.json")))
         // State
-        .with_state(pool)
+        .with_state(state)
+        // 405 with Allow, and 404 or a redirect for trailing slashes; set
+        // before the middleware so these answers pass through it too
+        .method_not_allowed_fallback(backend::handlers::fallback::method_not_allowed)
+        .fallback(backend::handlers::fallback::not_found)
         // Middleware
+        .layer(Extension(routes.clone()))
+        .layer(Extension(backend::handlers::fallback::TrailingSlash::from_env()))
         .layer(
             ServiceBuilder::new()
+                // Outermost, so the logs of every layer below carry the ID
+                .layer(middleware::from_fn(backend::middleware::request_id::assign))
+                // Before the access log, rate limits and audit records read it
+                .layer(middleware::from_fn_with_state(
+                    trusted_proxies,
+                    backend::middleware::proxy::resolve,
+                ))
                 .layer(TraceLayer::new_for_http())
-                .layer(CorsLayer::permissive()),
-        )
-        // Fallback for 404
-        .fallback(handler_404)
+                // Counts every answer, including those of the layers below
+                .layer(middleware::from_fn_with_state(
+                    routes.clone(),
+                    backend::middleware::metrics::track,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    backend::middleware::access_log::AccessLog::from_env(routes.clone()),
+                    backend::middleware::access_log::log,
+                ))
+                // Every layer below may panic; answer with a 500 problem
+                .layer(CatchPanicLayer::custom(limits::panic_response))
+                // HEAD runs as GET through every layer below, then drops the body
+                .layer(middleware::from_fn_with_state(
+                    routes.clone(),
+                    backend::middleware::head::serve_head,
+                ))
+                // Inside serve_head, so HEAD gets the same Cache-Control and 304s
+                .layer(middleware::from_fn_with_state(
+                    http_caching,
+                    backend::middleware::http_cache::apply,
+                ))
+                // Inside serve_head so HEAD reports the indented Content-Length
+                .layer(middleware::from_fn_with_state(
+                    backend::middleware::pretty::PrettyJson::from_env(),
+                    backend::middleware::pretty::indent,
+                ))
+                .layer(CorsLayer::permissive())
+                // Non-preflight OPTIONS; CorsLayer above answers preflights
+                .layer(middleware::from_fn_with_state(
+                    routes,
+                    backend::middleware::options::answer_options,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    envelope,
+                    backend::middleware::envelope::wrap,
+                ))
+                // Outside admission control so shed requests get JSON:API errors too
+                .layer(middleware::from_fn(backend::middleware::json_api::negotiate))
+                // Before admission control, so limited clients take no slot
+                .layer(middleware::from_fn_with_state(
+                    rate_limiter,
+                    backend::middleware::rate_limit::limit,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    backend::middleware::admission::AdmissionControl::from_env(),
+                    backend::middleware::admission::admit,
+                ))
+                // Inside admission control, so retries hold the request's slot
+                .layer(middleware::from_fn(backend::middleware::resilience::unavailable))
+                .layer(middleware::from_fn_with_state(
+                    db_deadline,
+                    backend::middleware::deadline::bound,
+                ))
+                .layer(middleware::from_fn_with_state(limits, limits::problem_details))
+                // Replaces axum's default body limit
+                .layer(middleware::from_fn_with_state(limits, limits::bound))
+                .layer(DefaultBodyLimit::disable()),
+        );
+
+    // Merged after the middleware so scrapes are not traced, limited or counted
+    match metrics {
+        Some(metrics) => app.merge(
+            Router::new()
+                .route("/metrics", get(backend::handlers::metrics::metrics))
+                .with_state(metrics),
+        ),
+        None => app,
+    }
+}
+
+/// SCIM 2.0 routes, mounted only when SCIM_BEARER_TOKEN is set
+fn scim_routes() -> Routes<backend::state::AppState> {
+    use backend::handlers::scim;
+
+    let Some(token) = scim::bearer_token_from_env() else {
+        return Routes::new();
+    };
+
+    info!("SCIM provisioning enabled at /scim/v2/Users");
+    Routes::new()
+        .get("/scim/v2/Users", scim::list_users)
+        .post("/scim/v2/Users", scim::create_user)
+        .get("/scim/v2/Users/:id", scim::get_user)
+        .put("/scim/v2/Users/:id", scim::replace_user)
+        .patch("/scim/v2/Users/:id", scim::patch_user)
+        .delete("/scim/v2/Users/:id", scim::delete_user)
+        .route_layer(middleware::from_fn_with_state(token, scim::require_bearer_token))
+}
+
+/// Operational admin routes, mounted only when ADMIN_TOKEN is set
+fn admin_routes() -> Routes<backend::state::AppState> {
+    use backend::handlers::{admin, tenants};
+    use backend::middleware::admin::{admin_token_from_env, require_admin_token};
+
+    let Some(token) = admin_token_from_env() else {
+        return Routes::new();
+    };
+
+    Routes::new()
+        .get("/admin/read-only", admin::get_read_only)
+        .put("/admin/read-only", admin::set_read_only)
+        .get("/admin/slo", admin::get_slo)
+        .get("/admin/routes", admin::list_routes)
+        .get(tenants::TENANTS_PATH, tenants::list_tenants)
+        .post(tenants::TENANTS_PATH, tenants::create_tenant)
+        .get(tenants::TENANT_PATH, tenants::get_tenant)
+        .patch(tenants::TENANT_PATH, tenants::update_tenant)
+        .route_layer(middleware::from_fn_with_state(token, require_admin_token))
+}
+
+/// Serve diagnostics on INTERNAL_ADDR, which must not be publicly exposed
+async fn start_internal_listener(
+    state: backend::state::AppState,
+    mut shutdown: watch::Receiver<bool>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let addr = std::env::var("INTERNAL_ADDR").ok()?;
+    let listener = match tokio::net::TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind internal listener on {}: {}", addr, e);
+            return None;
+        }
+    };
+
+    info!("Internal diagnostics listening on http://{}", addr);
+    let app = internal_app(state);
+    Some(tokio::spawn(async move {
+        let _ = axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown.changed().await;
+            })
+            .await;
+    }))
+}
+
+/// Routes for the internal listener
+fn internal_app(state: backend::state::AppState) -> Router {
+    let router = Router::new()
+        .route("/health/live", get(backend::handlers::health::live))
+        .route("/health/ready", get(backend::handlers::health::ready))
+        .route("/health/startup", get(backend::handlers::health::startup));
+
+    #[cfg(feature = "jemalloc")]
+    let router = router
+        .route("/admin/memory", get(backend::handlers::diagnostics::memory_stats))
+        .route("/admin/memory/heap-profile", axum::routing::post(backend::handlers::diagnostics::heap_profile));
+
+    #[cfg(feature = "cpu-profiling")]
+    let router = router.route("/admin/profile/cpu", get(backend::handlers::diagnostics::cpu_profile));
+
+    router
+        .with_state(state)
+        .layer(TraceLayer::new_for_http())
 }
 
 /// OpenAPI specification endpoint
 /// GET /api-docs/openapi.json
 #[instrument]
-async fn openapi_spec() -> impl IntoResponse {
-    Json(backend::docs::openapi_spec())
+async fn openapi_spec(
+    State(envelope): State<backend::middleware::envelope::ResponseEnvelope>,
+) -> impl IntoResponse {
+    Json(backend::docs::openapi_spec_for(envelope))
 }
 
 /// Root endpoint - returns basic message
@@ -88,11 +770,4 @@ async fn root() -> impl IntoResponse {
     Html("Hello, World!")
 }
 
-/// 404 handler
-#[instrument]
-async fn handler_404() -> impl IntoResponse {
-    (
-        StatusCode::NOT_FOUND,
-        Html("<h1>404 - Page Not Found</h1>"),
-    )
-}
+
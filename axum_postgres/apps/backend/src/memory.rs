@@ -0,0 +1,91 @@
+//! Heap statistics and profiling through jemalloc (`jemalloc` feature)
+//!
+//! Heap profile dumps need profiling enabled when the process starts, e.g.
+//! `_RJEM_MALLOC_CONF=prof:true,lg_prof_sample:19`.
+
+use std::ffi::CString;
+use std::path::Path;
+
+use serde::Serialize;
+use tikv_jemalloc_ctl::{epoch, raw, stats};
+
+/// Allocator-level memory statistics, in bytes
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct MemoryStats {
+    /// Bytes handed out to the application
+    pub allocated: usize,
+    /// Bytes in active pages, including page-level fragmentation
+    pub active: usize,
+    /// Bytes in physically resident pages
+    pub resident: usize,
+    pub mapped: usize,
+    /// Bytes unmapped but kept for reuse
+    pub retained: usize,
+    /// Bytes used by jemalloc itself
+    pub metadata: usize,
+    /// resident / allocated; values well above 1 point at fragmentation
+    pub fragmentation_ratio: f64,
+    /// Whether heap profile dumps are available
+    pub profiling_enabled: bool,
+}
+
+fn ctl_error(e: tikv_jemalloc_ctl::Error) -> String {
+    e.to_string()
+}
+
+/// Read current statistics
+pub fn stats() -> Result<MemoryStats, String> {
+    // Statistics are cached until the epoch advances
+    epoch::advance().map_err(ctl_error)?;
+
+    let allocated = stats::allocated::read().map_err(ctl_error)?;
+    let resident = stats::resident::read().map_err(ctl_error)?;
+
+    Ok(MemoryStats {
+        allocated,
+        active: stats::active::read().map_err(ctl_error)?,
+        resident,
+        mapped: stats::mapped::read().map_err(ctl_error)?,
+        retained: stats::retained::read().map_err(ctl_error)?,
+        metadata: stats::metadata::read().map_err(ctl_error)?,
+        fragmentation_ratio: if allocated == 0 {
+            0.0
+        } else {
+            resident as f64 / allocated as f64
+        },
+        profiling_enabled: profiling_enabled(),
+    })
+}
+
+/// Whether the process was started with heap profiling on
+pub fn profiling_enabled() -> bool {
+    // SAFETY: opt.prof is a read-only bool option
+    unsafe { raw::read::<bool>(b"opt.prof\0") }.unwrap_or(false)
+}
+
+/// Write a heap profile (jeprof format) to `path`
+pub fn dump_heap_profile(path: &Path) -> Result<(), String> {
+    if !profiling_enabled() {
+        return Err(
+            "Heap profiling is not enabled; start with _RJEM_MALLOC_CONF=prof:true".to_string(),
+        );
+    }
+
+    let path = CString::new(path.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    // SAFETY: prof.dump takes a NUL-terminated file name that outlives the call
+    unsafe { raw::write(b"prof.dump\0", path.as_ptr()) }.map_err(ctl_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_are_readable() {
+        let _buffer = vec![0u8; 1 << 20];
+        let stats = stats().expect("Failed to read jemalloc stats");
+        assert!(stats.allocated > 0);
+        assert!(stats.resident >= stats.active.min(stats.resident));
+    }
+}
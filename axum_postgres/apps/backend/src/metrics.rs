@@ -0,0 +1,213 @@
+//! Prometheus metrics at `GET /metrics`
+//!
+//! `middleware::metrics::track` counts requests and their latency per route
+//! template and status, and `repository::timing` the statements each
+//! repository method runs; the database pool and process gauges are sampled
+//! when the endpoint is scraped. The recorder is global, so it is installed
+//! once at startup.
+
+use metrics::{describe_counter, describe_gauge, describe_histogram, gauge, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use metrics_process::Collector;
+use sqlx::PgPool;
+
+use crate::cache::{
+    CACHE_ERRORS_TOTAL, CACHE_HITS_TOTAL, CACHE_MISSES_TOTAL, CACHE_TIER_HITS_TOTAL,
+    CACHE_TIER_MISSES_TOTAL,
+};
+use crate::changes::redis::{FANOUT_LAG, FANOUT_RECONNECTS_TOTAL};
+use crate::database::deadline::DB_DEADLINE_EXCEEDED_TOTAL;
+use crate::handlers::ws::{WS_CONNECTIONS, WS_EVENTS_SENT_TOTAL};
+use crate::publisher::{EVENTS_PUBLISHED_TOTAL, EVENTS_PUBLISH_FAILURES_TOTAL};
+use crate::repository::timing::{DB_QUERY_DURATION, DB_SLOW_QUERIES_TOTAL};
+
+/// Requests answered, by method, route and status
+pub const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+/// Time to answer a request, by method, route and status
+pub const HTTP_REQUEST_DURATION: &str = "http_request_duration_seconds";
+
+/// Latency histogram buckets, in seconds
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Installed recorder and the sources sampled on scrape
+#[derive(Clone)]
+pub struct Metrics {
+    handle: PrometheusHandle,
+    process: Collector,
+    pool: PgPool,
+}
+
+impl Metrics {
+    /// Install the Prometheus recorder, unless METRICS_ENABLED is `false`/`0`
+    pub fn from_env(pool: PgPool) -> Result<Option<Self>, String> {
+        let enabled = std::env::var("METRICS_ENABLED")
+            .map(|value| !matches!(value.to_ascii_lowercase().as_str(), "false" | "0"))
+            .unwrap_or(true);
+        if !enabled {
+            return Ok(None);
+        }
+        Self::install(pool).map(Some)
+    }
+
+    /// Install the Prometheus recorder; fails when one is already installed
+    pub fn install(pool: PgPool) -> Result<Self, String> {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(
+                Matcher::Full(HTTP_REQUEST_DURATION.to_string()),
+                DURATION_BUCKETS,
+            )
+            .and_then(|builder| {
+                builder.set_buckets_for_metric(
+                    Matcher::Full(DB_QUERY_DURATION.to_string()),
+                    DURATION_BUCKETS,
+                )
+            })
+            .and_then(|builder| {
+                builder.set_buckets_for_metric(Matcher::Full(FANOUT_LAG.to_string()), DURATION_BUCKETS)
+            })
+            .and_then(PrometheusBuilder::install_recorder)
+            .map_err(|e| format!("Failed to install the metrics recorder: {}", e))?;
+
+        describe_counter!(HTTP_REQUESTS_TOTAL, "Requests answered");
+        describe_histogram!(
+            HTTP_REQUEST_DURATION,
+            Unit::Seconds,
+            "Time to answer a request"
+        );
+        describe_histogram!(
+            DB_QUERY_DURATION,
+            Unit::Seconds,
+            "Time to run a repository statement"
+        );
+        describe_counter!(
+            DB_SLOW_QUERIES_TOTAL,
+            "Repository statements slower than SLOW_QUERY_MS"
+        );
+        describe_counter!(
+            DB_DEADLINE_EXCEEDED_TOTAL,
+            "Repository operations cut short by their request's deadline, by operation"
+        );
+        describe_counter!(
+            CACHE_ERRORS_TOTAL,
+            "Redis commands that failed, by operation"
+        );
+        describe_counter!(CACHE_HITS_TOTAL, "Reads answered from the cache, by entry");
+        describe_counter!(
+            CACHE_MISSES_TOTAL,
+            "Reads that went on to the database, by entry"
+        );
+        describe_counter!(
+            CACHE_TIER_HITS_TOTAL,
+            "Reads a cache tier answered, by tier"
+        );
+        describe_counter!(
+            CACHE_TIER_MISSES_TOTAL,
+            "Reads a cache tier passed on, by tier"
+        );
+        describe_gauge!(WS_CONNECTIONS, "Open WebSocket connections");
+        describe_counter!(
+            WS_EVENTS_SENT_TOTAL,
+            "Change events sent over WebSockets, by type"
+        );
+        describe_histogram!(
+            FANOUT_LAG,
+            Unit::Seconds,
+            "Time from relaying a change through Redis to receiving it"
+        );
+        describe_counter!(
+            FANOUT_RECONNECTS_TOTAL,
+            "Connections of the change feed fanout lost or failed to open, by side"
+        );
+        describe_counter!(
+            EVENTS_PUBLISHED_TOTAL,
+            "Domain events the broker confirmed, by type"
+        );
+        describe_counter!(
+            EVENTS_PUBLISH_FAILURES_TOTAL,
+            "Domain event publishes that failed and will be retried, by type"
+        );
+        describe_gauge!("db_pool_connections", "Open database connections, by state");
+        describe_gauge!(
+            "db_pool_max_connections",
+            "Largest number of database connections the pool opens"
+        );
+        let process = Collector::default();
+        process.describe();
+
+        Ok(Self {
+            handle,
+            process,
+            pool,
+        })
+    }
+
+    /// Prometheus text exposition of every metric
+    pub fn render(&self) -> String {
+        let idle = self.pool.num_idle() as f64;
+        let size = f64::from(self.pool.size());
+        gauge!("db_pool_connections", idle, "state" => "idle");
+        gauge!("db_pool_connections", (size - idle).max(0.0), "state" => "active");
+        gauge!(
+            "db_pool_max_connections",
+            f64::from(self.pool.options().get_max_connections())
+        );
+        self.process.collect();
+
+        self.handle.render()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::{body::Body, extract::Request};
+    use sqlx::postgres::PgPoolOptions;
+    use tower::util::ServiceExt;
+
+    use crate::repository::timing::{self, Timed};
+    use crate::routes::Routes;
+
+    #[tokio::test]
+    async fn test_requests_are_counted_by_route_template() {
+        let pool = PgPoolOptions::new()
+            .max_connections(3)
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let metrics = Metrics::install(pool).unwrap();
+
+        let (router, routes) = Routes::<()>::new()
+            .get("/api/users/:id", || async { "user" })
+            .into_parts();
+        let app = router.layer(axum::middleware::from_fn_with_state(
+            Arc::new(routes),
+            crate::middleware::metrics::track,
+        ));
+        for uri in ["/api/users/1", "/api/users/2", "/wp-login.php"] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let value = async { Ok::<_, sqlx::Error>(7) }.timed("user.test").await;
+        assert_eq!(value.unwrap(), 7);
+        timing::record("user.slow", Duration::from_secs(10));
+
+        let text = metrics.render();
+        assert!(text.contains(
+            r#"http_requests_total{method="GET",route="/api/users/:id",status="200"} 2"#
+        ));
+        assert!(
+            text.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#)
+        );
+        assert!(text.contains(r#"http_request_duration_seconds_bucket{method="GET",route="/api/users/:id",status="200",le="0.005"}"#));
+        assert!(text.contains(r#"db_pool_connections{state="idle"} 0"#));
+        assert!(text.contains("db_pool_max_connections 3"));
+        assert!(text.contains(r#"db_query_duration_seconds_count{statement="user.test"} 1"#));
+        assert!(text.contains(r#"db_slow_queries_total{statement="user.slow"} 1"#));
+        assert!(!text.contains(r#"db_slow_queries_total{statement="user.test"}"#));
+    }
+}
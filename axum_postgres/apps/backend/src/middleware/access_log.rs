@@ -0,0 +1,183 @@
+//! One access log line per request
+//!
+//! Unlike TraceLayer's debug output, the line is written at info level with
+//! everything needed to analyse traffic: method, route template, status,
+//! latency, response size, user agent and client IP. Its target is this
+//! module, so RUST_LOG can keep it while silencing the rest. Busy routes can
+//! be sampled with ACCESS_LOG_SAMPLE; errors are always logged.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    body::HttpBody,
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::middleware::client_ip;
+use crate::routes::RouteTable;
+
+/// Keep one line in `every` for a route; 0 drops them all
+struct Sampling {
+    every: u64,
+    seen: AtomicU64,
+}
+
+impl Sampling {
+    fn keep(&self) -> bool {
+        self.every != 0
+            && self
+                .seen
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.every)
+    }
+}
+
+/// Access log settings, shared by every request
+#[derive(Clone)]
+pub struct AccessLog {
+    routes: Arc<RouteTable>,
+    sampling: Arc<HashMap<String, Sampling>>,
+}
+
+impl AccessLog {
+    /// Log every request to the routes of `routes`, except as `sampling`
+    /// says: route template and how many requests share one line
+    pub fn new(routes: Arc<RouteTable>, sampling: impl IntoIterator<Item = (String, u64)>) -> Self {
+        let sampling = sampling
+            .into_iter()
+            .map(|(route, every)| {
+                let sampling = Sampling {
+                    every,
+                    seen: AtomicU64::new(0),
+                };
+                (route, sampling)
+            })
+            .collect();
+        Self {
+            routes,
+            sampling: Arc::new(sampling),
+        }
+    }
+
+    /// Sampling from ACCESS_LOG_SAMPLE, such as `/health/live=0,/api/users=10`
+    /// to drop health checks and keep one user listing in ten
+    ///
+    /// `None` when ACCESS_LOG_ENABLED is `false`/`0`.
+    pub fn from_env(routes: Arc<RouteTable>) -> Option<Self> {
+        let enabled = std::env::var("ACCESS_LOG_ENABLED")
+            .map(|value| !matches!(value.to_ascii_lowercase().as_str(), "false" | "0"))
+            .unwrap_or(true);
+        if !enabled {
+            return None;
+        }
+
+        let sampling = std::env::var("ACCESS_LOG_SAMPLE").unwrap_or_default();
+        let sampling = sampling.split(',').filter_map(|entry| {
+            let (route, every) = entry.trim().split_once('=')?;
+            match every.trim().parse() {
+                Ok(every) => Some((route.trim().to_string(), every)),
+                Err(_) => {
+                    tracing::warn!("Ignoring ACCESS_LOG_SAMPLE entry {:?}", entry);
+                    None
+                }
+            }
+        });
+        Some(Self::new(routes, sampling.collect::<Vec<_>>()))
+    }
+
+    fn keep(&self, route: &str, status: u16) -> bool {
+        status >= 400
+            || self
+                .sampling
+                .get(route)
+                .is_none_or(|sampling| sampling.keep())
+    }
+}
+
+/// Log the request once its response is ready, unless the access log is off
+///
+/// Goes inside `request_id::assign`, so the line carries the request ID.
+/// The size is that of the whole response body when known up front, else
+/// `Content-Length`; streamed bodies are logged without one.
+pub async fn log(
+    State(access_log): State<Option<AccessLog>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(access_log) = access_log else {
+        return next.run(request).await;
+    };
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("-")
+        .to_string();
+    let ip = client_ip(request.extensions()).unwrap_or_else(|| "-".into());
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let latency = started.elapsed();
+
+    let route = access_log.routes.template(&path).unwrap_or("unmatched");
+    let status = response.status().as_u16();
+    if !access_log.keep(route, status) {
+        return response;
+    }
+    let bytes = response.body().size_hint().exact().or_else(|| {
+        response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+    });
+    tracing::info!(
+        %method,
+        route,
+        path,
+        status,
+        latency_ms = latency.as_secs_f64() * 1000.0,
+        bytes,
+        user_agent,
+        ip,
+        "{} {} {}",
+        method,
+        path,
+        status
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::Routes;
+
+    #[test]
+    fn test_sampling_keeps_errors_and_one_in_n() {
+        let (_, routes) = Routes::<()>::new()
+            .get("/health", || async {})
+            .get("/api/users", || async {})
+            .get("/api/users/:id", || async {})
+            .into_parts();
+        let access_log = AccessLog::new(
+            Arc::new(routes),
+            [("/health".to_string(), 0), ("/api/users".to_string(), 3)],
+        );
+
+        assert!(!access_log.keep("/health", 200));
+        assert!(access_log.keep("/health", 503));
+        let kept: Vec<bool> = (0..6).map(|_| access_log.keep("/api/users", 200)).collect();
+        assert_eq!(kept, [true, false, false, true, false, false]);
+        assert!(access_log.keep("/api/users", 404));
+        assert!(access_log.keep("/api/users/:id", 200));
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, Query, Request, State},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+/// Admin bearer token from ADMIN_TOKEN; `/admin` routes are disabled when unset
+pub fn admin_token_from_env() -> Option<Arc<str>> {
+    std::env::var("ADMIN_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .map(Arc::from)
+}
+
+/// Reject requests that do not carry the admin bearer token
+pub async fn require_admin_token(
+    State(token): State<Arc<str>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !super::bearer_token_matches(request.headers(), &token) {
+        tracing::warn!("Rejected admin request without a valid bearer token");
+        return AppError::Unauthorized("Missing or invalid admin token".to_string())
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Admin bearer token for handlers with admin-only options
+///
+/// Unlike the `/admin` routes, these handlers are always mounted, so the
+/// token is optional and nothing is authorized without one.
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(Option<Arc<str>>);
+
+impl AdminToken {
+    pub fn new(token: Option<Arc<str>>) -> Self {
+        Self(token)
+    }
+
+    /// ADMIN_TOKEN, as for the `/admin` routes
+    pub fn from_env() -> Self {
+        Self::new(admin_token_from_env())
+    }
+
+    /// Whether the request carries the admin bearer token
+    pub fn authorizes(&self, headers: &HeaderMap) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|token| super::bearer_token_matches(headers, token))
+    }
+}
+
+/// `?include_deleted=true`, which only the admin token may use
+#[derive(Debug, Clone, Copy)]
+pub struct IncludeDeleted(pub bool);
+
+#[derive(Deserialize)]
+struct IncludeDeletedParam {
+    #[serde(default)]
+    include_deleted: bool,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IncludeDeleted
+where
+    AdminToken: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Malformed values are left for the handler's own query extractor
+        let requested = Query::<IncludeDeletedParam>::try_from_uri(&parts.uri)
+            .is_ok_and(|Query(param)| param.include_deleted);
+        if requested && !AdminToken::from_ref(state).authorizes(&parts.headers) {
+            tracing::warn!("Rejected include_deleted without a valid admin token");
+            return Err(AppError::Unauthorized(
+                "include_deleted requires the admin token".to_string(),
+            ));
+        }
+        Ok(Self(requested))
+    }
+}
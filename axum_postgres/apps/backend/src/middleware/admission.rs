@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+use crate::error::AppError;
+
+/// Route classes with their own concurrency budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    /// GET/HEAD API requests
+    Read,
+    /// Mutating API requests
+    Write,
+}
+
+impl RouteClass {
+    /// Class of a request, or `None` for routes that are never shed
+    ///
+    /// Health probes, docs and admin controls bypass admission control so the
+    /// service stays observable and operable while it sheds load.
+    pub fn classify(method: &Method, path: &str) -> Option<Self> {
+        let exempt = ["/health", "/api-docs", "/swagger-ui", "/admin"];
+        if exempt.iter().any(|prefix| path.starts_with(prefix)) {
+            return None;
+        }
+
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => Some(RouteClass::Read),
+            _ => Some(RouteClass::Write),
+        }
+    }
+}
+
+/// Concurrency and queueing budget for one route class
+#[derive(Debug, Clone, Copy)]
+pub struct ClassLimit {
+    /// Requests processed at once
+    pub concurrency: usize,
+    /// Requests allowed to wait for a slot; beyond this they are shed at once
+    pub max_queue: usize,
+    /// Longest a request may wait for a slot before it is shed
+    pub queue_timeout: Duration,
+}
+
+struct ClassState {
+    limit: ClassLimit,
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+}
+
+/// Admission control in front of the handlers
+///
+/// Under burst load the database pool saturates and every request times out
+/// at once. Bounding in-flight requests per route class and shedding the
+/// excess early with 503 `OVERLOADED` keeps accepted requests fast.
+#[derive(Clone)]
+pub struct AdmissionControl {
+    classes: Arc<HashMap<RouteClass, ClassState>>,
+}
+
+impl AdmissionControl {
+    pub fn new(limits: impl IntoIterator<Item = (RouteClass, ClassLimit)>) -> Self {
+        let classes = limits
+            .into_iter()
+            .map(|(class, limit)| {
+                let state = ClassState {
+                    limit,
+                    slots: Arc::new(Semaphore::new(limit.concurrency)),
+                    waiting: AtomicUsize::new(0),
+                };
+                (class, state)
+            })
+            .collect();
+
+        Self {
+            classes: Arc::new(classes),
+        }
+    }
+
+    /// Limits from ADMISSION_{READ,WRITE}_CONCURRENCY, ADMISSION_{READ,WRITE}_MAX_QUEUE
+    /// and ADMISSION_QUEUE_TIMEOUT_MS
+    ///
+    /// Defaults are sized for the 10-connection database pool.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let queue_timeout = Duration::from_millis(var("ADMISSION_QUEUE_TIMEOUT_MS", 250));
+
+        let limit = |prefix: &str, concurrency: u64| {
+            let concurrency = var(&format!("ADMISSION_{}_CONCURRENCY", prefix), concurrency);
+            ClassLimit {
+                concurrency: concurrency as usize,
+                max_queue: var(&format!("ADMISSION_{}_MAX_QUEUE", prefix), concurrency * 2)
+                    as usize,
+                queue_timeout,
+            }
+        };
+
+        Self::new([
+            (RouteClass::Read, limit("READ", 20)),
+            (RouteClass::Write, limit("WRITE", 10)),
+        ])
+    }
+}
+
+/// Shed requests that cannot get a slot within their class budget
+pub async fn admit(
+    State(admission): State<AdmissionControl>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(class) = RouteClass::classify(request.method(), request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let Some(state) = admission.classes.get(&class) else {
+        return next.run(request).await;
+    };
+
+    // Fast path: a free slot needs no queueing
+    let permit = match state.slots.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            if state.waiting.fetch_add(1, Ordering::SeqCst) >= state.limit.max_queue {
+                state.waiting.fetch_sub(1, Ordering::SeqCst);
+                return shed(class, "queue full");
+            }
+
+            let acquired = tokio::time::timeout(
+                state.limit.queue_timeout,
+                state.slots.clone().acquire_owned(),
+            )
+            .await;
+            state.waiting.fetch_sub(1, Ordering::SeqCst);
+
+            match acquired {
+                Ok(Ok(permit)) => permit,
+                _ => return shed(class, "queue timeout"),
+            }
+        }
+    };
+
+    let response = next.run(request).await;
+    drop(permit);
+    response
+}
+
+fn shed(class: RouteClass, reason: &str) -> Response {
+    tracing::warn!("Shedding {:?} request: {}", class, reason);
+    AppError::Overloaded {
+        retry_after_secs: 1,
+    }
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use tower::util::ServiceExt;
+
+    fn app(limit: ClassLimit) -> Router {
+        let admission = AdmissionControl::new([(RouteClass::Read, limit)]);
+        Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    "done"
+                }),
+            )
+            .route("/health", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(admission, admit))
+    }
+
+    fn get_request(uri: &str) -> Request {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            RouteClass::classify(&Method::GET, "/api/users"),
+            Some(RouteClass::Read)
+        );
+        assert_eq!(
+            RouteClass::classify(&Method::DELETE, "/api/users/1"),
+            Some(RouteClass::Write)
+        );
+        assert_eq!(RouteClass::classify(&Method::GET, "/health/ready"), None);
+    }
+
+    #[tokio::test]
+    async fn test_excess_requests_are_shed_after_queue_budget() {
+        let app = app(ClassLimit {
+            concurrency: 1,
+            max_queue: 10,
+            queue_timeout: Duration::from_millis(20),
+        });
+
+        let first = tokio::spawn(app.clone().oneshot(get_request("/slow")));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let shed = app.clone().oneshot(get_request("/slow")).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(shed.headers()["retry-after"], "1");
+
+        // Exempt routes are never shed
+        let health = app.clone().oneshot(get_request("/health")).await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_sheds_immediately() {
+        let app = app(ClassLimit {
+            concurrency: 1,
+            max_queue: 0,
+            queue_timeout: Duration::from_secs(10),
+        });
+
+        let first = tokio::spawn(app.clone().oneshot(get_request("/slow")));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let started = std::time::Instant::now();
+        let shed = app.clone().oneshot(get_request("/slow")).await.unwrap();
+        assert_eq!(shed.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        first.await.unwrap().unwrap();
+        let admitted = app.oneshot(get_request("/slow")).await.unwrap();
+        assert_eq!(admitted.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,52 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sqlx::PgPool;
+
+use crate::auth::api_key::{ApiKeyPrincipal, ApiKeyUsage, API_KEY_HEADER};
+use crate::auth::token::fingerprint;
+use crate::error::AppError;
+use crate::repository::api_keys::{ApiKeyRepository, ApiKeyRepositoryTrait};
+
+/// Resolve `X-API-Key` to an [`ApiKeyPrincipal`] in the request extensions
+///
+/// Requests without the header pass through untouched; an unknown,
+/// revoked or expired key, or one of another tenant than the request's,
+/// gets 401 rather than falling back to other credentials. Keys that
+/// authenticate are noted as used in [`ApiKeyUsage`].
+pub async fn authenticate(
+    State(pool): State<PgPool>,
+    State(usage): State<ApiKeyUsage>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = request.headers().get(API_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    let hash = fingerprint(key.to_str().unwrap_or_default());
+
+    match ApiKeyRepository::new(pool).authenticate(&hash).await {
+        Ok(Some(api_key)) => {
+            usage.record(api_key.id);
+            let principal = ApiKeyPrincipal::from(api_key);
+            tracing::debug!(
+                "Authenticated API key {} ({})",
+                principal.id,
+                principal.name
+            );
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Ok(None) => {
+            tracing::warn!("Rejected unknown, revoked or expired API key");
+            AppError::Unauthorized("Invalid API key".to_string()).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Failed to look up API key: {}", e);
+            AppError::InternalServerError("Failed to authenticate API key".to_string())
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! Deadline for the database work of each request
+//!
+//! See [`crate::database::deadline`]. Goes inside `resilience::unavailable`,
+//! which turns the operations cut short into 503 with Retry-After.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Instant;
+
+use crate::database::deadline;
+
+/// Give the database work of each request `budget` from when it gets
+/// here; `None` leaves requests without a deadline
+pub async fn bound(
+    State(budget): State<Option<Duration>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match budget {
+        Some(budget) => deadline::scope(Instant::now() + budget, next.run(request)).await,
+        None => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use tower::util::ServiceExt;
+
+    use crate::error::AppError;
+    use crate::middleware::resilience::unavailable;
+    use crate::repository::resilience::resilient;
+
+    #[tokio::test]
+    async fn test_requests_past_their_deadline_get_503() {
+        let app = Router::new()
+            .route(
+                "/saturated",
+                get(|| async {
+                    // Waits as long as a request queued behind a busy pool
+                    resilient("test.saturated", || async {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        Ok(())
+                    })
+                    .await
+                    .map_err(AppError::from)
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Some(Duration::from_millis(20)),
+                bound,
+            ))
+            .layer(axum::middleware::from_fn(unavailable));
+
+        let started = std::time::Instant::now();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/saturated")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key("retry-after"));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
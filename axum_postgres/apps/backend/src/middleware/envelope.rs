@@ -0,0 +1,109 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+use crate::error::PROBLEM_JSON;
+use crate::models::envelope::ApiResponse;
+
+/// Whether `/api` success bodies are wrapped as `{success, data, meta}`
+///
+/// Error bodies, problem details, gain `success: false` so clients can
+/// branch on `success` alone. Opt-in through RESPONSE_ENVELOPE.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResponseEnvelope(bool);
+
+impl ResponseEnvelope {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled)
+    }
+
+    /// RESPONSE_ENVELOPE (`true`/`1` enables the envelope)
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("RESPONSE_ENVELOPE")
+            .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1"))
+            .unwrap_or(false);
+        Self::new(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0
+    }
+}
+
+/// Wrap successful JSON responses under `/api` in the envelope
+///
+/// Problem details get `success: false` added, and other bodies, such as
+/// JSON:API documents, are passed through.
+pub async fn wrap(
+    State(envelope): State<ResponseEnvelope>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !envelope.is_enabled() || !request.uri().path().starts_with("/api/") {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    let content_type = response.headers().get(header::CONTENT_TYPE);
+    let is_json = content_type.is_some_and(|value| value.as_bytes() == b"application/json");
+    let is_problem = content_type.is_some_and(|value| value.as_bytes() == PROBLEM_JSON.as_bytes());
+    let is_success = response.status().is_success() && response.status() != StatusCode::NO_CONTENT;
+    if !(is_success && is_json || is_problem) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let Ok(data) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+
+    let body = if is_problem {
+        let mut problem = data;
+        problem["success"] = json!(false);
+        problem
+    } else {
+        envelop(data)
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+/// `{success: true, data, meta}` for a success body, see [`ApiResponse`]
+pub fn envelop(data: Value) -> Value {
+    serde_json::to_value(ApiResponse::from_body(data)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelop_objects_and_lists() {
+        let user = json!({"id": "1", "name": "Jane"});
+        assert_eq!(
+            envelop(user.clone()),
+            json!({"success": true, "data": user, "meta": {}})
+        );
+
+        let list = envelop(json!([{"id": "1"}, {"id": "2"}]));
+        assert_eq!(list["meta"]["count"], 2);
+        assert_eq!(list["data"][1]["id"], "2");
+
+        let linked = envelop(json!({
+            "data": [{"id": "1"}],
+            "next_cursor": "abc",
+            "_links": {"self": {"href": "/api/users", "method": "GET"}}
+        }));
+        assert_eq!(linked["data"], json!([{"id": "1"}]));
+        assert_eq!(linked["meta"]["links"]["self"]["href"], "/api/users");
+        assert_eq!(linked["meta"]["next_cursor"], "abc");
+    }
+}
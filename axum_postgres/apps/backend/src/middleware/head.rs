@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::routes::RouteTable;
+
+/// Answer HEAD by running the request as GET and dropping the body
+///
+/// axum strips HEAD bodies per route, but the response-mapping layers
+/// (envelope, JSON:API) would still see an empty body and leave headers
+/// describing the unmapped one. Running the whole stack as GET keeps
+/// Content-Length, ETag and Cache-Control identical to the GET response.
+/// Mount outside every layer that rewrites bodies or headers.
+///
+/// Routes with their own HEAD handler, such as cheap existence checks,
+/// receive the HEAD request unchanged.
+pub async fn serve_head(
+    State(routes): State<Arc<RouteTable>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::HEAD
+        || routes.has_handler(request.uri().path(), Method::HEAD.as_str())
+    {
+        return next.run(request).await;
+    }
+
+    *request.method_mut() = Method::GET;
+    let (mut parts, body) = next.run(request).await.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    // 204 and 304 never carry a body, so they have no length to advertise
+    if !matches!(parts.status, StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED) {
+        parts
+            .headers
+            .insert(header::CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+    }
+    Response::from_parts(parts, Body::empty())
+}
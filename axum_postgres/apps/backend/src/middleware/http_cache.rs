@@ -0,0 +1,254 @@
+//! Cache-Control policies and `If-Modified-Since` for read endpoints
+//!
+//! Successful GET responses under `/api` get the Cache-Control of their
+//! route from CACHE_CONTROL_ROUTES, which replaces whatever the handler
+//! set, or else CACHE_CONTROL_DEFAULT when the handler set none. Responses
+//! carrying `Last-Modified` become a bodiless 304 when the client's
+//! `If-Modified-Since` is no older, so pollers stop downloading unchanged
+//! users. `If-None-Match` takes precedence, as RFC 9110 asks: ETags catch
+//! changes within the same second, which HTTP dates cannot.
+//!
+//! The handler still runs; what is saved is the response body on the wire.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::conditional::modified_since;
+use crate::routes::RouteTable;
+
+/// Default for CACHE_CONTROL_DEFAULT: responses are per user, and clients
+/// must revalidate before reusing one
+pub const DEFAULT_CACHE_CONTROL: &str = "private, no-cache";
+
+/// Cache-Control policy of every route, shared by every request
+#[derive(Clone)]
+pub struct HttpCaching {
+    routes: Arc<RouteTable>,
+    default: Option<HeaderValue>,
+    policies: Arc<HashMap<String, HeaderValue>>,
+}
+
+impl HttpCaching {
+    /// `default` for `/api` routes without a policy of their own in
+    /// `policies`, which maps route templates to Cache-Control values
+    pub fn new(
+        routes: Arc<RouteTable>,
+        default: Option<HeaderValue>,
+        policies: HashMap<String, HeaderValue>,
+    ) -> Self {
+        Self {
+            routes,
+            default,
+            policies: Arc::new(policies),
+        }
+    }
+
+    /// Policies from CACHE_CONTROL_ROUTES, such as
+    /// `/api/users/:id=private, max-age=30;/api/users/count=no-store`, and
+    /// CACHE_CONTROL_DEFAULT, `private, no-cache` unless set; an empty
+    /// default leaves responses as the handlers made them
+    pub fn from_env(routes: Arc<RouteTable>) -> Result<Self, String> {
+        let value = |name: &str, value: &str| {
+            HeaderValue::from_str(value.trim())
+                .map_err(|_| format!("{} has an invalid Cache-Control value: {:?}", name, value))
+        };
+        let default = match std::env::var("CACHE_CONTROL_DEFAULT") {
+            Ok(default) if default.trim().is_empty() => None,
+            Ok(default) => Some(value("CACHE_CONTROL_DEFAULT", &default)?),
+            Err(_) => Some(HeaderValue::from_static(DEFAULT_CACHE_CONTROL)),
+        };
+        let mut policies = HashMap::new();
+        let routes_var = std::env::var("CACHE_CONTROL_ROUTES").unwrap_or_default();
+        for entry in routes_var
+            .split(';')
+            .filter(|entry| !entry.trim().is_empty())
+        {
+            let Some((route, policy)) = entry
+                .split_once('=')
+                .filter(|(route, _)| route.trim().starts_with('/'))
+            else {
+                return Err(format!(
+                    "CACHE_CONTROL_ROUTES entry is not /route=policy: {:?}",
+                    entry
+                ));
+            };
+            policies.insert(
+                route.trim().to_string(),
+                value("CACHE_CONTROL_ROUTES", policy)?,
+            );
+        }
+        Ok(Self::new(routes, default, policies))
+    }
+
+    /// Cache-Control for `path`, and whether it replaces the handler's
+    fn policy(&self, path: &str) -> Option<(&HeaderValue, bool)> {
+        let route_policy = self
+            .routes
+            .template(path)
+            .and_then(|route| self.policies.get(route));
+        match route_policy {
+            Some(policy) => Some((policy, true)),
+            None if path.starts_with("/api/") => {
+                self.default.as_ref().map(|policy| (policy, false))
+            }
+            None => None,
+        }
+    }
+}
+
+/// Apply the Cache-Control policy and answer `If-Modified-Since`
+///
+/// Goes inside `head::serve_head`, so HEAD requests arrive as GET.
+pub async fn apply(State(caching): State<HttpCaching>, request: Request, next: Next) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+    let path = request.uri().path().to_string();
+    let headers = request.headers();
+    let if_modified_since = if headers.contains_key(header::IF_NONE_MATCH) {
+        None
+    } else {
+        headers.get(header::IF_MODIFIED_SINCE).cloned()
+    };
+
+    let mut response = next.run(request).await;
+    let status = response.status();
+    if !(status.is_success() || status == StatusCode::NOT_MODIFIED) {
+        return response;
+    }
+
+    if let Some((policy, replace)) = caching.policy(&path) {
+        if replace || !response.headers().contains_key(header::CACHE_CONTROL) {
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, policy.clone());
+        }
+    }
+
+    let unmodified = status == StatusCode::OK
+        && if_modified_since.is_some_and(|since| {
+            response
+                .headers()
+                .get(header::LAST_MODIFIED)
+                .is_some_and(|last_modified| !modified_since(last_modified, &since))
+        });
+    if !unmodified {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::CONTENT_TYPE);
+    Response::from_parts(parts, Body::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use tower::util::ServiceExt;
+
+    const LAST_MODIFIED: &str = "Tue, 02 Jan 2024 03:04:05 GMT";
+
+    fn app(policies: &[(&str, &str)]) -> Router {
+        let (router, routes) = crate::routes::Routes::new()
+            .get("/api/users/:id", || async {
+                ([(header::LAST_MODIFIED, LAST_MODIFIED)], "user")
+            })
+            .get("/api/users", || async {
+                ([(header::CACHE_CONTROL, "no-cache")], "users")
+            })
+            .get("/health/live", || async { "ok" })
+            .into_parts();
+        let policies = policies
+            .iter()
+            .map(|(route, policy)| (route.to_string(), HeaderValue::from_str(policy).unwrap()))
+            .collect();
+        let caching = HttpCaching::new(
+            Arc::new(routes),
+            Some(HeaderValue::from_static(DEFAULT_CACHE_CONTROL)),
+            policies,
+        );
+        router
+            .with_state(())
+            .layer(axum::middleware::from_fn_with_state(caching, apply))
+    }
+
+    async fn send(app: Router, uri: &str, headers: &[(&str, &str)]) -> Response {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn cache_control(response: &Response) -> Option<&str> {
+        response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .map(|value| value.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_route_policies_replace_the_default() {
+        let app = app(&[("/api/users/:id", "private, max-age=30")]);
+        let user = send(app.clone(), "/api/users/7", &[]).await;
+        assert_eq!(cache_control(&user), Some("private, max-age=30"));
+        // The handler's own header beats the default
+        let users = send(app.clone(), "/api/users", &[]).await;
+        assert_eq!(cache_control(&users), Some("no-cache"));
+        // Only /api is covered by the default
+        let health = send(app, "/health/live", &[]).await;
+        assert_eq!(cache_control(&health), None);
+
+        let user = send(self::app(&[]), "/api/users/7", &[]).await;
+        assert_eq!(cache_control(&user), Some(DEFAULT_CACHE_CONTROL));
+    }
+
+    #[tokio::test]
+    async fn test_if_modified_since() {
+        let unchanged = send(
+            app(&[]),
+            "/api/users/7",
+            &[("if-modified-since", LAST_MODIFIED)],
+        )
+        .await;
+        assert_eq!(unchanged.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(unchanged.headers()[header::LAST_MODIFIED], LAST_MODIFIED);
+        assert_eq!(cache_control(&unchanged), Some(DEFAULT_CACHE_CONTROL));
+        let body = axum::body::to_bytes(unchanged.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let changed = send(
+            app(&[]),
+            "/api/users/7",
+            &[("if-modified-since", "Tue, 02 Jan 2024 03:04:04 GMT")],
+        )
+        .await;
+        assert_eq!(changed.status(), StatusCode::OK);
+
+        // If-None-Match wins, and unparsable dates are ignored
+        for headers in [
+            [
+                ("if-modified-since", LAST_MODIFIED),
+                ("if-none-match", "\"other\""),
+            ],
+            [("if-modified-since", "yesterday"), ("accept", "*/*")],
+        ] {
+            let response = send(app(&[]), "/api/users/7", &headers).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+}
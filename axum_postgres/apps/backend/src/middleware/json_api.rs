@@ -0,0 +1,129 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::models::json_api::{
+    attributes_from_document, error_document, to_document, ResourceKind, MEDIA_TYPE,
+};
+
+/// Largest JSON:API request document accepted, matching axum's `Json` default
+const MAX_REQUEST_BYTES: usize = 2 * 1024 * 1024;
+
+/// Serve `application/vnd.api+json` to clients that ask for it
+///
+/// Request documents are unwrapped to the plain body the handlers expect, and
+/// JSON responses are rewritten into resource or error documents when the
+/// `Accept` header lists the JSON:API media type. Other clients are untouched.
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let Some(kind) = ResourceKind::from_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let wants_json_api = accepts_json_api(request.headers());
+
+    let request = match unwrap_request(request).await {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+    let mut response = next.run(request).await;
+    // The representation depends on Accept, so caches must key on it
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+
+    if wants_json_api {
+        wrap_response(kind, response).await
+    } else {
+        response
+    }
+}
+
+fn accepts_json_api(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.trim() == MEDIA_TYPE)
+}
+
+/// Replace a JSON:API request document with its attributes
+async fn unwrap_request(request: Request) -> Result<Request, Response> {
+    let Some(content_type) = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return Ok(request);
+    };
+    if !content_type.starts_with(MEDIA_TYPE) {
+        return Ok(request);
+    }
+    // The spec forbids media type parameters on request documents
+    if content_type != MEDIA_TYPE {
+        return Err(error_response(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "JSON:API media type must not have parameters",
+        ));
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let bytes = to_bytes(body, MAX_REQUEST_BYTES)
+        .await
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let document = serde_json::from_slice(&bytes)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    let attributes = attributes_from_document(document)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &e))?;
+
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Request::from_parts(parts, Body::from(attributes.to_string())))
+}
+
+/// Rewrite a plain JSON response as a JSON:API document
+async fn wrap_response(kind: ResourceKind, response: Response) -> Response {
+    if matches!(
+        response.status(),
+        StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+    ) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    let document = if parts.status.is_success() {
+        match serde_json::from_slice(&bytes) {
+            Ok(body) => to_document(kind, body),
+            // Not JSON, so there is nothing to adapt
+            Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+        }
+    } else {
+        error_document(parts.status, &bytes)
+    };
+
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(MEDIA_TYPE));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(document.to_string()))
+}
+
+fn error_response(status: StatusCode, detail: &str) -> Response {
+    let body = serde_json::json!({"detail": detail});
+    (
+        status,
+        [(header::CONTENT_TYPE, MEDIA_TYPE)],
+        error_document(status, body.to_string().as_bytes()).to_string(),
+    )
+        .into_response()
+}
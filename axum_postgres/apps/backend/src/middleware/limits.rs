@@ -0,0 +1,280 @@
+//! Request timeout, body size limit and panic recovery
+//!
+//! [`bound`] limits the body of every request and the time it takes to
+//! answer. Upload routes, whose bodies are read as they arrive, get a body
+//! limit of their own and no timeout. Extractors that read too much answer
+//! with a plain-text 413 and tower-http's panic layer with nothing at all;
+//! [`problem_details`] and [`panic_response`] turn those into the app's
+//! problem details.
+
+use std::any::Any;
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+
+use crate::error::{AppError, PROBLEM_JSON};
+
+/// Limits applied to every request
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    /// Longest a handler may take to start its response
+    pub timeout: Duration,
+    /// Largest request body accepted
+    pub max_body_bytes: usize,
+    /// Largest body accepted by the upload routes
+    pub max_upload_bytes: usize,
+    /// Paths of the `POST` routes that take uploads
+    pub upload_paths: &'static [&'static str],
+}
+
+impl RequestLimits {
+    /// Limits from REQUEST_TIMEOUT_SECS, MAX_BODY_BYTES and MAX_UPLOAD_BYTES
+    ///
+    /// Defaults to 30 seconds, axum's own 2 MB body limit and 4 GB uploads.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            timeout: Duration::from_secs(var("REQUEST_TIMEOUT_SECS", 30)),
+            max_body_bytes: var("MAX_BODY_BYTES", 2 * 1024 * 1024) as usize,
+            max_upload_bytes: var("MAX_UPLOAD_BYTES", 4 * 1024 * 1024 * 1024) as usize,
+            upload_paths: &[],
+        }
+    }
+
+    /// Treat `POST` requests to `paths` as uploads
+    pub fn with_uploads(mut self, paths: &'static [&'static str]) -> Self {
+        self.upload_paths = paths;
+        self
+    }
+
+    fn is_upload(&self, request: &Request) -> bool {
+        request.method() == Method::POST && self.upload_paths.contains(&request.uri().path())
+    }
+
+    /// Largest body accepted for `request`
+    fn body_limit(&self, request: &Request) -> usize {
+        if self.is_upload(request) {
+            self.max_upload_bytes
+        } else {
+            self.max_body_bytes
+        }
+    }
+}
+
+/// Limit the body of requests and the time they take to answer
+///
+/// Bodies declared larger than the limit get a 413 at once; others are cut
+/// off once they pass it, which the extractor reading them reports. Upload
+/// routes have no timeout, as sending a large file takes as long as it
+/// takes. Replaces axum's default body limit, which must be disabled.
+pub async fn bound(State(limits): State<RequestLimits>, request: Request, next: Next) -> Response {
+    let limit = limits.body_limit(&request);
+    let declared = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if declared.is_some_and(|length| length > limit as u64) {
+        return AppError::PayloadTooLarge { limit }.into_response();
+    }
+
+    let upload = limits.is_upload(&request);
+    let request = request.map(|body| Body::new(Limited::new(body, limit)));
+    if upload {
+        return next.run(request).await;
+    }
+    match tokio::time::timeout(limits.timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => AppError::RequestTimeout.into_response(),
+    }
+}
+
+/// Answer the timeout and body limit rejections with problem details
+///
+/// Goes outside [`bound`]. Handlers that read too much of the body get a
+/// 413 from their extractor, which is rewritten with the limit of the
+/// request; responses that already are problem details are left alone.
+pub async fn problem_details(
+    State(limits): State<RequestLimits>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limit = limits.body_limit(&request);
+    let response = next.run(request).await;
+    let is_problem = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes() == PROBLEM_JSON.as_bytes());
+    if is_problem {
+        return response;
+    }
+
+    match response.status() {
+        StatusCode::REQUEST_TIMEOUT => AppError::RequestTimeout.into_response(),
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge { limit }.into_response(),
+        _ => response,
+    }
+}
+
+/// 500 for a panicking handler, for `CatchPanicLayer::custom`
+///
+/// The panic message is logged, never sent to the client.
+pub fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    tracing::error!("Handler panicked: {}", message);
+    AppError::InternalServerError("Internal server error".to_string()).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        extract::DefaultBodyLimit,
+        routing::{get, post},
+        Router,
+    };
+    use tower::util::ServiceExt;
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    fn app() -> Router {
+        let limits = RequestLimits {
+            timeout: Duration::from_millis(50),
+            max_body_bytes: 16,
+            max_upload_bytes: 32,
+            upload_paths: &["/upload"],
+        };
+        Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    "done"
+                }),
+            )
+            .route("/echo", post(|body: String| async move { body }))
+            .route(
+                "/upload",
+                post(|body: String| async move {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    body
+                }),
+            )
+            .route("/panic", get(boom))
+            .route(
+                "/missing",
+                get(|| async { AppError::UserNotFound.into_response() }),
+            )
+            .layer(
+                tower::ServiceBuilder::new()
+                    .layer(CatchPanicLayer::custom(panic_response))
+                    .layer(axum::middleware::from_fn_with_state(
+                        limits,
+                        problem_details,
+                    ))
+                    .layer(axum::middleware::from_fn_with_state(limits, bound))
+                    .layer(DefaultBodyLimit::disable()),
+            )
+    }
+
+    async fn boom() -> &'static str {
+        panic!("boom")
+    }
+
+    async fn send(request: Request<Body>) -> (StatusCode, serde_json::Value) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_JSON);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn get_request(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_a_problem() {
+        let (status, body) = send(get_request("/slow")).await;
+        assert_eq!(status, StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(body["code"], "REQUEST_TIMEOUT");
+    }
+
+    #[tokio::test]
+    async fn test_large_bodies_are_problems() {
+        let large = "x".repeat(17);
+        // Declared too large, and found too large while streaming
+        for content_length in [Some(large.len()), None] {
+            let mut request = Request::builder().method("POST").uri("/echo");
+            if let Some(length) = content_length {
+                request = request.header(header::CONTENT_LENGTH, length);
+            }
+            let (status, body) = send(request.body(Body::from(large.clone())).unwrap()).await;
+            assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+            assert_eq!(body["code"], "PAYLOAD_TOO_LARGE");
+            assert_eq!(body["detail"], "Request body is larger than 16 bytes");
+        }
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/echo")
+            .body(Body::from("small"))
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_have_their_own_limits() {
+        // Larger than other bodies, and slower than the timeout
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .body(Body::from("x".repeat(32)))
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .body(Body::from("x".repeat(33)))
+            .unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["detail"], "Request body is larger than 32 bytes");
+    }
+
+    #[tokio::test]
+    async fn test_panics_are_internal_errors() {
+        let (status, body) = send(get_request("/panic")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "INTERNAL_ERROR");
+        assert_eq!(body["detail"], "Internal server error");
+    }
+
+    #[tokio::test]
+    async fn test_other_problems_pass_through() {
+        let (status, body) = send(get_request("/missing")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "USER_NOT_FOUND");
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{counter, histogram};
+
+use crate::metrics::{HTTP_REQUESTS_TOTAL, HTTP_REQUEST_DURATION};
+use crate::routes::RouteTable;
+
+/// Route label of paths that match no route, so scans of random paths do
+/// not create a series each
+const UNMATCHED: &str = "unmatched";
+
+/// Count every request and its latency by method, route template and status
+///
+/// Runs outside the other layers so their rejections, such as 429 and 503,
+/// are counted too.
+pub async fn track(
+    State(routes): State<Arc<RouteTable>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = routes
+        .template(request.uri().path())
+        .unwrap_or(UNMATCHED)
+        .to_string();
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    counter!(HTTP_REQUESTS_TOTAL, 1, &labels);
+    histogram!(
+        HTTP_REQUEST_DURATION,
+        started.elapsed().as_secs_f64(),
+        &labels
+    );
+    response
+}
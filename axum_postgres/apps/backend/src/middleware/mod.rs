@@ -0,0 +1,64 @@
+pub mod access_log;
+pub mod admin;
+pub mod admission;
+pub mod api_key;
+pub mod deadline;
+pub mod envelope;
+pub mod head;
+pub mod http_cache;
+pub mod json_api;
+pub mod limits;
+pub mod metrics;
+pub mod options;
+pub mod pretty;
+pub mod proxy;
+pub mod rate_limit;
+pub mod read_only;
+pub mod request_id;
+pub mod resilience;
+pub mod session;
+pub mod slo;
+pub mod tenant;
+
+use std::net::SocketAddr;
+
+use axum::extract::ConnectInfo;
+use axum::http::{header, Extensions, HeaderMap};
+
+use self::proxy::ClientIp;
+
+/// Token of an `Authorization: Bearer <token>` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// The [`ClientIp`] resolved by [`proxy::resolve`], else the peer address
+/// when the server was started with connect info
+pub fn client_ip(extensions: &Extensions) -> Option<String> {
+    let client = extensions.get::<ClientIp>().map(|ClientIp(ip)| *ip);
+    client
+        .or_else(|| {
+            extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip())
+        })
+        .map(|ip| ip.to_string())
+}
+
+/// Whether the request carries `Authorization: Bearer <expected>`
+pub fn bearer_token_matches(headers: &HeaderMap, expected: &str) -> bool {
+    bearer_token(headers).is_some_and(|provided| constant_time_eq(provided, expected))
+}
+
+/// Compare secrets in constant time, so they cannot be guessed byte by byte
+pub fn constant_time_eq(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::routes::RouteTable;
+
+/// Answer OPTIONS with the methods registered for the path
+///
+/// CORS preflights are answered by `CorsLayer`, which must sit outside this
+/// middleware. Unknown paths fall through to the usual 404.
+pub async fn answer_options(
+    State(routes): State<Arc<RouteTable>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    match routes.allowed_methods(request.uri().path()) {
+        Some(methods) => (
+            StatusCode::NO_CONTENT,
+            [(header::ALLOW, methods.join(", "))],
+        )
+            .into_response(),
+        None => next.run(request).await,
+    }
+}
@@ -0,0 +1,188 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::de::IgnoredAny;
+
+/// Whether `?pretty=true` may ask for indented JSON
+///
+/// On by default for debugging; set PRETTY_JSON=false in production to skip
+/// the extra buffering and keep responses compact whatever the client asks.
+#[derive(Debug, Clone, Copy)]
+pub struct PrettyJson(bool);
+
+impl Default for PrettyJson {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+impl PrettyJson {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled)
+    }
+
+    /// PRETTY_JSON (`false`/`0` disables pretty printing)
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("PRETTY_JSON")
+            .map(|value| !matches!(value.to_ascii_lowercase().as_str(), "false" | "0"))
+            .unwrap_or(true);
+        Self::new(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0
+    }
+}
+
+/// Indent JSON responses for clients that ask for it
+///
+/// Requested with `?pretty=true` or a `pretty=true` parameter on an `Accept`
+/// media range. Applies to every JSON body, including errors, JSON:API and
+/// SCIM documents and the OpenAPI spec. Key order is kept as serialized.
+pub async fn indent(State(pretty): State<PrettyJson>, request: Request, next: Next) -> Response {
+    if !pretty.is_enabled() || !wants_pretty(&request) {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    if !is_json(response.headers()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    // Only well-formed JSON is reformatted; anything else goes out unchanged
+    if bytes.is_empty() || serde_json::from_slice::<IgnoredAny>(&bytes).is_err() {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(pretty_print(&bytes)))
+}
+
+fn wants_pretty(request: &Request) -> bool {
+    let in_query = request.uri().query().is_some_and(|query| {
+        query
+            .split('&')
+            .any(|pair| matches!(pair, "pretty" | "pretty=true" | "pretty=1"))
+    });
+
+    in_query
+        || request
+            .headers()
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split([',', ';']))
+            .any(|param| param.trim().eq_ignore_ascii_case("pretty=true"))
+}
+
+/// `application/json` and `+json` types such as `application/scim+json`
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| {
+            let media_type = media_type.trim();
+            media_type == "application/json" || media_type.ends_with("+json")
+        })
+}
+
+/// Re-indent valid JSON with two spaces, without reordering object keys
+///
+/// Parsing into `serde_json::Value` would sort keys, so the bytes are
+/// reformatted directly; whitespace outside strings is dropped and rewritten.
+fn pretty_print(json: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    let newline = |out: &mut Vec<u8>, depth: usize| {
+        out.push(b'\n');
+        out.extend(std::iter::repeat_n(b' ', depth * 2));
+    };
+
+    let mut bytes = json.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        if in_string {
+            out.push(byte);
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => {
+                in_string = true;
+                out.push(byte);
+            }
+            b'{' | b'[' => {
+                out.push(byte);
+                while bytes.next_if(u8::is_ascii_whitespace).is_some() {}
+                // Empty containers stay on one line
+                if let Some(close) = bytes.next_if(|next| matches!(next, b'}' | b']')) {
+                    out.push(close);
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            b'}' | b']' => {
+                depth -= 1;
+                newline(&mut out, depth);
+                out.push(byte);
+            }
+            b',' => {
+                out.push(byte);
+                newline(&mut out, depth);
+            }
+            b':' => out.extend_from_slice(b": "),
+            byte if byte.is_ascii_whitespace() => {}
+            byte => out.push(byte),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pretty_print_keeps_key_order() {
+        let compact = br#"{"success":false,"message":"a, \"b\": {c}","data":[],"meta":{"count":2,"ids":[1, 2]}}"#;
+        let expected = r#"{
+  "success": false,
+  "message": "a, \"b\": {c}",
+  "data": [],
+  "meta": {
+    "count": 2,
+    "ids": [
+      1,
+      2
+    ]
+  }
+}"#;
+        assert_eq!(String::from_utf8(pretty_print(compact)).unwrap(), expected);
+
+        // Matches serde_json's own pretty printer for sorted input
+        let value = serde_json::json!({"a": {"b": [true, null, "x"]}, "c": {}});
+        assert_eq!(
+            pretty_print(value.to_string().as_bytes()),
+            serde_json::to_vec_pretty(&value).unwrap()
+        );
+    }
+}
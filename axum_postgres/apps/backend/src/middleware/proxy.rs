@@ -0,0 +1,185 @@
+//! Client address of requests that come through reverse proxies
+//!
+//! Anyone can send `X-Forwarded-For`, so it is only read when the peer is
+//! one of the proxies listed in TRUSTED_PROXIES. Each proxy appends the
+//! address it got the request from, so the client is the right-most
+//! address that is not a trusted proxy itself; addresses left of it were
+//! written by the client and are ignored. [`resolve`] stores the result
+//! as a [`ClientIp`] extension, which rate limits, the access log and the
+//! audit log read through [`super::client_ip`].
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+
+/// Header proxies append the address they got the request from to
+pub const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Address of the client that sent the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Proxies whose `X-Forwarded-For` is believed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies {
+    networks: Arc<[IpNet]>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: impl IntoIterator<Item = IpNet>) -> Self {
+        Self {
+            networks: networks.into_iter().collect(),
+        }
+    }
+
+    /// Addresses and networks from TRUSTED_PROXIES, comma separated, e.g.
+    /// `10.0.0.0/8,192.0.2.1`; none by default
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let Some(value) = var("TRUSTED_PROXIES") else {
+            return Ok(Self::default());
+        };
+        let networks = value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("TRUSTED_PROXIES has an invalid address: {}", entry))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(networks))
+    }
+
+    pub fn trusts(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(&ip))
+    }
+
+    /// Client of a request from `peer`
+    ///
+    /// The peer itself unless it is trusted; then the right-most hop of
+    /// `X-Forwarded-For` that is not trusted, or the left-most when every
+    /// hop is. A hop that is not an address ends the search at the hop
+    /// right of it.
+    pub fn client(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer;
+        if !self.trusts(peer) {
+            return client;
+        }
+        let hops: Vec<&str> = headers
+            .get_all(FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        for hop in hops.into_iter().rev() {
+            let Ok(ip) = hop.parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.trusts(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// Store the [`ClientIp`] of requests whose peer address is known
+pub async fn resolve(
+    State(proxies): State<TrustedProxies>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(peer)) = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied()
+    {
+        let client = proxies.client(peer.ip(), request.headers());
+        request.extensions_mut().insert(ClientIp(client));
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Result<TrustedProxies, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        TrustedProxies::from_lookup(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(FORWARDED_FOR, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_trusted_proxies_from_env() {
+        assert_eq!(config(&[]).unwrap(), TrustedProxies::default());
+        let proxies =
+            config(&[("TRUSTED_PROXIES", "10.0.0.0/8, 192.0.2.1,2001:db8::/32")]).unwrap();
+        assert!(proxies.trusts(ip("10.1.2.3")));
+        assert!(proxies.trusts(ip("192.0.2.1")));
+        assert!(!proxies.trusts(ip("192.0.2.2")));
+        assert!(proxies.trusts(ip("2001:db8::1")));
+        assert!(config(&[("TRUSTED_PROXIES", "10.0.0.0/33")]).is_err());
+        assert!(config(&[("TRUSTED_PROXIES", "proxy.internal")]).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_for_is_only_read_from_trusted_proxies() {
+        let proxies = config(&[("TRUSTED_PROXIES", "10.0.0.0/8")]).unwrap();
+        let headers = forwarded_for(&["198.51.100.9, 203.0.113.7, 10.0.0.2"]);
+
+        // A client sending the header itself is not believed
+        assert_eq!(
+            proxies.client(ip("203.0.113.50"), &headers),
+            ip("203.0.113.50")
+        );
+        assert_eq!(
+            TrustedProxies::default().client(ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+
+        // Behind proxies, the hop left of them; spoofed hops further left
+        // are ignored
+        assert_eq!(proxies.client(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+        let split = forwarded_for(&["198.51.100.9", "203.0.113.7, 10.0.0.2"]);
+        assert_eq!(proxies.client(ip("10.0.0.1"), &split), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_forwarded_for_edge_cases() {
+        let proxies = config(&[("TRUSTED_PROXIES", "10.0.0.0/8")]).unwrap();
+        let client = |values: &[&str]| proxies.client(ip("10.0.0.1"), &forwarded_for(values));
+
+        assert_eq!(client(&[]), ip("10.0.0.1"));
+        assert_eq!(client(&["10.0.0.3, 10.0.0.2"]), ip("10.0.0.3"));
+        assert_eq!(client(&["203.0.113.7, unknown, 10.0.0.2"]), ip("10.0.0.2"));
+        assert_eq!(client(&["unknown"]), ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,581 @@
+//! Token bucket rate limiting per client
+//!
+//! Each client has a bucket per [`RouteGroup`] that holds up to the group's
+//! [`Quota`] of requests and refills evenly over its period. Signed-in users
+//! are keyed by their user ID, so they keep their budget across addresses;
+//! everyone else by client IP. Limits live in a [`RateLimitStore`]: in
+//! this instance's memory by default, so several instances each enforce
+//! their own limit, or with RATE_LIMIT_BACKEND=redis in Redis, where a
+//! sliding window of each client's requests is shared by every instance.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use metrics::counter;
+
+use crate::auth::token::{TokenKeys, TokenKind};
+use crate::cache::redis::RedisCache;
+use crate::cache::CACHE_ERRORS_TOTAL;
+use crate::error::AppError;
+use crate::middleware::{bearer_token, client_ip};
+
+/// Bucket count above which the memory store drops full buckets
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Routes sharing a limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    /// Sign-in, registration and password routes under `/api/auth`
+    Auth,
+    /// Every other API route, SCIM included
+    Api,
+}
+
+impl RouteGroup {
+    /// Group of a request path, or `None` for routes that are never limited
+    ///
+    /// Health probes, docs and admin controls are exempt, as for admission
+    /// control.
+    pub fn classify(path: &str) -> Option<Self> {
+        if path.starts_with("/api/auth/") {
+            Some(RouteGroup::Auth)
+        } else if path.starts_with("/api/") || path.starts_with("/scim/") {
+            Some(RouteGroup::Api)
+        } else {
+            None
+        }
+    }
+}
+
+/// Requests allowed per period, which is also the burst a full bucket allows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl Quota {
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            period: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Outcome of taking a request from a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    /// Requests left in the bucket
+    pub remaining: u32,
+    /// Until the bucket is full again
+    pub reset: Duration,
+    /// Until the next request is allowed; zero when this one was
+    pub retry_after: Duration,
+}
+
+/// Where buckets are kept
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Take one request from the bucket of `key`
+    async fn take(&self, key: &str, quota: Quota) -> Decision;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Buckets of this instance
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn take_at(&self, key: &str, quota: Quota, now: Instant) -> Decision {
+        let capacity = f64::from(quota.requests);
+        let rate = capacity / quota.period.as_secs_f64();
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * rate).min(capacity)
+        };
+
+        let mut buckets = self.buckets.lock().expect("Rate limit buckets poisoned");
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| refill(bucket) < capacity);
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let mut tokens = refill(bucket);
+        let allowed = tokens >= 1.0;
+        if allowed {
+            tokens -= 1.0;
+        }
+        *bucket = Bucket {
+            tokens,
+            updated: now,
+        };
+
+        let seconds = |missing: f64| Duration::from_secs_f64(missing.max(0.0) / rate);
+        Decision {
+            allowed,
+            limit: quota.requests,
+            remaining: tokens as u32,
+            reset: seconds(capacity - tokens),
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                seconds(1.0 - tokens)
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn take(&self, key: &str, quota: Quota) -> Decision {
+        self.take_at(key, quota, Instant::now())
+    }
+}
+
+/// Prefix of the keys of request logs in Redis
+const REDIS_KEY_PREFIX: &str = "rate_limit:";
+
+/// Sliding window log: the requests allowed in the last period, in a sorted
+/// set scored in microseconds by Redis's clock, so that instances with
+/// skewed clocks agree. Rejected requests are not logged.
+///
+/// KEYS[1] is the log, ARGV[1] the quota and ARGV[2] the period in
+/// microseconds. Returns whether the request was allowed, the requests in
+/// the window, and the times of now and of the oldest and newest of them.
+const SLIDING_WINDOW_SCRIPT: &str = r"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000000 + tonumber(time[2])
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', now - window)
+local count = redis.call('ZCARD', KEYS[1])
+local allowed = 0
+if count < limit then
+  -- Unique: the count grows with each request logged at the same time
+  redis.call('ZADD', KEYS[1], now, time[1] .. '.' .. time[2] .. '-' .. count)
+  count = count + 1
+  allowed = 1
+end
+redis.call('PEXPIRE', KEYS[1], math.ceil(window / 1000))
+local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')[2] or now
+local newest = redis.call('ZRANGE', KEYS[1], -1, -1, 'WITHSCORES')[2] or now
+return {allowed, count, now, tonumber(oldest), tonumber(newest)}
+";
+
+/// Sliding windows in Redis, shared by every instance
+///
+/// While Redis is unavailable, requests are limited by this instance's
+/// buckets instead.
+pub struct RedisStore {
+    redis: RedisCache,
+    script: redis::Script,
+    fallback: MemoryStore,
+}
+
+impl RedisStore {
+    pub fn new(redis: RedisCache) -> Self {
+        Self {
+            redis,
+            script: redis::Script::new(SLIDING_WINDOW_SCRIPT),
+            fallback: MemoryStore::new(),
+        }
+    }
+
+    /// Decision of the shared window, None while Redis is unavailable
+    async fn take_shared(&self, key: &str, quota: Quota) -> Option<Decision> {
+        let mut connection = self.redis.connection().await?;
+        let reply: Vec<i64> = self
+            .script
+            .key(format!("{}{}", REDIS_KEY_PREFIX, key))
+            .arg(quota.requests)
+            .arg(quota.period.as_micros() as u64)
+            .invoke_async(&mut connection)
+            .await
+            .map_err(|e| {
+                tracing::warn!("Redis rate limit of {} failed: {}", key, e);
+                counter!(CACHE_ERRORS_TOTAL, 1, "operation" => "rate_limit");
+            })
+            .ok()?;
+        window_decision(quota, &reply)
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+    async fn take(&self, key: &str, quota: Quota) -> Decision {
+        match self.take_shared(key, quota).await {
+            Some(decision) => decision,
+            None => self.fallback.take(key, quota).await,
+        }
+    }
+}
+
+/// Decision from the reply of [`SLIDING_WINDOW_SCRIPT`]
+///
+/// The window is full again once its newest request leaves it, and the
+/// next request is allowed once its oldest one does.
+fn window_decision(quota: Quota, reply: &[i64]) -> Option<Decision> {
+    let &[allowed, count, now, oldest, newest] = reply else {
+        tracing::warn!("Unexpected reply from the rate limit script: {:?}", reply);
+        return None;
+    };
+    let window = quota.period.as_micros() as i64;
+    let until = |at: i64| Duration::from_micros((at + window - now).max(0) as u64);
+    let allowed = allowed == 1;
+    Some(Decision {
+        allowed,
+        limit: quota.requests,
+        remaining: quota
+            .requests
+            .saturating_sub(u32::try_from(count).unwrap_or(u32::MAX)),
+        reset: until(newest),
+        retry_after: if allowed {
+            Duration::ZERO
+        } else {
+            until(oldest)
+        },
+    })
+}
+
+/// Where RATE_LIMIT_BACKEND keeps limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitBackend {
+    /// Token buckets in each instance's memory
+    Memory,
+    /// Sliding windows in Redis, shared by every instance
+    Redis,
+}
+
+impl RateLimitBackend {
+    /// RATE_LIMIT_BACKEND: `memory` unless set, or `redis`, which needs
+    /// REDIS_URL
+    pub fn from_env() -> Result<Self, String> {
+        let backend = std::env::var("RATE_LIMIT_BACKEND").unwrap_or_default();
+        match backend.trim() {
+            "" | "memory" => Ok(Self::Memory),
+            "redis" if std::env::var("REDIS_URL").is_ok_and(|url| !url.is_empty()) => {
+                Ok(Self::Redis)
+            }
+            "redis" => Err("RATE_LIMIT_BACKEND=redis needs REDIS_URL".to_string()),
+            other => Err(format!(
+                "RATE_LIMIT_BACKEND must be memory or redis: {}",
+                other
+            )),
+        }
+    }
+}
+
+/// Rate limits in front of the handlers
+#[derive(Clone)]
+pub struct RateLimiter {
+    quotas: Arc<HashMap<RouteGroup, Quota>>,
+    store: Arc<dyn RateLimitStore>,
+    token_keys: Option<TokenKeys>,
+}
+
+impl RateLimiter {
+    /// Limiter with the quota of each limited group, in memory
+    pub fn new(quotas: impl IntoIterator<Item = (RouteGroup, Quota)>) -> Self {
+        Self {
+            quotas: Arc::new(quotas.into_iter().collect()),
+            store: Arc::new(MemoryStore::new()),
+            token_keys: None,
+        }
+    }
+
+    /// Quotas from RATE_LIMIT_AUTH_PER_MINUTE and RATE_LIMIT_API_PER_MINUTE
+    ///
+    /// Defaults to 20 and 600 requests a minute; 0 turns a group's limit off.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u32| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let quotas = [
+            (RouteGroup::Auth, var("RATE_LIMIT_AUTH_PER_MINUTE", 20)),
+            (RouteGroup::Api, var("RATE_LIMIT_API_PER_MINUTE", 600)),
+        ];
+        Self::new(
+            quotas
+                .into_iter()
+                .filter(|(_, requests)| *requests > 0)
+                .map(|(group, requests)| (group, Quota::per_minute(requests))),
+        )
+    }
+
+    /// Keep buckets in `store` instead of this instance's memory
+    pub fn with_store(mut self, store: Arc<dyn RateLimitStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    /// Keep limits in `backend`, on `redis` for [`RateLimitBackend::Redis`]
+    pub fn with_backend(self, backend: RateLimitBackend, redis: Option<&RedisCache>) -> Self {
+        match (backend, redis) {
+            (RateLimitBackend::Memory, _) => self,
+            (RateLimitBackend::Redis, Some(redis)) => {
+                self.with_store(Arc::new(RedisStore::new(redis.clone())))
+            }
+            (RateLimitBackend::Redis, None) => {
+                tracing::warn!("Rate limiting in memory: Redis is not configured");
+                self
+            }
+        }
+    }
+
+    /// Key requests with a valid access token by their user
+    pub fn with_token_keys(mut self, token_keys: TokenKeys) -> Self {
+        self.token_keys = Some(token_keys);
+        self
+    }
+
+    /// `user:<id>` for a valid access token, else `ip:<address>`
+    fn client_key(&self, request: &Request) -> String {
+        let user = self.token_keys.as_ref().and_then(|keys| {
+            let token = bearer_token(request.headers())?;
+            keys.verify(token, TokenKind::Access).ok()
+        });
+        match user {
+            Some(claims) => format!("user:{}", claims.sub),
+            None => {
+                let ip = client_ip(request.extensions());
+                format!("ip:{}", ip.as_deref().unwrap_or("unknown"))
+            }
+        }
+    }
+}
+
+/// Reject requests over their group's quota with 429
+///
+/// Limited responses carry `X-RateLimit-Limit`, `X-RateLimit-Remaining`
+/// and `X-RateLimit-Reset`, in seconds until the bucket is full, and
+/// rejections `Retry-After`.
+pub async fn limit(State(limiter): State<RateLimiter>, request: Request, next: Next) -> Response {
+    let Some(group) = RouteGroup::classify(request.uri().path()) else {
+        return next.run(request).await;
+    };
+    let Some(quota) = limiter.quotas.get(&group).copied() else {
+        return next.run(request).await;
+    };
+
+    let key = format!("{:?}:{}", group, limiter.client_key(&request));
+    let decision = limiter.store.take(&key, quota).await;
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        tracing::warn!("Rate limited {}", key);
+        AppError::TooManyRequests {
+            retry_after_secs: ceil_secs(decision.retry_after),
+        }
+        .into_response()
+    };
+    insert_headers(response.headers_mut(), &decision);
+    response
+}
+
+fn insert_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert("x-ratelimit-limit", HeaderValue::from(decision.limit));
+    headers.insert(
+        "x-ratelimit-remaining",
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(
+        "x-ratelimit-reset",
+        HeaderValue::from(ceil_secs(decision.reset)),
+    );
+}
+
+/// Whole seconds, rounded up so clients do not retry too early
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+
+    use crate::middleware::proxy::ClientIp;
+    use tower::util::ServiceExt;
+
+    use crate::models::user_id::UserId;
+
+    #[test]
+    fn test_buckets_refill_over_the_period() {
+        let store = MemoryStore::new();
+        let quota = Quota::per_minute(2);
+        let start = Instant::now();
+
+        let first = store.take_at("a", quota, start);
+        assert!(first.allowed);
+        assert_eq!(first.remaining, 1);
+        assert_eq!(first.reset, Duration::from_secs(30));
+        assert!(store.take_at("a", quota, start).allowed);
+
+        let rejected = store.take_at("a", quota, start);
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.remaining, 0);
+        assert_eq!(rejected.retry_after, Duration::from_secs(30));
+        // Other clients have their own bucket
+        assert!(store.take_at("b", quota, start).allowed);
+
+        let later = start + Duration::from_secs(30);
+        assert!(store.take_at("a", quota, later).allowed);
+        assert!(!store.take_at("a", quota, later).allowed);
+    }
+
+    #[test]
+    fn test_sliding_window_decisions() {
+        let quota = Quota::per_minute(2);
+        let second = 1_000_000;
+
+        // The second request of the window, the first 10 seconds ago
+        let allowed = window_decision(quota, &[1, 2, 70 * second, 60 * second, 70 * second]);
+        assert_eq!(
+            allowed,
+            Some(Decision {
+                allowed: true,
+                limit: 2,
+                remaining: 0,
+                reset: Duration::from_secs(60),
+                retry_after: Duration::ZERO,
+            })
+        );
+
+        let rejected = window_decision(quota, &[0, 2, 80 * second, 60 * second, 70 * second]);
+        let rejected = rejected.unwrap();
+        assert!(!rejected.allowed);
+        assert_eq!(rejected.remaining, 0);
+        assert_eq!(rejected.reset, Duration::from_secs(50));
+        assert_eq!(rejected.retry_after, Duration::from_secs(40));
+
+        assert_eq!(window_decision(quota, &[1, 2]), None);
+    }
+
+    #[tokio::test]
+    async fn test_redis_store_falls_back_to_memory() {
+        // Nothing listens on port 1
+        let redis = RedisCache::new("redis://127.0.0.1:1", Duration::from_millis(100)).unwrap();
+        let store = RedisStore::new(redis);
+        let quota = Quota::per_minute(1);
+        assert!(store.take("a", quota).await.allowed);
+        assert!(!store.take("a", quota).await.allowed);
+    }
+
+    fn app(limiter: RateLimiter) -> Router {
+        Router::new()
+            .route("/api/users", get(|| async { "users" }))
+            .route("/api/auth/login", get(|| async { "login" }))
+            .route("/health", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(limiter, limit))
+    }
+
+    fn request(uri: &str, ip: &str, token: Option<&str>) -> Request {
+        let mut request = Request::builder()
+            .uri(uri)
+            .extension(ClientIp(ip.parse().unwrap()));
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_excess_requests_get_429() {
+        let app = app(RateLimiter::new([
+            (RouteGroup::Api, Quota::per_minute(2)),
+            (RouteGroup::Auth, Quota::per_minute(1)),
+        ]));
+        let send = |uri: &'static str, ip: &'static str| {
+            let app = app.clone();
+            async move { app.oneshot(request(uri, ip, None)).await.unwrap() }
+        };
+
+        let ok = send("/api/users", "203.0.113.1").await;
+        assert_eq!(ok.status(), StatusCode::OK);
+        assert_eq!(ok.headers()["x-ratelimit-limit"], "2");
+        assert_eq!(ok.headers()["x-ratelimit-remaining"], "1");
+        assert_eq!(ok.headers()["x-ratelimit-reset"], "30");
+        send("/api/users", "203.0.113.1").await;
+
+        let limited = send("/api/users", "203.0.113.1").await;
+        assert_eq!(limited.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers()["retry-after"], "30");
+        assert_eq!(limited.headers()["x-ratelimit-remaining"], "0");
+        let body = axum::body::to_bytes(limited.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "RATE_LIMITED");
+
+        // Groups and addresses have their own buckets; exempt routes none
+        assert_eq!(
+            send("/api/auth/login", "203.0.113.1").await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send("/api/users", "203.0.113.2").await.status(),
+            StatusCode::OK
+        );
+        let health = send("/health", "203.0.113.1").await;
+        assert_eq!(health.status(), StatusCode::OK);
+        assert!(!health.headers().contains_key("x-ratelimit-limit"));
+    }
+
+    #[tokio::test]
+    async fn test_users_are_limited_across_addresses() {
+        let keys = TokenKeys::new(b"secret");
+        #[cfg(not(feature = "uuid-ids"))]
+        let user_id = UserId::new(7);
+        #[cfg(feature = "uuid-ids")]
+        let user_id = UserId::new(uuid::Uuid::nil());
+        let token = keys.issue(user_id, TokenKind::Access);
+        let app =
+            app(RateLimiter::new([(RouteGroup::Api, Quota::per_minute(1))]).with_token_keys(keys));
+
+        let first = app
+            .clone()
+            .oneshot(request("/api/users", "203.0.113.1", Some(&token)))
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+        let second = app
+            .clone()
+            .oneshot(request("/api/users", "203.0.113.2", Some(&token)))
+            .await
+            .unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        // Invalid tokens fall back to the address
+        let forged = app
+            .oneshot(request("/api/users", "203.0.113.2", Some("forged")))
+            .await
+            .unwrap();
+        assert_eq!(forged.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+
+/// Runtime switch that rejects mutating requests
+///
+/// Used during failovers, replica promotion or data migrations: reads keep
+/// working while every POST/PUT/PATCH/DELETE gets 503 `READ_ONLY_MODE`.
+/// Starts from READ_ONLY_MODE and is toggled through `/admin/read-only`.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOnlyMode(Arc<AtomicBool>);
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    /// Initial state from READ_ONLY_MODE (`true`/`1` enables it)
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("READ_ONLY_MODE")
+            .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1"))
+            .unwrap_or(false);
+        Self::new(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Switch the mode, returning the previous state
+    pub fn set(&self, enabled: bool) -> bool {
+        self.0.swap(enabled, Ordering::SeqCst)
+    }
+}
+
+/// Reject mutating methods while read-only mode is on
+pub async fn reject_writes(
+    State(mode): State<ReadOnlyMode>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_read = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+
+    if !is_read && mode.is_enabled() {
+        tracing::warn!(
+            "Rejected {} {} in read-only mode",
+            request.method(),
+            request.uri().path()
+        );
+        return AppError::ReadOnly.into_response();
+    }
+
+    next.run(request).await
+}
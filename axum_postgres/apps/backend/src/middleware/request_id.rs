@@ -0,0 +1,169 @@
+//! `X-Request-Id` for every request
+//!
+//! The ID sent by a proxy or client is kept when it looks safe to log,
+//! otherwise a random UUID is assigned. It is stored as a [`RequestId`]
+//! extension, recorded on the tracing span every log line of the request
+//! belongs to, echoed in the response and added to problem details, so a
+//! failure reported by a user can be found in the logs.
+
+use std::fmt;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{json, Value};
+use tracing::Instrument;
+
+use crate::error::PROBLEM_JSON;
+
+/// Header with the ID of the request
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest incoming ID that is kept
+const MAX_LEN: usize = 128;
+
+/// ID of the current request, as sent in `X-Request-Id`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Random UUID
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .expect("Failed to generate a request ID");
+        Self(
+            uuid::Builder::from_random_bytes(bytes)
+                .into_uuid()
+                .to_string(),
+        )
+    }
+
+    /// The incoming ID, unless it is empty, too long or has characters
+    /// other than printable ASCII that could forge log lines
+    pub fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        let valid = !value.is_empty()
+            && value.len() <= MAX_LEN
+            && value.bytes().all(|byte| byte.is_ascii_graphic());
+        valid.then(|| Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Assign the request its ID and report it in the response
+///
+/// Goes outside every other layer so their logs carry the ID, and so
+/// problem details of any of them get a `request_id`.
+pub async fn assign(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::generate);
+    let value = HeaderValue::from_str(id.as_str()).expect("Request IDs are printable ASCII");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, value.clone());
+    request.extensions_mut().insert(id.clone());
+
+    let span = tracing::info_span!("request", request_id = %id);
+    crate::telemetry::set_parent(&span, request.headers());
+    let mut response = next.run(request).instrument(span).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+
+    let is_problem = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes() == PROBLEM_JSON.as_bytes());
+    if !is_problem {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let Ok(mut problem) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    problem["request_id"] = json!(id.as_str());
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(problem.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Extension, routing::get, Router};
+    use tower::util::ServiceExt;
+
+    use crate::error::AppError;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/id",
+                get(|Extension(id): Extension<RequestId>| async move { id.0 }),
+            )
+            .route("/missing", get(|| async { AppError::UserNotFound }))
+            .layer(axum::middleware::from_fn(assign))
+    }
+
+    async fn send(uri: &str, id: Option<&str>) -> (String, Vec<u8>) {
+        let mut request = Request::builder().uri(uri);
+        if let Some(id) = id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let header = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (header, body.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_incoming_ids_are_kept() {
+        let (header, body) = send("/id", Some("abc-123")).await;
+        assert_eq!(header, "abc-123");
+        assert_eq!(body, b"abc-123");
+    }
+
+    #[tokio::test]
+    async fn test_missing_or_unsafe_ids_are_replaced() {
+        for id in [None, Some(""), Some("two words"), Some(&*"x".repeat(129))] {
+            let (header, body) = send("/id", id).await;
+            assert_eq!(header.len(), 36, "{:?}", id);
+            assert!(uuid::Uuid::parse_str(&header).is_ok());
+            assert_eq!(body, header.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_problems_carry_the_id() {
+        let (header, body) = send("/missing", Some("req-42")).await;
+        assert_eq!(header, "req-42");
+        let problem: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["request_id"], "req-42");
+        assert_eq!(problem["code"], "USER_NOT_FOUND");
+    }
+}
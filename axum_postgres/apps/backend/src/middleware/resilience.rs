@@ -0,0 +1,67 @@
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+use crate::repository::resilience::{retry_after_secs, track_unavailable};
+
+/// Answer 503 with Retry-After, instead of the handler's server error, when
+/// a repository method gave up on the database during the request
+///
+/// Handlers turn most repository errors into 500s; this tells clients the
+/// failure is temporary and when to try again.
+pub async fn unavailable(request: Request, next: Next) -> Response {
+    let (response, unavailable) = track_unavailable(next.run(request)).await;
+    if unavailable && response.status().is_server_error() {
+        return AppError::DatabaseUnavailable {
+            retry_after_secs: retry_after_secs(),
+        }
+        .into_response();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use tower::util::ServiceExt;
+
+    use crate::repository::resilience::resilient;
+
+    #[tokio::test]
+    async fn test_server_errors_after_giving_up_become_503() {
+        let app = Router::new()
+            .route(
+                "/down",
+                get(|| async {
+                    resilient("test.down", || async {
+                        Err::<(), _>(sqlx::Error::PoolTimedOut)
+                    })
+                    .await
+                    .map_err(|_| AppError::InternalServerError("Failed".to_string()))
+                }),
+            )
+            .route(
+                "/broken",
+                get(|| async {
+                    resilient("test.broken", || async {
+                        Err::<(), _>(sqlx::Error::RowNotFound)
+                    })
+                    .await
+                    .map_err(|_| AppError::InternalServerError("Failed".to_string()))
+                }),
+            )
+            .layer(axum::middleware::from_fn(unavailable));
+
+        let request = |uri| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let down = app.clone().oneshot(request("/down")).await.unwrap();
+        assert_eq!(down.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(down.headers().contains_key("retry-after"));
+
+        let broken = app.oneshot(request("/broken")).await.unwrap();
+        assert_eq!(broken.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,107 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+
+use crate::auth::api_key::API_KEY_HEADER;
+use crate::auth::session::{
+    cookie, csrf_cookie, session_cookie, AuthMode, SessionUser, CSRF_HEADER, SESSION_COOKIE,
+    SESSION_IDLE_TIMEOUT_SECS,
+};
+use crate::auth::token::fingerprint;
+use crate::error::AppError;
+use crate::repository::sessions::{SessionRepository, SessionRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+
+/// Resolve the session cookie to a [`SessionUser`] in the request extensions
+///
+/// Only runs with AUTH_MODE=cookie, and not for requests with an API key.
+/// A missing, expired or signed-out session passes through unresolved, so
+/// public routes keep working and [`AuthUser`](crate::auth::AuthUser)
+/// rejects the rest. A live session is extended and its cookies re-sent
+/// with the new expiry; mutating requests must carry its CSRF token.
+pub async fn authenticate(
+    State(pool): State<PgPool>,
+    State(mode): State<AuthMode>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if mode != AuthMode::Cookie || request.headers().contains_key(API_KEY_HEADER) {
+        return next.run(request).await;
+    }
+    let Some(session_id) = cookie(request.headers(), SESSION_COOKIE).map(str::to_string) else {
+        return next.run(request).await;
+    };
+    let session_hash = fingerprint(&session_id);
+
+    let expires_at = Utc::now() + Duration::seconds(SESSION_IDLE_TIMEOUT_SECS);
+    let session = match SessionRepository::new(pool.clone())
+        .touch(&session_hash, expires_at)
+        .await
+    {
+        Ok(Some(session)) => session,
+        Ok(None) => {
+            tracing::debug!("Ignored unknown or expired session cookie");
+            return next.run(request).await;
+        }
+        Err(e) => return lookup_failed(e),
+    };
+    let user = match UserRepository::new(pool)
+        .get_user_by_key(session.user_key)
+        .await
+    {
+        Ok(Some(user)) if user.active => user,
+        Ok(_) => {
+            tracing::debug!("Ignored session of a deleted or inactive user");
+            return next.run(request).await;
+        }
+        Err(e) => return lookup_failed(e),
+    };
+
+    let is_read = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let csrf_ok = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|token| super::constant_time_eq(token, &session.csrf_token));
+    if !is_read && !csrf_ok {
+        tracing::warn!(
+            "Rejected {} {} without a valid CSRF token",
+            request.method(),
+            request.uri().path()
+        );
+        return AppError::Forbidden("Missing or invalid CSRF token".to_string()).into_response();
+    }
+
+    request.extensions_mut().insert(SessionUser {
+        id: user.user_id(),
+        session_hash,
+    });
+    let mut response = next.run(request).await;
+
+    // Sign-out and sign-in set their own cookies
+    let prefix = format!("{}=", SESSION_COOKIE);
+    let sets_session = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .any(|value| value.as_bytes().starts_with(prefix.as_bytes()));
+    if !sets_session {
+        let headers = response.headers_mut();
+        headers.append(header::SET_COOKIE, session_cookie(&session_id));
+        headers.append(header::SET_COOKIE, csrf_cookie(&session.csrf_token));
+    }
+    response
+}
+
+fn lookup_failed(e: sqlx::Error) -> Response {
+    tracing::error!("Failed to look up session: {}", e);
+    AppError::InternalServerError("Failed to authenticate session".to_string()).into_response()
+}
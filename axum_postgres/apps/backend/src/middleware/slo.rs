@@ -0,0 +1,32 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::slo::SloTracker;
+
+/// Record latency and status of every request whose route has an objective
+pub async fn record(
+    State(tracker): State<Arc<SloTracker>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| format!("{} {}", request.method(), path.as_str()))
+        .filter(|route| tracker.is_tracked(route));
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    if let Some(route) = route {
+        tracker.record(&route, started.elapsed(), response.status().as_u16());
+    }
+
+    response
+}
@@ -0,0 +1,14 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::tenant::{self, TenantContext};
+
+/// Run the request with its tenant as the current tenant
+///
+/// Resolving fails the request before any handler runs: unknown tenants get
+/// 404 and suspended ones 403. Handlers can still extract the
+/// [`TenantContext`], which is kept in the request extensions.
+pub async fn scope(tenant: TenantContext, mut request: Request, next: Next) -> Response {
+    let tenant_id = tenant.id;
+    request.extensions_mut().insert(tenant);
+    tenant::scope(tenant_id, next.run(request)).await
+}
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::auth::role::Role;
+use crate::models::user::{UserListQuery, UserResponse};
+
+/// User as shown under `/api/admin/users`, with their roles
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"id": "1", "name": "John Doe", "email": "john@example.com", "email_verified": true, "active": false, "display_name": null, "bio": null, "phone": null, "timezone": null, "locale": null, "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-02T00:00:00Z", "deleted_at": "2024-01-02T00:00:00Z", "roles": ["admin"]}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct AdminUserResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    /// Roles granted to the user, by name
+    pub roles: Vec<Role>,
+}
+
+/// One page of `GET /api/admin/users`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct AdminUserPage {
+    pub data: Vec<AdminUserResponse>,
+    /// Pass as `cursor` to fetch the next page; null on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Filters of `GET /api/admin/users`; soft-deleted users are always listed
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminUserQuery {
+    /// Only active or only inactive users
+    pub active: Option<bool>,
+    /// Case-insensitive substring of the email
+    pub email_contains: Option<String>,
+    /// Case-insensitive substring of the name
+    pub name_contains: Option<String>,
+}
+
+impl From<AdminUserQuery> for UserListQuery {
+    fn from(query: AdminUserQuery) -> Self {
+        Self {
+            active: query.active,
+            email_contains: query.email_contains,
+            name_contains: query.name_contains,
+            include_deleted: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Body of `PUT /api/admin/users/{id}/roles`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"roles": ["admin"]}))]
+pub struct UserRoles {
+    /// Every role the user should hold; others are revoked
+    pub roles: Vec<Role>,
+}
+
+/// Access token for acting as another user
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct ImpersonationResponse {
+    /// User the token acts as
+    pub user: UserResponse,
+    /// Send as `Authorization: Bearer <access_token>`; it cannot be refreshed
+    pub access_token: String,
+    #[schema(example = "Bearer")]
+    pub token_type: String,
+    /// Seconds until the access token expires
+    #[schema(example = 900)]
+    pub expires_in: i64,
+}
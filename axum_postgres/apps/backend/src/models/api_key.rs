@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+
+use crate::auth::api_key::{Scope, MAX_ROTATION_GRACE_SECS};
+use crate::models::user_id::UserId;
+
+/// Stored API key, without its hash
+#[derive(Debug, Clone, FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// Start of the key, to tell keys apart
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    /// User who created the key
+    pub created_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    /// Written in batches, so up to a minute behind
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// When the key stops working, if it was rotated or expired by an admin
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Stored API key as admins see it, revoked and expired ones included
+#[derive(Debug, Clone)]
+pub struct AdminApiKey {
+    pub api_key: ApiKey,
+    /// Id of the user who created the key, unless they are gone
+    pub creator: Option<UserId>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Key that took over when this one was rotated
+    pub replaced_by: Option<i64>,
+}
+
+/// API key as listed; the key itself is only shown when created
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"id": "3", "name": "HR sync", "prefix": "ak_Zm9vYmFy", "scopes": ["users:import"], "created_at": "2024-01-01T00:00:00+00:00", "last_used_at": null, "expires_at": null}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct ApiKeyResponse {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    /// Set once the key was rotated or expired; it stops working then
+    pub expires_at: Option<String>,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(key: ApiKey) -> Self {
+        Self {
+            id: key.id.to_string(),
+            name: key.name,
+            prefix: key.prefix,
+            scopes: key.scopes,
+            created_at: key.created_at.to_rfc3339(),
+            last_used_at: key.last_used_at.map(|at| at.to_rfc3339()),
+            expires_at: key.expires_at.map(|at| at.to_rfc3339()),
+        }
+    }
+}
+
+/// API key as listed under `/api/admin/api-keys`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"id": "3", "name": "HR sync", "prefix": "ak_Zm9vYmFy", "scopes": ["users:import"], "created_at": "2024-01-01T00:00:00+00:00", "last_used_at": "2024-01-03T00:00:00+00:00", "expires_at": "2024-01-04T00:00:00+00:00", "created_by": "1", "revoked_at": null, "replaced_by": "4"}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct AdminApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+    /// User who created the key; null once they are deleted for good
+    pub created_by: Option<String>,
+    pub revoked_at: Option<String>,
+    /// Key that took over when this one was rotated
+    pub replaced_by: Option<String>,
+}
+
+impl From<AdminApiKey> for AdminApiKeyResponse {
+    fn from(key: AdminApiKey) -> Self {
+        Self {
+            api_key: key.api_key.into(),
+            created_by: key.creator.map(|id| id.to_string()),
+            revoked_at: key.revoked_at.map(|at| at.to_rfc3339()),
+            replaced_by: key.replaced_by.map(|id| id.to_string()),
+        }
+    }
+}
+
+/// Newly created API key, with the key itself
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+    /// Send as `X-API-Key`; it is not shown again
+    pub key: String,
+}
+
+/// Body of `POST /api/api-keys`
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"name": "HR sync", "scopes": ["users:import"]}))]
+pub struct CreateApiKeyRequest {
+    /// What the key is for
+    #[validate(length(min = 1, max = 100, message = "Name must be 1 to 100 characters"))]
+    #[schema(min_length = 1, max_length = 100)]
+    pub name: String,
+
+    /// Any of `users:read`, `users:write`, `users:import` and `roles:write`,
+    /// or `users:*` or `roles:*` for every scope of the resource
+    #[validate(custom = "validate_scopes")]
+    #[schema(min_items = 1, example = json!(["users:write"]))]
+    pub scopes: Vec<String>,
+}
+
+fn validate_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    let message = if scopes.is_empty() {
+        "At least one scope is required".to_string()
+    } else if let Some(Err(message)) = scopes
+        .iter()
+        .map(|s| Scope::granted_by(s))
+        .find(Result::is_err)
+    {
+        message
+    } else {
+        return Ok(());
+    };
+    let mut error = ValidationError::new("scopes");
+    error.message = Some(message.into());
+    Err(error)
+}
+
+/// Body of `POST /api/admin/api-keys/{id}/rotate`
+#[derive(Debug, Clone, Default, Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"grace_period_secs": 86400}))]
+pub struct RotateApiKeyRequest {
+    /// How long the old key keeps working; a day if left out
+    #[validate(range(
+        max = "MAX_ROTATION_GRACE_SECS",
+        message = "Grace period must be at most 30 days"
+    ))]
+    #[schema(maximum = 2592000)]
+    pub grace_period_secs: Option<u32>,
+}
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::AuditAction;
+use crate::pagination::{DEFAULT_PAGE_SIZE, MAX_RANGE_ITEMS};
+
+/// Row of the audit_log table
+#[derive(Debug, Clone, FromRow)]
+pub struct AuditRecord {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor: Option<String>,
+    pub impersonated_by: Option<String>,
+    pub entity: String,
+    pub entity_id: String,
+    pub action: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub changes: Value,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// One change as listed by `GET /api/admin/audit-log`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "id": 42,
+    "occurred_at": "2024-01-02T00:00:00Z",
+    "actor": "user:1",
+    "impersonated_by": null,
+    "entity": "user",
+    "entity_id": "7",
+    "action": "update",
+    "before": {"id": "7", "name": "Jane Doe", "email": "jane@example.com"},
+    "after": {"id": "7", "name": "Jane Smith", "email": "jane@example.com"},
+    "changes": {"name": {"from": "Jane Doe", "to": "Jane Smith"}},
+    "request_id": "5f0c6d2e",
+    "ip": "203.0.113.7"
+}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct AuditEntryResponse {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    /// `user:<id>`, `api_key:<id>`, `scim` or `admin_token`; null when
+    /// unauthenticated
+    pub actor: Option<String>,
+    /// `user:<id>` of the admin who made the change with an impersonation
+    /// token for `actor`; null otherwise
+    pub impersonated_by: Option<String>,
+    /// `user`, `password`, `api_key`, `role_grant` or `read_only_mode`
+    pub entity: String,
+    pub entity_id: String,
+    /// `create`, `update`, `delete` or `restore`
+    pub action: String,
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<Value>,
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<Value>,
+    /// Fields that differ between `before` and `after`
+    #[schema(value_type = Object)]
+    pub changes: Value,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl From<AuditRecord> for AuditEntryResponse {
+    fn from(record: AuditRecord) -> Self {
+        Self {
+            id: record.id,
+            occurred_at: record.occurred_at,
+            actor: record.actor,
+            impersonated_by: record.impersonated_by,
+            entity: record.entity,
+            entity_id: record.entity_id,
+            action: record.action,
+            before: record.before,
+            after: record.after,
+            changes: record.changes,
+            request_id: record.request_id,
+            ip: record.ip,
+        }
+    }
+}
+
+/// Filters and page of `GET /api/admin/audit-log`
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    /// Only changes by this actor, e.g. `user:7`
+    pub actor: Option<String>,
+    /// Only changes to this kind of entity, e.g. `user`
+    pub entity: Option<String>,
+    /// Only changes to the entity with this ID
+    pub entity_id: Option<String>,
+    #[param(inline)]
+    pub action: Option<AuditAction>,
+    /// Only changes at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only changes before this time
+    pub until: Option<DateTime<Utc>>,
+    /// `next_cursor` of the previous page; omit for the first page
+    pub cursor: Option<String>,
+    /// Page size, 100 by default and at most 1000
+    pub limit: Option<i64>,
+}
+
+impl AuditLogQuery {
+    pub fn page_size(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_RANGE_ITEMS)
+    }
+}
+
+/// One page of the audit log, newest first
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct AuditLogPage {
+    pub data: Vec<AuditEntryResponse>,
+    /// Pass as `cursor` to fetch the next page; null on the last page
+    pub next_cursor: Option<String>,
+}
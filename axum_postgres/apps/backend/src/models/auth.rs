@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
+
+use crate::auth::password;
+use crate::models::user::{normalized_email, UserResponse};
+
+// Request bodies hold secrets and deliberately do not derive Debug, so they
+// cannot end up in `#[instrument]` spans or logs.
+
+/// Body of `POST /api/auth/register`
+#[derive(Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"name": "Jane Doe", "email": "jane@example.com", "password": "correct horse battery staple"}))]
+pub struct RegisterRequest {
+    #[validate(length(min = 1, message = "Name cannot be empty"))]
+    #[schema(min_length = 1, example = "Jane Doe")]
+    pub name: String,
+
+    #[validate(email(message = "Invalid email format"))]
+    #[schema(format = "email", example = "jane@example.com")]
+    #[serde(deserialize_with = "normalized_email")]
+    pub email: String,
+
+    /// 8 to 128 characters, and not a commonly used password
+    #[validate(custom = "validate_password")]
+    #[schema(format = Password, min_length = 8, max_length = 128)]
+    pub password: String,
+}
+
+/// Body of `POST /api/users/{id}/change-password`
+#[derive(Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"current_password": "correct horse battery staple", "new_password": "tr0ub4dor and more"}))]
+pub struct ChangePasswordRequest {
+    #[schema(format = Password)]
+    pub current_password: String,
+
+    /// Same policy as at registration
+    #[validate(custom = "validate_password")]
+    #[schema(format = Password, min_length = 8, max_length = 128)]
+    pub new_password: String,
+}
+
+/// Body of `POST /api/auth/login`
+#[derive(Deserialize, ToSchema)]
+#[schema(example = json!({"email": "jane@example.com", "password": "correct horse battery staple"}))]
+pub struct LoginRequest {
+    #[schema(format = "email", example = "jane@example.com")]
+    #[serde(deserialize_with = "normalized_email")]
+    pub email: String,
+
+    #[schema(format = Password)]
+    pub password: String,
+}
+
+/// Body of `POST /api/auth/refresh`
+#[derive(Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    /// Refresh token from register, login or an earlier refresh
+    pub refresh_token: String,
+}
+
+/// Access and refresh tokens
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct TokenResponse {
+    /// Send as `Authorization: Bearer <access_token>`
+    pub access_token: String,
+    /// Trade for a new pair at `POST /api/auth/refresh`
+    pub refresh_token: String,
+    #[schema(example = "Bearer")]
+    pub token_type: String,
+    /// Seconds until the access token expires
+    #[schema(example = 900)]
+    pub expires_in: i64,
+}
+
+/// Signed-in user with their tokens, returned by register and login
+///
+/// With AUTH_MODE=cookie there are no tokens: the session is in a cookie,
+/// and `csrf_token` must be sent as `X-CSRF-Token` on mutating requests.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct AuthResponse {
+    pub user: UserResponse,
+    #[serde(flatten)]
+    pub tokens: Option<TokenResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+}
+
+/// Body of `POST /api/auth/verify-email`
+#[derive(Deserialize, ToSchema)]
+pub struct VerifyEmailRequest {
+    /// Token from the link in the verification email
+    pub token: String,
+}
+
+/// Body of `POST /api/auth/resend-verification`
+#[derive(Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"email": "jane@example.com"}))]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "Invalid email format"))]
+    #[schema(format = "email", example = "jane@example.com")]
+    #[serde(deserialize_with = "normalized_email")]
+    pub email: String,
+}
+
+/// Body of `POST /api/auth/forgot-password`
+#[derive(Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"email": "jane@example.com"}))]
+pub struct ForgotPasswordRequest {
+    #[validate(email(message = "Invalid email format"))]
+    #[schema(format = "email", example = "jane@example.com")]
+    #[serde(deserialize_with = "normalized_email")]
+    pub email: String,
+}
+
+/// Body of `POST /api/auth/reset-password`
+#[derive(Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"token": "q2C0...", "new_password": "tr0ub4dor and more"}))]
+pub struct ResetPasswordRequest {
+    /// Token from the link in the reset email
+    pub token: String,
+
+    /// Same policy as at registration
+    #[validate(custom = "validate_password")]
+    #[schema(format = Password, min_length = 8, max_length = 128)]
+    pub new_password: String,
+}
+
+/// Query the provider redirects back with to
+/// `GET /api/auth/oauth/{provider}/callback`
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OAuthCallbackQuery {
+    /// Authorization code to exchange
+    pub code: Option<String>,
+    /// The `state` sent to the provider by `/start`
+    pub state: Option<String>,
+    /// Set instead of `code` when the user declined or the provider failed
+    pub error: Option<String>,
+}
+
+fn validate_password(value: &str) -> Result<(), ValidationError> {
+    match password::policy_violation(value) {
+        None => Ok(()),
+        Some(message) => {
+            let mut error = ValidationError::new("password");
+            error.message = Some(message.into());
+            Err(error)
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Success body with RESPONSE_ENVELOPE enabled: `{success, data, meta}`
+///
+/// Built by `middleware::envelope::wrap` from what the handlers return, so
+/// every `/api` endpoint gets the same shape without handlers knowing about
+/// it. The OpenAPI spec documents one `<Schema>Envelope` per wrapped body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiResponse<T> {
+    /// Always `true`; errors carry `false`
+    pub success: bool,
+    pub data: T,
+    pub meta: ResponseMeta,
+}
+
+impl<T> ApiResponse<T> {
+    pub fn new(data: T, meta: ResponseMeta) -> Self {
+        Self {
+            success: true,
+            data,
+            meta,
+        }
+    }
+}
+
+impl ApiResponse<Value> {
+    /// Envelope for a handler's success body
+    ///
+    /// Lists report their `count`, and the `_links` and paging members of a
+    /// collection move to `meta` so `data` is always the bare list.
+    pub fn from_body(body: Value) -> Self {
+        match body {
+            Value::Array(items) => {
+                let meta = ResponseMeta {
+                    count: Some(items.len()),
+                    ..ResponseMeta::default()
+                };
+                Self::new(Value::Array(items), meta)
+            }
+            Value::Object(mut object) if object.get("data").is_some_and(Value::is_array) => {
+                let items = object.remove("data").unwrap_or_default();
+                let meta = ResponseMeta {
+                    count: items.as_array().map(Vec::len),
+                    links: object.remove("_links"),
+                    // Paging members such as `next_cursor` join the count
+                    paging: object.into_iter().collect(),
+                };
+                Self::new(items, meta)
+            }
+            data => Self::new(data, ResponseMeta::default()),
+        }
+    }
+}
+
+/// `meta` of an enveloped response; empty for single resources
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct ResponseMeta {
+    /// Items in `data`, for lists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 2)]
+    pub count: Option<usize>,
+    /// Collection links, present when RESPONSE_LINKS is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub links: Option<Value>,
+    /// Paging members of a collection, such as `next_cursor`
+    #[serde(flatten)]
+    pub paging: BTreeMap<String, Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bodies_become_envelopes() {
+        let user = json!({"id": "1", "name": "Jane"});
+        assert_eq!(
+            serde_json::to_value(ApiResponse::from_body(user.clone())).unwrap(),
+            json!({"success": true, "data": user, "meta": {}})
+        );
+
+        let list = ApiResponse::from_body(json!([{"id": "1"}, {"id": "2"}]));
+        assert_eq!(list.meta.count, Some(2));
+        assert_eq!(list.data[1]["id"], "2");
+
+        let page = ApiResponse::from_body(json!({
+            "data": [{"id": "1"}],
+            "next_cursor": null,
+            "_links": {"self": {"href": "/api/users", "method": "GET"}}
+        }));
+        assert_eq!(page.data, json!([{"id": "1"}]));
+        assert_eq!(
+            serde_json::to_value(&page.meta).unwrap(),
+            json!({
+                "count": 1,
+                "links": {"self": {"href": "/api/users", "method": "GET"}},
+                "next_cursor": null
+            })
+        );
+    }
+}
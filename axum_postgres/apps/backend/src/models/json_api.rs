@@ -0,0 +1,251 @@
+//! JSON:API representation of the `/api` resources
+//!
+//! Documents are built from the plain JSON the handlers already produce,
+//! so handlers and models stay unaware of the format. See
+//! `middleware::json_api` for the content negotiation.
+
+use axum::http::StatusCode;
+use serde_json::{json, Map, Value};
+
+use crate::handlers::users::USER_HISTORY_PATH;
+
+pub const MEDIA_TYPE: &str = "application/vnd.api+json";
+
+/// Resource types served under `/api`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    User,
+    UserVersion,
+}
+
+impl ResourceKind {
+    /// Resource returned by a request path, if it has a JSON:API form
+    pub fn from_path(path: &str) -> Option<Self> {
+        let segments: Vec<&str> = path.trim_end_matches('/').split('/').skip(1).collect();
+        match segments.as_slice() {
+            // Plain answers about the collection, not resources
+            ["api", "users", "count" | "check-email" | "import"] => None,
+            ["api", "users"] | ["api", "users", _] => Some(Self::User),
+            ["api", "users", _, "history"] => Some(Self::UserVersion),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(self) -> &'static str {
+        match self {
+            Self::User => "users",
+            Self::UserVersion => "user-versions",
+        }
+    }
+
+    /// Field of the plain representation that identifies the resource
+    fn id_field(self) -> &'static str {
+        match self {
+            Self::User => "id",
+            Self::UserVersion => "version",
+        }
+    }
+
+    fn relationships(self, id: &str) -> Map<String, Value> {
+        let mut relationships = Map::new();
+        if self == Self::User {
+            relationships.insert(
+                "history".to_string(),
+                json!({"links": {"related": USER_HISTORY_PATH.replace(":id", id)}}),
+            );
+        }
+        relationships
+    }
+}
+
+/// Wrap a successful response body in a top-level document
+///
+/// Accepts a single object, a bare array, or a `{data, _links}` collection.
+pub fn to_document(kind: ResourceKind, body: Value) -> Value {
+    match body {
+        Value::Array(items) => json!({"data": resources(kind, items)}),
+        Value::Object(mut object) if object.get("data").is_some_and(Value::is_array) => {
+            let items = match object.remove("data") {
+                Some(Value::Array(items)) => items,
+                _ => Vec::new(),
+            };
+            let mut document = json!({"data": resources(kind, items)});
+            if let Some(links) = object.remove("_links").as_ref().and_then(hrefs) {
+                document["links"] = links;
+            }
+            // Remaining members such as `next_cursor` describe the page
+            if !object.is_empty() {
+                document["meta"] = Value::Object(object);
+            }
+            document
+        }
+        other => json!({"data": resource(kind, other)}),
+    }
+}
+
+fn resources(kind: ResourceKind, items: Vec<Value>) -> Vec<Value> {
+    items.into_iter().map(|item| resource(kind, item)).collect()
+}
+
+/// Convert one plain object into a resource object
+fn resource(kind: ResourceKind, value: Value) -> Value {
+    let Value::Object(mut attributes) = value else {
+        return value;
+    };
+
+    let id = match attributes.remove(kind.id_field()) {
+        Some(Value::String(id)) => id,
+        Some(other) => other.to_string(),
+        None => String::new(),
+    };
+    let links = attributes.remove("_links");
+
+    let mut resource = json!({
+        "type": kind.type_name(),
+        "id": id,
+        "attributes": attributes,
+    });
+    let relationships = kind.relationships(&id);
+    if !relationships.is_empty() {
+        resource["relationships"] = Value::Object(relationships);
+    }
+    if let Some(links) = links.as_ref().and_then(hrefs) {
+        resource["links"] = links;
+    }
+    resource
+}
+
+/// Reduce `_links` objects (`{rel: {href, method}}`) to JSON:API `{rel: href}`
+fn hrefs(links: &Value) -> Option<Value> {
+    let links: Map<String, Value> = links
+        .as_object()?
+        .iter()
+        .filter_map(|(rel, link)| Some((rel.clone(), link.get("href")?.clone())))
+        .collect();
+    (!links.is_empty()).then_some(Value::Object(links))
+}
+
+/// Error document for a failed response
+///
+/// Understands the problem details of `AppError` and falls back to the raw
+/// text that axum extractors produce for rejected requests. Each invalid
+/// field becomes an error of its own, pointing at the attribute.
+pub fn error_document(status: StatusCode, body: &[u8]) -> Value {
+    let problem = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(object)) => object,
+        _ => Map::from_iter([(
+            "detail".to_string(),
+            json!(String::from_utf8_lossy(body).trim()),
+        )]),
+    };
+    let error = |detail: &Value| {
+        let mut error = json!({
+            "status": status.as_u16().to_string(),
+            "title": status.canonical_reason().unwrap_or_default(),
+            "detail": detail.as_str().unwrap_or_default(),
+        });
+        if let Some(code) = problem.get("code") {
+            error["code"] = code.clone();
+        }
+        error
+    };
+
+    let fields = problem.get("errors").and_then(Value::as_array);
+    let errors = match fields {
+        Some(fields) if !fields.is_empty() => fields
+            .iter()
+            .map(|field| {
+                let mut error = error(&field["message"]);
+                if let Some(name) = field["field"].as_str() {
+                    error["source"] = json!({"pointer": format!("/data/attributes/{}", name)});
+                }
+                error
+            })
+            .collect(),
+        _ => vec![error(problem.get("detail").unwrap_or(&Value::Null))],
+    };
+    json!({"errors": errors})
+}
+
+/// Plain request body from a `{data: {type, attributes}}` document
+pub fn attributes_from_document(document: Value) -> Result<Value, String> {
+    match document.get("data").and_then(|data| data.get("attributes")) {
+        Some(attributes @ Value::Object(_)) => Ok(attributes.clone()),
+        _ => Err("Request document must contain data.attributes".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resource_kind_from_path() {
+        assert_eq!(ResourceKind::from_path("/api/users"), Some(ResourceKind::User));
+        assert_eq!(ResourceKind::from_path("/api/users/7"), Some(ResourceKind::User));
+        assert_eq!(
+            ResourceKind::from_path("/api/users/7/history"),
+            Some(ResourceKind::UserVersion)
+        );
+        assert_eq!(ResourceKind::from_path("/api/users/import"), None);
+        assert_eq!(ResourceKind::from_path("/scim/v2/Users"), None);
+    }
+
+    #[test]
+    fn test_user_becomes_resource_object() {
+        let user = json!({
+            "id": "7",
+            "name": "Jane",
+            "email": "jane@example.com",
+            "active": true,
+            "created_at": "2024-01-01T00:00:00+00:00",
+            "_links": {"self": {"href": "/api/users/7", "method": "GET"}}
+        });
+
+        let document = to_document(ResourceKind::User, user);
+        let data = &document["data"];
+        assert_eq!(data["type"], "users");
+        assert_eq!(data["id"], "7");
+        assert_eq!(data["attributes"]["name"], "Jane");
+        assert!(data["attributes"].get("id").is_none());
+        assert_eq!(data["links"]["self"], "/api/users/7");
+        assert_eq!(
+            data["relationships"]["history"]["links"]["related"],
+            "/api/users/7/history"
+        );
+
+        let collection = to_document(
+            ResourceKind::UserVersion,
+            json!([{"version": 1, "operation": "INSERT"}]),
+        );
+        assert_eq!(collection["data"][0]["id"], "1");
+        assert!(collection["data"][0].get("relationships").is_none());
+    }
+
+    #[test]
+    fn test_errors_and_request_documents() {
+        let body = br#"{"type":"/problems/user-not-found","title":"Not Found","status":404,"detail":"User not found","code":"USER_NOT_FOUND"}"#;
+        let document = error_document(StatusCode::NOT_FOUND, body);
+        assert_eq!(document["errors"][0]["status"], "404");
+        assert_eq!(document["errors"][0]["detail"], "User not found");
+        assert_eq!(document["errors"][0]["code"], "USER_NOT_FOUND");
+
+        let body = br#"{"status":400,"detail":"Validation errors: email: Invalid email format","code":"VALIDATION_FAILED","errors":[{"field":"email","message":"Invalid email format"}]}"#;
+        let document = error_document(StatusCode::BAD_REQUEST, body);
+        assert_eq!(document["errors"][0]["detail"], "Invalid email format");
+        assert_eq!(
+            document["errors"][0]["source"]["pointer"],
+            "/data/attributes/email"
+        );
+
+        let document = error_document(StatusCode::UNPROCESSABLE_ENTITY, b"missing field `name`");
+        assert_eq!(document["errors"][0]["detail"], "missing field `name`");
+
+        let attributes = attributes_from_document(json!({
+            "data": {"type": "users", "attributes": {"name": "Jane"}}
+        }))
+        .unwrap();
+        assert_eq!(attributes, json!({"name": "Jane"}));
+        assert!(attributes_from_document(json!({"name": "Jane"})).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Whether responses carry a `_links` block
+///
+/// Opt-in through RESPONSE_LINKS so existing clients keep the bare shapes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResponseLinks(bool);
+
+impl ResponseLinks {
+    pub fn new(enabled: bool) -> Self {
+        Self(enabled)
+    }
+
+    /// RESPONSE_LINKS (`true`/`1` enables links)
+    pub fn from_env() -> Self {
+        let enabled = std::env::var("RESPONSE_LINKS")
+            .map(|value| matches!(value.to_ascii_lowercase().as_str(), "true" | "1"))
+            .unwrap_or(false);
+        Self::new(enabled)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0
+    }
+}
+
+/// A related action: where to send which request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"href": "/api/users/1", "method": "GET"}))]
+pub struct Link {
+    pub href: String,
+    pub method: String,
+}
+
+impl Link {
+    /// Link to a router path template such as `/api/users/:id`
+    ///
+    /// Handlers pass the same constants the router is built from, so links
+    /// follow the mounted routes.
+    pub fn to(method: &str, template: &str, id: Option<&str>) -> Self {
+        let href = match id {
+            Some(id) => template.replace(":id", id),
+            None => template.to_string(),
+        };
+        Self {
+            href,
+            method: method.to_string(),
+        }
+    }
+}
+
+/// Links attached to a single user
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct UserLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    pub update: Link,
+    pub delete: Link,
+    pub history: Link,
+}
+
+/// Links attached to a list of users
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CollectionLinks {
+    #[serde(rename = "self")]
+    pub self_link: Link,
+    pub create: Link,
+    /// Next page, present when more results follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<Link>,
+    /// Previous page, present after the first page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<Link>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_expands_path_template() {
+        let link = Link::to("PUT", "/api/users/:id", Some("42"));
+        assert_eq!(link.href, "/api/users/42");
+        assert_eq!(link.method, "PUT");
+
+        let json = serde_json::to_value(CollectionLinks {
+            self_link: Link::to("GET", "/api/users", None),
+            create: Link::to("POST", "/api/users", None),
+            next: None,
+            prev: None,
+        })
+        .unwrap();
+        assert_eq!(json["self"]["href"], "/api/users");
+        assert!(json.get("next").is_none());
+    }
+}
@@ -1 +1,14 @@
-pub mod user;
\ No newline at end of file
+pub mod admin;
+pub mod api_key;
+pub mod audit;
+pub mod auth;
+pub mod envelope;
+pub mod json_api;
+pub mod links;
+pub mod merge_patch;
+pub mod role;
+pub mod scim;
+pub mod tenant;
+pub mod user;
+pub mod user_id;
+pub mod user_history;
\ No newline at end of file
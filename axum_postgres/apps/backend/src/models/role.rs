@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+/// Role as stored, with how many users hold it
+#[derive(Debug, Clone, FromRow)]
+pub struct RoleRecord {
+    pub name: String,
+    pub description: String,
+    pub members: i64,
+}
+
+/// Role as listed by `GET /api/admin/roles`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"name": "admin", "description": "Delete and import users, and grant roles", "members": 2}))]
+pub struct RoleResponse {
+    pub name: String,
+    pub description: String,
+    /// Live users holding the role
+    pub members: i64,
+}
+
+impl From<RoleRecord> for RoleResponse {
+    fn from(role: RoleRecord) -> Self {
+        Self {
+            name: role.name,
+            description: role.description,
+            members: role.members,
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! SCIM 2.0 (RFC 7643/7644) resources mapped onto the user model
+//!
+//! `userName` and the primary email are both the user's email address, and
+//! `displayName` (or `name.formatted`) is the user's name. Attributes this
+//! backend does not store, such as `externalId`, are accepted and ignored so
+//! identity providers can provision without custom attribute mappings.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use super::user::{normalize_email, UpdateUserRequest, User};
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const PATCH_OP_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+/// SCIM User resource
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[schema(example = json!({
+    "schemas": [USER_SCHEMA],
+    "id": "42",
+    "userName": "jane@example.com",
+    "name": {"formatted": "Jane Doe"},
+    "displayName": "Jane Doe",
+    "emails": [{"value": "jane@example.com", "type": "work", "primary": true}],
+    "active": true,
+    "meta": {"resourceType": "User", "created": "2024-01-01T00:00:00+00:00", "lastModified": "2024-01-01T00:00:00+00:00", "location": "/scim/v2/Users/42"}
+}))]
+pub struct ScimUser {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub user_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<ScimName>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emails: Vec<ScimEmail>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ScimMeta>,
+}
+
+/// SCIM complex `name` attribute
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formatted: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+}
+
+/// SCIM multi-valued `emails` entry
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ScimEmail {
+    pub value: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+/// SCIM resource metadata
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: String,
+    pub created: String,
+    #[serde(default)]
+    pub last_modified: String,
+    pub location: String,
+}
+
+/// SCIM list response for `GET /scim/v2/Users`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListResponse {
+    pub schemas: Vec<String>,
+    pub total_results: usize,
+    pub start_index: usize,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<ScimUser>,
+}
+
+/// SCIM PATCH request body
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "schemas": [PATCH_OP_SCHEMA],
+    "Operations": [{"op": "replace", "value": {"active": false}}]
+}))]
+pub struct ScimPatchRequest {
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+/// Single PATCH operation
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScimPatchOperation {
+    /// add, replace or remove (case-insensitive)
+    pub op: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Object)]
+    pub value: Option<Value>,
+}
+
+/// SCIM error body
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[schema(example = json!({"schemas": [ERROR_SCHEMA], "status": "404", "detail": "User not found"}))]
+pub struct ScimErrorResponse {
+    pub schemas: Vec<String>,
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scim_type: Option<String>,
+    pub detail: String,
+}
+
+/// Supported subset of the SCIM filter grammar: a single `eq` comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScimFilter {
+    /// `userName eq "..."` or `emails.value eq "..."`, compared case-insensitively
+    UserName(String),
+    /// `id eq "..."`
+    Id(String),
+    /// `active eq true|false`
+    Active(bool),
+}
+
+/// Reason a filter or PATCH body was rejected, with its SCIM `scimType`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScimRequestError {
+    pub scim_type: &'static str,
+    pub detail: String,
+}
+
+impl ScimRequestError {
+    fn new(scim_type: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            scim_type,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl ScimFilter {
+    /// Parse a filter such as `userName eq "jane@example.com"`
+    pub fn parse(filter: &str) -> Result<Self, ScimRequestError> {
+        let invalid =
+            || ScimRequestError::new("invalidFilter", format!("Unsupported filter: {}", filter));
+
+        let mut parts = filter.trim().splitn(3, char::is_whitespace);
+        let attribute = parts.next().ok_or_else(invalid)?;
+        let operator = parts.next().ok_or_else(invalid)?;
+        let value = parts.next().ok_or_else(invalid)?.trim();
+
+        if !operator.eq_ignore_ascii_case("eq") {
+            return Err(invalid());
+        }
+
+        let value: Value = serde_json::from_str(value).map_err(|_| invalid())?;
+        match (attribute.to_ascii_lowercase().as_str(), value) {
+            ("username" | "emails.value" | "emails", Value::String(s)) => {
+                Ok(ScimFilter::UserName(s))
+            }
+            ("id", Value::String(s)) => Ok(ScimFilter::Id(s)),
+            ("active", Value::Bool(b)) => Ok(ScimFilter::Active(b)),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Whether a user satisfies the filter
+    pub fn matches(&self, user: &User) -> bool {
+        match self {
+            ScimFilter::UserName(email) => user.email.eq_ignore_ascii_case(email),
+            ScimFilter::Id(id) => user.user_id().to_string() == *id,
+            ScimFilter::Active(active) => user.active == *active,
+        }
+    }
+}
+
+impl ScimUser {
+    /// Build the SCIM representation of a stored user
+    pub fn from_user(user: &User) -> Self {
+        Self {
+            schemas: vec![USER_SCHEMA.to_string()],
+            id: Some(user.user_id().to_string()),
+            user_name: user.email.clone(),
+            name: Some(ScimName {
+                formatted: Some(user.name.clone()),
+                ..Default::default()
+            }),
+            display_name: Some(user.name.clone()),
+            emails: vec![ScimEmail {
+                value: user.email.clone(),
+                kind: Some("work".to_string()),
+                primary: true,
+            }],
+            active: Some(user.active),
+            meta: Some(ScimMeta {
+                resource_type: "User".to_string(),
+                created: user.created_at.to_rfc3339(),
+                last_modified: user.updated_at.to_rfc3339(),
+                location: format!("/scim/v2/Users/{}", user.user_id()),
+            }),
+        }
+    }
+
+    /// Name to store for this resource
+    ///
+    /// Prefers `displayName`, then `name.formatted`, then given and family
+    /// name, and finally falls back to `userName`.
+    pub fn resolved_name(&self) -> String {
+        if let Some(display_name) = self.display_name.as_ref().filter(|n| !n.trim().is_empty()) {
+            return display_name.clone();
+        }
+        self.name
+            .as_ref()
+            .and_then(ScimName::resolved)
+            .unwrap_or_else(|| self.user_name.clone())
+    }
+
+    /// Full replacement of a user, as sent by `PUT`
+    pub fn to_update(&self) -> UpdateUserRequest {
+        UpdateUserRequest {
+            name: Some(self.resolved_name()),
+            email: Some(normalize_email(&self.user_name)),
+            active: Some(self.active.unwrap_or(true)),
+            ..Default::default()
+        }
+    }
+}
+
+impl ScimName {
+    fn resolved(&self) -> Option<String> {
+        if let Some(formatted) = self.formatted.as_ref().filter(|n| !n.trim().is_empty()) {
+            return Some(formatted.clone());
+        }
+        let joined = [self.given_name.as_deref(), self.family_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        (!joined.is_empty()).then_some(joined)
+    }
+}
+
+impl ScimPatchRequest {
+    /// Fold the operations into a partial user update
+    ///
+    /// Handles both the Okta style (no `path`, object `value`) and the Azure AD
+    /// style (`path` per attribute, booleans sent as `"True"`/`"False"`).
+    pub fn to_update(&self) -> Result<UpdateUserRequest, ScimRequestError> {
+        let mut update = UpdateUserRequest {
+            name: None,
+            email: None,
+            active: None,
+            ..Default::default()
+        };
+
+        for operation in &self.operations {
+            let op = operation.op.to_ascii_lowercase();
+            match op.as_str() {
+                "add" | "replace" => {
+                    let value = operation.value.as_ref().ok_or_else(|| {
+                        ScimRequestError::new(
+                            "invalidValue",
+                            format!("Missing value for {} operation", op),
+                        )
+                    })?;
+                    match &operation.path {
+                        Some(path) => apply_attribute(&mut update, path, value)?,
+                        None => {
+                            let attributes = value.as_object().ok_or_else(|| {
+                                ScimRequestError::new(
+                                    "invalidValue",
+                                    "Operation without path requires an object value",
+                                )
+                            })?;
+                            for (attribute, value) in attributes {
+                                apply_attribute(&mut update, attribute, value)?;
+                            }
+                        }
+                    }
+                }
+                "remove" => {
+                    if let Some(path) = operation
+                        .path
+                        .as_deref()
+                        .filter(|p| Attribute::from_path(p).is_some())
+                    {
+                        return Err(ScimRequestError::new(
+                            "mutability",
+                            format!("Attribute {} cannot be removed", path),
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(ScimRequestError::new(
+                        "invalidSyntax",
+                        format!("Unsupported PATCH operation: {}", operation.op),
+                    ))
+                }
+            }
+        }
+
+        Ok(update)
+    }
+}
+
+/// User attributes that PATCH can change
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Attribute {
+    Active,
+    UserName,
+    DisplayName,
+    Name,
+    Emails,
+}
+
+impl Attribute {
+    /// Attribute a path targets, if it is one the user model stores
+    fn from_path(path: &str) -> Option<Self> {
+        let path = path.to_ascii_lowercase();
+        match path.as_str() {
+            "active" => Some(Attribute::Active),
+            "username" => Some(Attribute::UserName),
+            "displayname" => Some(Attribute::DisplayName),
+            "name" | "name.formatted" => Some(Attribute::Name),
+            "emails" | "emails.value" => Some(Attribute::Emails),
+            p if p.starts_with("emails[") => Some(Attribute::Emails),
+            _ => None,
+        }
+    }
+}
+
+fn apply_attribute(
+    update: &mut UpdateUserRequest,
+    path: &str,
+    value: &Value,
+) -> Result<(), ScimRequestError> {
+    let invalid = || ScimRequestError::new("invalidValue", format!("Invalid value for {}", path));
+
+    match Attribute::from_path(path) {
+        Some(Attribute::Active) => {
+            update.active = Some(match value {
+                Value::Bool(active) => *active,
+                Value::String(s) if s.eq_ignore_ascii_case("true") => true,
+                Value::String(s) if s.eq_ignore_ascii_case("false") => false,
+                _ => return Err(invalid()),
+            });
+        }
+        Some(Attribute::UserName) => {
+            update.email = Some(normalize_email(value.as_str().ok_or_else(invalid)?))
+        }
+        Some(Attribute::DisplayName) => {
+            update.name = Some(value.as_str().ok_or_else(invalid)?.to_string())
+        }
+        Some(Attribute::Name) => {
+            let name = match value {
+                Value::String(s) => Some(s.clone()),
+                Value::Object(_) => serde_json::from_value::<ScimName>(value.clone())
+                    .map_err(|_| invalid())?
+                    .resolved(),
+                _ => return Err(invalid()),
+            };
+            // displayName wins when both are sent
+            if update.name.is_none() {
+                update.name = name;
+            }
+        }
+        Some(Attribute::Emails) => {
+            let email = match value {
+                Value::String(s) => Some(s.clone()),
+                Value::Array(_) => serde_json::from_value::<Vec<ScimEmail>>(value.clone())
+                    .map_err(|_| invalid())?
+                    .into_iter()
+                    .max_by_key(|email| email.primary)
+                    .map(|email| email.value),
+                _ => return Err(invalid()),
+            };
+            // userName is the canonical email when both are sent
+            if update.email.is_none() {
+                update.email = email.as_deref().map(normalize_email);
+            }
+        }
+        None => tracing::debug!("Ignoring unsupported SCIM attribute: {}", path),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    use uuid::Uuid;
+
+    fn user() -> User {
+        User {
+            id: 7,
+            public_id: Uuid::nil(),
+            name: "Jane Doe".to_string(),
+            email: "jane@example.com".to_string(),
+            active: true,
+            display_name: None,
+            bio: None,
+            phone: None,
+            timezone: None,
+            locale: None,
+            email_verified: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_parsing_and_matching() {
+        let filter = ScimFilter::parse(r#"userName eq "JANE@example.com""#).unwrap();
+        assert!(filter.matches(&user()));
+
+        assert_eq!(
+            ScimFilter::parse("active eq false").unwrap(),
+            ScimFilter::Active(false)
+        );
+        assert_eq!(
+            ScimFilter::parse(r#"id eq "7""#).unwrap(),
+            ScimFilter::Id("7".to_string())
+        );
+
+        let unsupported = ScimFilter::parse(r#"userName co "jane""#).unwrap_err();
+        assert_eq!(unsupported.scim_type, "invalidFilter");
+        assert!(ScimFilter::parse("userName eq").is_err());
+    }
+
+    #[test]
+    fn test_from_user_and_resolved_name() {
+        let resource = ScimUser::from_user(&user());
+        let id = user().user_id().to_string();
+        assert_eq!(resource.user_name, "jane@example.com");
+        assert_eq!(resource.id.as_deref(), Some(id.as_str()));
+        assert_eq!(
+            resource.meta.unwrap().location,
+            format!("/scim/v2/Users/{}", id)
+        );
+
+        let request: ScimUser = serde_json::from_value(json!({
+            "userName": "john@example.com",
+            "name": {"givenName": "John", "familyName": "Smith"},
+            "externalId": "00u1"
+        }))
+        .unwrap();
+        assert_eq!(request.resolved_name(), "John Smith");
+        assert_eq!(request.to_update().active, Some(true));
+    }
+
+    #[test]
+    fn test_patch_okta_and_azure_styles() {
+        let okta: ScimPatchRequest = serde_json::from_value(json!({
+            "schemas": [PATCH_OP_SCHEMA],
+            "Operations": [{"op": "replace", "value": {"active": false, "displayName": "Jane Smith"}}]
+        }))
+        .unwrap();
+        let update = okta.to_update().unwrap();
+        assert_eq!(update.active, Some(false));
+        assert_eq!(update.name.as_deref(), Some("Jane Smith"));
+
+        let azure: ScimPatchRequest = serde_json::from_value(json!({
+            "schemas": [PATCH_OP_SCHEMA],
+            "Operations": [
+                {"op": "Replace", "path": "active", "value": "False"},
+                {"op": "Replace", "path": "emails[type eq \"work\"].value", "value": "jane.smith@example.com"},
+                {"op": "Add", "path": "externalId", "value": "abc"}
+            ]
+        }))
+        .unwrap();
+        let update = azure.to_update().unwrap();
+        assert_eq!(update.active, Some(false));
+        assert_eq!(update.email.as_deref(), Some("jane.smith@example.com"));
+
+        let remove: ScimPatchRequest = serde_json::from_value(json!({
+            "Operations": [{"op": "remove", "path": "userName"}]
+        }))
+        .unwrap();
+        assert_eq!(remove.to_update().unwrap_err().scim_type, "mutability");
+    }
+}
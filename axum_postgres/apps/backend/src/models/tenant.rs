@@ -0,0 +1,93 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+
+/// Stored tenant
+#[derive(Debug, Clone, FromRow)]
+pub struct Tenant {
+    pub id: i32,
+    /// Subdomain and `X-Tenant-Id` value naming the tenant
+    pub slug: String,
+    pub name: String,
+    /// Requests for a suspended tenant are refused
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Tenant as returned by `/admin/tenants`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"id": 2, "slug": "acme", "name": "Acme Corp", "active": true, "created_at": "2024-01-01T00:00:00+00:00", "updated_at": "2024-01-01T00:00:00+00:00"}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct TenantResponse {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub active: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<Tenant> for TenantResponse {
+    fn from(tenant: Tenant) -> Self {
+        Self {
+            id: tenant.id,
+            slug: tenant.slug,
+            name: tenant.name,
+            active: tenant.active,
+            created_at: tenant.created_at.to_rfc3339(),
+            updated_at: tenant.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Body of `POST /admin/tenants`
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"slug": "acme", "name": "Acme Corp"}))]
+pub struct CreateTenantRequest {
+    /// Lowercase DNS label: letters, digits and inner hyphens
+    #[validate(custom = "validate_slug")]
+    #[schema(max_length = 63, example = "acme")]
+    pub slug: String,
+
+    #[validate(length(min = 1, max = 255, message = "Name must be 1 to 255 characters"))]
+    #[schema(min_length = 1, max_length = 255, example = "Acme Corp")]
+    pub name: String,
+}
+
+/// Body of `PATCH /admin/tenants/{id}`; absent fields are left alone
+#[derive(Debug, Clone, Default, Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"active": false}))]
+pub struct UpdateTenantRequest {
+    #[validate(length(min = 1, max = 255, message = "Name must be 1 to 255 characters"))]
+    #[schema(min_length = 1, max_length = 255)]
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// false suspends the tenant
+    #[serde(default)]
+    pub active: Option<bool>,
+}
+
+/// A DNS label, so every slug also works as a subdomain
+static SLUG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?$").unwrap());
+
+/// Whether `slug` can name a tenant
+pub fn is_valid_slug(slug: &str) -> bool {
+    SLUG.is_match(slug)
+}
+
+fn validate_slug(slug: &str) -> Result<(), ValidationError> {
+    if is_valid_slug(slug) {
+        return Ok(());
+    }
+    let mut error = ValidationError::new("slug");
+    error.message = Some("Slug must be 1 to 63 lowercase letters, digits and inner hyphens".into());
+    Err(error)
+}
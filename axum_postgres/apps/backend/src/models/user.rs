@@ -1,35 +1,159 @@
+use std::sync::LazyLock;
+
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use serde_json::json;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
-use utoipa::ToSchema;
-use validator::Validate;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::models::links::{CollectionLinks, UserLinks};
+use crate::models::user_id::UserId;
 
 /// User model for database operations
 /// Maps to the test_users table
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct User {
+    /// Serial row key; clients see [`User::user_id`] instead
     pub id: i32,
+    /// UUIDv7 shown as the id with the `uuid-ids` feature
+    pub public_id: Uuid,
     pub name: String,
     pub email: String,
     pub active: bool,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub phone: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
+    /// Set by following the verification email; reset when the email changes
+    pub email_verified: bool,
     pub created_at: DateTime<Utc>,
+    /// Bumped by a database trigger on every change
+    pub updated_at: DateTime<Utc>,
+    /// Set when the user is soft-deleted
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 /// User model for API responses
 /// Converts database id (i32) to string for JSON compatibility
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-#[schema(example = json!({"id": "1", "name": "John Doe", "email": "john@example.com", "active": true, "created_at": "2024-01-01T00:00:00Z"}))]
+#[schema(example = json!({"id": "1", "name": "John Doe", "email": "john@example.com", "email_verified": true, "active": true, "display_name": "John", "bio": null, "phone": "+14155550123", "timezone": "America/New_York", "locale": "en-US", "created_at": "2024-01-01T00:00:00Z", "updated_at": "2024-01-02T00:00:00Z"}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
 pub struct UserResponse {
     pub id: String,
     pub name: String,
     pub email: String,
+    /// Whether the user followed the link in the verification email
+    pub email_verified: bool,
     pub active: bool,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub phone: Option<String>,
+    pub timezone: Option<String>,
+    pub locale: Option<String>,
     pub created_at: String,
+    /// Last modification time, equal to `created_at` until the first change
+    pub updated_at: String,
+    /// Deletion time, only on soft-deleted users shown to admins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    /// Related actions, present when RESPONSE_LINKS is enabled
+    #[serde(rename = "_links", default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<UserLinks>,
+}
+
+/// Users with collection links, returned when RESPONSE_LINKS is enabled
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct UserCollection {
+    pub data: Vec<UserResponse>,
+    #[serde(rename = "_links")]
+    pub links: CollectionLinks,
+}
+
+/// One page of users, returned for `?cursor=` or `?limit=`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct UserPage {
+    pub data: Vec<UserResponse>,
+    /// Pass as `cursor` to fetch the next page; null on the last page
+    pub next_cursor: Option<String>,
+    /// Collection links with `next`, present when RESPONSE_LINKS is enabled
+    #[serde(rename = "_links", default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<CollectionLinks>,
+}
+
+/// Body of `GET /api/users`: a bare array, a linked collection, or a page
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum UserList {
+    Plain(Vec<UserResponse>),
+    Linked(UserCollection),
+    Page(UserPage),
+}
+
+/// Canonical form of an email address: trimmed and lowercased
+///
+/// Emails are unique regardless of case, so they are stored the way they
+/// are compared.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+pub(crate) fn normalized_email<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    String::deserialize(deserializer).map(|email| normalize_email(&email))
+}
+
+fn normalized_optional_email<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(|email| email.as_deref().map(normalize_email))
+}
+
+/// Tells an explicit `null` (`Some(None)`) apart from an absent field (`None`)
+fn nullable<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+static E164: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\+[1-9][0-9]{1,14}$").unwrap());
+
+/// Language, then optional script and region: `en`, `en-US`, `zh-Hant-TW`
+static BCP47: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[A-Za-z]{2,3}(-[A-Za-z]{4})?(-([A-Za-z]{2}|[0-9]{3}))?$").unwrap()
+});
+
+fn invalid(code: &'static str, message: &'static str) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(message.into());
+    error
+}
+
+fn validate_phone(phone: &str) -> Result<(), ValidationError> {
+    if E164.is_match(phone) {
+        Ok(())
+    } else {
+        Err(invalid("phone", "Phone must be in E.164 format, e.g. +14155550123"))
+    }
+}
+
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    match timezone.parse::<chrono_tz::Tz>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(invalid("timezone", "Timezone must be an IANA name, e.g. America/New_York")),
+    }
+}
+
+fn validate_locale(locale: &str) -> Result<(), ValidationError> {
+    if BCP47.is_match(locale) {
+        Ok(())
+    } else {
+        Err(invalid("locale", "Locale must be a BCP 47 tag, e.g. en-US"))
+    }
 }
 
 /// User creation request model
-#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
 #[schema(example = json!({"name": "Jane Doe", "email": "jane@example.com"}))]
 pub struct CreateUserRequest {
     #[validate(length(min = 1, message = "Name cannot be empty"))]
@@ -38,11 +162,107 @@ pub struct CreateUserRequest {
     
     #[validate(email(message = "Invalid email format"))]
     #[schema(format = "email", example = "jane@example.com")]
+    #[serde(deserialize_with = "normalized_email")]
     pub email: String,
+
+    /// Name to show instead of `name`
+    #[validate(length(min = 1, max = 100, message = "Display name must be 1 to 100 characters"))]
+    #[schema(max_length = 100, example = "Jane")]
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Short free-text description
+    #[validate(length(max = 500, message = "Bio must be at most 500 characters"))]
+    #[schema(max_length = 500, example = "Platform engineer")]
+    #[serde(default)]
+    pub bio: Option<String>,
+
+    /// E.164 number, e.g. `+14155550123`
+    #[validate(custom = "validate_phone")]
+    #[schema(example = "+14155550123")]
+    #[serde(default)]
+    pub phone: Option<String>,
+
+    /// IANA time zone, e.g. `America/New_York`
+    #[validate(custom = "validate_timezone")]
+    #[schema(example = "America/New_York")]
+    #[serde(default)]
+    pub timezone: Option<String>,
+
+    /// BCP 47 language tag, e.g. `en-US`
+    #[validate(custom = "validate_locale")]
+    #[schema(example = "en-US")]
+    #[serde(default)]
+    pub locale: Option<String>,
 }
 
-/// User update request model
+/// Full replacement of a user, the body of `PUT /api/users/{id}`
 #[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[schema(example = json!({"name": "Jane Smith", "email": "jane.smith@example.com", "active": true}))]
+pub struct ReplaceUserRequest {
+    #[validate(length(min = 1, message = "Name cannot be empty"))]
+    #[schema(min_length = 1, example = "Jane Smith")]
+    pub name: String,
+
+    #[validate(email(message = "Invalid email format"))]
+    #[schema(format = "email", example = "jane.smith@example.com")]
+    #[serde(deserialize_with = "normalized_email")]
+    pub email: String,
+
+    #[schema(example = true)]
+    pub active: bool,
+
+    /// Name to show instead of `name`
+    #[validate(length(min = 1, max = 100, message = "Display name must be 1 to 100 characters"))]
+    #[schema(max_length = 100, example = "Jane")]
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Short free-text description
+    #[validate(length(max = 500, message = "Bio must be at most 500 characters"))]
+    #[schema(max_length = 500, example = "Platform engineer")]
+    #[serde(default)]
+    pub bio: Option<String>,
+
+    /// E.164 number, e.g. `+14155550123`
+    #[validate(custom = "validate_phone")]
+    #[schema(example = "+14155550123")]
+    #[serde(default)]
+    pub phone: Option<String>,
+
+    /// IANA time zone, e.g. `America/New_York`
+    #[validate(custom = "validate_timezone")]
+    #[schema(example = "America/New_York")]
+    #[serde(default)]
+    pub timezone: Option<String>,
+
+    /// BCP 47 language tag, e.g. `en-US`
+    #[validate(custom = "validate_locale")]
+    #[schema(example = "en-US")]
+    #[serde(default)]
+    pub locale: Option<String>,
+}
+
+impl From<ReplaceUserRequest> for UpdateUserRequest {
+    fn from(request: ReplaceUserRequest) -> Self {
+        Self {
+            name: Some(request.name),
+            email: Some(request.email),
+            active: Some(request.active),
+            // Absent profile fields are cleared, as befits a full replacement
+            display_name: Some(request.display_name),
+            bio: Some(request.bio),
+            phone: Some(request.phone),
+            timezone: Some(request.timezone),
+            locale: Some(request.locale),
+        }
+    }
+}
+
+/// User update request model
+///
+/// Also documents the merge patch accepted by `PATCH /api/users/{id}`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate, ToSchema)]
 #[schema(example = json!({"name": "Jane Smith", "email": "jane.smith@example.com", "active": false}))]
 pub struct UpdateUserRequest {
     #[validate(length(min = 1, message = "Name cannot be empty"))]
@@ -51,54 +271,273 @@ pub struct UpdateUserRequest {
     
     #[validate(email(message = "Invalid email format"))]
     #[schema(format = "email", example = "jane.smith@example.com")]
+    #[serde(default, deserialize_with = "normalized_optional_email")]
     pub email: Option<String>,
     
     #[schema(example = false)]
     pub active: Option<bool>,
+
+    /// Name to show instead of `name`; null clears it
+    #[validate(length(min = 1, max = 100, message = "Display name must be 1 to 100 characters"))]
+    #[schema(max_length = 100, example = "Jane")]
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<Option<String>>,
+
+    /// Short free-text description; null clears it
+    #[validate(length(max = 500, message = "Bio must be at most 500 characters"))]
+    #[schema(max_length = 500, example = "Platform engineer")]
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub bio: Option<Option<String>>,
+
+    /// E.164 number, e.g. `+14155550123`; null clears it
+    #[validate(custom = "validate_phone")]
+    #[schema(example = "+14155550123")]
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub phone: Option<Option<String>>,
+
+    /// IANA time zone, e.g. `America/New_York`; null clears it
+    #[validate(custom = "validate_timezone")]
+    #[schema(example = "America/New_York")]
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Option<String>>,
+
+    /// BCP 47 language tag, e.g. `en-US`; null clears it
+    #[validate(custom = "validate_locale")]
+    #[schema(example = "en-US")]
+    #[serde(default, deserialize_with = "nullable", skip_serializing_if = "Option::is_none")]
+    pub locale: Option<Option<String>>,
+}
+
+/// Field the user listing is sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSortField {
+    Name,
+    Email,
+    #[default]
+    CreatedAt,
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Filters and sort order of `GET /api/users`
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserListQuery {
+    /// Only active or only inactive users
+    pub active: Option<bool>,
+    /// Case-insensitive substring of the email
+    pub email_contains: Option<String>,
+    /// Case-insensitive substring of the name
+    pub name_contains: Option<String>,
+    /// Sort field, `created_at` by default
+    #[param(inline)]
+    pub sort_by: Option<UserSortField>,
+    /// Sort direction; `desc` for `created_at`, otherwise `asc` by default
+    #[param(inline)]
+    pub order: Option<SortOrder>,
+    /// Admin only: also list soft-deleted users
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+impl UserListQuery {
+    pub fn sort_field(&self) -> UserSortField {
+        self.sort_by.unwrap_or_default()
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.order.unwrap_or(match self.sort_field() {
+            UserSortField::CreatedAt => SortOrder::Desc,
+            UserSortField::Name | UserSortField::Email => SortOrder::Asc,
+        })
+    }
+
+    /// Whether the listing keeps its default newest-first order
+    pub fn is_default_order(&self) -> bool {
+        self.sort_field() == UserSortField::CreatedAt && self.sort_order() == SortOrder::Desc
+    }
+}
+
+/// Query of `GET /api/users/search`
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserSearchQuery {
+    /// Words to find in name or email; supports `"quoted phrases"`, `or` and `-word`
+    pub q: String,
+    /// Maximum number of results, 100 by default and at most 1000
+    pub limit: Option<i64>,
 }
 
-/// Error response model for API errors
+/// Body of `GET /api/users/count`
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-#[schema(example = json!({"success": false, "message": "Error occurred"}))]
-pub struct ErrorResponse {
-    pub success: bool,
-    pub message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+#[schema(example = json!({"count": 1234}))]
+pub struct UserCount {
+    /// Users matching the filters
+    pub count: i64,
+}
+
+/// Query of `GET /api/users/check-email`
+#[derive(Debug, Clone, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EmailCheckQuery {
+    /// Address the user wants to sign up with
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+/// Body of `GET /api/users/check-email`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"available": true}))]
+pub struct EmailAvailability {
+    /// False when a user already has this address, in any letter case
+    pub available: bool,
+}
+
+/// Body of `POST /api/users/import`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"processed": 3, "created": 2, "rejected": [{"row": 3, "errors": ["email: Invalid email format"]}]}))]
+pub struct ImportSummary {
+    /// Rows read, the header excluded
+    pub processed: usize,
+    /// Users inserted
+    pub created: usize,
+    /// Rows that were skipped, in file order; only the first 1000 are
+    /// listed, the others are counted in `processed` alone
+    pub rejected: Vec<RejectedRow>,
+}
+
+/// Body of `GET /api/users/import/{request_id}`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+#[schema(example = json!({"request_id": "5f0c6d2e-8a1b-4c3d-9e7f-0a1b2c3d4e5f", "status": "running", "processed": 250000, "created": 249870, "rejected": 130, "error": null, "started_at": "2024-01-01T00:00:00+00:00", "updated_at": "2024-01-01T00:01:30+00:00", "finished_at": null}))]
+pub struct ImportStatus {
+    /// `X-Request-Id` of the upload
+    pub request_id: String,
+    /// `running`, `completed` or `failed`
+    pub status: String,
+    /// Rows read so far, the header excluded
+    pub processed: i64,
+    /// Users inserted so far
+    pub created: i64,
+    /// Rows skipped so far
+    pub rejected: i64,
+    /// Why the import failed
     pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// When the counts last changed; a running import that stopped
+    /// changing was cut off, e.g. by a restart
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
 }
 
-impl ErrorResponse {
-    pub fn new(message: String) -> Self {
-        Self {
-            success: false,
-            message,
-            error: None,
-        }
-    }
+/// Rows of an import read so far, and what became of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportCounts {
+    pub processed: usize,
+    pub created: usize,
+    pub rejected: usize,
+}
 
-    pub fn with_error(message: String, error: String) -> Self {
-        Self {
-            success: false,
-            message,
-            error: Some(error),
-        }
-    }
+/// CSV row that was not imported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RejectedRow {
+    /// Line where the row starts in the file; the header is line 1
+    pub row: usize,
+    pub errors: Vec<String>,
+}
+
+/// Body of every error: RFC 7807 problem details, sent as
+/// `application/problem+json`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"type": "/problems/user-not-found", "title": "Not Found", "status": 404, "detail": "User not found", "code": "USER_NOT_FOUND", "request_id": "5f0c6d2e-8a1b-4c3d-9e7f-0a1b2c3d4e5f"}))]
+pub struct ErrorResponse {
+    /// Identifies the kind of problem, one per `code`; a relative URI
+    /// reference that is not served
+    #[serde(rename = "type")]
+    #[schema(example = "/problems/user-not-found")]
+    pub problem_type: String,
+    /// Reason phrase of the status
+    #[schema(example = "Not Found")]
+    pub title: String,
+    /// HTTP status code
+    #[schema(example = 404)]
+    pub status: u16,
+    /// What went wrong, for people; may change between releases
+    pub detail: String,
+    /// Stable machine-readable code, e.g. `USER_NOT_FOUND` or `EMAIL_TAKEN`
+    #[schema(example = "USER_NOT_FOUND")]
+    pub code: String,
+    /// Fields that failed validation, with code `VALIDATION_FAILED`, so
+    /// forms can show each message next to its field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+    /// `X-Request-Id` of the request, to quote when reporting the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "5f0c6d2e-8a1b-4c3d-9e7f-0a1b2c3d4e5f")]
+    pub request_id: Option<String>,
+}
+
+/// A field that failed validation; a field breaking several rules is
+/// listed once per rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({"field": "email", "code": "invalid_email", "message": "Invalid email format"}))]
+pub struct FieldError {
+    /// Name of the field in the request, or its path such as
+    /// `address.city` for nested fields
+    pub field: String,
+    /// Stable snake case code of the rule, e.g. `invalid_email`,
+    /// `too_short` or `too_long`
+    #[schema(example = "invalid_email")]
+    pub code: String,
+    /// What is wrong, for people
+    pub message: String,
 }
 
 impl From<User> for UserResponse {
     /// Convert database User to API UserResponse
     fn from(user: User) -> Self {
         Self {
-            id: user.id.to_string(),
+            id: user.user_id().to_string(),
             name: user.name,
             email: user.email,
+            email_verified: user.email_verified,
             active: user.active,
+            display_name: user.display_name,
+            bio: user.bio,
+            phone: user.phone,
+            timezone: user.timezone,
+            locale: user.locale,
             created_at: user.created_at.to_rfc3339(),
+            updated_at: user.updated_at.to_rfc3339(),
+            deleted_at: user.deleted_at.map(|deleted_at| deleted_at.to_rfc3339()),
+            links: None,
         }
     }
 }
 
+impl UserResponse {
+    pub fn with_links(mut self, links: UserLinks) -> Self {
+        self.links = Some(links);
+        self
+    }
+}
+
 impl User {
+    /// Id shown to clients: the serial id, or `public_id` with `uuid-ids`
+    pub fn user_id(&self) -> UserId {
+        #[cfg(not(feature = "uuid-ids"))]
+        return UserId::new(self.id);
+        #[cfg(feature = "uuid-ids")]
+        return UserId::new(self.public_id);
+    }
+
     /// Convert to API response format
     pub fn to_response(self) -> UserResponse {
         self.into()
@@ -118,10 +557,19 @@ mod tests {
     fn test_user_serialization() {
         let user = User {
             id: 1,
+            public_id: Uuid::nil(),
             name: "Test User".to_string(),
             email: "test@example.com".to_string(),
             active: true,
+            display_name: None,
+            bio: None,
+            phone: None,
+            timezone: None,
+            locale: None,
+            email_verified: false,
             created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
         };
         
         // Test serialization to JSON
@@ -130,8 +578,9 @@ mod tests {
         assert!(json.contains("test@example.com"));
         
         // Test conversion to API response
+        let id = user.user_id().to_string();
         let response = user.to_response();
-        assert_eq!(response.id, "1");
+        assert_eq!(response.id, id);
         assert_eq!(response.name, "Test User");
         assert!(!response.created_at.is_empty());
     }
@@ -144,6 +593,7 @@ mod tests {
         let valid_request = CreateUserRequest {
             name: "Valid User".to_string(),
             email: "valid@example.com".to_string(),
+            ..Default::default()
         };
         assert!(valid_request.validate().is_ok());
         
@@ -151,6 +601,7 @@ mod tests {
         let invalid_email = CreateUserRequest {
             name: "Valid User".to_string(),
             email: "invalid-email".to_string(),
+            ..Default::default()
         };
         assert!(invalid_email.validate().is_err());
         
@@ -158,10 +609,50 @@ mod tests {
         let empty_name = CreateUserRequest {
             name: "".to_string(),
             email: "valid@example.com".to_string(),
+            ..Default::default()
         };
         assert!(empty_name.validate().is_err());
     }
     
+    #[test]
+    fn test_request_emails_are_normalized() {
+        let create: CreateUserRequest =
+            serde_json::from_str(r#"{"name": "Jane", "email": " Jane@Example.COM "}"#).unwrap();
+        assert_eq!(create.email, "jane@example.com");
+
+        let update: UpdateUserRequest = serde_json::from_str(r#"{"email": "Jane@Example.com"}"#).unwrap();
+        assert_eq!(update.email.as_deref(), Some("jane@example.com"));
+        let update: UpdateUserRequest = serde_json::from_str(r#"{"active": true}"#).unwrap();
+        assert_eq!(update.email, None);
+    }
+
+    #[test]
+    fn test_profile_field_validation() {
+        let profile = |phone: &str, timezone: &str, locale: &str| CreateUserRequest {
+            name: "Jane".to_string(),
+            email: "jane@example.com".to_string(),
+            phone: Some(phone.to_string()),
+            timezone: Some(timezone.to_string()),
+            locale: Some(locale.to_string()),
+            ..Default::default()
+        };
+        assert!(profile("+14155550123", "America/New_York", "en-US").validate().is_ok());
+        assert!(profile("+81312345678", "Asia/Tokyo", "zh-Hant-TW").validate().is_ok());
+
+        let errors = profile("415-555-0123", "Mars/Olympus", "english").validate().unwrap_err();
+        let fields = errors.field_errors();
+        assert!(fields.contains_key("phone"));
+        assert!(fields.contains_key("timezone"));
+        assert!(fields.contains_key("locale"));
+
+        // Explicit null clears; absent keeps
+        let update: UpdateUserRequest = serde_json::from_str(r#"{"bio": null}"#).unwrap();
+        assert_eq!(update.bio, Some(None));
+        assert_eq!(update.phone, None);
+        let update: UpdateUserRequest = serde_json::from_str(r#"{"phone": "bad"}"#).unwrap();
+        assert!(update.validate().is_err());
+    }
+
     #[test]
     fn test_update_user_request_validation() {
         use validator::Validate;
@@ -171,6 +662,7 @@ mod tests {
             name: Some("Updated Name".to_string()),
             email: None,
             active: Some(false),
+            ..Default::default()
         };
         assert!(valid_update.validate().is_ok());
         
@@ -179,6 +671,7 @@ mod tests {
             name: None,
             email: Some("invalid-email".to_string()),
             active: None,
+            ..Default::default()
         };
         assert!(invalid_email.validate().is_err());
         
@@ -187,6 +680,7 @@ mod tests {
             name: Some("".to_string()),
             email: None,
             active: None,
+            ..Default::default()
         };
         assert!(empty_name.validate().is_err());
     }
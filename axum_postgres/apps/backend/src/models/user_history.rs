@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::casing;
+
+/// Row of the test_users_history table
+#[derive(Debug, Clone, FromRow)]
+pub struct UserHistoryRecord {
+    pub version: i32,
+    pub operation: String,
+    pub data: Value,
+    pub changed_by: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Old and new value of a single field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldChange {
+    pub from: Value,
+    pub to: Value,
+}
+
+/// One version of a user with the fields changed since the previous version
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[schema(example = json!({
+    "version": 2,
+    "operation": "UPDATE",
+    "changed_by": null,
+    "changed_at": "2024-01-02T00:00:00+00:00",
+    "data": {"id": 1, "name": "Jane Smith", "email": "jane@example.com", "active": true, "created_at": "2024-01-01T00:00:00+00:00"},
+    "changes": {"name": {"from": "Jane Doe", "to": "Jane Smith"}}
+}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct UserHistoryEntry {
+    pub version: i32,
+    /// INSERT, UPDATE or DELETE
+    pub operation: String,
+    pub changed_by: Option<String>,
+    pub changed_at: String,
+    /// Row state after the change (before it, for DELETE)
+    #[schema(value_type = Object)]
+    pub data: Value,
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+impl UserHistoryEntry {
+    /// Convert records ordered by version into entries with per-version diffs
+    pub fn from_records(records: Vec<UserHistoryRecord>) -> Vec<UserHistoryEntry> {
+        let mut previous = Value::Null;
+
+        records
+            .into_iter()
+            .map(|mut record| {
+                record.data = public_snapshot(record.data);
+                let changes = if record.operation == "DELETE" {
+                    diff(&record.data, &Value::Null)
+                } else {
+                    diff(&previous, &record.data)
+                };
+                previous = record.data.clone();
+
+                UserHistoryEntry {
+                    version: record.version,
+                    operation: record.operation,
+                    changed_by: record.changed_by,
+                    changed_at: record.changed_at.to_rfc3339(),
+                    data: casing::rename_keys(record.data),
+                    changes: changes
+                        .into_iter()
+                        .map(|(field, change)| (casing::field_name(&field), change))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Row snapshot with the id clients know
+///
+/// Snapshots hold both the serial `id` and `public_id`; only the one shown
+/// as the user's id is kept, under `id`.
+fn public_snapshot(mut data: Value) -> Value {
+    if let Some(fields) = data.as_object_mut() {
+        let public_id = fields.remove("public_id");
+        if cfg!(feature = "uuid-ids") {
+            if let Some(public_id) = public_id {
+                fields.insert("id".to_string(), public_id);
+            }
+        }
+    }
+    data
+}
+
+/// Field-by-field difference between two snapshots; a missing snapshot
+/// counts as having no fields
+pub fn diff(before: &Value, after: &Value) -> BTreeMap<String, FieldChange> {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    before
+        .keys()
+        .chain(after.keys())
+        .filter_map(|key| {
+            let from = before.get(key).cloned().unwrap_or(Value::Null);
+            let to = after.get(key).cloned().unwrap_or(Value::Null);
+            (from != to).then(|| (key.clone(), FieldChange { from, to }))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(version: i32, operation: &str, data: Value) -> UserHistoryRecord {
+        UserHistoryRecord {
+            version,
+            operation: operation.to_string(),
+            data,
+            changed_by: None,
+            changed_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_history_diffs_between_versions() {
+        let entries = UserHistoryEntry::from_records(vec![
+            record(1, "INSERT", json!({"id": 1, "name": "Jane", "active": true})),
+            record(2, "UPDATE", json!({"id": 1, "name": "Janet", "active": true})),
+            record(3, "DELETE", json!({"id": 1, "name": "Janet", "active": true})),
+        ]);
+
+        assert_eq!(entries[0].changes.len(), 3);
+        assert_eq!(entries[0].changes["name"].from, Value::Null);
+
+        assert_eq!(entries[1].changes.len(), 1);
+        assert_eq!(
+            entries[1].changes["name"],
+            FieldChange {
+                from: json!("Jane"),
+                to: json!("Janet")
+            }
+        );
+
+        assert_eq!(entries[2].changes.len(), 3);
+        assert_eq!(entries[2].changes["active"].to, Value::Null);
+    }
+
+    #[test]
+    fn test_history_shows_public_id() {
+        let public_id = "01900000-0000-7000-8000-000000000000";
+        let entries = UserHistoryEntry::from_records(vec![record(
+            1,
+            "INSERT",
+            json!({"id": 1, "public_id": public_id, "name": "Jane"}),
+        )]);
+
+        let expected_id = if cfg!(feature = "uuid-ids") {
+            json!(public_id)
+        } else {
+            json!(1)
+        };
+        assert_eq!(entries[0].data, json!({"id": expected_id, "name": "Jane"}));
+        assert_eq!(entries[0].changes.len(), 2);
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::openapi::{KnownFormat, ObjectBuilder, RefOr, Schema, SchemaFormat, SchemaType};
+use utoipa::ToSchema;
+
+/// Value behind a [`UserId`]: the serial row key by default, or the UUIDv7
+/// `public_id` column with the `uuid-ids` feature
+#[cfg(not(feature = "uuid-ids"))]
+pub type UserIdValue = i32;
+#[cfg(feature = "uuid-ids")]
+pub type UserIdValue = uuid::Uuid;
+
+/// Id of a user as clients see it
+///
+/// The API shows it as a string in responses and paths. Extract it with
+/// [`crate::extract::Path`] so malformed ids answer 400.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, sqlx::Type)]
+#[serde(transparent)]
+#[sqlx(transparent)]
+pub struct UserId(UserIdValue);
+
+/// Text that is not a user id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUserId;
+
+impl fmt::Display for InvalidUserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid user ID format")
+    }
+}
+
+impl std::error::Error for InvalidUserId {}
+
+impl UserId {
+    pub fn new(id: UserIdValue) -> Self {
+        Self(id)
+    }
+
+    pub fn get(self) -> UserIdValue {
+        self.0
+    }
+}
+
+impl From<UserIdValue> for UserId {
+    fn from(id: UserIdValue) -> Self {
+        Self(id)
+    }
+}
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for UserId {
+    type Err = InvalidUserId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self).map_err(|_| InvalidUserId)
+    }
+}
+
+/// Accepts JSON numbers as well as the strings used in paths
+impl<'de> Deserialize<'de> for UserId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct UserIdVisitor;
+
+        impl Visitor<'_> for UserIdVisitor {
+            type Value = UserId;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a user ID")
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<UserId, E> {
+                self.visit_str(&value.to_string())
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<UserId, E> {
+                self.visit_str(&value.to_string())
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<UserId, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(UserIdVisitor)
+    }
+}
+
+impl<'s> ToSchema<'s> for UserId {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new().description(Some("Id of a user"));
+        #[cfg(not(feature = "uuid-ids"))]
+        let schema = schema
+            .schema_type(SchemaType::Integer)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Int32)))
+            .example(Some(1.into()));
+        #[cfg(feature = "uuid-ids")]
+        let schema = schema
+            .schema_type(SchemaType::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Uuid)))
+            .example(Some("01900000-0000-7000-8000-000000000000".into()));
+        ("UserId", schema.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "uuid-ids"))]
+    fn test_user_id_parses_and_deserializes() {
+        assert_eq!("42".parse::<UserId>(), Ok(UserId::new(42)));
+        assert!("abc".parse::<UserId>().is_err());
+        assert_eq!(UserId::new(42).to_string(), "42");
+
+        assert_eq!(
+            serde_json::from_str::<UserId>("42").unwrap(),
+            UserId::new(42)
+        );
+        assert_eq!(
+            serde_json::from_str::<UserId>("\"42\"").unwrap(),
+            UserId::new(42)
+        );
+        assert!(serde_json::from_str::<UserId>("\"abc\"").is_err());
+        assert!(serde_json::from_str::<UserId>("4294967296").is_err());
+        assert_eq!(serde_json::to_string(&UserId::new(42)).unwrap(), "42");
+    }
+
+    #[test]
+    #[cfg(feature = "uuid-ids")]
+    fn test_user_id_parses_uuids() {
+        let text = "01900000-0000-7000-8000-000000000000";
+        let id = text.parse::<UserId>().unwrap();
+        assert_eq!(id.to_string(), text);
+        assert!("42".parse::<UserId>().is_err());
+
+        assert_eq!(
+            serde_json::from_str::<UserId>(&format!("\"{}\"", text)).unwrap(),
+            id
+        );
+        assert!(serde_json::from_str::<UserId>("42").is_err());
+        assert_eq!(serde_json::to_string(&id).unwrap(), format!("\"{}\"", text));
+    }
+}
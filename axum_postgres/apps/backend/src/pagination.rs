@@ -0,0 +1,290 @@
+//! Shared pagination for list endpoints
+//!
+//! `Range: items=0-99` selects a slice of a collection. The response is 206
+//! with `Content-Range: items 0-99/1234`, or 416 when the range starts past
+//! the end. Headers that cannot be parsed are ignored and the full list is
+//! returned, as RFC 9110 requires for unsupported ranges.
+//!
+//! `?cursor=` pages by key instead of offset: each page ends with an opaque
+//! `next_cursor` naming the last item returned, so deep pages cost the same
+//! as the first and concurrent inserts do not shift items between pages.
+
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderValue, StatusCode},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+/// Range unit advertised in `Accept-Ranges` and `Content-Range`
+pub const RANGE_UNIT: &str = "items";
+
+/// Largest slice served for one request; longer ranges are truncated
+pub const MAX_RANGE_ITEMS: i64 = 1000;
+
+/// Parsed `Range: items=...` header
+///
+/// Extract as `Option<ItemRange>`: a missing or unparseable header yields
+/// `None` and the handler serves the whole collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemRange {
+    /// `items=first-last`, or `items=first-` when `last` is None
+    From { first: i64, last: Option<i64> },
+    /// `items=-count`: the final `count` items
+    Suffix { count: i64 },
+}
+
+/// Slice of a collection to load and report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeWindow {
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl ItemRange {
+    pub fn parse(value: &str) -> Option<Self> {
+        let spec = value.trim().strip_prefix(RANGE_UNIT)?.strip_prefix('=')?;
+        // Multiple ranges would need multipart responses; treat as unsupported
+        if spec.contains(',') {
+            return None;
+        }
+
+        let (first, last) = spec.trim().split_once('-')?;
+        if first.is_empty() {
+            let count: i64 = last.parse().ok()?;
+            return (count > 0).then_some(Self::Suffix { count });
+        }
+
+        let first: i64 = first.parse().ok()?;
+        let last = match last {
+            "" => None,
+            last => Some(last.parse::<i64>().ok()?),
+        };
+        if first < 0 || last.is_some_and(|last| last < first) {
+            return None;
+        }
+        Some(Self::From { first, last })
+    }
+
+    /// Resolve against the collection size; None means 416
+    pub fn window(self, total: i64) -> Option<RangeWindow> {
+        let (offset, end) = match self {
+            Self::From { first, last } => {
+                if first >= total {
+                    return None;
+                }
+                (first, last.map_or(total, |last| (last + 1).min(total)))
+            }
+            Self::Suffix { count } => {
+                if total == 0 {
+                    return None;
+                }
+                ((total - count).max(0), total)
+            }
+        };
+        Some(RangeWindow {
+            offset,
+            limit: (end - offset).min(MAX_RANGE_ITEMS),
+        })
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ItemRange
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .headers
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::parse)
+            .ok_or(StatusCode::RANGE_NOT_SATISFIABLE)
+    }
+}
+
+impl RangeWindow {
+    /// `Content-Range` for the items actually returned
+    pub fn content_range(self, returned: usize, total: i64) -> HeaderValue {
+        let value = if returned == 0 {
+            format!("{} */{}", RANGE_UNIT, total)
+        } else {
+            format!(
+                "{} {}-{}/{}",
+                RANGE_UNIT,
+                self.offset,
+                self.offset + returned as i64 - 1,
+                total
+            )
+        };
+        HeaderValue::from_str(&value).expect("Content-Range is ASCII")
+    }
+}
+
+/// `Content-Range` of a 416 response
+pub fn unsatisfied_range(total: i64) -> HeaderValue {
+    HeaderValue::from_str(&format!("{} */{}", RANGE_UNIT, total)).expect("Content-Range is ASCII")
+}
+
+/// Page size when `?cursor=` pagination is requested without a `limit`
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+
+/// Position in a listing ordered by `created_at DESC, id DESC`
+///
+/// The next page holds the items strictly after it in that order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i32,
+}
+
+impl Cursor {
+    /// Opaque token: hex of `<created_at micros>:<id>`
+    ///
+    /// Clients must only echo it back, which leaves the format free to change.
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.created_at.timestamp_micros(), self.id)
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        if !token.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(token.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let (micros, id) = std::str::from_utf8(&bytes).ok()?.split_once(':')?;
+        Some(Self {
+            created_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// Query parameters of cursor pagination
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CursorQuery {
+    /// `next_cursor` of the previous page; omit for the first page
+    pub cursor: Option<String>,
+    /// Page size, 100 by default and at most 1000
+    pub limit: Option<i64>,
+}
+
+impl CursorQuery {
+    /// Whether the client asked for a page rather than the whole listing
+    pub fn is_requested(&self) -> bool {
+        self.cursor.is_some() || self.limit.is_some()
+    }
+
+    pub fn page_size(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_RANGE_ITEMS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range_header() {
+        assert_eq!(
+            ItemRange::parse("items=0-99"),
+            Some(ItemRange::From {
+                first: 0,
+                last: Some(99)
+            })
+        );
+        assert_eq!(
+            ItemRange::parse("items=100-"),
+            Some(ItemRange::From {
+                first: 100,
+                last: None
+            })
+        );
+        assert_eq!(
+            ItemRange::parse("items=-10"),
+            Some(ItemRange::Suffix { count: 10 })
+        );
+
+        for invalid in [
+            "bytes=0-99",
+            "items=5-1",
+            "items=0-1,5-9",
+            "items=a-b",
+            "items=-0",
+        ] {
+            assert_eq!(ItemRange::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_window_clamps_to_collection() {
+        let range = ItemRange::From {
+            first: 10,
+            last: Some(99),
+        };
+        assert_eq!(
+            range.window(50),
+            Some(RangeWindow {
+                offset: 10,
+                limit: 40
+            })
+        );
+        assert_eq!(range.window(10), None);
+
+        let open = ItemRange::From {
+            first: 0,
+            last: None,
+        };
+        assert_eq!(
+            open.window(5_000),
+            Some(RangeWindow {
+                offset: 0,
+                limit: MAX_RANGE_ITEMS
+            })
+        );
+
+        let suffix = ItemRange::Suffix { count: 10 };
+        assert_eq!(
+            suffix.window(4),
+            Some(RangeWindow {
+                offset: 0,
+                limit: 4
+            })
+        );
+        assert_eq!(suffix.window(0), None);
+
+        let window = RangeWindow {
+            offset: 10,
+            limit: 40,
+        };
+        assert_eq!(window.content_range(40, 50), "items 10-49/50");
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            created_at: DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            id: 42,
+        };
+        let token = cursor.encode();
+        assert!(token.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_eq!(Cursor::decode(&token), Some(cursor));
+
+        for invalid in ["", "zz", "abc", "3132", "313a"] {
+            assert_eq!(Cursor::decode(invalid), None, "{}", invalid);
+        }
+    }
+}
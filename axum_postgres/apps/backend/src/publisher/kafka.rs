@@ -0,0 +1,109 @@
+//! Kafka adapter for the publisher
+//!
+//! Events are keyed by tenant and user, so the events of one user land in
+//! one partition in order, and carry a `Message-Id` header consumers can
+//! deduplicate retries on and a `Tenant-Id` header naming the tenant. The
+//! producer is idempotent, so its own retries are written once.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rdkafka::config::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+
+use super::{EventPublisher, Publication, PublisherError, TENANT_ID_HEADER};
+use crate::health::HealthCheck;
+
+/// Header carrying the message id
+pub const MESSAGE_ID_HEADER: &str = "Message-Id";
+
+/// Wait for the brokers to confirm a publish or answer a metadata request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publisher to Kafka topics
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+}
+
+/// Optional readiness check on the publisher's brokers
+///
+/// Events are not needed to serve HTTP traffic, so unreachable brokers
+/// degrade the service rather than taking it out of rotation.
+pub struct KafkaPublisherCheck {
+    producer: FutureProducer,
+}
+
+/// Create a producer for the comma separated bootstrap `brokers`
+///
+/// The producer connects lazily; topics must exist unless the brokers
+/// create them on first use.
+pub fn connect(brokers: &str) -> Result<(KafkaPublisher, KafkaPublisherCheck), PublisherError> {
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("enable.idempotence", "true")
+        .set("message.timeout.ms", TIMEOUT.as_millis().to_string())
+        .create()
+        .map_err(broker_error)?;
+    let check = KafkaPublisherCheck {
+        producer: producer.clone(),
+    };
+    Ok((KafkaPublisher { producer }, check))
+}
+
+fn broker_error<E: std::fmt::Display>(e: E) -> PublisherError {
+    PublisherError::Broker(e.to_string())
+}
+
+#[async_trait]
+impl EventPublisher for KafkaPublisher {
+    async fn publish(&self, publication: &Publication) -> Result<(), PublisherError> {
+        let payload = serde_json::to_vec(&publication.envelope).map_err(broker_error)?;
+        let tenant_id = publication.tenant_id.to_string();
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: MESSAGE_ID_HEADER,
+                value: Some(publication.message_id.as_str()),
+            })
+            .insert(Header {
+                key: TENANT_ID_HEADER,
+                value: Some(tenant_id.as_str()),
+            });
+        self.producer
+            .send(
+                FutureRecord::to(&publication.topic)
+                    .key(&publication.key)
+                    .payload(&payload)
+                    .headers(headers),
+                TIMEOUT,
+            )
+            .await
+            .map_err(|(e, _)| broker_error(e))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthCheck for KafkaPublisherCheck {
+    fn name(&self) -> &str {
+        "event_publisher"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        let producer = self.producer.clone();
+        // Metadata requests block the calling thread
+        tokio::task::spawn_blocking(move || {
+            producer
+                .client()
+                .fetch_metadata(None, TIMEOUT)
+                .map(|_| ())
+                .map_err(|e| format!("Kafka brokers are unreachable: {}", e))
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+}
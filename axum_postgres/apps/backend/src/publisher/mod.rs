@@ -0,0 +1,639 @@
+//! Domain event publishing to a message broker
+//!
+//! Every committed user change leaves a row in the `event_outbox` table,
+//! written by a trigger in the transaction of the change. A [`Dispatcher`]
+//! turns the rows into domain events, builds their envelopes with the
+//! [`EventRegistry`], which validates each payload and adds older schema
+//! versions during a migration, and hands them to an [`EventPublisher`]. A
+//! row is deleted once the broker confirmed it stored all of its events;
+//! until then the dispatcher retries with a growing delay, so a broker
+//! outage delays events rather than losing them. Rows whose events do not
+//! match their schema are logged and deleted.
+//!
+//! Only the replica that holds the dispatch advisory lock publishes, in
+//! outbox order. Rows wait while no replica holds it and are picked up by
+//! the next holder. The [`ChangeFeed`] only wakes the dispatcher up; the
+//! outbox is polled as well, so lost notifications delay events by at most
+//! a poll interval.
+//!
+//! Delivery is at least once: an event whose confirmation was lost is
+//! published again with the same message id, which NATS uses to store it
+//! once and Kafka consumers can deduplicate on. Created and updated events
+//! carry the user as it is when the event is dispatched, so a user changed
+//! twice in quick succession may be reported with its latest state both
+//! times.
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use sqlx::PgPool;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+use tracing::{debug, error, info, warn};
+
+use crate::changes::{ChangeFeed, ChangeOp, UserChange};
+use crate::database::lock::lock_key;
+use crate::events::user::{UserCreatedV1, UserDeletedV1, UserUpdatedV1};
+use crate::events::{EventEnvelope, EventError, EventRegistry};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+use crate::tenant;
+
+/// Events the broker confirmed, by event type
+pub const EVENTS_PUBLISHED_TOTAL: &str = "events_published_total";
+/// Publishes that failed, by event type; the event is retried
+pub const EVENTS_PUBLISH_FAILURES_TOTAL: &str = "events_publish_failures_total";
+
+/// Header naming the tenant an event belongs to
+pub const TENANT_ID_HEADER: &str = "Tenant-Id";
+
+/// Advisory lock held by the replica that publishes
+const DISPATCH_LOCK: &str = "events.dispatch";
+
+/// Wait before trying to take the lock again, between checks that the
+/// connection holding it is still open, and between polls of the outbox
+const LOCK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wait before the first retry of a failed publish; doubled for every
+/// other one, up to `MAX_RETRY_DELAY`
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Outbox rows read at a time
+const BATCH_SIZE: i64 = 100;
+
+/// Publisher error type
+#[derive(Debug)]
+pub enum PublisherError {
+    /// Broker connection failure, or the broker did not confirm a publish
+    Broker(String),
+    /// The outbox or the user the event is about could not be read
+    Database(String),
+    /// The payload does not match its registered schema
+    Event(EventError),
+}
+
+impl std::fmt::Display for PublisherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublisherError::Broker(msg) => write!(f, "Broker error: {}", msg),
+            PublisherError::Database(msg) => write!(f, "Database error: {}", msg),
+            PublisherError::Event(e) => write!(f, "Event error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PublisherError {}
+
+impl From<EventError> for PublisherError {
+    fn from(e: EventError) -> Self {
+        PublisherError::Event(e)
+    }
+}
+
+impl From<sqlx::Error> for PublisherError {
+    fn from(e: sqlx::Error) -> Self {
+        PublisherError::Database(e.to_string())
+    }
+}
+
+/// An event on its way to the broker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Publication {
+    /// Topic (subject, routing key) to publish to
+    pub topic: String,
+    /// Same for every attempt, so brokers and consumers that deduplicate
+    /// store it once
+    pub message_id: String,
+    /// Partition key: the events of one user share it and stay in order
+    pub key: String,
+    /// Tenant of the user the event is about
+    pub tenant_id: i32,
+    pub envelope: EventEnvelope,
+}
+
+/// Destination of domain events
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    /// Publish one event; resolves once the broker confirmed it stored it
+    async fn publish(&self, publication: &Publication) -> Result<(), PublisherError>;
+}
+
+/// Topic of each event type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Topics {
+    /// Events without a topic of their own go to `<prefix>.<event type>`
+    pub prefix: String,
+    /// Topic by event type, such as `user.created`
+    pub overrides: HashMap<String, String>,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self {
+            prefix: "events".to_string(),
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl Topics {
+    /// Topic events of `event_type` are published to
+    pub fn topic(&self, event_type: &str) -> String {
+        self.overrides
+            .get(event_type)
+            .cloned()
+            .unwrap_or_else(|| format!("{}.{}", self.prefix, event_type))
+    }
+
+    /// Subjects a stream needs to take every topic
+    pub fn subjects(&self) -> Vec<String> {
+        let mut subjects = vec![format!("{}.>", self.prefix)];
+        for topic in self.overrides.values() {
+            if !subjects.contains(topic) {
+                subjects.push(topic.clone());
+            }
+        }
+        subjects
+    }
+
+    /// `user.created=crm.users.created,user.deleted=crm.users.deleted`
+    fn parse_overrides(value: &str) -> Result<HashMap<String, String>, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| match pair.split_once('=') {
+                Some((event_type, topic))
+                    if !event_type.trim().is_empty() && !topic.trim().is_empty() =>
+                {
+                    Ok((event_type.trim().to_string(), topic.trim().to_string()))
+                }
+                _ => Err(format!(
+                    "PUBLISHER_TOPICS must list event=topic pairs: {}",
+                    pair
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Broker events are published to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Broker {
+    /// NATS JetStream, storing events in `stream`
+    Nats { url: String, stream: String },
+    /// Kafka, with `brokers` the bootstrap servers
+    Kafka { brokers: String },
+}
+
+impl Broker {
+    /// EVENT_PUBLISHER value, which is also the cargo feature it needs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Broker::Nats { .. } => "nats",
+            Broker::Kafka { .. } => "kafka",
+        }
+    }
+}
+
+/// Publisher configuration read from environment variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublisherConfig {
+    pub broker: Broker,
+    pub topics: Topics,
+}
+
+impl PublisherConfig {
+    /// Read publisher settings; `None` unless EVENT_PUBLISHER is `nats` or
+    /// `kafka`
+    ///
+    /// Variables: EVENT_PUBLISHER, NATS_URL and PUBLISHER_STREAM for NATS,
+    /// KAFKA_BROKERS for Kafka, PUBLISHER_TOPIC_PREFIX, PUBLISHER_TOPICS
+    /// (comma separated `event=topic` pairs)
+    pub fn from_env() -> Result<Option<Self>, String> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(var: impl Fn(&str) -> Option<String>) -> Result<Option<Self>, String> {
+        let broker = match var("EVENT_PUBLISHER").unwrap_or_default().trim() {
+            "" | "none" => return Ok(None),
+            "nats" => Broker::Nats {
+                url: var("NATS_URL").unwrap_or_else(|| "nats://localhost:4222".to_string()),
+                stream: var("PUBLISHER_STREAM").unwrap_or_else(|| "DOMAIN_EVENTS".to_string()),
+            },
+            "kafka" => Broker::Kafka {
+                brokers: var("KAFKA_BROKERS").unwrap_or_else(|| "localhost:9092".to_string()),
+            },
+            other => {
+                return Err(format!(
+                    "EVENT_PUBLISHER must be none, nats or kafka: {}",
+                    other
+                ))
+            }
+        };
+
+        let mut topics = Topics::default();
+        if let Some(prefix) = var("PUBLISHER_TOPIC_PREFIX").filter(|prefix| !prefix.is_empty()) {
+            topics.prefix = prefix.trim_end_matches('.').to_string();
+        }
+        if let Some(overrides) = var("PUBLISHER_TOPICS") {
+            topics.overrides = Topics::parse_overrides(&overrides)?;
+        }
+
+        Ok(Some(Self { broker, topics }))
+    }
+}
+
+/// A user change waiting in the outbox for its events to be published
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub change: UserChange,
+    /// When the change was made; the `occurred_at` of its events
+    pub created_at: DateTime<Utc>,
+}
+
+/// Start recording user changes in the outbox
+///
+/// Changes made before are not recorded, and so never published.
+pub async fn enable_outbox(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE event_outbox_state SET enabled = TRUE WHERE NOT enabled")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Publishes the domain events of user changes
+pub struct Dispatcher {
+    registry: Arc<EventRegistry>,
+    publisher: Arc<dyn EventPublisher>,
+    topics: Topics,
+}
+
+impl Dispatcher {
+    pub fn new(registry: Arc<EventRegistry>, publisher: Arc<dyn EventPublisher>) -> Self {
+        Self {
+            registry,
+            publisher,
+            topics: Topics::default(),
+        }
+    }
+
+    pub fn with_topics(mut self, topics: Topics) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Publish the outbox whenever this replica holds the dispatch lock,
+    /// until shutdown; `feed` tells it when there is something new
+    ///
+    /// Holds one connection of `pool` while it publishes.
+    pub async fn run(self, pool: PgPool, feed: ChangeFeed, mut shutdown: watch::Receiver<bool>) {
+        loop {
+            match self
+                .dispatch_while_holding_lock(&pool, &feed, &mut shutdown)
+                .await
+            {
+                Ok(true) => break,
+                // Another replica publishes; try again in case it stops
+                Ok(false) => {}
+                Err(e) => warn!("Stopped publishing events: {}", e),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(LOCK_INTERVAL) => {}
+                _ = shutdown.changed() => break,
+            }
+        }
+        info!("Event dispatcher stopped");
+    }
+
+    /// Publish if the lock is free; true when it stopped for shutdown
+    async fn dispatch_while_holding_lock(
+        &self,
+        pool: &PgPool,
+        feed: &ChangeFeed,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<bool, String> {
+        let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+        let held: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(lock_key(DISPATCH_LOCK))
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !held {
+            return Ok(false);
+        }
+        info!("Publishing domain events");
+
+        let mut events = feed.subscribe();
+        let mut ticker = tokio::time::interval(LOCK_INTERVAL);
+        // Publish what is waiting from the start, then whenever woken up;
+        // after a failure not before the retry delay has passed
+        let mut next_batch = Some(Instant::now());
+        let mut delay = RETRY_DELAY;
+        let dispatched = loop {
+            let due = next_batch.unwrap_or_else(|| Instant::now() + LOCK_INTERVAL);
+            tokio::select! {
+                _ = tokio::time::sleep_until(due), if next_batch.is_some() => {
+                    match self.publish_batch(pool).await {
+                        // A full batch may have more behind it
+                        Ok(published) if published == BATCH_SIZE as usize => {
+                            next_batch = Some(Instant::now());
+                        }
+                        Ok(_) => {
+                            next_batch = None;
+                            delay = RETRY_DELAY;
+                        }
+                        Err(e) => {
+                            warn!("Failed to publish the outbox, retrying in {:?}: {}", delay, e);
+                            next_batch = Some(Instant::now() + delay);
+                            delay = (delay * 2).min(MAX_RETRY_DELAY);
+                        }
+                    }
+                }
+                event = events.recv() => match event {
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        next_batch.get_or_insert_with(Instant::now);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break Ok(true),
+                },
+                // The lock is gone with the connection; another replica may have taken it
+                _ = ticker.tick() => {
+                    if let Err(e) = sqlx::query("SELECT 1").execute(&mut *conn).await {
+                        break Err(format!("lost the {} lock connection: {}", DISPATCH_LOCK, e));
+                    }
+                    next_batch.get_or_insert_with(Instant::now);
+                }
+                _ = shutdown.changed() => break Ok(true),
+            }
+        };
+
+        // The lock belongs to the connection, which goes back to the pool
+        let _ = sqlx::query("SELECT pg_advisory_unlock($1)")
+            .bind(lock_key(DISPATCH_LOCK))
+            .execute(&mut *conn)
+            .await;
+        dispatched
+    }
+
+    /// Publish the oldest outbox entries, deleting each once its events
+    /// are confirmed; the number of entries handled
+    ///
+    /// Stops at the first entry that fails to publish, so events keep
+    /// their order. Entries with events that do not match their schema,
+    /// or that cannot be read, are logged and deleted.
+    pub async fn publish_batch(&self, pool: &PgPool) -> Result<usize, PublisherError> {
+        let rows: Vec<(i64, serde_json::Value, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT id, change, created_at FROM event_outbox ORDER BY id LIMIT $1",
+        )
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        for (id, change, created_at) in &rows {
+            match serde_json::from_value(change.clone()) {
+                Ok(change) => {
+                    let entry = OutboxEntry {
+                        id: *id,
+                        change,
+                        created_at: *created_at,
+                    };
+                    match self.dispatch(pool, &entry).await {
+                        Ok(()) => {}
+                        Err(PublisherError::Event(e)) => {
+                            error!("Dropped the events of outbox entry {}: {}", id, e)
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(e) => error!("Dropped unreadable outbox entry {}: {}", id, e),
+            }
+            sqlx::query("DELETE FROM event_outbox WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+        Ok(rows.len())
+    }
+
+    /// Publish the events reporting `entry`, each until the broker
+    /// confirms it or fails
+    pub async fn dispatch(&self, pool: &PgPool, entry: &OutboxEntry) -> Result<(), PublisherError> {
+        let change = &entry.change;
+        for mut envelope in self.envelopes(pool, change).await? {
+            envelope.occurred_at = entry.created_at;
+            let event_type = envelope.event_type.clone();
+            let publication = Publication {
+                topic: self.topics.topic(&envelope.event_type),
+                message_id: format!("{}:{}", entry.id, envelope.schema_id()),
+                key: format!("{}:{}", change.tenant_id, change.id),
+                tenant_id: change.tenant_id,
+                envelope,
+            };
+            if let Err(e) = self.publisher.publish(&publication).await {
+                counter!(EVENTS_PUBLISH_FAILURES_TOTAL, 1, "event" => event_type);
+                return Err(e);
+            }
+            counter!(EVENTS_PUBLISHED_TOTAL, 1, "event" => event_type);
+        }
+        Ok(())
+    }
+
+    /// Validated envelopes of the event reporting `change`; none when the
+    /// user is gone before its creation is published, as its deletion
+    /// follows
+    async fn envelopes(
+        &self,
+        pool: &PgPool,
+        change: &UserChange,
+    ) -> Result<Vec<EventEnvelope>, PublisherError> {
+        if change.deleted || change.op == ChangeOp::Delete {
+            return Ok(self.registry.envelopes(&UserDeletedV1::new(change.id))?);
+        }
+
+        let user = tenant::scope(change.tenant_id, async {
+            UserRepository::new(pool.clone())
+                .get_user_by_id(change.id)
+                .await
+        })
+        .await?;
+        let Some(user) = user else {
+            debug!(
+                "User ID {} is gone; not publishing its {:?}",
+                change.id, change.op
+            );
+            return Ok(Vec::new());
+        };
+
+        Ok(match change.op {
+            ChangeOp::Insert => self.registry.envelopes(&UserCreatedV1::from(&user))?,
+            _ => self.registry.envelopes(&UserUpdatedV1::from(&user))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DomainEvent;
+    use crate::models::user_id::UserId;
+    use sqlx::postgres::PgPoolOptions;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+
+    /// Records what it publishes, failing the first `failures` attempts
+    #[derive(Default)]
+    struct RecordingPublisher {
+        published: Mutex<Vec<Publication>>,
+        attempts: AtomicU32,
+        failures: u32,
+    }
+
+    #[async_trait]
+    impl EventPublisher for RecordingPublisher {
+        async fn publish(&self, publication: &Publication) -> Result<(), PublisherError> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(PublisherError::Broker("no responders".to_string()));
+            }
+            self.published.lock().unwrap().push(publication.clone());
+            Ok(())
+        }
+    }
+
+    fn config(vars: &[(&str, &str)]) -> Result<Option<PublisherConfig>, String> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        PublisherConfig::from_lookup(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    /// Schemas of the `E` events published, in order
+    fn schemas<E: DomainEvent>(publications: &[Publication]) -> Vec<String> {
+        publications
+            .iter()
+            .filter(|publication| publication.envelope.event_type == E::NAME)
+            .map(|publication| publication.envelope.schema_id())
+            .collect()
+    }
+
+    fn deletion() -> OutboxEntry {
+        OutboxEntry {
+            id: 17,
+            change: UserChange {
+                op: ChangeOp::Update,
+                id: UserId::new(Default::default()),
+                tenant_id: 2,
+                deleted: true,
+            },
+            created_at: "2024-01-01T00:00:00Z".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        assert_eq!(config(&[]), Ok(None));
+        assert_eq!(config(&[("EVENT_PUBLISHER", "none")]), Ok(None));
+        assert!(config(&[("EVENT_PUBLISHER", "rabbitmq")]).is_err());
+
+        let nats = config(&[("EVENT_PUBLISHER", "nats")]).unwrap().unwrap();
+        assert_eq!(
+            nats.broker,
+            Broker::Nats {
+                url: "nats://localhost:4222".to_string(),
+                stream: "DOMAIN_EVENTS".to_string()
+            }
+        );
+        assert_eq!(nats.topics.topic("user.created"), "events.user.created");
+        assert_eq!(nats.topics.subjects(), vec!["events.>"]);
+
+        let kafka = config(&[
+            ("EVENT_PUBLISHER", "kafka"),
+            ("KAFKA_BROKERS", "kafka-1:9092,kafka-2:9092"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            kafka.broker,
+            Broker::Kafka {
+                brokers: "kafka-1:9092,kafka-2:9092".to_string()
+            }
+        );
+        assert_eq!(kafka.broker.name(), "kafka");
+
+        let custom = config(&[
+            ("EVENT_PUBLISHER", "nats"),
+            ("PUBLISHER_TOPIC_PREFIX", "app."),
+            ("PUBLISHER_TOPICS", "user.deleted = crm.users.deleted"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(custom.topics.topic("user.created"), "app.user.created");
+        assert_eq!(custom.topics.topic("user.deleted"), "crm.users.deleted");
+        assert_eq!(custom.topics.subjects(), vec!["app.>", "crm.users.deleted"]);
+
+        assert!(config(&[
+            ("EVENT_PUBLISHER", "nats"),
+            ("PUBLISHER_TOPICS", "user.deleted")
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_publishes_deletions_to_their_topic() {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let publisher = Arc::new(RecordingPublisher::default());
+        let dispatcher = Dispatcher::new(
+            Arc::new(EventRegistry::with_domain_events()),
+            publisher.clone(),
+        );
+
+        dispatcher.dispatch(&pool, &deletion()).await.unwrap();
+
+        let published = publisher.published.lock().unwrap();
+        assert_eq!(
+            schemas::<UserDeletedV1>(&published),
+            vec!["user.deleted@v1"]
+        );
+        assert_eq!(published[0].topic, "events.user.deleted");
+        assert_eq!(published[0].tenant_id, 2);
+        assert_eq!(published[0].message_id, "17:user.deleted@v1");
+        assert!(published[0].key.starts_with("2:"));
+        assert_eq!(published[0].envelope.occurred_at, deletion().created_at);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_fails_without_retrying_until_confirmed() {
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let flaky = Arc::new(RecordingPublisher {
+            failures: 1,
+            ..Default::default()
+        });
+        let dispatcher = Dispatcher::new(
+            Arc::new(EventRegistry::with_domain_events()),
+            flaky.clone(),
+        );
+
+        assert!(matches!(
+            dispatcher.dispatch(&pool, &deletion()).await,
+            Err(PublisherError::Broker(_))
+        ));
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 1);
+
+        // The retry is the same message, so brokers store it once
+        dispatcher.dispatch(&pool, &deletion()).await.unwrap();
+        let published = flaky.published.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].message_id, "17:user.deleted@v1");
+    }
+}
@@ -0,0 +1,104 @@
+//! NATS JetStream adapter for the publisher
+//!
+//! Events are published with the `Nats-Msg-Id` header set to the message
+//! id, so a retry of a publish whose confirmation was lost is stored once,
+//! and a `Tenant-Id` header naming the tenant of the user.
+
+use async_nats::jetstream::{self, context::Publish};
+use async_trait::async_trait;
+
+use super::{EventPublisher, Publication, PublisherError, Topics, TENANT_ID_HEADER};
+use crate::health::HealthCheck;
+
+/// Publisher to a JetStream stream
+pub struct NatsPublisher {
+    context: jetstream::Context,
+}
+
+/// Optional readiness check on the publisher's broker connection
+///
+/// Events are not needed to serve HTTP traffic, so a lost connection
+/// degrades the service rather than taking it out of rotation.
+pub struct NatsPublisherCheck {
+    client: async_nats::Client,
+}
+
+/// Connect to NATS at `url` and make sure `stream` takes `topics`
+///
+/// When the stream exists already its subjects are left as they are, and
+/// must cover the topics for publishes to be confirmed.
+pub async fn connect(
+    url: &str,
+    stream: &str,
+    topics: &Topics,
+) -> Result<(NatsPublisher, NatsPublisherCheck), PublisherError> {
+    let client = async_nats::connect(url)
+        .await
+        .map_err(broker_error)?;
+    let check = NatsPublisherCheck {
+        client: client.clone(),
+    };
+    let context = jetstream::new(client);
+
+    context
+        .get_or_create_stream(jetstream::stream::Config {
+            name: stream.to_string(),
+            subjects: topics.subjects(),
+            ..Default::default()
+        })
+        .await
+        .map_err(broker_error)?;
+
+    Ok((NatsPublisher { context }, check))
+}
+
+fn broker_error<E: std::fmt::Display>(e: E) -> PublisherError {
+    PublisherError::Broker(e.to_string())
+}
+
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    async fn publish(&self, publication: &Publication) -> Result<(), PublisherError> {
+        let payload = serde_json::to_vec(&publication.envelope).map_err(broker_error)?;
+        let ack = self
+            .context
+            .send_publish(
+                publication.topic.clone(),
+                Publish::build()
+                    .payload(payload.into())
+                    .message_id(&publication.message_id)
+                    .header(TENANT_ID_HEADER, publication.tenant_id.to_string().as_str()),
+            )
+            .await
+            .map_err(broker_error)?
+            .await
+            .map_err(broker_error)?;
+        if ack.duplicate {
+            tracing::debug!(
+                "{} was already stored as {} in {}",
+                publication.message_id,
+                ack.sequence,
+                ack.stream
+            );
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl HealthCheck for NatsPublisherCheck {
+    fn name(&self) -> &str {
+        "event_publisher"
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        match self.client.connection_state() {
+            async_nats::connection::State::Connected => Ok(()),
+            state => Err(format!("NATS connection is {}", state)),
+        }
+    }
+
+    fn required(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,307 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::models::api_key::{AdminApiKey, ApiKey};
+use crate::models::user::User;
+use crate::models::user_id::UserId;
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
+
+/// API key store
+#[async_trait::async_trait]
+pub trait ApiKeyRepositoryTrait {
+    async fn create(
+        &self,
+        user: &User,
+        name: &str,
+        prefix: &str,
+        key_hash: &[u8],
+        scopes: &[String],
+    ) -> Result<ApiKey, sqlx::Error>;
+    async fn list_for(&self, user: &User) -> Result<Vec<ApiKey>, sqlx::Error>;
+    async fn revoke(&self, user: &User, id: i64) -> Result<bool, sqlx::Error>;
+    async fn authenticate(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, sqlx::Error>;
+    async fn list_all(&self) -> Result<Vec<AdminApiKey>, sqlx::Error>;
+    async fn rotate(
+        &self,
+        id: i64,
+        prefix: &str,
+        key_hash: &[u8],
+        grace_period_secs: u32,
+    ) -> Result<Option<ApiKey>, sqlx::Error>;
+    async fn expire(&self, id: i64) -> Result<Option<ApiKey>, sqlx::Error>;
+    async fn record_use(&self, ids: &[i64], used_at: &[DateTime<Utc>]) -> Result<(), sqlx::Error>;
+}
+
+/// API key repository implementation with PostgreSQL
+///
+/// Keys are created in, and only authenticate for, the tenant that was
+/// current when the repository was built.
+pub struct ApiKeyRepository {
+    pool: PgPool,
+    tenant_id: i32,
+}
+
+impl ApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tenant_id: tenant::current(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ApiKeyRepositoryTrait for ApiKeyRepository {
+    async fn create(
+        &self,
+        user: &User,
+        name: &str,
+        prefix: &str,
+        key_hash: &[u8],
+        scopes: &[String],
+    ) -> Result<ApiKey, sqlx::Error> {
+        resilient_write("api_keys.create", move || async move {
+            sqlx::query_as!(
+                ApiKey,
+                r#"
+                INSERT INTO api_keys (name, prefix, key_hash, scopes, created_by, tenant_id)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING id, name, prefix, scopes, created_by, created_at, last_used_at, expires_at
+                "#,
+                name,
+                prefix,
+                key_hash,
+                scopes,
+                user.id,
+                self.tenant_id
+            )
+            .fetch_one(&self.pool)
+            .timed("api_keys.create")
+            .await
+        })
+        .await
+    }
+
+    /// The user's keys that still work, newest first
+    async fn list_for(&self, user: &User) -> Result<Vec<ApiKey>, sqlx::Error> {
+        resilient("api_keys.list_for", move || async move {
+            sqlx::query_as!(
+                ApiKey,
+                r#"
+                SELECT id, name, prefix, scopes, created_by, created_at, last_used_at, expires_at
+                FROM api_keys
+                WHERE created_by = $1 AND revoked_at IS NULL
+                    AND (expires_at IS NULL OR expires_at > NOW())
+                ORDER BY id DESC
+                "#,
+                user.id
+            )
+            .fetch_all(&self.pool)
+            .timed("api_keys.list_for")
+            .await
+        })
+        .await
+    }
+
+    /// Revoke one of the user's keys; false if they have no such key
+    async fn revoke(&self, user: &User, id: i64) -> Result<bool, sqlx::Error> {
+        resilient_write("api_keys.revoke", move || async move {
+            let result = sqlx::query!(
+                r#"
+                UPDATE api_keys SET revoked_at = NOW()
+                WHERE id = $1 AND created_by = $2 AND revoked_at IS NULL
+                "#,
+                id,
+                user.id
+            )
+            .execute(&self.pool)
+            .timed("api_keys.revoke")
+            .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// The key with this hash unless it is revoked, expired or of another
+    /// tenant
+    ///
+    /// Does not mark the key used; that is up to [`crate::auth::api_key::ApiKeyUsage`].
+    async fn authenticate(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, sqlx::Error> {
+        resilient("api_keys.authenticate", move || async move {
+            sqlx::query_as!(
+                ApiKey,
+                r#"
+                SELECT id, name, prefix, scopes, created_by, created_at, last_used_at, expires_at
+                FROM api_keys
+                WHERE key_hash = $1 AND tenant_id = $2 AND revoked_at IS NULL
+                    AND (expires_at IS NULL OR expires_at > NOW())
+                "#,
+                key_hash,
+                self.tenant_id
+            )
+            .fetch_optional(&self.pool)
+            .timed("api_keys.authenticate")
+            .await
+        })
+        .await
+    }
+
+    /// Every key of the tenant, revoked and expired ones included, newest
+    /// first
+    async fn list_all(&self) -> Result<Vec<AdminApiKey>, sqlx::Error> {
+        resilient("api_keys.list_all", move || async move {
+            let rows = sqlx::query!(
+                r#"
+                SELECT k.id, k.name, k.prefix, k.scopes, k.created_by, k.created_at,
+                    k.last_used_at, k.expires_at, k.revoked_at, k.replaced_by,
+                    u.public_id AS "creator_public_id?"
+                FROM api_keys k
+                LEFT JOIN test_users u ON u.id = k.created_by
+                WHERE k.tenant_id = $1
+                ORDER BY k.id DESC
+                "#,
+                self.tenant_id
+            )
+            .fetch_all(&self.pool)
+            .timed("api_keys.list_all")
+            .await?;
+            Ok(rows
+                .into_iter()
+                .map(|row| {
+                    #[cfg(not(feature = "uuid-ids"))]
+                    let creator = row.created_by.map(UserId::new);
+                    #[cfg(feature = "uuid-ids")]
+                    let creator = row.creator_public_id.map(UserId::new);
+                    AdminApiKey {
+                        api_key: ApiKey {
+                            id: row.id,
+                            name: row.name,
+                            prefix: row.prefix,
+                            scopes: row.scopes,
+                            created_by: row.created_by,
+                            created_at: row.created_at,
+                            last_used_at: row.last_used_at,
+                            expires_at: row.expires_at,
+                        },
+                        creator,
+                        revoked_at: row.revoked_at,
+                        replaced_by: row.replaced_by,
+                    }
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Replace a key with a new one of the same name, scopes and creator
+    ///
+    /// The old key keeps working for `grace_period_secs`, or until it
+    /// expires anyway if that is sooner. None if the tenant has no working
+    /// key with this id that was not rotated already.
+    async fn rotate(
+        &self,
+        id: i64,
+        prefix: &str,
+        key_hash: &[u8],
+        grace_period_secs: u32,
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        resilient_write("api_keys.rotate", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let Some(old) = sqlx::query!(
+                r#"
+                SELECT name, scopes, created_by FROM api_keys
+                WHERE id = $1 AND tenant_id = $2 AND revoked_at IS NULL
+                    AND replaced_by IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+                FOR UPDATE
+                "#,
+                id,
+                self.tenant_id
+            )
+            .fetch_optional(&mut *tx)
+            .timed("api_keys.rotate")
+            .await?
+            else {
+                return Ok(None);
+            };
+            let new = sqlx::query_as!(
+                ApiKey,
+                r#"
+                INSERT INTO api_keys (name, prefix, key_hash, scopes, created_by, tenant_id)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING id, name, prefix, scopes, created_by, created_at, last_used_at, expires_at
+                "#,
+                old.name,
+                prefix,
+                key_hash,
+                &old.scopes,
+                old.created_by,
+                self.tenant_id
+            )
+            .fetch_one(&mut *tx)
+            .timed("api_keys.rotate")
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE api_keys
+                SET replaced_by = $2,
+                    expires_at = LEAST(expires_at, NOW() + make_interval(secs => $3))
+                WHERE id = $1
+                "#,
+                id,
+                new.id,
+                f64::from(grace_period_secs)
+            )
+            .execute(&mut *tx)
+            .timed("api_keys.rotate")
+            .await?;
+            tx.commit().await?;
+            Ok(Some(new))
+        })
+        .await
+    }
+
+    /// Make a working key of the tenant stop working now; None if there
+    /// is none with this id
+    async fn expire(&self, id: i64) -> Result<Option<ApiKey>, sqlx::Error> {
+        resilient_write("api_keys.expire", move || async move {
+            sqlx::query_as!(
+                ApiKey,
+                r#"
+                UPDATE api_keys SET expires_at = NOW()
+                WHERE id = $1 AND tenant_id = $2 AND revoked_at IS NULL
+                    AND (expires_at IS NULL OR expires_at > NOW())
+                RETURNING id, name, prefix, scopes, created_by, created_at, last_used_at, expires_at
+                "#,
+                id,
+                self.tenant_id
+            )
+            .fetch_optional(&self.pool)
+            .timed("api_keys.expire")
+            .await
+        })
+        .await
+    }
+
+    /// Store when keys were last used, `used_at[i]` for `ids[i]`, of any
+    /// tenant; times older than the stored ones are ignored
+    async fn record_use(&self, ids: &[i64], used_at: &[DateTime<Utc>]) -> Result<(), sqlx::Error> {
+        resilient("api_keys.record_use", move || async move {
+            sqlx::query!(
+                r#"
+                UPDATE api_keys k SET last_used_at = used.at
+                FROM UNNEST($1::BIGINT[], $2::TIMESTAMPTZ[]) AS used (id, at)
+                WHERE k.id = used.id AND (k.last_used_at IS NULL OR k.last_used_at < used.at)
+                "#,
+                ids,
+                used_at
+            )
+            .execute(&self.pool)
+            .timed("api_keys.record_use")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::models::audit::AuditRecord;
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
+
+/// Event to store, see [`crate::audit::AuditEvent`]
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry {
+    pub actor: Option<String>,
+    pub impersonated_by: Option<String>,
+    pub entity: &'static str,
+    pub entity_id: String,
+    pub action: &'static str,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub changes: Value,
+    pub request_id: Option<String>,
+    pub ip: Option<String>,
+}
+
+/// Filters of an audit log listing; None matches anything
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub entity: Option<String>,
+    pub entity_id: Option<String>,
+    pub action: Option<&'static str>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only entries older than this one
+    pub before_id: Option<i64>,
+}
+
+/// Audit log store
+#[async_trait::async_trait]
+pub trait AuditRepositoryTrait {
+    async fn insert(&self, entry: NewAuditEntry) -> Result<(), sqlx::Error>;
+    /// Store the entries with one statement
+    async fn insert_many(&self, entries: Vec<NewAuditEntry>) -> Result<(), sqlx::Error>;
+    async fn list(&self, filter: &AuditFilter, limit: i64)
+        -> Result<Vec<AuditRecord>, sqlx::Error>;
+}
+
+/// Audit repository implementation with PostgreSQL, scoped to the tenant
+/// current when it was built
+pub struct AuditRepository {
+    pool: PgPool,
+    tenant_id: i32,
+}
+
+impl AuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tenant_id: tenant::current(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditRepositoryTrait for AuditRepository {
+    async fn insert(&self, entry: NewAuditEntry) -> Result<(), sqlx::Error> {
+        let entry = &entry;
+        resilient_write("audit.insert", move || async move {
+            sqlx::query!(
+                r#"
+                INSERT INTO audit_log (actor, entity, entity_id, action, before, after, changes, request_id, ip, tenant_id, impersonated_by)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                "#,
+                entry.actor,
+                entry.entity,
+                entry.entity_id,
+                entry.action,
+                entry.before,
+                entry.after,
+                entry.changes,
+                entry.request_id,
+                entry.ip,
+                self.tenant_id,
+                entry.impersonated_by
+            )
+            .execute(&self.pool)
+            .timed("audit.insert")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_many(&self, entries: Vec<NewAuditEntry>) -> Result<(), sqlx::Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let column = |field: fn(&NewAuditEntry) -> Option<String>| -> Vec<Option<String>> {
+            entries.iter().map(field).collect()
+        };
+        let (actors, impersonated_by, request_ids, ips) = (
+            column(|entry| entry.actor.clone()),
+            column(|entry| entry.impersonated_by.clone()),
+            column(|entry| entry.request_id.clone()),
+            column(|entry| entry.ip.clone()),
+        );
+        let entities: Vec<&str> = entries.iter().map(|entry| entry.entity).collect();
+        let entity_ids: Vec<&str> = entries.iter().map(|entry| entry.entity_id.as_str()).collect();
+        let actions: Vec<&str> = entries.iter().map(|entry| entry.action).collect();
+        let befores: Vec<Option<Value>> = entries.iter().map(|entry| entry.before.clone()).collect();
+        let afters: Vec<Option<Value>> = entries.iter().map(|entry| entry.after.clone()).collect();
+        let changes: Vec<Value> = entries.iter().map(|entry| entry.changes.clone()).collect();
+
+        let (actors, entities, entity_ids, actions) = (&actors, &entities, &entity_ids, &actions);
+        let (befores, afters, changes) = (&befores, &afters, &changes);
+        let (request_ids, ips, impersonated_by) = (&request_ids, &ips, &impersonated_by);
+        resilient_write("audit.insert_many", move || async move {
+            sqlx::query!(
+                r#"
+                INSERT INTO audit_log (actor, entity, entity_id, action, before, after, changes, request_id, ip, tenant_id, impersonated_by)
+                SELECT actor, entity, entity_id, action, before, after, changes, request_id, ip, $10, impersonated_by
+                FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::jsonb[], $6::jsonb[], $7::jsonb[], $8::text[], $9::text[], $11::text[])
+                    AS entry(actor, entity, entity_id, action, before, after, changes, request_id, ip, impersonated_by)
+                "#,
+                actors as _,
+                entities as _,
+                entity_ids as _,
+                actions as _,
+                befores as _,
+                afters as _,
+                changes as _,
+                request_ids as _,
+                ips as _,
+                self.tenant_id,
+                impersonated_by as _
+            )
+            .execute(&self.pool)
+            .timed("audit.insert_many")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Matching entries, newest first
+    async fn list(
+        &self,
+        filter: &AuditFilter,
+        limit: i64,
+    ) -> Result<Vec<AuditRecord>, sqlx::Error> {
+        resilient("audit.list", move || async move {
+            sqlx::query_as!(
+                AuditRecord,
+                r#"
+                SELECT id, occurred_at, actor, impersonated_by, entity, entity_id, action, before, after, changes, request_id, ip
+                FROM audit_log
+                WHERE tenant_id = $9
+                  AND ($1::text IS NULL OR actor = $1)
+                  AND ($2::text IS NULL OR entity = $2)
+                  AND ($3::text IS NULL OR entity_id = $3)
+                  AND ($4::text IS NULL OR action = $4)
+                  AND ($5::timestamptz IS NULL OR occurred_at >= $5)
+                  AND ($6::timestamptz IS NULL OR occurred_at < $6)
+                  AND ($7::bigint IS NULL OR id < $7)
+                ORDER BY id DESC
+                LIMIT $8
+                "#,
+                filter.actor,
+                filter.entity,
+                filter.entity_id,
+                filter.action,
+                filter.since,
+                filter.until,
+                filter.before_id,
+                limit,
+                self.tenant_id
+            )
+            .fetch_all(&self.pool)
+            .timed("audit.list")
+            .await
+        })
+        .await
+    }
+}
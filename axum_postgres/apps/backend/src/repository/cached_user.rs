@@ -0,0 +1,303 @@
+//! Read-through cache in front of a [`UserRepositoryTrait`]
+//!
+//! Caches users by id and the first page of the unfiltered listing, per
+//! tenant. Writes through [`CachedUserRepository`] drop the entries they
+//! make stale; [`invalidate_on_changes`] drops them for every other write,
+//! whichever replica or tool made it. Entries expire after the cache TTL
+//! either way, which bounds how stale a read can be when the change feed
+//! is off or a notification was lost.
+
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use metrics::counter;
+use tokio::sync::{broadcast, watch};
+
+use crate::cache::{Cache, CacheExt, TieredCache, CACHE_HITS_TOTAL, CACHE_MISSES_TOTAL};
+use crate::changes::{ChangeEvent, ChangeFeed};
+use crate::models::user::{CreateUserRequest, UpdateUserRequest, User, UserListQuery};
+use crate::models::user_history::UserHistoryRecord;
+use crate::models::user_id::UserId;
+use crate::pagination::Cursor;
+use crate::repository::user::UserRepositoryTrait;
+use crate::tenant;
+
+/// Cache key of the user `id` of `tenant_id`
+pub fn user_key(tenant_id: i32, id: UserId) -> String {
+    format!("users:{}:{}", tenant_id, id)
+}
+
+/// Cache key of the hash holding the first pages of `tenant_id`
+pub fn list_key(tenant_id: i32) -> String {
+    format!("users:{}:list", tenant_id)
+}
+
+/// Whether `query` lists every live user in the default order
+fn is_unfiltered(query: &UserListQuery) -> bool {
+    query.active.is_none()
+        && query.email_contains.is_none()
+        && query.name_contains.is_none()
+        && !query.include_deleted
+        && query.is_default_order()
+}
+
+/// [`UserRepositoryTrait`] caching reads of `R` in a [`TieredCache`]
+///
+/// Scoped to the tenant that was current when it was built, like
+/// [`crate::repository::user::UserRepository`]. Without any tier every call
+/// goes straight to `R`.
+pub struct CachedUserRepository<R> {
+    inner: R,
+    cache: TieredCache,
+    tenant_id: i32,
+}
+
+impl<R: UserRepositoryTrait + Sync> CachedUserRepository<R> {
+    pub fn new(inner: R, cache: TieredCache) -> Self {
+        Self {
+            inner,
+            cache,
+            tenant_id: tenant::current(),
+        }
+    }
+
+    fn ttl(&self) -> Duration {
+        self.cache.ttl()
+    }
+
+    /// `field` of the list hash, or the page `load`ed from `R`
+    async fn cached_page<F>(&self, field: String, load: F) -> Result<Vec<User>, sqlx::Error>
+    where
+        F: std::future::Future<Output = Result<Vec<User>, sqlx::Error>>,
+    {
+        let key = list_key(self.tenant_id);
+        if let Some(users) = self.cache.get_field_json(&key, &field).await {
+            counter!(CACHE_HITS_TOTAL, 1, "entry" => "user_list");
+            return Ok(users);
+        }
+        counter!(CACHE_MISSES_TOTAL, 1, "entry" => "user_list");
+        let users = load.await?;
+        self.cache
+            .set_field_json(&key, &field, &users, self.ttl())
+            .await;
+        Ok(users)
+    }
+
+    /// Drop the listings, and the user `id` when given
+    async fn invalidate(&self, id: Option<UserId>) {
+        if !self.cache.is_enabled() {
+            return;
+        }
+        if let Some(id) = id {
+            self.cache.delete(&user_key(self.tenant_id, id)).await;
+        }
+        self.cache.delete(&list_key(self.tenant_id)).await;
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: UserRepositoryTrait + Send + Sync> UserRepositoryTrait for CachedUserRepository<R> {
+    async fn create_user(&self, user: CreateUserRequest) -> Result<User, sqlx::Error> {
+        let user = self.inner.create_user(user).await?;
+        self.invalidate(None).await;
+        Ok(user)
+    }
+
+    async fn import_users(&self, users: Vec<CreateUserRequest>) -> Result<Vec<User>, sqlx::Error> {
+        let users = self.inner.import_users(users).await?;
+        self.invalidate(None).await;
+        Ok(users)
+    }
+
+    /// Read through the cache; only found users are cached
+    async fn get_user_by_id(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        if !self.cache.is_enabled() {
+            return self.inner.get_user_by_id(id).await;
+        }
+        let key = user_key(self.tenant_id, id);
+        if let Some(user) = self.cache.get_json(&key).await {
+            counter!(CACHE_HITS_TOTAL, 1, "entry" => "user");
+            return Ok(Some(user));
+        }
+        counter!(CACHE_MISSES_TOTAL, 1, "entry" => "user");
+        let user = self.inner.get_user_by_id(id).await?;
+        if let Some(user) = &user {
+            self.cache.set_json(&key, user, self.ttl()).await;
+        }
+        Ok(user)
+    }
+
+    async fn get_user_by_key(&self, key: i32) -> Result<Option<User>, sqlx::Error> {
+        self.inner.get_user_by_key(key).await
+    }
+
+    async fn exists(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        self.inner.exists(id).await
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, sqlx::Error> {
+        self.inner.find_user_by_email(email).await
+    }
+
+    async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error> {
+        self.inner.email_exists(email).await
+    }
+
+    async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        self.inner.list_users().await
+    }
+
+    /// Read through the cache for the first page of the unfiltered listing
+    async fn list_users_page(
+        &self,
+        query: &UserListQuery,
+        offset: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        match limit {
+            Some(limit) if offset == 0 && is_unfiltered(query) && self.cache.is_enabled() => {
+                let load = self.inner.list_users_page(query, 0, Some(limit));
+                self.cached_page(format!("page:{}", limit), load).await
+            }
+            _ => self.inner.list_users_page(query, offset, limit).await,
+        }
+    }
+
+    /// Read through the cache for the first keyset page of the unfiltered
+    /// listing
+    async fn list_users_after(
+        &self,
+        query: &UserListQuery,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        if after.is_none() && is_unfiltered(query) && self.cache.is_enabled() {
+            let load = self.inner.list_users_after(query, None, limit);
+            return self.cached_page(format!("after:{}", limit), load).await;
+        }
+        self.inner.list_users_after(query, after, limit).await
+    }
+
+    async fn count_users(&self, query: &UserListQuery) -> Result<i64, sqlx::Error> {
+        self.inner.count_users(query).await
+    }
+
+    async fn search_users(&self, terms: &str, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+        self.inner.search_users(terms, limit).await
+    }
+
+    async fn users_version(&self) -> Result<i64, sqlx::Error> {
+        self.inner.users_version().await
+    }
+
+    async fn update_user(
+        &self,
+        id: UserId,
+        user: UpdateUserRequest,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let user = self.inner.update_user(id, user).await?;
+        self.invalidate(Some(id)).await;
+        Ok(user)
+    }
+
+    async fn set_user_active(&self, id: UserId, active: bool) -> Result<Option<User>, sqlx::Error> {
+        let user = self.inner.set_user_active(id, active).await?;
+        self.invalidate(Some(id)).await;
+        Ok(user)
+    }
+
+    async fn delete_user(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        let deleted = self.inner.delete_user(id).await?;
+        self.invalidate(Some(id)).await;
+        Ok(deleted)
+    }
+
+    async fn purge_user(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        let purged = self.inner.purge_user(id).await?;
+        self.invalidate(Some(id)).await;
+        Ok(purged)
+    }
+
+    async fn restore_user(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        let user = self.inner.restore_user(id).await?;
+        self.invalidate(Some(id)).await;
+        Ok(user)
+    }
+
+    async fn get_user_including_deleted(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        self.inner.get_user_including_deleted(id).await
+    }
+
+    async fn get_user_history(&self, id: UserId) -> Result<Vec<UserHistoryRecord>, sqlx::Error> {
+        self.inner.get_user_history(id).await
+    }
+
+    fn stream_users<'a>(
+        &'a self,
+        query: &UserListQuery,
+    ) -> BoxStream<'a, Result<User, sqlx::Error>> {
+        self.inner.stream_users(query)
+    }
+}
+
+/// Drop cached users as the change feed reports them changed, until
+/// shutdown; does nothing without a cache
+///
+/// Catches the writes that bypass [`CachedUserRepository`]: other
+/// replicas, other handlers and SQL run by hand.
+pub fn invalidate_on_changes(
+    cache: TieredCache,
+    changes: &ChangeFeed,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    if !cache.is_enabled() {
+        return;
+    }
+    let mut events = changes.subscribe();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(ChangeEvent::User(change)) => {
+                        cache.delete(&user_key(change.tenant_id, change.id)).await;
+                        cache.delete(&list_key(change.tenant_id)).await;
+                    }
+                    // Which entries went stale is unknown; they expire with the TTL
+                    Ok(ChangeEvent::Resync) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        tracing::warn!("Missed user changes; cached users may be stale for up to {:?}", cache.ttl());
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = shutdown.changed() => return,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::user::{SortOrder, UserSortField};
+
+    #[test]
+    fn test_only_unfiltered_listings_are_cached() {
+        let query = |build: fn(&mut UserListQuery)| {
+            let mut query = UserListQuery::default();
+            build(&mut query);
+            is_unfiltered(&query)
+        };
+        assert!(query(|_| {}));
+        assert!(query(|q| q.sort_by = Some(UserSortField::CreatedAt)));
+        assert!(!query(|q| q.active = Some(true)));
+        assert!(!query(|q| q.name_contains = Some("jane".to_string())));
+        assert!(!query(|q| q.include_deleted = true));
+        assert!(!query(|q| q.order = Some(SortOrder::Asc)));
+    }
+
+    #[test]
+    fn test_keys_are_scoped_to_the_tenant() {
+        let id = UserId::new(Default::default());
+        assert_ne!(user_key(1, id), user_key(2, id));
+        assert_eq!(list_key(2), "users:2:list");
+    }
+}
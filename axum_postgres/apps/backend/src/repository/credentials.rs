@@ -0,0 +1,168 @@
+use sqlx::PgPool;
+
+use crate::models::user::{CreateUserRequest, User};
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
+
+/// Password storage and email verification for users who sign in through
+/// `/api/auth`
+#[async_trait::async_trait]
+pub trait CredentialRepositoryTrait {
+    async fn register(
+        &self,
+        user: CreateUserRequest,
+        password_hash: &str,
+    ) -> Result<User, sqlx::Error>;
+    async fn password_hash(&self, user: &User) -> Result<Option<String>, sqlx::Error>;
+    async fn set_password_hash(&self, user: &User, password_hash: &str) -> Result<(), sqlx::Error>;
+    async fn clear_password_hash(&self, user: &User) -> Result<bool, sqlx::Error>;
+    async fn mark_email_verified(
+        &self,
+        user: &User,
+        email: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+}
+
+/// Credential repository implementation with PostgreSQL; users register
+/// in the tenant current when it was built
+pub struct CredentialRepository {
+    pool: PgPool,
+    tenant_id: i32,
+}
+
+impl CredentialRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tenant_id: tenant::current(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CredentialRepositoryTrait for CredentialRepository {
+    /// Create a user together with their password, in one transaction
+    async fn register(
+        &self,
+        user: CreateUserRequest,
+        password_hash: &str,
+    ) -> Result<User, sqlx::Error> {
+        let user = &user;
+        resilient_write("credentials.register", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let user = sqlx::query_as!(
+                User,
+                r#"
+                INSERT INTO test_users (name, email, display_name, bio, phone, timezone, locale, tenant_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                user.name,
+                user.email,
+                user.display_name,
+                user.bio,
+                user.phone,
+                user.timezone,
+                user.locale,
+                self.tenant_id
+            )
+            .fetch_one(&mut *tx)
+            .timed("credentials.register")
+            .await?;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO user_credentials (user_id, password_hash)
+                VALUES ($1, $2)
+                "#,
+                user.id,
+                password_hash
+            )
+            .execute(&mut *tx)
+            .timed("credentials.register")
+            .await?;
+
+            tx.commit().await?;
+            Ok(user)
+        })
+        .await
+    }
+
+    /// Stored password hash of a user; None when they have no password
+    async fn password_hash(&self, user: &User) -> Result<Option<String>, sqlx::Error> {
+        resilient("credentials.password_hash", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                SELECT password_hash FROM user_credentials WHERE user_id = $1
+                "#,
+                user.id
+            )
+            .fetch_optional(&self.pool)
+            .timed("credentials.password_hash")
+            .await
+        })
+        .await
+    }
+
+    /// Store a new password hash, replacing any previous one
+    async fn set_password_hash(&self, user: &User, password_hash: &str) -> Result<(), sqlx::Error> {
+        resilient("credentials.set_password_hash", move || async move {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_credentials (user_id, password_hash)
+                VALUES ($1, $2)
+                ON CONFLICT (user_id) DO UPDATE
+                SET password_hash = EXCLUDED.password_hash, updated_at = NOW()
+                "#,
+                user.id,
+                password_hash
+            )
+            .execute(&self.pool)
+            .timed("credentials.set_password_hash")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Remove the user's password, so they cannot sign in with it any more;
+    /// false when they had none
+    async fn clear_password_hash(&self, user: &User) -> Result<bool, sqlx::Error> {
+        resilient("credentials.clear_password_hash", move || async move {
+            let result = sqlx::query!("DELETE FROM user_credentials WHERE user_id = $1", user.id)
+                .execute(&self.pool)
+                .timed("credentials.clear_password_hash")
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Mark the user's email verified, if it is still `email`
+    ///
+    /// None when the user is deleted or their address has changed since.
+    async fn mark_email_verified(
+        &self,
+        user: &User,
+        email: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        resilient("credentials.mark_email_verified", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                UPDATE test_users
+                SET email_verified = TRUE
+                WHERE id = $1 AND LOWER(email) = LOWER($2) AND deleted_at IS NULL
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                user.id,
+                email
+            )
+            .fetch_optional(&self.pool)
+            .timed("credentials.mark_email_verified")
+            .await
+        })
+        .await
+    }
+}
@@ -0,0 +1,49 @@
+use sqlx::PgPool;
+
+use crate::repository::resilience::resilient;
+use crate::repository::timing::Timed;
+
+/// Housekeeping across tables, for scheduled jobs
+#[async_trait::async_trait]
+pub trait MaintenanceRepositoryTrait {
+    async fn purge_expired(&self) -> Result<u64, sqlx::Error>;
+}
+
+/// Maintenance repository implementation with PostgreSQL
+pub struct MaintenanceRepository {
+    pool: PgPool,
+}
+
+impl MaintenanceRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl MaintenanceRepositoryTrait for MaintenanceRepository {
+    /// Delete expired sessions, refresh tokens, password reset links and
+    /// OAuth sign-ins, returning how many rows went
+    ///
+    /// None of them can be used once expired. Writes to those tables drop
+    /// expired rows as they go too, but only of the table they write to.
+    async fn purge_expired(&self) -> Result<u64, sqlx::Error> {
+        resilient("maintenance.purge_expired", move || async move {
+            let mut deleted = 0;
+            for statement in [
+                sqlx::query!("DELETE FROM sessions WHERE expires_at <= NOW()"),
+                sqlx::query!("DELETE FROM refresh_tokens WHERE expires_at <= NOW()"),
+                sqlx::query!("DELETE FROM password_resets WHERE expires_at <= NOW()"),
+                sqlx::query!("DELETE FROM oauth_states WHERE expires_at <= NOW()"),
+            ] {
+                deleted += statement
+                    .execute(&self.pool)
+                    .timed("maintenance.purge_expired")
+                    .await?
+                    .rows_affected();
+            }
+            Ok(deleted)
+        })
+        .await
+    }
+}
@@ -1 +1,17 @@
-pub mod user;
\ No newline at end of file
+pub mod api_keys;
+pub mod audit;
+pub mod cached_user;
+pub mod credentials;
+pub mod maintenance;
+pub mod oauth;
+pub mod password_resets;
+pub mod refresh_tokens;
+pub mod resilience;
+pub mod roles;
+pub mod sessions;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod tenants;
+pub mod timing;
+pub mod user;
+pub mod user_imports;
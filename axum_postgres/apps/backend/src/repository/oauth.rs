@@ -0,0 +1,319 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::auth::oauth::Provider;
+use crate::models::user::{CreateUserRequest, User};
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
+
+/// Started sign-ins and provider accounts linked to users
+#[async_trait::async_trait]
+pub trait OAuthRepositoryTrait {
+    async fn save_state(
+        &self,
+        provider: Provider,
+        state_hash: &[u8],
+        code_verifier: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+    async fn take_state(
+        &self,
+        provider: Provider,
+        state_hash: &[u8],
+    ) -> Result<Option<String>, sqlx::Error>;
+    async fn find_identity(
+        &self,
+        provider: Provider,
+        subject: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+    async fn link(
+        &self,
+        provider: Provider,
+        subject: &str,
+        email: &str,
+        user: &User,
+    ) -> Result<(), sqlx::Error>;
+    async fn take_over(
+        &self,
+        provider: Provider,
+        subject: &str,
+        email: &str,
+        user: &User,
+    ) -> Result<Option<User>, sqlx::Error>;
+    async fn provision(
+        &self,
+        provider: Provider,
+        subject: &str,
+        user: CreateUserRequest,
+    ) -> Result<User, sqlx::Error>;
+}
+
+/// OAuth repository implementation with PostgreSQL; users are found and
+/// provisioned in the tenant current when it was built
+pub struct OAuthRepository {
+    pool: PgPool,
+    tenant_id: i32,
+}
+
+impl OAuthRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tenant_id: tenant::current(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthRepositoryTrait for OAuthRepository {
+    /// Remember a started sign-in, dropping any that expired unused
+    async fn save_state(
+        &self,
+        provider: Provider,
+        state_hash: &[u8],
+        code_verifier: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        resilient_write("oauth.save_state", move || async move {
+            sqlx::query!("DELETE FROM oauth_states WHERE expires_at <= NOW()")
+                .execute(&self.pool)
+                .timed("oauth.save_state")
+                .await?;
+            sqlx::query!(
+                r#"
+                INSERT INTO oauth_states (state_hash, provider, code_verifier, expires_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                state_hash,
+                provider.as_str(),
+                code_verifier,
+                expires_at
+            )
+            .execute(&self.pool)
+            .timed("oauth.save_state")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Use up a started sign-in, returning its PKCE code verifier
+    ///
+    /// None when the state is unknown, expired, already used or was started
+    /// for another provider.
+    async fn take_state(
+        &self,
+        provider: Provider,
+        state_hash: &[u8],
+    ) -> Result<Option<String>, sqlx::Error> {
+        resilient_write("oauth.take_state", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                DELETE FROM oauth_states
+                WHERE state_hash = $1 AND provider = $2 AND expires_at > NOW()
+                RETURNING code_verifier
+                "#,
+                state_hash,
+                provider.as_str()
+            )
+            .fetch_optional(&self.pool)
+            .timed("oauth.take_state")
+            .await
+        })
+        .await
+    }
+
+    /// Live user linked to a provider account
+    async fn find_identity(
+        &self,
+        provider: Provider,
+        subject: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        resilient("oauth.find_identity", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT u.id, u.public_id, u.name, u.email, u.active, u.display_name, u.bio, u.phone, u.timezone, u.locale, u.email_verified, u.created_at, u.updated_at, u.deleted_at
+                FROM user_identities i
+                JOIN test_users u ON u.id = i.user_id
+                WHERE i.provider = $1 AND i.subject = $2 AND u.tenant_id = $3 AND u.deleted_at IS NULL
+                "#,
+                provider.as_str(),
+                subject,
+                self.tenant_id
+            )
+            .fetch_optional(&self.pool)
+            .timed("oauth.find_identity")
+            .await
+        })
+        .await
+    }
+
+    /// Link a provider account to a user, moving it if it was linked before
+    async fn link(
+        &self,
+        provider: Provider,
+        subject: &str,
+        email: &str,
+        user: &User,
+    ) -> Result<(), sqlx::Error> {
+        resilient_write("oauth.link", move || async move {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_identities (provider, subject, user_id, email)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (provider, subject) DO UPDATE
+                SET user_id = EXCLUDED.user_id, email = EXCLUDED.email
+                "#,
+                provider.as_str(),
+                subject,
+                user.id,
+                email
+            )
+            .execute(&self.pool)
+            .timed("oauth.link")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Link a provider account to a user who never verified their email,
+    /// taking the account from whoever registered it, in one transaction
+    ///
+    /// Anyone could have registered the address, so the user's password,
+    /// API keys, sessions and refresh tokens stop working, and the provider
+    /// vouches for the address instead. None when the user is deleted or
+    /// their address has changed since.
+    async fn take_over(
+        &self,
+        provider: Provider,
+        subject: &str,
+        email: &str,
+        user: &User,
+    ) -> Result<Option<User>, sqlx::Error> {
+        resilient_write("oauth.take_over", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let Some(user) = sqlx::query_as!(
+                User,
+                r#"
+                UPDATE test_users
+                SET email_verified = TRUE
+                WHERE id = $1 AND LOWER(email) = LOWER($2) AND deleted_at IS NULL
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                user.id,
+                email
+            )
+            .fetch_optional(&mut *tx)
+            .timed("oauth.take_over")
+            .await?
+            else {
+                return Ok(None);
+            };
+
+            sqlx::query!("DELETE FROM user_credentials WHERE user_id = $1", user.id)
+                .execute(&mut *tx)
+                .timed("oauth.take_over")
+                .await?;
+            sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user.id)
+                .execute(&mut *tx)
+                .timed("oauth.take_over")
+                .await?;
+            sqlx::query!(
+                r#"
+                UPDATE refresh_tokens SET revoked_at = NOW()
+                WHERE user_id = $1 AND revoked_at IS NULL
+                "#,
+                user.id
+            )
+            .execute(&mut *tx)
+            .timed("oauth.take_over")
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE api_keys SET revoked_at = NOW()
+                WHERE created_by = $1 AND revoked_at IS NULL
+                "#,
+                user.id
+            )
+            .execute(&mut *tx)
+            .timed("oauth.take_over")
+            .await?;
+            sqlx::query!(
+                r#"
+                INSERT INTO user_identities (provider, subject, user_id, email)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (provider, subject) DO UPDATE
+                SET user_id = EXCLUDED.user_id, email = EXCLUDED.email
+                "#,
+                provider.as_str(),
+                subject,
+                user.id,
+                email
+            )
+            .execute(&mut *tx)
+            .timed("oauth.take_over")
+            .await?;
+
+            tx.commit().await?;
+            Ok(Some(user))
+        })
+        .await
+    }
+
+    /// Create a user for a provider account and link them, in one transaction
+    ///
+    /// The provider vouched for the email, so the user starts verified.
+    async fn provision(
+        &self,
+        provider: Provider,
+        subject: &str,
+        user: CreateUserRequest,
+    ) -> Result<User, sqlx::Error> {
+        let user = &user;
+        resilient_write("oauth.provision", move || async move {
+            let mut tx = self.pool.begin().await?;
+            let user = sqlx::query_as!(
+                User,
+                r#"
+                INSERT INTO test_users (name, email, display_name, bio, phone, timezone, locale, email_verified, tenant_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, TRUE, $8)
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                user.name,
+                user.email,
+                user.display_name,
+                user.bio,
+                user.phone,
+                user.timezone,
+                user.locale,
+                self.tenant_id
+            )
+            .fetch_one(&mut *tx)
+            .timed("oauth.provision")
+            .await?;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO user_identities (provider, subject, user_id, email)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (provider, subject) DO UPDATE
+                SET user_id = EXCLUDED.user_id, email = EXCLUDED.email
+                "#,
+                provider.as_str(),
+                subject,
+                user.id,
+                user.email
+            )
+            .execute(&mut *tx)
+            .timed("oauth.provision")
+            .await?;
+
+            tx.commit().await?;
+            Ok(user)
+        })
+        .await
+    }
+}
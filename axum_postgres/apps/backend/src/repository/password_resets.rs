@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::models::user::User;
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+
+/// Password reset tokens and the forgot-password requests that asked for them
+#[async_trait::async_trait]
+pub trait PasswordResetRepositoryTrait {
+    async fn record_request(
+        &self,
+        email_hash: &[u8],
+        window_start: DateTime<Utc>,
+    ) -> Result<i64, sqlx::Error>;
+    async fn create(
+        &self,
+        user: &User,
+        token_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+    async fn take(&self, token_hash: &[u8]) -> Result<Option<i32>, sqlx::Error>;
+    async fn delete_all(&self, user: &User) -> Result<u64, sqlx::Error>;
+}
+
+/// Password reset repository implementation with PostgreSQL
+pub struct PasswordResetRepository {
+    pool: PgPool,
+}
+
+impl PasswordResetRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PasswordResetRepositoryTrait for PasswordResetRepository {
+    /// Note a request for an address and count its requests since
+    /// `window_start`, this one included; drops requests older than that
+    async fn record_request(
+        &self,
+        email_hash: &[u8],
+        window_start: DateTime<Utc>,
+    ) -> Result<i64, sqlx::Error> {
+        resilient_write("password_resets.record_request", move || async move {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query!(
+                "DELETE FROM password_reset_requests WHERE requested_at <= $1",
+                window_start
+            )
+            .execute(&mut *tx)
+            .timed("password_resets.record_request")
+            .await?;
+            sqlx::query!(
+                "INSERT INTO password_reset_requests (email_hash) VALUES ($1)",
+                email_hash
+            )
+            .execute(&mut *tx)
+            .timed("password_resets.record_request")
+            .await?;
+            let count = sqlx::query_scalar!(
+                r#"
+                SELECT COUNT(*) AS "count!" FROM password_reset_requests
+                WHERE email_hash = $1 AND requested_at > $2
+                "#,
+                email_hash,
+                window_start
+            )
+            .fetch_one(&mut *tx)
+            .timed("password_resets.record_request")
+            .await?;
+            tx.commit().await?;
+            Ok(count)
+        })
+        .await
+    }
+
+    /// Store a reset token, dropping tokens that expired
+    async fn create(
+        &self,
+        user: &User,
+        token_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        resilient_write("password_resets.create", move || async move {
+            sqlx::query!("DELETE FROM password_resets WHERE expires_at <= NOW()")
+                .execute(&self.pool)
+                .timed("password_resets.create")
+                .await?;
+            sqlx::query!(
+                r#"
+                INSERT INTO password_resets (token_hash, user_id, expires_at)
+                VALUES ($1, $2, $3)
+                "#,
+                token_hash,
+                user.id,
+                expires_at
+            )
+            .execute(&self.pool)
+            .timed("password_resets.create")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Use up a reset token, returning the serial key of its user
+    ///
+    /// None when the token is unknown, expired or already used.
+    async fn take(&self, token_hash: &[u8]) -> Result<Option<i32>, sqlx::Error> {
+        resilient_write("password_resets.take", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                DELETE FROM password_resets
+                WHERE token_hash = $1 AND expires_at > NOW()
+                RETURNING user_id
+                "#,
+                token_hash
+            )
+            .fetch_optional(&self.pool)
+            .timed("password_resets.take")
+            .await
+        })
+        .await
+    }
+
+    /// Void every outstanding reset token of a user
+    async fn delete_all(&self, user: &User) -> Result<u64, sqlx::Error> {
+        resilient("password_resets.delete_all", move || async move {
+            let result = sqlx::query!("DELETE FROM password_resets WHERE user_id = $1", user.id)
+                .execute(&self.pool)
+                .timed("password_resets.delete_all")
+                .await?;
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::models::user::User;
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+
+/// What presenting a refresh token did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// The token was current and is now used up; its successor joins the family
+    Rotated { user_key: i32, family_id: Uuid },
+    /// The token had been used before, so its family is now revoked
+    Reused,
+    /// The token was revoked, has expired or was never stored
+    Revoked,
+}
+
+/// Refresh token store for rotation and revocation
+#[async_trait::async_trait]
+pub trait RefreshTokenRepositoryTrait {
+    async fn save(
+        &self,
+        user: &User,
+        family_id: Option<Uuid>,
+        token_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid, sqlx::Error>;
+    async fn rotate(&self, token_hash: &[u8]) -> Result<Rotation, sqlx::Error>;
+    async fn revoke_family_of(&self, token_hash: &[u8]) -> Result<u64, sqlx::Error>;
+    async fn revoke_all(&self, user: &User) -> Result<u64, sqlx::Error>;
+}
+
+/// Refresh token repository implementation with PostgreSQL
+pub struct RefreshTokenRepository {
+    pool: PgPool,
+}
+
+impl RefreshTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn revoke_family(&self, family_id: Uuid) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE refresh_tokens SET revoked_at = NOW()
+            WHERE family_id = $1 AND revoked_at IS NULL
+            "#,
+            family_id
+        )
+        .execute(&self.pool)
+        .timed("refresh_tokens.revoke_family")
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenRepositoryTrait for RefreshTokenRepository {
+    /// Store a newly issued token; without a family it starts a new one
+    ///
+    /// Returns the token's family.
+    async fn save(
+        &self,
+        user: &User,
+        family_id: Option<Uuid>,
+        token_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid, sqlx::Error> {
+        resilient_write("refresh_tokens.save", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                INSERT INTO refresh_tokens (user_id, family_id, token_hash, expires_at)
+                VALUES ($1, COALESCE($2, gen_random_uuid()), $3, $4)
+                RETURNING family_id
+                "#,
+                user.id,
+                family_id,
+                token_hash,
+                expires_at
+            )
+            .fetch_one(&self.pool)
+            .timed("refresh_tokens.save")
+            .await
+        })
+        .await
+    }
+
+    /// Use up a token, or revoke its family if it was used already
+    async fn rotate(&self, token_hash: &[u8]) -> Result<Rotation, sqlx::Error> {
+        resilient_write("refresh_tokens.rotate", move || async move {
+            let rotated = sqlx::query!(
+                r#"
+                UPDATE refresh_tokens SET used_at = NOW()
+                WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+                RETURNING user_id, family_id
+                "#,
+                token_hash
+            )
+            .fetch_optional(&self.pool)
+            .timed("refresh_tokens.rotate")
+            .await?;
+            if let Some(row) = rotated {
+                return Ok(Rotation::Rotated {
+                    user_key: row.user_id,
+                    family_id: row.family_id,
+                });
+            }
+
+            let reused = sqlx::query_scalar!(
+                r#"
+                SELECT family_id FROM refresh_tokens
+                WHERE token_hash = $1 AND used_at IS NOT NULL AND revoked_at IS NULL
+                "#,
+                token_hash
+            )
+            .fetch_optional(&self.pool)
+            .timed("refresh_tokens.rotate")
+            .await?;
+            match reused {
+                Some(family_id) => {
+                    self.revoke_family(family_id).await?;
+                    Ok(Rotation::Reused)
+                }
+                None => Ok(Rotation::Revoked),
+            }
+        })
+        .await
+    }
+
+    /// Revoke the token and every other token of its sign-in
+    async fn revoke_family_of(&self, token_hash: &[u8]) -> Result<u64, sqlx::Error> {
+        resilient("refresh_tokens.revoke_family_of", move || async move {
+            let family_id = sqlx::query_scalar!(
+                r#"
+                SELECT family_id FROM refresh_tokens WHERE token_hash = $1
+                "#,
+                token_hash
+            )
+            .fetch_optional(&self.pool)
+            .timed("refresh_tokens.revoke_family_of")
+            .await?;
+            match family_id {
+                Some(family_id) => self.revoke_family(family_id).await,
+                None => Ok(0),
+            }
+        })
+        .await
+    }
+
+    /// Revoke every refresh token of the user, signing them out everywhere
+    async fn revoke_all(&self, user: &User) -> Result<u64, sqlx::Error> {
+        resilient("refresh_tokens.revoke_all", move || async move {
+            let result = sqlx::query!(
+                r#"
+                UPDATE refresh_tokens SET revoked_at = NOW()
+                WHERE user_id = $1 AND revoked_at IS NULL
+                "#,
+                user.id
+            )
+            .execute(&self.pool)
+            .timed("refresh_tokens.revoke_all")
+            .await?;
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+}
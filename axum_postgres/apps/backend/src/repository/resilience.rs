@@ -0,0 +1,569 @@
+//! Retries and a circuit breaker around repository methods
+//!
+//! Every repository method runs through [`resilient`]. Transient failures,
+//! such as a reset connection or a pool timeout, are retried with jittered
+//! exponential backoff. After DB_CIRCUIT_FAILURES operations in a row fail
+//! that way, the circuit opens: for DB_CIRCUIT_OPEN_SECS methods fail at
+//! once instead of waiting on the database, then one operation is let
+//! through to probe it. Inside a request, operations also stop at the
+//! request's [`deadline`], retries included. `middleware::resilience`
+//! answers those failures with 503 and Retry-After.
+//!
+//! Writes that must not happen twice, such as inserts or rotating a
+//! refresh token, run through [`resilient_write`] instead. A connection
+//! lost mid-statement may have taken a commit with it, and a second run
+//! would then meet the first one's effect, so these are only retried when
+//! the failure came before the statement was sent, and once started they
+//! are not cut short at the deadline.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::io;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use metrics::counter;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::database::deadline::{self, DB_DEADLINE_EXCEEDED_TOTAL};
+
+tokio::task_local! {
+    /// Set when a method of the current request gave up on the database
+    static UNAVAILABLE: Cell<bool>;
+    /// Set while an operation runs, so the methods it calls do not retry
+    /// on their own
+    static IN_OPERATION: ();
+}
+
+/// Retry and circuit settings, shared by every repository
+#[derive(Debug, Clone, Copy)]
+pub struct ResiliencePolicy {
+    /// Tries per operation, the first one included
+    pub attempts: u32,
+    /// Backoff ceiling of the first retry; doubles on each retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Operations failing in a row that open the circuit
+    pub failure_threshold: u32,
+    /// How long the circuit stays open
+    pub open_for: Duration,
+}
+
+impl Default for ResiliencePolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(1),
+            failure_threshold: 5,
+            open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ResiliencePolicy {
+    /// Policy from DB_RETRY_ATTEMPTS, DB_RETRY_BASE_DELAY_MS,
+    /// DB_RETRY_MAX_DELAY_MS, DB_CIRCUIT_FAILURES and DB_CIRCUIT_OPEN_SECS
+    ///
+    /// DB_RETRY_ATTEMPTS=1 turns retries off, DB_CIRCUIT_FAILURES=0 the
+    /// circuit breaker.
+    pub fn from_env() -> Self {
+        let number = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+        };
+        let defaults = Self::default();
+        Self {
+            attempts: number("DB_RETRY_ATTEMPTS")
+                .map(|attempts: u64| attempts.max(1) as u32)
+                .unwrap_or(defaults.attempts),
+            base_delay: number("DB_RETRY_BASE_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.base_delay),
+            max_delay: number("DB_RETRY_MAX_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_delay),
+            failure_threshold: number("DB_CIRCUIT_FAILURES")
+                .map(|failures: u64| failures as u32)
+                .unwrap_or(defaults.failure_threshold),
+            open_for: number("DB_CIRCUIT_OPEN_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.open_for),
+        }
+    }
+
+    /// Random delay before retry `retry`, counting from 0, up to the
+    /// doubled base delay ("full jitter")
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let mut bytes = [0u8; 4];
+        let fraction = match SystemRandom::new().fill(&mut bytes) {
+            Ok(()) => f64::from(u32::from_le_bytes(bytes)) / f64::from(u32::MAX),
+            Err(_) => 1.0,
+        };
+        ceiling.mul_f64(fraction)
+    }
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    /// Operations failed in a row
+    failures: u32,
+    open_until: Option<Instant>,
+    /// A probe is running after the circuit was open
+    probing: bool,
+}
+
+/// Opens after `failure_threshold` failed operations in a row
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_for,
+            state: Mutex::new(CircuitState::default()),
+        }
+    }
+
+    /// Whether an operation may run, else how long until the next probe
+    pub fn allow(&self) -> Result<(), Duration> {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(until) if now < until => Err(until - now),
+            // Let one probe through; the rest wait for its answer, or for
+            // the next probe should it never answer
+            Some(_) => {
+                state.open_until = Some(now + self.open_for);
+                state.probing = true;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Time until the next probe, `None` when the circuit is closed
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after_at(Instant::now())
+    }
+
+    fn retry_after_at(&self, now: Instant) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.open_until.map(|until| {
+            until
+                .saturating_duration_since(now)
+                .max(Duration::from_secs(1))
+        })
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            tracing::info!("Database answered again; closing the circuit");
+        }
+        *state = CircuitState::default();
+    }
+
+    pub fn record_failure(&self) {
+        self.record_failure_at(Instant::now());
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failures += 1;
+        if state.probing || state.failures >= self.failure_threshold {
+            if state.open_until.is_none() {
+                tracing::error!(
+                    "Database failed {} operations in a row; opening the circuit for {:?}",
+                    state.failures,
+                    self.open_for
+                );
+            }
+            state.open_until = Some(now + self.open_for);
+            state.probing = false;
+        }
+    }
+}
+
+/// Policy and circuit of the process, from the environment
+fn global() -> &'static (ResiliencePolicy, CircuitBreaker) {
+    static GLOBAL: OnceLock<(ResiliencePolicy, CircuitBreaker)> = OnceLock::new();
+    GLOBAL.get_or_init(|| {
+        let policy = ResiliencePolicy::from_env();
+        let breaker = CircuitBreaker::new(policy.failure_threshold, policy.open_for);
+        (policy, breaker)
+    })
+}
+
+/// Seconds clients should wait before retrying while the database is out:
+/// until the next probe when the circuit is open, else one
+pub fn retry_after_secs() -> u64 {
+    global()
+        .1
+        .retry_after()
+        .map(|wait| wait.as_secs_f64().ceil() as u64)
+        .unwrap_or(1)
+}
+
+/// Run the operation of a repository method, retrying transient failures
+///
+/// `operation` builds the whole operation again on each try, so a
+/// transaction is retried from its start. Methods called by another one
+/// run once, leaving retries to the outer method.
+pub async fn resilient<T, F, Fut>(name: &'static str, operation: F) -> Result<T, sqlx::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    if IN_OPERATION.try_with(|_| ()).is_ok() {
+        return operation().await;
+    }
+    let (policy, breaker) = global();
+    run(policy, breaker, name, true, operation).await
+}
+
+/// Run the operation of a repository method that must not write twice
+///
+/// Like [`resilient`], except that only failures before the statement was
+/// sent, such as a pool timeout or a refused connection, are retried, and
+/// an operation that started is left to finish past the request deadline.
+pub async fn resilient_write<T, F, Fut>(name: &'static str, operation: F) -> Result<T, sqlx::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    if IN_OPERATION.try_with(|_| ()).is_ok() {
+        return operation().await;
+    }
+    let (policy, breaker) = global();
+    run(policy, breaker, name, false, operation).await
+}
+
+/// `repeatable` operations may run again after failing partway
+async fn run<T, F, Fut>(
+    policy: &ResiliencePolicy,
+    breaker: &CircuitBreaker,
+    name: &'static str,
+    repeatable: bool,
+    operation: F,
+) -> Result<T, sqlx::Error>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    if breaker.allow().is_err() {
+        mark_unavailable();
+        return Err(circuit_open());
+    }
+
+    let deadline = deadline::current();
+    let mut retry = 0;
+    loop {
+        let attempt = IN_OPERATION.scope((), operation());
+        let result = match deadline {
+            Some(deadline) if repeatable => tokio::time::timeout_at(deadline, attempt).await.ok(),
+            // A write cut short may commit all the same; one that started
+            // is left to finish
+            Some(deadline) if tokio::time::Instant::now() >= deadline => None,
+            _ => Some(attempt.await),
+        };
+        // Running out of time says nothing of the database being down, so
+        // the circuit is left as it is
+        let Some(result) = result else {
+            tracing::warn!(
+                operation = name,
+                "Request deadline passed before the database answered"
+            );
+            counter!(DB_DEADLINE_EXCEEDED_TOTAL, 1, "operation" => name);
+            mark_unavailable();
+            return Err(sqlx::Error::PoolTimedOut);
+        };
+        match result {
+            Ok(value) => {
+                breaker.record_success();
+                return Ok(value);
+            }
+            Err(e) if is_transient(&e) => {
+                let delay = policy.backoff(retry);
+                let out_of_time = deadline
+                    .is_some_and(|deadline| tokio::time::Instant::now() + delay >= deadline);
+                let may_have_run = !repeatable && !is_unsent(&e);
+                if retry + 1 >= policy.attempts || out_of_time || may_have_run {
+                    breaker.record_failure();
+                    mark_unavailable();
+                    return Err(e);
+                }
+                tracing::warn!(
+                    operation = name,
+                    "Transient database error, retrying in {:?}: {}",
+                    delay,
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                retry += 1;
+            }
+            // The database answered, so it is up
+            Err(e) => {
+                breaker.record_success();
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Failures that may pass on their own: the connection was lost, refused or
+/// not available in time, or the server is restarting
+pub fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Io(e) => matches!(
+            e.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof
+                | io::ErrorKind::TimedOut
+        ),
+        sqlx::Error::Database(db) => db
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code.starts_with("57P")),
+        _ => false,
+    }
+}
+
+/// Transient failures that leave no doubt the statement never reached the
+/// database: no connection was to be had
+fn is_unsent(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut => true,
+        sqlx::Error::Io(e) => e.kind() == io::ErrorKind::ConnectionRefused,
+        _ => false,
+    }
+}
+
+/// Error of the methods refused while the circuit is open
+fn circuit_open() -> sqlx::Error {
+    sqlx::Error::Io(io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        "Database circuit is open",
+    ))
+}
+
+fn mark_unavailable() {
+    let _ = UNAVAILABLE.try_with(|unavailable| unavailable.set(true));
+}
+
+/// Run `future`, telling whether a repository method in it gave up on the
+/// database
+pub async fn track_unavailable<F: Future>(future: F) -> (F::Output, bool) {
+    UNAVAILABLE
+        .scope(Cell::new(false), async {
+            let output = future.await;
+            (output, UNAVAILABLE.with(Cell::get))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn policy() -> ResiliencePolicy {
+        ResiliencePolicy {
+            attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            failure_threshold: 2,
+            open_for: Duration::from_secs(30),
+        }
+    }
+
+    fn reset() -> sqlx::Error {
+        sqlx::Error::Io(io::Error::from(io::ErrorKind::ConnectionReset))
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors_are_retried() {
+        let policy = policy();
+        let breaker = CircuitBreaker::new(policy.failure_threshold, policy.open_for);
+        let calls = AtomicU32::new(0);
+
+        let result = run(&policy, &breaker, "test", true, || async {
+            match calls.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(sqlx::Error::PoolTimedOut),
+                1 => Err(reset()),
+                _ => Ok(7),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        // Other errors are the caller's to handle
+        calls.store(0, Ordering::Relaxed);
+        let result: Result<(), _> = run(&policy, &breaker, "test", true, || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_after_repeated_failures() {
+        let policy = policy();
+        let breaker = CircuitBreaker::new(policy.failure_threshold, policy.open_for);
+        let calls = AtomicU32::new(0);
+        let failing = || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err::<(), _>(reset())
+        };
+
+        let (result, unavailable) =
+            track_unavailable(run(&policy, &breaker, "test", true, failing)).await;
+        assert!(result.is_err());
+        assert!(unavailable);
+        assert!(breaker.retry_after().is_none());
+        let _ = run(&policy, &breaker, "test", true, failing).await;
+        assert_eq!(calls.load(Ordering::Relaxed), 6);
+        assert!(breaker.retry_after().unwrap() > Duration::from_secs(29));
+
+        // Open: refused without touching the database
+        let result = run(&policy, &breaker, "test", true, failing).await;
+        assert!(is_transient(&result.unwrap_err()));
+        assert_eq!(calls.load(Ordering::Relaxed), 6);
+    }
+
+    #[tokio::test]
+    async fn test_operations_stop_at_the_request_deadline() {
+        let policy = policy();
+        let breaker = CircuitBreaker::new(policy.failure_threshold, policy.open_for);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(20);
+
+        // Queued behind a saturated pool, say
+        let started = Instant::now();
+        let (result, unavailable) = track_unavailable(deadline::scope(
+            deadline,
+            run(&policy, &breaker, "test", true, || async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            }),
+        ))
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert!(unavailable);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(breaker.retry_after().is_none());
+
+        // No retries past the deadline
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = deadline::scope(
+            tokio::time::Instant::now(),
+            run(&policy, &breaker, "test", true, || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(reset())
+            }),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_writes_only_retry_failures_before_they_were_sent() {
+        let policy = policy();
+        let breaker = CircuitBreaker::new(policy.failure_threshold, policy.open_for);
+        let calls = AtomicU32::new(0);
+
+        let result = run(&policy, &breaker, "test", false, || async {
+            match calls.fetch_add(1, Ordering::Relaxed) {
+                0 => Err(sqlx::Error::PoolTimedOut),
+                _ => Ok(7),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // The write may have committed before the connection was lost
+        calls.store(0, Ordering::Relaxed);
+        let (result, unavailable): (Result<(), _>, _) =
+            track_unavailable(run(&policy, &breaker, "test", false, || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(reset())
+            }))
+            .await;
+        assert!(result.is_err());
+        assert!(unavailable);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_started_writes_finish_past_the_deadline() {
+        let policy = policy();
+        let breaker = CircuitBreaker::new(policy.failure_threshold, policy.open_for);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(20);
+
+        let result = deadline::scope(
+            deadline,
+            run(&policy, &breaker, "test", false, || async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(7)
+            }),
+        )
+        .await;
+        assert_eq!(result.unwrap(), 7);
+
+        // Writes not started by the deadline are not started at all
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = deadline::scope(
+            deadline,
+            run(&policy, &breaker, "test", false, || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            }),
+        )
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_one_probe_closes_or_reopens_the_circuit() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(10));
+        let start = Instant::now();
+        breaker.record_failure_at(start);
+        assert!(breaker.allow_at(start + Duration::from_secs(5)).is_err());
+
+        let later = start + Duration::from_secs(11);
+        assert!(breaker.allow_at(later).is_ok());
+        assert!(breaker.allow_at(later).is_err());
+        breaker.record_failure_at(later);
+        assert!(breaker.allow_at(later + Duration::from_secs(5)).is_err());
+
+        let much_later = later + Duration::from_secs(11);
+        assert!(breaker.allow_at(much_later).is_ok());
+        breaker.record_success();
+        assert!(breaker.allow_at(much_later).is_ok());
+        assert!(breaker.allow_at(much_later).is_ok());
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+
+use crate::auth::role::Role;
+use crate::models::role::RoleRecord;
+use crate::models::user::User;
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
+
+/// Roles and their grants to users
+#[async_trait::async_trait]
+pub trait RoleRepositoryTrait {
+    async fn list(&self) -> Result<Vec<RoleRecord>, sqlx::Error>;
+    async fn roles_of(&self, user_key: i32) -> Result<Vec<Role>, sqlx::Error>;
+    async fn roles_of_all(&self, user_keys: &[i32])
+        -> Result<HashMap<i32, Vec<Role>>, sqlx::Error>;
+    async fn members(&self, role: Role) -> Result<Vec<User>, sqlx::Error>;
+    async fn grant(&self, role: Role, user: &User) -> Result<bool, sqlx::Error>;
+    async fn revoke(&self, role: Role, user: &User) -> Result<bool, sqlx::Error>;
+}
+
+/// Role repository implementation with PostgreSQL; members are counted
+/// and listed within the tenant current when it was built
+pub struct RoleRepository {
+    pool: PgPool,
+    tenant_id: i32,
+}
+
+impl RoleRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tenant_id: tenant::current(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RoleRepositoryTrait for RoleRepository {
+    /// Every role with how many live users hold it
+    async fn list(&self) -> Result<Vec<RoleRecord>, sqlx::Error> {
+        resilient("roles.list", move || async move {
+            sqlx::query_as!(
+                RoleRecord,
+                r#"
+                SELECT r.name, r.description, COUNT(u.id) AS "members!"
+                FROM roles r
+                LEFT JOIN user_roles ur ON ur.role_id = r.id
+                LEFT JOIN test_users u
+                    ON u.id = ur.user_id AND u.tenant_id = $1 AND u.deleted_at IS NULL
+                GROUP BY r.id
+                ORDER BY r.name
+                "#,
+                self.tenant_id
+            )
+            .fetch_all(&self.pool)
+            .timed("roles.list")
+            .await
+        })
+        .await
+    }
+
+    /// Roles of a live user, by row key; roles this build does not know are dropped
+    async fn roles_of(&self, user_key: i32) -> Result<Vec<Role>, sqlx::Error> {
+        resilient("roles.roles_of", move || async move {
+            let names = sqlx::query_scalar!(
+                r#"
+                SELECT r.name
+                FROM user_roles ur
+                JOIN roles r ON r.id = ur.role_id
+                JOIN test_users u ON u.id = ur.user_id
+                WHERE ur.user_id = $1 AND u.deleted_at IS NULL
+                "#,
+                user_key
+            )
+            .fetch_all(&self.pool)
+            .timed("roles.roles_of")
+            .await?;
+            Ok(names.iter().filter_map(|name| name.parse().ok()).collect())
+        })
+        .await
+    }
+
+    /// Roles granted to each of the users, by row key, deleted users included
+    ///
+    /// Users without roles are left out of the map.
+    async fn roles_of_all(
+        &self,
+        user_keys: &[i32],
+    ) -> Result<HashMap<i32, Vec<Role>>, sqlx::Error> {
+        resilient("roles.roles_of_all", move || async move {
+            let grants = sqlx::query!(
+                r#"
+                SELECT ur.user_id, r.name
+                FROM user_roles ur
+                JOIN roles r ON r.id = ur.role_id
+                WHERE ur.user_id = ANY($1)
+                ORDER BY r.name
+                "#,
+                user_keys
+            )
+            .fetch_all(&self.pool)
+            .timed("roles.roles_of_all")
+            .await?;
+            let mut roles: HashMap<i32, Vec<Role>> = HashMap::new();
+            for grant in grants {
+                if let Ok(role) = grant.name.parse() {
+                    roles.entry(grant.user_id).or_default().push(role);
+                }
+            }
+            Ok(roles)
+        })
+        .await
+    }
+
+    /// Live users holding the role, oldest grant first
+    async fn members(&self, role: Role) -> Result<Vec<User>, sqlx::Error> {
+        resilient("roles.members", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT u.id, u.public_id, u.name, u.email, u.active, u.display_name, u.bio, u.phone, u.timezone, u.locale, u.email_verified, u.created_at, u.updated_at, u.deleted_at
+                FROM user_roles ur
+                JOIN roles r ON r.id = ur.role_id
+                JOIN test_users u ON u.id = ur.user_id
+                WHERE r.name = $1 AND u.tenant_id = $2 AND u.deleted_at IS NULL
+                ORDER BY ur.granted_at, u.id
+                "#,
+                role.as_str(),
+                self.tenant_id
+            )
+            .fetch_all(&self.pool)
+            .timed("roles.members")
+            .await
+        })
+        .await
+    }
+
+    /// Grant the role; false if the user already held it
+    async fn grant(&self, role: Role, user: &User) -> Result<bool, sqlx::Error> {
+        resilient_write("roles.grant", move || async move {
+            let result = sqlx::query!(
+                r#"
+                INSERT INTO user_roles (user_id, role_id)
+                SELECT $1, id FROM roles WHERE name = $2
+                ON CONFLICT (user_id, role_id) DO NOTHING
+                "#,
+                user.id,
+                role.as_str()
+            )
+            .execute(&self.pool)
+            .timed("roles.grant")
+            .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Take the role away; false if the user did not hold it
+    async fn revoke(&self, role: Role, user: &User) -> Result<bool, sqlx::Error> {
+        resilient_write("roles.revoke", move || async move {
+            let result = sqlx::query!(
+                r#"
+                DELETE FROM user_roles
+                WHERE user_id = $1 AND role_id = (SELECT id FROM roles WHERE name = $2)
+                "#,
+                user.id,
+                role.as_str()
+            )
+            .execute(&self.pool)
+            .timed("roles.revoke")
+            .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::models::user::User;
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+
+/// Session a cookie resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveSession {
+    /// Serial key of the signed-in user
+    pub user_key: i32,
+    pub csrf_token: String,
+}
+
+/// Cookie session store
+#[async_trait::async_trait]
+pub trait SessionRepositoryTrait {
+    async fn create(
+        &self,
+        user: &User,
+        id_hash: &[u8],
+        csrf_token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+    async fn touch(
+        &self,
+        id_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<ActiveSession>, sqlx::Error>;
+    async fn delete(&self, id_hash: &[u8]) -> Result<bool, sqlx::Error>;
+    async fn delete_all(&self, user: &User) -> Result<u64, sqlx::Error>;
+}
+
+/// Session repository implementation with PostgreSQL
+pub struct SessionRepository {
+    pool: PgPool,
+}
+
+impl SessionRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionRepositoryTrait for SessionRepository {
+    /// Start a session, dropping sessions that expired
+    async fn create(
+        &self,
+        user: &User,
+        id_hash: &[u8],
+        csrf_token: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        resilient_write("sessions.create", move || async move {
+            sqlx::query!("DELETE FROM sessions WHERE expires_at <= NOW()")
+                .execute(&self.pool)
+                .timed("sessions.create")
+                .await?;
+            sqlx::query!(
+                r#"
+                INSERT INTO sessions (id_hash, user_id, csrf_token, expires_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                id_hash,
+                user.id,
+                csrf_token,
+                expires_at
+            )
+            .execute(&self.pool)
+            .timed("sessions.create")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Find a live session and extend it to `expires_at`
+    async fn touch(
+        &self,
+        id_hash: &[u8],
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<ActiveSession>, sqlx::Error> {
+        resilient("sessions.touch", move || async move {
+            sqlx::query_as!(
+                ActiveSession,
+                r#"
+                UPDATE sessions SET last_seen_at = NOW(), expires_at = $2
+                WHERE id_hash = $1 AND expires_at > NOW()
+                RETURNING user_id AS user_key, csrf_token
+                "#,
+                id_hash,
+                expires_at
+            )
+            .fetch_optional(&self.pool)
+            .timed("sessions.touch")
+            .await
+        })
+        .await
+    }
+
+    /// End one session; false when there was none
+    async fn delete(&self, id_hash: &[u8]) -> Result<bool, sqlx::Error> {
+        resilient("sessions.delete", move || async move {
+            let result = sqlx::query!("DELETE FROM sessions WHERE id_hash = $1", id_hash)
+                .execute(&self.pool)
+                .timed("sessions.delete")
+                .await?;
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// End every session of a user
+    async fn delete_all(&self, user: &User) -> Result<u64, sqlx::Error> {
+        resilient("sessions.delete_all", move || async move {
+            let result = sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user.id)
+                .execute(&self.pool)
+                .timed("sessions.delete_all")
+                .await?;
+            Ok(result.rows_affected())
+        })
+        .await
+    }
+}
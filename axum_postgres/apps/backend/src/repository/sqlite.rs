@@ -0,0 +1,753 @@
+//! SQLite implementation of [`UserRepositoryTrait`] (cargo feature `sqlite`)
+//!
+//! For local development and tests without a Postgres server: an in-memory
+//! database with the schema of `migrations_sqlite/` behaves like the
+//! Postgres user repository, triggers and all. Only users are covered; the
+//! server itself, credentials, sessions and the other repositories still
+//! need Postgres.
+//!
+//! The differences worth knowing:
+//! - `search_users` matches every word of `terms` in the name or email,
+//!   names first, instead of ranked full-text search
+//! - history `changed_by` is always NULL, as SQLite has no `app.actor`
+//! - timestamps have millisecond precision
+//! - queries are neither retried nor guarded by the circuit breaker
+
+use std::str::FromStr;
+
+use futures::stream::BoxStream;
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+use crate::models::user::{CreateUserRequest, UpdateUserRequest, User, UserListQuery};
+use crate::models::user_history::UserHistoryRecord;
+use crate::models::user_id::UserId;
+use crate::pagination::Cursor;
+use crate::repository::timing::Timed;
+use crate::repository::user::{contains_pattern, order_by, UserRepositoryTrait, IMPORT_BATCH_SIZE};
+
+/// Migrations of the SQLite schema, separate from the Postgres ones
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+const USER_COLUMNS: &str = "id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at";
+
+/// Current time in the format timestamps are stored in
+const NOW: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+/// Open the database at `url` and bring its schema up to date
+///
+/// `sqlite::memory:` gives a fresh database that lives as long as the pool;
+/// it is held on a single connection, as every connection to `:memory:`
+/// opens a database of its own. File databases are created when missing.
+pub async fn connect(url: &str) -> Result<SqlitePool, sqlx::Error> {
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    let pool = if url.contains(":memory:") || url.contains("mode=memory") {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?
+    } else {
+        SqlitePoolOptions::new().connect_with(options).await?
+    };
+    MIGRATOR.run(&pool).await?;
+    Ok(pool)
+}
+
+/// User repository implementation with SQLite
+pub struct SqliteUserRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteUserRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Serial row key of the user with this id, deleted or not
+    #[cfg(not(feature = "uuid-ids"))]
+    async fn row_key(&self, id: UserId) -> Result<Option<i32>, sqlx::Error> {
+        Ok(Some(id.get()))
+    }
+
+    #[cfg(feature = "uuid-ids")]
+    async fn row_key(&self, id: UserId) -> Result<Option<i32>, sqlx::Error> {
+        sqlx::query_scalar("SELECT id FROM test_users WHERE public_id = ?")
+            .bind(id.get())
+            .fetch_optional(&self.pool)
+            .timed("sqlite_user.row_key")
+            .await
+    }
+
+    /// Run `UPDATE test_users SET <set> WHERE id = <key> AND <condition>`
+    /// and read the row back, None when no row matched
+    ///
+    /// RETURNING would hand back the row before the update triggers touch
+    /// `updated_at`, so it is selected again in the same transaction.
+    async fn update_where(
+        &self,
+        key: i32,
+        mut builder: QueryBuilder<'_, Sqlite>,
+        condition: &str,
+        statement: &'static str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        builder
+            .push(" WHERE id = ")
+            .push_bind(key)
+            .push(" AND ")
+            .push(condition);
+        let updated = builder.build().execute(&mut *tx).timed(statement).await?;
+        if updated.rows_affected() == 0 {
+            return Ok(None);
+        }
+        let user = sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM test_users WHERE id = ?",
+            USER_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&mut *tx)
+        .timed(statement)
+        .await?;
+        tx.commit().await?;
+        Ok(user)
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepositoryTrait for SqliteUserRepository {
+    /// Create a new user
+    async fn create_user(&self, user: CreateUserRequest) -> Result<User, sqlx::Error> {
+        sqlx::query_as::<_, User>(&format!(
+            "INSERT INTO test_users (name, email, display_name, bio, phone, timezone, locale) \
+             VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING {}",
+            USER_COLUMNS
+        ))
+        .bind(user.name)
+        .bind(user.email)
+        .bind(user.display_name)
+        .bind(user.bio)
+        .bind(user.phone)
+        .bind(user.timezone)
+        .bind(user.locale)
+        .fetch_one(&self.pool)
+        .timed("sqlite_user.create_user")
+        .await
+    }
+
+    /// Insert many users in one transaction, skipping taken emails
+    async fn import_users(&self, users: Vec<CreateUserRequest>) -> Result<Vec<User>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut created = Vec::with_capacity(users.len());
+        for batch in users.chunks(IMPORT_BATCH_SIZE) {
+            let mut builder = QueryBuilder::<Sqlite>::new(
+                "INSERT INTO test_users (name, email, display_name, bio, phone, timezone, locale) ",
+            );
+            builder.push_values(batch, |mut row, user| {
+                row.push_bind(user.name.clone())
+                    .push_bind(user.email.clone())
+                    .push_bind(user.display_name.clone())
+                    .push_bind(user.bio.clone())
+                    .push_bind(user.phone.clone())
+                    .push_bind(user.timezone.clone())
+                    .push_bind(user.locale.clone());
+            });
+            builder
+                .push(" ON CONFLICT (LOWER(email)) WHERE deleted_at IS NULL DO NOTHING RETURNING ")
+                .push(USER_COLUMNS);
+            let inserted = builder
+                .build_query_as::<User>()
+                .fetch_all(&mut *tx)
+                .timed("sqlite_user.import_users")
+                .await?;
+            created.extend(inserted);
+        }
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    /// Get user by ID
+    async fn get_user_by_id(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(None);
+        };
+        self.get_user_by_key(key).await
+    }
+
+    /// Get user by serial row key
+    async fn get_user_by_key(&self, key: i32) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM test_users WHERE id = ? AND deleted_at IS NULL",
+            USER_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .timed("sqlite_user.get_user_by_key")
+        .await
+    }
+
+    /// Whether a live user has this ID, without loading it
+    async fn exists(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(false);
+        };
+        sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM test_users WHERE id = ? AND deleted_at IS NULL)",
+        )
+        .bind(key)
+        .fetch_one(&self.pool)
+        .timed("sqlite_user.exists")
+        .await
+    }
+
+    /// Find user by email, ignoring case
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM test_users WHERE LOWER(email) = LOWER(?) AND deleted_at IS NULL",
+            USER_COLUMNS
+        ))
+        .bind(email)
+        .fetch_optional(&self.pool)
+        .timed("sqlite_user.find_user_by_email")
+        .await
+    }
+
+    /// Whether a live user has this email, ignoring case
+    async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM test_users WHERE LOWER(email) = LOWER(?) AND deleted_at IS NULL)",
+        )
+        .bind(email)
+        .fetch_one(&self.pool)
+        .timed("sqlite_user.email_exists")
+        .await
+    }
+
+    /// List all users ordered by created_at desc
+    async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM test_users WHERE deleted_at IS NULL ORDER BY created_at DESC, id DESC",
+            USER_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .timed("sqlite_user.list_users")
+        .await
+    }
+
+    /// List matching users in the requested order, skipping `offset`
+    async fn list_users_page(
+        &self,
+        query: &UserListQuery,
+        offset: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        let mut builder = filtered(USER_COLUMNS, query);
+        builder
+            .push(" ORDER BY ")
+            .push(order_by(query))
+            // LIMIT -1 is no limit
+            .push(" LIMIT ")
+            .push_bind(limit.unwrap_or(-1))
+            .push(" OFFSET ")
+            .push_bind(offset);
+        builder
+            .build_query_as::<User>()
+            .fetch_all(&self.pool)
+            .timed("sqlite_user.list_users_page")
+            .await
+    }
+
+    /// List up to `limit` matching users following `after`, newest first
+    async fn list_users_after(
+        &self,
+        query: &UserListQuery,
+        after: Option<Cursor>,
+        limit: i64,
+    ) -> Result<Vec<User>, sqlx::Error> {
+        let mut builder = filtered(USER_COLUMNS, query);
+        if let Some(after) = after {
+            // The bound timestamp is normalized to the stored text format,
+            // which then compares in time order
+            builder
+                .push(" AND (created_at, id) < (strftime('%Y-%m-%dT%H:%M:%fZ', ")
+                .push_bind(after.created_at)
+                .push("), ")
+                .push_bind(after.id)
+                .push(")");
+        }
+        builder
+            .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+            .push_bind(limit);
+        builder
+            .build_query_as::<User>()
+            .fetch_all(&self.pool)
+            .timed("sqlite_user.list_users_after")
+            .await
+    }
+
+    /// Count matching users
+    async fn count_users(&self, query: &UserListQuery) -> Result<i64, sqlx::Error> {
+        filtered("COUNT(*)", query)
+            .build_query_scalar::<i64>()
+            .fetch_one(&self.pool)
+            .timed("sqlite_user.count_users")
+            .await
+    }
+
+    /// Users whose name or email contains every word of `terms`, ignoring
+    /// ASCII case; name matches first, then newest first
+    async fn search_users(&self, terms: &str, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+        let words: Vec<String> = terms.split_whitespace().map(contains_pattern).collect();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut builder = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT {} FROM test_users WHERE deleted_at IS NULL",
+            USER_COLUMNS
+        ));
+        for word in &words {
+            builder
+                .push(" AND (name LIKE ")
+                .push_bind(word)
+                .push(" ESCAPE '\\' OR email LIKE ")
+                .push_bind(word)
+                .push(" ESCAPE '\\')");
+        }
+        builder.push(" ORDER BY (");
+        let mut in_name = builder.separated(" AND ");
+        for word in &words {
+            in_name
+                .push("name LIKE ")
+                .push_bind_unseparated(word)
+                .push_unseparated(" ESCAPE '\\'");
+        }
+        builder.push(") DESC, id DESC LIMIT ").push_bind(limit);
+        builder
+            .build_query_as::<User>()
+            .fetch_all(&self.pool)
+            .timed("sqlite_user.search_users")
+            .await
+    }
+
+    /// Change counter of test_users, bumped by every written row
+    async fn users_version(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT version FROM collection_versions WHERE name = 'test_users'")
+            .fetch_one(&self.pool)
+            .timed("sqlite_user.users_version")
+            .await
+    }
+
+    /// Update user by ID, writing only the fields present in `user`
+    async fn update_user(
+        &self,
+        id: UserId,
+        user: UpdateUserRequest,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(None);
+        };
+        let mut builder = QueryBuilder::<Sqlite>::new("UPDATE test_users SET ");
+        let mut fields = builder.separated(", ");
+        let mut any = false;
+        if let Some(name) = user.name {
+            fields.push("name = ").push_bind_unseparated(name);
+            any = true;
+        }
+        if let Some(email) = user.email {
+            fields.push("email = ").push_bind_unseparated(email);
+            any = true;
+        }
+        if let Some(active) = user.active {
+            fields.push("active = ").push_bind_unseparated(active);
+            any = true;
+        }
+        // Profile fields: Some(None) writes NULL
+        if let Some(display_name) = user.display_name {
+            fields
+                .push("display_name = ")
+                .push_bind_unseparated(display_name);
+            any = true;
+        }
+        if let Some(bio) = user.bio {
+            fields.push("bio = ").push_bind_unseparated(bio);
+            any = true;
+        }
+        if let Some(phone) = user.phone {
+            fields.push("phone = ").push_bind_unseparated(phone);
+            any = true;
+        }
+        if let Some(timezone) = user.timezone {
+            fields.push("timezone = ").push_bind_unseparated(timezone);
+            any = true;
+        }
+        if let Some(locale) = user.locale {
+            fields.push("locale = ").push_bind_unseparated(locale);
+            any = true;
+        }
+        if !any {
+            // No updates, return current user
+            return self.get_user_by_key(key).await;
+        }
+        self.update_where(
+            key,
+            builder,
+            "deleted_at IS NULL",
+            "sqlite_user.update_user",
+        )
+        .await
+    }
+
+    /// Activate or deactivate a live user, leaving every other field alone
+    async fn set_user_active(&self, id: UserId, active: bool) -> Result<Option<User>, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(None);
+        };
+        let mut builder = QueryBuilder::<Sqlite>::new("UPDATE test_users SET active = ");
+        builder.push_bind(active);
+        self.update_where(
+            key,
+            builder,
+            "deleted_at IS NULL",
+            "sqlite_user.set_user_active",
+        )
+        .await
+    }
+
+    /// Soft-delete user by ID; false when missing or already deleted
+    async fn delete_user(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(false);
+        };
+        let result = sqlx::query(&format!(
+            "UPDATE test_users SET deleted_at = {} WHERE id = ? AND deleted_at IS NULL",
+            NOW
+        ))
+        .bind(key)
+        .execute(&self.pool)
+        .timed("sqlite_user.delete_user")
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Permanently remove a user, deleted or not
+    async fn purge_user(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(false);
+        };
+        let result = sqlx::query("DELETE FROM test_users WHERE id = ?")
+            .bind(key)
+            .execute(&self.pool)
+            .timed("sqlite_user.purge_user")
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Undo a soft delete; None when the user is not deleted or does not exist
+    async fn restore_user(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(None);
+        };
+        let builder = QueryBuilder::<Sqlite>::new("UPDATE test_users SET deleted_at = NULL");
+        self.update_where(
+            key,
+            builder,
+            "deleted_at IS NOT NULL",
+            "sqlite_user.restore_user",
+        )
+        .await
+    }
+
+    /// Get user by ID, including soft-deleted users
+    async fn get_user_including_deleted(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(None);
+        };
+        sqlx::query_as::<_, User>(&format!(
+            "SELECT {} FROM test_users WHERE id = ?",
+            USER_COLUMNS
+        ))
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .timed("sqlite_user.get_user_including_deleted")
+        .await
+    }
+
+    /// Get every recorded version of a user, oldest first
+    async fn get_user_history(&self, id: UserId) -> Result<Vec<UserHistoryRecord>, sqlx::Error> {
+        let Some(key) = self.row_key(id).await? else {
+            return Ok(Vec::new());
+        };
+        sqlx::query_as::<_, UserHistoryRecord>(
+            "SELECT version, operation, data, changed_by, changed_at \
+             FROM test_users_history WHERE user_id = ? ORDER BY version",
+        )
+        .bind(key)
+        .fetch_all(&self.pool)
+        .timed("sqlite_user.get_user_history")
+        .await
+    }
+
+    /// Stream matching users in id order, one row at a time
+    ///
+    /// Holds the connection until the stream ends; sort parameters are
+    /// ignored.
+    fn stream_users<'a>(
+        &'a self,
+        query: &UserListQuery,
+    ) -> BoxStream<'a, Result<User, sqlx::Error>> {
+        sqlx::query_as::<_, User>(
+            r#"
+            SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+            FROM test_users
+            WHERE (?1 OR deleted_at IS NULL)
+              AND (?2 IS NULL OR active = ?2)
+              AND (?3 IS NULL OR email LIKE ?3 ESCAPE '\')
+              AND (?4 IS NULL OR name LIKE ?4 ESCAPE '\')
+            ORDER BY id
+            "#,
+        )
+        .bind(query.include_deleted)
+        .bind(query.active)
+        .bind(query.email_contains.as_deref().map(contains_pattern))
+        .bind(query.name_contains.as_deref().map(contains_pattern))
+        .fetch(&self.pool)
+    }
+}
+
+/// `SELECT <columns> FROM test_users` with the filters of `query`
+///
+/// LIKE ignores ASCII case in SQLite, like ILIKE for those letters.
+fn filtered<'a>(columns: &str, query: &UserListQuery) -> QueryBuilder<'a, Sqlite> {
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM test_users WHERE TRUE", columns));
+    if !query.include_deleted {
+        builder.push(" AND deleted_at IS NULL");
+    }
+    if let Some(active) = query.active {
+        builder.push(" AND active = ").push_bind(active);
+    }
+    if let Some(text) = &query.email_contains {
+        builder
+            .push(" AND email LIKE ")
+            .push_bind(contains_pattern(text))
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(text) = &query.name_contains {
+        builder
+            .push(" AND name LIKE ")
+            .push_bind(contains_pattern(text))
+            .push(" ESCAPE '\\'");
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_repo() -> SqliteUserRepository {
+        let pool = connect("sqlite::memory:")
+            .await
+            .expect("Failed to open SQLite database");
+        SqliteUserRepository::new(pool)
+    }
+
+    fn request(name: &str, email: &str) -> CreateUserRequest {
+        CreateUserRequest {
+            name: name.to_string(),
+            email: email.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_update_and_history() {
+        let repo = setup_repo().await;
+        let created = repo
+            .create_user(request("Jane Doe", "jane@example.com"))
+            .await
+            .expect("Failed to create user");
+        assert!(created.active);
+        assert!(!created.email_verified);
+        assert_eq!(repo.users_version().await.unwrap(), 1);
+
+        let fetched = repo
+            .get_user_by_id(created.user_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.public_id, created.public_id);
+        assert_eq!(created.public_id.get_version_num(), 7);
+        assert_eq!(fetched.created_at, created.created_at);
+
+        // Setting the current state again changes nothing
+        let same = repo
+            .set_user_active(created.user_id(), true)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(same.updated_at, created.updated_at);
+
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let updated = repo
+            .update_user(
+                created.user_id(),
+                UpdateUserRequest {
+                    name: Some("Jane Smith".to_string()),
+                    bio: Some(Some("Hello".to_string())),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.name, "Jane Smith");
+        assert_eq!(updated.bio.as_deref(), Some("Hello"));
+        assert_eq!(updated.email, "jane@example.com");
+        assert!(updated.updated_at > created.updated_at);
+
+        let history = repo.get_user_history(created.user_id()).await.unwrap();
+        let operations: Vec<_> = history.iter().map(|h| h.operation.as_str()).collect();
+        assert_eq!(operations, ["INSERT", "UPDATE"]);
+        assert_eq!(history[1].version, 2);
+        assert_eq!(history[0].data["name"], "Jane Doe");
+        assert_eq!(history[1].data["name"], "Jane Smith");
+        assert_eq!(history[1].data["active"], true);
+        assert_eq!(history[1].data["public_id"], created.public_id.to_string());
+        assert_eq!(history[1].changed_by, None);
+    }
+
+    #[tokio::test]
+    async fn test_emails_are_unique_ignoring_case_among_live_users() {
+        let repo = setup_repo().await;
+        let first = repo
+            .create_user(request("First", "Same@Example.com"))
+            .await
+            .unwrap();
+        assert!(repo
+            .create_user(request("Second", "same@example.com"))
+            .await
+            .is_err());
+        assert!(repo.email_exists("SAME@example.COM").await.unwrap());
+
+        assert!(repo.delete_user(first.user_id()).await.unwrap());
+        assert!(!repo.delete_user(first.user_id()).await.unwrap());
+        assert!(repo
+            .get_user_by_id(first.user_id())
+            .await
+            .unwrap()
+            .is_none());
+        let deleted = repo
+            .get_user_including_deleted(first.user_id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(deleted.deleted_at.is_some());
+
+        let imported = repo
+            .import_users(vec![
+                request("Second", "same@example.com"),
+                request("Third", "SAME@EXAMPLE.COM"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(imported.len(), 1);
+        // The address is live again, so the deleted user cannot come back
+        assert!(repo.restore_user(first.user_id()).await.is_err());
+
+        assert!(repo.purge_user(imported[0].user_id()).await.unwrap());
+        let restored = repo.restore_user(first.user_id()).await.unwrap().unwrap();
+        assert!(restored.deleted_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_new_email_must_be_verified_again() {
+        let repo = setup_repo().await;
+        let user = repo
+            .create_user(request("Verified", "verified@example.com"))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE test_users SET email_verified = TRUE WHERE id = ?")
+            .bind(user.id)
+            .execute(&repo.pool)
+            .await
+            .unwrap();
+
+        let update = |email: &str| UpdateUserRequest {
+            email: Some(email.to_string()),
+            ..Default::default()
+        };
+        let recased = repo
+            .update_user(user.user_id(), update("Verified@Example.com"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(recased.email_verified);
+        let moved = repo
+            .update_user(user.user_id(), update("moved@example.com"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!moved.email_verified);
+    }
+
+    #[tokio::test]
+    async fn test_list_filter_search_and_stream() {
+        use futures::TryStreamExt;
+
+        let repo = setup_repo().await;
+        let users = repo
+            .import_users(vec![
+                request("Alice Anders", "alice@example.com"),
+                request("Bob Baker", "bob@alice.example.com"),
+                request("Carol 100%", "carol@example.com"),
+            ])
+            .await
+            .unwrap();
+        repo.set_user_active(users[1].user_id(), false)
+            .await
+            .unwrap();
+
+        let all = UserListQuery::default();
+        assert_eq!(repo.count_users(&all).await.unwrap(), 3);
+        let inactive = UserListQuery {
+            active: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            repo.list_users_page(&inactive, 0, None).await.unwrap()[0].name,
+            "Bob Baker"
+        );
+        let percent = UserListQuery {
+            name_contains: Some("0%".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(repo.count_users(&percent).await.unwrap(), 1);
+
+        // Same created_at in one statement: the id breaks the tie
+        let first = repo.list_users_after(&all, None, 2).await.unwrap();
+        assert_eq!(first.len(), 2);
+        let last = &first[1];
+        let cursor = Cursor {
+            created_at: last.created_at,
+            id: last.id,
+        };
+        let rest = repo.list_users_after(&all, Some(cursor), 2).await.unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].id, users[0].id);
+
+        let found = repo.search_users("ALICE", 10).await.unwrap();
+        let names: Vec<_> = found.iter().map(|user| user.name.as_str()).collect();
+        assert_eq!(names, ["Alice Anders", "Bob Baker"]);
+        assert!(repo
+            .search_users("alice carol", 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let streamed: Vec<User> = repo.stream_users(&all).try_collect().await.unwrap();
+        let ids: Vec<_> = streamed.iter().map(|user| user.id).collect();
+        assert_eq!(ids, users.iter().map(|user| user.id).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,138 @@
+use sqlx::PgPool;
+
+use crate::models::tenant::{CreateTenantRequest, Tenant, UpdateTenantRequest};
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+
+/// Tenants users belong to
+///
+/// Unlike the other repositories this one is not scoped to the current
+/// tenant: it is what resolves the tenant in the first place.
+#[async_trait::async_trait]
+pub trait TenantRepositoryTrait {
+    async fn list(&self) -> Result<Vec<Tenant>, sqlx::Error>;
+    async fn get(&self, id: i32) -> Result<Option<Tenant>, sqlx::Error>;
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<Tenant>, sqlx::Error>;
+    async fn create(&self, tenant: &CreateTenantRequest) -> Result<Tenant, sqlx::Error>;
+    async fn update(
+        &self,
+        id: i32,
+        tenant: &UpdateTenantRequest,
+    ) -> Result<Option<Tenant>, sqlx::Error>;
+}
+
+/// Tenant repository implementation with PostgreSQL
+pub struct TenantRepository {
+    pool: PgPool,
+}
+
+impl TenantRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl TenantRepositoryTrait for TenantRepository {
+    /// Every tenant, oldest first
+    async fn list(&self) -> Result<Vec<Tenant>, sqlx::Error> {
+        resilient("tenants.list", move || async move {
+            sqlx::query_as!(
+                Tenant,
+                r#"
+                SELECT id, slug, name, active, created_at, updated_at
+                FROM tenants
+                ORDER BY id
+                "#
+            )
+            .fetch_all(&self.pool)
+            .timed("tenants.list")
+            .await
+        })
+        .await
+    }
+
+    async fn get(&self, id: i32) -> Result<Option<Tenant>, sqlx::Error> {
+        resilient("tenants.get", move || async move {
+            sqlx::query_as!(
+                Tenant,
+                r#"
+                SELECT id, slug, name, active, created_at, updated_at
+                FROM tenants
+                WHERE id = $1
+                "#,
+                id
+            )
+            .fetch_optional(&self.pool)
+            .timed("tenants.get")
+            .await
+        })
+        .await
+    }
+
+    async fn find_by_slug(&self, slug: &str) -> Result<Option<Tenant>, sqlx::Error> {
+        resilient("tenants.find_by_slug", move || async move {
+            sqlx::query_as!(
+                Tenant,
+                r#"
+                SELECT id, slug, name, active, created_at, updated_at
+                FROM tenants
+                WHERE slug = $1
+                "#,
+                slug
+            )
+            .fetch_optional(&self.pool)
+            .timed("tenants.find_by_slug")
+            .await
+        })
+        .await
+    }
+
+    /// Create a tenant; a taken slug is a unique violation
+    async fn create(&self, tenant: &CreateTenantRequest) -> Result<Tenant, sqlx::Error> {
+        resilient_write("tenants.create", move || async move {
+            sqlx::query_as!(
+                Tenant,
+                r#"
+                INSERT INTO tenants (slug, name)
+                VALUES ($1, $2)
+                RETURNING id, slug, name, active, created_at, updated_at
+                "#,
+                tenant.slug,
+                tenant.name
+            )
+            .fetch_one(&self.pool)
+            .timed("tenants.create")
+            .await
+        })
+        .await
+    }
+
+    /// Rename, suspend or reactivate a tenant; None when there is no such tenant
+    async fn update(
+        &self,
+        id: i32,
+        tenant: &UpdateTenantRequest,
+    ) -> Result<Option<Tenant>, sqlx::Error> {
+        resilient("tenants.update", move || async move {
+            sqlx::query_as!(
+                Tenant,
+                r#"
+                UPDATE tenants
+                SET name = COALESCE($2, name),
+                    active = COALESCE($3, active),
+                    updated_at = NOW()
+                WHERE id = $1
+                RETURNING id, slug, name, active, created_at, updated_at
+                "#,
+                id,
+                tenant.name,
+                tenant.active
+            )
+            .fetch_optional(&self.pool)
+            .timed("tenants.update")
+            .await
+        })
+        .await
+    }
+}
@@ -0,0 +1,61 @@
+//! Statement timings of the repositories
+//!
+//! Every repository query is awaited through [`Timed::timed`] with a
+//! `<repository>.<method>` name. The duration goes to the
+//! `db_query_duration_seconds` histogram of `GET /metrics`, and statements
+//! slower than SLOW_QUERY_MS (500 by default) are logged as warnings.
+
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use metrics::{counter, histogram};
+
+/// Time to run a statement, by statement name
+pub const DB_QUERY_DURATION: &str = "db_query_duration_seconds";
+/// Statements slower than SLOW_QUERY_MS, by statement name
+pub const DB_SLOW_QUERIES_TOTAL: &str = "db_slow_queries_total";
+
+/// Statements taking longer than this are logged
+pub fn slow_query_threshold() -> Duration {
+    static THRESHOLD: OnceLock<Duration> = OnceLock::new();
+    *THRESHOLD.get_or_init(|| {
+        let millis = std::env::var("SLOW_QUERY_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(500);
+        Duration::from_millis(millis)
+    })
+}
+
+/// Record that `statement` took `elapsed`
+pub fn record(statement: &'static str, elapsed: Duration) {
+    histogram!(DB_QUERY_DURATION, elapsed.as_secs_f64(), "statement" => statement);
+    if elapsed >= slow_query_threshold() {
+        counter!(DB_SLOW_QUERIES_TOTAL, 1, "statement" => statement);
+        tracing::warn!(
+            statement,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "Slow query {} took {:?}",
+            statement,
+            elapsed
+        );
+    }
+}
+
+/// Time a query future under a statement name
+pub trait Timed: Future + Sized {
+    fn timed(self, statement: &'static str) -> impl Future<Output = Self::Output> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            let started = Instant::now();
+            let output = self.await;
+            record(statement, started.elapsed());
+            output
+        }
+    }
+}
+
+impl<F: Future> Timed for F {}
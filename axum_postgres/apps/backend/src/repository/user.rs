@@ -1,24 +1,181 @@
-use sqlx::PgPool;
-use crate::models::user::{User, CreateUserRequest, UpdateUserRequest};
+use futures::stream::BoxStream;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use crate::database::DbPools;
+use crate::models::user::{
+    User, CreateUserRequest, SortOrder, UpdateUserRequest, UserListQuery, UserSortField,
+};
+use crate::models::user_history::UserHistoryRecord;
+use crate::models::user_id::UserId;
+use crate::pagination::Cursor;
+use crate::repository::resilience::{is_transient, resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
 
 /// User repository trait for database operations
 #[async_trait::async_trait]
 pub trait UserRepositoryTrait {
     async fn create_user(&self, user: CreateUserRequest) -> Result<User, sqlx::Error>;
-    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, sqlx::Error>;
+    async fn import_users(&self, users: Vec<CreateUserRequest>) -> Result<Vec<User>, sqlx::Error>;
+    async fn get_user_by_id(&self, id: UserId) -> Result<Option<User>, sqlx::Error>;
+    async fn get_user_by_key(&self, key: i32) -> Result<Option<User>, sqlx::Error>;
+    async fn exists(&self, id: UserId) -> Result<bool, sqlx::Error>;
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, sqlx::Error>;
+    async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error>;
     async fn list_users(&self) -> Result<Vec<User>, sqlx::Error>;
-    async fn update_user(&self, id: i32, user: UpdateUserRequest) -> Result<Option<User>, sqlx::Error>;
-    async fn delete_user(&self, id: i32) -> Result<bool, sqlx::Error>;
+    async fn list_users_page(&self, query: &UserListQuery, offset: i64, limit: Option<i64>) -> Result<Vec<User>, sqlx::Error>;
+    async fn list_users_after(&self, query: &UserListQuery, after: Option<Cursor>, limit: i64) -> Result<Vec<User>, sqlx::Error>;
+    async fn count_users(&self, query: &UserListQuery) -> Result<i64, sqlx::Error>;
+    async fn search_users(&self, terms: &str, limit: i64) -> Result<Vec<User>, sqlx::Error>;
+    async fn users_version(&self) -> Result<i64, sqlx::Error>;
+    async fn update_user(&self, id: UserId, user: UpdateUserRequest) -> Result<Option<User>, sqlx::Error>;
+    async fn set_user_active(&self, id: UserId, active: bool) -> Result<Option<User>, sqlx::Error>;
+    async fn delete_user(&self, id: UserId) -> Result<bool, sqlx::Error>;
+    async fn purge_user(&self, id: UserId) -> Result<bool, sqlx::Error>;
+    async fn restore_user(&self, id: UserId) -> Result<Option<User>, sqlx::Error>;
+    async fn get_user_including_deleted(&self, id: UserId) -> Result<Option<User>, sqlx::Error>;
+    async fn get_user_history(&self, id: UserId) -> Result<Vec<UserHistoryRecord>, sqlx::Error>;
+    fn stream_users<'a>(&'a self, query: &UserListQuery) -> BoxStream<'a, Result<User, sqlx::Error>>;
 }
 
 /// User repository implementation with PostgreSQL
+///
+/// Read-only methods query the replica pool; built from a single pool,
+/// everything goes to it. Every query is scoped to the tenant that was
+/// current when the repository was built.
 pub struct UserRepository {
-    pool: PgPool,
+    pools: DbPools,
+    tenant_id: i32,
 }
 
 impl UserRepository {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pools: impl Into<DbPools>) -> Self {
+        Self {
+            pools: pools.into(),
+            tenant_id: tenant::current(),
+        }
+    }
+
+    /// Scope the queries to another tenant than the current one
+    pub fn in_tenant(mut self, tenant_id: i32) -> Self {
+        self.tenant_id = tenant_id;
+        self
+    }
+
+    fn reader(&self) -> &PgPool {
+        &self.pools.replica
+    }
+
+    fn writer(&self) -> &PgPool {
+        &self.pools.primary
+    }
+
+    /// Serial row key of the user with this id, deleted or not
+    ///
+    /// The queries below all key on the serial `id`; with `uuid-ids` the
+    /// client's id is looked up first, so None means no such user.
+    #[cfg(not(feature = "uuid-ids"))]
+    async fn row_key(&self, _pool: &PgPool, id: UserId) -> Result<Option<i32>, sqlx::Error> {
+        Ok(Some(id.get()))
+    }
+
+    #[cfg(feature = "uuid-ids")]
+    async fn row_key(&self, pool: &PgPool, id: UserId) -> Result<Option<i32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT id FROM test_users WHERE public_id = $1 AND tenant_id = $2
+            "#,
+            id.get(),
+            self.tenant_id
+        )
+        .fetch_optional(pool)
+        .timed("user.row_key")
+        .await
+    }
+
+    /// Import through a temporary table filled with `COPY ... FROM STDIN`
+    ///
+    /// One round trip per [`COPY_CHUNK_BYTES`] of CSV instead of one
+    /// statement per [`IMPORT_BATCH_SIZE`] rows, and no bind parameters to
+    /// parse; the final INSERT skips taken emails like
+    /// [`Self::insert_users`].
+    async fn copy_users(&self, users: &[CreateUserRequest]) -> Result<Vec<User>, sqlx::Error> {
+        let mut tx = self.writer().begin().await?;
+        sqlx::query(
+            "CREATE TEMP TABLE user_import \
+             (name text, email text, display_name text, bio text, phone text, timezone text, locale text) \
+             ON COMMIT DROP",
+        )
+        .execute(&mut *tx)
+        .timed("user.copy_users")
+        .await?;
+
+        let mut copy = tx
+            .copy_in_raw(
+                "COPY user_import (name, email, display_name, bio, phone, timezone, locale) \
+                 FROM STDIN WITH (FORMAT csv)",
+            )
+            .await?;
+        let mut chunk = Vec::with_capacity(COPY_CHUNK_BYTES);
+        for user in users {
+            copy_row(&mut chunk, user);
+            if chunk.len() >= COPY_CHUNK_BYTES {
+                copy.send(std::mem::take(&mut chunk)).await?;
+            }
+        }
+        if !chunk.is_empty() {
+            copy.send(chunk).await?;
+        }
+        copy.finish().timed("user.copy_users").await?;
+
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "INSERT INTO test_users (tenant_id, name, email, display_name, bio, phone, timezone, locale) SELECT ",
+        );
+        builder
+            .push_bind(self.tenant_id)
+            .push(
+                ", name, email, display_name, bio, phone, timezone, locale FROM user_import \
+                 ON CONFLICT (tenant_id, LOWER(email)) WHERE deleted_at IS NULL DO NOTHING RETURNING ",
+            )
+            .push(USER_COLUMNS);
+        let created = builder
+            .build_query_as::<User>()
+            .fetch_all(&mut *tx)
+            .timed("user.copy_users")
+            .await?;
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    /// Import with multi-row INSERTs of [`IMPORT_BATCH_SIZE`] users
+    async fn insert_users(&self, users: &[CreateUserRequest]) -> Result<Vec<User>, sqlx::Error> {
+        let mut tx = self.writer().begin().await?;
+        let mut created = Vec::with_capacity(users.len());
+        for batch in users.chunks(IMPORT_BATCH_SIZE) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                "INSERT INTO test_users (tenant_id, name, email, display_name, bio, phone, timezone, locale) ",
+            );
+            builder.push_values(batch, |mut row, user| {
+                row.push_bind(self.tenant_id)
+                    .push_bind(user.name.clone())
+                    .push_bind(user.email.clone())
+                    .push_bind(user.display_name.clone())
+                    .push_bind(user.bio.clone())
+                    .push_bind(user.phone.clone())
+                    .push_bind(user.timezone.clone())
+                    .push_bind(user.locale.clone());
+            });
+            builder
+                .push(" ON CONFLICT (tenant_id, LOWER(email)) WHERE deleted_at IS NULL DO NOTHING RETURNING ")
+                .push(USER_COLUMNS);
+            let inserted = builder
+                .build_query_as::<User>()
+                .fetch_all(&mut *tx)
+                .timed("user.insert_users")
+                .await?;
+            created.extend(inserted);
+        }
+        tx.commit().await?;
+        Ok(created)
     }
 }
 
@@ -26,184 +183,588 @@ impl UserRepository {
 impl UserRepositoryTrait for UserRepository {
     /// Create a new user
     async fn create_user(&self, user: CreateUserRequest) -> Result<User, sqlx::Error> {
-        sqlx::query_as!(
-            User,
-            r#"
-            INSERT INTO test_users (name, email) 
-            VALUES ($1, $2) 
-            RETURNING id, name, email, active, created_at
-            "#,
-            user.name,
-            user.email
-        )
-        .fetch_one(&self.pool)
+        let user = &user;
+        resilient_write("user.create_user", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                INSERT INTO test_users (name, email, display_name, bio, phone, timezone, locale, tenant_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                user.name,
+                user.email,
+                user.display_name,
+                user.bio,
+                user.phone,
+                user.timezone,
+                user.locale,
+                self.tenant_id
+            )
+            .fetch_one(self.writer())
+            .timed("user.create_user")
+            .await
+        })
+        .await
+    }
+
+    /// Insert many users in one transaction
+    ///
+    /// A user whose email already belongs to a live user, in any letter
+    /// case, is skipped instead of failing the batch; only the inserted
+    /// users are returned. Users are loaded with COPY, falling back to
+    /// batched INSERTs when COPY fails for any reason but a lost connection.
+    async fn import_users(&self, users: Vec<CreateUserRequest>) -> Result<Vec<User>, sqlx::Error> {
+        let users = &users;
+        resilient_write("user.import_users", move || async move {
+            match self.copy_users(users).await {
+                Err(e) if !is_transient(&e) => {
+                    tracing::warn!("COPY import failed, inserting in batches instead: {}", e);
+                    self.insert_users(users).await
+                }
+                result => result,
+            }
+        })
         .await
     }
 
     /// Get user by ID
-    async fn get_user_by_id(&self, id: i32) -> Result<Option<User>, sqlx::Error> {
-        sqlx::query_as!(
-            User,
-            r#"
-            SELECT id, name, email, active, created_at 
-            FROM test_users 
-            WHERE id = $1
-            "#,
-            id
-        )
-        .fetch_optional(&self.pool)
+    async fn get_user_by_id(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        resilient("user.get_user_by_id", move || async move {
+            let Some(key) = self.row_key(self.reader(), id).await? else {
+                return Ok(None);
+            };
+            self.get_user_by_key(key).await
+        })
+        .await
+    }
+
+    /// Get user by serial row key, as other tables refer to users
+    async fn get_user_by_key(&self, key: i32) -> Result<Option<User>, sqlx::Error> {
+        resilient("user.get_user_by_key", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at 
+                FROM test_users 
+                WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NULL
+                "#,
+                key,
+                self.tenant_id
+            )
+            .fetch_optional(self.reader())
+            .timed("user.get_user_by_key")
+            .await
+        })
+        .await
+    }
+
+    /// Whether a live user has this ID, without loading it
+    async fn exists(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        resilient("user.exists", move || async move {
+            let Some(key) = self.row_key(self.reader(), id).await? else {
+                return Ok(false);
+            };
+            sqlx::query_scalar!(
+                r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM test_users WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NULL
+                ) AS "exists!"
+                "#,
+                key,
+                self.tenant_id
+            )
+            .fetch_one(self.reader())
+            .timed("user.exists")
+            .await
+        })
+        .await
+    }
+
+    /// Find user by email, ignoring case
+    async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, sqlx::Error> {
+        resilient("user.find_user_by_email", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                FROM test_users
+                WHERE LOWER(email) = LOWER($1) AND tenant_id = $2 AND deleted_at IS NULL
+                "#,
+                email,
+                self.tenant_id
+            )
+            .fetch_optional(self.reader())
+            .timed("user.find_user_by_email")
+            .await
+        })
+        .await
+    }
+
+    /// Whether a live user has this email, ignoring case
+    async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error> {
+        resilient("user.email_exists", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM test_users
+                    WHERE LOWER(email) = LOWER($1) AND tenant_id = $2 AND deleted_at IS NULL
+                ) AS "exists!"
+                "#,
+                email,
+                self.tenant_id
+            )
+            .fetch_one(self.reader())
+            .timed("user.email_exists")
+            .await
+        })
         .await
     }
 
     /// List all users ordered by created_at desc
     async fn list_users(&self) -> Result<Vec<User>, sqlx::Error> {
-        sqlx::query_as!(
-            User,
-            r#"
-            SELECT id, name, email, active, created_at 
-            FROM test_users 
-            ORDER BY created_at DESC
-            "#
-        )
-        .fetch_all(&self.pool)
+        resilient("user.list_users", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at 
+                FROM test_users 
+                WHERE tenant_id = $1 AND deleted_at IS NULL
+                ORDER BY created_at DESC, id DESC
+                "#,
+                self.tenant_id
+            )
+            .fetch_all(self.reader())
+            .timed("user.list_users")
+            .await
+        })
         .await
     }
 
-    /// Update user by ID
-    async fn update_user(&self, id: i32, user: UpdateUserRequest) -> Result<Option<User>, sqlx::Error> {
-        // Use pattern matching to handle all possible combinations
-        // This is more verbose but type-safe with sqlx macros
-        match (&user.name, &user.email, user.active) {
-            (Some(name), Some(email), Some(active)) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET name = $1, email = $2, active = $3 
-                    WHERE id = $4 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    name,
-                    email,
-                    active,
-                    id
-                )
-                .fetch_optional(&self.pool)
+    /// List matching users in the requested order, skipping `offset`
+    ///
+    /// A `limit` of None returns every remaining user.
+    async fn list_users_page(&self, query: &UserListQuery, offset: i64, limit: Option<i64>) -> Result<Vec<User>, sqlx::Error> {
+        resilient("user.list_users_page", move || async move {
+            let mut builder = filtered(USER_COLUMNS, self.tenant_id, query);
+            builder
+                .push(" ORDER BY ")
+                .push(order_by(query))
+                .push(" OFFSET ")
+                .push_bind(offset)
+                // LIMIT NULL is LIMIT ALL
+                .push(" LIMIT ")
+                .push_bind(limit);
+            builder
+                .build_query_as::<User>()
+                .fetch_all(self.reader())
+                .timed("user.list_users_page")
                 .await
+        })
+        .await
+    }
+
+    /// List up to `limit` matching users following `after`, newest first
+    ///
+    /// Keyset pagination: seeks on `(created_at, id)` instead of skipping rows.
+    async fn list_users_after(&self, query: &UserListQuery, after: Option<Cursor>, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+        resilient("user.list_users_after", move || async move {
+            let mut builder = filtered(USER_COLUMNS, self.tenant_id, query);
+            if let Some(after) = after {
+                builder
+                    .push(" AND (created_at, id) < (")
+                    .push_bind(after.created_at)
+                    .push(", ")
+                    .push_bind(after.id)
+                    .push(")");
             }
-            (Some(name), Some(email), None) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET name = $1, email = $2 
-                    WHERE id = $3 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    name,
-                    email,
-                    id
-                )
-                .fetch_optional(&self.pool)
+            builder
+                .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+                .push_bind(limit);
+            builder
+                .build_query_as::<User>()
+                .fetch_all(self.reader())
+                .timed("user.list_users_after")
                 .await
-            }
-            (Some(name), None, Some(active)) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET name = $1, active = $2 
-                    WHERE id = $3 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    name,
-                    active,
-                    id
-                )
-                .fetch_optional(&self.pool)
+        })
+        .await
+    }
+
+    /// Count matching users
+    async fn count_users(&self, query: &UserListQuery) -> Result<i64, sqlx::Error> {
+        resilient("user.count_users", move || async move {
+            filtered("COUNT(*)", self.tenant_id, query)
+                .build_query_scalar::<i64>()
+                .fetch_one(self.reader())
+                .timed("user.count_users")
                 .await
+        })
+        .await
+    }
+
+    /// Full-text search over name and email, best matches first
+    ///
+    /// `terms` use web search syntax: words, `"quoted phrases"`, `or` and `-word`.
+    async fn search_users(&self, terms: &str, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+        resilient("user.search_users", move || async move {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                FROM test_users, websearch_to_tsquery('simple', $1) AS query
+                WHERE search_vector @@ query AND tenant_id = $3 AND deleted_at IS NULL
+                ORDER BY ts_rank(search_vector, query) DESC, id DESC
+                LIMIT $2
+                "#,
+                terms,
+                limit,
+                self.tenant_id
+            )
+            .fetch_all(self.reader())
+            .timed("user.search_users")
+            .await
+        })
+        .await
+    }
+
+    /// Change counter of test_users, bumped by every writing statement
+    async fn users_version(&self) -> Result<i64, sqlx::Error> {
+        resilient("user.users_version", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                SELECT version FROM collection_versions WHERE name = 'test_users'
+                "#
+            )
+            .fetch_one(self.reader())
+            .timed("user.users_version")
+            .await
+        })
+        .await
+    }
+
+    /// Update user by ID
+    ///
+    /// Only the fields present in `user` are written; each optional field
+    /// is one `set` line below.
+    async fn update_user(&self, id: UserId, user: UpdateUserRequest) -> Result<Option<User>, sqlx::Error> {
+        let user = &user;
+        resilient_write("user.update_user", move || async move {
+            let Some(key) = self.row_key(self.writer(), id).await? else {
+                return Ok(None);
+            };
+            let mut builder = QueryBuilder::<Postgres>::new("UPDATE test_users SET ");
+            let mut fields = builder.separated(", ");
+            let mut any = false;
+            if let Some(name) = &user.name {
+                fields.push("name = ").push_bind_unseparated(name);
+                any = true;
             }
-            (None, Some(email), Some(active)) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET email = $1, active = $2 
-                    WHERE id = $3 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    email,
-                    active,
-                    id
-                )
-                .fetch_optional(&self.pool)
-                .await
+            if let Some(email) = &user.email {
+                fields.push("email = ").push_bind_unseparated(email);
+                any = true;
             }
-            (Some(name), None, None) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET name = $1 
-                    WHERE id = $2 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    name,
-                    id
-                )
-                .fetch_optional(&self.pool)
-                .await
+            if let Some(active) = &user.active {
+                fields.push("active = ").push_bind_unseparated(active);
+                any = true;
             }
-            (None, Some(email), None) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET email = $1 
-                    WHERE id = $2 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    email,
-                    id
-                )
-                .fetch_optional(&self.pool)
-                .await
+            // Profile fields: Some(None) writes NULL
+            if let Some(display_name) = &user.display_name {
+                fields.push("display_name = ").push_bind_unseparated(display_name);
+                any = true;
             }
-            (None, None, Some(active)) => {
-                sqlx::query_as!(
-                    User,
-                    r#"
-                    UPDATE test_users 
-                    SET active = $1 
-                    WHERE id = $2 
-                    RETURNING id, name, email, active, created_at
-                    "#,
-                    active,
-                    id
-                )
-                .fetch_optional(&self.pool)
-                .await
+            if let Some(bio) = &user.bio {
+                fields.push("bio = ").push_bind_unseparated(bio);
+                any = true;
+            }
+            if let Some(phone) = &user.phone {
+                fields.push("phone = ").push_bind_unseparated(phone);
+                any = true;
             }
-            (None, None, None) => {
+            if let Some(timezone) = &user.timezone {
+                fields.push("timezone = ").push_bind_unseparated(timezone);
+                any = true;
+            }
+            if let Some(locale) = &user.locale {
+                fields.push("locale = ").push_bind_unseparated(locale);
+                any = true;
+            }
+
+            if !any {
                 // No updates, return current user
-                self.get_user_by_id(id).await
+                return self.get_user_by_id(id).await;
             }
-        }
+
+            builder
+                .push(" WHERE id = ")
+                .push_bind(key)
+                .push(" AND tenant_id = ")
+                .push_bind(self.tenant_id)
+                .push(" AND deleted_at IS NULL RETURNING ")
+                .push(USER_COLUMNS);
+            builder
+                .build_query_as::<User>()
+                .fetch_optional(self.writer())
+                .timed("user.update_user")
+                .await
+        })
+        .await
+    }
+
+    /// Activate or deactivate a live user, leaving every other field alone
+    ///
+    /// Setting the current state again is a no-op: no history entry is
+    /// written and `updated_at` stays put.
+    async fn set_user_active(&self, id: UserId, active: bool) -> Result<Option<User>, sqlx::Error> {
+        resilient("user.set_user_active", move || async move {
+            let Some(key) = self.row_key(self.writer(), id).await? else {
+                return Ok(None);
+            };
+            sqlx::query_as!(
+                User,
+                r#"
+                UPDATE test_users
+                SET active = $2
+                WHERE id = $1 AND tenant_id = $3 AND deleted_at IS NULL
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                key,
+                active,
+                self.tenant_id
+            )
+            .fetch_optional(self.writer())
+            .timed("user.set_user_active")
+            .await
+        })
+        .await
+    }
+
+    /// Soft-delete user by ID; false when missing or already deleted
+    async fn delete_user(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        resilient_write("user.delete_user", move || async move {
+            let Some(key) = self.row_key(self.writer(), id).await? else {
+                return Ok(false);
+            };
+            let result = sqlx::query!(
+                r#"
+                UPDATE test_users
+                SET deleted_at = NOW()
+                WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NULL
+                "#,
+                key,
+                self.tenant_id
+            )
+            .execute(self.writer())
+            .timed("user.delete_user")
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Permanently remove a user, deleted or not
+    async fn purge_user(&self, id: UserId) -> Result<bool, sqlx::Error> {
+        resilient_write("user.purge_user", move || async move {
+            let Some(key) = self.row_key(self.writer(), id).await? else {
+                return Ok(false);
+            };
+            let result = sqlx::query!(
+                r#"
+                DELETE FROM test_users
+                WHERE id = $1 AND tenant_id = $2
+                "#,
+                key,
+                self.tenant_id
+            )
+            .execute(self.writer())
+            .timed("user.purge_user")
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Undo a soft delete; None when the user is not deleted or does not exist
+    async fn restore_user(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        resilient_write("user.restore_user", move || async move {
+            let Some(key) = self.row_key(self.writer(), id).await? else {
+                return Ok(None);
+            };
+            sqlx::query_as!(
+                User,
+                r#"
+                UPDATE test_users
+                SET deleted_at = NULL
+                WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NOT NULL
+                RETURNING id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                "#,
+                key,
+                self.tenant_id
+            )
+            .fetch_optional(self.writer())
+            .timed("user.restore_user")
+            .await
+        })
+        .await
+    }
+
+    /// Get user by ID, including soft-deleted users
+    async fn get_user_including_deleted(&self, id: UserId) -> Result<Option<User>, sqlx::Error> {
+        resilient("user.get_user_including_deleted", move || async move {
+            let Some(key) = self.row_key(self.reader(), id).await? else {
+                return Ok(None);
+            };
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+                FROM test_users
+                WHERE id = $1 AND tenant_id = $2
+                "#,
+                key,
+                self.tenant_id
+            )
+            .fetch_optional(self.reader())
+            .timed("user.get_user_including_deleted")
+            .await
+        })
+        .await
+    }
+
+    /// Get every recorded version of a user, oldest first
+    ///
+    /// Matched on the tenant in the snapshots, so the history of a purged
+    /// user stays readable.
+    async fn get_user_history(&self, id: UserId) -> Result<Vec<UserHistoryRecord>, sqlx::Error> {
+        resilient("user.get_user_history", move || async move {
+            let Some(key) = self.row_key(self.reader(), id).await? else {
+                return Ok(Vec::new());
+            };
+            sqlx::query_as!(
+                UserHistoryRecord,
+                r#"
+                SELECT version, operation, data, changed_by, changed_at
+                FROM test_users_history
+                WHERE user_id = $1
+                  -- Snapshots older than tenants belong to the default tenant
+                  AND COALESCE((data->>'tenant_id')::int, 1) = $2
+                ORDER BY version
+                "#,
+                key,
+                self.tenant_id
+            )
+            .fetch_all(self.reader())
+            .timed("user.get_user_history")
+            .await
+        })
+        .await
     }
 
-    /// Delete user by ID
-    async fn delete_user(&self, id: i32) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query!(
+    /// Stream matching users in id order, one row at a time
+    ///
+    /// Rows are read from the open cursor as the stream is polled, so it
+    /// holds one connection until it ends. Neither retried nor timed: a
+    /// stream cannot restart halfway, and exports are expected to be slow.
+    /// Sort parameters are ignored.
+    fn stream_users<'a>(&'a self, query: &UserListQuery) -> BoxStream<'a, Result<User, sqlx::Error>> {
+        sqlx::query_as!(
+            User,
             r#"
-            DELETE FROM test_users 
-            WHERE id = $1
+            SELECT id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at
+            FROM test_users
+            WHERE ($1 OR deleted_at IS NULL)
+              AND ($2::bool IS NULL OR active = $2)
+              AND ($3::text IS NULL OR email ILIKE $3)
+              AND ($4::text IS NULL OR name ILIKE $4)
+              AND tenant_id = $5
+            ORDER BY id
             "#,
-            id
+            query.include_deleted,
+            query.active,
+            query.email_contains.as_deref().map(contains_pattern),
+            query.name_contains.as_deref().map(contains_pattern),
+            self.tenant_id
         )
-        .execute(&self.pool)
-        .await?;
+        .fetch(self.reader())
+    }
+}
+
+/// Rows per INSERT of an import, within Postgres' 65535 bind parameters
+pub(super) const IMPORT_BATCH_SIZE: usize = 1000;
 
-        Ok(result.rows_affected() > 0)
+/// CSV sent per message of a COPY import
+const COPY_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Append `user` as a COPY CSV line: missing fields are left empty, which
+/// COPY reads as NULL, and the others quoted so an empty string stays one
+fn copy_row(line: &mut Vec<u8>, user: &CreateUserRequest) {
+    let fields = [
+        Some(user.name.as_str()),
+        Some(user.email.as_str()),
+        user.display_name.as_deref(),
+        user.bio.as_deref(),
+        user.phone.as_deref(),
+        user.timezone.as_deref(),
+        user.locale.as_deref(),
+    ];
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            line.push(b',');
+        }
+        if let Some(field) = field {
+            line.push(b'"');
+            line.extend_from_slice(field.replace('"', "\"\"").as_bytes());
+            line.push(b'"');
+        }
+    }
+    line.push(b'\n');
+}
+
+const USER_COLUMNS: &str = "id, public_id, name, email, active, display_name, bio, phone, timezone, locale, email_verified, created_at, updated_at, deleted_at";
+
+/// `SELECT <columns> FROM test_users` with the tenant's users matching the
+/// filters of `query`
+///
+/// Filter values are bound as parameters, never spliced into the SQL.
+fn filtered<'a>(columns: &str, tenant_id: i32, query: &'a UserListQuery) -> QueryBuilder<'a, Postgres> {
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM test_users WHERE tenant_id = ", columns));
+    builder.push_bind(tenant_id);
+    if !query.include_deleted {
+        builder.push(" AND deleted_at IS NULL");
+    }
+    if let Some(active) = query.active {
+        builder.push(" AND active = ").push_bind(active);
+    }
+    if let Some(text) = &query.email_contains {
+        builder.push(" AND email ILIKE ").push_bind(contains_pattern(text));
+    }
+    if let Some(text) = &query.name_contains {
+        builder.push(" AND name ILIKE ").push_bind(contains_pattern(text));
+    }
+    builder
+}
+
+/// ILIKE pattern matching `text` anywhere, with its wildcards escaped
+pub(super) fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// ORDER BY clause from the whitelisted sort field and direction
+///
+/// `id` breaks ties so pages are stable.
+pub(super) fn order_by(query: &UserListQuery) -> &'static str {
+    match (query.sort_field(), query.sort_order()) {
+        (UserSortField::Name, SortOrder::Asc) => "name ASC, id ASC",
+        (UserSortField::Name, SortOrder::Desc) => "name DESC, id DESC",
+        (UserSortField::Email, SortOrder::Asc) => "email ASC, id ASC",
+        (UserSortField::Email, SortOrder::Desc) => "email DESC, id DESC",
+        (UserSortField::CreatedAt, SortOrder::Asc) => "created_at ASC, id ASC",
+        (UserSortField::CreatedAt, SortOrder::Desc) => "created_at DESC, id DESC",
     }
 }
 
@@ -218,22 +779,53 @@ mod tests {
         create_pool_from_env().await.expect("Failed to create test pool")
     }
 
+    /// An id that no user has, in either id format
+    fn missing_user_id() -> UserId {
+        let id = if cfg!(feature = "uuid-ids") {
+            "00000000-0000-7000-8000-000000000000"
+        } else {
+            "99999"
+        };
+        id.parse().unwrap()
+    }
+
+    /// An address no earlier run used, so the tests can run again against
+    /// the same database
+    fn unique_email(name: &str) -> String {
+        format!(
+            "{}_{}@example.com",
+            name,
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        )
+    }
+
+    #[test]
+    fn test_contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("jane"), "%jane%");
+        assert_eq!(contains_pattern("50%_off\\"), "%50\\%\\_off\\\\%");
+    }
+
     #[tokio::test]
     async fn test_create_and_get_user() {
         let pool = setup_test_pool().await;
         let repo = UserRepository::new(pool);
+        let email = unique_email("test");
 
         let create_request = CreateUserRequest {
             name: "Test User".to_string(),
-            email: "test@example.com".to_string(),
+            email: email.clone(),
+            ..Default::default()
         };
 
         let created_user = repo.create_user(create_request).await.expect("Failed to create user");
         assert_eq!(created_user.name, "Test User");
-        assert_eq!(created_user.email, "test@example.com");
+        assert_eq!(created_user.email, email);
         assert!(created_user.active);
 
-        let retrieved_user = repo.get_user_by_id(created_user.id).await.expect("Failed to get user");
+        let retrieved_user = repo.get_user_by_id(created_user.user_id()).await.expect("Failed to get user");
         assert!(retrieved_user.is_some());
         let user = retrieved_user.unwrap();
         assert_eq!(user.id, created_user.id);
@@ -254,11 +846,13 @@ mod tests {
     async fn test_update_user() {
         let pool = setup_test_pool().await;
         let repo = UserRepository::new(pool);
+        let email = unique_email("update_test");
 
         // Create a test user first
         let create_request = CreateUserRequest {
             name: "Update Test User".to_string(),
-            email: "update_test@example.com".to_string(),
+            email: email.clone(),
+            ..Default::default()
         };
 
         let created_user = repo.create_user(create_request).await.expect("Failed to create user");
@@ -268,14 +862,69 @@ mod tests {
             name: Some("Updated Name".to_string()),
             email: None,
             active: Some(false),
+            ..Default::default()
         };
 
-        let updated_user = repo.update_user(created_user.id, update_request).await.expect("Failed to update user");
+        let updated_user = repo.update_user(created_user.user_id(), update_request).await.expect("Failed to update user");
         assert!(updated_user.is_some());
         let user = updated_user.unwrap();
         assert_eq!(user.name, "Updated Name");
         assert!(!user.active);
-        assert_eq!(user.email, "update_test@example.com"); // Should remain unchanged
+        assert_eq!(user.email, email); // Should remain unchanged
+    }
+
+    #[tokio::test]
+    async fn test_update_user_every_field_combination() {
+        let pool = setup_test_pool().await;
+        let repo = UserRepository::new(pool);
+
+        let created = repo
+            .create_user(CreateUserRequest {
+                name: "Combination User".to_string(),
+                email: "combination_test@example.com".to_string(),
+                ..Default::default()
+            })
+            .await
+            .expect("Failed to create user");
+
+        for mask in 0..8u8 {
+            let before = repo
+                .get_user_by_id(created.user_id())
+                .await
+                .expect("Failed to get user")
+                .expect("User exists");
+            let update = UpdateUserRequest {
+                name: (mask & 1 != 0).then(|| format!("Combination {}", mask)),
+                email: (mask & 2 != 0).then(|| format!("combination_test_{}@example.com", mask)),
+                active: (mask & 4 != 0).then_some(!before.active),
+                ..Default::default()
+            };
+
+            let after = repo
+                .update_user(created.user_id(), update.clone())
+                .await
+                .expect("Failed to update user")
+                .expect("User exists");
+            assert_eq!(after.name, update.name.unwrap_or(before.name), "mask {}", mask);
+            assert_eq!(after.email, update.email.unwrap_or(before.email), "mask {}", mask);
+            assert_eq!(after.active, update.active.unwrap_or(before.active), "mask {}", mask);
+        }
+
+        let missing = repo
+            .update_user(
+                missing_user_id(),
+                UpdateUserRequest {
+                    name: Some("Ghost".to_string()),
+                    email: None,
+                    active: None,
+                    ..Default::default()
+                },
+            )
+            .await
+            .expect("Failed to update missing user");
+        assert!(missing.is_none());
+
+        repo.purge_user(created.user_id()).await.expect("Failed to purge user");
     }
 
     #[tokio::test]
@@ -287,20 +936,68 @@ mod tests {
         let create_request = CreateUserRequest {
             name: "Delete Test User".to_string(),
             email: "delete_test@example.com".to_string(),
+            ..Default::default()
         };
 
         let created_user = repo.create_user(create_request).await.expect("Failed to create user");
 
         // Delete the user
-        let deleted = repo.delete_user(created_user.id).await.expect("Failed to delete user");
+        assert!(repo.exists(created_user.user_id()).await.expect("Failed to check user"));
+        let deleted = repo.delete_user(created_user.user_id()).await.expect("Failed to delete user");
         assert!(deleted);
+        assert!(!repo.exists(created_user.user_id()).await.expect("Failed to check deleted user"));
 
         // Verify user is deleted
-        let retrieved_user = repo.get_user_by_id(created_user.id).await.expect("Failed to check deleted user");
+        let retrieved_user = repo.get_user_by_id(created_user.user_id()).await.expect("Failed to check deleted user");
         assert!(retrieved_user.is_none());
 
         // Try to delete non-existent user
-        let not_deleted = repo.delete_user(99999).await.expect("Failed to handle non-existent user delete");
+        let not_deleted = repo.delete_user(missing_user_id()).await.expect("Failed to handle non-existent user delete");
         assert!(!not_deleted);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_copy_and_batched_imports_agree() {
+        let pool = setup_test_pool().await;
+        let repo = UserRepository::new(pool);
+
+        let users = |prefix: &str| {
+            vec![
+                CreateUserRequest {
+                    name: "Quote \"Q\", Comma".to_string(),
+                    email: format!("{}_quote@example.com", prefix),
+                    bio: Some("two\nlines".to_string()),
+                    display_name: Some(String::new()),
+                    ..Default::default()
+                },
+                CreateUserRequest {
+                    name: "Taken".to_string(),
+                    email: "IMPORT_TAKEN@example.com".to_string(),
+                    ..Default::default()
+                },
+            ]
+        };
+        let taken = repo
+            .create_user(CreateUserRequest {
+                name: "Taken".to_string(),
+                email: "import_taken@example.com".to_string(),
+                ..Default::default()
+            })
+            .await
+            .expect("Failed to create user");
+
+        let copied = repo.copy_users(&users("copy")).await.expect("Failed to COPY users");
+        let inserted = repo.insert_users(&users("insert")).await.expect("Failed to insert users");
+        for (created, prefix) in [(&copied, "copy"), (&inserted, "insert")] {
+            assert_eq!(created.len(), 1);
+            let user = &created[0];
+            assert_eq!(user.name, "Quote \"Q\", Comma");
+            assert_eq!(user.email, format!("{}_quote@example.com", prefix));
+            assert_eq!(user.bio.as_deref(), Some("two\nlines"));
+            assert_eq!(user.display_name.as_deref(), Some(""));
+            assert_eq!(user.phone, None);
+            repo.purge_user(user.user_id()).await.expect("Failed to purge user");
+        }
+        repo.purge_user(taken.user_id()).await.expect("Failed to purge user");
+    }
+}
@@ -0,0 +1,148 @@
+use sqlx::PgPool;
+
+use crate::models::user::{ImportCounts, ImportStatus};
+use crate::repository::resilience::{resilient, resilient_write};
+use crate::repository::timing::Timed;
+use crate::tenant;
+
+/// Progress of user imports, by the request ID of their upload
+#[async_trait::async_trait]
+pub trait UserImportRepositoryTrait {
+    async fn start(&self, request_id: &str) -> Result<Option<i64>, sqlx::Error>;
+    async fn record_progress(&self, id: i64, counts: ImportCounts) -> Result<(), sqlx::Error>;
+    async fn finish(
+        &self,
+        id: i64,
+        counts: ImportCounts,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error>;
+    async fn find(&self, request_id: &str) -> Result<Option<ImportStatus>, sqlx::Error>;
+}
+
+/// User import repository implementation with PostgreSQL, scoped to the
+/// tenant current when it was built
+pub struct UserImportRepository {
+    pool: PgPool,
+    tenant_id: i32,
+}
+
+impl UserImportRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tenant_id: tenant::current(),
+        }
+    }
+}
+
+/// Counts as stored
+fn stored(count: usize) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+#[async_trait::async_trait]
+impl UserImportRepositoryTrait for UserImportRepository {
+    /// Record that the upload `request_id` started importing, returning the
+    /// import's key
+    ///
+    /// An import that finished under the same request ID is replaced;
+    /// None when one is still running.
+    async fn start(&self, request_id: &str) -> Result<Option<i64>, sqlx::Error> {
+        resilient_write("user_imports.start", move || async move {
+            sqlx::query_scalar!(
+                r#"
+                INSERT INTO user_imports (tenant_id, request_id)
+                VALUES ($1, $2)
+                ON CONFLICT (tenant_id, request_id) DO UPDATE
+                SET status = 'running', processed = 0, created = 0, rejected = 0, error = NULL,
+                    started_at = NOW(), updated_at = NOW(), finished_at = NULL
+                WHERE user_imports.status <> 'running'
+                RETURNING id
+                "#,
+                self.tenant_id,
+                request_id
+            )
+            .fetch_optional(&self.pool)
+            .timed("user_imports.start")
+            .await
+        })
+        .await
+    }
+
+    /// Store how far a running import got
+    async fn record_progress(&self, id: i64, counts: ImportCounts) -> Result<(), sqlx::Error> {
+        resilient_write("user_imports.record_progress", move || async move {
+            sqlx::query!(
+                r#"
+                UPDATE user_imports
+                SET processed = $2, created = $3, rejected = $4, updated_at = NOW()
+                WHERE id = $1 AND status = 'running'
+                "#,
+                id,
+                stored(counts.processed),
+                stored(counts.created),
+                stored(counts.rejected)
+            )
+            .execute(&self.pool)
+            .timed("user_imports.record_progress")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Mark an import completed, or failed with `error`
+    async fn finish(
+        &self,
+        id: i64,
+        counts: ImportCounts,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        resilient_write("user_imports.finish", move || async move {
+            sqlx::query!(
+                r#"
+                UPDATE user_imports
+                SET status = CASE WHEN $5::TEXT IS NULL THEN 'completed' ELSE 'failed' END,
+                    processed = $2,
+                    created = $3,
+                    rejected = $4,
+                    error = $5,
+                    updated_at = NOW(),
+                    finished_at = NOW()
+                WHERE id = $1
+                "#,
+                id,
+                stored(counts.processed),
+                stored(counts.created),
+                stored(counts.rejected),
+                error
+            )
+            .execute(&self.pool)
+            .timed("user_imports.finish")
+            .await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The import of the upload `request_id`, in the current tenant
+    async fn find(&self, request_id: &str) -> Result<Option<ImportStatus>, sqlx::Error> {
+        resilient("user_imports.find", move || async move {
+            sqlx::query_as!(
+                ImportStatus,
+                r#"
+                SELECT request_id, status, processed, created, rejected, error,
+                       started_at, updated_at, finished_at
+                FROM user_imports
+                WHERE tenant_id = $1 AND request_id = $2
+                "#,
+                self.tenant_id,
+                request_id
+            )
+            .fetch_optional(&self.pool)
+            .timed("user_imports.find")
+            .await
+        })
+        .await
+    }
+}
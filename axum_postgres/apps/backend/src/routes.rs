@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::Infallible;
+
+use axum::{
+    extract::Request,
+    handler::Handler,
+    response::IntoResponse,
+    routing::{self, MethodRouter, Route},
+    Router,
+};
+use serde::Serialize;
+use tower::{Layer, Service};
+use utoipa::ToSchema;
+
+/// A mounted path and the methods it answers
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[schema(example = json!({"path": "/api/users/:id", "methods": ["DELETE", "GET", "HEAD", "OPTIONS", "PUT"]}))]
+pub struct RouteInfo {
+    pub path: String,
+    pub methods: Vec<String>,
+}
+
+/// Paths and methods registered through `Routes`
+///
+/// axum cannot list the routes of a built router, so the table is filled in
+/// as routes are added and is the source for `Allow` and `/admin/routes`.
+#[derive(Debug, Clone, Default)]
+pub struct RouteTable {
+    routes: BTreeMap<String, BTreeSet<&'static str>>,
+}
+
+impl RouteTable {
+    fn add(&mut self, path: &str, method: &'static str) {
+        self.routes
+            .entry(path.to_string())
+            .or_default()
+            .insert(method);
+    }
+
+    /// Every route with the methods it answers, sorted by path
+    pub fn list(&self) -> Vec<RouteInfo> {
+        self.routes
+            .iter()
+            .map(|(path, methods)| RouteInfo {
+                path: path.clone(),
+                methods: with_implied(methods)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Methods answered for a request path, or None when nothing matches
+    pub fn allowed_methods(&self, path: &str) -> Option<Vec<&'static str>> {
+        self.registered(path).map(with_implied)
+    }
+
+    /// Whether `method` has its own handler on the path, not counting the
+    /// HEAD and OPTIONS every route answers implicitly
+    pub fn has_handler(&self, path: &str, method: &str) -> bool {
+        self.registered(path)
+            .is_some_and(|methods| methods.contains(method))
+    }
+
+    /// Template of the route matching a request path, such as
+    /// `/api/users/:id` for `/api/users/7`
+    pub fn template(&self, path: &str) -> Option<&str> {
+        self.matching(path).map(|(template, _)| template.as_str())
+    }
+
+    /// Methods registered for the route matching a request path
+    fn registered(&self, path: &str) -> Option<&BTreeSet<&'static str>> {
+        self.matching(path).map(|(_, methods)| methods)
+    }
+
+    /// Route matching a request path
+    ///
+    /// Like axum, a static segment wins over a parameter in the same place.
+    fn matching(&self, path: &str) -> Option<(&String, &BTreeSet<&'static str>)> {
+        self.routes
+            .iter()
+            .filter(|(template, _)| matches_template(template, path))
+            .max_by_key(|(template, _)| {
+                template
+                    .split('/')
+                    .filter(|segment| !segment.starts_with([':', '*']))
+                    .count()
+            })
+    }
+}
+
+/// GET routes also answer HEAD, and every route answers OPTIONS
+fn with_implied(methods: &BTreeSet<&'static str>) -> Vec<&'static str> {
+    let mut methods = methods.clone();
+    if methods.contains("GET") {
+        methods.insert("HEAD");
+    }
+    methods.insert("OPTIONS");
+    methods.into_iter().collect()
+}
+
+/// Whether a request path matches an axum template such as `/api/users/:id`
+fn matches_template(template: &str, path: &str) -> bool {
+    let mut template = template.split('/');
+    let mut path = path.split('/');
+    loop {
+        match (template.next(), path.next()) {
+            (None, None) => return true,
+            (Some(segment), Some(_)) if segment.starts_with('*') => return true,
+            (Some(segment), Some(actual)) if segment.starts_with(':') && !actual.is_empty() => {}
+            (Some(segment), Some(actual)) if segment == actual => {}
+            _ => return false,
+        }
+    }
+}
+
+/// `Router` builder that records what it mounts in a `RouteTable`
+pub struct Routes<S = ()> {
+    router: Router<S>,
+    table: RouteTable,
+}
+
+impl<S> Default for Routes<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Routes<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            router: Router::new(),
+            table: RouteTable::default(),
+        }
+    }
+
+    pub fn get<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.add(path, "GET", routing::get(handler))
+    }
+
+    pub fn post<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.add(path, "POST", routing::post(handler))
+    }
+
+    pub fn put<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.add(path, "PUT", routing::put(handler))
+    }
+
+    pub fn patch<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.add(path, "PATCH", routing::patch(handler))
+    }
+
+    pub fn delete<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.add(path, "DELETE", routing::delete(handler))
+    }
+
+    /// Dedicated HEAD handler; other GET routes answer HEAD by running GET
+    pub fn head<H, T>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        self.add(path, "HEAD", routing::head(handler))
+    }
+
+    fn add(mut self, path: &str, method: &'static str, method_router: MethodRouter<S>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self.table.add(path, method);
+        self
+    }
+
+    pub fn merge(mut self, other: Routes<S>) -> Self {
+        self.router = self.router.merge(other.router);
+        for (path, methods) in other.table.routes {
+            for method in methods {
+                self.table.add(&path, method);
+            }
+        }
+        self
+    }
+
+    /// `Router::route_layer`: applies to the routes added so far
+    pub fn route_layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.route_layer(layer);
+        self
+    }
+
+    pub fn into_parts(self) -> (Router<S>, RouteTable) {
+        (self.router, self.table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn ok() {}
+
+    #[test]
+    fn test_table_lists_methods_and_matches_templates() {
+        let (_, table) = Routes::<()>::new()
+            .get("/api/users", ok)
+            .post("/api/users", ok)
+            .merge(
+                Routes::new()
+                    .put("/api/users/:id", ok)
+                    .delete("/api/users/:id", ok),
+            )
+            .into_parts();
+
+        assert_eq!(
+            table.list(),
+            vec![
+                RouteInfo {
+                    path: "/api/users".to_string(),
+                    methods: ["GET", "HEAD", "OPTIONS", "POST"]
+                        .map(String::from)
+                        .to_vec(),
+                },
+                RouteInfo {
+                    path: "/api/users/:id".to_string(),
+                    methods: ["DELETE", "OPTIONS", "PUT"].map(String::from).to_vec(),
+                },
+            ]
+        );
+
+        assert_eq!(
+            table.allowed_methods("/api/users/42"),
+            Some(vec!["DELETE", "OPTIONS", "PUT"])
+        );
+        assert_eq!(table.allowed_methods("/api/users/42/history"), None);
+        assert!(table.has_handler("/api/users/42", "PUT"));
+        assert_eq!(table.template("/api/users/42"), Some("/api/users/:id"));
+        assert!(!table.has_handler("/api/users", "HEAD"));
+        assert_eq!(table.allowed_methods("/api/users/"), None);
+        assert!(matches_template("/files/*path", "/files/a/b"));
+    }
+}
@@ -0,0 +1,90 @@
+//! Users each seed environment loads besides the admin
+
+use crate::models::user::CreateUserRequest;
+
+use super::SeedEnv;
+
+/// A seeded user, matched by email when seeding again
+#[derive(Debug, Clone, Copy)]
+pub struct SeedUser {
+    pub name: &'static str,
+    pub email: &'static str,
+    pub timezone: Option<&'static str>,
+    pub locale: Option<&'static str>,
+    pub active: bool,
+}
+
+impl SeedUser {
+    const fn new(name: &'static str, email: &'static str) -> Self {
+        Self {
+            name,
+            email,
+            timezone: None,
+            locale: None,
+            active: true,
+        }
+    }
+
+    const fn located(self, timezone: &'static str, locale: &'static str) -> Self {
+        Self {
+            timezone: Some(timezone),
+            locale: Some(locale),
+            ..self
+        }
+    }
+
+    const fn inactive(self) -> Self {
+        Self {
+            active: false,
+            ..self
+        }
+    }
+
+    pub fn request(&self) -> CreateUserRequest {
+        CreateUserRequest {
+            name: self.name.to_string(),
+            email: self.email.to_string(),
+            timezone: self.timezone.map(str::to_string),
+            locale: self.locale.map(str::to_string),
+            ..Default::default()
+        }
+    }
+}
+
+/// Demo users to click through the API with
+const DEMO: &[SeedUser] = &[
+    SeedUser::new("Aiko Tanaka", "aiko.tanaka@demo.example.com").located("Asia/Tokyo", "ja-JP"),
+    SeedUser::new("Ben Carter", "ben.carter@demo.example.com").located("America/New_York", "en-US"),
+    SeedUser::new("Chloé Martin", "chloe.martin@demo.example.com").located("Europe/Paris", "fr-FR"),
+    SeedUser::new("Diego Ramírez", "diego.ramirez@demo.example.com")
+        .located("America/Mexico_City", "es-MX"),
+    SeedUser::new("Emma Schmidt", "emma.schmidt@demo.example.com")
+        .located("Europe/Berlin", "de-DE"),
+    SeedUser::new("Farid Haddad", "farid.haddad@demo.example.com"),
+    SeedUser::new("Grace Okafor", "grace.okafor@demo.example.com").located("Africa/Lagos", "en-NG"),
+    SeedUser::new("Hiroshi Sato", "hiroshi.sato@demo.example.com").located("Asia/Tokyo", "ja-JP"),
+    SeedUser::new("Isabella Rossi", "isabella.rossi@demo.example.com")
+        .located("Europe/Rome", "it-IT"),
+    SeedUser::new("Jonas Berg", "jonas.berg@demo.example.com").inactive(),
+    SeedUser::new("Kavya Iyer", "kavya.iyer@demo.example.com").located("Asia/Kolkata", "en-IN"),
+    SeedUser::new("Liam O'Brien", "liam.obrien@demo.example.com").located("Europe/Dublin", "en-IE"),
+    SeedUser::new("Mei Chen", "mei.chen@demo.example.com").located("Asia/Shanghai", "zh-CN"),
+    SeedUser::new("Noah Williams", "noah.williams@demo.example.com").inactive(),
+    SeedUser::new("Olivia Brown", "olivia.brown@demo.example.com")
+        .located("Australia/Sydney", "en-AU"),
+];
+
+/// Fixtures tests can rely on: two active users and an inactive one
+const TEST: &[SeedUser] = &[
+    SeedUser::new("Seed User One", "seed.one@test.example.com"),
+    SeedUser::new("Seed User Two", "seed.two@test.example.com").located("Europe/London", "en-GB"),
+    SeedUser::new("Seed User Inactive", "seed.inactive@test.example.com").inactive(),
+];
+
+/// Users seeded in `env`
+pub fn users(env: SeedEnv) -> &'static [SeedUser] {
+    match env {
+        SeedEnv::Dev => DEMO,
+        SeedEnv::Test => TEST,
+    }
+}
@@ -0,0 +1,304 @@
+//! Seed data for development and test databases
+//!
+//! `cargo run --bin seed -- --env dev|test [--reset]` loads an admin user
+//! and demo users through the repositories. Seeding is idempotent: users
+//! are matched by email, so running it again only adds what is missing and
+//! never changes a password. `--reset` first deletes the users this
+//! environment seeds, and nothing else.
+
+mod data;
+
+use std::fmt;
+use std::str::FromStr;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use sqlx::PgPool;
+
+use crate::auth::password::PasswordService;
+use crate::auth::role::Role;
+use crate::models::user::{CreateUserRequest, User};
+use crate::repository::credentials::{CredentialRepository, CredentialRepositoryTrait};
+use crate::repository::roles::{RoleRepository, RoleRepositoryTrait};
+use crate::repository::user::{UserRepository, UserRepositoryTrait};
+
+/// Email of the seeded admin unless SEED_ADMIN_EMAIL is set
+pub const DEFAULT_ADMIN_EMAIL: &str = "admin@example.com";
+
+/// Password of the admin seeded with `--env test`, unless
+/// SEED_ADMIN_PASSWORD is set
+pub const TEST_ADMIN_PASSWORD: &str = "seed-test-admin-password";
+
+/// Which data set to load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedEnv {
+    /// An admin with a generated password, and demo users around the world
+    Dev,
+    /// An admin with a known password, and a handful of fixtures
+    Test,
+}
+
+impl FromStr for SeedEnv {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "dev" | "development" => Ok(Self::Dev),
+            "test" => Ok(Self::Test),
+            _ => Err(format!(
+                "Unknown seed environment '{}'; use dev or test",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SeedEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dev => "dev",
+            Self::Test => "test",
+        })
+    }
+}
+
+/// Command line of the seed binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeedOptions {
+    pub env: SeedEnv,
+    /// Delete the seeded users before seeding again
+    pub reset: bool,
+}
+
+impl SeedOptions {
+    pub const USAGE: &'static str = "Usage: seed [--env dev|test] [--reset]";
+
+    /// Options from the arguments after the program name; `--env` defaults
+    /// to `dev`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            env: SeedEnv::Dev,
+            reset: false,
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--reset" => options.reset = true,
+                "--env" => {
+                    let value = args.next().ok_or("--env needs a value: dev or test")?;
+                    options.env = value.parse()?;
+                }
+                _ => match arg.strip_prefix("--env=") {
+                    Some(value) => options.env = value.parse()?,
+                    None => return Err(format!("Unknown argument '{}'", arg)),
+                },
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// What a run changed
+#[derive(Debug, Default)]
+pub struct SeedReport {
+    /// Seeded users deleted by `--reset`
+    pub removed: usize,
+    /// Emails of the users created
+    pub created: Vec<String>,
+    /// Seeded users that were already there
+    pub existing: usize,
+    /// Generated password of the admin, when this run created them
+    pub admin_password: Option<String>,
+}
+
+/// Load the seed data of `options.env` into the database of `pool`
+pub async fn run(pool: &PgPool, options: &SeedOptions) -> Result<SeedReport, sqlx::Error> {
+    let users = UserRepository::new(pool.clone());
+    let mut report = SeedReport::default();
+    let admin_email =
+        std::env::var("SEED_ADMIN_EMAIL").unwrap_or_else(|_| DEFAULT_ADMIN_EMAIL.to_string());
+    let seeded = data::users(options.env);
+
+    if options.reset {
+        let emails =
+            std::iter::once(admin_email.as_str()).chain(seeded.iter().map(|seed| seed.email));
+        for email in emails {
+            if let Some(user) = users.find_user_by_email(email).await? {
+                users.purge_user(user.user_id()).await?;
+                report.removed += 1;
+            }
+        }
+    }
+
+    seed_admin(pool, options.env, &admin_email, &mut report).await?;
+
+    // Only the missing users are inserted and returned
+    let requests = seeded.iter().map(data::SeedUser::request).collect();
+    let created = users.import_users(requests).await?;
+    report.existing += seeded.len() - created.len();
+    for user in &created {
+        let inactive = seeded
+            .iter()
+            .any(|seed| seed.email == user.email && !seed.active);
+        if inactive {
+            users.set_user_active(user.user_id(), false).await?;
+        }
+        report.created.push(user.email.clone());
+    }
+    Ok(report)
+}
+
+/// Create the admin with a password, verified email and the admin role,
+/// unless a user already has the email; then only the role is granted
+async fn seed_admin(
+    pool: &PgPool,
+    env: SeedEnv,
+    email: &str,
+    report: &mut SeedReport,
+) -> Result<User, sqlx::Error> {
+    let roles = RoleRepository::new(pool.clone());
+    if let Some(admin) = UserRepository::new(pool.clone())
+        .find_user_by_email(email)
+        .await?
+    {
+        roles.grant(Role::Admin, &admin).await?;
+        report.existing += 1;
+        return Ok(admin);
+    }
+
+    let (password, generated) = match (std::env::var("SEED_ADMIN_PASSWORD"), env) {
+        (Ok(password), _) => (password, false),
+        (Err(_), SeedEnv::Test) => (TEST_ADMIN_PASSWORD.to_string(), false),
+        (Err(_), SeedEnv::Dev) => (generate_password(), true),
+    };
+    let hash = {
+        let password = password.clone();
+        tokio::task::spawn_blocking(move || PasswordService::default().hash(&password))
+            .await
+            .map_err(|e| sqlx::Error::Protocol(format!("Password hashing failed: {}", e)))?
+    };
+
+    let credentials = CredentialRepository::new(pool.clone());
+    let request = CreateUserRequest {
+        name: "Admin".to_string(),
+        email: email.to_string(),
+        ..Default::default()
+    };
+    let admin = credentials.register(request, &hash).await?;
+    let admin = credentials
+        .mark_email_verified(&admin, email)
+        .await?
+        .unwrap_or(admin);
+    roles.grant(Role::Admin, &admin).await?;
+
+    report.created.push(admin.email.clone());
+    if generated {
+        report.admin_password = Some(password);
+    }
+    Ok(admin)
+}
+
+/// Random password of 24 URL-safe characters
+fn generate_password() -> String {
+    let mut bytes = [0u8; 18];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("Failed to generate a password");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_pool_from_env;
+    use dotenvy::dotenv;
+
+    fn args(args: &[&str]) -> Result<SeedOptions, String> {
+        SeedOptions::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_options_from_args() {
+        assert_eq!(
+            args(&[]).unwrap(),
+            SeedOptions {
+                env: SeedEnv::Dev,
+                reset: false
+            }
+        );
+        assert_eq!(
+            args(&["--env", "test", "--reset"]).unwrap(),
+            SeedOptions {
+                env: SeedEnv::Test,
+                reset: true
+            }
+        );
+        assert_eq!(args(&["--env=development"]).unwrap().env, SeedEnv::Dev);
+        assert!(args(&["--env"]).is_err());
+        assert!(args(&["--env", "prod"]).is_err());
+        assert!(args(&["--force"]).is_err());
+    }
+
+    #[test]
+    fn test_seed_users_are_valid() {
+        use validator::Validate;
+
+        for env in [SeedEnv::Dev, SeedEnv::Test] {
+            for seed in data::users(env) {
+                assert!(seed.request().validate().is_ok(), "{:?}", seed);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seeding_is_idempotent() {
+        dotenv().ok();
+        let pool = create_pool_from_env()
+            .await
+            .expect("Failed to create test pool");
+        let options = SeedOptions {
+            env: SeedEnv::Test,
+            reset: true,
+        };
+        let seeded = data::users(SeedEnv::Test).len() + 1;
+
+        let first = run(&pool, &options).await.expect("Failed to seed");
+        assert_eq!(first.created.len(), seeded);
+        assert_eq!(first.admin_password, None);
+
+        let again = run(
+            &pool,
+            &SeedOptions {
+                reset: false,
+                ..options
+            },
+        )
+        .await
+        .expect("Failed to seed again");
+        assert!(again.created.is_empty());
+        assert_eq!(again.existing, seeded);
+
+        let reset = run(&pool, &options).await.expect("Failed to reset");
+        assert_eq!(reset.removed, seeded);
+        assert_eq!(reset.created.len(), seeded);
+
+        let admin = UserRepository::new(pool.clone())
+            .find_user_by_email(DEFAULT_ADMIN_EMAIL)
+            .await
+            .unwrap()
+            .expect("Admin is seeded");
+        assert!(admin.email_verified);
+        let roles = RoleRepository::new(pool.clone())
+            .roles_of(admin.id)
+            .await
+            .unwrap();
+        assert_eq!(roles, vec![Role::Admin]);
+        let hash = CredentialRepository::new(pool)
+            .password_hash(&admin)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(PasswordService::default().verify(TEST_ADMIN_PASSWORD, &hash));
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Width of one aggregation bucket in the rolling window
+const BUCKET_SECS: u64 = 60;
+
+/// Latency and error-rate objective for one route
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct SloObjective {
+    /// `METHOD /path` using the router's path syntax, e.g. `GET /api/users/:id`
+    pub route: String,
+    /// Requests slower than this count against the budget
+    // SLO_CONFIG files keep snake_case whatever the response casing
+    #[cfg_attr(feature = "camel-case", serde(alias = "latency_ms"))]
+    pub latency_ms: u64,
+    /// Fraction of requests that must be good (fast and not 5xx), e.g. 0.99
+    pub target: f64,
+}
+
+/// SLO configuration loaded from the file named by SLO_CONFIG
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloConfig {
+    /// Rolling window compliance is computed over
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    #[serde(default)]
+    pub objectives: Vec<SloObjective>,
+}
+
+fn default_window_secs() -> u64 {
+    3600
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: default_window_secs(),
+            objectives: Vec::new(),
+        }
+    }
+}
+
+impl SloConfig {
+    /// Load SLO_CONFIG; no objectives when it is unset
+    pub fn from_env() -> Result<Self, String> {
+        let Ok(path) = std::env::var("SLO_CONFIG") else {
+            return Ok(Self::default());
+        };
+
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read SLO config {}: {}", path, e))?;
+        let config: SloConfig = serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid SLO config {}: {}", path, e))?;
+
+        for objective in &config.objectives {
+            if !(0.0..1.0).contains(&objective.target) {
+                return Err(format!(
+                    "SLO target for {} must be in [0, 1), got {}",
+                    objective.route, objective.target
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    total: u64,
+    slow: u64,
+    errors: u64,
+}
+
+impl Counts {
+    fn bad(&self) -> u64 {
+        self.slow + self.errors
+    }
+
+    fn add(&mut self, other: &Counts) {
+        self.total += other.total;
+        self.slow += other.slow;
+        self.errors += other.errors;
+    }
+}
+
+#[derive(Default)]
+struct RouteWindow {
+    /// (bucket start in unix seconds, counts), oldest first
+    buckets: VecDeque<(u64, Counts)>,
+    /// Counts since startup, never pruned
+    lifetime: Counts,
+}
+
+/// Compliance of one route over the window
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct SloStatus {
+    pub objective: SloObjective,
+    pub total: u64,
+    /// Requests slower than the latency objective
+    pub slow: u64,
+    /// Requests answered with 5xx
+    pub errors: u64,
+    /// Fraction of good requests; 1.0 with no traffic
+    pub compliance: f64,
+    /// How fast the error budget is being spent; 1.0 spends it exactly over the window
+    pub burn_rate: f64,
+    /// Fraction of the window's error budget left; negative once overspent
+    pub error_budget_remaining: f64,
+    /// Bad requests since startup
+    pub budget_burn_total: u64,
+    pub met: bool,
+}
+
+/// Summary served by `/admin/slo`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct SloReport {
+    pub window_secs: u64,
+    pub routes: Vec<SloStatus>,
+}
+
+/// Per-route SLO accounting over a rolling window
+///
+/// Only routes with a configured objective are tracked, so memory stays
+/// bounded by the config rather than by traffic.
+pub struct SloTracker {
+    window_secs: u64,
+    objectives: HashMap<String, SloObjective>,
+    windows: HashMap<String, Mutex<RouteWindow>>,
+}
+
+impl Default for SloTracker {
+    fn default() -> Self {
+        Self::new(SloConfig::default())
+    }
+}
+
+impl SloTracker {
+    pub fn new(config: SloConfig) -> Self {
+        let objectives: HashMap<String, SloObjective> = config
+            .objectives
+            .into_iter()
+            .map(|objective| (objective.route.clone(), objective))
+            .collect();
+        let windows = objectives
+            .keys()
+            .map(|route| (route.clone(), Mutex::new(RouteWindow::default())))
+            .collect();
+
+        Self {
+            window_secs: config.window_secs.max(BUCKET_SECS),
+            objectives,
+            windows,
+        }
+    }
+
+    /// Whether a route has an objective
+    pub fn is_tracked(&self, route: &str) -> bool {
+        self.objectives.contains_key(route)
+    }
+
+    /// Record one request against its route's objective
+    pub fn record(&self, route: &str, latency: Duration, status: u16) {
+        self.record_at(route, latency, status, now_secs());
+    }
+
+    fn record_at(&self, route: &str, latency: Duration, status: u16, now: u64) {
+        let (Some(objective), Some(window)) = (self.objectives.get(route), self.windows.get(route))
+        else {
+            return;
+        };
+
+        let event = Counts {
+            total: 1,
+            slow: u64::from(latency.as_millis() as u64 > objective.latency_ms && status < 500),
+            errors: u64::from(status >= 500),
+        };
+
+        let bucket = now - now % BUCKET_SECS;
+        let mut window = window.lock().unwrap();
+        match window.buckets.back_mut() {
+            Some((start, counts)) if *start == bucket => counts.add(&event),
+            _ => window.buckets.push_back((bucket, event)),
+        }
+        window.lifetime.add(&event);
+        self.prune(&mut window, now);
+    }
+
+    fn prune(&self, window: &mut RouteWindow, now: u64) {
+        let oldest = now.saturating_sub(self.window_secs);
+        while window
+            .buckets
+            .front()
+            .is_some_and(|(start, _)| *start + BUCKET_SECS <= oldest)
+        {
+            window.buckets.pop_front();
+        }
+    }
+
+    /// Current compliance of every tracked route
+    pub fn report(&self) -> SloReport {
+        self.report_at(now_secs())
+    }
+
+    fn report_at(&self, now: u64) -> SloReport {
+        let mut routes: Vec<SloStatus> = self
+            .objectives
+            .values()
+            .map(|objective| {
+                let mut window = self.windows[&objective.route].lock().unwrap();
+                self.prune(&mut window, now);
+
+                let mut counts = Counts::default();
+                for (_, bucket) in &window.buckets {
+                    counts.add(bucket);
+                }
+
+                let compliance = if counts.total == 0 {
+                    1.0
+                } else {
+                    1.0 - counts.bad() as f64 / counts.total as f64
+                };
+                let budget = 1.0 - objective.target;
+                let burn_rate = (1.0 - compliance) / budget;
+
+                SloStatus {
+                    objective: objective.clone(),
+                    total: counts.total,
+                    slow: counts.slow,
+                    errors: counts.errors,
+                    compliance,
+                    burn_rate,
+                    error_budget_remaining: 1.0 - burn_rate,
+                    budget_burn_total: window.lifetime.bad(),
+                    met: compliance >= objective.target,
+                }
+            })
+            .collect();
+        routes.sort_by(|a, b| a.objective.route.cmp(&b.objective.route));
+
+        SloReport {
+            window_secs: self.window_secs,
+            routes,
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> SloTracker {
+        SloTracker::new(SloConfig {
+            window_secs: 600,
+            objectives: vec![SloObjective {
+                route: "GET /api/users".to_string(),
+                latency_ms: 100,
+                target: 0.9,
+            }],
+        })
+    }
+
+    #[test]
+    fn test_compliance_and_burn_rate() {
+        let tracker = tracker();
+        let fast = Duration::from_millis(10);
+        let now = 1_000_000;
+
+        for _ in 0..8 {
+            tracker.record_at("GET /api/users", fast, 200, now);
+        }
+        tracker.record_at("GET /api/users", Duration::from_millis(500), 200, now);
+        tracker.record_at("GET /api/users", fast, 503, now);
+        // Untracked routes are ignored
+        tracker.record_at("GET /api/other", fast, 500, now);
+
+        let report = tracker.report_at(now);
+        assert_eq!(report.routes.len(), 1);
+        let status = &report.routes[0];
+        assert_eq!((status.total, status.slow, status.errors), (10, 1, 1));
+        assert!((status.compliance - 0.8).abs() < 1e-9);
+        assert!((status.burn_rate - 2.0).abs() < 1e-9);
+        assert!(status.error_budget_remaining < 0.0);
+        assert!(!status.met);
+    }
+
+    #[test]
+    fn test_old_buckets_leave_the_window() {
+        let tracker = tracker();
+        let now = 1_000_000;
+        tracker.record_at("GET /api/users", Duration::from_millis(10), 500, now);
+
+        let later = now + 3600;
+        tracker.record_at("GET /api/users", Duration::from_millis(10), 200, later);
+
+        let status = &tracker.report_at(later).routes[0];
+        assert_eq!(status.total, 1);
+        assert!(status.met);
+        assert_eq!(status.budget_burn_total, 1);
+    }
+
+    #[test]
+    fn test_no_traffic_is_compliant() {
+        let status = &tracker().report_at(1_000_000).routes[0];
+        assert_eq!(status.compliance, 1.0);
+        assert_eq!(status.burn_rate, 0.0);
+    }
+}
@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use sqlx::PgPool;
+
+use crate::auth::api_key::ApiKeyUsage;
+use crate::auth::oauth::OAuthProviders;
+use crate::database::DbPools;
+use crate::auth::session::AuthMode;
+use crate::auth::token::TokenKeys;
+use crate::auth::verification::EmailVerification;
+use crate::cache::TieredCache;
+use crate::changes::ChangeFeed;
+use crate::handlers::ws::WebSocketConfig;
+use crate::health::HealthRegistry;
+use crate::mail::Mail;
+use crate::middleware::admin::AdminToken;
+use crate::middleware::envelope::ResponseEnvelope;
+use crate::middleware::read_only::ReadOnlyMode;
+use crate::models::links::ResponseLinks;
+use crate::slo::SloTracker;
+use crate::status::Uptime;
+use crate::tenant::TenantResolver;
+
+/// Shared application state
+///
+/// Handlers extract only the part they need, e.g. `State<PgPool>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    /// Pool read-only queries go to; `pool` when there is no replica
+    pub replica: PgPool,
+    pub health: Arc<HealthRegistry>,
+    pub read_only: ReadOnlyMode,
+    pub slo: Arc<SloTracker>,
+    pub links: ResponseLinks,
+    pub envelope: ResponseEnvelope,
+    pub admin_token: AdminToken,
+    pub token_keys: TokenKeys,
+    pub oauth: OAuthProviders,
+    pub auth_mode: AuthMode,
+    pub mail: Mail,
+    pub email_verification: EmailVerification,
+    pub uptime: Uptime,
+    pub tenants: TenantResolver,
+    /// Committed user changes, from any replica
+    pub changes: ChangeFeed,
+    /// Redis, when REDIS_URL is set
+    pub cache: TieredCache,
+    pub websocket: WebSocketConfig,
+    /// API key uses not yet written to the database
+    pub api_key_usage: ApiKeyUsage,
+}
+
+impl AppState {
+    pub fn new(pool: PgPool, health: HealthRegistry) -> Self {
+        Self {
+            replica: pool.clone(),
+            pool,
+            health: Arc::new(health),
+            read_only: ReadOnlyMode::default(),
+            slo: Arc::new(SloTracker::default()),
+            links: ResponseLinks::default(),
+            envelope: ResponseEnvelope::default(),
+            admin_token: AdminToken::default(),
+            token_keys: TokenKeys::default(),
+            oauth: OAuthProviders::default(),
+            auth_mode: AuthMode::default(),
+            mail: Mail::default(),
+            email_verification: EmailVerification::default(),
+            uptime: Uptime::start(),
+            tenants: TenantResolver::default(),
+            changes: ChangeFeed::default(),
+            cache: TieredCache::default(),
+            websocket: WebSocketConfig::default(),
+            api_key_usage: ApiKeyUsage::default(),
+        }
+    }
+
+    pub fn with_replica(mut self, replica: PgPool) -> Self {
+        self.replica = replica;
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: ReadOnlyMode) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn with_slo(mut self, slo: SloTracker) -> Self {
+        self.slo = Arc::new(slo);
+        self
+    }
+
+    pub fn with_links(mut self, links: ResponseLinks) -> Self {
+        self.links = links;
+        self
+    }
+
+    pub fn with_envelope(mut self, envelope: ResponseEnvelope) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    pub fn with_admin_token(mut self, admin_token: AdminToken) -> Self {
+        self.admin_token = admin_token;
+        self
+    }
+
+    pub fn with_token_keys(mut self, token_keys: TokenKeys) -> Self {
+        self.token_keys = token_keys;
+        self
+    }
+
+    pub fn with_oauth(mut self, oauth: OAuthProviders) -> Self {
+        self.oauth = oauth;
+        self
+    }
+
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    pub fn with_mail(mut self, mail: Mail) -> Self {
+        self.mail = mail;
+        self
+    }
+
+    pub fn with_email_verification(mut self, email_verification: EmailVerification) -> Self {
+        self.email_verification = email_verification;
+        self
+    }
+
+    pub fn with_tenants(mut self, tenants: TenantResolver) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    pub fn with_changes(mut self, changes: ChangeFeed) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: TieredCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn with_websocket(mut self, websocket: WebSocketConfig) -> Self {
+        self.websocket = websocket;
+        self
+    }
+
+    pub fn with_api_key_usage(mut self, api_key_usage: ApiKeyUsage) -> Self {
+        self.api_key_usage = api_key_usage;
+        self
+    }
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for DbPools {
+    fn from_ref(state: &AppState) -> Self {
+        DbPools::new(state.pool.clone(), Some(state.replica.clone()))
+    }
+}
+
+impl FromRef<AppState> for Arc<HealthRegistry> {
+    fn from_ref(state: &AppState) -> Self {
+        state.health.clone()
+    }
+}
+
+impl FromRef<AppState> for ReadOnlyMode {
+    fn from_ref(state: &AppState) -> Self {
+        state.read_only.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<SloTracker> {
+    fn from_ref(state: &AppState) -> Self {
+        state.slo.clone()
+    }
+}
+
+impl FromRef<AppState> for ResponseLinks {
+    fn from_ref(state: &AppState) -> Self {
+        state.links
+    }
+}
+
+impl FromRef<AppState> for ResponseEnvelope {
+    fn from_ref(state: &AppState) -> Self {
+        state.envelope
+    }
+}
+
+impl FromRef<AppState> for AdminToken {
+    fn from_ref(state: &AppState) -> Self {
+        state.admin_token.clone()
+    }
+}
+
+impl FromRef<AppState> for TokenKeys {
+    fn from_ref(state: &AppState) -> Self {
+        state.token_keys.clone()
+    }
+}
+
+impl FromRef<AppState> for OAuthProviders {
+    fn from_ref(state: &AppState) -> Self {
+        state.oauth.clone()
+    }
+}
+
+impl FromRef<AppState> for AuthMode {
+    fn from_ref(state: &AppState) -> Self {
+        state.auth_mode
+    }
+}
+
+impl FromRef<AppState> for Mail {
+    fn from_ref(state: &AppState) -> Self {
+        state.mail.clone()
+    }
+}
+
+impl FromRef<AppState> for EmailVerification {
+    fn from_ref(state: &AppState) -> Self {
+        state.email_verification
+    }
+}
+
+impl FromRef<AppState> for Uptime {
+    fn from_ref(state: &AppState) -> Self {
+        state.uptime
+    }
+}
+
+impl FromRef<AppState> for TenantResolver {
+    fn from_ref(state: &AppState) -> Self {
+        state.tenants.clone()
+    }
+}
+
+impl FromRef<AppState> for ChangeFeed {
+    fn from_ref(state: &AppState) -> Self {
+        state.changes.clone()
+    }
+}
+
+impl FromRef<AppState> for TieredCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.cache.clone()
+    }
+}
+
+impl FromRef<AppState> for WebSocketConfig {
+    fn from_ref(state: &AppState) -> Self {
+        state.websocket
+    }
+}
+
+impl FromRef<AppState> for ApiKeyUsage {
+    fn from_ref(state: &AppState) -> Self {
+        state.api_key_usage.clone()
+    }
+}
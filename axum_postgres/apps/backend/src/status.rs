@@ -0,0 +1,124 @@
+//! Service status for operators at `GET /api/admin/status`
+//!
+//! Build information comes from `build.rs`; the rest is read when the
+//! endpoint is called.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Cargo features this binary was built with, by name
+const FEATURES: &[(&str, bool)] = &[
+    ("nats", cfg!(feature = "nats")),
+    ("jemalloc", cfg!(feature = "jemalloc")),
+    ("cpu-profiling", cfg!(feature = "cpu-profiling")),
+    ("otel", cfg!(feature = "otel")),
+    ("camel-case", cfg!(feature = "camel-case")),
+    ("uuid-ids", cfg!(feature = "uuid-ids")),
+];
+
+/// When the service started, for its uptime
+#[derive(Debug, Clone, Copy)]
+pub struct Uptime {
+    started_at: DateTime<Utc>,
+}
+
+impl Uptime {
+    /// Start counting now
+    pub fn start() -> Self {
+        Self {
+            started_at: Utc::now(),
+        }
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// Whole seconds since the service started
+    pub fn seconds(&self) -> u64 {
+        (Utc::now() - self.started_at).num_seconds().max(0) as u64
+    }
+}
+
+/// What was built, from where and when
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: &'static str,
+    /// Commit built, `unknown` outside a checkout without GIT_SHA
+    pub git_sha: &'static str,
+    pub built_at: Option<DateTime<Utc>>,
+    /// Cargo features, enabled or not
+    #[schema(value_type = Object, example = json!({"otel": false, "uuid-ids": false}))]
+    pub features: BTreeMap<&'static str, bool>,
+}
+
+impl BuildInfo {
+    /// Information on this binary
+    pub fn current() -> Self {
+        let built_at = env!("BUILD_TIMESTAMP")
+            .parse()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0));
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("BUILD_GIT_SHA"),
+            built_at,
+            features: FEATURES.iter().copied().collect(),
+        }
+    }
+}
+
+/// Connections of the database pool
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct PoolStatus {
+    /// Open connections
+    pub size: u32,
+    /// Open connections not in use
+    pub idle: u32,
+    /// Most connections the pool opens
+    pub max: u32,
+}
+
+/// Schema version of the database
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct MigrationStatus {
+    /// Newest migration applied, null when none has been
+    pub applied: Option<i64>,
+    /// Newest migration this build ships
+    pub latest: i64,
+    /// Migrations of this build not applied yet
+    pub pending: Vec<i64>,
+}
+
+impl From<crate::database::MigrationState> for MigrationStatus {
+    fn from(state: crate::database::MigrationState) -> Self {
+        Self {
+            applied: state.applied,
+            latest: state.latest,
+            pending: state.pending,
+        }
+    }
+}
+
+/// Status of the running service
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(example = json!({"build": {"version": "0.1.0", "git_sha": "5e75bc9c0d3f4a0b8a3f0b4f6a1d2e3c4b5a6978", "built_at": "2024-01-01T00:00:00Z", "features": {"camel-case": false, "cpu-profiling": false, "jemalloc": false, "nats": false, "otel": false, "uuid-ids": false}}, "started_at": "2024-01-02T00:00:00Z", "uptime_secs": 3600, "pool": {"size": 3, "idle": 2, "max": 10}, "migrations": {"applied": 20, "latest": 20, "pending": []}, "flags": {"cookie_sessions": false, "read_only": false, "response_envelope": false}}))]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct ServiceStatus {
+    pub build: BuildInfo,
+    pub started_at: DateTime<Utc>,
+    pub uptime_secs: u64,
+    pub pool: PoolStatus,
+    /// Null when the database could not be read
+    pub migrations: Option<MigrationStatus>,
+    /// Runtime switches, on or off
+    #[schema(value_type = Object)]
+    pub flags: BTreeMap<&'static str, bool>,
+}
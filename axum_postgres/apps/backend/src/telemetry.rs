@@ -0,0 +1,268 @@
+//! OpenTelemetry span export over OTLP
+//!
+//! With the `otel` feature and OTEL_EXPORTER_OTLP_ENDPOINT set, tracing spans
+//! are exported to a collector such as Jaeger or Tempo. The request span
+//! continues the trace of an incoming `traceparent` header, and each
+//! statement sqlx logs becomes a child span of the span that ran it.
+
+use axum::http::HeaderMap;
+use tracing::Span;
+
+/// Log directive for the statements sqlx reports, turned into spans
+#[cfg(feature = "otel")]
+const DB_QUERY_DIRECTIVE: &str = "sqlx::query=debug";
+
+#[cfg(feature = "otel")]
+pub use otel::Telemetry;
+
+/// Span export; without the `otel` feature there is nothing to export to
+#[cfg(not(feature = "otel"))]
+pub struct Telemetry;
+
+#[cfg(not(feature = "otel"))]
+impl Telemetry {
+    /// Always `None`, and an error when OTEL_EXPORTER_OTLP_ENDPOINT is set
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(_) => Err(
+                "OTEL_EXPORTER_OTLP_ENDPOINT is set but the `otel` feature is disabled; spans are not exported"
+                    .to_string(),
+            ),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn layer<S>(
+        &self,
+        _filter: tracing_subscriber::EnvFilter,
+    ) -> impl tracing_subscriber::Layer<S>
+    where
+        S: tracing::Subscriber,
+    {
+        tracing_subscriber::layer::Identity::new()
+    }
+
+    pub fn shutdown(self) {}
+}
+
+/// Continue the trace of the `traceparent` header in `span`
+pub fn set_parent(span: &Span, headers: &HeaderMap) {
+    #[cfg(feature = "otel")]
+    otel::set_parent(span, headers);
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, headers);
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::time::{Duration, SystemTime};
+
+    use axum::http::HeaderMap;
+    use opentelemetry::{
+        global,
+        propagation::Extractor,
+        trace::{Span as _, SpanKind, Tracer as _, TracerProvider as _},
+        KeyValue,
+    };
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator,
+        runtime,
+        trace::{Tracer, TracerProvider},
+        Resource,
+    };
+    use tracing::{field::Field, Event, Span, Subscriber};
+    use tracing_opentelemetry::{OpenTelemetrySpanExt, OtelData, PreSampledTracer};
+    use tracing_subscriber::{layer::Context, registry::LookupSpan, EnvFilter, Layer};
+
+    use super::DB_QUERY_DIRECTIVE;
+
+    /// Installed tracer provider; flush it with [`Telemetry::shutdown`]
+    pub struct Telemetry {
+        provider: TracerProvider,
+    }
+
+    impl Telemetry {
+        /// Exporter for OTEL_EXPORTER_OTLP_ENDPOINT, `None` when it is unset
+        ///
+        /// Spans are sent as OTLP/HTTP protobuf to `<endpoint>/v1/traces`,
+        /// as service OTEL_SERVICE_NAME, `backend` by default.
+        pub fn from_env() -> Result<Option<Self>, String> {
+            if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_err() {
+                return Ok(None);
+            }
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .build()
+                .map_err(|e| format!("Failed to create the OTLP exporter: {}", e))?;
+            let service = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "backend".into());
+            let provider = TracerProvider::builder()
+                .with_batch_exporter(exporter, runtime::Tokio)
+                .with_resource(Resource::new_with_defaults([KeyValue::new(
+                    "service.name",
+                    service,
+                )]))
+                .build();
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            Ok(Some(Self { provider }))
+        }
+
+        /// Layer exporting the spans `filter` enables, and the statements
+        /// sqlx reports as their children
+        pub fn layer<S>(&self, filter: EnvFilter) -> impl Layer<S>
+        where
+            S: Subscriber + for<'a> LookupSpan<'a>,
+        {
+            let tracer = self.provider.tracer("backend");
+            let filter = filter.add_directive(
+                DB_QUERY_DIRECTIVE
+                    .parse()
+                    .expect("The query directive is valid"),
+            );
+            tracing_opentelemetry::layer()
+                .with_tracer(tracer.clone())
+                .and_then(DbQuerySpans { tracer })
+                .with_filter(filter)
+        }
+
+        /// Export the spans still buffered
+        pub fn shutdown(self) {
+            if let Err(e) = self.provider.shutdown() {
+                eprintln!("Failed to flush spans: {}", e);
+            }
+        }
+    }
+
+    pub fn set_parent(span: &Span, headers: &HeaderMap) {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+        span.set_parent(parent);
+    }
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(|name| name.as_str()).collect()
+        }
+    }
+
+    /// Turns the `sqlx::query` events into spans
+    ///
+    /// sqlx reports a statement once it finished, with its duration, so the
+    /// span is started back in time and ended at once.
+    struct DbQuerySpans {
+        tracer: Tracer,
+    }
+
+    impl<S> Layer<S> for DbQuerySpans
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            if event.metadata().target() != "sqlx::query" {
+                return;
+            }
+            let Some(span) = ctx.event_span(event) else {
+                return;
+            };
+            let mut query = QueryFields::default();
+            event.record(&mut query);
+
+            let mut extensions = span.extensions_mut();
+            let Some(data) = extensions.get_mut::<OtelData>() else {
+                return;
+            };
+            let parent = self.tracer.sampled_context(data);
+            let end = SystemTime::now();
+            let start = end - Duration::from_secs_f64(query.elapsed_secs.max(0.0));
+            let statement = if query.statement.trim().is_empty() {
+                query.summary.clone()
+            } else {
+                query.statement.trim().to_string()
+            };
+            let mut attributes = vec![
+                KeyValue::new("db.system", "postgresql"),
+                KeyValue::new("db.statement", statement),
+            ];
+            if let Some(rows) = query.rows_returned {
+                attributes.push(KeyValue::new("db.rows_returned", rows as i64));
+            }
+            if let Some(rows) = query.rows_affected {
+                attributes.push(KeyValue::new("db.rows_affected", rows as i64));
+            }
+            self.tracer
+                .span_builder(query.summary)
+                .with_kind(SpanKind::Client)
+                .with_start_time(start)
+                .with_attributes(attributes)
+                .start_with_context(&self.tracer, &parent)
+                .end_with_timestamp(end);
+        }
+    }
+
+    #[derive(Default)]
+    struct QueryFields {
+        summary: String,
+        statement: String,
+        rows_affected: Option<u64>,
+        rows_returned: Option<u64>,
+        elapsed_secs: f64,
+    }
+
+    impl tracing::field::Visit for QueryFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            match field.name() {
+                "summary" => self.summary = value.to_string(),
+                "db.statement" => self.statement = value.to_string(),
+                _ => {}
+            }
+        }
+
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            match field.name() {
+                "rows_affected" => self.rows_affected = Some(value),
+                "rows_returned" => self.rows_returned = Some(value),
+                _ => {}
+            }
+        }
+
+        fn record_f64(&mut self, field: &Field, value: f64) {
+            if field.name() == "elapsed_secs" {
+                self.elapsed_secs = value;
+            }
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use opentelemetry::{propagation::TextMapPropagator, trace::TraceContextExt};
+
+        #[test]
+        fn test_traceparent_is_extracted() {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+                    .parse()
+                    .unwrap(),
+            );
+            let parent = TraceContextPropagator::new().extract(&HeaderExtractor(&headers));
+            let span = parent.span();
+            let context = span.span_context();
+            assert!(context.is_remote());
+            assert_eq!(
+                context.trace_id().to_string(),
+                "0af7651916cd43dd8448eb211c80319c"
+            );
+            assert_eq!(context.span_id().to_string(), "b7ad6b7169203331");
+        }
+    }
+}
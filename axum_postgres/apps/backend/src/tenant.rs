@@ -0,0 +1,324 @@
+//! Soft multi-tenancy: every user belongs to a tenant
+//!
+//! A request names its tenant with the `X-Tenant-Id` header, by slug or
+//! numeric id, or with a subdomain of TENANT_DOMAIN (`acme.example.com`
+//! for TENANT_DOMAIN=example.com); the header wins. Requests naming no
+//! tenant belong to the default tenant, so single-tenant deployments need
+//! no setup.
+//!
+//! `middleware::tenant::scope` resolves the [`TenantContext`] once and runs
+//! the rest of the request with its id as the [`current`] tenant.
+//! Repositories read it when they are built and scope every query to it, so
+//! a repository built for a request only ever sees that tenant's users;
+//! build it before spawning tasks that use it.
+//!
+//! With TENANT_ISOLATION=rls the database enforces the same: the pool
+//! points `app.tenant_id` of every connection it hands out at the current
+//! tenant, and row-level security policies hide other tenants' rows from
+//! any query, including ones that forget to filter.
+
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts, HeaderMap},
+};
+use sqlx::{PgConnection, PgPool};
+
+use crate::error::AppError;
+use crate::models::tenant::{is_valid_slug, Tenant};
+use crate::repository::tenants::{TenantRepository, TenantRepositoryTrait};
+
+/// Header naming the tenant of a request
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+/// Tenant of the rows created before multi-tenancy, and of requests that
+/// name no tenant
+pub const DEFAULT_TENANT_ID: i32 = 1;
+pub const DEFAULT_TENANT_SLUG: &str = "default";
+
+tokio::task_local! {
+    /// Tenant of the request this task serves
+    static CURRENT: i32;
+}
+
+/// Tenant the current task works for; the default tenant outside requests,
+/// e.g. in the seed binary or the event consumer
+pub fn current() -> i32 {
+    CURRENT.try_with(|id| *id).unwrap_or(DEFAULT_TENANT_ID)
+}
+
+/// Run `future` with `tenant_id` as the current tenant
+pub async fn scope<F: Future>(tenant_id: i32, future: F) -> F::Output {
+    CURRENT.scope(tenant_id, future).await
+}
+
+/// How tenants are kept apart
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TenantIsolation {
+    /// The repositories add the tenant to every query
+    #[default]
+    Query,
+    /// Row-level security policies also enforce it, on connections that
+    /// carry the tenant in `app.tenant_id`
+    RowLevelSecurity,
+}
+
+impl FromStr for TenantIsolation {
+    type Err = String;
+
+    /// `query` or `rls`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "query" => Ok(Self::Query),
+            "rls" => Ok(Self::RowLevelSecurity),
+            other => Err(format!("TENANT_ISOLATION must be query or rls: {}", other)),
+        }
+    }
+}
+
+/// Point `app.tenant_id` of `conn` at the current tenant
+///
+/// The pool runs this on every connection it opens or hands out in RLS
+/// mode, so no query runs with the tenant of an earlier request.
+pub async fn set_connection_tenant(conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('app.tenant_id', $1, false)")
+        .bind(current().to_string())
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Fail when the policies do not apply to the role of `pool`
+///
+/// Superusers, roles with BYPASSRLS and the owner of the tables are exempt
+/// from row-level security, so RLS mode with such a role would isolate
+/// nothing.
+pub async fn check_row_level_security(pool: &PgPool) -> Result<(), String> {
+    let (role, active): (String, bool) =
+        sqlx::query_as("SELECT current_user::TEXT, row_security_active('test_users')")
+            .fetch_one(pool)
+            .await
+            .map_err(|e| format!("Failed to check row-level security: {}", e))?;
+    if active {
+        Ok(())
+    } else {
+        Err(format!(
+            "TENANT_ISOLATION=rls, but role {} bypasses row-level security on test_users; \
+             connect as a role that neither owns the tables nor is a superuser",
+            role
+        ))
+    }
+}
+
+/// How a request named its tenant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantKey {
+    Id(i32),
+    Slug(String),
+}
+
+/// Finds the tenant a request names
+#[derive(Debug, Clone, Default)]
+pub struct TenantResolver {
+    /// Parent domain of tenant subdomains; None turns them off
+    domain: Option<Arc<str>>,
+}
+
+impl TenantResolver {
+    pub fn new(domain: Option<&str>) -> Self {
+        Self {
+            domain: domain
+                .map(|domain| domain.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .map(Arc::from),
+        }
+    }
+
+    /// Subdomains of TENANT_DOMAIN name tenants when it is set
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("TENANT_DOMAIN").ok().as_deref())
+    }
+
+    /// Tenant named by `X-Tenant-Id`, or else by the Host subdomain
+    ///
+    /// None when the request names no tenant; a header that is neither an
+    /// id nor a valid slug is a bad request.
+    pub fn requested(&self, headers: &HeaderMap) -> Result<Option<TenantKey>, AppError> {
+        if let Some(value) = headers.get(TENANT_HEADER) {
+            let value = value
+                .to_str()
+                .map(str::trim)
+                .map_err(|_| AppError::BadRequest("Invalid X-Tenant-Id header".to_string()))?;
+            return match value.parse::<i32>() {
+                Ok(id) => Ok(Some(TenantKey::Id(id))),
+                Err(_) if is_valid_slug(value) => Ok(Some(TenantKey::Slug(value.to_string()))),
+                Err(_) => Err(AppError::BadRequest(
+                    "X-Tenant-Id must be a tenant id or slug".to_string(),
+                )),
+            };
+        }
+        Ok(self.subdomain(headers).map(TenantKey::Slug))
+    }
+
+    /// `acme` of `acme.example.com[:port]` when TENANT_DOMAIN is example.com
+    fn subdomain(&self, headers: &HeaderMap) -> Option<String> {
+        let domain = self.domain.as_deref()?;
+        let host = headers.get(header::HOST)?.to_str().ok()?;
+        let host = host.split(':').next()?.to_ascii_lowercase();
+        let label = host.strip_suffix(domain)?.strip_suffix('.')?;
+        (is_valid_slug(label)).then(|| label.to_string())
+    }
+}
+
+/// Tenant of the request, as an extractor
+///
+/// Taken from the request extensions once `middleware::tenant::scope` has
+/// resolved it, otherwise looked up from the headers. Rejects with 404 for
+/// an unknown tenant and 403 for a suspended one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantContext {
+    pub id: i32,
+    pub slug: String,
+}
+
+impl TenantContext {
+    /// The tenant of requests that name none
+    pub fn default_tenant() -> Self {
+        Self {
+            id: DEFAULT_TENANT_ID,
+            slug: DEFAULT_TENANT_SLUG.to_string(),
+        }
+    }
+}
+
+impl From<Tenant> for TenantContext {
+    fn from(tenant: Tenant) -> Self {
+        Self {
+            id: tenant.id,
+            slug: tenant.slug,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TenantContext
+where
+    PgPool: FromRef<S>,
+    TenantResolver: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(tenant) = parts.extensions.get::<TenantContext>() {
+            return Ok(tenant.clone());
+        }
+        let Some(key) = TenantResolver::from_ref(state).requested(&parts.headers)? else {
+            return Ok(Self::default_tenant());
+        };
+
+        let repo = TenantRepository::new(PgPool::from_ref(state));
+        let tenant = match &key {
+            TenantKey::Id(id) => repo.get(*id).await,
+            TenantKey::Slug(slug) => repo.find_by_slug(slug).await,
+        }
+        .map_err(|e| {
+            tracing::error!("Database error resolving tenant {:?}: {:?}", key, e);
+            AppError::from(e)
+        })?
+        .ok_or_else(|| AppError::NotFound("Tenant not found".to_string()))?;
+        if !tenant.active {
+            return Err(AppError::Forbidden("Tenant is suspended".to_string()));
+        }
+        Ok(tenant.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_header_names_tenant_by_id_or_slug() {
+        let resolver = TenantResolver::default();
+        assert_eq!(
+            resolver
+                .requested(&headers(&[("x-tenant-id", "7")]))
+                .unwrap(),
+            Some(TenantKey::Id(7))
+        );
+        assert_eq!(
+            resolver
+                .requested(&headers(&[("x-tenant-id", " acme ")]))
+                .unwrap(),
+            Some(TenantKey::Slug("acme".to_string()))
+        );
+        assert!(resolver
+            .requested(&headers(&[("x-tenant-id", "Not A Slug")]))
+            .is_err());
+        assert_eq!(resolver.requested(&HeaderMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_subdomain_names_tenant_under_tenant_domain() {
+        let resolver = TenantResolver::new(Some("Example.com"));
+        let requested = |host: &str| resolver.requested(&headers(&[("host", host)])).unwrap();
+
+        assert_eq!(
+            requested("acme.example.com"),
+            Some(TenantKey::Slug("acme".to_string()))
+        );
+        assert_eq!(
+            requested("ACME.example.com:3000"),
+            Some(TenantKey::Slug("acme".to_string()))
+        );
+        assert_eq!(requested("example.com"), None);
+        assert_eq!(requested("a.b.example.com"), None);
+        assert_eq!(requested("acme.example.org"), None);
+        assert_eq!(requested("acmeexample.com"), None);
+        // The header wins over the subdomain
+        assert_eq!(
+            resolver
+                .requested(&headers(&[
+                    ("host", "acme.example.com"),
+                    ("x-tenant-id", "2")
+                ]))
+                .unwrap(),
+            Some(TenantKey::Id(2))
+        );
+        // Without TENANT_DOMAIN hosts are ignored
+        assert_eq!(
+            TenantResolver::default()
+                .requested(&headers(&[("host", "acme.example.com")]))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tenant_isolation_parses() {
+        assert_eq!("query".parse(), Ok(TenantIsolation::Query));
+        assert_eq!(" rls ".parse(), Ok(TenantIsolation::RowLevelSecurity));
+        assert!("row-level".parse::<TenantIsolation>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_current_tenant_is_task_local() {
+        assert_eq!(current(), DEFAULT_TENANT_ID);
+        assert_eq!(scope(5, async { current() }).await, 5);
+        assert_eq!(current(), DEFAULT_TENANT_ID);
+    }
+}
@@ -1,8 +1,19 @@
 use backend::database::create_pool_from_env;
 use backend::models::user::{CreateUserRequest, UpdateUserRequest};
+use backend::models::user_id::UserId;
 use backend::repository::user::{UserRepository, UserRepositoryTrait};
 use dotenvy::dotenv;
 
+/// An id that no user has, in either id format
+fn missing_user_id() -> UserId {
+    let id = if cfg!(feature = "uuid-ids") {
+        "00000000-0000-7000-8000-000000000000"
+    } else {
+        "99999"
+    };
+    id.parse().unwrap()
+}
+
 #[tokio::test]
 async fn test_user_repository_integration() {
     dotenv().ok();
@@ -17,6 +28,7 @@ async fn test_user_repository_integration() {
     let create_request = CreateUserRequest {
         name: "Integration Test User".to_string(),
         email: "integration_test@example.com".to_string(),
+        ..Default::default()
     };
 
     let created_user = repo
@@ -30,7 +42,7 @@ async fn test_user_repository_integration() {
 
     // Test get user by id
     let retrieved_user = repo
-        .get_user_by_id(created_user.id)
+        .get_user_by_id(created_user.user_id())
         .await
         .expect("Failed to get user");
 
@@ -49,10 +61,11 @@ async fn test_user_repository_integration() {
         name: Some("Updated Integration User".to_string()),
         email: None,
         active: Some(false),
+        ..Default::default()
     };
 
     let updated_user = repo
-        .update_user(created_user.id, update_request)
+        .update_user(created_user.user_id(), update_request)
         .await
         .expect("Failed to update user");
 
@@ -64,7 +77,7 @@ async fn test_user_repository_integration() {
 
     // Test delete user
     let deleted = repo
-        .delete_user(created_user.id)
+        .delete_user(created_user.user_id())
         .await
         .expect("Failed to delete user");
 
@@ -72,7 +85,7 @@ async fn test_user_repository_integration() {
 
     // Verify user is deleted
     let deleted_user = repo
-        .get_user_by_id(created_user.id)
+        .get_user_by_id(created_user.user_id())
         .await
         .expect("Failed to check deleted user");
 
@@ -91,7 +104,7 @@ async fn test_user_repository_edge_cases() {
 
     // Test get non-existent user
     let non_existent = repo
-        .get_user_by_id(99999)
+        .get_user_by_id(missing_user_id())
         .await
         .expect("Failed to handle non-existent user");
 
@@ -99,7 +112,7 @@ async fn test_user_repository_edge_cases() {
 
     // Test delete non-existent user
     let not_deleted = repo
-        .delete_user(99999)
+        .delete_user(missing_user_id())
         .await
         .expect("Failed to handle non-existent user delete");
 
@@ -110,12 +123,431 @@ async fn test_user_repository_edge_cases() {
         name: Some("Non-existent User".to_string()),
         email: None,
         active: None,
+        ..Default::default()
     };
 
     let not_updated = repo
-        .update_user(99999, update_request)
+        .update_user(missing_user_id(), update_request)
         .await
         .expect("Failed to handle non-existent user update");
 
     assert!(not_updated.is_none());
-}
\ No newline at end of file
+}
+
+/// Create `rls_test_app`, a role that row-level security applies to
+async fn create_rls_test_role(admin: &sqlx::PgPool) {
+    for statement in [
+        "DO $$ BEGIN CREATE ROLE rls_test_app NOLOGIN; EXCEPTION WHEN duplicate_object THEN NULL; END $$",
+        "GRANT SELECT, INSERT, UPDATE, DELETE ON test_users, test_users_history, collection_versions, event_outbox_state, event_outbox TO rls_test_app",
+        "GRANT USAGE ON ALL SEQUENCES IN SCHEMA public TO rls_test_app",
+    ] {
+        sqlx::query(statement)
+            .execute(admin)
+            .await
+            .expect("Failed to set up the test role");
+    }
+}
+
+#[tokio::test]
+async fn test_row_level_security_isolates_tenants() {
+    use backend::database::DatabaseConfig;
+    use backend::tenant::{self, TenantIsolation, DEFAULT_TENANT_ID};
+
+    dotenv().ok();
+
+    let admin = create_pool_from_env()
+        .await
+        .expect("Failed to create database pool");
+    // Superusers bypass the policies, so the queries below run as a plain role
+    create_rls_test_role(&admin).await;
+    let slug = format!(
+        "rls-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    let email = format!("{}@rls.example.com", slug);
+    let other: i32 =
+        sqlx::query_scalar("INSERT INTO tenants (slug, name) VALUES ($1, 'RLS') RETURNING id")
+            .bind(&slug)
+            .fetch_one(&admin)
+            .await
+            .expect("Failed to create tenant");
+
+    let config = DatabaseConfig {
+        max_connections: 1,
+        tenant_isolation: TenantIsolation::RowLevelSecurity,
+        ..DatabaseConfig::from_env().unwrap()
+    };
+    let pool = config.connect().await.expect("Failed to create RLS pool");
+
+    // Returns the tenant setting and how many rows with `email` are visible
+    let visible = |tenant_id: i32| {
+        let pool = pool.clone();
+        let email = email.clone();
+        tenant::scope(tenant_id, async move {
+            let mut conn = pool.acquire().await.unwrap();
+            sqlx::query("SET ROLE rls_test_app")
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+            let setting: String = sqlx::query_scalar("SELECT current_setting('app.tenant_id')")
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM test_users WHERE email = $1")
+                .bind(&email)
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+            sqlx::query("RESET ROLE").execute(&mut *conn).await.unwrap();
+            (setting, count)
+        })
+    };
+
+    tenant::scope(other, async {
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("SET ROLE rls_test_app")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+
+        // Rows inserted without a tenant join the connection's
+        let tenant_id: i32 = sqlx::query_scalar(
+            "INSERT INTO test_users (name, email) VALUES ('RLS User', $1) RETURNING tenant_id",
+        )
+        .bind(&email)
+        .fetch_one(&mut *conn)
+        .await
+        .expect("Failed to insert as the tenant");
+        assert_eq!(tenant_id, other);
+
+        // No query sees or writes other tenants' rows, filtered or not
+        let foreign: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM test_users WHERE tenant_id <> $1")
+                .bind(other)
+                .fetch_one(&mut *conn)
+                .await
+                .unwrap();
+        assert_eq!(foreign, 0);
+        let error = sqlx::query(
+            "INSERT INTO test_users (name, email, tenant_id) VALUES ('RLS User', $1, $2)",
+        )
+        .bind(format!("other-{}", email))
+        .bind(DEFAULT_TENANT_ID)
+        .execute(&mut *conn)
+        .await
+        .unwrap_err();
+        assert_eq!(
+            error.as_database_error().and_then(|e| e.code()).as_deref(),
+            Some("42501")
+        );
+
+        sqlx::query("RESET ROLE").execute(&mut *conn).await.unwrap();
+    })
+    .await;
+
+    // The one pooled connection is pointed at each tenant in turn
+    assert_eq!(visible(other).await, (other.to_string(), 1));
+    assert_eq!(
+        visible(DEFAULT_TENANT_ID).await,
+        (DEFAULT_TENANT_ID.to_string(), 0)
+    );
+
+    sqlx::query("DELETE FROM test_users WHERE email = $1")
+        .bind(&email)
+        .execute(&admin)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_export_reads_the_tenant_of_the_request_under_row_level_security() {
+    use backend::database::{DatabaseConfig, DbPools};
+    use backend::models::user::UserListQuery;
+    use backend::tenant::{self, TenantIsolation};
+
+    dotenv().ok();
+
+    let admin = create_pool_from_env()
+        .await
+        .expect("Failed to create database pool");
+    create_rls_test_role(&admin).await;
+    let slug = format!(
+        "export-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos()
+    );
+    let email = format!("{}@rls.example.com", slug);
+    let other: i32 =
+        sqlx::query_scalar("INSERT INTO tenants (slug, name) VALUES ($1, 'Export') RETURNING id")
+            .bind(&slug)
+            .fetch_one(&admin)
+            .await
+            .expect("Failed to create tenant");
+    sqlx::query("INSERT INTO test_users (name, email, tenant_id) VALUES ('Exported User', $1, $2)")
+        .bind(&email)
+        .bind(other)
+        .execute(&admin)
+        .await
+        .expect("Failed to create user");
+
+    // Every connection of the pool runs as the plain role
+    let config = DatabaseConfig::from_env().unwrap();
+    let separator = if config.url.contains('?') { '&' } else { '?' };
+    let config = DatabaseConfig {
+        url: format!("{}{}options=-c%20role%3Drls_test_app", config.url, separator),
+        tenant_isolation: TenantIsolation::RowLevelSecurity,
+        ..config
+    };
+    let pool = config.connect().await.expect("Failed to create RLS pool");
+
+    // The export reads on a task of its own, which still sees the tenant
+    let mut chunks = tenant::scope(other, async {
+        backend::export::export_users(DbPools::from(pool), UserListQuery::default())
+    })
+    .await;
+    let mut exported = Vec::new();
+    while let Some(chunk) = chunks.recv().await {
+        exported.extend_from_slice(&chunk.expect("Export failed"));
+    }
+    let exported = String::from_utf8(exported).unwrap();
+    assert_eq!(exported.lines().count(), 1);
+    assert!(exported.contains(&email));
+
+    sqlx::query("DELETE FROM test_users WHERE email = $1")
+        .bind(&email)
+        .execute(&admin)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_advisory_lock_runs_work_once_at_a_time() {
+    use backend::database::lock::{try_with_advisory_lock, with_advisory_lock};
+
+    dotenv().ok();
+
+    let pool = create_pool_from_env()
+        .await
+        .expect("Failed to create database pool");
+    let name = "tests.advisory_lock";
+
+    // While one holder runs, others skip instead of running alongside it
+    let skipped = with_advisory_lock(&pool, name, async {
+        try_with_advisory_lock(&pool, name, async { "ran" })
+            .await
+            .unwrap()
+    })
+    .await
+    .unwrap();
+    assert_eq!(skipped, None);
+
+    // Ending the work released the lock
+    let ran = try_with_advisory_lock(&pool, name, async { "ran" })
+        .await
+        .unwrap();
+    assert_eq!(ran, Some("ran"));
+
+    // Migrating an up to date database under the lock is a no-op
+    backend::database::run_migrations(&pool)
+        .await
+        .expect("Failed to run migrations");
+}
+
+#[tokio::test]
+async fn test_change_feed_forwards_committed_user_changes() {
+    use backend::changes::{ChangeEvent, ChangeFeed, ChangeOp};
+    use std::time::Duration;
+
+    dotenv().ok();
+
+    let pool = create_pool_from_env()
+        .await
+        .expect("Failed to create database pool");
+    let feed = ChangeFeed::default();
+    let mut changes = feed.subscribe();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let listener = tokio::spawn(feed.clone().listen(pool.clone(), shutdown_rx));
+    // Give the listener time to LISTEN before anything changes
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let repo = UserRepository::new(pool);
+    let user = repo
+        .create_user(CreateUserRequest {
+            name: "Change Feed User".to_string(),
+            email: "change_feed@example.com".to_string(),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create user");
+    // A no-op update is not a change
+    repo.update_user(
+        user.user_id(),
+        UpdateUserRequest {
+            name: Some(user.name.clone()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(repo.delete_user(user.user_id()).await.unwrap());
+    assert!(repo.purge_user(user.user_id()).await.unwrap());
+
+    // Other tests change users concurrently; only this user's changes count
+    let mut ops = Vec::new();
+    while ops.len() < 3 {
+        let event = tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .expect("No change arrived")
+            .unwrap();
+        if let ChangeEvent::User(change) = event {
+            if change.id == user.user_id() {
+                assert_eq!(change.tenant_id, backend::tenant::DEFAULT_TENANT_ID);
+                ops.push(change.op);
+            }
+        }
+    }
+    // Soft deletes are updates; purging removes the row
+    assert_eq!(ops, [ChangeOp::Insert, ChangeOp::Update, ChangeOp::Delete]);
+
+    shutdown_tx.send(true).unwrap();
+    listener.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_cached_repository_reads_through_when_redis_is_down() {
+    use backend::cache::{redis::RedisCache, TieredCache};
+    use backend::models::user::UserListQuery;
+    use backend::repository::cached_user::CachedUserRepository;
+    use std::time::Duration;
+
+    dotenv().ok();
+
+    let pool = create_pool_from_env()
+        .await
+        .expect("Failed to create database pool");
+    // Nothing listens on port 1
+    let redis = RedisCache::new("redis://127.0.0.1:1", Duration::from_millis(100)).unwrap();
+    let cache = TieredCache::new(None, Some(redis));
+    let repo = CachedUserRepository::new(UserRepository::new(pool), cache);
+
+    let user = repo
+        .create_user(CreateUserRequest {
+            name: "Cached User".to_string(),
+            email: "cached_user@example.com".to_string(),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create user");
+    let found = repo.get_user_by_id(user.user_id()).await.unwrap().unwrap();
+    assert_eq!(found.email, "cached_user@example.com");
+
+    let renamed = repo
+        .update_user(
+            user.user_id(),
+            UpdateUserRequest {
+                name: Some("Renamed Cached User".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(renamed.name, "Renamed Cached User");
+    let page = repo
+        .list_users_after(&UserListQuery::default(), None, 1000)
+        .await
+        .unwrap();
+    assert!(page.iter().any(|u| u.id == user.id && u.name == renamed.name));
+
+    assert!(repo.purge_user(user.user_id()).await.unwrap());
+    assert!(repo.get_user_by_id(user.user_id()).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_dispatcher_publishes_the_outbox_in_order() {
+    use async_trait::async_trait;
+    use backend::events::EventRegistry;
+    use backend::publisher::{self, Dispatcher, EventPublisher, Publication, PublisherError};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct RecordingPublisher(Mutex<Vec<Publication>>);
+
+    #[async_trait]
+    impl EventPublisher for RecordingPublisher {
+        async fn publish(&self, publication: &Publication) -> Result<(), PublisherError> {
+            self.0.lock().unwrap().push(publication.clone());
+            Ok(())
+        }
+    }
+
+    dotenv().ok();
+
+    let pool = create_pool_from_env()
+        .await
+        .expect("Failed to create database pool");
+    publisher::enable_outbox(&pool).await.unwrap();
+    let recorder = Arc::new(RecordingPublisher::default());
+    let dispatcher = Dispatcher::new(
+        Arc::new(EventRegistry::with_domain_events()),
+        recorder.clone(),
+    );
+    let drain = || async {
+        while dispatcher.publish_batch(&pool).await.unwrap() > 0 {}
+    };
+
+    let repo = UserRepository::new(pool.clone());
+    let user = repo
+        .create_user(CreateUserRequest {
+            name: "Outbox User".to_string(),
+            email: "outbox@example.com".to_string(),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create user");
+    repo.update_user(
+        user.user_id(),
+        UpdateUserRequest {
+            name: Some("Outbox User Renamed".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    drain().await;
+    assert!(repo.delete_user(user.user_id()).await.unwrap());
+    drain().await;
+    assert!(repo.purge_user(user.user_id()).await.unwrap());
+    drain().await;
+
+    sqlx::query("UPDATE event_outbox_state SET enabled = FALSE")
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM event_outbox")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // Other tests change users concurrently; only this user's events count
+    let key = format!("{}:{}", backend::tenant::DEFAULT_TENANT_ID, user.user_id());
+    let published = recorder.0.lock().unwrap();
+    let events: Vec<&str> = published
+        .iter()
+        .filter(|publication| publication.key == key)
+        .map(|publication| publication.envelope.event_type.as_str())
+        .collect();
+    // Purging a soft-deleted user reports its deletion again
+    assert_eq!(
+        events,
+        ["user.created", "user.updated", "user.deleted", "user.deleted"]
+    );
+    let message_ids: std::collections::HashSet<_> =
+        published.iter().map(|publication| &publication.message_id).collect();
+    assert_eq!(message_ids.len(), published.len());
+}